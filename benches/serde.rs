@@ -0,0 +1,145 @@
+#![feature(plugin, custom_derive, custom_attribute)]
+#![plugin(serde_macros)]
+
+//! Benchmarks for the serde functions.
+//!
+//! Every value is encoded into a growing `Vec` and into one that has been
+//! allocated with exactly the right size up front, and decoded both from a
+//! slice and through `std::io::Read`.  Run them with `cargo bench`.
+//!
+//! The `small` group is what most RPC traffic looks like: messages of a few
+//! dozen bytes, decoded one at a time, where the fixed cost of each number
+//! and string read matters more than raw throughput.  To see what a change
+//! to the read path does to it, save a baseline on the commit before it and
+//! compare against it on the commit after:
+//!
+//! ```text
+//! cargo bench --bench serde -- small --save-baseline before
+//! cargo bench --bench serde -- small --baseline before
+//! ```
+
+#[macro_use]
+extern crate criterion;
+extern crate bincode;
+extern crate serde;
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::iter;
+
+use criterion::{Criterion, black_box};
+
+use bincode::{Infinite, Bounded};
+use bincode::serde::{serialize, serialize_into, serialize_sized, serialized_size, deserialize, deserialize_from};
+use bincode::serde::{Deserializer, SliceReader};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+enum Shape {
+    Point,
+    Circle(f64),
+    Polygon(Vec<(f32, f32)>),
+    Group { name: String, children: Vec<Shape> },
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+struct Request {
+    id: u64,
+    method: String,
+    flags: u8,
+    deadline_ms: u32,
+    position: (f32, f32),
+    tag: char,
+}
+
+/// A `Read` that hides that it is reading from a slice, so that decoding
+/// goes through the same path as it would for a file or socket.
+struct Opaque<'a>(&'a [u8]);
+
+impl<'a> Read for Opaque<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+fn bench_value<T>(c: &mut Criterion, name: &str, value: T)
+    where T: serde::Serialize + serde::Deserialize + 'static
+{
+    let encoded = serialize(&value, Infinite).unwrap();
+    let size = serialized_size(&value) as usize;
+
+    c.bench_function(&format!("{}/encode", name), |b| {
+        b.iter(|| serialize(black_box(&value), Infinite).unwrap())
+    });
+    c.bench_function(&format!("{}/encode_presized", name), |b| {
+        b.iter(|| {
+            let mut buffer = Vec::with_capacity(size);
+            serialize_into(&mut buffer, black_box(&value), Infinite).unwrap();
+            buffer
+        })
+    });
+    c.bench_function(&format!("{}/encode_sized", name), |b| {
+        b.iter(|| serialize_sized(black_box(&value)).unwrap())
+    });
+    c.bench_function(&format!("{}/decode_slice", name), |b| {
+        b.iter(|| deserialize::<T>(black_box(&encoded)).unwrap())
+    });
+    c.bench_function(&format!("{}/decode_read", name), |b| {
+        b.iter(|| deserialize_from::<_, T, _>(&mut Opaque(black_box(&encoded)), Infinite).unwrap())
+    });
+}
+
+fn primitives(c: &mut Criterion) {
+    bench_value(c, "u64", 0x0102030405060708u64);
+    bench_value(c, "primitives", (true, 7u8, -3i16, 1u32 << 20, -1i64, 1.5f32, 0.25f64));
+}
+
+fn strings(c: &mut Criterion) {
+    bench_value(c, "string_short", "hello".to_string());
+    bench_value(c, "string_long", iter::repeat("ünïcödé ").take(1024).collect::<String>());
+    bench_value(c, "strings", (0..256).map(|i| i.to_string()).collect::<Vec<String>>());
+}
+
+fn bytes(c: &mut Criterion) {
+    bench_value(c, "bytes_64", vec![0xabu8; 64]);
+    bench_value(c, "bytes_64k", vec![0xabu8; 64 * 1024]);
+}
+
+fn enums(c: &mut Criterion) {
+    let leaf = Shape::Polygon(vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+    let mut shape = Shape::Group { name: "leaf".to_string(), children: vec![leaf, Shape::Circle(2.0), Shape::Point] };
+    for depth in 0..8 {
+        shape = Shape::Group { name: format!("level {}", depth), children: vec![shape.clone(), shape] };
+    }
+    bench_value(c, "nested_enums", shape);
+}
+
+fn maps(c: &mut Criterion) {
+    let map: HashMap<u32, String> = (0..1024).map(|i| (i, format!("value {}", i))).collect();
+    bench_value(c, "hashmap", map);
+}
+
+fn small(c: &mut Criterion) {
+    let request = Request {
+        id: 42,
+        method: "get".to_string(),
+        flags: 3,
+        deadline_ms: 250,
+        position: (1.5, -2.0),
+        tag: 'q',
+    };
+    let encoded = serialize(&request, Infinite).unwrap();
+    bench_value(c, "small/request", request);
+
+    c.bench_function("small/request/decode_slice_bounded", |b| {
+        b.iter(|| {
+            let mut deserializer = Deserializer::new(SliceReader::new(black_box(&encoded)), Bounded(1024));
+            <Request as serde::Deserialize>::deserialize(&mut deserializer).unwrap()
+        })
+    });
+    c.bench_function("small/request/decode_read_bounded", |b| {
+        b.iter(|| deserialize_from::<_, Request, _>(&mut Opaque(black_box(&encoded)), Bounded(1024)).unwrap())
+    });
+}
+
+criterion_group!(benches, primitives, strings, bytes, enums, maps, small);
+criterion_main!(benches);
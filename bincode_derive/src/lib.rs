@@ -0,0 +1,328 @@
+//! `#[derive(Encode, Decode, Pod)]` for bincode's `codec` traits.
+//!
+//! This crate is not meant to be used directly; enable the `derive` feature
+//! of `bincode` and use the derives re-exported from there.
+//!
+//! Enum variants are tagged with their position by default.  A variant can
+//! pin its wire tag with `#[bincode(tag = 5)]`, so that reordering or
+//! removing variants doesn't silently change the meaning of old data.
+//! Variants without an explicit tag take the tag of the previous variant
+//! plus one, just like the discriminants of a C-like enum.
+//!
+//! `#[derive(Pod)]` only works on structs, and only sets `Pod::PACKED` for
+//! ones that are `#[repr(C)]` with no padding; the check happens when the
+//! crate using it is compiled.
+//!
+//! `#[derive(MaxSize)]` implements `MaxSizeIn` for every layout, summing up
+//! the bounds of the fields, and for enums adding the variant tag to the
+//! largest variant.  Every field has to implement `MaxSizeIn` too.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, parse_quote, Attribute, Data, DataEnum, DeriveInput, Fields, Generics, Ident, Index,
+          LitInt, Member, Meta, Token};
+
+#[proc_macro_derive(Encode, attributes(bincode))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), quote!(::bincode::codec::Encode));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            let (pattern, writes) = encode_fields(&data.fields);
+            quote! {
+                let #name #pattern = *self;
+                #writes
+            }
+        }
+        Data::Enum(ref data) => {
+            let tags = match variant_tags(data) {
+                Ok(tags) => tags,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let arms = data.variants.iter().zip(tags).map(|(variant, tag)| {
+                let variant_name = &variant.ident;
+                let (pattern, writes) = encode_fields(&variant.fields);
+                quote! {
+                    #name::#variant_name #pattern => {
+                        encoder.write_variant(#tag)?;
+                        #writes
+                    }
+                }
+            });
+            quote! {
+                match *self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(_) => return syn::Error::new_spanned(name, "#[derive(Encode)] is not supported for unions")
+            .to_compile_error().into(),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::bincode::codec::Encode for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn encode<__W: ::std::io::Write>(&self, encoder: &mut ::bincode::codec::Encoder<__W>)
+                -> ::bincode::codec::EncodeResult<()>
+            {
+                #body
+                Ok(())
+            }
+        }
+    };
+    expanded.into()
+}
+
+#[proc_macro_derive(Decode, attributes(bincode))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), quote!(::bincode::codec::Decode));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            let construct = decode_fields(&data.fields);
+            quote! {
+                Ok(#name #construct)
+            }
+        }
+        Data::Enum(ref data) => {
+            let tags = match variant_tags(data) {
+                Ok(tags) => tags,
+                Err(err) => return err.to_compile_error().into(),
+            };
+            let arms = data.variants.iter().zip(tags).map(|(variant, tag)| {
+                let variant_name = &variant.ident;
+                let construct = decode_fields(&variant.fields);
+                quote! {
+                    #tag => Ok(#name::#variant_name #construct),
+                }
+            });
+            quote! {
+                let tag = decoder.read_variant()?;
+                match tag {
+                    #(#arms)*
+                    _ => Err(::bincode::codec::DecodeError::invalid_encoding(
+                        "unknown tag when reading enum variant",
+                        Some(format!("Got {}", tag)))),
+                }
+            }
+        }
+        Data::Union(_) => return syn::Error::new_spanned(name, "#[derive(Decode)] is not supported for unions")
+            .to_compile_error().into(),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::bincode::codec::Decode for #name #ty_generics #where_clause {
+            fn decode<__R: ::std::io::Read, __L: ::bincode::SizeLimit>(decoder: &mut ::bincode::codec::Decoder<__R, __L>)
+                -> ::bincode::codec::DecodeResult<Self>
+            {
+                #body
+            }
+        }
+    };
+    expanded.into()
+}
+
+#[proc_macro_derive(Pod)]
+pub fn derive_pod(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let generics = add_bounds(input.generics.clone(), quote!(::bincode::codec::Pod));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => return syn::Error::new_spanned(name, "#[derive(Pod)] is only supported for structs")
+            .to_compile_error().into(),
+    };
+    let repr_c = match is_repr_c(&input.attrs) {
+        Ok(repr_c) => repr_c,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let types: Vec<&syn::Type> = fields.iter().map(|f| &f.ty).collect();
+    let members: Vec<Member> = fields.iter().enumerate().map(|(i, f)| match f.ident {
+        Some(ref ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index::from(i)),
+    }).collect();
+
+    // Fields are swapped through a copy, so that `#[repr(packed)]` structs
+    // don't need references to unaligned fields.
+    let expanded = quote! {
+        unsafe impl #impl_generics ::bincode::codec::Pod for #name #ty_generics #where_clause {
+            const PACKED: bool = #repr_c
+                && 0 #(+ ::std::mem::size_of::<#types>())* == ::std::mem::size_of::<Self>()
+                #(&& <#types as ::bincode::codec::Pod>::PACKED)*;
+
+            fn swap_to_wire(&mut self) {
+                #({
+                    let mut field = self.#members;
+                    ::bincode::codec::Pod::swap_to_wire(&mut field);
+                    self.#members = field;
+                })*
+            }
+        }
+    };
+    expanded.into()
+}
+
+#[proc_macro_derive(MaxSize)]
+pub fn derive_max_size(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let layout = Ident::new("__L", Span::call_site());
+    let mut generics = add_bounds(input.generics.clone(), quote!(::bincode::MaxSizeIn<#layout>));
+    generics.params.push(parse_quote!(#layout: ::bincode::format::StaticLayout));
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+
+    let size = match input.data {
+        Data::Struct(ref data) => fields_max_size(&data.fields, &layout),
+        Data::Enum(ref data) => {
+            // The largest variant, worked out one variant at a time since
+            // there is no `max` to call in a constant.
+            let largest = data.variants.iter().fold(quote!(0), |largest, variant| {
+                let size = fields_max_size(&variant.fields, &layout);
+                quote!({
+                    let largest: usize = #largest;
+                    let size: usize = #size;
+                    if size > largest { size } else { largest }
+                })
+            });
+            quote!(<#layout as ::bincode::format::StaticLayout>::VARIANT_TAG_BYTES + #largest)
+        }
+        Data::Union(_) => return syn::Error::new_spanned(name, "#[derive(MaxSize)] is not supported for unions")
+            .to_compile_error().into(),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::bincode::MaxSizeIn<#layout> for #name #ty_generics #where_clause {
+            const MAX_SIZE_IN: usize = #size;
+        }
+    };
+    expanded.into()
+}
+
+/// Returns whether a type is `#[repr(C)]`, possibly along with other
+/// representation hints.
+fn is_repr_c(attrs: &[Attribute]) -> syn::Result<bool> {
+    for attr in attrs {
+        if !attr.path().is_ident("repr") {
+            continue;
+        }
+        let hints = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+        if hints.iter().any(|hint| hint.path().is_ident("C")) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Works out the wire tag of every variant, honoring `#[bincode(tag = N)]`.
+fn variant_tags(data: &DataEnum) -> syn::Result<Vec<u32>> {
+    let mut tags: Vec<u32> = Vec::with_capacity(data.variants.len());
+    let mut next: u64 = 0;
+
+    for variant in &data.variants {
+        let mut explicit = None;
+        for attr in &variant.attrs {
+            if !attr.path().is_ident("bincode") {
+                continue;
+            }
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag") {
+                    let lit: LitInt = meta.value()?.parse()?;
+                    explicit = Some(lit.base10_parse::<u32>()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown bincode attribute, expected `tag`"))
+                }
+            })?;
+        }
+
+        let tag = match explicit {
+            Some(tag) => tag,
+            None if next <= u32::MAX as u64 => next as u32,
+            None => return Err(syn::Error::new_spanned(&variant.ident,
+                                                       "variant tag doesn't fit in a u32")),
+        };
+        if tags.contains(&tag) {
+            return Err(syn::Error::new_spanned(&variant.ident,
+                                               format!("duplicate bincode tag {}", tag)));
+        }
+        tags.push(tag);
+        next = tag as u64 + 1;
+    }
+
+    Ok(tags)
+}
+
+fn add_bounds(mut generics: Generics, bound: TokenStream2) -> Generics {
+    let params: Vec<Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+    {
+        let where_clause = generics.make_where_clause();
+        for param in params {
+            where_clause.predicates.push(parse_quote!(#param: #bound));
+        }
+    }
+    generics
+}
+
+/// Builds a pattern binding every field by reference, along with the
+/// statements that encode those bindings in order.
+fn encode_fields(fields: &Fields) -> (TokenStream2, TokenStream2) {
+    let bindings: Vec<Ident> = (0..fields.len())
+        .map(|i| Ident::new(&format!("__field{}", i), Span::call_site()))
+        .collect();
+
+    let pattern = match *fields {
+        Fields::Named(ref named) => {
+            let names = named.named.iter().map(|f| f.ident.as_ref().unwrap());
+            quote!({ #(#names: ref #bindings),* })
+        }
+        Fields::Unnamed(_) => quote!(( #(ref #bindings),* )),
+        Fields::Unit => quote!(),
+    };
+
+    let writes = quote! {
+        #(::bincode::codec::Encode::encode(#bindings, encoder)?;)*
+    };
+
+    (pattern, writes)
+}
+
+/// Builds the constructor arguments for a struct or variant, decoding each
+/// field in order.
+fn decode_fields(fields: &Fields) -> TokenStream2 {
+    match *fields {
+        Fields::Named(ref named) => {
+            let names = named.named.iter().map(|f| f.ident.as_ref().unwrap());
+            quote!({ #(#names: ::bincode::codec::Decode::decode(decoder)?),* })
+        }
+        Fields::Unnamed(ref unnamed) => {
+            let decodes = unnamed.unnamed.iter()
+                .map(|_| quote!(::bincode::codec::Decode::decode(decoder)?));
+            quote!(( #(#decodes),* ))
+        }
+        Fields::Unit => quote!(),
+    }
+}
+
+/// Builds the sum of the `MaxSizeIn` bounds of the fields of a struct or
+/// variant in the layout `layout`.
+fn fields_max_size(fields: &Fields, layout: &Ident) -> TokenStream2 {
+    let types = fields.iter().map(|f| &f.ty);
+    quote!(0 #(+ <#types as ::bincode::MaxSizeIn<#layout>>::MAX_SIZE_IN)*)
+}
@@ -1,7 +1,7 @@
 extern crate bincode;
 extern crate rustc_serialize;
 
-use bincode::SizeLimit;
+use bincode::Infinite;
 use bincode::rustc_serialize::{encode, decode};
 
 #[derive(RustcEncodable, RustcDecodable, PartialEq)]
@@ -20,7 +20,7 @@ fn main() {
         entities: vec![Entity {x: 0.0, y: 4.0}, Entity {x: 10.0, y: 20.5}]
     };
 
-    let encoded: Vec<u8> = encode(&world, SizeLimit::Infinite).unwrap();
+    let encoded: Vec<u8> = encode(&world, Infinite).unwrap();
 
     // 8 bytes for the length of the vector, 4 bytes per float.
     assert_eq!(encoded.len(), 8 + 4 * 4);
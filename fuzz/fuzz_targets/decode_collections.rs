@@ -0,0 +1,11 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate bincode;
+
+use std::collections::{BTreeMap, HashMap};
+
+fuzz_target!(|data: &[u8]| {
+    bincode::fuzz_util::arbitrary_roundtrip::<(String, Vec<u32>, Option<Vec<u8>>)>(data, 4096);
+    bincode::fuzz_util::arbitrary_roundtrip::<HashMap<String, Vec<i8>>>(data, 4096);
+    bincode::fuzz_util::arbitrary_roundtrip::<BTreeMap<u16, Option<String>>>(data, 4096);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate bincode;
+
+fuzz_target!(|data: &[u8]| {
+    bincode::fuzz_util::arbitrary_roundtrip::<Vec<Vec<Option<Vec<String>>>>>(data, 1024);
+    bincode::fuzz_util::arbitrary_roundtrip::<Option<Box<Option<Box<Option<(u8, String)>>>>>>(data, 1024);
+});
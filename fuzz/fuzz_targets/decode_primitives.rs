@@ -0,0 +1,7 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate bincode;
+
+fuzz_target!(|data: &[u8]| {
+    bincode::fuzz_util::arbitrary_roundtrip::<(bool, u8, i16, u32, i64, usize, i8, ())>(data, 64);
+});
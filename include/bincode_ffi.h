@@ -0,0 +1,38 @@
+/*
+ * C interface to bincode's frame helpers, see the `ffi` module.
+ *
+ * A frame is the payload length as a big-endian 64-bit integer, followed by
+ * the payload.  Every function returns one of the BINCODE_FRAME_* codes and
+ * only writes to its out-parameters on success.
+ */
+
+#ifndef BINCODE_FFI_H
+#define BINCODE_FFI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BINCODE_FRAME_OK          0
+#define BINCODE_FRAME_NULL       -1
+#define BINCODE_FRAME_INCOMPLETE -2
+#define BINCODE_FRAME_TOO_LARGE  -3
+
+#define BINCODE_FRAME_HEADER_LEN 8
+
+int bincode_frame_payload_len(const uint8_t *buf, size_t buf_len, uint64_t *payload_len);
+
+int bincode_frame_check(const uint8_t *buf, size_t buf_len, uint64_t max_payload_len,
+                        size_t *frame_len);
+
+int bincode_frame_payload(const uint8_t *buf, size_t buf_len, uint64_t max_payload_len,
+                          const uint8_t **payload, size_t *payload_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
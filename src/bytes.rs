@@ -0,0 +1,122 @@
+//! Encoding into and decoding out of the `bytes` crate's buffers.
+//!
+//! Networking code built on `bytes` keeps its data in `Bytes`, `BytesMut`,
+//! or other `Buf` and `BufMut` implementations.  The functions here read and
+//! write those directly, which saves copying every message to or from a
+//! `Vec<u8>` on the way.  This module is enabled by the `bytes` feature.
+
+use std::fmt;
+use std::ops::Deref;
+
+use bytes_crate::{Buf, BufMut, Bytes};
+use serde_crate as serde;
+
+use ::SizeLimit;
+use ::serde::{serialize_into, serialize_sized, deserialize_from, SerializeResult, DeserializeResult};
+
+/// Serializes an object into a `BufMut`, such as a `BytesMut`.
+///
+/// If the serialization would take more bytes than allowed by `size_limit`,
+/// an error is returned and *no bytes* will be written into the buffer.
+/// A `BufMut` that runs out of room panics, as it does for any other write.
+pub fn serialize_into_buf<B, T, S>(buf: &mut B, value: &T, size_limit: S) -> SerializeResult<()>
+    where B: BufMut, T: serde::Serialize, S: SizeLimit,
+{
+    serialize_into(&mut buf.writer(), value, size_limit)
+}
+
+/// Serializes an object into a `Bytes`.
+///
+/// The output is allocated once, at the right size, and handed over to the
+/// `Bytes` without being copied.
+pub fn serialize_to_bytes<T: serde::Serialize>(value: &T) -> SerializeResult<Bytes> {
+    serialize_sized(value).map(Bytes::from)
+}
+
+/// Deserializes an object from the start of a `Buf`, and advances the buffer
+/// past it.
+///
+/// A `Bytes` or `BytesMut` can be passed in directly; whatever follows the
+/// object is left in the buffer.
+pub fn deserialize_from_buf<B, T, S>(buf: &mut B, size_limit: S) -> DeserializeResult<T>
+    where B: Buf, T: serde::Deserialize, S: SizeLimit,
+{
+    deserialize_from(&mut buf.reader(), size_limit)
+}
+
+/// A byte buffer field that is kept as a `Bytes`.
+///
+/// It is encoded just like a `Vec<u8>`.  Large binary attachments held in a
+/// `Payload` are written straight out of the `Bytes` without being copied
+/// into a `Vec` first, and once decoded can be cloned and sliced without
+/// copying them again.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Payload(pub Bytes);
+
+impl Payload {
+    /// Unwraps the `Bytes`.
+    pub fn into_inner(self) -> Bytes {
+        self.0
+    }
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Bytes> for Payload {
+    fn from(bytes: Bytes) -> Payload {
+        Payload(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Payload {
+        Payload(Bytes::from(bytes))
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Payload({:?})", self.0)
+    }
+}
+
+impl serde::Serialize for Payload {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl serde::Deserialize for Payload {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Payload, D::Error> {
+        deserializer.deserialize_bytes(PayloadVisitor)
+    }
+}
+
+struct PayloadVisitor;
+
+impl serde::de::Visitor for PayloadVisitor {
+    type Value = Payload;
+
+    fn visit_bytes<E: serde::de::Error>(&mut self, bytes: &[u8]) -> Result<Payload, E> {
+        Ok(Payload(Bytes::from(bytes.to_vec())))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(&mut self, bytes: Vec<u8>) -> Result<Payload, E> {
+        Ok(Payload(Bytes::from(bytes)))
+    }
+
+    fn visit_seq<V: serde::de::SeqVisitor>(&mut self, mut visitor: V) -> Result<Payload, V::Error> {
+        let mut bytes = Vec::new();
+        while let Some(byte) = try!(visitor.visit()) {
+            bytes.push(byte);
+        }
+        try!(visitor.end());
+        Ok(Payload(Bytes::from(bytes)))
+    }
+}
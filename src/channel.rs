@@ -0,0 +1,207 @@
+//! Sending typed messages over a stream.
+//!
+//! A `TypedStream` wraps anything that is both `Read` and `Write`, like a
+//! `TcpStream` or a Unix socket, and sends and receives whole messages on
+//! it.  Each message is serialized with serde and sent as a frame: its
+//! length as a big-endian `u64`, followed by its bytes.  That's the same
+//! layout bincode uses for a byte buffer, and the one `FrameReader` reads by
+//! default.
+//!
+//! ```rust,ignore
+//! let stream = TcpStream::connect("127.0.0.1:4000").unwrap();
+//! let mut channel: TypedStream<Request, Response, _> = TypedStream::new(stream).max_message_len(1 << 20);
+//! channel.send(&Request::Ping).unwrap();
+//! let response = channel.recv().unwrap();
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::io::Error as IoError;
+use std::marker::PhantomData;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use serde_crate as serde;
+
+use ::{Bounded, Infinite};
+use ::frame::{FrameReader, FrameError};
+use ::serde::{serialize, deserialize_from, SerializeError, DeserializeError};
+
+/// An error that can be produced while sending or receiving a message.
+#[derive(Debug)]
+pub enum ChannelError {
+    /// If the error stems from the underlying stream, that error will be
+    /// stored and returned here.  A stream that ends in the middle of a
+    /// message produces an `UnexpectedEof` error.
+    IoError(IoError),
+    /// The other end closed the stream between two messages.
+    Closed,
+    /// A message is longer than the maximum allowed length.  When sending,
+    /// nothing has been written.
+    SizeLimit,
+    /// A message to send could not be serialized.
+    Serialize(SerializeError),
+    /// A message that was received could not be deserialized.
+    Deserialize(DeserializeError),
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChannelError::IoError(ref ioerr) =>
+                write!(fmt, "IoError: {}", ioerr),
+            ChannelError::Closed =>
+                write!(fmt, "Closed"),
+            ChannelError::SizeLimit =>
+                write!(fmt, "SizeLimit"),
+            ChannelError::Serialize(ref err) =>
+                write!(fmt, "Serialize: {}", err),
+            ChannelError::Deserialize(ref err) =>
+                write!(fmt, "Deserialize: {}", err),
+        }
+    }
+}
+
+pub type ChannelResult<T> = Result<T, ChannelError>;
+
+impl Error for ChannelError {
+    fn description(&self) -> &str {
+        match *self {
+            ChannelError::IoError(ref err) => Error::description(err),
+            ChannelError::Closed => "the stream was closed",
+            ChannelError::SizeLimit => "a message is longer than the size limit",
+            ChannelError::Serialize(ref err) => err.description(),
+            ChannelError::Deserialize(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ChannelError::IoError(ref err) => err.cause(),
+            ChannelError::Closed => None,
+            ChannelError::SizeLimit => None,
+            ChannelError::Serialize(ref err) => Some(err),
+            ChannelError::Deserialize(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<IoError> for ChannelError {
+    fn from(err: IoError) -> ChannelError {
+        ChannelError::IoError(err)
+    }
+}
+
+impl From<FrameError> for ChannelError {
+    fn from(err: FrameError) -> ChannelError {
+        match err {
+            FrameError::IoError(err) => ChannelError::IoError(err),
+            FrameError::Truncated => ChannelError::IoError(
+                IoError::new(io::ErrorKind::UnexpectedEof, "the stream ended in the middle of a message")),
+            FrameError::SizeLimit => ChannelError::SizeLimit,
+            // A `TypedStream` doesn't number its frames.
+            FrameError::FrameGap { .. } => ChannelError::IoError(
+                IoError::new(io::ErrorKind::InvalidData, "a message arrived out of sequence")),
+        }
+    }
+}
+
+impl From<SerializeError> for ChannelError {
+    fn from(err: SerializeError) -> ChannelError {
+        match err {
+            SerializeError::IoError(err) => ChannelError::IoError(err),
+            SerializeError::SizeLimit => ChannelError::SizeLimit,
+            err => ChannelError::Serialize(err),
+        }
+    }
+}
+
+impl From<DeserializeError> for ChannelError {
+    fn from(err: DeserializeError) -> ChannelError {
+        ChannelError::Deserialize(err)
+    }
+}
+
+/// Sends messages of type `T` and receives messages of type `U` over a
+/// stream `S`.
+///
+/// Both ends of a connection use a `TypedStream`, with `T` and `U` swapped.
+/// Reads and writes that only get part of a message through are retried
+/// until the whole message has been sent or received, so a `TypedStream`
+/// is meant for blocking streams.
+pub struct TypedStream<T, U, S> {
+    frames: FrameReader<S>,
+    max_len: Option<u64>,
+    _types: PhantomData<fn(&T) -> U>,
+}
+
+impl<T, U, S> TypedStream<T, U, S>
+    where T: serde::Serialize,
+          U: serde::Deserialize,
+          S: Read + Write,
+{
+    /// Creates a `TypedStream` with no maximum message length.
+    pub fn new(stream: S) -> TypedStream<T, U, S> {
+        TypedStream {
+            frames: FrameReader::new(stream),
+            max_len: None,
+            _types: PhantomData,
+        }
+    }
+
+    /// Sets the longest message, in bytes, to send or receive.
+    ///
+    /// Messages that are longer are rejected when sending, and when
+    /// receiving before any memory is allocated for them.  Without a
+    /// maximum, the other end can make this end allocate as much memory as
+    /// it likes.
+    pub fn max_message_len(mut self, max_len: u64) -> TypedStream<T, U, S> {
+        self.frames = self.frames.max_frame_len(max_len);
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Sends a message, and flushes the stream.
+    pub fn send(&mut self, message: &T) -> ChannelResult<()> {
+        let payload = match self.max_len {
+            Some(max_len) => try!(serialize(message, Bounded(max_len))),
+            None => try!(serialize(message, Infinite)),
+        };
+
+        let stream = self.frames.get_mut();
+        try!(stream.write_u64::<BigEndian>(payload.len() as u64));
+        try!(stream.write_all(&payload));
+        try!(stream.flush());
+        Ok(())
+    }
+
+    /// Waits for the next message and receives it.
+    ///
+    /// Returns `ChannelError::Closed` if the other end closed the stream
+    /// instead of sending another message.
+    pub fn recv(&mut self) -> ChannelResult<U> {
+        let mut frame = match try!(self.frames.next_frame()) {
+            Some(frame) => frame,
+            None => return Err(ChannelError::Closed),
+        };
+        Ok(try!(deserialize_from(&mut frame, Infinite)))
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        self.frames.get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    ///
+    /// Reading from or writing to the stream directly will most likely
+    /// leave it in the middle of a message.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.frames.get_mut()
+    }
+
+    /// Unwraps the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.frames.into_inner()
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::{Read, Write};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use ::{SizeLimit, Infinite};
+
+use super::{Encode, Decode, Encoder, Decoder, EncodeResult, DecodeResult, DecodeError};
+
+/// Encodes an object into a `Vec` of bytes in aligned mode.
+///
+/// Every number is placed at a multiple of its size from the start of the
+/// output, so that once the output is loaded at an aligned address (as a
+/// memory-mapped file is), numbers can be read in place.  See
+/// `Encoder::set_aligned`.
+pub fn encode_aligned<T: Encode + ?Sized>(t: &T) -> EncodeResult<Vec<u8>> {
+    let mut w = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut w);
+        encoder.set_aligned(true);
+        try!(t.encode(&mut encoder));
+    }
+    Ok(w)
+}
+
+/// Decodes an object from a slice of bytes written by `encode_aligned`.
+pub fn decode_aligned<T: Decode>(b: &[u8]) -> DecodeResult<T> {
+    let mut b = b;
+    let mut decoder = Decoder::new(&mut b, Infinite);
+    decoder.set_aligned(true);
+    T::decode(&mut decoder)
+}
+
+/// A `Vec` that is encoded with a fixed stride, so that its elements can be
+/// read one at a time, by index.
+///
+/// It is written with `Encoder::write_fixed_seq`: the length, then the
+/// stride, then each element padded out to the stride.  `FixedSeq` reads
+/// single elements back out of the encoded bytes without decoding the rest.
+/// Elements that are all the same size, like structs of numbers, waste no
+/// space beyond their alignment.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct FixedVec<T>(pub Vec<T>);
+
+impl<T> FixedVec<T> {
+    /// Unwraps the `Vec`.
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> From<Vec<T>> for FixedVec<T> {
+    fn from(values: Vec<T>) -> FixedVec<T> {
+        FixedVec(values)
+    }
+}
+
+impl<T: Encode> Encode for FixedVec<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        encoder.write_fixed_seq(&self.0)
+    }
+}
+
+impl<T: Decode> Decode for FixedVec<T> {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<FixedVec<T>> {
+        decoder.read_fixed_seq().map(FixedVec)
+    }
+}
+
+/// Random access into an encoded `FixedVec`.
+///
+/// ```rust,ignore
+/// let file = unsafe { Mmap::map(&File::open("samples.bin")?)? };
+/// let samples = FixedSeq::new(&file[..], true)?;
+/// let last: Sample = samples.get(samples.len() - 1)?.unwrap();
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct FixedSeq<'a> {
+    elements: &'a [u8],
+    len: usize,
+    stride: usize,
+    aligned: bool,
+}
+
+impl<'a> FixedSeq<'a> {
+    /// Reads the length and stride at the start of `bytes`, which must be
+    /// where a `FixedVec` was encoded.
+    ///
+    /// `aligned` says whether it was encoded in aligned mode.  Returns an
+    /// error if `bytes` is too short to hold every element.
+    pub fn new(bytes: &'a [u8], aligned: bool) -> DecodeResult<FixedSeq<'a>> {
+        if bytes.len() < 16 {
+            return Err(truncated());
+        }
+        let len = BigEndian::read_u64(&bytes[..8]);
+        let stride = BigEndian::read_u64(&bytes[8..16]);
+        let rest = &bytes[16..];
+        match len.checked_mul(stride) {
+            Some(size) if size <= rest.len() as u64 => Ok(FixedSeq {
+                elements: &rest[..size as usize],
+                len: len as usize,
+                stride: stride as usize,
+                aligned: aligned,
+            }),
+            _ => Err(truncated()),
+        }
+    }
+
+    /// Returns the number of elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes each element takes up.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Returns the number of bytes the whole sequence takes up, so that
+    /// whatever was encoded after it can be found.
+    pub fn encoded_len(&self) -> usize {
+        16 + self.elements.len()
+    }
+
+    /// Returns the encoded bytes of an element, padding included, or `None`
+    /// if `index` is out of range.
+    pub fn get_bytes(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.len {
+            return None;
+        }
+        let start = index * self.stride;
+        Some(&self.elements[start..start + self.stride])
+    }
+
+    /// Decodes one element, or returns `None` if `index` is out of range.
+    pub fn get<T: Decode>(&self, index: usize) -> DecodeResult<Option<T>> {
+        let mut bytes = match self.get_bytes(index) {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let mut decoder = Decoder::new(&mut bytes, Infinite);
+        decoder.set_aligned(self.aligned);
+        T::decode(&mut decoder).map(Some)
+    }
+}
+
+fn truncated() -> DecodeError {
+    DecodeError::invalid_encoding("fixed sequence is longer than its input", None)
+}
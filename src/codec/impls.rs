@@ -0,0 +1,304 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hash};
+use std::num;
+use std::io::{Read, Write};
+
+use ::SizeLimit;
+
+use super::{Encode, Decode, Encoder, Decoder, EncodeResult, DecodeResult, DecodeError};
+
+macro_rules! impl_primitive {
+    ($ty:ty, $write_method:ident, $read_method:ident) => {
+        impl Encode for $ty {
+            fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+                encoder.$write_method(*self)
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<$ty> {
+                decoder.$read_method()
+            }
+        }
+    }
+}
+
+impl_primitive!(bool, write_bool, read_bool);
+impl_primitive!(u8, write_u8, read_u8);
+impl_primitive!(u16, write_u16, read_u16);
+impl_primitive!(u32, write_u32, read_u32);
+impl_primitive!(u64, write_u64, read_u64);
+impl_primitive!(usize, write_usize, read_usize);
+impl_primitive!(i8, write_i8, read_i8);
+impl_primitive!(i16, write_i16, read_i16);
+impl_primitive!(i32, write_i32, read_i32);
+impl_primitive!(i64, write_i64, read_i64);
+impl_primitive!(isize, write_isize, read_isize);
+impl_primitive!(f32, write_f32, read_f32);
+impl_primitive!(f64, write_f64, read_f64);
+impl_primitive!(char, write_char, read_char);
+
+macro_rules! impl_non_zero {
+    ($($ty:ident($int:ty)),*) => {
+        $(
+            impl Encode for num::$ty {
+                fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+                    self.get().encode(encoder)
+                }
+            }
+
+            impl Decode for num::$ty {
+                fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<num::$ty> {
+                    let value: $int = try!(Decode::decode(decoder));
+                    match num::$ty::new(value) {
+                        Some(value) => Ok(value),
+                        None => Err(DecodeError::invalid_encoding(
+                            "zero in a non-zero integer",
+                            Some(format!("expected a {}", stringify!($ty))))),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+impl_non_zero!(NonZeroU8(u8), NonZeroU16(u16), NonZeroU32(u32), NonZeroU64(u64), NonZeroUsize(usize),
+               NonZeroI8(i8), NonZeroI16(i16), NonZeroI32(i32), NonZeroI64(i64), NonZeroIsize(isize));
+
+impl Encode for () {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        encoder.write_unit()
+    }
+}
+
+impl Decode for () {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<()> {
+        decoder.read_unit()
+    }
+}
+
+impl Encode for str {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        encoder.write_str(self)
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        encoder.write_str(self)
+    }
+}
+
+impl Decode for String {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<String> {
+        decoder.read_string()
+    }
+}
+
+impl<'a, T: Encode + ?Sized> Encode for &'a T {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        (**self).encode(encoder)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        (**self).encode(encoder)
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<Box<T>> {
+        T::decode(decoder).map(Box::new)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        match *self {
+            None => encoder.write_option_tag(false),
+            Some(ref v) => {
+                try!(encoder.write_option_tag(true));
+                v.encode(encoder)
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<Option<T>> {
+        if try!(decoder.read_option_tag()) {
+            T::decode(decoder).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        try!(encoder.write_len(self.len()));
+        for elem in self {
+            try!(elem.encode(encoder));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Encode, const N: usize> Encode for [T; N] {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        self[..].encode(encoder)
+    }
+}
+
+impl<T: Decode, const N: usize> Decode for [T; N] {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<[T; N]> {
+        let len = try!(decoder.read_len());
+        if len != N {
+            return Err(DecodeError::invalid_encoding(
+                "invalid length when decoding fixed size array",
+                Some(format!("Expected {}, got {}", N, len))));
+        }
+        let mut elems = Vec::with_capacity(N);
+        for _ in 0..N {
+            elems.push(try!(T::decode(decoder)));
+        }
+        match elems.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("exactly N elements were decoded"),
+        }
+    }
+}
+
+macro_rules! impl_seq {
+    ($ty:ident, [$($bound:ident),*], $with_capacity:expr, $insert:ident) => {
+        impl<T: Encode $(+ $bound)*> Encode for $ty<T> {
+            fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+                try!(encoder.write_len(self.len()));
+                for elem in self.iter() {
+                    try!(elem.encode(encoder));
+                }
+                Ok(())
+            }
+        }
+
+        impl<T: Decode $(+ $bound)*> Decode for $ty<T> {
+            fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<$ty<T>> {
+                let len = try!(decoder.read_len());
+                // The length comes straight off the wire, so don't trust it
+                // with a huge up front allocation.
+                let mut seq = $with_capacity(::std::cmp::min(len, 4096));
+                for _ in 0..len {
+                    seq.$insert(try!(T::decode(decoder)));
+                }
+                Ok(seq)
+            }
+        }
+    }
+}
+
+impl_seq!(Vec, [], Vec::with_capacity, push);
+impl_seq!(VecDeque, [], VecDeque::with_capacity, push_back);
+impl_seq!(BTreeSet, [Ord], |_| BTreeSet::new(), insert);
+
+impl<T: Encode + Eq + Hash, S: BuildHasher> Encode for HashSet<T, S> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        try!(encoder.write_len(self.len()));
+        for elem in self.iter() {
+            try!(elem.encode(encoder));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode + Eq + Hash, S: BuildHasher + Default> Decode for HashSet<T, S> {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<HashSet<T, S>> {
+        let len = try!(decoder.read_len());
+        let mut set = HashSet::with_hasher(S::default());
+        for _ in 0..len {
+            set.insert(try!(T::decode(decoder)));
+        }
+        Ok(set)
+    }
+}
+
+impl<K: Encode + Eq + Hash, V: Encode, S: BuildHasher> Encode for HashMap<K, V, S> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        try!(encoder.write_len(self.len()));
+        for (k, v) in self.iter() {
+            try!(k.encode(encoder));
+            try!(v.encode(encoder));
+        }
+        Ok(())
+    }
+}
+
+impl<K: Decode + Eq + Hash, V: Decode, S: BuildHasher + Default> Decode for HashMap<K, V, S> {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<HashMap<K, V, S>> {
+        let len = try!(decoder.read_len());
+        let mut map = HashMap::with_hasher(S::default());
+        for _ in 0..len {
+            let k = try!(K::decode(decoder));
+            let v = try!(V::decode(decoder));
+            map.insert(k, v);
+        }
+        Ok(map)
+    }
+}
+
+impl<K: Encode + Ord, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        try!(encoder.write_len(self.len()));
+        for (k, v) in self.iter() {
+            try!(k.encode(encoder));
+            try!(v.encode(encoder));
+        }
+        Ok(())
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<BTreeMap<K, V>> {
+        let len = try!(decoder.read_len());
+        let mut map = BTreeMap::new();
+        for _ in 0..len {
+            let k = try!(K::decode(decoder));
+            let v = try!(V::decode(decoder));
+            map.insert(k, v);
+        }
+        Ok(map)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: Encode),+> Encode for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+                let ($(ref $name,)+) = *self;
+                $(try!($name.encode(encoder));)+
+                Ok(())
+            }
+        }
+
+        impl<$($name: Decode),+> Decode for ($($name,)+) {
+            fn decode<R: Read, Lim: SizeLimit>(decoder: &mut Decoder<R, Lim>) -> DecodeResult<($($name,)+)> {
+                Ok(($(try!($name::decode(decoder)),)+))
+            }
+        }
+    }
+}
+
+impl_tuple! { A }
+impl_tuple! { A B }
+impl_tuple! { A B C }
+impl_tuple! { A B C D }
+impl_tuple! { A B C D E }
+impl_tuple! { A B C D E F }
+impl_tuple! { A B C D E F G }
+impl_tuple! { A B C D E F G H }
+impl_tuple! { A B C D E F G H I }
+impl_tuple! { A B C D E F G H I J }
+impl_tuple! { A B C D E F G H I J K }
+impl_tuple! { A B C D E F G H I J K L }
@@ -0,0 +1,163 @@
+//! A collection of encoding and decoding functions built on bincode's own
+//! `Encode` and `Decode` traits.
+//!
+//! These traits don't go through `serde` or `rustc_serialize` at all: an
+//! implementation writes its fields straight to an `Encoder` and reads them
+//! back from a `Decoder`.  This keeps compile times and code size down for
+//! projects that only ever need bincode, and produces exactly the same bytes
+//! as the other two frontends.
+//!
+//! Implementations are provided for the primitive types and the common std
+//! containers.  With the `derive` feature enabled, `#[derive(Encode, Decode)]`
+//! writes them for your own structs and enums.
+//!
+//! `encode_aligned` lays numbers out at their natural alignment, and a
+//! `FixedVec` gives every element the same stride, so that an encoded file
+//! can be memory-mapped and its elements read by index with `FixedSeq`.
+//!
+//! ```rust
+//! use bincode::Infinite;
+//! use bincode::codec::{encode, decode};
+//!
+//! let target = (1u32, Some("hello world".to_string()));
+//! let encoded: Vec<u8> = encode(&target, Infinite).unwrap();
+//! let decoded: (u32, Option<String>) = decode(&encoded[..]).unwrap();
+//! assert_eq!(target, decoded);
+//! ```
+
+use std::io::{Write, Read};
+use ::{SizeLimit, Infinite};
+use ::tee::TeeWriteAll;
+
+pub use self::writer::{Encoder, EncodeResult, EncodeError};
+pub use self::reader::{Decoder, DecodeResult, DecodeError};
+pub use self::pod::{Pod, PodVec};
+pub use self::aligned::{encode_aligned, decode_aligned, FixedVec, FixedSeq};
+
+use self::writer::SizeCounter;
+
+mod aligned;
+mod impls;
+mod pod;
+mod reader;
+mod writer;
+
+/// A value that can be written in the bincode format.
+pub trait Encode {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()>;
+}
+
+/// A value that can be read from the bincode format.
+pub trait Decode: Sized {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<Self>;
+}
+
+/// Encodes an encodable object into a `Vec` of bytes.
+///
+/// If the encoding would take more bytes than allowed by `size_limit`,
+/// an error is returned.
+pub fn encode<T: Encode + ?Sized, L: SizeLimit>(t: &T, size_limit: L) -> EncodeResult<Vec<u8>> {
+    let mut w = match size_limit.limit() {
+        Some(l) => {
+            let actual_size = try!(encoded_size_bounded(t, l).ok_or(EncodeError::SizeLimit));
+            Vec::with_capacity(actual_size as usize)
+        }
+        None => Vec::new()
+    };
+
+    try!(encode_into(t, &mut w, Infinite));
+    Ok(w)
+}
+
+/// Encodes an encodable object into a `Vec` of bytes that is allocated
+/// exactly once.
+///
+/// The size of the value is worked out first, so that the `Vec` starts out
+/// with exactly the capacity it needs instead of growing as it is written
+/// to.  That means walking the value twice, which is still cheaper than
+/// reallocating and copying the output for all but the smallest messages.
+pub fn encode_sized<T: Encode + ?Sized>(t: &T) -> EncodeResult<Vec<u8>> {
+    let mut w = Vec::with_capacity(encoded_size(t) as usize);
+    try!(encode_into(t, &mut w, Infinite));
+    Ok(w)
+}
+
+/// Encodes an object directly into a `Writer`.
+///
+/// If the encoding would take more bytes than allowed by `size_limit`, an error
+/// is returned and *no bytes* will be written into the `Writer`.
+///
+/// If this returns an `EncodeError` (other than SizeLimit), assume that the
+/// writer is in an invalid state, as writing could bail out in the middle of
+/// encoding.
+pub fn encode_into<T: Encode + ?Sized, W: Write, L: SizeLimit>(t: &T,
+                                                               w: &mut W,
+                                                               size_limit: L)
+                                                               -> EncodeResult<()> {
+    if let Some(x) = size_limit.limit() {
+        if encoded_size_bounded(t, x).is_none() {
+            return Err(EncodeError::SizeLimit);
+        }
+    }
+
+    t.encode(&mut Encoder::new(w))
+}
+
+/// Encodes an object directly into several `Writer`s at once.
+///
+/// The value is only encoded once, and every byte of it is written to each of
+/// the writers in turn, so a message can go to a file and a socket without
+/// being encoded twice or buffered.  Use a `TeeWrite` instead when there is a
+/// fixed number of writers.
+///
+/// If the encoding would take more bytes than allowed by `size_limit`, an error
+/// is returned and *no bytes* will be written into any of the writers.
+pub fn encode_into_many<T: Encode + ?Sized, L: SizeLimit>(t: &T,
+                                                          writers: &mut [&mut dyn Write],
+                                                          size_limit: L)
+                                                          -> EncodeResult<()> {
+    encode_into(t, &mut TeeWriteAll::new(writers), size_limit)
+}
+
+/// Decodes a slice of bytes into an object.
+///
+/// This method does not have a size-limit because if you already have the bytes
+/// in memory, then you don't gain anything by having a limiter.
+pub fn decode<T: Decode>(b: &[u8]) -> DecodeResult<T> {
+    let mut b = b;
+    decode_from(&mut b, Infinite)
+}
+
+/// Decodes an object directly from a `Buffer`ed Reader.
+///
+/// If the provided `SizeLimit` is reached, the decode will bail immediately.
+/// A SizeLimit can help prevent an attacker from flooding your server with
+/// a neverending stream of values that runs your server out of memory.
+///
+/// If this returns a `DecodeError`, assume that the buffer that you passed
+/// in is in an invalid state, as the error could be returned during any point
+/// in the reading.
+pub fn decode_from<R: Read, T: Decode, L: SizeLimit>(r: &mut R, size_limit: L) -> DecodeResult<T> {
+    T::decode(&mut Decoder::new(r, size_limit))
+}
+
+/// Returns the size that an object would be if encoded using bincode.
+pub fn encoded_size<T: Encode + ?Sized>(t: &T) -> u64 {
+    use std::u64::MAX;
+    let mut counter = SizeCounter::new(MAX);
+    t.encode(&mut Encoder::new(&mut counter)).ok();
+    counter.written
+}
+
+/// Given a maximum size limit, check how large an object would be if it
+/// were to be encoded.
+///
+/// If it can be encoded in `max` or fewer bytes, that number will be returned
+/// inside `Some`.  If it goes over bounds, then None is returned.
+pub fn encoded_size_bounded<T: Encode + ?Sized>(t: &T, max: u64) -> Option<u64> {
+    let mut counter = SizeCounter::new(max);
+    match t.encode(&mut Encoder::new(&mut counter)) {
+        Ok(()) => Some(counter.written),
+        Err(_) => None,
+    }
+}
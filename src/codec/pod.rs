@@ -0,0 +1,183 @@
+use std::cmp;
+use std::io::{Read, Write};
+use std::mem;
+use std::slice;
+
+use ::SizeLimit;
+
+use super::{Encode, Decode, Encoder, Decoder, EncodeResult, DecodeResult};
+
+/// The most elements a `PodVec` copies at once on a little-endian target.
+const CHUNK: usize = 256;
+
+/// A plain-old-data type that can be encoded by copying its memory.
+///
+/// Long runs of `Pod` values, read and written through `PodVec` or the
+/// `write_pod_slice` and `read_pod_vec` methods, are copied in bulk instead
+/// of being encoded field by field.  On big-endian targets the bytes are
+/// copied as they are; on little-endian ones each value is byte-swapped in
+/// a buffer first, which is still much faster than going through the
+/// `Encoder` for every field.  If `PACKED` is `false`, or in aligned mode,
+/// the values are encoded with `Encode` and `Decode` as usual, so the output
+/// is the same either way.
+///
+/// With the `derive` feature, `#[derive(Pod)]` implements this for structs
+/// whose fields are all `Pod`.  `PACKED` is only set for structs that are
+/// `#[repr(C)]` and have no padding.
+///
+/// This is implemented for the integer and float types.  It is not for
+/// `bool`, `char`, `usize`, or `isize`, since not every bit pattern is one
+/// of their values or since they are encoded at a different size, nor for
+/// arrays, which are encoded with a length.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value of the type, and the `Encode`
+/// and `Decode` implementations must agree with what `PACKED` promises.
+pub unsafe trait Pod: Copy + Encode + Decode + 'static {
+    /// Whether the value's memory, once `swap_to_wire` has been called, is
+    /// exactly its encoding: its fields are in declaration order with no
+    /// padding between them.
+    const PACKED: bool;
+
+    /// Converts every field between native and big-endian byte order, in
+    /// place.
+    ///
+    /// This does nothing on big-endian targets, and undoes itself on
+    /// little-endian ones.
+    fn swap_to_wire(&mut self);
+}
+
+macro_rules! impl_pod_int {
+    ($($ty:ty),*) => {
+        $(
+            unsafe impl Pod for $ty {
+                const PACKED: bool = true;
+
+                fn swap_to_wire(&mut self) {
+                    *self = self.to_be();
+                }
+            }
+        )*
+    }
+}
+
+impl_pod_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+unsafe impl Pod for f32 {
+    const PACKED: bool = true;
+
+    fn swap_to_wire(&mut self) {
+        *self = f32::from_bits(self.to_bits().to_be());
+    }
+}
+
+unsafe impl Pod for f64 {
+    const PACKED: bool = true;
+
+    fn swap_to_wire(&mut self) {
+        *self = f64::from_bits(self.to_bits().to_be());
+    }
+}
+
+fn as_bytes<T: Pod>(values: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values)) }
+}
+
+fn as_bytes_mut<T: Pod>(values: &mut [T]) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, mem::size_of_val(values)) }
+}
+
+impl<'a, W: Write> Encoder<'a, W> {
+    /// Writes a slice of `Pod` values, in bulk if the type allows it.
+    ///
+    /// The output is the same as that of encoding the slice itself.
+    pub fn write_pod_slice<T: Pod>(&mut self, values: &[T]) -> EncodeResult<()> {
+        try!(self.write_len(values.len()));
+        if !T::PACKED || self.is_aligned() {
+            for value in values {
+                try!(value.encode(self));
+            }
+            return Ok(());
+        }
+
+        if cfg!(target_endian = "big") {
+            return self.write_raw(as_bytes(values));
+        }
+        let mut buffer = Vec::with_capacity(cmp::min(values.len(), CHUNK));
+        for chunk in values.chunks(CHUNK) {
+            buffer.clear();
+            buffer.extend_from_slice(chunk);
+            for value in &mut buffer {
+                value.swap_to_wire();
+            }
+            try!(self.write_raw(as_bytes(&buffer)));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: Read, L: SizeLimit> Decoder<'a, R, L> {
+    /// Reads a `Vec` of `Pod` values, in bulk if the type allows it.
+    ///
+    /// This reads the same input as decoding a `Vec<T>`.
+    pub fn read_pod_vec<T: Pod>(&mut self) -> DecodeResult<Vec<T>> {
+        let len = try!(self.read_len());
+        if !T::PACKED || self.is_aligned() {
+            let mut values = Vec::with_capacity(cmp::min(len, CHUNK));
+            for _ in 0..len {
+                values.push(try!(T::decode(self)));
+            }
+            return Ok(values);
+        }
+
+        // The values are read a chunk at a time, so that a length prefix
+        // that is larger than the input or the size limit doesn't get
+        // allocated up front.
+        let mut values: Vec<T> = Vec::with_capacity(cmp::min(len, CHUNK));
+        while values.len() < len {
+            let start = values.len();
+            let count = cmp::min(len - start, CHUNK);
+            values.extend((0..count).map(|_| unsafe { mem::zeroed::<T>() }));
+            try!(self.read_raw(as_bytes_mut(&mut values[start..])));
+        }
+        if cfg!(target_endian = "little") {
+            for value in &mut values {
+                value.swap_to_wire();
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// A `Vec` of plain-old-data values that is encoded in bulk.
+///
+/// It is encoded just like a `Vec<T>`, and can take the place of one in a
+/// type that holds many samples or records of the same kind.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct PodVec<T>(pub Vec<T>);
+
+impl<T> PodVec<T> {
+    /// Unwraps the `Vec`.
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> From<Vec<T>> for PodVec<T> {
+    fn from(values: Vec<T>) -> PodVec<T> {
+        PodVec(values)
+    }
+}
+
+impl<T: Pod> Encode for PodVec<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> EncodeResult<()> {
+        encoder.write_pod_slice(&self.0)
+    }
+}
+
+impl<T: Pod> Decode for PodVec<T> {
+    fn decode<R: Read, L: SizeLimit>(decoder: &mut Decoder<R, L>) -> DecodeResult<PodVec<T>> {
+        decoder.read_pod_vec().map(PodVec)
+    }
+}
@@ -0,0 +1,354 @@
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use std::io::Error as IoError;
+
+use byteorder::{BigEndian, ReadBytesExt};
+use num_traits;
+
+use ::SizeLimit;
+use ::format;
+
+use super::Decode;
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct InvalidEncoding {
+    desc: &'static str,
+    detail: Option<String>,
+}
+
+impl fmt::Display for InvalidEncoding {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidEncoding { detail: None, desc } =>
+                write!(fmt, "{}", desc),
+            InvalidEncoding { detail: Some(ref detail), desc } =>
+                write!(fmt, "{} ({})", desc, detail)
+        }
+    }
+}
+
+/// An error that can be produced during decoding.
+///
+/// If decoding from a Buffer, assume that the buffer has been left
+/// in an invalid state.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// If the error stems from the reader that is being used
+    /// during decoding, that error will be stored and returned here.
+    IoError(IoError),
+    /// If the bytes in the reader are not decodable because of an invalid
+    /// encoding, this error will be returned.  This error is only possible
+    /// if a stream is corrupted.  A stream produced from `encode` or `encode_into`
+    /// should **never** produce an InvalidEncoding error.
+    InvalidEncoding(InvalidEncoding),
+    /// If decoding a message takes more than the provided size limit, this
+    /// error is returned.
+    SizeLimit,
+    /// If a length in the input is too large to be added up or to fit in a
+    /// `usize`, which on a 32-bit target could otherwise wrap around to a
+    /// small number, this error is returned.
+    LengthOverflow,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::IoError(ref ioerr) =>
+                write!(fmt, "IoError: {}", ioerr),
+            DecodeError::InvalidEncoding(ref ib) =>
+                write!(fmt, "InvalidEncoding: {}", ib),
+            DecodeError::SizeLimit =>
+                write!(fmt, "SizeLimit"),
+            DecodeError::LengthOverflow =>
+                write!(fmt, "LengthOverflow"),
+        }
+    }
+}
+
+pub type DecodeResult<T> = Result<T, DecodeError>;
+
+fn wrap_io(err: IoError) -> DecodeError {
+    DecodeError::IoError(err)
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        match *self {
+            DecodeError::IoError(ref err) => Error::description(err),
+            DecodeError::InvalidEncoding(ref ib) => ib.desc,
+            DecodeError::SizeLimit => "the size limit for decoding has been reached",
+            DecodeError::LengthOverflow => "a length in the input is too large",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            DecodeError::IoError(ref err) => err.cause(),
+            DecodeError::InvalidEncoding(_) => None,
+            DecodeError::SizeLimit => None,
+            DecodeError::LengthOverflow => None,
+        }
+    }
+}
+
+impl From<IoError> for DecodeError {
+    fn from(err: IoError) -> DecodeError {
+        DecodeError::IoError(err)
+    }
+}
+
+impl DecodeError {
+    /// Creates an `InvalidEncoding` error.
+    ///
+    /// This is meant for `Decode` implementations that find a value they
+    /// can't represent, such as an unknown enum tag.
+    pub fn invalid_encoding(desc: &'static str, detail: Option<String>) -> DecodeError {
+        DecodeError::InvalidEncoding(InvalidEncoding {
+            desc: desc,
+            detail: detail,
+        })
+    }
+}
+
+/// A Decoder that reads the bincode wire format from a Reader.
+///
+/// `Decode` implementations call the `read_*` methods in the same order as
+/// the matching `Encode` implementation called the `write_*` methods.
+pub struct Decoder<'a, R: 'a, L: SizeLimit> {
+    reader: &'a mut R,
+    size_limit: L,
+    read: u64,
+    aligned: bool,
+}
+
+impl<'a, R: Read, L: SizeLimit> Decoder<'a, R, L> {
+    pub fn new(r: &'a mut R, size_limit: L) -> Decoder<'a, R, L> {
+        Decoder {
+            reader: r,
+            size_limit: size_limit,
+            read: 0,
+            aligned: false,
+        }
+    }
+
+    /// Turns aligned mode on or off, to read the output of an `Encoder` in
+    /// aligned mode.  The padding is skipped without being checked.
+    pub fn set_aligned(&mut self, aligned: bool) {
+        self.aligned = aligned;
+    }
+
+    /// Returns whether aligned mode is on.
+    pub fn is_aligned(&self) -> bool {
+        self.aligned
+    }
+
+    /// Reads and throws away `count` bytes.
+    pub fn skip(&mut self, count: u64) -> DecodeResult<()> {
+        let mut buf = [0; 64];
+        let mut left = count;
+        while left > 0 {
+            let len = ::std::cmp::min(left, buf.len() as u64) as usize;
+            try!(self.read_raw(&mut buf[..len]));
+            left -= len as u64;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes read from the contained Reader.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    fn read_bytes(&mut self, count: u64) -> DecodeResult<()> {
+        if !self.size_limit.add(count) {
+            return Err(DecodeError::SizeLimit);
+        }
+        self.read = match self.read.checked_add(count) {
+            Some(read) => read,
+            None => return Err(DecodeError::LengthOverflow),
+        };
+        Ok(())
+    }
+
+    fn read_type<T>(&mut self) -> DecodeResult<()> {
+        use std::mem::size_of;
+        let size = size_of::<T>() as u64;
+        if self.aligned {
+            let padding = (size - self.read % size) % size;
+            try!(self.skip(padding));
+        }
+        self.read_bytes(size)
+    }
+
+    pub fn read_unit(&mut self) -> DecodeResult<()> {
+        Ok(())
+    }
+
+    pub fn read_bool(&mut self) -> DecodeResult<bool> {
+        let x = try!(self.read_u8());
+        match format::bool_from_byte(x) {
+            Some(x) => Ok(x),
+            None => Err(DecodeError::invalid_encoding(
+                "invalid u8 when decoding bool",
+                Some(format!("Expected 0 or 1, got {}", x)))),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> DecodeResult<u8> {
+        try!(self.read_type::<u8>());
+        self.reader.read_u8().map_err(wrap_io)
+    }
+
+    pub fn read_u16(&mut self) -> DecodeResult<u16> {
+        try!(self.read_type::<u16>());
+        self.reader.read_u16::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_u32(&mut self) -> DecodeResult<u32> {
+        try!(self.read_type::<u32>());
+        self.reader.read_u32::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_u64(&mut self) -> DecodeResult<u64> {
+        try!(self.read_type::<u64>());
+        self.reader.read_u64::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_usize(&mut self) -> DecodeResult<usize> {
+        let value = try!(self.read_u64());
+        match num_traits::cast(value) {
+            Some(value) => Ok(value),
+            None => Err(DecodeError::invalid_encoding(
+                "usize out of range",
+                Some(format!("{} does not fit in a usize", value)))),
+        }
+    }
+
+    pub fn read_i8(&mut self) -> DecodeResult<i8> {
+        try!(self.read_type::<i8>());
+        self.reader.read_i8().map_err(wrap_io)
+    }
+
+    pub fn read_i16(&mut self) -> DecodeResult<i16> {
+        try!(self.read_type::<i16>());
+        self.reader.read_i16::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_i32(&mut self) -> DecodeResult<i32> {
+        try!(self.read_type::<i32>());
+        self.reader.read_i32::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_i64(&mut self) -> DecodeResult<i64> {
+        try!(self.read_type::<i64>());
+        self.reader.read_i64::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_isize(&mut self) -> DecodeResult<isize> {
+        let value = try!(self.read_i64());
+        match num_traits::cast(value) {
+            Some(value) => Ok(value),
+            None => Err(DecodeError::invalid_encoding(
+                "isize out of range",
+                Some(format!("{} does not fit in an isize", value)))),
+        }
+    }
+
+    pub fn read_f32(&mut self) -> DecodeResult<f32> {
+        try!(self.read_type::<f32>());
+        self.reader.read_f32::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_f64(&mut self) -> DecodeResult<f64> {
+        try!(self.read_type::<f64>());
+        self.reader.read_f64::<BigEndian>().map_err(wrap_io)
+    }
+
+    pub fn read_char(&mut self) -> DecodeResult<char> {
+        let first_byte = try!(self.read_u8());
+        let width = format::char_width(first_byte);
+        if width == 0 {
+            return Err(DecodeError::invalid_encoding("Invalid char encoding", None));
+        }
+
+        try!(self.read_bytes(width as u64 - 1));
+        let mut buf = [first_byte, 0, 0, 0];
+        try!(self.reader.read_exact(&mut buf[1..width]));
+
+        match format::char_from_bytes(&buf[..width]) {
+            Some(c) => Ok(c),
+            None => Err(DecodeError::invalid_encoding("Invalid char encoding", None)),
+        }
+    }
+
+    /// Reads the length prefix of a string, sequence, or map.
+    pub fn read_len(&mut self) -> DecodeResult<usize> {
+        self.read_usize()
+    }
+
+    pub fn read_string(&mut self) -> DecodeResult<String> {
+        let bytes = try!(self.read_byte_buf());
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(err) => Err(DecodeError::invalid_encoding(
+                "error while decoding utf8 string",
+                Some(format!("Decode error: {}", err)))),
+        }
+    }
+
+    /// Reads a length-prefixed run of bytes.
+    pub fn read_byte_buf(&mut self) -> DecodeResult<Vec<u8>> {
+        let len = try!(self.read_u64());
+        try!(self.read_bytes(len));
+
+        let mut buffer = Vec::new();
+        try!(self.reader.by_ref().take(len).read_to_end(&mut buffer));
+        if buffer.len() as u64 != len {
+            return Err(DecodeError::IoError(IoError::new(::std::io::ErrorKind::UnexpectedEof,
+                                                         "unexpected end of input")));
+        }
+        Ok(buffer)
+    }
+
+    /// Fills `buf` with bytes as they are, without a length in front.
+    pub fn read_raw(&mut self, buf: &mut [u8]) -> DecodeResult<()> {
+        try!(self.read_bytes(buf.len() as u64));
+        self.reader.read_exact(buf).map_err(wrap_io)
+    }
+
+    /// Reads a sequence written by `Encoder::write_fixed_seq`.
+    pub fn read_fixed_seq<T: Decode>(&mut self) -> DecodeResult<Vec<T>> {
+        let len = try!(self.read_len());
+        let stride = try!(self.read_u64());
+        let mut values = Vec::with_capacity(::std::cmp::min(len, 4096));
+        for _ in 0..len {
+            let start = self.read;
+            values.push(try!(T::decode(self)));
+            let used = self.read - start;
+            if used > stride {
+                return Err(DecodeError::invalid_encoding(
+                    "value is longer than the stride of its sequence",
+                    Some(format!("Expected at most {} bytes, read {}", stride, used))));
+            }
+            try!(self.skip(stride - used));
+        }
+        Ok(values)
+    }
+
+    /// Reads the tag of an enum variant.
+    pub fn read_variant(&mut self) -> DecodeResult<u32> {
+        self.read_u32()
+    }
+
+    /// Reads the tag that precedes an `Option`, returning `true` for `Some`.
+    pub fn read_option_tag(&mut self) -> DecodeResult<bool> {
+        let x = try!(self.read_u8());
+        match format::option_from_tag(x) {
+            Some(is_some) => Ok(is_some),
+            None => Err(DecodeError::invalid_encoding(
+                "invalid tag when decoding Option",
+                Some(format!("Expected 0 or 1, got {}", x)))),
+        }
+    }
+}
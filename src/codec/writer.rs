@@ -0,0 +1,303 @@
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::io::Error as IoError;
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use ::format;
+
+use super::Encode;
+
+pub type EncodeResult<T> = Result<T, EncodeError>;
+
+/// An error that can be produced during encoding.
+#[derive(Debug)]
+pub enum EncodeError {
+    /// An error originating from the underlying `Writer`.
+    IoError(IoError),
+    /// An object could not be encoded with the given size limit.
+    ///
+    /// This error is returned before any bytes are written to the
+    /// output `Writer`.
+    SizeLimit,
+}
+
+fn wrap_io(err: IoError) -> EncodeError {
+    EncodeError::IoError(err)
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            EncodeError::IoError(ref err) => write!(f, "IoError: {}", err),
+            EncodeError::SizeLimit => write!(f, "SizeLimit"),
+        }
+    }
+}
+
+impl Error for EncodeError {
+    fn description(&self) -> &str {
+        match *self {
+            EncodeError::IoError(ref err) => Error::description(err),
+            EncodeError::SizeLimit => "the size limit for encoding has been reached",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            EncodeError::IoError(ref err) => err.cause(),
+            EncodeError::SizeLimit => None,
+        }
+    }
+}
+
+impl From<IoError> for EncodeError {
+    fn from(err: IoError) -> EncodeError {
+        EncodeError::IoError(err)
+    }
+}
+
+/// An Encoder that writes the bincode wire format directly into a Writer.
+///
+/// `Encode` implementations call the `write_*` methods in the order their
+/// fields appear; the byte layout is identical to the one produced by the
+/// `serde` and `rustc_serialize` frontends.
+///
+/// In aligned mode (see `set_aligned`), zeros are written before every
+/// number as needed to put it at a multiple of its size from the start of
+/// the encoding, the way a `#[repr(C)]` struct is laid out in memory.
+pub struct Encoder<'a, W: 'a> {
+    writer: &'a mut W,
+    written: u64,
+    aligned: bool,
+    max_align: u64,
+}
+
+impl<'a, W: Write> Encoder<'a, W> {
+    pub fn new(w: &'a mut W) -> Encoder<'a, W> {
+        Encoder {
+            writer: w,
+            written: 0,
+            aligned: false,
+            max_align: 1,
+        }
+    }
+
+    /// Turns aligned mode on or off.
+    ///
+    /// The output can only be read by a `Decoder` in aligned mode that
+    /// starts at the same place.  Bytes, strings, and chars are not aligned.
+    pub fn set_aligned(&mut self, aligned: bool) {
+        self.aligned = aligned;
+    }
+
+    /// Returns whether aligned mode is on.
+    pub fn is_aligned(&self) -> bool {
+        self.aligned
+    }
+
+    /// Returns the number of bytes written so far, including padding.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// In aligned mode, writes zeros up to the next multiple of `align`.
+    fn align(&mut self, align: u64) -> EncodeResult<()> {
+        if !self.aligned {
+            return Ok(());
+        }
+        self.max_align = cmp::max(self.max_align, align);
+        let padding = (align - self.written % align) % align;
+        self.write_zeros(padding)
+    }
+
+    fn write_zeros(&mut self, count: u64) -> EncodeResult<()> {
+        const ZEROS: [u8; 8] = [0; 8];
+        let mut left = count;
+        while left > 0 {
+            let len = cmp::min(left, ZEROS.len() as u64);
+            try!(self.write_raw(&ZEROS[..len as usize]));
+            left -= len;
+        }
+        Ok(())
+    }
+
+    fn write_type<T>(&mut self) -> EncodeResult<()> {
+        use std::mem::size_of;
+        let size = size_of::<T>() as u64;
+        try!(self.align(size));
+        self.written += size;
+        Ok(())
+    }
+
+    pub fn write_unit(&mut self) -> EncodeResult<()> {
+        Ok(())
+    }
+
+    pub fn write_bool(&mut self, v: bool) -> EncodeResult<()> {
+        self.write_u8(format::bool_byte(v))
+    }
+
+    pub fn write_u8(&mut self, v: u8) -> EncodeResult<()> {
+        try!(self.write_type::<u8>());
+        self.writer.write_u8(v).map_err(wrap_io)
+    }
+
+    pub fn write_u16(&mut self, v: u16) -> EncodeResult<()> {
+        try!(self.write_type::<u16>());
+        self.writer.write_u16::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    pub fn write_u32(&mut self, v: u32) -> EncodeResult<()> {
+        try!(self.write_type::<u32>());
+        self.writer.write_u32::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    pub fn write_u64(&mut self, v: u64) -> EncodeResult<()> {
+        try!(self.write_type::<u64>());
+        self.writer.write_u64::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    pub fn write_usize(&mut self, v: usize) -> EncodeResult<()> {
+        self.write_u64(v as u64)
+    }
+
+    pub fn write_i8(&mut self, v: i8) -> EncodeResult<()> {
+        try!(self.write_type::<i8>());
+        self.writer.write_i8(v).map_err(wrap_io)
+    }
+
+    pub fn write_i16(&mut self, v: i16) -> EncodeResult<()> {
+        try!(self.write_type::<i16>());
+        self.writer.write_i16::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    pub fn write_i32(&mut self, v: i32) -> EncodeResult<()> {
+        try!(self.write_type::<i32>());
+        self.writer.write_i32::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    pub fn write_i64(&mut self, v: i64) -> EncodeResult<()> {
+        try!(self.write_type::<i64>());
+        self.writer.write_i64::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    pub fn write_isize(&mut self, v: isize) -> EncodeResult<()> {
+        self.write_i64(v as i64)
+    }
+
+    pub fn write_f32(&mut self, v: f32) -> EncodeResult<()> {
+        try!(self.write_type::<f32>());
+        self.writer.write_f32::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    pub fn write_f64(&mut self, v: f64) -> EncodeResult<()> {
+        try!(self.write_type::<f64>());
+        self.writer.write_f64::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    /// Writes a `char` as its raw UTF-8 bytes.
+    pub fn write_char(&mut self, v: char) -> EncodeResult<()> {
+        let mut buf = [0; 4];
+        self.write_raw(format::char_bytes(v, &mut buf))
+    }
+
+    /// Writes the length prefix of a string, sequence, or map.
+    pub fn write_len(&mut self, len: usize) -> EncodeResult<()> {
+        self.write_usize(len)
+    }
+
+    pub fn write_str(&mut self, v: &str) -> EncodeResult<()> {
+        self.write_bytes(v.as_bytes())
+    }
+
+    /// Writes a length-prefixed run of bytes.
+    pub fn write_bytes(&mut self, v: &[u8]) -> EncodeResult<()> {
+        try!(self.write_len(v.len()));
+        self.write_raw(v)
+    }
+
+    /// Writes bytes as they are, without a length in front.
+    pub fn write_raw(&mut self, v: &[u8]) -> EncodeResult<()> {
+        self.written += v.len() as u64;
+        self.writer.write_all(v).map_err(wrap_io)
+    }
+
+    /// Writes a sequence of values that all take up the same number of
+    /// bytes, its stride, so that any one of them can be found without
+    /// reading the others.
+    ///
+    /// The stride is written after the length.  It is the size of the
+    /// largest value, and in aligned mode a multiple of the largest
+    /// alignment inside the values too, so that every value starts aligned;
+    /// smaller values are followed by zeros.  Working it out takes one pass
+    /// over the values before they are written.
+    pub fn write_fixed_seq<T: Encode>(&mut self, values: &[T]) -> EncodeResult<()> {
+        let mut size = 0;
+        let mut align = 1;
+        for value in values {
+            let mut counter = SizeCounter::new(::std::u64::MAX);
+            let mut encoder = Encoder::new(&mut counter);
+            encoder.set_aligned(self.aligned);
+            try!(value.encode(&mut encoder));
+            size = cmp::max(size, encoder.written);
+            align = cmp::max(align, encoder.max_align);
+        }
+        let stride = (size + align - 1) / align * align;
+
+        try!(self.write_len(values.len()));
+        try!(self.write_u64(stride));
+        for value in values {
+            let start = self.written;
+            try!(value.encode(self));
+            let padding = start + stride - self.written;
+            try!(self.write_zeros(padding));
+        }
+        Ok(())
+    }
+
+    /// Writes the tag of an enum variant.
+    pub fn write_variant(&mut self, tag: u32) -> EncodeResult<()> {
+        self.write_u32(tag)
+    }
+
+    /// Writes the tag that precedes an `Option`; `true` for `Some`.
+    pub fn write_option_tag(&mut self, is_some: bool) -> EncodeResult<()> {
+        self.write_u8(format::option_tag(is_some))
+    }
+}
+
+/// A Writer that only counts the bytes that pass through it.
+///
+/// Writing more than `size_limit` bytes fails, which stops an oversized
+/// encode as early as possible.
+pub struct SizeCounter {
+    pub size_limit: u64,
+    pub written: u64
+}
+
+impl SizeCounter {
+    pub fn new(limit: u64) -> SizeCounter {
+        SizeCounter {
+            size_limit: limit,
+            written: 0
+        }
+    }
+}
+
+impl Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        if self.written <= self.size_limit {
+            Ok(buf.len())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, "size limit exceeded"))
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! A file of several bincode values that can be read one at a time.
+//!
+//! A `ContainerWriter` writes a header, then any number of sections, each
+//! the bincode encoding of a value under a name, then an index of where the
+//! sections are:
+//!
+//! ```text
+//! magic: b"bincont\0" | version: u32 (big-endian)
+//! section 0 | section 1 | ...
+//! index: Vec<(String, u64, u64)>, the name, offset and length of each section
+//! index offset: u64 (big-endian)
+//! ```
+//!
+//! A `ContainerReader` reads the header and the index when it is opened,
+//! and after that only the sections that are asked for, by name or by
+//! number, seeking straight to them.  A save file can keep its thumbnail,
+//! its metadata, and the world itself as separate sections, and a menu
+//! that lists save files never has to read the world.
+//!
+//! ```rust,ignore
+//! let mut container = ContainerWriter::new(File::create("save.bin")?)?;
+//! container.write_section("meta", &meta)?;
+//! container.write_section("world", &world)?;
+//! container.finish()?;
+//!
+//! let mut container = ContainerReader::open(File::open("save.bin")?)?;
+//! let meta: Meta = container.section("meta")?;
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io::Error as IoError;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use serde_crate as serde;
+
+use ::{Bounded, Infinite};
+use ::serde::{serialize, deserialize_from, SerializeError, DeserializeError};
+
+/// The bytes every container starts with.
+const MAGIC: &'static [u8; 8] = b"bincont\0";
+
+/// The version of the layout, written after the magic bytes.
+const VERSION: u32 = 1;
+
+/// The length of the magic bytes and version.
+const HEADER_LEN: u64 = 12;
+
+/// The length of the index offset at the very end.
+const FOOTER_LEN: u64 = 8;
+
+/// An error that can be produced while writing or reading a container.
+#[derive(Debug)]
+pub enum ContainerError {
+    /// If the error stems from the underlying file, that error will be
+    /// stored and returned here.
+    IoError(IoError),
+    /// The file doesn't start with the container magic bytes, is of a
+    /// version this doesn't read, or its index is damaged.
+    NotAContainer(&'static str),
+    /// There is no section with the given name or number.
+    NoSection,
+    /// A section with the given name has already been written.
+    DuplicateSection(String),
+    /// A section to write could not be serialized.
+    Serialize(SerializeError),
+    /// A section, or the index, could not be deserialized.
+    Deserialize(DeserializeError),
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ContainerError::IoError(ref ioerr) =>
+                write!(fmt, "IoError: {}", ioerr),
+            ContainerError::NotAContainer(desc) =>
+                write!(fmt, "NotAContainer: {}", desc),
+            ContainerError::NoSection =>
+                write!(fmt, "NoSection"),
+            ContainerError::DuplicateSection(ref name) =>
+                write!(fmt, "DuplicateSection: {}", name),
+            ContainerError::Serialize(ref err) =>
+                write!(fmt, "Serialize: {}", err),
+            ContainerError::Deserialize(ref err) =>
+                write!(fmt, "Deserialize: {}", err),
+        }
+    }
+}
+
+pub type ContainerResult<T> = Result<T, ContainerError>;
+
+impl Error for ContainerError {
+    fn description(&self) -> &str {
+        match *self {
+            ContainerError::IoError(ref err) => Error::description(err),
+            ContainerError::NotAContainer(desc) => desc,
+            ContainerError::NoSection => "there is no such section in the container",
+            ContainerError::DuplicateSection(_) => "a section of the same name was already written",
+            ContainerError::Serialize(ref err) => err.description(),
+            ContainerError::Deserialize(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ContainerError::IoError(ref err) => err.cause(),
+            ContainerError::NotAContainer(_) => None,
+            ContainerError::NoSection => None,
+            ContainerError::DuplicateSection(_) => None,
+            ContainerError::Serialize(ref err) => Some(err),
+            ContainerError::Deserialize(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<IoError> for ContainerError {
+    fn from(err: IoError) -> ContainerError {
+        ContainerError::IoError(err)
+    }
+}
+
+impl From<SerializeError> for ContainerError {
+    fn from(err: SerializeError) -> ContainerError {
+        match err {
+            SerializeError::IoError(err) => ContainerError::IoError(err),
+            err => ContainerError::Serialize(err),
+        }
+    }
+}
+
+impl From<DeserializeError> for ContainerError {
+    fn from(err: DeserializeError) -> ContainerError {
+        ContainerError::Deserialize(err)
+    }
+}
+
+/// Where a section is: its name, offset from the start of the file, and
+/// length.
+type Entry = (String, u64, u64);
+
+/// Writes the sections of a container one after the other.
+///
+/// The container isn't readable until `finish` has written the index.
+pub struct ContainerWriter<W> {
+    writer: W,
+    offset: u64,
+    index: Vec<Entry>,
+}
+
+impl<W: Write> ContainerWriter<W> {
+    /// Writes the header of a container to `writer`.
+    pub fn new(mut writer: W) -> ContainerResult<ContainerWriter<W>> {
+        try!(writer.write_all(MAGIC));
+        try!(writer.write_u32::<BigEndian>(VERSION));
+        Ok(ContainerWriter {
+            writer: writer,
+            offset: HEADER_LEN,
+            index: Vec::new(),
+        })
+    }
+
+    /// Writes `value` as the next section, under `name`, and returns the
+    /// number of the section, counting from 0.
+    pub fn write_section<T>(&mut self, name: &str, value: &T) -> ContainerResult<usize>
+        where T: serde::Serialize,
+    {
+        if self.index.iter().any(|entry| entry.0 == name) {
+            return Err(ContainerError::DuplicateSection(name.to_string()));
+        }
+        let bytes = try!(serialize(value, Infinite));
+        try!(self.writer.write_all(&bytes));
+        self.index.push((name.to_string(), self.offset, bytes.len() as u64));
+        self.offset += bytes.len() as u64;
+        Ok(self.index.len() - 1)
+    }
+
+    /// Writes the index and returns the underlying writer.
+    pub fn finish(mut self) -> ContainerResult<W> {
+        let index = try!(serialize(&self.index, Infinite));
+        try!(self.writer.write_all(&index));
+        try!(self.writer.write_u64::<BigEndian>(self.offset));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+}
+
+/// Reads sections out of a container, only when they are asked for.
+pub struct ContainerReader<R> {
+    reader: R,
+    index: Vec<Entry>,
+}
+
+impl<R: Read + Seek> ContainerReader<R> {
+    /// Checks the header of the container in `reader` and reads its index.
+    pub fn open(mut reader: R) -> ContainerResult<ContainerReader<R>> {
+        let mut magic = [0; 8];
+        try!(reader.seek(SeekFrom::Start(0)));
+        try!(reader.read_exact(&mut magic).map_err(not_a_container));
+        if &magic != MAGIC {
+            return Err(ContainerError::NotAContainer("the file does not start with the container magic bytes"));
+        }
+        if try!(reader.read_u32::<BigEndian>().map_err(not_a_container)) != VERSION {
+            return Err(ContainerError::NotAContainer("the container is of an unknown version"));
+        }
+
+        let end = try!(reader.seek(SeekFrom::End(0)));
+        if end < HEADER_LEN + FOOTER_LEN {
+            return Err(ContainerError::NotAContainer("the container has no index"));
+        }
+        try!(reader.seek(SeekFrom::Start(end - FOOTER_LEN)));
+        let index_offset = try!(reader.read_u64::<BigEndian>());
+        if index_offset < HEADER_LEN || index_offset > end - FOOTER_LEN {
+            return Err(ContainerError::NotAContainer("the index offset is out of range"));
+        }
+
+        try!(reader.seek(SeekFrom::Start(index_offset)));
+        let index_len = end - FOOTER_LEN - index_offset;
+        let index: Vec<Entry> = try!(deserialize_from(&mut (&mut reader).take(index_len), Bounded(index_len)));
+        for &(_, offset, len) in &index {
+            match offset.checked_add(len) {
+                Some(section_end) if offset >= HEADER_LEN && section_end <= index_offset => {}
+                _ => return Err(ContainerError::NotAContainer("a section lies outside of the container")),
+            }
+        }
+
+        Ok(ContainerReader {
+            reader: reader,
+            index: index,
+        })
+    }
+
+    /// Returns the number of sections.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Returns whether there are no sections.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Returns the names of the sections, in the order they were written.
+    pub fn names(&self) -> Vec<&str> {
+        self.index.iter().map(|entry| &entry.0[..]).collect()
+    }
+
+    /// Returns the number of the section called `name`, if there is one.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.index.iter().position(|entry| entry.0 == name)
+    }
+
+    /// Reads the section called `name`.
+    pub fn section<T>(&mut self, name: &str) -> ContainerResult<T>
+        where T: serde::Deserialize,
+    {
+        match self.position(name) {
+            Some(number) => self.section_at(number),
+            None => Err(ContainerError::NoSection),
+        }
+    }
+
+    /// Reads the section with the given number, counting from 0.
+    pub fn section_at<T>(&mut self, number: usize) -> ContainerResult<T>
+        where T: serde::Deserialize,
+    {
+        let (offset, len) = match self.index.get(number) {
+            Some(&(_, offset, len)) => (offset, len),
+            None => return Err(ContainerError::NoSection),
+        };
+        try!(self.reader.seek(SeekFrom::Start(offset)));
+        let value = try!(deserialize_from(&mut (&mut self.reader).take(len), Bounded(len)));
+        Ok(value)
+    }
+
+    /// Unwraps this `ContainerReader`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn not_a_container(err: io::Error) -> ContainerError {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        ContainerError::NotAContainer("the file is too short to be a container")
+    } else {
+        ContainerError::IoError(err)
+    }
+}
@@ -0,0 +1,159 @@
+use std::cmp;
+use std::io::{self, Read};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use frame::{LengthPrefix, FrameError, FrameResult};
+
+#[cfg(feature = "serde")]
+use serde_crate as serde;
+#[cfg(feature = "serde")]
+use ::Infinite;
+#[cfg(feature = "serde")]
+use ::serde::{Deserializer, SliceReader, DeserializeResult};
+
+/// A position in a slice of bytes that messages are read out of one after
+/// the other.
+///
+/// The cursor keeps track of how far into the slice it is, so code reading
+/// a buffer full of messages doesn't have to work out offsets by hand.  It
+/// is `Copy`, so looking ahead is a matter of reading from a copy: `peek`
+/// decodes the next message without moving past it, and a copy can be read
+/// as far as needed to decide what to do with the original.  A `Checkpoint`
+/// does the same for the cursor itself, by remembering a position that
+/// `rewind` goes back to.
+///
+/// ```rust,ignore
+/// let mut cursor = SliceCursor::new(&buffer);
+/// while !cursor.is_empty() {
+///     let header: Header = cursor.peek()?;
+///     match header.kind {
+///         Kind::Ping => { cursor.deserialize::<Ping>()?; }
+///         Kind::Data => handle(cursor.deserialize::<Data>()?),
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct SliceCursor<'a> {
+    input: &'a [u8],
+    offset: usize,
+}
+
+/// A position a `SliceCursor` can be rewound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+impl<'a> SliceCursor<'a> {
+    /// Creates a cursor at the start of `input`.
+    pub fn new(input: &'a [u8]) -> SliceCursor<'a> {
+        SliceCursor {
+            input: input,
+            offset: 0,
+        }
+    }
+
+    /// Returns the number of bytes that have been read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the bytes that have not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.input[self.offset..]
+    }
+
+    /// Returns whether all of the input has been read.
+    pub fn is_empty(&self) -> bool {
+        self.offset == self.input.len()
+    }
+
+    /// Returns the whole input, read or not.
+    pub fn get_ref(&self) -> &'a [u8] {
+        self.input
+    }
+
+    /// Returns the next `len` bytes and moves past them, or returns `None`
+    /// and stays put if there aren't that many left.
+    pub fn split_off(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.input.len() - self.offset {
+            return None;
+        }
+        let bytes = &self.input[self.offset..self.offset + len];
+        self.offset += len;
+        Some(bytes)
+    }
+
+    /// Returns the payload of the next length-prefixed frame, as
+    /// `FrameReader` reads them, and moves past it.
+    ///
+    /// If all of the input has been read, `None` is returned.  If the
+    /// input ends in the middle of a frame, a `Truncated` error is returned
+    /// and the cursor stays where it is, so that it can be tried again once
+    /// the rest of the frame has arrived.
+    pub fn split_off_message(&mut self, prefix: LengthPrefix) -> FrameResult<Option<&'a [u8]>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let rest = self.remaining();
+        if rest.len() < prefix.width() {
+            return Err(FrameError::Truncated);
+        }
+        let len = match prefix {
+            LengthPrefix::U32 => BigEndian::read_u32(rest) as u64,
+            LengthPrefix::U64 => BigEndian::read_u64(rest),
+        };
+        if len > (rest.len() - prefix.width()) as u64 {
+            return Err(FrameError::Truncated);
+        }
+        self.offset += prefix.width();
+        Ok(self.split_off(len as usize))
+    }
+
+    /// Returns the current position, for `rewind` to go back to.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.offset)
+    }
+
+    /// Goes back, or forward, to a position returned by `checkpoint`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the checkpoint is past the end of the input, which can
+    /// only happen if it came from a cursor over a longer one.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        assert!(checkpoint.0 <= self.input.len(), "checkpoint past the end of the input");
+        self.offset = checkpoint.0;
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a> SliceCursor<'a> {
+    /// Deserializes the next message and moves past it.
+    ///
+    /// If it can't be deserialized, the cursor stays where it is.
+    pub fn deserialize<T: serde::Deserialize>(&mut self) -> DeserializeResult<T> {
+        let mut deserializer = Deserializer::new(SliceReader::new(self.remaining()), Infinite);
+        let value = try!(serde::Deserialize::deserialize(&mut deserializer));
+        self.offset = self.input.len() - deserializer.into_inner().remaining().len();
+        Ok(value)
+    }
+
+    /// Deserializes the next message without moving past it.
+    ///
+    /// `T` doesn't have to be the whole message.  A struct of its first few
+    /// fields reads just those, which is enough to look at a header before
+    /// deciding how to read the rest.
+    pub fn peek<T: serde::Deserialize>(&self) -> DeserializeResult<T> {
+        let mut cursor = *self;
+        cursor.deserialize()
+    }
+}
+
+impl<'a> Read for SliceCursor<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = cmp::min(buf.len(), self.input.len() - self.offset);
+        buf[..len].copy_from_slice(&self.input[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
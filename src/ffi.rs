@@ -0,0 +1,152 @@
+//! C functions for framing bincode messages.
+//!
+//! Components written in C or C++ can't call bincode's generic functions,
+//! but they can still route bincode messages if they know where each one
+//! ends.  A frame is laid out the same way bincode encodes a byte buffer:
+//! the payload length as a big-endian `u64`, followed by the payload.
+//!
+//! The functions below find and check frames without copying or decoding
+//! anything.  Each returns one of the `BINCODE_FRAME_*` status codes and
+//! only writes to its out-pointers on success.  A matching header lives in
+//! `include/bincode_ffi.h`.  The module is enabled by the `ffi` feature; link
+//! it into C code through a `staticlib` or `cdylib` crate that depends on
+//! bincode.
+
+use std::os::raw::c_int;
+use std::slice;
+
+use byteorder::{BigEndian, ByteOrder};
+
+/// The frame is valid.
+pub const BINCODE_FRAME_OK: c_int = 0;
+/// A required pointer was null.
+pub const BINCODE_FRAME_NULL: c_int = -1;
+/// The buffer ends before the frame does; read more bytes and try again.
+pub const BINCODE_FRAME_INCOMPLETE: c_int = -2;
+/// The payload is longer than the allowed maximum.
+pub const BINCODE_FRAME_TOO_LARGE: c_int = -3;
+
+/// The length of the header in front of every frame.
+pub const BINCODE_FRAME_HEADER_LEN: usize = 8;
+
+/// Checks that `buf` starts with a complete frame whose payload is at most
+/// `max_payload_len` bytes, returning the payload's offset and length.
+fn check_frame(buf: &[u8], max_payload_len: u64) -> Result<(usize, usize), c_int> {
+    if buf.len() < BINCODE_FRAME_HEADER_LEN {
+        return Err(BINCODE_FRAME_INCOMPLETE);
+    }
+
+    let payload_len = BigEndian::read_u64(&buf[..BINCODE_FRAME_HEADER_LEN]);
+    if payload_len > max_payload_len {
+        return Err(BINCODE_FRAME_TOO_LARGE);
+    }
+
+    let available = (buf.len() - BINCODE_FRAME_HEADER_LEN) as u64;
+    if payload_len > available {
+        return Err(BINCODE_FRAME_INCOMPLETE);
+    }
+
+    Ok((BINCODE_FRAME_HEADER_LEN, payload_len as usize))
+}
+
+unsafe fn as_slice<'a>(buf: *const u8, buf_len: usize) -> Option<&'a [u8]> {
+    if buf.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(buf, buf_len))
+    }
+}
+
+/// Reads the payload length from the header of the frame at the start of
+/// `buf`, without checking that the payload itself is there.
+///
+/// # Safety
+///
+/// `buf` must be valid for reads of `buf_len` bytes, and `payload_len` must
+/// be valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn bincode_frame_payload_len(buf: *const u8,
+                                                   buf_len: usize,
+                                                   payload_len: *mut u64)
+                                                   -> c_int {
+    let buf = match as_slice(buf, buf_len) {
+        Some(buf) => buf,
+        None => return BINCODE_FRAME_NULL,
+    };
+    if payload_len.is_null() {
+        return BINCODE_FRAME_NULL;
+    }
+    if buf.len() < BINCODE_FRAME_HEADER_LEN {
+        return BINCODE_FRAME_INCOMPLETE;
+    }
+
+    *payload_len = BigEndian::read_u64(&buf[..BINCODE_FRAME_HEADER_LEN]);
+    BINCODE_FRAME_OK
+}
+
+/// Checks that `buf` starts with a complete frame whose payload is at most
+/// `max_payload_len` bytes, and stores the length of the whole frame,
+/// header included, in `frame_len`.
+///
+/// The next frame in a stream starts `frame_len` bytes after this one.
+///
+/// # Safety
+///
+/// `buf` must be valid for reads of `buf_len` bytes, and `frame_len` must be
+/// valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn bincode_frame_check(buf: *const u8,
+                                             buf_len: usize,
+                                             max_payload_len: u64,
+                                             frame_len: *mut usize)
+                                             -> c_int {
+    let buf = match as_slice(buf, buf_len) {
+        Some(buf) => buf,
+        None => return BINCODE_FRAME_NULL,
+    };
+    if frame_len.is_null() {
+        return BINCODE_FRAME_NULL;
+    }
+
+    match check_frame(buf, max_payload_len) {
+        Ok((offset, len)) => {
+            *frame_len = offset + len;
+            BINCODE_FRAME_OK
+        }
+        Err(status) => status,
+    }
+}
+
+/// Checks the frame at the start of `buf` like `bincode_frame_check`, and
+/// points `payload` at its payload, which is `payload_len` bytes long.
+///
+/// The payload points into `buf`; nothing is copied.
+///
+/// # Safety
+///
+/// `buf` must be valid for reads of `buf_len` bytes, and `payload` and
+/// `payload_len` must be valid for a write.
+#[no_mangle]
+pub unsafe extern "C" fn bincode_frame_payload(buf: *const u8,
+                                               buf_len: usize,
+                                               max_payload_len: u64,
+                                               payload: *mut *const u8,
+                                               payload_len: *mut usize)
+                                               -> c_int {
+    let bytes = match as_slice(buf, buf_len) {
+        Some(bytes) => bytes,
+        None => return BINCODE_FRAME_NULL,
+    };
+    if payload.is_null() || payload_len.is_null() {
+        return BINCODE_FRAME_NULL;
+    }
+
+    match check_frame(bytes, max_payload_len) {
+        Ok((offset, len)) => {
+            *payload = buf.offset(offset as isize);
+            *payload_len = len;
+            BINCODE_FRAME_OK
+        }
+        Err(status) => status,
+    }
+}
@@ -0,0 +1,273 @@
+//! The parts of the wire format that aren't just a number written out.
+//!
+//! Every frontend, `serde`, `rustc_serialize`, and `codec`, writes numbers
+//! big-endian at their own width, and goes through the functions here for
+//! everything else: booleans, chars, lengths, enum tags, and option tags.
+//! Keeping them in one place is what keeps the frontends from drifting
+//! apart, so a frontend should never spell out one of these itself.
+//!
+//! How wide each of these is depends on the layout a message is written
+//! with: `set_fixed_arrays` leaves the length out of fixed-size arrays,
+//! `set_bit_packing` packs bools into bits, and `set_legacy_chars` puts a
+//! length in front of chars.  Code outside of bincode that works out the
+//! size of a header or the offset of a field should ask the `Layout` it
+//! writes with, or, for the presets, the constants of `StaticLayout`.  The
+//! `rustc_serialize` and `codec` frontends always write `StandardLayout`.
+
+use std::error::Error;
+use std::fmt;
+use std::str;
+
+// The widths in the plain layout, which the settings of a layout adjust.
+const LEN_PREFIX_BYTES: usize = 8;
+const USIZE_BYTES: usize = 8;
+const VARIANT_TAG_BYTES: usize = 4;
+const OPTION_TAG_BYTES: usize = 1;
+const MAX_UTF8_BYTES: usize = 4;
+
+/// The settings that decide how values are laid out, which a `Serializer`
+/// and a `Deserializer` take as a type parameter.
+///
+/// `serde::Config` is the layout that is decided at run time.  The layout
+/// types of the presets, `StandardLayout`, `CompactLayout`, `LegacyLayout`
+/// and `FixedLayout`, hold nothing and answer every question with a
+/// constant, so a `Serializer` or `Deserializer` that is given one is
+/// compiled for that layout alone.
+///
+/// ```rust,ignore
+/// let mut deserializer = Deserializer::with_layout(SliceReader::new(&bytes), Bounded(1 << 20), CompactLayout);
+/// let status: Status = serde::Deserialize::deserialize(&mut deserializer)?;
+/// ```
+///
+/// The provided methods give the widths of the parts of a message that
+/// the layout writes.
+pub trait Layout: Copy {
+    /// Whether bools and `Bits` fields are packed into bits.
+    fn bit_packing(&self) -> bool;
+
+    /// Whether chars are written as a string of one char.
+    fn legacy_chars(&self) -> bool;
+
+    /// Whether fixed-size arrays are written without their length.
+    fn fixed_arrays(&self) -> bool;
+
+    /// Whether types that have a human-readable form, such as a date
+    /// written as text, are asked for it.  None of the presets are, and
+    /// bincode itself writes the same bytes either way.
+    fn human_readable(&self) -> bool {
+        false
+    }
+
+    /// The number of bytes the length in front of a string, a sequence or
+    /// a map takes up.  Lengths, like `usize` values, are written as a
+    /// `u64`.
+    fn len_prefix_bytes(&self) -> usize {
+        LEN_PREFIX_BYTES
+    }
+
+    /// The number of bytes the length in front of a fixed-size array takes
+    /// up, which is none when they are written without it.
+    fn array_len_bytes(&self) -> usize {
+        if self.fixed_arrays() { 0 } else { LEN_PREFIX_BYTES }
+    }
+
+    /// The number of bytes a `usize` or `isize` takes up, which are written
+    /// as a `u64` or `i64` whatever the width of the machine.
+    fn usize_bytes(&self) -> usize {
+        USIZE_BYTES
+    }
+
+    /// The number of bytes the tag in front of an enum variant takes up.
+    /// Tags are written as a `u32`.
+    fn variant_tag_bytes(&self) -> usize {
+        VARIANT_TAG_BYTES
+    }
+
+    /// The number of bytes the tag in front of an `Option` takes up.
+    fn option_tag_bytes(&self) -> usize {
+        OPTION_TAG_BYTES
+    }
+
+    /// The number of bits a `bool` takes up: one when bools are packed into
+    /// bits, and a whole byte otherwise.
+    fn bool_bits(&self) -> usize {
+        if self.bit_packing() { 1 } else { 8 }
+    }
+
+    /// The largest number of bytes a `char` takes up, which is the longest
+    /// UTF-8 encoding of one, with a length in front of it for legacy
+    /// chars.
+    fn max_char_bytes(&self) -> usize {
+        if self.legacy_chars() { LEN_PREFIX_BYTES + MAX_UTF8_BYTES } else { MAX_UTF8_BYTES }
+    }
+}
+
+/// A `Layout` that is fixed at compile time, whose settings and widths are
+/// constants.
+///
+/// The constants are the same as what the methods of `Layout` return, but
+/// can be used where a constant is needed, such as in `MaxSize`.
+pub trait StaticLayout: Layout {
+    /// Whether bools and `Bits` fields are packed into bits.
+    const BIT_PACKING: bool;
+    /// Whether chars are written as a string of one char.
+    const LEGACY_CHARS: bool;
+    /// Whether fixed-size arrays are written without their length.
+    const FIXED_ARRAYS: bool;
+
+    /// See `Layout::len_prefix_bytes`.
+    const LEN_PREFIX_BYTES: usize = LEN_PREFIX_BYTES;
+    /// See `Layout::array_len_bytes`.
+    const ARRAY_LEN_BYTES: usize = if Self::FIXED_ARRAYS { 0 } else { LEN_PREFIX_BYTES };
+    /// See `Layout::usize_bytes`.
+    const USIZE_BYTES: usize = USIZE_BYTES;
+    /// See `Layout::variant_tag_bytes`.
+    const VARIANT_TAG_BYTES: usize = VARIANT_TAG_BYTES;
+    /// See `Layout::option_tag_bytes`.
+    const OPTION_TAG_BYTES: usize = OPTION_TAG_BYTES;
+    /// See `Layout::bool_bits`.
+    const BOOL_BITS: usize = if Self::BIT_PACKING { 1 } else { 8 };
+    /// See `Layout::max_char_bytes`.
+    const MAX_CHAR_BYTES: usize = if Self::LEGACY_CHARS { LEN_PREFIX_BYTES + MAX_UTF8_BYTES } else { MAX_UTF8_BYTES };
+}
+
+macro_rules! static_layouts {
+    ($($(#[$attr:meta])* $name:ident: $bit_packing:expr, $legacy_chars:expr, $fixed_arrays:expr;)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+            pub struct $name;
+
+            impl Layout for $name {
+                #[inline]
+                fn bit_packing(&self) -> bool { $bit_packing }
+
+                #[inline]
+                fn legacy_chars(&self) -> bool { $legacy_chars }
+
+                #[inline]
+                fn fixed_arrays(&self) -> bool { $fixed_arrays }
+            }
+
+            impl StaticLayout for $name {
+                const BIT_PACKING: bool = $bit_packing;
+                const LEGACY_CHARS: bool = $legacy_chars;
+                const FIXED_ARRAYS: bool = $fixed_arrays;
+            }
+        )*
+    }
+}
+
+static_layouts! {
+    /// The plain bincode layout, fixed at compile time.  It is the layout of
+    /// `serde::Config::standard`, and the only one the `rustc_serialize`
+    /// and `codec` frontends write.
+    StandardLayout: false, false, false;
+    /// The layout of `serde::Config::compact`, fixed at compile time.
+    CompactLayout: true, false, false;
+    /// The layout of `serde::Config::legacy`, fixed at compile time.
+    LegacyLayout: false, true, false;
+    /// The layout of `serde::Config::fixed`, fixed at compile time.
+    FixedLayout: false, false, true;
+}
+
+/// The error `variant_tag` returns for an enum variant whose index doesn't
+/// fit in the `u32` tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagOverflow {
+    /// The index of the variant.
+    pub index: usize,
+}
+
+impl fmt::Display for TagOverflow {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "variant index {} doesn't fit in a u32 tag", self.index)
+    }
+}
+
+impl Error for TagOverflow {
+    fn description(&self) -> &str {
+        "variant index doesn't fit in a u32 tag"
+    }
+}
+
+/// Returns the byte a `bool` is written as.
+pub fn bool_byte(v: bool) -> u8 {
+    if v {1} else {0}
+}
+
+/// Reads a `bool` back from its byte, or returns `None` if the byte isn't
+/// one that `bool_byte` writes.
+pub fn bool_from_byte(byte: u8) -> Option<bool> {
+    match byte {
+        1 => Some(true),
+        0 => Some(false),
+        _ => None,
+    }
+}
+
+/// Returns the byte in front of an `Option`: 1 for `Some` and 0 for `None`.
+pub fn option_tag(is_some: bool) -> u8 {
+    bool_byte(is_some)
+}
+
+/// Reads the byte in front of an `Option`, returning `Some(true)` for
+/// `Some`, or `None` if the byte isn't a valid tag.
+pub fn option_from_tag(byte: u8) -> Option<bool> {
+    bool_from_byte(byte)
+}
+
+/// Returns the tag of the enum variant at `index`, or a `TagOverflow` error
+/// if the index doesn't fit in a `u32`.
+pub fn variant_tag(index: usize) -> Result<u32, TagOverflow> {
+    if index > ::std::u32::MAX as usize {
+        return Err(TagOverflow { index: index });
+    }
+    Ok(index as u32)
+}
+
+/// Returns the bytes a `char` is written as, which are its UTF-8 encoding
+/// and nothing else.  There is no length in front; `char_width` tells a
+/// reader how many bytes to expect from the first one.
+pub fn char_bytes(v: char, buf: &mut [u8; 4]) -> &[u8] {
+    v.encode_utf8(buf).as_bytes()
+}
+
+/// Returns how many bytes the `char` whose UTF-8 encoding starts with
+/// `first_byte` takes up, or 0 if no encoding can start with that byte.
+pub fn char_width(first_byte: u8) -> usize {
+    UTF8_CHAR_WIDTH[first_byte as usize] as usize
+}
+
+/// Reads a `char` back from the bytes `char_bytes` writes, or returns
+/// `None` if they aren't the UTF-8 encoding of exactly one `char`.
+pub fn char_from_bytes(bytes: &[u8]) -> Option<char> {
+    let s = match str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(_) => return None,
+    };
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+static UTF8_CHAR_WIDTH: [u8; 256] = [
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x1F
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x3F
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x5F
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
+1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x7F
+0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,
+0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0, // 0x9F
+0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,
+0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0, // 0xBF
+0,0,2,2,2,2,2,2,2,2,2,2,2,2,2,2,
+2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2, // 0xDF
+3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3, // 0xEF
+4,4,4,4,4,0,0,0,0,0,0,0,0,0,0,0, // 0xFF
+];
@@ -0,0 +1,408 @@
+//! Splitting messages that are too large for one datagram into fragments,
+//! and putting them back together.
+//!
+//! `serialize_datagram` refuses a message that doesn't fit in one datagram.
+//! For the occasional one that doesn't, such as a full world snapshot among
+//! a game's small state updates, a `Fragmenter` serializes it and cuts it
+//! into numbered fragments that each fit, and a `Reassembler` on the other
+//! end collects them and hands the message back once all of them are in.
+//!
+//! Every fragment starts with a header of `FRAGMENT_HEADER_LEN` bytes: the
+//! id of the message as a big-endian `u32`, then the index of the fragment
+//! and the number of fragments in the message, each as a big-endian `u16`.
+//! A message that fits in one datagram is sent as a single fragment, so
+//! both ends don't have to tell the two kinds apart.
+//!
+//! Datagrams get lost, and a message with a fragment missing would wait
+//! forever.  The reassembler gives up on a message when its first fragment
+//! is older than a timeout, and on the oldest messages when the fragments
+//! it holds go over a memory budget, so that a peer can't make it hold on
+//! to any more than that.
+//!
+//! ```rust,ignore
+//! let mut fragmenter = Fragmenter::new(1200);
+//! for fragment in fragmenter.fragment(&snapshot)? {
+//!     socket.send_to(&fragment, peer)?;
+//! }
+//!
+//! let mut reassembler = Reassembler::new().timeout(Duration::from_secs(2));
+//! let len = socket.recv(&mut buf)?;
+//! if let Some(snapshot) = reassembler.receive::<Snapshot>(&buf[..len], Instant::now())? {
+//!     apply(snapshot);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::time::{Duration, Instant};
+
+use byteorder::{BigEndian, ByteOrder};
+use serde_crate as serde;
+
+use ::serde::{serialize_datagram, deserialize_datagram, SerializeError, DeserializeError};
+
+/// The length of the header in front of every fragment.
+pub const FRAGMENT_HEADER_LEN: usize = 8;
+
+/// The most fragments a message can be cut into.
+pub const MAX_FRAGMENTS: usize = 0xffff;
+
+/// An error that can be produced while fragmenting or reassembling a
+/// message.
+#[derive(Debug)]
+pub enum FragmentError {
+    /// A datagram is too short to be a fragment, or its index and count
+    /// don't make sense, or don't agree with the other fragments of its
+    /// message.
+    Malformed,
+    /// A message would need more than `MAX_FRAGMENTS` fragments, or the
+    /// datagram size has no room for anything after the header.
+    TooLarge,
+    /// A fragment is larger than the whole memory budget of the reassembler.
+    OverBudget,
+    /// A message to send could not be serialized.
+    Serialize(SerializeError),
+    /// A message that was put back together could not be deserialized.
+    Deserialize(DeserializeError),
+}
+
+impl fmt::Display for FragmentError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FragmentError::Malformed =>
+                write!(fmt, "Malformed"),
+            FragmentError::TooLarge =>
+                write!(fmt, "TooLarge"),
+            FragmentError::OverBudget =>
+                write!(fmt, "OverBudget"),
+            FragmentError::Serialize(ref err) =>
+                write!(fmt, "Serialize: {}", err),
+            FragmentError::Deserialize(ref err) =>
+                write!(fmt, "Deserialize: {}", err),
+        }
+    }
+}
+
+pub type FragmentResult<T> = Result<T, FragmentError>;
+
+impl Error for FragmentError {
+    fn description(&self) -> &str {
+        match *self {
+            FragmentError::Malformed => "a datagram is not a valid fragment",
+            FragmentError::TooLarge => "a message needs too many fragments",
+            FragmentError::OverBudget => "a fragment is larger than the memory budget",
+            FragmentError::Serialize(ref err) => err.description(),
+            FragmentError::Deserialize(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            FragmentError::Malformed => None,
+            FragmentError::TooLarge => None,
+            FragmentError::OverBudget => None,
+            FragmentError::Serialize(ref err) => Some(err),
+            FragmentError::Deserialize(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<SerializeError> for FragmentError {
+    fn from(err: SerializeError) -> FragmentError {
+        match err {
+            SerializeError::SizeLimit => FragmentError::TooLarge,
+            err => FragmentError::Serialize(err),
+        }
+    }
+}
+
+impl From<DeserializeError> for FragmentError {
+    fn from(err: DeserializeError) -> FragmentError {
+        FragmentError::Deserialize(err)
+    }
+}
+
+/// Cuts messages into fragments of at most `mtu` bytes each, numbering the
+/// messages as it goes.
+pub struct Fragmenter {
+    mtu: usize,
+    next_id: u32,
+}
+
+impl Fragmenter {
+    /// Creates a fragmenter for datagrams of at most `mtu` bytes, headers
+    /// included.
+    pub fn new(mtu: usize) -> Fragmenter {
+        Fragmenter {
+            mtu: mtu,
+            next_id: 0,
+        }
+    }
+
+    /// The id the next message will be sent with.
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// Serializes `value` and cuts it into fragments.
+    ///
+    /// The message is serialized with `serialize_datagram`, with a budget
+    /// of as many bytes as `MAX_FRAGMENTS` fragments can carry, so a value
+    /// that is too large fails with `FragmentError::TooLarge` as soon as
+    /// it goes over.
+    pub fn fragment<T: serde::Serialize>(&mut self, value: &T) -> FragmentResult<Vec<Vec<u8>>> {
+        let chunk = try!(self.chunk_len());
+        let message = try!(serialize_datagram(value, chunk.saturating_mul(MAX_FRAGMENTS)));
+        self.fragment_bytes(&message)
+    }
+
+    /// Cuts an already serialized message into fragments.
+    pub fn fragment_bytes(&mut self, message: &[u8]) -> FragmentResult<Vec<Vec<u8>>> {
+        let chunk = try!(self.chunk_len());
+        let count = if message.is_empty() { 1 } else { (message.len() + chunk - 1) / chunk };
+        if count > MAX_FRAGMENTS {
+            return Err(FragmentError::TooLarge);
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let mut fragments = Vec::with_capacity(count);
+        for index in 0..count {
+            let start = index * chunk;
+            let end = ::std::cmp::min(start + chunk, message.len());
+            let mut fragment = vec![0; FRAGMENT_HEADER_LEN];
+            BigEndian::write_u32(&mut fragment[0..4], id);
+            BigEndian::write_u16(&mut fragment[4..6], index as u16);
+            BigEndian::write_u16(&mut fragment[6..8], count as u16);
+            fragment.extend_from_slice(&message[start..end]);
+            fragments.push(fragment);
+        }
+        Ok(fragments)
+    }
+
+    /// The number of message bytes that fit in one fragment.
+    fn chunk_len(&self) -> FragmentResult<usize> {
+        if self.mtu <= FRAGMENT_HEADER_LEN {
+            return Err(FragmentError::TooLarge);
+        }
+        Ok(self.mtu - FRAGMENT_HEADER_LEN)
+    }
+}
+
+/// The fragments of a message that has not been completed yet.
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    received: usize,
+    len: usize,
+    started: Instant,
+}
+
+impl Partial {
+    /// The bytes the message counts against the memory budget: its
+    /// fragments, and the table they are kept in.
+    fn cost(&self) -> usize {
+        slots_cost(self.fragments.len()) + self.len
+    }
+}
+
+/// The bytes of the table that keeps the fragments of a message of `count`
+/// fragments, which is allocated as soon as its first fragment arrives.
+fn slots_cost(count: usize) -> usize {
+    count * mem::size_of::<Option<Vec<u8>>>()
+}
+
+/// Puts messages back together out of their fragments, as they arrive in
+/// any order.
+///
+/// Each `Reassembler` is for the fragments of a single sender, since it
+/// tells messages apart by their id alone.  Fragments that come twice are
+/// ignored.  A fragment of a message that was already completed or given
+/// up on starts that message over, and is given up on in turn when it
+/// times out.
+pub struct Reassembler {
+    timeout: Duration,
+    budget: usize,
+    partial: HashMap<u32, Partial>,
+    buffered: usize,
+    dropped: u64,
+}
+
+impl Reassembler {
+    /// Creates a reassembler that gives up on messages after five seconds,
+    /// and holds on to at most 1 MiB of fragments.
+    pub fn new() -> Reassembler {
+        Reassembler {
+            timeout: Duration::from_secs(5),
+            budget: 1 << 20,
+            partial: HashMap::new(),
+            buffered: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Sets how long after its first fragment arrived a message is given
+    /// up on, if it isn't complete by then.
+    pub fn timeout(mut self, timeout: Duration) -> Reassembler {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many bytes of fragments can be held at once.
+    ///
+    /// Every message that has been started also counts a table of
+    /// fragments, of 24 bytes a fragment on 64-bit targets, against the
+    /// budget, so that a flood of first fragments of made-up messages with
+    /// nothing in them can't go past it either.  When a new fragment would
+    /// go over, the messages that were started the longest ago are given up
+    /// on until it fits.
+    pub fn memory_budget(mut self, bytes: usize) -> Reassembler {
+        self.budget = bytes;
+        self
+    }
+
+    /// The number of bytes of fragments being held, counting the table of
+    /// fragments each message is given when it is started.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// The number of messages that have some of their fragments in, but
+    /// not all.
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// The number of messages that were given up on so far, because they
+    /// timed out or to stay within the memory budget.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Takes in a fragment that arrived at `now`, and returns the message
+    /// it belongs to if that was its last missing fragment.
+    ///
+    /// Messages that have timed out by `now` are given up on first.
+    pub fn push(&mut self, datagram: &[u8], now: Instant) -> FragmentResult<Option<Vec<u8>>> {
+        self.expire(now);
+
+        if datagram.len() < FRAGMENT_HEADER_LEN {
+            return Err(FragmentError::Malformed);
+        }
+        let id = BigEndian::read_u32(&datagram[0..4]);
+        let index = BigEndian::read_u16(&datagram[4..6]) as usize;
+        let count = BigEndian::read_u16(&datagram[6..8]) as usize;
+        let payload = &datagram[FRAGMENT_HEADER_LEN..];
+        if index >= count {
+            return Err(FragmentError::Malformed);
+        }
+
+        if count == 1 {
+            return Ok(Some(payload.to_vec()));
+        }
+
+        if let Some(partial) = self.partial.get(&id) {
+            if partial.fragments.len() != count {
+                return Err(FragmentError::Malformed);
+            }
+            if partial.fragments[index].is_some() {
+                return Ok(None);
+            }
+        }
+
+        if slots_cost(count) + payload.len() > self.budget {
+            return Err(FragmentError::OverBudget);
+        }
+        // Making room can give up on this very message, after which its
+        // table has to be paid for again.
+        loop {
+            let needed = if self.partial.contains_key(&id) {
+                payload.len()
+            } else {
+                slots_cost(count) + payload.len()
+            };
+            if self.buffered + needed <= self.budget {
+                self.buffered += needed;
+                break;
+            }
+            self.drop_oldest();
+        }
+
+        let complete = {
+            let partial = self.partial.entry(id).or_insert_with(|| Partial {
+                fragments: vec![None; count],
+                received: 0,
+                len: 0,
+                started: now,
+            });
+            partial.fragments[index] = Some(payload.to_vec());
+            partial.received += 1;
+            partial.len += payload.len();
+            partial.received == count
+        };
+
+        if !complete {
+            return Ok(None);
+        }
+        let partial = self.partial.remove(&id).unwrap();
+        self.buffered -= partial.cost();
+        let mut message = Vec::with_capacity(partial.len);
+        for fragment in partial.fragments {
+            message.extend_from_slice(&fragment.unwrap());
+        }
+        Ok(Some(message))
+    }
+
+    /// Takes in a fragment like `push`, and deserializes the message once it
+    /// is complete.
+    ///
+    /// The message has to take up all of its fragments, as with
+    /// `deserialize_datagram`.
+    pub fn receive<T: serde::Deserialize>(&mut self, datagram: &[u8], now: Instant) -> FragmentResult<Option<T>> {
+        match try!(self.push(datagram, now)) {
+            Some(message) => Ok(Some(try!(deserialize_datagram(&message)))),
+            None => Ok(None),
+        }
+    }
+
+    /// Gives up on the messages whose first fragment arrived longer than
+    /// the timeout before `now`, and returns how many there were.
+    ///
+    /// `push` does this itself, but a reassembler that stops getting
+    /// fragments keeps what it has until this is called.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let timeout = self.timeout;
+        let expired: Vec<u32> = self.partial.iter()
+            .filter(|&(_, partial)| now.duration_since(partial.started) > timeout)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &expired {
+            self.remove(*id);
+        }
+        expired.len()
+    }
+
+    fn drop_oldest(&mut self) {
+        let oldest = self.partial.iter()
+            .min_by_key(|&(_, partial)| partial.started)
+            .map(|(&id, _)| id);
+        if let Some(id) = oldest {
+            self.remove(id);
+        }
+    }
+
+    fn remove(&mut self, id: u32) {
+        if let Some(partial) = self.partial.remove(&id) {
+            self.buffered -= partial.cost();
+            self.dropped += 1;
+        }
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Reassembler {
+        Reassembler::new()
+    }
+}
@@ -0,0 +1,542 @@
+//! Splitting a stream into length-prefixed frames.
+//!
+//! A common way to send several bincode messages down one stream is to put
+//! the length of each message in front of it.  `FrameReader` takes such a
+//! stream apart into the raw bytes of each message, without decoding them,
+//! so that frames can be counted, routed, or stored before anything looks
+//! inside.
+//!
+//! The length is a big-endian `u32` or `u64`.  With a `u64` prefix, a frame
+//! is laid out exactly like bincode encodes a byte buffer, so a stream of
+//! serialized `Vec<u8>`s can be read back as frames.
+//!
+//! `FrameWriter` writes frames the same way.  Over a transport that can
+//! lose or reorder messages, both ends can also put a sequence number in
+//! front of every frame: a big-endian `u64` that starts at 0 and goes up by
+//! one with each frame, ahead of the length.  The reader then reports a
+//! `FrameGap` whenever a frame doesn't carry the number it expected.
+//!
+//! `FrameSink` queues frames for a non-blocking writer, and pushes back on
+//! whoever is producing them once too many bytes are waiting to be written.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::io::Error as IoError;
+
+use byteorder::{BigEndian, ByteOrder};
+
+/// The width of the length in front of every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthPrefix {
+    /// A big-endian `u32`.
+    U32,
+    /// A big-endian `u64`, which is how bincode encodes lengths itself.
+    U64,
+}
+
+impl LengthPrefix {
+    /// The number of bytes the length takes up.
+    pub fn width(&self) -> usize {
+        match *self {
+            LengthPrefix::U32 => 4,
+            LengthPrefix::U64 => 8,
+        }
+    }
+
+    fn read(&self, bytes: &[u8]) -> u64 {
+        match *self {
+            LengthPrefix::U32 => BigEndian::read_u32(bytes) as u64,
+            LengthPrefix::U64 => BigEndian::read_u64(bytes),
+        }
+    }
+}
+
+/// An error that can be produced while reading frames.
+#[derive(Debug)]
+pub enum FrameError {
+    /// If the error stems from the reader that frames are read from, that
+    /// error will be stored and returned here.
+    IoError(IoError),
+    /// The stream ended in the middle of a frame.
+    Truncated,
+    /// A frame is longer than the maximum allowed length.
+    SizeLimit,
+    /// A frame didn't carry the sequence number that was expected, because
+    /// frames before it were lost or it arrived out of order.
+    ///
+    /// The frame itself has been read, and the next call to `next_frame`
+    /// returns it.
+    FrameGap {
+        /// The sequence number that was expected.
+        expected: u64,
+        /// The sequence number the frame carried.
+        got: u64,
+    },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameError::IoError(ref ioerr) =>
+                write!(fmt, "IoError: {}", ioerr),
+            FrameError::Truncated =>
+                write!(fmt, "Truncated"),
+            FrameError::SizeLimit =>
+                write!(fmt, "SizeLimit"),
+            FrameError::FrameGap { expected, got } =>
+                write!(fmt, "FrameGap: expected frame {}, got frame {}", expected, got),
+        }
+    }
+}
+
+pub type FrameResult<T> = Result<T, FrameError>;
+
+impl Error for FrameError {
+    fn description(&self) -> &str {
+        match *self {
+            FrameError::IoError(ref err) => Error::description(err),
+            FrameError::Truncated => "the stream ended in the middle of a frame",
+            FrameError::SizeLimit => "a frame is longer than the size limit",
+            FrameError::FrameGap { .. } => "a frame didn't carry the expected sequence number",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            FrameError::IoError(ref err) => err.cause(),
+            FrameError::Truncated => None,
+            FrameError::SizeLimit => None,
+            FrameError::FrameGap { .. } => None,
+        }
+    }
+}
+
+impl From<IoError> for FrameError {
+    fn from(err: IoError) -> FrameError {
+        FrameError::IoError(err)
+    }
+}
+
+/// Reads length-prefixed frames from a `Read`.
+///
+/// Each call to `next_frame` reads one whole frame and returns its payload,
+/// without the length.  The reader can also be used as an iterator over
+/// owned frames.
+///
+/// A frame whose length is over the maximum is rejected before any of it is
+/// read.  The reader is left right after that frame's length, so the stream
+/// can't be read any further.
+pub struct FrameReader<R> {
+    reader: R,
+    prefix: LengthPrefix,
+    max_len: u64,
+    buffer: Vec<u8>,
+    sequence: Option<u64>,
+    pending: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Creates a frame reader for frames with a `u64` length and no
+    /// maximum length.
+    pub fn new(reader: R) -> FrameReader<R> {
+        FrameReader {
+            reader: reader,
+            prefix: LengthPrefix::U64,
+            max_len: ::std::u64::MAX,
+            buffer: Vec::new(),
+            sequence: None,
+            pending: false,
+        }
+    }
+
+    /// Sets the width of the length in front of every frame.
+    pub fn length_prefix(mut self, prefix: LengthPrefix) -> FrameReader<R> {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Sets the longest payload to accept.
+    ///
+    /// Without a maximum, a corrupt or malicious length can make the reader
+    /// allocate as much memory as the stream has bytes.
+    pub fn max_frame_len(mut self, max_len: u64) -> FrameReader<R> {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Expects a sequence number in front of every frame, as written by a
+    /// `FrameWriter` with sequence numbers, starting from 0.
+    ///
+    /// A frame whose number isn't the one expected is reported as a
+    /// `FrameError::FrameGap`, and is returned by the call after that.  From
+    /// then on, the reader expects the number after the one that frame
+    /// carried, so a run of lost frames is only reported once.
+    pub fn sequence_numbers(mut self) -> FrameReader<R> {
+        self.sequence = Some(0);
+        self
+    }
+
+    /// Reads the next frame, and returns its payload.
+    ///
+    /// Returns `None` if the stream ends right before a frame, and
+    /// `FrameError::Truncated` if it ends in the middle of one.
+    pub fn next_frame(&mut self) -> FrameResult<Option<&[u8]>> {
+        if self.pending {
+            self.pending = false;
+            return Ok(Some(&self.buffer));
+        }
+
+        let mut header = [0u8; 16];
+        let seq_width = if self.sequence.is_some() { 8 } else { 0 };
+        let width = seq_width + self.prefix.width();
+        let filled = try!(read_full(&mut self.reader, &mut header[..width]));
+        if filled == 0 {
+            return Ok(None);
+        }
+        if filled < width {
+            return Err(FrameError::Truncated);
+        }
+
+        let len = self.prefix.read(&header[seq_width..width]);
+        if len > self.max_len {
+            return Err(FrameError::SizeLimit);
+        }
+
+        // Reading through `take` grows the buffer as data arrives, so a
+        // length that is larger than the rest of the stream doesn't get
+        // allocated up front.
+        self.buffer.clear();
+        try!(self.reader.by_ref().take(len).read_to_end(&mut self.buffer));
+        if (self.buffer.len() as u64) < len {
+            return Err(FrameError::Truncated);
+        }
+
+        if let Some(expected) = self.sequence {
+            let got = BigEndian::read_u64(&header[..8]);
+            self.sequence = Some(got.wrapping_add(1));
+            if got != expected {
+                self.pending = true;
+                return Err(FrameError::FrameGap { expected: expected, got: got });
+            }
+        }
+        Ok(Some(&self.buffer))
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwraps the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = FrameResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<FrameResult<Vec<u8>>> {
+        match self.next_frame() {
+            Ok(Some(frame)) => Some(Ok(frame.to_vec())),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// Writes length-prefixed frames to a `Write`, in the layout `FrameReader`
+/// reads.
+pub struct FrameWriter<W> {
+    writer: W,
+    prefix: LengthPrefix,
+    sequence: Option<u64>,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// Creates a frame writer for frames with a `u64` length.
+    pub fn new(writer: W) -> FrameWriter<W> {
+        FrameWriter {
+            writer: writer,
+            prefix: LengthPrefix::U64,
+            sequence: None,
+        }
+    }
+
+    /// Sets the width of the length in front of every frame.
+    pub fn length_prefix(mut self, prefix: LengthPrefix) -> FrameWriter<W> {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Puts a sequence number in front of every frame, starting from 0.
+    ///
+    /// The reader has to expect them too, with
+    /// `FrameReader::sequence_numbers`.
+    pub fn sequence_numbers(mut self) -> FrameWriter<W> {
+        self.sequence = Some(0);
+        self
+    }
+
+    /// The sequence number the next frame will carry, if frames carry one.
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    /// Writes one frame with `payload` in it.
+    ///
+    /// The header and payload are written with a single `write_all`, so a
+    /// transport that sends every write as its own message doesn't split
+    /// them up.  Returns `FrameError::SizeLimit` if the payload is too long
+    /// for a `u32` length, before anything is written.
+    pub fn write_frame(&mut self, payload: &[u8]) -> FrameResult<()> {
+        let mut frame = Vec::with_capacity(16 + payload.len());
+        try!(encode_frame(self.prefix, self.sequence, payload, &mut frame));
+        try!(self.writer.write_all(&frame));
+        if let Some(sequence) = self.sequence {
+            self.sequence = Some(sequence.wrapping_add(1));
+        }
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> FrameResult<()> {
+        try!(self.writer.flush());
+        Ok(())
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Unwraps the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Queues length-prefixed frames for a non-blocking `Write`, and stops
+/// taking them once too many bytes are waiting to be written.
+///
+/// A `FrameWriter` on a non-blocking socket can't tell a slow reader from
+/// a fast one, and a program that queues every frame it makes will hold
+/// more and more of them in memory when the other end falls behind.  A
+/// `FrameSink` keeps the encoded frames in one buffer, and refuses new ones
+/// while that buffer is over its high-water mark.  It is driven the way an
+/// event loop drives a sink:
+///
+/// * `poll_ready` writes what it can, and returns whether another frame
+///   will be taken;
+/// * `start_send` encodes a frame into the buffer, or hands back `false`
+///   if the sink is over the mark, so the caller stops producing frames
+///   until the writer is ready again;
+/// * `poll_flush` writes what it can, and returns whether everything has
+///   been written and flushed.
+///
+/// A write that fails with `WouldBlock` isn't an error: these methods
+/// return `Ok(false)`, and should be called again once the writer is
+/// writable.  The frames are laid out as a `FrameWriter` lays them out.
+///
+/// ```rust,ignore
+/// let mut sink = FrameSink::new(stream).high_water_mark(1 << 20);
+/// while sink.poll_ready()? {
+///     match outgoing.pop() {
+///         Some(message) => { sink.start_send(&message)?; }
+///         None => break,
+///     }
+/// }
+/// sink.poll_flush()?;
+/// ```
+pub struct FrameSink<W> {
+    writer: W,
+    prefix: LengthPrefix,
+    sequence: Option<u64>,
+    buffer: Vec<u8>,
+    written: usize,
+    high_water: usize,
+    flushed: bool,
+}
+
+impl<W: Write> FrameSink<W> {
+    /// Creates a frame sink for frames with a `u64` length and a high-water
+    /// mark of 64 KiB.
+    pub fn new(writer: W) -> FrameSink<W> {
+        FrameSink {
+            writer: writer,
+            prefix: LengthPrefix::U64,
+            sequence: None,
+            buffer: Vec::new(),
+            written: 0,
+            high_water: 64 * 1024,
+            flushed: true,
+        }
+    }
+
+    /// Sets the width of the length in front of every frame.
+    pub fn length_prefix(mut self, prefix: LengthPrefix) -> FrameSink<W> {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Puts a sequence number in front of every frame, starting from 0, as
+    /// `FrameWriter::sequence_numbers` does.
+    pub fn sequence_numbers(mut self) -> FrameSink<W> {
+        self.sequence = Some(0);
+        self
+    }
+
+    /// Sets how many bytes can be waiting to be written before the sink
+    /// stops taking frames.
+    ///
+    /// A frame is taken whenever fewer bytes than this are waiting, however
+    /// long it is, so the buffer can grow past the mark by one frame.
+    pub fn high_water_mark(mut self, bytes: usize) -> FrameSink<W> {
+        self.high_water = bytes;
+        self
+    }
+
+    /// The number of bytes waiting to be written.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.written
+    }
+
+    /// The sequence number the next frame will carry, if frames carry one.
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.sequence
+    }
+
+    /// Writes as much of the buffer as the writer takes, and returns
+    /// whether the sink will take another frame.
+    pub fn poll_ready(&mut self) -> FrameResult<bool> {
+        if self.buffered() >= self.high_water {
+            try!(self.write_buffer());
+        }
+        Ok(self.buffered() < self.high_water)
+    }
+
+    /// Encodes a frame with `payload` in it into the buffer.
+    ///
+    /// Returns `false`, and leaves the buffer as it was, if the sink is
+    /// over its high-water mark; call `poll_ready` until it isn't and try
+    /// again.  Nothing is written to the writer until `poll_ready` or
+    /// `poll_flush` is called.  Returns `FrameError::SizeLimit` if the
+    /// payload is too long for a `u32` length.
+    pub fn start_send(&mut self, payload: &[u8]) -> FrameResult<bool> {
+        if self.buffered() >= self.high_water {
+            return Ok(false);
+        }
+        if self.written > 0 {
+            self.buffer.drain(..self.written);
+            self.written = 0;
+        }
+        try!(encode_frame(self.prefix, self.sequence, payload, &mut self.buffer));
+        if let Some(sequence) = self.sequence {
+            self.sequence = Some(sequence.wrapping_add(1));
+        }
+        self.flushed = false;
+        Ok(true)
+    }
+
+    /// Writes as much of the buffer as the writer takes, and flushes the
+    /// writer once all of it is written.
+    ///
+    /// Returns whether every frame has been written and flushed.
+    pub fn poll_flush(&mut self) -> FrameResult<bool> {
+        if !try!(self.write_buffer()) {
+            return Ok(false);
+        }
+        if !self.flushed {
+            match self.writer.flush() {
+                Ok(()) => self.flushed = true,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(err) => return Err(FrameError::IoError(err)),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// Writing to it directly while frames are buffered will most likely
+    /// leave the stream in the middle of a frame.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Unwraps the underlying writer.  Frames that haven't been written
+    /// yet are dropped, so call `poll_flush` until it returns `true` first.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes until the buffer is empty or the writer would block, and
+    /// returns whether the buffer is empty.
+    fn write_buffer(&mut self) -> FrameResult<bool> {
+        while self.written < self.buffer.len() {
+            match self.writer.write(&self.buffer[self.written..]) {
+                Ok(0) => return Err(FrameError::IoError(
+                    IoError::new(io::ErrorKind::WriteZero, "the writer stopped taking bytes"))),
+                Ok(n) => self.written += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(err) => return Err(FrameError::IoError(err)),
+            }
+        }
+        self.buffer.clear();
+        self.written = 0;
+        Ok(true)
+    }
+}
+
+/// Appends a frame with `payload` in it to `out`, with a sequence number in
+/// front if there is one.
+fn encode_frame(prefix: LengthPrefix, sequence: Option<u64>, payload: &[u8], out: &mut Vec<u8>) -> FrameResult<()> {
+    let len = payload.len() as u64;
+    let mut header = [0u8; 16];
+    let mut width = 0;
+    if let Some(sequence) = sequence {
+        BigEndian::write_u64(&mut header[..8], sequence);
+        width = 8;
+    }
+    match prefix {
+        LengthPrefix::U32 if len > ::std::u32::MAX as u64 => return Err(FrameError::SizeLimit),
+        LengthPrefix::U32 => BigEndian::write_u32(&mut header[width..width + 4], len as u32),
+        LengthPrefix::U64 => BigEndian::write_u64(&mut header[width..width + 8], len),
+    }
+    width += prefix.width();
+    out.extend_from_slice(&header[..width]);
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Reads until `buf` is full or the stream ends, and returns how many bytes
+/// were read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
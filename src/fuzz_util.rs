@@ -0,0 +1,62 @@
+//! Helpers for fuzzing bincode, and types that are decoded with it.
+//!
+//! The targets in bincode's `fuzz` directory are built on these, and they
+//! work just as well from a crate's own fuzz targets:
+//!
+//! ```rust,ignore
+//! fuzz_target!(|data: &[u8]| {
+//!     bincode::fuzz_util::arbitrary_roundtrip::<MyMessage>(data, 4096);
+//! });
+//! ```
+//!
+//! The helpers panic when something is wrong, which is what fuzzers look
+//! for.  They are enabled by the `fuzz` feature.
+
+use std::fmt::Debug;
+
+use serde_crate::{Serialize, Deserialize};
+
+use ::{Bounded, Infinite};
+use ::roundtrip::assert_roundtrip;
+use ::serde::deserialize_from_with_stats;
+
+/// Decodes a `T` from arbitrary bytes, and checks that it round trips.
+///
+/// Decoding may fail, but it must not panic, and it must stay within
+/// `limit`: neither the bytes read nor the largest string or byte buffer
+/// allocated may go past it.  If decoding succeeds, the value is handed to
+/// `roundtrip`.
+///
+/// bincode has no separate limit on how deeply values may nest, but every
+/// level of a recursive type costs at least one byte for the `Option` tag,
+/// enum tag, or sequence length that it needs, so `limit` also bounds the
+/// depth.  Keep it small enough that a value nested `limit` levels deep
+/// doesn't overflow the stack.
+pub fn arbitrary_roundtrip<T>(data: &[u8], limit: u64)
+    where T: Serialize + Deserialize + PartialEq + Debug
+{
+    let mut reader = data;
+    let (value, stats) = match deserialize_from_with_stats::<_, T, _>(&mut reader, Bounded(limit)) {
+        Ok(decoded) => decoded,
+        Err(_) => return,
+    };
+
+    assert!(stats.bytes <= limit,
+            "read {} bytes with a limit of {}", stats.bytes, limit);
+    assert!(stats.largest_allocation as u64 <= limit,
+            "allocated {} bytes with a limit of {}", stats.largest_allocation, limit);
+    assert_eq!(stats.bytes, (data.len() - reader.len()) as u64);
+
+    roundtrip(&value);
+}
+
+/// Checks that `value` decodes to itself after being encoded, and that
+/// `serialized_size` agrees with the length of the encoding.
+///
+/// `T`'s `PartialEq` has to be reflexive for the values being checked, so
+/// floats that may be NaN need a wrapper that compares their bits.
+pub fn roundtrip<T>(value: &T)
+    where T: Serialize + Deserialize + PartialEq + Debug
+{
+    assert_roundtrip(value, Infinite);
+}
@@ -11,7 +11,40 @@
 //! or the `serde` crate.  `rustc_serialize` and `serde` are crates and and also the names of their
 //! corresponding modules inside of `bincode`.  Both modules have exactly equivalant functions, and
 //! and the only difference is whether or not the library user wants to use `rustc_serialize` or
-//! `serde`.
+//! `serde`.  Both write exactly the same bytes for the same value, as does the `codec` module
+//! below, since all three leave the encoding of everything that isn't a plain number to one
+//! module, `format`, whose constants give the width of each length prefix and tag.
+//!
+//! If you don't want to depend on either, the `codec` module offers the same functions on top of
+//! bincode's own `Encode` and `Decode` traits.  Enable the `derive` feature to get
+//! `#[derive(Encode, Decode)]`, and `#[derive(Pod)]` for plain-old-data structs that are
+//! copied in bulk.
+//!
+//! The `frame` module splits a stream of length-prefixed messages into frames without decoding
+//! them, can number the frames to catch ones that were lost or reordered, and queues frames for
+//! non-blocking writers with a high-water mark for back-pressure.  The `channel` module sends and
+//! receives typed `serde` messages over such a stream, and the `fragment` module cuts messages
+//! that are too large for one datagram into fragments and puts them back together.
+//! A `SliceCursor` reads messages or frames out of a buffer one after the other, keeping track of
+//! the offset, and can peek at the next one without moving past it.
+//! The `log` module appends checksummed records to a file and reads them back after a crash, and
+//! the `pool` module serializes frames into buffers that are reused between messages.  The
+//! `container` module writes several named values to one file, with an index at the end, so that
+//! they can be read back one at a time.
+//!
+//! The `schema` module describes the wire layout of a `serde` type, for decoders written in other
+//! languages, the `testvectors` module checks encodings against committed fixtures, and the
+//! `roundtrip` module checks that generated values of a type round trip.  `test_util` has an
+//! in-memory loopback for testing protocol code without sockets, and readers and writers that
+//! fail the way sockets do, on a seeded schedule.
+//!
+//! The `wasm` feature adds the `wasm` module, which converts messages to and from JavaScript
+//! `Uint8Array`s, and the `ffi` feature adds the `ffi` module, which lets C code find and check
+//! bincode frames.  The `bytes` feature adds the `bytes` module, which encodes into and decodes
+//! out of the `bytes` crate's `BufMut` and `Buf`.  The `text` feature adds the `text` module, which
+//! serializes to and deserializes from hex and base64.  The `fuzz` feature adds `fuzz_util`,
+//! which the fuzz targets in the `fuzz` directory are built on.  The `shmem` feature adds the
+//! `shmem` module, a ring of frames in a shared memory region for messages between processes.
 //!
 //! ### Using Basic Functions
 //!
@@ -23,7 +56,7 @@
 //!     // The object that we will serialize.
 //!     let target = Some("hello world".to_string());
 //!     // The maximum size of the encoded message.
-//!     let limit = bincode::SizeLimit::Bounded(20);
+//!     let limit = bincode::Bounded(20);
 //!
 //!     let encoded: Vec<u8>        = encode(&target, limit).unwrap();
 //!     let decoded: Option<String> = decode(&encoded[..]).unwrap();
@@ -43,15 +76,65 @@ extern crate byteorder;
 extern crate num_traits;
 #[cfg(feature = "serde")]
 extern crate serde as serde_crate;
+#[cfg(feature = "derive")]
+extern crate bincode_derive;
+#[cfg(feature = "wasm")]
+extern crate js_sys;
+#[cfg(feature = "bytes")]
+extern crate bytes as bytes_crate;
 
 
 pub use refbox::{RefBox, StrBox, SliceBox};
+pub use max_size::{MaxSize, MaxSizeIn};
+pub use tee::TeeWrite;
+pub use cursor::{SliceCursor, Checkpoint};
+pub use codec::{Encode, Decode};
+#[cfg(feature = "serde")]
+pub use schema::{Value, FieldDiff, diff, probe, ProbeResult};
+#[cfg(feature = "derive")]
+pub use bincode_derive::{Encode, Decode, Pod, MaxSize};
 
 mod refbox;
+mod max_size;
+mod tee;
+mod cursor;
+pub mod format;
+pub mod codec;
+pub mod frame;
 #[cfg(feature = "rustc-serialize")]
 pub mod rustc_serialize;
 #[cfg(feature = "serde")]
 pub mod serde;
+#[cfg(feature = "serde")]
+pub mod channel;
+#[cfg(feature = "serde")]
+pub mod log;
+#[cfg(feature = "serde")]
+pub mod pool;
+#[cfg(feature = "serde")]
+pub mod fragment;
+#[cfg(feature = "serde")]
+pub mod container;
+#[cfg(feature = "serde")]
+pub mod schema;
+#[cfg(feature = "serde")]
+pub mod test_util;
+#[cfg(feature = "serde")]
+pub mod testvectors;
+#[cfg(feature = "serde")]
+pub mod roundtrip;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(all(feature = "bytes", feature = "serde"))]
+pub mod bytes;
+#[cfg(feature = "text")]
+pub mod text;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fuzz")]
+pub mod fuzz_util;
+#[cfg(feature = "shmem")]
+pub mod shmem;
 
 /// A limit on the amount of bytes that can be read or written.
 ///
@@ -71,9 +154,67 @@ pub mod serde;
 /// encoding function, the encoder will verify that the structure can be encoded
 /// within that limit.  This verification occurs before any bytes are written to
 /// the Writer, so recovering from an error is easy.
+///
+/// bincode ships with the `Infinite` and `Bounded` policies.  Decoders are
+/// generic over the policy, so you can plug in your own, for example one that
+/// only logs when a soft limit is crossed.  Encoders only ever look at
+/// `limit()`, since they check the size of a value before writing it.
+pub trait SizeLimit {
+    /// Tells the limit that `n` more bytes are about to be read.
+    ///
+    /// Returns `false` if reading them would go over the limit, in which
+    /// case the decoder bails out with a size limit error.
+    fn add(&mut self, n: u64) -> bool;
+
+    /// Returns the number of bytes that can still be read or written, or
+    /// `None` if there is no limit.
+    fn limit(&self) -> Option<u64>;
+}
+
+/// A `SizeLimit` that lets any number of bytes through.
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
-pub enum SizeLimit {
-    Infinite,
-    Bounded(u64)
+pub struct Infinite;
+
+/// A `SizeLimit` that allows at most the given number of bytes.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Bounded(pub u64);
+
+impl SizeLimit for Infinite {
+    #[inline(always)]
+    fn add(&mut self, _: u64) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn limit(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl SizeLimit for Bounded {
+    #[inline(always)]
+    fn add(&mut self, n: u64) -> bool {
+        if self.0 >= n {
+            self.0 -= n;
+            true
+        } else {
+            false
+        }
+    }
+
+    #[inline(always)]
+    fn limit(&self) -> Option<u64> {
+        Some(self.0)
+    }
 }
 
+/// Lets a caller keep hold of a stateful limit and look at it afterwards.
+impl<'a, S: SizeLimit + ?Sized> SizeLimit for &'a mut S {
+    fn add(&mut self, n: u64) -> bool {
+        (**self).add(n)
+    }
+
+    fn limit(&self) -> Option<u64> {
+        (**self).limit()
+    }
+}
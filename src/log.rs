@@ -0,0 +1,730 @@
+//! An append-only log of records that survives crashes.
+//!
+//! A `RecordWriter` appends serialized records to a file (or any `Write`),
+//! each one framed with its length and a CRC-32 checksum:
+//!
+//! ```text
+//! length: u32 (big-endian) | crc: u32 (big-endian) | payload: [u8; length]
+//! ```
+//!
+//! The checksum covers the length and the payload.  A `RecordReader` reads
+//! the records back and checks every checksum.  When a process dies in the
+//! middle of an append, the end of the file holds a torn record: a partial
+//! header, a partial payload, or a payload that never made it to disk.
+//! The reader stops cleanly at a torn record at the end of the log, and
+//! `valid_len` tells where the intact records end, so the file can be cut
+//! back to that length before appending again.  A bad record anywhere
+//! else is reported as corruption.
+//!
+//! ```rust,ignore
+//! let mut file = OpenOptions::new().read(true).write(true).create(true).open("queue.log")?;
+//! let mut reader = RecordReader::new(&mut file);
+//! while let Some(event) = reader.next_record::<Event>()? {
+//!     replay(event);
+//! }
+//! let end = reader.valid_len();
+//! file.set_len(end)?;
+//! file.seek(SeekFrom::Start(end))?;
+//!
+//! let mut writer = RecordWriter::new(file).sync_with(|file: &mut File| file.sync_data());
+//! writer.append(&Event::Started)?;
+//! ```
+//!
+//! With `resync_markers` on both sides, every record is preceded by a
+//! four-byte sync marker, `b1 9c 5a 7e`.  A record that is damaged in the
+//! middle of the log is then no longer the end of it: after the error,
+//! `RecordReader::resync` scans forward to the next marker and reading
+//! carries on from the record behind it.
+//!
+//! ```rust,ignore
+//! let mut reader = RecordReader::new(BufReader::new(file)).resync_markers();
+//! loop {
+//!     match reader.next_record::<Event>() {
+//!         Ok(Some(event)) => replay(event),
+//!         Ok(None) => break,
+//!         Err(err) => {
+//!             warn!("skipping a damaged record: {}", err);
+//!             if reader.resync()?.is_none() {
+//!                 break;
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! A `Journal` keeps a value up to date on top of such a log.  Changes are
+//! appended as deltas, with a snapshot of the whole value every so often,
+//! and `Journal::replay` rebuilds the value from the latest snapshot and
+//! the deltas after it.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Cursor, Read, Write};
+use std::io::Error as IoError;
+
+use byteorder::{BigEndian, ByteOrder};
+use serde_crate as serde;
+
+use ::{SizeLimit, Infinite};
+use ::serde::{serialize, serialize_into, deserialize_from, SerializeError, DeserializeError};
+
+/// The bytes in front of every record: its length and checksum.
+const HEADER_LEN: usize = 8;
+
+/// The sync marker that goes in front of every record when markers are on.
+const MARKER: [u8; 4] = [0xb1, 0x9c, 0x5a, 0x7e];
+
+/// An error that can be produced while appending or reading records.
+#[derive(Debug)]
+pub enum LogError {
+    /// If the error stems from the underlying file, that error will be
+    /// stored and returned here.
+    IoError(IoError),
+    /// A record that is followed by more data has a bad checksum.  The
+    /// offset of the record is given.
+    Corrupt(u64),
+    /// A record is longer than the maximum allowed length.
+    SizeLimit,
+    /// A record to append could not be serialized.
+    Serialize(SerializeError),
+    /// A record that was read could not be deserialized.
+    Deserialize(DeserializeError),
+    /// The records in a journal are out of order, or one of them isn't
+    /// a journal entry.
+    InvalidJournal(&'static str),
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LogError::IoError(ref ioerr) =>
+                write!(fmt, "IoError: {}", ioerr),
+            LogError::Corrupt(offset) =>
+                write!(fmt, "Corrupt: bad checksum at offset {}", offset),
+            LogError::SizeLimit =>
+                write!(fmt, "SizeLimit"),
+            LogError::Serialize(ref err) =>
+                write!(fmt, "Serialize: {}", err),
+            LogError::Deserialize(ref err) =>
+                write!(fmt, "Deserialize: {}", err),
+            LogError::InvalidJournal(desc) =>
+                write!(fmt, "InvalidJournal: {}", desc),
+        }
+    }
+}
+
+pub type LogResult<T> = Result<T, LogError>;
+
+impl Error for LogError {
+    fn description(&self) -> &str {
+        match *self {
+            LogError::IoError(ref err) => Error::description(err),
+            LogError::Corrupt(_) => "a record in the middle of the log has a bad checksum",
+            LogError::SizeLimit => "a record is longer than the size limit",
+            LogError::Serialize(ref err) => err.description(),
+            LogError::Deserialize(ref err) => err.description(),
+            LogError::InvalidJournal(desc) => desc,
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            LogError::IoError(ref err) => err.cause(),
+            LogError::Corrupt(_) => None,
+            LogError::SizeLimit => None,
+            LogError::Serialize(ref err) => Some(err),
+            LogError::Deserialize(ref err) => Some(err),
+            LogError::InvalidJournal(_) => None,
+        }
+    }
+}
+
+impl From<IoError> for LogError {
+    fn from(err: IoError) -> LogError {
+        LogError::IoError(err)
+    }
+}
+
+impl From<SerializeError> for LogError {
+    fn from(err: SerializeError) -> LogError {
+        match err {
+            SerializeError::IoError(err) => LogError::IoError(err),
+            SerializeError::SizeLimit => LogError::SizeLimit,
+            err => LogError::Serialize(err),
+        }
+    }
+}
+
+impl From<DeserializeError> for LogError {
+    fn from(err: DeserializeError) -> LogError {
+        LogError::Deserialize(err)
+    }
+}
+
+type SyncHook<W> = Box<FnMut(&mut W) -> io::Result<()>>;
+
+/// Appends checksummed records to a `Write`.
+///
+/// Every record is written with a single `write_all`, then the writer is
+/// flushed.  How often records are made durable is up to the sync hook and
+/// `sync_every`: by default nothing beyond the flush is done, which leaves
+/// the data to the operating system.
+pub struct RecordWriter<W> {
+    writer: W,
+    sync: Option<SyncHook<W>>,
+    sync_every: u64,
+    unsynced: u64,
+    markers: bool,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> RecordWriter<W> {
+    /// Creates a `RecordWriter` that appends to the end of `writer`.
+    pub fn new(writer: W) -> RecordWriter<W> {
+        RecordWriter {
+            writer: writer,
+            sync: None,
+            sync_every: 1,
+            unsynced: 0,
+            markers: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Sets the hook that makes appended records durable, such as
+    /// `File::sync_data`.
+    pub fn sync_with<F>(mut self, sync: F) -> RecordWriter<W>
+        where F: FnMut(&mut W) -> io::Result<()> + 'static
+    {
+        self.sync = Some(Box::new(sync));
+        self
+    }
+
+    /// Calls the sync hook after every `count` records, instead of after
+    /// every one.  With `0`, it is only called by `sync`.
+    ///
+    /// Records appended since the last sync can be lost in a crash, and
+    /// then show up as a torn end of the log.
+    pub fn sync_every(mut self, count: u64) -> RecordWriter<W> {
+        self.sync_every = count;
+        self
+    }
+
+    /// Puts a sync marker in front of every record, so that a reader can
+    /// find the next record after a damaged one.  The log has to be read
+    /// with `RecordReader::resync_markers`.
+    pub fn resync_markers(mut self) -> RecordWriter<W> {
+        self.markers = true;
+        self
+    }
+
+    /// Appends a record holding `bytes`.
+    pub fn append_bytes(&mut self, bytes: &[u8]) -> LogResult<()> {
+        if bytes.len() as u64 > ::std::u32::MAX as u64 {
+            return Err(LogError::SizeLimit);
+        }
+        self.buffer.clear();
+        if self.markers {
+            self.buffer.extend_from_slice(&MARKER);
+        }
+        let start = self.buffer.len();
+        self.buffer.resize(start + HEADER_LEN, 0);
+        BigEndian::write_u32(&mut self.buffer[start..start + 4], bytes.len() as u32);
+        let crc = crc32_update(crc32_update(!0, &self.buffer[start..start + 4]), bytes);
+        BigEndian::write_u32(&mut self.buffer[start + 4..start + HEADER_LEN], !crc);
+        self.buffer.extend_from_slice(bytes);
+
+        try!(self.writer.write_all(&self.buffer));
+        try!(self.writer.flush());
+        self.unsynced += 1;
+        if self.sync_every != 0 && self.unsynced >= self.sync_every {
+            try!(self.sync());
+        }
+        Ok(())
+    }
+
+    /// Serializes `record` and appends it.
+    pub fn append<T: serde::Serialize>(&mut self, record: &T) -> LogResult<()> {
+        let bytes = try!(serialize(record, Infinite));
+        self.append_bytes(&bytes)
+    }
+
+    /// Flushes the writer and calls the sync hook.
+    pub fn sync(&mut self) -> LogResult<()> {
+        try!(self.writer.flush());
+        if let Some(ref mut sync) = self.sync {
+            try!(sync(&mut self.writer));
+        }
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Unwraps the underlying writer, without syncing it.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads checksummed records from a `Read`.
+pub struct RecordReader<R> {
+    reader: R,
+    max_len: u64,
+    valid_len: u64,
+    torn: bool,
+    markers: bool,
+    /// Whether the marker in front of the next record has been read by
+    /// `resync` already.
+    after_marker: bool,
+    /// How many bytes have been read, not counting those in `pushback`.
+    position: u64,
+    /// Bytes that were read ahead, to tell a damaged record from a torn
+    /// one or to find the marker after it, and have to be read again
+    /// before the rest of the log.
+    pushback: Cursor<Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl<R: Read> RecordReader<R> {
+    /// Creates a `RecordReader` that reads from the start of a log.
+    pub fn new(reader: R) -> RecordReader<R> {
+        RecordReader {
+            reader: reader,
+            max_len: ::std::u32::MAX as u64,
+            valid_len: 0,
+            torn: false,
+            markers: false,
+            after_marker: false,
+            position: 0,
+            pushback: Cursor::new(Vec::new()),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Sets the longest record to accept.
+    ///
+    /// A longer record is an error rather than a torn end, since it can't
+    /// have been appended by a writer that respects the same limit.
+    pub fn max_record_len(mut self, max_len: u64) -> RecordReader<R> {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Expects a sync marker in front of every record, as written by
+    /// `RecordWriter::resync_markers`.  A record without one is corrupt.
+    pub fn resync_markers(mut self) -> RecordReader<R> {
+        self.markers = true;
+        self
+    }
+
+    /// Reads the next record, and returns its payload as bytes.
+    ///
+    /// Returns `None` at the end of the log, whether it ends cleanly or
+    /// with a torn record.
+    pub fn next_bytes(&mut self) -> LogResult<Option<&[u8]>> {
+        if self.torn {
+            return Ok(None);
+        }
+
+        // The marker and the header are appended together, so a torn
+        // append can end anywhere in either.
+        let mut marked = self.after_marker;
+        if self.markers && !marked {
+            let mut marker = [0; 4];
+            let filled = try!(self.read_head(&mut marker));
+            if filled < marker.len() {
+                self.torn = filled > 0;
+                return Ok(None);
+            }
+            if marker != MARKER {
+                return Err(LogError::Corrupt(self.valid_len));
+            }
+            marked = true;
+        }
+        self.after_marker = false;
+
+        let mut header = [0; HEADER_LEN];
+        let filled = try!(self.read_head(&mut header));
+        if filled < HEADER_LEN {
+            self.torn = filled > 0 || marked;
+            return Ok(None);
+        }
+        let len = BigEndian::read_u32(&header[..4]) as u64;
+        let crc = BigEndian::read_u32(&header[4..]);
+        if len > self.max_len {
+            return Err(LogError::SizeLimit);
+        }
+
+        // A length that runs past the end of the log is what a torn append
+        // looks like, so it is read through `take` rather than trusted.
+        self.buffer.clear();
+        try!(Read::chain(&mut self.pushback, &mut self.reader).take(len).read_to_end(&mut self.buffer));
+        self.position += self.buffer.len() as u64;
+        let short = (self.buffer.len() as u64) < len;
+        if short || !crc32_update(crc32_update(!0, &header[..4]), &self.buffer) != crc {
+            // A damaged length can take in the records after it, or run
+            // past the end of the log as if the record was torn.  With
+            // markers, whatever was read is searched for the next one, so
+            // that those records can still be found.
+            if self.markers {
+                if let Some(found) = find_marker(&self.buffer) {
+                    let rest = self.buffer[found..].to_vec();
+                    self.push_back(rest);
+                    return Err(LogError::Corrupt(self.valid_len));
+                }
+            }
+            if short {
+                self.torn = true;
+                return Ok(None);
+            }
+            // Only the last record can be torn; anything after it means the
+            // log was damaged some other way.
+            let mut next = [0];
+            if try!(read_full(&mut Read::chain(&mut self.pushback, &mut self.reader), &mut next)) == 0 {
+                self.torn = true;
+                return Ok(None);
+            }
+            self.position += 1;
+            self.push_back(vec![next[0]]);
+            return Err(LogError::Corrupt(self.valid_len));
+        }
+
+        if marked {
+            self.valid_len += MARKER.len() as u64;
+        }
+        self.valid_len += HEADER_LEN as u64 + len;
+        Ok(Some(&self.buffer))
+    }
+
+    /// Skips ahead to the next sync marker, after `next_bytes` or
+    /// `next_record` returned an error, and returns how many bytes were
+    /// skipped.  The next record read is the one behind the marker.
+    ///
+    /// Returns `None` if there is no marker before the end of the log.
+    /// After a record that couldn't be deserialized, the next marker is
+    /// right behind it and nothing is skipped.
+    ///
+    /// A record with a damaged length can take in the records after it.
+    /// The bytes that were read for it are searched for a marker too, so
+    /// reading picks up again at the first marker after the damaged header,
+    /// even when the length ran past the end of the log.  The marker can
+    /// also turn up inside a record, in which case what follows it fails
+    /// its checksum and the reader has to resync again.
+    pub fn resync(&mut self) -> LogResult<Option<u64>> {
+        if self.torn {
+            return Ok(None);
+        }
+        match try!(scan_to_marker(&mut Read::chain(&mut self.pushback, &mut self.reader))) {
+            Some(skipped) => {
+                self.position += skipped;
+                self.valid_len = self.position;
+                self.position += MARKER.len() as u64;
+                self.after_marker = true;
+                Ok(Some(skipped))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the next record and deserializes it.
+    pub fn next_record<T: serde::Deserialize>(&mut self) -> LogResult<Option<T>> {
+        match try!(self.next_bytes()) {
+            Some(mut record) => Ok(Some(try!(deserialize_from(&mut record, Infinite)))),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the number of bytes taken up by the intact records read so
+    /// far.  Once the reader has reached the end, this is where the next
+    /// record should be appended.
+    pub fn valid_len(&self) -> u64 {
+        self.valid_len
+    }
+
+    /// Returns `true` if the reader stopped at a torn record.
+    pub fn is_torn(&self) -> bool {
+        self.torn
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Fills `buf` like `read_full`, starting with the bytes that were
+    /// pushed back.
+    fn read_head(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let filled = try!(read_full(&mut Read::chain(&mut self.pushback, &mut self.reader), buf));
+        self.position += filled as u64;
+        Ok(filled)
+    }
+
+    /// Puts `bytes`, which were just read, back in front of the rest of
+    /// the log.
+    fn push_back(&mut self, mut bytes: Vec<u8>) {
+        self.position -= bytes.len() as u64;
+        let unread = self.pushback.position() as usize;
+        bytes.extend_from_slice(&self.pushback.get_ref()[unread..]);
+        self.pushback = Cursor::new(bytes);
+    }
+}
+
+/// Skips ahead in a log written with `RecordWriter::resync_markers` to just
+/// past the next sync marker, and returns how many bytes were skipped
+/// before it, or `None` if there is no marker before the end.
+///
+/// This is for logs that are read some other way than with a
+/// `RecordReader`, which has `RecordReader::resync`.  It reads a byte at a
+/// time, so `reader` should be buffered.
+pub fn resync<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    scan_to_marker(reader)
+}
+
+fn scan_to_marker<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+    let mut window = [0; 4];
+    let mut scanned = 0u64;
+    loop {
+        let mut byte = [0];
+        if try!(read_full(reader, &mut byte)) == 0 {
+            return Ok(None);
+        }
+        window = [window[1], window[2], window[3], byte[0]];
+        scanned += 1;
+        if scanned >= MARKER.len() as u64 && window == MARKER {
+            return Ok(Some(scanned - MARKER.len() as u64));
+        }
+    }
+}
+
+/// Returns where the first sync marker in `bytes` starts.
+fn find_marker(bytes: &[u8]) -> Option<usize> {
+    bytes.windows(MARKER.len()).position(|window| window == MARKER)
+}
+
+/// The tag in front of a journal entry that holds the whole value.
+const SNAPSHOT: u8 = 0;
+/// The tag in front of a journal entry that holds a change to it.
+const DELTA: u8 = 1;
+
+/// A value that can be kept in a `Journal`.
+pub trait Journaled: serde::Serialize + serde::Deserialize {
+    /// A change to the value.
+    type Delta: serde::Serialize + serde::Deserialize;
+
+    /// Makes a change to the value.
+    fn apply(&mut self, delta: Self::Delta);
+}
+
+/// A value that is persisted as snapshots and the deltas between them.
+///
+/// Each entry in the log is a record holding a tag byte (`0` for a
+/// snapshot, `1` for a delta) followed by the serialized snapshot or delta.
+/// A delta is appended before it is applied, so the log is never behind
+/// the value in memory.  After `snapshot_every` deltas, a snapshot is
+/// appended instead, which bounds how many deltas a replay has to apply.
+///
+/// ```rust,ignore
+/// let mut file = OpenOptions::new().read(true).write(true).create(true).open("state.log")?;
+/// let replay = Journal::<State>::replay(&mut file, Bounded(1 << 20))?;
+/// file.set_len(replay.valid_len)?;
+/// file.seek(SeekFrom::End(0))?;
+///
+/// let state = replay.state.unwrap_or_default();
+/// let mut journal = Journal::new(RecordWriter::new(file), state)?.snapshot_every(1000);
+/// journal.apply(Delta::Insert(key, value))?;
+/// ```
+pub struct Journal<T, W = io::Sink> {
+    log: RecordWriter<W>,
+    state: T,
+    snapshot_every: u64,
+    deltas: u64,
+    buffer: Vec<u8>,
+}
+
+/// What `Journal::replay` found in a log.
+#[derive(Debug)]
+pub struct Replay<T> {
+    /// The value as of the last intact entry, or `None` if the log is
+    /// empty.
+    pub state: Option<T>,
+    /// The number of deltas applied since the last snapshot.
+    pub deltas: u64,
+    /// The number of bytes taken up by intact entries.  Anything after
+    /// this is a torn entry, and should be cut off before appending.
+    pub valid_len: u64,
+}
+
+impl<T: Journaled, W: Write> Journal<T, W> {
+    /// Creates a `Journal` that starts out holding `state`, and appends a
+    /// snapshot of it to `log`.
+    ///
+    /// This is also how to carry on with a log after replaying it; the
+    /// snapshot spares the next replay from going over the old deltas.
+    pub fn new(log: RecordWriter<W>, state: T) -> LogResult<Journal<T, W>> {
+        let mut journal = Journal {
+            log: log,
+            state: state,
+            snapshot_every: 100,
+            deltas: 0,
+            buffer: Vec::new(),
+        };
+        try!(journal.snapshot());
+        Ok(journal)
+    }
+
+    /// Sets how many deltas are appended between snapshots.  With `0`,
+    /// snapshots are only appended by `snapshot`.
+    pub fn snapshot_every(mut self, count: u64) -> Journal<T, W> {
+        self.snapshot_every = count;
+        self
+    }
+
+    /// Appends `delta` to the log and applies it to the value.
+    ///
+    /// If a snapshot is due, it is appended instead of the delta.
+    pub fn apply(&mut self, delta: T::Delta) -> LogResult<()> {
+        if self.snapshot_every != 0 && self.deltas + 1 >= self.snapshot_every {
+            self.state.apply(delta);
+            return self.snapshot();
+        }
+        try!(write_entry(&mut self.buffer, DELTA, &delta));
+        try!(self.log.append_bytes(&self.buffer));
+        self.state.apply(delta);
+        self.deltas += 1;
+        Ok(())
+    }
+
+    /// Appends a snapshot of the value to the log.
+    pub fn snapshot(&mut self) -> LogResult<()> {
+        try!(write_entry(&mut self.buffer, SNAPSHOT, &self.state));
+        try!(self.log.append_bytes(&self.buffer));
+        self.deltas = 0;
+        Ok(())
+    }
+
+    /// Returns the current value.
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    /// Gets a mutable reference to the log.
+    pub fn log_mut(&mut self) -> &mut RecordWriter<W> {
+        &mut self.log
+    }
+
+    /// Unwraps the value and the log.
+    pub fn into_parts(self) -> (T, RecordWriter<W>) {
+        (self.state, self.log)
+    }
+}
+
+impl<T: Journaled> Journal<T> {
+    /// Rebuilds the value from a log written by a `Journal`.
+    ///
+    /// `size_limit` applies to each entry on its own, both to its length in
+    /// the log and to its deserialization.  A torn entry at the end of the
+    /// log is left out, as `RecordReader` does.
+    pub fn replay<R, S>(reader: R, size_limit: S) -> LogResult<Replay<T>>
+        where R: Read, S: SizeLimit + Clone
+    {
+        let mut records = RecordReader::new(reader);
+        if let Some(limit) = size_limit.limit() {
+            // Leave room for the tag byte.
+            records = records.max_record_len(limit.saturating_add(1));
+        }
+
+        let mut state: Option<T> = None;
+        let mut deltas = 0;
+        while let Some(record) = try!(records.next_bytes()) {
+            let (tag, mut rest) = match record.split_first() {
+                Some((&tag, rest)) => (tag, rest),
+                None => return Err(LogError::InvalidJournal("empty journal entry")),
+            };
+            match tag {
+                SNAPSHOT => {
+                    state = Some(try!(deserialize_from(&mut rest, size_limit.clone())));
+                    deltas = 0;
+                }
+                DELTA => {
+                    let delta = try!(deserialize_from(&mut rest, size_limit.clone()));
+                    match state {
+                        Some(ref mut state) => state.apply(delta),
+                        None => return Err(LogError::InvalidJournal("delta before the first snapshot")),
+                    }
+                    deltas += 1;
+                }
+                _ => return Err(LogError::InvalidJournal("unknown journal entry tag")),
+            }
+        }
+
+        Ok(Replay {
+            state: state,
+            deltas: deltas,
+            valid_len: records.valid_len(),
+        })
+    }
+}
+
+/// Serializes a journal entry into `buffer`, replacing what was there.
+fn write_entry<V: serde::Serialize>(buffer: &mut Vec<u8>, tag: u8, value: &V) -> LogResult<()> {
+    buffer.clear();
+    buffer.push(tag);
+    try!(serialize_into(buffer, value, Infinite));
+    Ok(())
+}
+
+/// Reads until `buf` is full or the stream ends, and returns how many bytes
+/// were read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Runs the bytes through a CRC-32 (the IEEE polynomial, as used by zlib
+/// and Ethernet).  Start with `!0` and invert the result.
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
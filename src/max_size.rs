@@ -0,0 +1,135 @@
+use std::marker::PhantomData;
+
+use format::{StaticLayout, StandardLayout};
+
+/// A type whose encoded size has a known upper bound in the plain layout,
+/// `StandardLayout`.
+///
+/// `MAX_SIZE` is the largest number of bytes that encoding any value of the
+/// type can produce.  Types with a variable length on the wire (`String`,
+/// `Vec<T>`, maps) have no such bound and do not implement this trait.
+///
+/// It is implemented for every type that implements `MaxSizeIn` for the
+/// plain layout, which is where the bounds are worked out.
+pub trait MaxSize {
+    /// The maximum number of bytes a value of this type encodes to.
+    const MAX_SIZE: usize;
+}
+
+impl<T: MaxSizeIn<StandardLayout> + ?Sized> MaxSize for T {
+    const MAX_SIZE: usize = <T as MaxSizeIn<StandardLayout>>::MAX_SIZE_IN;
+}
+
+/// A type whose encoded size has a known upper bound in the layout `L`.
+///
+/// The layout matters to some types: a fixed-size array has no length in
+/// front of it in `FixedLayout`, and a `char` has one in `LegacyLayout`.
+/// `#[derive(MaxSize)]`, with the `derive` feature, implements this for
+/// every layout at once.  Implementing it by hand is a matter of summing
+/// up the bounds of the fields:
+///
+/// ```rust
+/// use bincode::{MaxSize, MaxSizeIn};
+/// use bincode::format::{StaticLayout, FixedLayout};
+///
+/// struct Reading {
+///     sensor: u16,
+///     samples: [f32; 2],
+///     flag: Option<bool>,
+/// }
+///
+/// impl<L: StaticLayout> MaxSizeIn<L> for Reading {
+///     const MAX_SIZE_IN: usize = <u16 as MaxSizeIn<L>>::MAX_SIZE_IN
+///         + <[f32; 2] as MaxSizeIn<L>>::MAX_SIZE_IN
+///         + <Option<bool> as MaxSizeIn<L>>::MAX_SIZE_IN;
+/// }
+///
+/// assert_eq!(Reading::MAX_SIZE, 2 + 8 + 8 + 2);
+/// assert_eq!(<Reading as MaxSizeIn<FixedLayout>>::MAX_SIZE_IN, 2 + 8 + 2);
+/// ```
+///
+/// For enums, add the variant tag, `L::VARIANT_TAG_BYTES`, to the largest
+/// variant.
+pub trait MaxSizeIn<L: StaticLayout> {
+    /// The maximum number of bytes a value of this type encodes to in the
+    /// layout `L`.
+    const MAX_SIZE_IN: usize;
+}
+
+macro_rules! impl_max_size {
+    ($($ty:ty => $size:expr),*) => {
+        $(
+            impl<L: StaticLayout> MaxSizeIn<L> for $ty {
+                const MAX_SIZE_IN: usize = $size;
+            }
+        )*
+    }
+}
+
+// A packed bool takes up less than a byte, but never more.
+impl_max_size! {
+    () => 0,
+    bool => 1,
+    u8 => 1,
+    u16 => 2,
+    u32 => 4,
+    u64 => 8,
+    usize => L::USIZE_BYTES,
+    i8 => 1,
+    i16 => 2,
+    i32 => 4,
+    i64 => 8,
+    isize => L::USIZE_BYTES,
+    f32 => 4,
+    f64 => 8,
+    char => L::MAX_CHAR_BYTES
+}
+
+impl<L: StaticLayout, T: MaxSizeIn<L>> MaxSizeIn<L> for Option<T> {
+    const MAX_SIZE_IN: usize = L::OPTION_TAG_BYTES + T::MAX_SIZE_IN;
+}
+
+impl<L: StaticLayout, T: MaxSizeIn<L>> MaxSizeIn<L> for Box<T> {
+    const MAX_SIZE_IN: usize = T::MAX_SIZE_IN;
+}
+
+impl<'a, L: StaticLayout, T: MaxSizeIn<L>> MaxSizeIn<L> for &'a T {
+    const MAX_SIZE_IN: usize = T::MAX_SIZE_IN;
+}
+
+// Fixed size arrays are written like any other sequence, length prefix
+// included, unless the layout leaves it out.
+impl<L: StaticLayout, T: MaxSizeIn<L>, const N: usize> MaxSizeIn<L> for [T; N] {
+    const MAX_SIZE_IN: usize = L::ARRAY_LEN_BYTES + N * T::MAX_SIZE_IN;
+}
+
+macro_rules! impl_max_size_tuple {
+    ($($name:ident)+) => {
+        impl<Lay: StaticLayout, $($name: MaxSizeIn<Lay>),+> MaxSizeIn<Lay> for ($($name,)+) {
+            const MAX_SIZE_IN: usize = 0 $(+ $name::MAX_SIZE_IN)+;
+        }
+    }
+}
+
+impl_max_size_tuple! { A }
+impl_max_size_tuple! { A B }
+impl_max_size_tuple! { A B C }
+impl_max_size_tuple! { A B C D }
+impl_max_size_tuple! { A B C D E }
+impl_max_size_tuple! { A B C D E F }
+impl_max_size_tuple! { A B C D E F G }
+impl_max_size_tuple! { A B C D E F G H }
+impl_max_size_tuple! { A B C D E F G H I }
+impl_max_size_tuple! { A B C D E F G H I J }
+impl_max_size_tuple! { A B C D E F G H I J K }
+impl_max_size_tuple! { A B C D E F G H I J K L }
+
+/// Fails to compile when a `T` might not fit into `N` bytes.
+///
+/// The check lives in an associated constant so that it is evaluated once
+/// per `(T, N)` pair at monomorphization time.
+pub struct AssertFits<T, const N: usize>(PhantomData<T>);
+
+impl<T: MaxSize, const N: usize> AssertFits<T, N> {
+    pub const OK: () = assert!(N >= T::MAX_SIZE, "buffer is smaller than the MaxSize of the encoded type");
+}
@@ -0,0 +1,232 @@
+//! Serializing framed messages into reusable buffers.
+//!
+//! A server that sends many small messages spends a good part of its time
+//! allocating and freeing the `Vec` each one is serialized into.  A
+//! `BufferPool` keeps a fixed number of spare buffers that threads check out
+//! and give back without taking a lock, and a `PooledSerializer` serializes
+//! messages into them as frames, with their length in front like `channel`
+//! sends them and `FrameReader` reads them.  With the `bytes` feature a
+//! frame can also be handed on as `Bytes`, which still gives its buffer back
+//! when dropped.
+//!
+//! ```rust,ignore
+//! let serializer = PooledSerializer::new(BufferPool::new(64)).max_message_len(1 << 20);
+//! // In each connection's thread, with its own clone of `serializer`:
+//! let frame = serializer.serialize(&response).unwrap();
+//! stream.write_all(&frame).unwrap();
+//! // Dropping `frame` puts its buffer back into the pool.
+//! ```
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::u32;
+
+use byteorder::{BigEndian, ByteOrder};
+#[cfg(feature = "bytes")]
+use bytes_crate::Bytes;
+use serde_crate as serde;
+
+use ::{Bounded, Infinite};
+use ::frame::LengthPrefix;
+use ::serde::{serialize_into, SerializeResult, SerializeError};
+
+const EMPTY: usize = 0;
+const BUSY: usize = 1;
+const FULL: usize = 2;
+
+/// One spare buffer.  The `Vec` is moved in and out of the slot in place,
+/// so giving a buffer back or taking it out never allocates; `state` says
+/// whether the slot holds a buffer and lets one thread at a time touch it.
+struct Slot {
+    state: AtomicUsize,
+    buffer: UnsafeCell<Vec<u8>>,
+}
+
+/// The spare buffers, each slot holding one or nothing.
+struct Slots {
+    slots: Vec<Slot>,
+    max_buffer_len: usize,
+}
+
+// A slot's buffer is only touched by the thread that moved its state to
+// `BUSY`.
+unsafe impl Sync for Slots {}
+
+/// A fixed number of spare byte buffers shared between threads.
+///
+/// Cloning a `BufferPool` gives another handle to the same buffers.  When
+/// the pool is empty `checkout` allocates a new buffer, and when it is full
+/// `recycle` frees the buffer it is given, so the pool never holds more than
+/// the number of buffers it was created with.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<Slots>,
+}
+
+impl BufferPool {
+    /// Creates a pool that keeps up to `buffers` spare buffers.
+    pub fn new(buffers: usize) -> BufferPool {
+        BufferPool::with_max_buffer_len(buffers, 64 * 1024)
+    }
+
+    /// Creates a pool that keeps up to `buffers` spare buffers, and frees
+    /// instead of keeping those that have grown larger than `max_len`
+    /// bytes, so that one huge message doesn't pin its memory forever.
+    pub fn with_max_buffer_len(buffers: usize, max_len: usize) -> BufferPool {
+        BufferPool {
+            inner: Arc::new(Slots {
+                slots: (0..buffers).map(|_| Slot {
+                    state: AtomicUsize::new(EMPTY),
+                    buffer: UnsafeCell::new(Vec::new()),
+                }).collect(),
+                max_buffer_len: max_len,
+            }),
+        }
+    }
+
+    /// Takes an empty buffer out of the pool, or allocates one if there are
+    /// none left.
+    pub fn checkout(&self) -> Vec<u8> {
+        for slot in &self.inner.slots {
+            if slot.state.compare_exchange(FULL, BUSY, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+                let buffer = mem::replace(unsafe { &mut *slot.buffer.get() }, Vec::new());
+                slot.state.store(EMPTY, Ordering::Release);
+                return buffer;
+            }
+        }
+        Vec::new()
+    }
+
+    /// Gives a buffer back to the pool.
+    pub fn recycle(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 || buffer.capacity() > self.inner.max_buffer_len {
+            return;
+        }
+        buffer.clear();
+        for slot in &self.inner.slots {
+            if slot.state.compare_exchange(EMPTY, BUSY, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+                unsafe { *slot.buffer.get() = buffer };
+                slot.state.store(FULL, Ordering::Release);
+                return;
+            }
+        }
+    }
+
+    /// Returns the number of spare buffers in the pool right now.
+    pub fn available(&self) -> usize {
+        self.inner.slots.iter().filter(|slot| slot.state.load(Ordering::Relaxed) == FULL).count()
+    }
+}
+
+/// A buffer checked out of a `BufferPool`, which goes back to the pool when
+/// it is dropped.
+pub struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: BufferPool,
+}
+
+impl PooledBuffer {
+    /// Takes the bytes out of the pool for good, to keep them or hand them
+    /// to something that wants a `Vec<u8>`.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        mem::replace(&mut self.buffer, Vec::new())
+    }
+
+    /// Turns the frame into `Bytes`.  The buffer goes back to the pool once
+    /// the last clone of the `Bytes` is dropped.
+    #[cfg(feature = "bytes")]
+    pub fn into_bytes(self) -> Bytes {
+        Bytes::from_owner(self)
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        self.pool.recycle(mem::replace(&mut self.buffer, Vec::new()));
+    }
+}
+
+/// Serializes messages as frames into buffers from a `BufferPool`.
+///
+/// A `PooledSerializer` is cheap to clone and can be shared between
+/// threads, so each connection of a server can have its own.
+#[derive(Clone)]
+pub struct PooledSerializer {
+    pool: BufferPool,
+    prefix: LengthPrefix,
+    max_len: Option<u64>,
+}
+
+impl PooledSerializer {
+    /// Creates a serializer that takes its buffers from `pool` and puts a
+    /// big-endian `u64` length in front of each message.
+    pub fn new(pool: BufferPool) -> PooledSerializer {
+        PooledSerializer {
+            pool: pool,
+            prefix: LengthPrefix::U64,
+            max_len: None,
+        }
+    }
+
+    /// Sets the width of the length in front of each message.
+    pub fn length_prefix(mut self, prefix: LengthPrefix) -> PooledSerializer {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Sets the maximum length of a message, not counting its length
+    /// prefix.  Longer messages are refused with a `SizeLimit` error.
+    pub fn max_message_len(mut self, max_len: u64) -> PooledSerializer {
+        self.max_len = Some(max_len);
+        self
+    }
+
+    /// Returns the pool the buffers come from.
+    pub fn pool(&self) -> &BufferPool {
+        &self.pool
+    }
+
+    /// Serializes `value` as a frame: its length, then its bytes.
+    pub fn serialize<T: serde::Serialize>(&self, value: &T) -> SerializeResult<PooledBuffer> {
+        let mut frame = PooledBuffer {
+            buffer: self.pool.checkout(),
+            pool: self.pool.clone(),
+        };
+        let width = self.prefix.width();
+        frame.buffer.extend_from_slice(&[0; 8][..width]);
+
+        let max_len = match self.prefix {
+            LengthPrefix::U32 => Some(self.max_len.unwrap_or(u32::MAX as u64)),
+            LengthPrefix::U64 => self.max_len,
+        };
+        try!(match max_len {
+            Some(max_len) => serialize_into(&mut frame.buffer, value, Bounded(max_len)),
+            None => serialize_into(&mut frame.buffer, value, Infinite),
+        });
+
+        let len = (frame.buffer.len() - width) as u64;
+        match self.prefix {
+            LengthPrefix::U32 if len > u32::MAX as u64 => return Err(SerializeError::SizeLimit),
+            LengthPrefix::U32 => BigEndian::write_u32(&mut frame.buffer[..width], len as u32),
+            LengthPrefix::U64 => BigEndian::write_u64(&mut frame.buffer[..width], len),
+        }
+        Ok(frame)
+    }
+}
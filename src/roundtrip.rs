@@ -0,0 +1,450 @@
+//! Property-based round trip tests.
+//!
+//! `assert_roundtrips!` generates values of a type, and checks that each of
+//! them decodes to itself after being encoded, and that `serialized_size`
+//! agrees with the length of its encoding:
+//!
+//! ```rust,ignore
+//! #[macro_use]
+//! extern crate bincode;
+//!
+//! #[test]
+//! fn message_roundtrips() {
+//!     assert_roundtrips!(Message, bincode::Bounded(1024));
+//! }
+//! ```
+//!
+//! The values come from a `Generator`, which builds any `serde::Deserialize`
+//! type out of random numbers, strings, lengths, and enum variants.  It is
+//! seeded, so a failing value can be reproduced, and it fits into other
+//! property testing crates by seeding it from their random source.  With
+//! quickcheck, for example:
+//!
+//! ```rust,ignore
+//! impl Arbitrary for Message {
+//!     fn arbitrary<G: Gen>(g: &mut G) -> Message {
+//!         Generator::new(g.next_u64()).generate().unwrap()
+//!     }
+//! }
+//! ```
+//!
+//! Types whose `PartialEq` isn't reflexive can't be checked; floats are only
+//! ever generated as finite numbers for that reason.
+
+use std::fmt::Debug;
+
+use serde_crate as serde;
+use serde_crate::de::Error;
+use serde_crate::de::value::ValueDeserializer;
+
+use ::SizeLimit;
+use ::serde::{serialize, serialized_size, deserialize, DeserializeError, DeserializeResult, SerializeError};
+
+/// The number of values `assert_roundtrips` checks.
+const CASES: u64 = 256;
+
+/// Checks that values of `T` round trip, and that those that don't fit in
+/// `size_limit` are rejected when encoding.
+#[macro_export]
+macro_rules! assert_roundtrips {
+    ($t:ty) => {
+        $crate::roundtrip::assert_roundtrips::<$t, _>($crate::Infinite)
+    };
+    ($t:ty, $size_limit:expr) => {
+        $crate::roundtrip::assert_roundtrips::<$t, _>($size_limit)
+    };
+}
+
+/// Generates values of `T` and checks each of them with `assert_roundtrip`.
+///
+/// The values are generated from a fixed sequence of seeds, so a failure
+/// will happen again on the next run.
+///
+/// # Panics
+///
+/// Panics if a value fails to round trip, or if `T` can't be generated.
+pub fn assert_roundtrips<T, S>(size_limit: S)
+    where T: serde::Serialize + serde::Deserialize + PartialEq + Debug,
+          S: SizeLimit + Copy,
+{
+    for seed in 0..CASES {
+        let value: T = match Generator::new(seed).generate() {
+            Ok(value) => value,
+            Err(err) => panic!("could not generate a value with seed {}: {:?}", seed, err),
+        };
+        assert_roundtrip(&value, size_limit);
+    }
+}
+
+/// Checks that `value` decodes to itself after being encoded, and that
+/// `serialized_size` agrees with the length of the encoding.
+///
+/// If `value` doesn't fit in `size_limit`, this checks that encoding it
+/// fails with `SerializeError::SizeLimit` instead.
+pub fn assert_roundtrip<T, S>(value: &T, size_limit: S)
+    where T: serde::Serialize + serde::Deserialize + PartialEq + Debug,
+          S: SizeLimit,
+{
+    let fits = size_limit.limit().map_or(true, |limit| serialized_size(value) <= limit);
+    let encoded = match serialize(value, size_limit) {
+        Ok(encoded) => encoded,
+        Err(SerializeError::SizeLimit) if !fits => return,
+        Err(err) => panic!("could not encode {:?}: {:?}", value, err),
+    };
+    assert!(fits, "{:?} was encoded even though it doesn't fit in the size limit", value);
+    assert_eq!(serialized_size(value), encoded.len() as u64);
+
+    let decoded: T = match deserialize(&encoded) {
+        Ok(decoded) => decoded,
+        Err(err) => panic!("could not decode {:?}: {:?}", value, err),
+    };
+    assert_eq!(&decoded, value);
+}
+
+/// Builds values of any `serde::Deserialize` type out of random data.
+///
+/// A `Generator` is a `serde::Deserializer` that makes up the value of each
+/// number, the length of each string and collection, and the variant of each
+/// enum as it goes along.  Collections get shorter the deeper they are
+/// nested, and past `max_depth` options are always `None` and enums always
+/// take their first variant, so recursive types stay finite as long as their
+/// first variant doesn't recurse.
+pub struct Generator {
+    state: u64,
+    max_len: usize,
+    max_depth: usize,
+    depth: usize,
+}
+
+impl Generator {
+    /// Creates a generator with the given seed.  Generators with the same
+    /// seed generate the same values.
+    pub fn new(seed: u64) -> Generator {
+        Generator {
+            // xorshift gets stuck on zero, so the lowest bit is always set.
+            state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1,
+            max_len: 8,
+            max_depth: 8,
+            depth: 0,
+        }
+    }
+
+    /// Sets the longest string, byte buffer, or collection to generate.
+    /// The default is 8.
+    pub fn max_len(mut self, max_len: usize) -> Generator {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Sets how deeply options, enums, and collections may be nested.
+    /// The default is 8.
+    pub fn max_depth(mut self, max_depth: usize) -> Generator {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Generates a value.
+    ///
+    /// This fails if `T` expects something the generator can't come up with,
+    /// such as a type that deserializes differently depending on the input.
+    pub fn generate<T: serde::Deserialize>(&mut self) -> DeserializeResult<T> {
+        serde::Deserialize::deserialize(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        if n == 0 { 0 } else { self.next_u64() % n }
+    }
+
+    fn next_len(&mut self) -> usize {
+        if self.depth >= self.max_depth {
+            return 0;
+        }
+        let max_len = self.max_len / (self.depth + 1);
+        self.below(max_len as u64 + 1) as usize
+    }
+
+    fn next_char(&mut self) -> char {
+        const CHARS: &'static [char] = &['a', 'z', 'A', '0', ' ', '\n', '\u{0}', '\u{7f}',
+                                         'é', 'ß', '€', '中', '\u{10ffff}', '😀'];
+        CHARS[self.below(CHARS.len() as u64) as usize]
+    }
+
+    fn next_string(&mut self) -> String {
+        let len = self.next_len();
+        (0..len).map(|_| self.next_char()).collect()
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        // Finite, and with a fractional part now and then.
+        (self.next_u64() as i32) as f64 / 4.0
+    }
+}
+
+macro_rules! generate_primitive {
+    ($dser_method:ident, $visitor_method:ident, $ty:ty) => {
+        fn $dser_method<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+            where V: serde::de::Visitor,
+        {
+            let value = self.next_u64() as $ty;
+            visitor.$visitor_method(value)
+        }
+    }
+}
+
+impl serde::Deserializer for Generator {
+    type Error = DeserializeError;
+
+    fn deserialize<V>(&mut self, _visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        Err(DeserializeError::custom("bincode can not generate a type that doesn't say what it expects"))
+    }
+
+    generate_primitive!(deserialize_u8, visit_u8, u8);
+    generate_primitive!(deserialize_u16, visit_u16, u16);
+    generate_primitive!(deserialize_u32, visit_u32, u32);
+    generate_primitive!(deserialize_u64, visit_u64, u64);
+    generate_primitive!(deserialize_usize, visit_usize, usize);
+    generate_primitive!(deserialize_i8, visit_i8, i8);
+    generate_primitive!(deserialize_i16, visit_i16, i16);
+    generate_primitive!(deserialize_i32, visit_i32, i32);
+    generate_primitive!(deserialize_i64, visit_i64, i64);
+    generate_primitive!(deserialize_isize, visit_isize, isize);
+
+    fn deserialize_bool<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = self.below(2) == 1;
+        visitor.visit_bool(value)
+    }
+
+    fn deserialize_f32<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = self.next_f64() as f32;
+        visitor.visit_f32(value)
+    }
+
+    fn deserialize_f64<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = self.next_f64();
+        visitor.visit_f64(value)
+    }
+
+    fn deserialize_char<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = self.next_char();
+        visitor.visit_char(value)
+    }
+
+    fn deserialize_str<V>(&mut self, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = self.next_string();
+        visitor.visit_string(value)
+    }
+
+    fn deserialize_bytes<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let len = self.next_len();
+        let value = (0..len).map(|_| self.next_u64() as u8).collect();
+        visitor.visit_byte_buf(value)
+    }
+
+    fn deserialize_unit<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        if self.depth >= self.max_depth || self.below(2) == 0 {
+            return visitor.visit_none();
+        }
+        self.depth += 1;
+        let result = visitor.visit_some(&mut *self);
+        self.depth -= 1;
+        result
+    }
+
+    fn deserialize_seq<V>(&mut self, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let len = self.next_len();
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_fixed_size_array<V>(&mut self, len: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_tuple<V>(&mut self, len: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.depth += 1;
+        let result = visitor.visit_seq(Elements { generator: &mut *self, remaining: len });
+        self.depth -= 1;
+        result
+    }
+
+    fn deserialize_map<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let len = self.next_len();
+        self.depth += 1;
+        let result = visitor.visit_map(Elements { generator: &mut *self, remaining: len });
+        self.depth -= 1;
+        result
+    }
+
+    fn deserialize_struct<V>(&mut self,
+                             _name: &'static str,
+                             fields: &'static [&'static str],
+                             visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(&mut self, _name: &'static str, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(&mut self,
+                           _name: &'static str,
+                           variants: &'static [&'static str],
+                           mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::EnumVisitor,
+    {
+        let index = if self.depth >= self.max_depth {
+            0
+        } else {
+            self.below(variants.len() as u64) as usize
+        };
+        self.depth += 1;
+        let result = visitor.visit(Variant { generator: &mut *self, index: index });
+        self.depth -= 1;
+        result
+    }
+}
+
+/// Hands out `remaining` generated elements or entries.
+struct Elements<'a> {
+    generator: &'a mut Generator,
+    remaining: usize,
+}
+
+impl<'a> serde::de::SeqVisitor for Elements<'a> {
+    type Error = DeserializeError;
+
+    fn visit<T>(&mut self) -> DeserializeResult<Option<T>>
+        where T: serde::de::Deserialize,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let value = try!(serde::Deserialize::deserialize(&mut *self.generator));
+        Ok(Some(value))
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> serde::de::MapVisitor for Elements<'a> {
+    type Error = DeserializeError;
+
+    fn visit_key<K>(&mut self) -> DeserializeResult<Option<K>>
+        where K: serde::de::Deserialize,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let key = try!(serde::Deserialize::deserialize(&mut *self.generator));
+        Ok(Some(key))
+    }
+
+    fn visit_value<V>(&mut self) -> DeserializeResult<V>
+        where V: serde::de::Deserialize,
+    {
+        serde::Deserialize::deserialize(&mut *self.generator)
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// Generates the variant at `index`.
+struct Variant<'a> {
+    generator: &'a mut Generator,
+    index: usize,
+}
+
+impl<'a> serde::de::VariantVisitor for Variant<'a> {
+    type Error = DeserializeError;
+
+    fn visit_variant<V>(&mut self) -> DeserializeResult<V>
+        where V: serde::Deserialize,
+    {
+        let mut deserializer = self.index.into_deserializer();
+        let attempt: Result<V, serde::de::value::Error> = serde::Deserialize::deserialize(&mut deserializer);
+        Ok(try!(attempt))
+    }
+
+    fn visit_unit(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+
+    fn visit_newtype<T>(&mut self) -> DeserializeResult<T>
+        where T: serde::de::Deserialize,
+    {
+        serde::Deserialize::deserialize(&mut *self.generator)
+    }
+
+    fn visit_tuple<V>(&mut self, len: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        serde::Deserializer::deserialize_tuple(self.generator, len, visitor)
+    }
+
+    fn visit_struct<V>(&mut self, fields: &'static [&'static str], visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        serde::Deserializer::deserialize_tuple(self.generator, fields.len(), visitor)
+    }
+}
@@ -4,7 +4,10 @@
 
 use rustc_serialize_crate::{Encodable, Decodable};
 use std::io::{Write, Read};
-use ::SizeLimit;
+use ::{SizeLimit, Infinite};
+use ::MaxSize;
+use ::max_size::AssertFits;
+use ::tee::TeeWriteAll;
 
 pub use self::writer::{SizeChecker, EncoderWriter, EncodingResult, EncodingError};
 pub use self::reader::{DecoderReader, DecodingResult, DecodingError};
@@ -16,11 +19,11 @@ mod writer;
 ///
 /// If the encoding would take more bytes than allowed by `size_limit`,
 /// an error is returned.
-pub fn encode<T: Encodable>(t: &T, size_limit: SizeLimit) -> EncodingResult<Vec<u8>> {
+pub fn encode<T: Encodable, S: SizeLimit>(t: &T, size_limit: S) -> EncodingResult<Vec<u8>> {
     // Since we are putting values directly into a vector, we can do size
     // computation out here and pre-allocate a buffer of *exactly*
     // the right size.
-    let mut w = if let SizeLimit::Bounded(l) = size_limit {
+    let mut w = if let Some(l) = size_limit.limit() {
         let actual_size = encoded_size_bounded(t, l);
         let actual_size = try!(actual_size.ok_or(EncodingError::SizeLimit));
         Vec::with_capacity(actual_size as usize)
@@ -28,19 +31,32 @@ pub fn encode<T: Encodable>(t: &T, size_limit: SizeLimit) -> EncodingResult<Vec<
         vec![]
     };
 
-    match encode_into(t, &mut w, SizeLimit::Infinite) {
+    match encode_into(t, &mut w, Infinite) {
         Ok(()) => Ok(w),
         Err(e) => Err(e)
     }
 }
 
+/// Encodes an encodable object into a `Vec` of bytes that is allocated
+/// exactly once.
+///
+/// The size of the value is worked out first, so that the `Vec` starts out
+/// with exactly the capacity it needs instead of growing as it is written
+/// to.  That means walking the value twice, which is still cheaper than
+/// reallocating and copying the output for all but the smallest messages.
+pub fn encode_sized<T: Encodable>(t: &T) -> EncodingResult<Vec<u8>> {
+    let mut w = Vec::with_capacity(encoded_size(t) as usize);
+    try!(encode_into(t, &mut w, Infinite));
+    Ok(w)
+}
+
 /// Decodes a slice of bytes into an object.
 ///
 /// This method does not have a size-limit because if you already have the bytes
 /// in memory, then you don't gain anything by having a limiter.
 pub fn decode<T: Decodable>(b: &[u8]) -> DecodingResult<T> {
     let mut b = b;
-    decode_from(&mut b, SizeLimit::Infinite)
+    decode_from(&mut b, Infinite)
 }
 
 /// Encodes an object directly into a `Writer`.
@@ -51,21 +67,49 @@ pub fn decode<T: Decodable>(b: &[u8]) -> DecodingResult<T> {
 /// If this returns an `EncodingError` (other than SizeLimit), assume that the
 /// writer is in an invalid state, as writing could bail out in the middle of
 /// encoding.
-pub fn encode_into<T: Encodable, W: Write>(t: &T,
-                                           w: &mut W,
-                                           size_limit: SizeLimit)
-                                           -> EncodingResult<()> {
-    try!(match size_limit {
-        SizeLimit::Infinite => Ok(()),
-        SizeLimit::Bounded(x) => {
-            let mut size_checker = SizeChecker::new(x);
-            t.encode(&mut size_checker)
-        }
-    });
+pub fn encode_into<T: Encodable, W: Write, S: SizeLimit>(t: &T,
+                                                         w: &mut W,
+                                                         size_limit: S)
+                                                         -> EncodingResult<()> {
+    if let Some(x) = size_limit.limit() {
+        let mut size_checker = SizeChecker::new(x);
+        try!(t.encode(&mut size_checker));
+    }
 
     t.encode(&mut writer::EncoderWriter::new(w))
 }
 
+/// Encodes an object directly into several `Writer`s at once.
+///
+/// The value is only encoded once, and every byte of it is written to each of
+/// the writers in turn, so a message can go to a file and a socket without
+/// being encoded twice or buffered.  Use a `TeeWrite` instead when there is a
+/// fixed number of writers.
+///
+/// If the encoding would take more bytes than allowed by `size_limit`, an error
+/// is returned and *no bytes* will be written into any of the writers.
+pub fn encode_into_many<T: Encodable, S: SizeLimit>(t: &T,
+                                                    writers: &mut [&mut dyn Write],
+                                                    size_limit: S)
+                                                    -> EncodingResult<()> {
+    encode_into(t, &mut TeeWriteAll::new(writers), size_limit)
+}
+
+/// Encodes an object into a fixed-size buffer, returning the number of
+/// bytes written.
+///
+/// The buffer must be at least `T::MAX_SIZE` bytes long; if it isn't, the
+/// call fails to compile instead of failing at runtime.
+pub fn encode_into_array<T, const N: usize>(t: &T, buffer: &mut [u8; N]) -> EncodingResult<usize>
+    where T: Encodable + MaxSize
+{
+    let () = AssertFits::<T, N>::OK;
+
+    let mut w = &mut buffer[..];
+    try!(encode_into(t, &mut w, Infinite));
+    Ok(N - w.len())
+}
+
 /// Decoes an object directly from a `Buffer`ed Reader.
 ///
 /// If the provided `SizeLimit` is reached, the decode will bail immediately.
@@ -75,7 +119,7 @@ pub fn encode_into<T: Encodable, W: Write>(t: &T,
 /// If this returns an `DecodingError`, assume that the buffer that you passed
 /// in is in an invalid state, as the error could be returned during any point
 /// in the reading.
-pub fn decode_from<R: Read, T: Decodable>(r: &mut R, size_limit: SizeLimit) -> DecodingResult<T> {
+pub fn decode_from<R: Read, T: Decodable, S: SizeLimit>(r: &mut R, size_limit: S) -> DecodingResult<T> {
     Decodable::decode(&mut reader::DecoderReader::new(r, size_limit))
 }
 
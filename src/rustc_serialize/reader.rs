@@ -7,7 +7,9 @@ use std::convert::From;
 use rustc_serialize_crate::Decoder;
 
 use byteorder::{BigEndian, ReadBytesExt};
+use num_traits;
 use ::SizeLimit;
+use ::format;
 
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct InvalidEncoding {
@@ -42,7 +44,11 @@ pub enum DecodingError {
     InvalidEncoding(InvalidEncoding),
     /// If decoding a message takes more than the provided size limit, this
     /// error is returned.
-    SizeLimit
+    SizeLimit,
+    /// If a length in the input is too large to be added up or to fit in a
+    /// `usize`, which on a 32-bit target could otherwise wrap around to a
+    /// small number, this error is returned.
+    LengthOverflow,
 }
 
 impl fmt::Display for DecodingError {
@@ -53,7 +59,9 @@ impl fmt::Display for DecodingError {
             DecodingError::InvalidEncoding(ref ib) =>
                 write!(fmt, "InvalidEncoding: {}", ib),
             DecodingError::SizeLimit =>
-                write!(fmt, "SizeLimit")
+                write!(fmt, "SizeLimit"),
+            DecodingError::LengthOverflow =>
+                write!(fmt, "LengthOverflow"),
         }
     }
 }
@@ -69,7 +77,8 @@ impl Error for DecodingError {
         match *self {
             DecodingError::IoError(ref err) => Error::description(err),
             DecodingError::InvalidEncoding(ref ib) => ib.desc,
-            DecodingError::SizeLimit => "the size limit for decoding has been reached"
+            DecodingError::SizeLimit => "the size limit for decoding has been reached",
+            DecodingError::LengthOverflow => "a length in the input is too large",
         }
     }
 
@@ -77,7 +86,8 @@ impl Error for DecodingError {
         match *self {
             DecodingError::IoError(ref err)     => err.cause(),
             DecodingError::InvalidEncoding(_) => None,
-            DecodingError::SizeLimit => None
+            DecodingError::SizeLimit => None,
+            DecodingError::LengthOverflow => None,
         }
     }
 }
@@ -94,18 +104,18 @@ impl From<IoError> for DecodingError {
 /// In most cases, prefer the `decode_from` function.
 ///
 /// ```rust,ignore
-/// let dr = bincode::rustc_serialize::DecoderReader::new(&mut some_reader, SizeLimit::Infinite);
+/// let dr = bincode::rustc_serialize::DecoderReader::new(&mut some_reader, bincode::Infinite);
 /// let result: T = Decodable::decode(&mut dr);
 /// let bytes_read = dr.bytes_read();
 /// ```
-pub struct DecoderReader<'a, R: 'a> {
+pub struct DecoderReader<'a, R: 'a, S: SizeLimit> {
     reader: &'a mut R,
-    size_limit: SizeLimit,
+    size_limit: S,
     read: u64
 }
 
-impl<'a, R: Read> DecoderReader<'a, R> {
-    pub fn new(r: &'a mut R, size_limit: SizeLimit) -> DecoderReader<'a, R> {
+impl<'a, R: Read, S: SizeLimit> DecoderReader<'a, R, S> {
+    pub fn new(r: &'a mut R, size_limit: S) -> DecoderReader<'a, R, S> {
         DecoderReader {
             reader: r,
             size_limit: size_limit,
@@ -119,17 +129,16 @@ impl<'a, R: Read> DecoderReader<'a, R> {
     }
 }
 
-impl <'a, A> DecoderReader<'a, A> {
+impl <'a, A, S: SizeLimit> DecoderReader<'a, A, S> {
     fn read_bytes(&mut self, count: u64) -> Result<(), DecodingError> {
+        if !self.size_limit.add(count) {
+            return Err(DecodingError::SizeLimit);
+        }
         self.read = match self.read.checked_add(count) {
             Some(read) => read,
-            None => return Err(DecodingError::SizeLimit),
+            None => return Err(DecodingError::LengthOverflow),
         };
-        match self.size_limit {
-            SizeLimit::Infinite => Ok(()),
-            SizeLimit::Bounded(x) if self.read <= x => Ok(()),
-            SizeLimit::Bounded(_) => Err(DecodingError::SizeLimit)
-        }
+        Ok(())
     }
 
     fn read_type<T>(&mut self) -> Result<(), DecodingError> {
@@ -138,14 +147,18 @@ impl <'a, A> DecoderReader<'a, A> {
     }
 }
 
-impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
+impl<'a, R: Read, S: SizeLimit> Decoder for DecoderReader<'a, R, S> {
     type Error = DecodingError;
 
     fn read_nil(&mut self) -> DecodingResult<()> {
         Ok(())
     }
     fn read_usize(&mut self) -> DecodingResult<usize> {
-        Ok(try!(self.read_u64().map(|x| x as usize)))
+        let value = try!(self.read_u64());
+        match num_traits::cast(value) {
+            Some(value) => Ok(value),
+            None => Err(DecodingError::LengthOverflow),
+        }
     }
     fn read_u64(&mut self) -> DecodingResult<u64> {
         try!(self.read_type::<u64>());
@@ -183,11 +196,10 @@ impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
         self.reader.read_i8().map_err(wrap_io)
     }
     fn read_bool(&mut self) -> DecodingResult<bool> {
-        let x = try!(self.read_i8());
-        match x {
-            1 => Ok(true),
-            0 => Ok(false),
-            _ => Err(DecodingError::InvalidEncoding(InvalidEncoding{
+        let x = try!(self.read_u8());
+        match format::bool_from_byte(x) {
+            Some(x) => Ok(x),
+            None => Err(DecodingError::InvalidEncoding(InvalidEncoding{
                 desc: "invalid u8 when decoding bool",
                 detail: Some(format!("Expected 0 or 1, got {}", x))
             })),
@@ -202,40 +214,22 @@ impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
         self.reader.read_f32::<BigEndian>().map_err(wrap_io)
     }
     fn read_char(&mut self) -> DecodingResult<char> {
-        use std::str;
-
         let error = DecodingError::InvalidEncoding(InvalidEncoding {
             desc: "Invalid char encoding",
             detail: None
         });
 
-        let mut buf = [0];
-
-        let _ = try!(self.reader.read(&mut buf[..]));
-        let first_byte = buf[0];
-        let width = utf8_char_width(first_byte);
-        if width == 1 { return Ok(first_byte as char) }
+        // Charge every byte against the size limit before it is read.
+        try!(self.read_type::<u8>());
+        let first_byte = try!(self.reader.read_u8());
+        let width = format::char_width(first_byte);
         if width == 0 { return Err(error)}
 
+        try!(self.read_bytes(width as u64 - 1));
         let mut buf = [first_byte, 0, 0, 0];
-        {
-            let mut start = 1;
-            while start < width {
-                match try!(self.reader.read(&mut buf[start .. width])) {
-                    n if n == width - start => break,
-                    n if n < width - start => { start += n; }
-                    _ => return Err(error)
-                }
-            }
-        }
-
-        let res = try!(match str::from_utf8(&buf[..width]).ok() {
-            Some(s) => Ok(s.chars().next().unwrap()),
-            None => Err(error)
-        });
+        try!(self.reader.read_exact(&mut buf[1..width]));
 
-        try!(self.read_bytes(res.len_utf8() as u64));
-        Ok(res)
+        format::char_from_bytes(&buf[..width]).ok_or(error)
     }
 
     fn read_str(&mut self) -> DecodingResult<String> {
@@ -244,6 +238,10 @@ impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
 
         let mut buff = Vec::new();
         try!(self.reader.by_ref().take(len as u64).read_to_end(&mut buff));
+        if buff.len() != len {
+            return Err(DecodingError::IoError(IoError::new(::std::io::ErrorKind::UnexpectedEof,
+                                                           "unexpected end of input")));
+        }
         match String::from_utf8(buff) {
             Ok(s) => Ok(s),
             Err(err) => Err(DecodingError::InvalidEncoding(InvalidEncoding {
@@ -253,12 +251,12 @@ impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
         }
     }
     fn read_enum<T, F>(&mut self, _: &str, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> DecodingResult<T>
-        where F: FnMut(&mut DecoderReader<'a, R>, usize) -> DecodingResult<T>
+        where F: FnMut(&mut DecoderReader<'a, R, S>, usize) -> DecodingResult<T>
     {
         let id = try!(self.read_u32());
         let id = id as usize;
@@ -272,12 +270,12 @@ impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
             }
     }
     fn read_enum_variant_arg<T, F>(&mut self, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> DecodingResult<T>
-        where F: FnMut(&mut DecoderReader<'a, R>, usize) -> DecodingResult<T>
+        where F: FnMut(&mut DecoderReader<'a, R, S>, usize) -> DecodingResult<T>
     {
         self.read_enum_variant(names, f)
     }
@@ -286,77 +284,76 @@ impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
                                             f_idx: usize,
                                             f: F)
                                             -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         self.read_enum_variant_arg(f_idx, f)
     }
     fn read_struct<T, F>(&mut self, _: &str, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_struct_field<T, F>(&mut self, _: &str, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_tuple<T, F>(&mut self, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_tuple_arg<T, F>(&mut self, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_tuple_struct<T, F>(&mut self, _: &str, len: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         self.read_tuple(len, f)
     }
     fn read_tuple_struct_arg<T, F>(&mut self, a_idx: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         self.read_tuple_arg(a_idx, f)
     }
     fn read_option<T, F>(&mut self, mut f: F) -> DecodingResult<T>
-        where F: FnMut(&mut DecoderReader<'a, R>, bool) -> DecodingResult<T>
+        where F: FnMut(&mut DecoderReader<'a, R, S>, bool) -> DecodingResult<T>
     {
         let x = try!(self.read_u8());
-        match x {
-                1 => f(self, true),
-                0 => f(self, false),
-                _ => Err(DecodingError::InvalidEncoding(InvalidEncoding {
+        match format::option_from_tag(x) {
+                Some(is_some) => f(self, is_some),
+                None => Err(DecodingError::InvalidEncoding(InvalidEncoding {
                     desc: "invalid tag when decoding Option",
                     detail: Some(format!("Expected 0 or 1, got {}", x))
                 })),
             }
     }
     fn read_seq<T, F>(&mut self, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>, usize) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>, usize) -> DecodingResult<T>
     {
         let len = try!(self.read_usize());
         f(self, len)
     }
     fn read_seq_elt<T, F>(&mut self, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_map<T, F>(&mut self, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>, usize) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>, usize) -> DecodingResult<T>
     {
         let len = try!(self.read_usize());
         f(self, len)
     }
     fn read_map_elt_key<T, F>(&mut self, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
     fn read_map_elt_val<T, F>(&mut self, _: usize, f: F) -> DecodingResult<T>
-        where F: FnOnce(&mut DecoderReader<'a, R>) -> DecodingResult<T>
+        where F: FnOnce(&mut DecoderReader<'a, R, S>) -> DecodingResult<T>
     {
         f(self)
     }
@@ -367,26 +364,3 @@ impl<'a, R: Read> Decoder for DecoderReader<'a, R> {
         })
     }
 }
-
-static UTF8_CHAR_WIDTH: [u8; 256] = [
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x1F
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x3F
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x5F
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x7F
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0, // 0x9F
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0, // 0xBF
-0,0,2,2,2,2,2,2,2,2,2,2,2,2,2,2,
-2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2, // 0xDF
-3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3, // 0xEF
-4,4,4,4,4,0,0,0,0,0,0,0,0,0,0,0, // 0xFF
-];
-
-fn utf8_char_width(b: u8) -> usize {
-    UTF8_CHAR_WIDTH[b as usize] as usize
-}
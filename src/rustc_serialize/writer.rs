@@ -1,4 +1,4 @@
-use std::io::Write;
+use std::io::{ErrorKind, Write};
 use std::io::Error as IoError;
 use std::error::Error;
 use std::fmt;
@@ -7,6 +7,8 @@ use rustc_serialize_crate::Encoder;
 
 use byteorder::{BigEndian, WriteBytesExt};
 
+use ::format::{self, StaticLayout, StandardLayout, TagOverflow};
+
 pub type EncodingResult<T> = Result<T, EncodingError>;
 
 
@@ -39,6 +41,12 @@ fn wrap_io(err: IoError) -> EncodingError {
     EncodingError::IoError(err)
 }
 
+impl From<TagOverflow> for EncodingError {
+    fn from(err: TagOverflow) -> EncodingError {
+        EncodingError::IoError(IoError::new(ErrorKind::InvalidInput, err))
+    }
+}
+
 impl fmt::Display for EncodingError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
@@ -132,7 +140,7 @@ impl<'a, W: Write> Encoder for EncoderWriter<'a, W> {
         self.writer.write_i8(v).map_err(wrap_io)
     }
     fn emit_bool(&mut self, v: bool) -> EncodingResult<()> {
-        self.writer.write_u8(if v {1} else {0}).map_err(wrap_io)
+        self.writer.write_u8(format::bool_byte(v)).map_err(wrap_io)
     }
     fn emit_f64(&mut self, v: f64) -> EncodingResult<()> {
         self.writer.write_f64::<BigEndian>(v).map_err(wrap_io)
@@ -141,15 +149,8 @@ impl<'a, W: Write> Encoder for EncoderWriter<'a, W> {
         self.writer.write_f32::<BigEndian>(v).map_err(wrap_io)
     }
     fn emit_char(&mut self, v: char) -> EncodingResult<()> {
-        // TODO: change this back once unicode works
-        //let mut cbuf = [0; 4];
-        //let sz = v.encode_utf8(&mut cbuf[..]).unwrap_or(0);
-        //let ptr = &cbuf[..sz];
-        //self.writer.write_all(ptr).map_err(EncodingError::IoError)
-
-        let mut inter = String::with_capacity(1);
-        inter.push(v);
-        self.writer.write_all(inter.as_bytes()).map_err(EncodingError::IoError)
+        let mut buf = [0; 4];
+        self.writer.write_all(format::char_bytes(v, &mut buf)).map_err(EncodingError::IoError)
     }
     fn emit_str(&mut self, v: &str) -> EncodingResult<()> {
         try!(self.emit_usize(v.len()));
@@ -163,11 +164,7 @@ impl<'a, W: Write> Encoder for EncoderWriter<'a, W> {
     fn emit_enum_variant<F>(&mut self, _: &str, v_id: usize, _: usize, f: F) -> EncodingResult<()>
         where F: FnOnce(&mut EncoderWriter<'a, W>) -> EncodingResult<()>
     {
-        let max_u32: u32 = ::std::u32::MAX;
-        if v_id > (max_u32 as usize) {
-                panic!("Variant tag doesn't fit in a u32")
-            }
-        try!(self.emit_u32(v_id as u32));
+        try!(self.emit_u32(try!(format::variant_tag(v_id))));
         f(self)
     }
     fn emit_enum_variant_arg<F>(&mut self, _: usize, f: F) -> EncodingResult<()>
@@ -226,12 +223,12 @@ impl<'a, W: Write> Encoder for EncoderWriter<'a, W> {
         f(self)
     }
     fn emit_option_none(&mut self) -> EncodingResult<()> {
-        self.writer.write_u8(0).map_err(wrap_io)
+        self.writer.write_u8(format::option_tag(false)).map_err(wrap_io)
     }
     fn emit_option_some<F>(&mut self, f: F) -> EncodingResult<()>
         where F: FnOnce(&mut EncoderWriter<'a, W>) -> EncodingResult<()>
     {
-        try!(self.writer.write_u8(1).map_err(wrap_io));
+        try!(self.writer.write_u8(format::option_tag(true)).map_err(wrap_io));
         f(self)
     }
     fn emit_seq<F>(&mut self, len: usize, f: F) -> EncodingResult<()>
@@ -313,7 +310,7 @@ impl Encoder for SizeChecker {
         self.add_raw(v.len_utf8())
     }
     fn emit_str(&mut self, v: &str) -> EncodingResult<()> {
-        try!(self.add_value(0 as u64));
+        try!(self.add_raw(StandardLayout::LEN_PREFIX_BYTES));
         self.add_raw(v.len())
     }
     fn emit_enum<F>(&mut self, __: &str, f: F) -> EncodingResult<()>
@@ -324,7 +321,7 @@ impl Encoder for SizeChecker {
     fn emit_enum_variant<F>(&mut self, _: &str, v_id: usize, _: usize, f: F) -> EncodingResult<()>
         where F: FnOnce(&mut SizeChecker) -> EncodingResult<()>
     {
-        try!(self.add_value(v_id as u32));
+        try!(self.add_value(try!(format::variant_tag(v_id))));
         f(self)
     }
     fn emit_enum_variant_arg<F>(&mut self, _: usize, f: F) -> EncodingResult<()>
@@ -0,0 +1,222 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+
+use super::{Descriptor, Format, Container, Field, Variant, VariantFormat};
+
+/// A reason why messages of one layout can't be read as another, as found
+/// by `compatible`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Incompatibility {
+    /// Where the layouts differ, written like `FieldDiff::path`, with `[]`
+    /// for the elements of a sequence.  It is empty for the whole message.
+    pub path: String,
+    /// What is different there.
+    pub reason: String,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(fmt, "Incompatibility: {}", self.reason)
+        } else {
+            write!(fmt, "Incompatibility at {}: {}", self.path, self.reason)
+        }
+    }
+}
+
+impl Error for Incompatibility {
+    fn description(&self) -> &str {
+        "two layouts are not wire-compatible"
+    }
+}
+
+/// Checks that every message written with the `old` layout can be read with
+/// the `new` one, and means the same thing there.
+///
+/// bincode doesn't write down field names or types, so this is checked
+/// position by position: structs must have fields of the same formats in
+/// the same order, and numbers keep their width.  Fields and types may be
+/// renamed, but a field that has moved to another position is reported
+/// even if the bytes would still line up, since that is almost always a
+/// mistake.  Enums may gain variants at the end, but not lose or reorder
+/// them.  Fields can't be added, since a struct has no length that would
+/// tell old and new messages apart.
+///
+/// This is meant to run in a test, against a descriptor traced from a
+/// frozen copy of the type as it was last released:
+///
+/// ```rust,ignore
+/// let old = bincode::schema::trace::<v1::Message>().unwrap();
+/// let new = bincode::schema::trace::<Message>().unwrap();
+/// bincode::schema::compatible(&old, &new).unwrap();
+/// ```
+pub fn compatible(old: &Descriptor, new: &Descriptor) -> Result<(), Incompatibility> {
+    let mut checker = Checker {
+        old: old,
+        new: new,
+        path: String::new(),
+        checked: BTreeSet::new(),
+    };
+    checker.format(&old.root, &new.root)
+}
+
+struct Checker<'a> {
+    old: &'a Descriptor,
+    new: &'a Descriptor,
+    path: String,
+    /// The pairs of named types that have been or are being compared, so
+    /// that recursive types are only compared once.
+    checked: BTreeSet<(&'a str, &'a str)>,
+}
+
+impl<'a> Checker<'a> {
+    fn fail<T: Into<String>>(&self, reason: T) -> Result<(), Incompatibility> {
+        Err(Incompatibility {
+            path: self.path.clone(),
+            reason: reason.into(),
+        })
+    }
+
+    /// Compares two formats under `segment` of the path.
+    fn nested(&mut self, segment: &str, old: &'a Format, new: &'a Format) -> Result<(), Incompatibility> {
+        let len = self.path.len();
+        self.path.push_str(segment);
+        let result = self.format(old, new);
+        self.path.truncate(len);
+        result
+    }
+
+    fn format(&mut self, old: &'a Format, new: &'a Format) -> Result<(), Incompatibility> {
+        match (old, new) {
+            (&Format::Option(ref old), &Format::Option(ref new)) => self.format(old, new),
+            (&Format::Seq(ref old), &Format::Seq(ref new)) => self.nested("[]", old, new),
+            (&Format::Map(ref old_key, ref old_value), &Format::Map(ref new_key, ref new_value)) => {
+                try!(self.nested("[].key", old_key, new_key));
+                self.nested("[].value", old_value, new_value)
+            }
+            (&Format::Tuple(ref old), &Format::Tuple(ref new)) => self.formats(old, new),
+            (&Format::Array(old_len, ref old), &Format::Array(new_len, ref new)) => {
+                if old_len != new_len {
+                    return self.fail(format!("an array of {} elements became one of {}", old_len, new_len));
+                }
+                self.nested("[]", old, new)
+            }
+            (&Format::Named(ref old), &Format::Named(ref new)) => self.named(old, new),
+            (&Format::Named(ref old), new) => {
+                // A newtype struct is written like what it holds.
+                match self.old.types.get(old) {
+                    Some(&Container::NewtypeStruct(ref old)) => self.format(old, new),
+                    _ => self.fail(format!("{} became {}", old, describe(new))),
+                }
+            }
+            (old, &Format::Named(ref new)) => {
+                match self.new.types.get(new) {
+                    Some(&Container::NewtypeStruct(ref new)) => self.format(old, new),
+                    _ => self.fail(format!("{} became {}", describe(old), new)),
+                }
+            }
+            (old, new) if old == new => Ok(()),
+            (old, new) => self.fail(format!("{} became {}", describe(old), describe(new))),
+        }
+    }
+
+    fn formats(&mut self, old: &'a [Format], new: &'a [Format]) -> Result<(), Incompatibility> {
+        if old.len() != new.len() {
+            return self.fail(format!("{} elements became {}", old.len(), new.len()));
+        }
+        for (i, (old, new)) in old.iter().zip(new).enumerate() {
+            try!(self.nested(&format!(".{}", i), old, new));
+        }
+        Ok(())
+    }
+
+    fn fields(&mut self, old: &'a [Field], new: &'a [Field]) -> Result<(), Incompatibility> {
+        for (i, (old_field, new_field)) in old.iter().zip(new).enumerate() {
+            if old_field.name != new_field.name {
+                if let Some(j) = new.iter().position(|field| field.name == old_field.name) {
+                    return self.fail(format!("field `{}` moved from position {} to {}", old_field.name, i, j));
+                }
+            }
+            try!(self.nested(&format!(".{}", new_field.name), &old_field.format, &new_field.format));
+        }
+        if new.len() > old.len() {
+            return self.fail(format!("field `{}` was added", new[old.len()].name));
+        }
+        if old.len() > new.len() {
+            return self.fail(format!("field `{}` was removed", old[new.len()].name));
+        }
+        Ok(())
+    }
+
+    fn variants(&mut self, old: &'a [Variant], new: &'a [Variant]) -> Result<(), Incompatibility> {
+        if old.len() > new.len() {
+            return self.fail(format!("variant `{}` was removed", old[new.len()].name));
+        }
+        for (i, (old_variant, new_variant)) in old.iter().zip(new).enumerate() {
+            if old_variant.name != new_variant.name {
+                if let Some(j) = new.iter().position(|variant| variant.name == old_variant.name) {
+                    return self.fail(format!("variant `{}` moved from tag {} to {}", old_variant.name, i, j));
+                }
+            }
+            let len = self.path.len();
+            self.path.push_str("::");
+            self.path.push_str(&new_variant.name);
+            let result = match (&old_variant.format, &new_variant.format) {
+                (&VariantFormat::Unit, &VariantFormat::Unit) => Ok(()),
+                (&VariantFormat::Newtype(ref old), &VariantFormat::Newtype(ref new)) => self.format(old, new),
+                (&VariantFormat::Tuple(ref old), &VariantFormat::Tuple(ref new)) => self.formats(old, new),
+                (&VariantFormat::Struct(ref old), &VariantFormat::Struct(ref new)) => self.fields(old, new),
+                _ => self.fail("the kind of variant changed"),
+            };
+            self.path.truncate(len);
+            try!(result);
+        }
+        Ok(())
+    }
+
+    fn named(&mut self, old_name: &'a str, new_name: &'a str) -> Result<(), Incompatibility> {
+        if !self.checked.insert((old_name, new_name)) {
+            return Ok(());
+        }
+        let (old, new) = match (self.old.types.get(old_name), self.new.types.get(new_name)) {
+            (Some(old), Some(new)) => (old, new),
+            _ => return self.fail(format!("{} or {} is not described", old_name, new_name)),
+        };
+        match (old, new) {
+            (&Container::UnitStruct, &Container::UnitStruct) => Ok(()),
+            (&Container::NewtypeStruct(ref old), &Container::NewtypeStruct(ref new)) => self.format(old, new),
+            (&Container::TupleStruct(ref old), &Container::TupleStruct(ref new)) => self.formats(old, new),
+            (&Container::Struct(ref old), &Container::Struct(ref new)) => self.fields(old, new),
+            (&Container::Enum(ref old), &Container::Enum(ref new)) => self.variants(old, new),
+            _ => self.fail(format!("{} became {}", old_name, new_name)),
+        }
+    }
+}
+
+/// Names a format for an `Incompatibility`.
+fn describe(format: &Format) -> String {
+    match *format {
+        Format::Unit => "()".to_string(),
+        Format::Bool => "bool".to_string(),
+        Format::U8 => "u8".to_string(),
+        Format::U16 => "u16".to_string(),
+        Format::U32 => "u32".to_string(),
+        Format::U64 => "u64".to_string(),
+        Format::I8 => "i8".to_string(),
+        Format::I16 => "i16".to_string(),
+        Format::I32 => "i32".to_string(),
+        Format::I64 => "i64".to_string(),
+        Format::F32 => "f32".to_string(),
+        Format::F64 => "f64".to_string(),
+        Format::Char => "char".to_string(),
+        Format::Str => "a string".to_string(),
+        Format::Bytes => "bytes".to_string(),
+        Format::Option(_) => "an option".to_string(),
+        Format::Seq(_) => "a sequence".to_string(),
+        Format::Map(..) => "a map".to_string(),
+        Format::Tuple(ref formats) => format!("a tuple of {}", formats.len()),
+        Format::Array(len, _) => format!("an array of {}", len),
+        Format::Named(ref name) => name.clone(),
+    }
+}
@@ -0,0 +1,234 @@
+use std::cmp;
+
+use ::serde::DeserializeResult;
+use super::{Descriptor, Format, Container, Field, VariantFormat};
+use super::value::{Value, ValueReader, MAX_DEPTH, custom};
+
+/// A place where two messages differ, as found by `diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDiff {
+    /// Where the value is: `.name` for a struct field, `.0` for a tuple
+    /// element, `[3]` for an element of a sequence, `[3].key` and
+    /// `[3].value` for the parts of a map entry, and `::Name` for the
+    /// fields of an enum variant.  It is empty for the whole message.
+    pub path: String,
+    /// The offset of the value in the first message.
+    pub offset_a: u64,
+    /// The offset of the value in the second message.
+    pub offset_b: u64,
+    /// The value in the first message, or `None` if the sequence or map it
+    /// is in has fewer elements there.  Map entries that only one side has
+    /// are given as a `Seq` of the key and the value.
+    pub a: Option<Value>,
+    /// The value in the second message, or `None` likewise.
+    pub b: Option<Value>,
+}
+
+/// Compares two messages of the type `descriptor` was traced from, field by
+/// field, and returns every place where they differ.
+///
+/// Values are compared as far down as they have the same shape.  Where they
+/// don't, like an enum with different variants or an `Option` that is only
+/// set on one side, the whole value on each side is reported.  Elements of
+/// sequences and maps are compared by position, and the elements that only
+/// one side has are reported with `None` for the other side.
+///
+/// ```rust,ignore
+/// let descriptor = bincode::schema::trace::<Snapshot>().unwrap();
+/// for change in bincode::diff(&yesterday, &today, &descriptor).unwrap() {
+///     println!("{} at {:#x}: {:?} -> {:?}", change.path, change.offset_a, change.a, change.b);
+/// }
+/// ```
+pub fn diff(a: &[u8], b: &[u8], descriptor: &Descriptor) -> DeserializeResult<Vec<FieldDiff>> {
+    let mut differ = Differ {
+        a: ValueReader::new(a, descriptor),
+        b: ValueReader::new(b, descriptor),
+        path: String::new(),
+        depth: 0,
+        diffs: Vec::new(),
+    };
+    try!(differ.compare(&descriptor.root));
+    Ok(differ.diffs)
+}
+
+struct Differ<'a, 'b, 'd> {
+    a: ValueReader<'a, 'd>,
+    b: ValueReader<'b, 'd>,
+    path: String,
+    depth: usize,
+    diffs: Vec<FieldDiff>,
+}
+
+impl<'a, 'b, 'd> Differ<'a, 'b, 'd> {
+    fn push(&mut self, offsets: (u64, u64), a: Option<Value>, b: Option<Value>) {
+        self.diffs.push(FieldDiff {
+            path: self.path.clone(),
+            offset_a: offsets.0,
+            offset_b: offsets.1,
+            a: a,
+            b: b,
+        });
+    }
+
+    fn offsets(&self) -> (u64, u64) {
+        (self.a.offset(), self.b.offset())
+    }
+
+    /// Compares the next value of each message under `segment` of the path.
+    fn compare_at(&mut self, segment: &str, format: &Format) -> DeserializeResult<()> {
+        let len = self.path.len();
+        self.path.push_str(segment);
+        let result = self.compare(format);
+        self.path.truncate(len);
+        result
+    }
+
+    fn compare(&mut self, format: &Format) -> DeserializeResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(custom("a value is nested too deeply"));
+        }
+        try!(self.compare_format(format));
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn compare_format(&mut self, format: &Format) -> DeserializeResult<()> {
+        let offsets = self.offsets();
+        match *format {
+            Format::Option(ref format) => {
+                let a: Option<()> = try!(self.a.primitive());
+                let b: Option<()> = try!(self.b.primitive());
+                match (a, b) {
+                    (Some(()), Some(())) => return self.compare(format),
+                    (None, None) => {}
+                    (a, b) => {
+                        let a = try!(read_option(&mut self.a, a, format));
+                        let b = try!(read_option(&mut self.b, b, format));
+                        self.push(offsets, Some(a), Some(b));
+                    }
+                }
+                Ok(())
+            }
+            Format::Seq(ref format) | Format::Array(_, ref format) => {
+                let len_a = try!(self.a.read_len());
+                let len_b = try!(self.b.read_len());
+                for i in 0..cmp::min(len_a, len_b) {
+                    try!(self.compare_at(&format!("[{}]", i), format));
+                }
+                self.compare_rest(len_a, len_b, |reader, i| {
+                    reader.read(format).map(|value| (format!("[{}]", i), value))
+                })
+            }
+            Format::Map(ref key, ref value) => {
+                let len_a = try!(self.a.read_len());
+                let len_b = try!(self.b.read_len());
+                for i in 0..cmp::min(len_a, len_b) {
+                    try!(self.compare_at(&format!("[{}].key", i), key));
+                    try!(self.compare_at(&format!("[{}].value", i), value));
+                }
+                self.compare_rest(len_a, len_b, |reader, i| {
+                    let k = try!(reader.read(key));
+                    let v = try!(reader.read(value));
+                    Ok((format!("[{}]", i), Value::Seq(vec![k, v])))
+                })
+            }
+            Format::Tuple(ref formats) => self.compare_tuple(formats),
+            Format::Named(ref name) => self.compare_named(name),
+            ref format => {
+                let a = try!(self.a.read(format));
+                let b = try!(self.b.read(format));
+                if a != b {
+                    self.push(offsets, Some(a), Some(b));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reports the elements past the end of the shorter of two sequences.
+    fn compare_rest<F>(&mut self, len_a: usize, len_b: usize, mut read: F) -> DeserializeResult<()>
+        where F: FnMut(&mut ValueReader, usize) -> DeserializeResult<(String, Value)>
+    {
+        let common = cmp::min(len_a, len_b);
+        for i in common..len_a {
+            let offsets = self.offsets();
+            let (segment, value) = try!(read(&mut self.a, i));
+            self.diffs.push(FieldDiff {
+                path: format!("{}{}", self.path, segment),
+                offset_a: offsets.0,
+                offset_b: offsets.1,
+                a: Some(value),
+                b: None,
+            });
+        }
+        for i in common..len_b {
+            let offsets = self.offsets();
+            let (segment, value) = try!(read(&mut self.b, i));
+            self.diffs.push(FieldDiff {
+                path: format!("{}{}", self.path, segment),
+                offset_a: offsets.0,
+                offset_b: offsets.1,
+                a: None,
+                b: Some(value),
+            });
+        }
+        Ok(())
+    }
+
+    fn compare_tuple(&mut self, formats: &[Format]) -> DeserializeResult<()> {
+        for (i, format) in formats.iter().enumerate() {
+            try!(self.compare_at(&format!(".{}", i), format));
+        }
+        Ok(())
+    }
+
+    fn compare_fields(&mut self, fields: &[Field]) -> DeserializeResult<()> {
+        for field in fields {
+            try!(self.compare_at(&format!(".{}", field.name), &field.format));
+        }
+        Ok(())
+    }
+
+    fn compare_named(&mut self, name: &str) -> DeserializeResult<()> {
+        let offsets = self.offsets();
+        match *try!(self.a.container(name)) {
+            Container::UnitStruct => Ok(()),
+            Container::NewtypeStruct(ref format) => self.compare(format),
+            Container::TupleStruct(ref formats) => self.compare_tuple(formats),
+            Container::Struct(ref fields) => self.compare_fields(fields),
+            Container::Enum(ref variants) => {
+                let (index_a, variant_a) = try!(self.a.read_tag(name, variants));
+                let (index_b, variant_b) = try!(self.b.read_tag(name, variants));
+                if index_a != index_b {
+                    let a = try!(self.a.read_variant(variant_a));
+                    let b = try!(self.b.read_variant(variant_b));
+                    self.push(offsets,
+                              Some(Value::Variant(index_a, variant_a.name.clone(), Box::new(a))),
+                              Some(Value::Variant(index_b, variant_b.name.clone(), Box::new(b))));
+                    return Ok(());
+                }
+
+                let len = self.path.len();
+                self.path.push_str("::");
+                self.path.push_str(&variant_a.name);
+                let result = match variant_a.format {
+                    VariantFormat::Unit => Ok(()),
+                    VariantFormat::Newtype(ref format) => self.compare(format),
+                    VariantFormat::Tuple(ref formats) => self.compare_tuple(formats),
+                    VariantFormat::Struct(ref fields) => self.compare_fields(fields),
+                };
+                self.path.truncate(len);
+                result
+            }
+        }
+    }
+}
+
+/// Reads the rest of an `Option` whose tag has been read.
+fn read_option(reader: &mut ValueReader, tag: Option<()>, format: &Format) -> DeserializeResult<Value> {
+    match tag {
+        Some(()) => Ok(Value::Option(Some(Box::new(try!(reader.read(format)))))),
+        None => Ok(Value::Option(None)),
+    }
+}
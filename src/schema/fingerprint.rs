@@ -0,0 +1,238 @@
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, ByteOrder};
+use serde_crate as serde;
+
+use ::SizeLimit;
+use ::serde::{serialize, Deserializer, SliceReader, SerializeResult, DeserializeResult, DeserializeError};
+use super::{trace, Descriptor, Format, Container, Field, Variant, VariantFormat};
+
+impl Descriptor {
+    /// Returns a 64-bit hash of the wire layout.
+    ///
+    /// Two types have the same fingerprint when their messages are laid out
+    /// the same way: the same fields in the same order with the same names
+    /// and formats, and the same enum variants.  The names of structs and
+    /// enums themselves are left out, so renaming a type doesn't change its
+    /// fingerprint, but reordering, retyping, adding, or removing a field or
+    /// variant does.  The fingerprint of a layout doesn't change between
+    /// versions of bincode.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fingerprinter {
+            descriptor: self,
+            hash: FNV_OFFSET,
+            stack: Vec::new(),
+        };
+        hasher.format(&self.root);
+        hasher.hash
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Walks a descriptor from its root, hashing each format as it goes.
+struct Fingerprinter<'a> {
+    descriptor: &'a Descriptor,
+    hash: u64,
+    /// The named types that are being hashed, innermost last, so that a
+    /// recursive type refers back to itself instead of going on forever.
+    stack: Vec<&'a str>,
+}
+
+impl<'a> Fingerprinter<'a> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn tag(&mut self, tag: u8) {
+        self.bytes(&[tag]);
+    }
+
+    fn len(&mut self, len: usize) {
+        let mut bytes = [0; 8];
+        BigEndian::write_u64(&mut bytes, len as u64);
+        self.bytes(&bytes);
+    }
+
+    fn name(&mut self, name: &str) {
+        self.len(name.len());
+        self.bytes(name.as_bytes());
+    }
+
+    fn formats(&mut self, formats: &'a [Format]) {
+        self.len(formats.len());
+        for format in formats {
+            self.format(format);
+        }
+    }
+
+    fn fields(&mut self, fields: &'a [Field]) {
+        self.len(fields.len());
+        for field in fields {
+            self.name(&field.name);
+            self.format(&field.format);
+        }
+    }
+
+    fn format(&mut self, format: &'a Format) {
+        match *format {
+            Format::Unit => self.tag(0),
+            Format::Bool => self.tag(1),
+            Format::U8 => self.tag(2),
+            Format::U16 => self.tag(3),
+            Format::U32 => self.tag(4),
+            Format::U64 => self.tag(5),
+            Format::I8 => self.tag(6),
+            Format::I16 => self.tag(7),
+            Format::I32 => self.tag(8),
+            Format::I64 => self.tag(9),
+            Format::F32 => self.tag(10),
+            Format::F64 => self.tag(11),
+            Format::Char => self.tag(12),
+            Format::Str => self.tag(13),
+            Format::Bytes => self.tag(14),
+            Format::Option(ref inner) => {
+                self.tag(15);
+                self.format(inner);
+            }
+            Format::Seq(ref inner) => {
+                self.tag(16);
+                self.format(inner);
+            }
+            Format::Map(ref key, ref value) => {
+                self.tag(17);
+                self.format(key);
+                self.format(value);
+            }
+            Format::Tuple(ref formats) => {
+                self.tag(18);
+                self.formats(formats);
+            }
+            Format::Array(len, ref inner) => {
+                self.tag(19);
+                self.len(len);
+                self.format(inner);
+            }
+            Format::Named(ref name) => self.named(name),
+        }
+    }
+
+    fn named(&mut self, name: &'a str) {
+        if let Some(depth) = self.stack.iter().rev().position(|&outer| outer == name) {
+            self.tag(20);
+            self.len(depth);
+            return;
+        }
+        let container = match self.descriptor.types.get(name) {
+            Some(container) => container,
+            None => {
+                self.tag(21);
+                self.name(name);
+                return;
+            }
+        };
+        self.stack.push(name);
+        match *container {
+            Container::UnitStruct => self.tag(22),
+            Container::NewtypeStruct(ref format) => {
+                self.tag(23);
+                self.format(format);
+            }
+            Container::TupleStruct(ref formats) => {
+                self.tag(24);
+                self.formats(formats);
+            }
+            Container::Struct(ref fields) => {
+                self.tag(25);
+                self.fields(fields);
+            }
+            Container::Enum(ref variants) => {
+                self.tag(26);
+                self.variants(variants);
+            }
+        }
+        self.stack.pop();
+    }
+
+    fn variants(&mut self, variants: &'a [Variant]) {
+        self.len(variants.len());
+        for variant in variants {
+            self.name(&variant.name);
+            match variant.format {
+                VariantFormat::Unit => self.tag(0),
+                VariantFormat::Newtype(ref format) => {
+                    self.tag(1);
+                    self.format(format);
+                }
+                VariantFormat::Tuple(ref formats) => {
+                    self.tag(2);
+                    self.formats(formats);
+                }
+                VariantFormat::Struct(ref fields) => {
+                    self.tag(3);
+                    self.fields(fields);
+                }
+            }
+        }
+    }
+}
+
+thread_local! {
+    static FINGERPRINTS: RefCell<HashMap<TypeId, u64>> = RefCell::new(HashMap::new());
+}
+
+/// Traces `T` and returns the fingerprint of its layout.
+///
+/// Tracing takes a while, so the fingerprint of each type is worked out
+/// once per thread and remembered.
+pub fn fingerprint<T: serde::Deserialize + 'static>() -> DeserializeResult<u64> {
+    let id = TypeId::of::<T>();
+    if let Some(fingerprint) = FINGERPRINTS.with(|cache| cache.borrow().get(&id).cloned()) {
+        return Ok(fingerprint);
+    }
+    let fingerprint = try!(trace::<T>()).fingerprint();
+    FINGERPRINTS.with(|cache| cache.borrow_mut().insert(id, fingerprint));
+    Ok(fingerprint)
+}
+
+/// Serializes an object with the fingerprint of its type in front of it.
+///
+/// The fingerprint is a `u64`.  `deserialize_fingerprinted` checks it
+/// against the type it is asked for, which catches a sender and receiver
+/// whose definitions of a message have drifted apart.  If `T` can't be
+/// traced, its tracing error is returned as a `Custom` error.
+pub fn serialize_fingerprinted<T, S>(value: &T, size_limit: S) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize + serde::Deserialize + 'static,
+          S: SizeLimit,
+{
+    let fingerprint = match fingerprint::<T>() {
+        Ok(fingerprint) => fingerprint,
+        Err(err) => return Err(::serde::SerializeError::Custom(err.to_string())),
+    };
+    serialize(&(fingerprint, value), size_limit)
+}
+
+/// Deserializes an object written by `serialize_fingerprinted`.
+///
+/// If the fingerprint in front of it isn't that of `T`, nothing else is read
+/// and a `SchemaMismatch` error is returned.
+pub fn deserialize_fingerprinted<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize + 'static,
+{
+    let expected = try!(fingerprint::<T>());
+    let mut deserializer = Deserializer::new(SliceReader::new(bytes), ::Infinite);
+    let found: u64 = try!(serde::Deserialize::deserialize(&mut deserializer));
+    if found != expected {
+        return Err(DeserializeError::SchemaMismatch {
+            expected: expected,
+            found: found,
+        });
+    }
+    serde::Deserialize::deserialize(&mut deserializer)
+}
@@ -0,0 +1,199 @@
+use std::fmt::Write;
+
+use super::{Descriptor, Format, Container, Field, Variant, VariantFormat};
+
+/// Renders a descriptor as JSON.
+///
+/// Every number, length prefix, and tag says how wide it is, so that a
+/// reader doesn't need to know bincode's conventions to make sense of it.
+pub fn descriptor_to_json(descriptor: &Descriptor) -> String {
+    let mut out = String::new();
+    out.push_str("{\"encoding\":\"bincode\",\"endianness\":\"big\",\"root\":");
+    write_format(&mut out, &descriptor.root);
+    out.push_str(",\"types\":{");
+    for (i, (name, container)) in descriptor.types.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(&mut out, name);
+        out.push(':');
+        write_container(&mut out, container);
+    }
+    out.push_str("}}");
+    out
+}
+
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_number(out: &mut String, kind: &str, width: usize) {
+    let _ = write!(out, "{{\"kind\":\"{}\",\"width\":{}}}", kind, width);
+}
+
+/// The `u64` in front of strings, byte buffers, sequences, and maps.
+fn write_length(out: &mut String) {
+    out.push_str(",\"length\":");
+    write_number(out, "u64", 8);
+}
+
+fn write_formats(out: &mut String, formats: &[Format]) {
+    out.push('[');
+    for (i, format) in formats.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_format(out, format);
+    }
+    out.push(']');
+}
+
+fn write_fields(out: &mut String, fields: &[Field]) {
+    out.push('[');
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        write_string(out, &field.name);
+        out.push_str(",\"type\":");
+        write_format(out, &field.format);
+        out.push('}');
+    }
+    out.push(']');
+}
+
+fn write_format(out: &mut String, format: &Format) {
+    match *format {
+        Format::Unit => write_number(out, "unit", 0),
+        Format::Bool => write_number(out, "bool", 1),
+        Format::U8 => write_number(out, "u8", 1),
+        Format::U16 => write_number(out, "u16", 2),
+        Format::U32 => write_number(out, "u32", 4),
+        Format::U64 => write_number(out, "u64", 8),
+        Format::I8 => write_number(out, "i8", 1),
+        Format::I16 => write_number(out, "i16", 2),
+        Format::I32 => write_number(out, "i32", 4),
+        Format::I64 => write_number(out, "i64", 8),
+        Format::F32 => write_number(out, "f32", 4),
+        Format::F64 => write_number(out, "f64", 8),
+        Format::Char => out.push_str("{\"kind\":\"char\",\"encoding\":\"utf8\"}"),
+        Format::Str => {
+            out.push_str("{\"kind\":\"string\",\"encoding\":\"utf8\"");
+            write_length(out);
+            out.push('}');
+        }
+        Format::Bytes => {
+            out.push_str("{\"kind\":\"bytes\"");
+            write_length(out);
+            out.push('}');
+        }
+        Format::Option(ref inner) => {
+            out.push_str("{\"kind\":\"option\",\"tag\":");
+            write_number(out, "u8", 1);
+            out.push_str(",\"some\":");
+            write_format(out, inner);
+            out.push('}');
+        }
+        Format::Seq(ref element) => {
+            out.push_str("{\"kind\":\"seq\"");
+            write_length(out);
+            out.push_str(",\"element\":");
+            write_format(out, element);
+            out.push('}');
+        }
+        Format::Map(ref key, ref value) => {
+            out.push_str("{\"kind\":\"map\"");
+            write_length(out);
+            out.push_str(",\"key\":");
+            write_format(out, key);
+            out.push_str(",\"value\":");
+            write_format(out, value);
+            out.push('}');
+        }
+        Format::Tuple(ref elements) => {
+            out.push_str("{\"kind\":\"tuple\",\"elements\":");
+            write_formats(out, elements);
+            out.push('}');
+        }
+        Format::Array(size, ref element) => {
+            let _ = write!(out, "{{\"kind\":\"array\",\"size\":{}", size);
+            write_length(out);
+            out.push_str(",\"element\":");
+            write_format(out, element);
+            out.push('}');
+        }
+        Format::Named(ref name) => {
+            out.push_str("{\"kind\":\"named\",\"name\":");
+            write_string(out, name);
+            out.push('}');
+        }
+    }
+}
+
+fn write_container(out: &mut String, container: &Container) {
+    match *container {
+        Container::UnitStruct => out.push_str("{\"kind\":\"unit_struct\"}"),
+        Container::NewtypeStruct(ref format) => {
+            out.push_str("{\"kind\":\"newtype_struct\",\"type\":");
+            write_format(out, format);
+            out.push('}');
+        }
+        Container::TupleStruct(ref elements) => {
+            out.push_str("{\"kind\":\"tuple_struct\",\"elements\":");
+            write_formats(out, elements);
+            out.push('}');
+        }
+        Container::Struct(ref fields) => {
+            out.push_str("{\"kind\":\"struct\",\"fields\":");
+            write_fields(out, fields);
+            out.push('}');
+        }
+        Container::Enum(ref variants) => {
+            out.push_str("{\"kind\":\"enum\",\"tag\":");
+            write_number(out, "u32", 4);
+            out.push_str(",\"variants\":[");
+            for (tag, variant) in variants.iter().enumerate() {
+                if tag > 0 {
+                    out.push(',');
+                }
+                write_variant(out, tag, variant);
+            }
+            out.push_str("]}");
+        }
+    }
+}
+
+fn write_variant(out: &mut String, tag: usize, variant: &Variant) {
+    out.push_str("{\"name\":");
+    write_string(out, &variant.name);
+    let _ = write!(out, ",\"tag\":{}", tag);
+    match variant.format {
+        VariantFormat::Unit => out.push_str(",\"kind\":\"unit\""),
+        VariantFormat::Newtype(ref format) => {
+            out.push_str(",\"kind\":\"newtype\",\"type\":");
+            write_format(out, format);
+        }
+        VariantFormat::Tuple(ref elements) => {
+            out.push_str(",\"kind\":\"tuple\",\"elements\":");
+            write_formats(out, elements);
+        }
+        VariantFormat::Struct(ref fields) => {
+            out.push_str(",\"kind\":\"struct\",\"fields\":");
+            write_fields(out, fields);
+        }
+    }
+    out.push('}');
+}
@@ -0,0 +1,568 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use serde_crate as serde;
+
+use ::Infinite;
+use ::serde::{serialize, deserialize, Serializer, DeserializeResult};
+use super::{trace, Descriptor, Format, Container, Field, Variant, VariantFormat};
+use super::value::{Value, MAX_DEPTH, deserialize_value_with_schema, custom};
+use super::json::write_string;
+
+/// Renders a value as JSON.
+///
+/// Structs become objects, with their fields in order, and tuples,
+/// sequences, arrays, and byte buffers become arrays.  A map becomes an
+/// object if its keys are strings, and an array of `[key, value]` pairs if
+/// they aren't.  `None` and `()` are `null`, and `Some` is whatever it
+/// holds.  A unit variant is its name as a string, and any other variant is
+/// an object with its name as the only key.
+///
+/// bincode doesn't depend on a JSON library, so this returns the JSON as
+/// text, which `serde_json` or any other JSON library can parse.  The
+/// value is turned back into a `T` by `from_json_value`.
+///
+/// ```rust,ignore
+/// let json = bincode::schema::to_json_value(&order).unwrap();
+/// // {"id":7,"side":"Buy","price":{"Limit":100},"tags":["a","b"]}
+/// ```
+pub fn to_json_value<T>(value: &T) -> DeserializeResult<String>
+    where T: serde::Serialize + serde::Deserialize,
+{
+    let descriptor = try!(trace::<T>());
+    let bytes = match serialize(value, Infinite) {
+        Ok(bytes) => bytes,
+        Err(err) => return Err(custom(&err.to_string())),
+    };
+    let value = try!(deserialize_value_with_schema(&bytes, &descriptor));
+    let mut out = String::new();
+    try!(write_value(&mut out, &value));
+    Ok(out)
+}
+
+/// Builds a `T` from JSON laid out as `to_json_value` writes it.
+///
+/// Test fixtures for a binary protocol can be kept as JSON, where they can
+/// be read and edited, and turned into messages when the tests run.
+/// Numbers have to fit in the type of the field they are for, and every
+/// field of a struct has to be given, with no others; anything else is an
+/// error that says what is wrong.  A map with string keys can be given as
+/// an object or as an array of pairs.
+pub fn from_json_value<T: serde::Deserialize>(json: &str) -> DeserializeResult<T> {
+    let descriptor = try!(trace::<T>());
+    let json = try!(Parser { input: json.as_bytes(), pos: 0 }.parse());
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = Encoder {
+            serializer: Serializer::new(&mut bytes),
+            descriptor: &descriptor,
+            depth: 0,
+        };
+        try!(encoder.encode(&descriptor.root, &json));
+    }
+    deserialize(&bytes)
+}
+
+fn write_value(out: &mut String, value: &Value) -> DeserializeResult<()> {
+    match *value {
+        Value::Unit | Value::Option(None) => out.push_str("null"),
+        Value::Bool(v) => out.push_str(if v { "true" } else { "false" }),
+        Value::U8(v) => { let _ = write!(out, "{}", v); }
+        Value::U16(v) => { let _ = write!(out, "{}", v); }
+        Value::U32(v) => { let _ = write!(out, "{}", v); }
+        Value::U64(v) => { let _ = write!(out, "{}", v); }
+        Value::I8(v) => { let _ = write!(out, "{}", v); }
+        Value::I16(v) => { let _ = write!(out, "{}", v); }
+        Value::I32(v) => { let _ = write!(out, "{}", v); }
+        Value::I64(v) => { let _ = write!(out, "{}", v); }
+        Value::F32(v) => try!(write_float(out, v as f64)),
+        Value::F64(v) => try!(write_float(out, v)),
+        Value::Char(v) => write_string(out, &v.to_string()),
+        Value::Str(ref v) => write_string(out, v),
+        Value::Bytes(ref bytes) => {
+            out.push('[');
+            for (i, byte) in bytes.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let _ = write!(out, "{}", byte);
+            }
+            out.push(']');
+        }
+        Value::Option(Some(ref value)) => try!(write_value(out, value)),
+        Value::Seq(ref values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                try!(write_value(out, value));
+            }
+            out.push(']');
+        }
+        Value::Map(ref entries) => {
+            let string_keys = !entries.is_empty() && entries.iter().all(|&(ref key, _)| key.as_str().is_some());
+            out.push(if string_keys { '{' } else { '[' });
+            for (i, &(ref key, ref value)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                if string_keys {
+                    try!(write_value(out, key));
+                    out.push(':');
+                    try!(write_value(out, value));
+                } else {
+                    out.push('[');
+                    try!(write_value(out, key));
+                    out.push(',');
+                    try!(write_value(out, value));
+                    out.push(']');
+                }
+            }
+            out.push(if string_keys { '}' } else { ']' });
+        }
+        Value::Struct(ref fields) => {
+            out.push('{');
+            for (i, &(ref name, ref value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, name);
+                out.push(':');
+                try!(write_value(out, value));
+            }
+            out.push('}');
+        }
+        Value::Variant(_, ref name, ref value) => {
+            if **value == Value::Unit {
+                write_string(out, name);
+            } else {
+                out.push('{');
+                write_string(out, name);
+                out.push(':');
+                try!(write_value(out, value));
+                out.push('}');
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_float(out: &mut String, v: f64) -> DeserializeResult<()> {
+    if !v.is_finite() {
+        return Err(custom(&format!("{} can't be written as JSON", v)));
+    }
+    let _ = write!(out, "{:?}", v);
+    Ok(())
+}
+
+/// A parsed JSON value.  Numbers are kept as text until it is known what
+/// type they are for.
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Number(_) => "a number",
+            Json::Str(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(mut self) -> DeserializeResult<Json> {
+        let json = try!(self.value(0));
+        self.skip_whitespace();
+        if self.pos < self.input.len() {
+            return Err(self.error("unexpected text after the JSON value"));
+        }
+        Ok(json)
+    }
+
+    fn error(&self, message: &str) -> ::serde::DeserializeError {
+        custom(&format!("invalid JSON at byte {}: {}", self.pos, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.input.len() && (self.input[self.pos] as char).is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).cloned()
+    }
+
+    fn expect(&mut self, c: u8) -> DeserializeResult<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c as char)))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, json: Json) -> DeserializeResult<Json> {
+        if self.input[self.pos..].starts_with(keyword.as_bytes()) {
+            self.pos += keyword.len();
+            Ok(json)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> DeserializeResult<Json> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        match self.peek() {
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::Str),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(try!(self.value(depth + 1)));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(values));
+                        }
+                        _ => return Err(self.error("expected `,` or `]`")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("expected a string key"));
+                    }
+                    let key = try!(self.string());
+                    try!(self.expect(b':'));
+                    members.push((key, try!(self.value(depth + 1))));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(members));
+                        }
+                        _ => return Err(self.error("expected `,` or `}`")),
+                    }
+                }
+            }
+            Some(c) if c == b'-' || (c as char).is_ascii_digit() => {
+                let start = self.pos;
+                while self.pos < self.input.len() && b"+-.eE0123456789".contains(&self.input[self.pos]) {
+                    self.pos += 1;
+                }
+                let number = String::from_utf8_lossy(&self.input[start..self.pos]).into_owned();
+                Ok(Json::Number(number))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> DeserializeResult<String> {
+        self.pos += 1;
+        let mut bytes = Vec::new();
+        loop {
+            let c = match self.input.get(self.pos) {
+                Some(&c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.input.get(self.pos).cloned();
+                    self.pos += 1;
+                    let c = match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => try!(self.unicode_escape()),
+                        _ => return Err(self.error("invalid escape in string")),
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("string is not valid utf8"))
+    }
+
+    /// Reads the four hex digits after `\u`, and the low surrogate after
+    /// them if they are a high one.
+    fn unicode_escape(&mut self) -> DeserializeResult<char> {
+        let high = try!(self.hex4());
+        let code = if high >= 0xd800 && high < 0xdc00 {
+            if !self.input[self.pos..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate in string"));
+            }
+            self.pos += 2;
+            let low = try!(self.hex4());
+            if low < 0xdc00 || low >= 0xe000 {
+                return Err(self.error("unpaired surrogate in string"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        ::std::char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape in string"))
+    }
+
+    fn hex4(&mut self) -> DeserializeResult<u32> {
+        let digits = match self.input.get(self.pos..self.pos + 4) {
+            Some(digits) => digits,
+            None => return Err(self.error("invalid unicode escape in string")),
+        };
+        let mut code = 0;
+        for &digit in digits {
+            match (digit as char).to_digit(16) {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(self.error("invalid unicode escape in string")),
+            }
+        }
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+/// Writes JSON out as bincode, following a `Descriptor`.
+struct Encoder<'a, 'b> {
+    serializer: Serializer<'a, Vec<u8>>,
+    descriptor: &'b Descriptor,
+    depth: usize,
+}
+
+impl<'a, 'b> Encoder<'a, 'b> {
+    fn put<T: serde::Serialize>(&mut self, value: T) -> DeserializeResult<()> {
+        value.serialize(&mut self.serializer).map_err(|err| custom(&err.to_string()))
+    }
+
+    fn number<T: FromStr>(&mut self, json: &Json, type_name: &str) -> DeserializeResult<T> {
+        match *json {
+            Json::Number(ref number) => match number.parse() {
+                Ok(value) => Ok(value),
+                Err(_) => Err(custom(&format!("{} is not a valid {}", number, type_name))),
+            },
+            ref json => Err(mismatch(type_name, json)),
+        }
+    }
+
+    fn encode(&mut self, format: &'b Format, json: &Json) -> DeserializeResult<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(custom("a value is nested too deeply"));
+        }
+        try!(self.encode_format(format, json));
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn encode_format(&mut self, format: &'b Format, json: &Json) -> DeserializeResult<()> {
+        match *format {
+            Format::Unit => match *json {
+                Json::Null => Ok(()),
+                ref json => Err(mismatch("null", json)),
+            },
+            Format::Bool => match *json {
+                Json::Bool(v) => self.put(v),
+                ref json => Err(mismatch("a boolean", json)),
+            },
+            Format::U8 => { let v: u8 = try!(self.number(json, "u8")); self.put(v) }
+            Format::U16 => { let v: u16 = try!(self.number(json, "u16")); self.put(v) }
+            Format::U32 => { let v: u32 = try!(self.number(json, "u32")); self.put(v) }
+            Format::U64 => { let v: u64 = try!(self.number(json, "u64")); self.put(v) }
+            Format::I8 => { let v: i8 = try!(self.number(json, "i8")); self.put(v) }
+            Format::I16 => { let v: i16 = try!(self.number(json, "i16")); self.put(v) }
+            Format::I32 => { let v: i32 = try!(self.number(json, "i32")); self.put(v) }
+            Format::I64 => { let v: i64 = try!(self.number(json, "i64")); self.put(v) }
+            Format::F32 => { let v: f32 = try!(self.number(json, "f32")); self.put(v) }
+            Format::F64 => { let v: f64 = try!(self.number(json, "f64")); self.put(v) }
+            Format::Char => match *json {
+                Json::Str(ref s) if s.chars().count() == 1 => self.put(s.chars().next().unwrap()),
+                ref json => Err(mismatch("a string of one char", json)),
+            },
+            Format::Str => match *json {
+                Json::Str(ref s) => self.put(&s[..]),
+                ref json => Err(mismatch("a string", json)),
+            },
+            Format::Bytes => match *json {
+                Json::Array(ref elements) => {
+                    try!(self.put(elements.len()));
+                    for element in elements {
+                        let byte: u8 = try!(self.number(element, "u8"));
+                        try!(self.put(byte));
+                    }
+                    Ok(())
+                }
+                ref json => Err(mismatch("an array of bytes", json)),
+            },
+            Format::Option(ref inner) => match *json {
+                Json::Null => self.put(::format::option_tag(false)),
+                ref json => {
+                    try!(self.put(::format::option_tag(true)));
+                    self.encode(inner, json)
+                }
+            },
+            Format::Seq(ref element) => match *json {
+                Json::Array(ref elements) => {
+                    try!(self.put(elements.len()));
+                    for json in elements {
+                        try!(self.encode(element, json));
+                    }
+                    Ok(())
+                }
+                ref json => Err(mismatch("an array", json)),
+            },
+            Format::Array(size, ref element) => match *json {
+                Json::Array(ref elements) if elements.len() == size => {
+                    try!(self.put(size));
+                    for json in elements {
+                        try!(self.encode(element, json));
+                    }
+                    Ok(())
+                }
+                ref json => Err(mismatch(&format!("an array of {} elements", size), json)),
+            },
+            Format::Map(ref key, ref value) => match *json {
+                Json::Object(ref members) if **key == Format::Str => {
+                    try!(self.put(members.len()));
+                    for &(ref k, ref v) in members {
+                        try!(self.put(&k[..]));
+                        try!(self.encode(value, v));
+                    }
+                    Ok(())
+                }
+                Json::Array(ref entries) => {
+                    try!(self.put(entries.len()));
+                    for entry in entries {
+                        match *entry {
+                            Json::Array(ref pair) if pair.len() == 2 => {
+                                try!(self.encode(key, &pair[0]));
+                                try!(self.encode(value, &pair[1]));
+                            }
+                            ref json => return Err(mismatch("a [key, value] pair", json)),
+                        }
+                    }
+                    Ok(())
+                }
+                ref json => Err(mismatch("an array of [key, value] pairs", json)),
+            },
+            Format::Tuple(ref formats) => self.encode_tuple(formats, json),
+            Format::Named(ref name) => self.encode_named(name, json),
+        }
+    }
+
+    fn encode_tuple(&mut self, formats: &'b [Format], json: &Json) -> DeserializeResult<()> {
+        match *json {
+            Json::Array(ref elements) if elements.len() == formats.len() => {
+                for (format, json) in formats.iter().zip(elements) {
+                    try!(self.encode(format, json));
+                }
+                Ok(())
+            }
+            ref json => Err(mismatch(&format!("an array of {} elements", formats.len()), json)),
+        }
+    }
+
+    fn encode_fields(&mut self, fields: &'b [Field], json: &Json) -> DeserializeResult<()> {
+        let members = match *json {
+            Json::Object(ref members) => members,
+            ref json => return Err(mismatch("an object", json)),
+        };
+        for &(ref name, _) in members {
+            if !fields.iter().any(|field| field.name == *name) {
+                return Err(custom(&format!("there is no field named `{}`", name)));
+            }
+        }
+        for field in fields {
+            match members.iter().find(|&&(ref name, _)| *name == field.name) {
+                Some(&(_, ref json)) => try!(self.encode(&field.format, json)),
+                None => return Err(custom(&format!("field `{}` is missing", field.name))),
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_named(&mut self, name: &str, json: &Json) -> DeserializeResult<()> {
+        let descriptor = self.descriptor;
+        let container = match descriptor.types.get(name) {
+            Some(container) => container,
+            None => return Err(custom(&format!("the schema has no type named {}", name))),
+        };
+        match *container {
+            Container::UnitStruct => self.encode_format(&Format::Unit, json),
+            Container::NewtypeStruct(ref format) => self.encode(format, json),
+            Container::TupleStruct(ref formats) => self.encode_tuple(formats, json),
+            Container::Struct(ref fields) => self.encode_fields(fields, json),
+            Container::Enum(ref variants) => {
+                let (variant_name, json) = match *json {
+                    Json::Str(ref variant_name) => (variant_name, None),
+                    Json::Object(ref members) if members.len() == 1 => (&members[0].0, Some(&members[0].1)),
+                    ref json => return Err(mismatch(&format!("a variant of {}", name), json)),
+                };
+                let (tag, variant) = match variants.iter().enumerate().find(|&(_, v)| v.name == *variant_name) {
+                    Some(found) => found,
+                    None => return Err(custom(&format!("{} has no variant named `{}`", name, variant_name))),
+                };
+                try!(self.put(try!(::format::variant_tag(tag).map_err(|err| custom(&err.to_string())))));
+                self.encode_variant(variant, json)
+            }
+        }
+    }
+
+    fn encode_variant(&mut self, variant: &'b Variant, json: Option<&Json>) -> DeserializeResult<()> {
+        match (&variant.format, json) {
+            (&VariantFormat::Unit, None) => Ok(()),
+            (&VariantFormat::Newtype(ref format), Some(json)) => self.encode(format, json),
+            (&VariantFormat::Tuple(ref formats), Some(json)) => self.encode_tuple(formats, json),
+            (&VariantFormat::Struct(ref fields), Some(json)) => self.encode_fields(fields, json),
+            (&VariantFormat::Unit, Some(_)) => {
+                Err(custom(&format!("`{}` is a unit variant, and is written as a string", variant.name)))
+            }
+            (_, None) => Err(custom(&format!("variant `{}` needs a value", variant.name))),
+        }
+    }
+}
+
+fn mismatch(expected: &str, json: &Json) -> ::serde::DeserializeError {
+    custom(&format!("expected {}, got {}", expected, json.kind()))
+}
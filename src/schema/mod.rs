@@ -0,0 +1,189 @@
+//! Describes the wire layout of a type.
+//!
+//! `trace` works out how a `serde::Deserialize` type is laid out on the
+//! wire without needing a value of it, and returns a `Descriptor`: the order
+//! of fields, the width of every number, the prefixes in front of strings,
+//! sequences, options, and enums.  `export_descriptor` renders that as JSON,
+//! which services written in other languages can use to read and write
+//! compatible messages.
+//!
+//! ```rust,ignore
+//! let json = bincode::schema::export_descriptor::<MyMessage>().unwrap();
+//! std::fs::File::create("my_message.json").unwrap().write_all(json.as_bytes()).unwrap();
+//! ```
+//!
+//! `deserialize_value_with_schema` uses a `Descriptor` to decode a message
+//! into a `Value`, for tools that handle messages of any type, and `diff`
+//! uses one to compare two messages field by field.  `patch_field` uses one
+//! to overwrite a single fixed-size field of a message in place, and an
+//! `Update` carries new values for fields named the same way to a receiver
+//! that applies them to its own copy of a message.
+//!
+//! `Descriptor::fingerprint` hashes a layout down to a `u64`.
+//! `serialize_fingerprinted` puts the fingerprint of a message's type in
+//! front of it, and `deserialize_fingerprinted` refuses messages whose
+//! fingerprint isn't that of the type they are read as, so that a sender
+//! and receiver that disagree about a struct get a `SchemaMismatch` error
+//! instead of garbage.  `compatible` checks that messages of an old layout
+//! can still be read with a new one, and says where they can't.
+//!
+//! `to_json_value` renders a value as JSON, field names and all, and
+//! `from_json_value` builds one back from JSON, so that fixtures for a
+//! binary protocol can be written and reviewed as text.
+//!
+//! `probe` tries a blob against several candidate layouts and ranks how
+//! well each of them fits, to recover data whose type was lost.
+//!
+//! Structs and enums are described once in `Descriptor::types` and referred
+//! to by name everywhere else, which is also how recursive types are
+//! described.  A recursive type can only be traced if it reaches itself
+//! through an `Option`, a collection, or an enum whose first variant isn't
+//! recursive.
+
+use std::collections::BTreeMap;
+
+use serde_crate::Deserialize;
+
+use serde::DeserializeResult;
+
+pub use self::trace::trace;
+pub use self::value::{Value, deserialize_value_with_schema};
+pub use self::diff::{FieldDiff, diff};
+pub use self::compat::{compatible, Incompatibility};
+pub use self::fingerprint::{fingerprint, serialize_fingerprinted, deserialize_fingerprinted};
+pub use self::json_value::{to_json_value, from_json_value};
+pub use self::patch::{patch_field, PatchError};
+pub use self::update::{Update, serialize_update, deserialize_update};
+pub use self::probe::{probe, ProbeResult};
+
+mod compat;
+mod diff;
+mod fingerprint;
+mod json;
+mod json_value;
+mod patch;
+mod probe;
+mod text;
+mod trace;
+mod update;
+mod value;
+
+/// The wire layout of a type, as found by `trace`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Descriptor {
+    /// The layout of the traced type itself.
+    pub root: Format,
+    /// Every struct and enum the traced type refers to, by name.
+    pub types: BTreeMap<String, Container>,
+}
+
+/// The layout of a single value.
+///
+/// All numbers are big-endian.  `usize` and `isize` are written as `U64`
+/// and `I64`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Format {
+    Unit,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    /// A single utf8 encoded character.
+    Char,
+    /// A `u64` length followed by that many bytes of utf8.
+    Str,
+    /// A `u64` length followed by that many bytes.
+    Bytes,
+    /// A `u8` tag, `0` for none or `1` for some, followed by the value if
+    /// there is one.
+    Option(Box<Format>),
+    /// A `u64` length followed by that many elements.
+    Seq(Box<Format>),
+    /// A `u64` length followed by that many key-value pairs.
+    Map(Box<Format>, Box<Format>),
+    /// The elements one after the other.
+    Tuple(Vec<Format>),
+    /// A fixed size array of the given size.  It is written like a `Seq`,
+    /// length included.
+    Array(usize, Box<Format>),
+    /// A struct or enum, described in `Descriptor::types`.
+    Named(String),
+}
+
+/// The layout of a named struct or enum.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Container {
+    /// Takes up no bytes at all.
+    UnitStruct,
+    NewtypeStruct(Format),
+    TupleStruct(Vec<Format>),
+    /// The fields one after the other, in declaration order.
+    Struct(Vec<Field>),
+    /// A `u32` tag, which is the index of the variant, followed by the
+    /// variant's fields.
+    Enum(Vec<Variant>),
+}
+
+/// A named field of a struct or struct variant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub format: Format,
+}
+
+/// A variant of an enum.  Its tag is its index in `Container::Enum`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub format: VariantFormat,
+}
+
+/// The fields of an enum variant.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VariantFormat {
+    Unit,
+    Newtype(Format),
+    Tuple(Vec<Format>),
+    Struct(Vec<Field>),
+}
+
+impl Descriptor {
+    /// Describes the layout as JSON, like `export_descriptor` does.
+    pub fn to_json(&self) -> String {
+        json::descriptor_to_json(self)
+    }
+
+    /// Describes the layout as text, for people reviewing a protocol.
+    ///
+    /// Each struct and enum is listed with the offset and width of every
+    /// field, as far as they are fixed, and the format of each field along
+    /// with the length prefixes and tags that go in front of it.
+    ///
+    /// ```text
+    /// struct Header
+    ///     offset width  field
+    ///          0     4  id: u32
+    ///          4    8+  name: string (u64 length, utf8)
+    ///        12+     1  flags: u8
+    /// ```
+    pub fn to_text(&self) -> String {
+        text::descriptor_to_text(self)
+    }
+}
+
+/// Traces `T` and describes its wire layout as JSON.
+///
+/// The description is meant to be read by programs: it spells out the
+/// byte order and the width of every number and length prefix, so that a
+/// decoder for another language can be written or generated from it.
+pub fn export_descriptor<T: Deserialize>() -> DeserializeResult<String> {
+    let descriptor = try!(trace::<T>());
+    Ok(json::descriptor_to_json(&descriptor))
+}
@@ -0,0 +1,384 @@
+use std::error::Error;
+use std::fmt;
+
+use serde_crate as serde;
+
+use ::Infinite;
+use ::serde::{serialize, SerializeError, DeserializeError};
+use super::{Descriptor, Format, Container, Field, VariantFormat};
+use super::text::fixed_size;
+use super::value::{ValueReader, MAX_DEPTH, custom};
+
+/// An error that can be produced by `patch_field` and `Update`.
+#[derive(Debug)]
+pub enum PatchError {
+    /// The path doesn't name a field of the layout, or isn't a path.
+    NoSuchField(String),
+    /// The path names a field that this message doesn't have: the value of
+    /// an `Option` that is `None`, a variant other than the one the message
+    /// holds, or an element past the end of a sequence.
+    NotPresent(String),
+    /// The field doesn't always take up the same number of bytes, like a
+    /// string or an `Option`, so it can't be overwritten in place.
+    NotFixedSize(String),
+    /// The new value isn't laid out the way the field is.
+    WrongLayout(String),
+    /// The new value could not be serialized.
+    Serialize(SerializeError),
+    /// The message could not be read up to and including the field.
+    Deserialize(DeserializeError),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::NoSuchField(ref path) =>
+                write!(fmt, "NoSuchField: {}", path),
+            PatchError::NotPresent(ref path) =>
+                write!(fmt, "NotPresent: {}", path),
+            PatchError::NotFixedSize(ref path) =>
+                write!(fmt, "NotFixedSize: {}", path),
+            PatchError::WrongLayout(ref path) =>
+                write!(fmt, "WrongLayout: {}", path),
+            PatchError::Serialize(ref err) =>
+                write!(fmt, "Serialize: {}", err),
+            PatchError::Deserialize(ref err) =>
+                write!(fmt, "Deserialize: {}", err),
+        }
+    }
+}
+
+impl Error for PatchError {
+    fn description(&self) -> &str {
+        match *self {
+            PatchError::NoSuchField(_) => "the path does not name a field of the layout",
+            PatchError::NotPresent(_) => "the field is not present in the message",
+            PatchError::NotFixedSize(_) => "the field does not have a fixed size",
+            PatchError::WrongLayout(_) => "the new value is not laid out like the field",
+            PatchError::Serialize(ref err) => err.description(),
+            PatchError::Deserialize(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            PatchError::Serialize(ref err) => Some(err),
+            PatchError::Deserialize(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<SerializeError> for PatchError {
+    fn from(err: SerializeError) -> PatchError {
+        PatchError::Serialize(err)
+    }
+}
+
+impl From<DeserializeError> for PatchError {
+    fn from(err: DeserializeError) -> PatchError {
+        PatchError::Deserialize(err)
+    }
+}
+
+/// Overwrites one field of a message in place with `value`, without
+/// reading or writing the rest of it.
+///
+/// `path` names the field the way `FieldDiff::path` does: `.name` for a
+/// struct field, `.0` for a tuple element, `[3]` for an element of a
+/// sequence, `[3].key` and `[3].value` for the parts of a map entry, and
+/// `::Name` for the fields of an enum variant.  Newtype structs, and the
+/// `Option`s along the way that are set, don't add anything to it.
+///
+/// Only fields that always take up the same number of bytes can be
+/// patched, like numbers and structs of them, since anything else could
+/// change the length of the message.  The message is only read as far as
+/// the end of the field, so a counter in a large record that is kept on
+/// disk can be bumped without decoding or writing the whole record.
+///
+/// ```rust,ignore
+/// let descriptor = bincode::schema::trace::<Record>().unwrap();
+/// bincode::schema::patch_field(&mut record, ".stats.reads", &(reads + 1), &descriptor).unwrap();
+/// ```
+pub fn patch_field<T>(bytes: &mut [u8], path: &str, value: &T, descriptor: &Descriptor) -> Result<(), PatchError>
+    where T: serde::Serialize,
+{
+    let (start, end, format) = try!(locate(bytes, path, descriptor));
+    if fixed_size(descriptor, format).is_none() {
+        return Err(PatchError::NotFixedSize(path.to_string()));
+    }
+    let encoded = try!(serialize(value, Infinite));
+    if encoded.len() != end - start || !has_layout(&encoded, format, descriptor) {
+        return Err(PatchError::WrongLayout(path.to_string()));
+    }
+
+    bytes[start..end].copy_from_slice(&encoded);
+    Ok(())
+}
+
+/// Finds the field `path` names in a message, and returns where it starts,
+/// where it ends, and its format.
+pub fn locate<'d>(bytes: &[u8], path: &str, descriptor: &'d Descriptor) -> Result<(usize, usize, &'d Format), PatchError> {
+    let segments = match parse_path(path) {
+        Some(segments) => segments,
+        None => return Err(PatchError::NoSuchField(path.to_string())),
+    };
+    let mut finder = Finder {
+        reader: ValueReader::new(bytes, descriptor),
+        path: path,
+        depth: 0,
+    };
+    let format = try!(finder.find(&descriptor.root, &segments));
+    let start = finder.reader.offset();
+    // Reading the old value checks that all of it is there.
+    try!(finder.reader.read(format));
+    Ok((start as usize, finder.reader.offset() as usize, format))
+}
+
+/// Returns the format of the field `path` names in the layout, whether or
+/// not any particular message has it.
+pub fn resolve<'d>(path: &str, descriptor: &'d Descriptor) -> Result<&'d Format, PatchError> {
+    let segments = match parse_path(path) {
+        Some(segments) => segments,
+        None => return Err(PatchError::NoSuchField(path.to_string())),
+    };
+    match resolve_in(descriptor, &descriptor.root, &segments, 0) {
+        Some(format) => Ok(format),
+        None => Err(PatchError::NoSuchField(path.to_string())),
+    }
+}
+
+fn resolve_in<'d>(descriptor: &'d Descriptor, format: &'d Format, segments: &[Segment], depth: usize) -> Option<&'d Format> {
+    let (segment, rest) = match segments.split_first() {
+        Some((&segment, rest)) => (segment, rest),
+        None => return Some(format),
+    };
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    let depth = depth + 1;
+    match (format, segment) {
+        (&Format::Option(ref format), _) => resolve_in(descriptor, format, segments, depth),
+        (&Format::Seq(ref format), Segment::Index(_)) |
+        (&Format::Array(_, ref format), Segment::Index(_)) => resolve_in(descriptor, format, rest, depth),
+        (&Format::Map(ref key, ref value), Segment::Index(_)) => {
+            match rest.split_first() {
+                Some((&Segment::Name("key"), rest)) => resolve_in(descriptor, key, rest, depth),
+                Some((&Segment::Name("value"), rest)) => resolve_in(descriptor, value, rest, depth),
+                _ => None,
+            }
+        }
+        (&Format::Tuple(ref formats), Segment::Name(name)) => {
+            element(formats, name).and_then(|format| resolve_in(descriptor, format, rest, depth))
+        }
+        (&Format::Named(ref type_name), _) => {
+            let (format, rest) = match (descriptor.types.get(type_name), segment) {
+                (Some(&Container::NewtypeStruct(ref format)), _) => (Some(format), segments),
+                (Some(&Container::TupleStruct(ref formats)), Segment::Name(name)) => (element(formats, name), rest),
+                (Some(&Container::Struct(ref fields)), Segment::Name(name)) => (field(fields, name), rest),
+                (Some(&Container::Enum(ref variants)), Segment::Variant(name)) => {
+                    let variant = match variants.iter().find(|variant| variant.name == name) {
+                        Some(variant) => variant,
+                        None => return None,
+                    };
+                    match (&variant.format, rest.split_first()) {
+                        (&VariantFormat::Newtype(ref format), _) => (Some(format), rest),
+                        (&VariantFormat::Tuple(ref formats), Some((&Segment::Name(name), rest))) =>
+                            (element(formats, name), rest),
+                        (&VariantFormat::Struct(ref fields), Some((&Segment::Name(name), rest))) =>
+                            (field(fields, name), rest),
+                        _ => (None, rest),
+                    }
+                }
+                _ => (None, rest),
+            };
+            format.and_then(|format| resolve_in(descriptor, format, rest, depth))
+        }
+        _ => None,
+    }
+}
+
+fn element<'d>(formats: &'d [Format], name: &str) -> Option<&'d Format> {
+    name.parse::<usize>().ok().and_then(|index| formats.get(index))
+}
+
+fn field<'d>(fields: &'d [Field], name: &str) -> Option<&'d Format> {
+    fields.iter().find(|field| field.name == name).map(|field| &field.format)
+}
+
+/// Returns whether `encoded` is exactly one value of `format`.
+pub fn has_layout(encoded: &[u8], format: &Format, descriptor: &Descriptor) -> bool {
+    let mut reader = ValueReader::new(encoded, descriptor);
+    reader.read(format).is_ok() && reader.offset() == encoded.len() as u64
+}
+
+#[derive(Clone, Copy)]
+enum Segment<'p> {
+    /// `.name`, or `.0` for an element of a tuple.
+    Name(&'p str),
+    /// `[3]`
+    Index(usize),
+    /// `::Name`
+    Variant(&'p str),
+}
+
+fn parse_path(path: &str) -> Option<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if rest.starts_with("::") {
+            let len = name_len(&rest[2..]);
+            if len == 0 {
+                return None;
+            }
+            segments.push(Segment::Variant(&rest[2..2 + len]));
+            rest = &rest[2 + len..];
+        } else if rest.starts_with('.') {
+            let len = name_len(&rest[1..]);
+            if len == 0 {
+                return None;
+            }
+            segments.push(Segment::Name(&rest[1..1 + len]));
+            rest = &rest[1 + len..];
+        } else if rest.starts_with('[') {
+            let close = match rest.find(']') {
+                Some(close) => close,
+                None => return None,
+            };
+            match rest[1..close].parse() {
+                Ok(index) => segments.push(Segment::Index(index)),
+                Err(_) => return None,
+            }
+            rest = &rest[close + 1..];
+        } else {
+            return None;
+        }
+    }
+    Some(segments)
+}
+
+fn name_len(rest: &str) -> usize {
+    rest.find(|c| c == '.' || c == '[' || c == ':').unwrap_or(rest.len())
+}
+
+/// Reads through a message up to the field a path names.
+struct Finder<'a, 'd> {
+    reader: ValueReader<'a, 'd>,
+    path: &'a str,
+    depth: usize,
+}
+
+impl<'a, 'd> Finder<'a, 'd> {
+    fn no_such_field(&self) -> PatchError {
+        PatchError::NoSuchField(self.path.to_string())
+    }
+
+    fn not_present(&self) -> PatchError {
+        PatchError::NotPresent(self.path.to_string())
+    }
+
+    /// Reads up to the value `segments` name inside a value of `format`,
+    /// and returns its format.
+    fn find(&mut self, format: &'d Format, segments: &[Segment]) -> Result<&'d Format, PatchError> {
+        let (segment, rest) = match segments.split_first() {
+            Some((&segment, rest)) => (segment, rest),
+            None => return Ok(format),
+        };
+        // Newtypes and `Option`s don't use up a segment, so a recursive
+        // type could otherwise be followed for as long as the input lasts.
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(PatchError::Deserialize(custom("a value is nested too deeply")));
+        }
+        match (format, segment) {
+            (&Format::Option(ref format), _) => {
+                let present: Option<()> = try!(self.reader.primitive());
+                match present {
+                    Some(()) => self.find(format, segments),
+                    None => Err(self.not_present()),
+                }
+            }
+            (&Format::Seq(ref format), Segment::Index(index)) |
+            (&Format::Array(_, ref format), Segment::Index(index)) => {
+                let len = try!(self.reader.read_len());
+                if index >= len {
+                    return Err(self.not_present());
+                }
+                for _ in 0..index {
+                    try!(self.reader.read(format));
+                }
+                self.find(format, rest)
+            }
+            (&Format::Map(ref key, ref value), Segment::Index(index)) => {
+                let len = try!(self.reader.read_len());
+                if index >= len {
+                    return Err(self.not_present());
+                }
+                for _ in 0..index {
+                    try!(self.reader.read(key));
+                    try!(self.reader.read(value));
+                }
+                match rest.split_first() {
+                    Some((&Segment::Name("key"), rest)) => self.find(key, rest),
+                    Some((&Segment::Name("value"), rest)) => {
+                        try!(self.reader.read(key));
+                        self.find(value, rest)
+                    }
+                    _ => Err(self.no_such_field()),
+                }
+            }
+            (&Format::Tuple(ref formats), Segment::Name(name)) => self.find_element(formats, name, rest),
+            (&Format::Named(ref type_name), _) => self.find_named(type_name, segment, segments),
+            _ => Err(self.no_such_field()),
+        }
+    }
+
+    fn find_element(&mut self, formats: &'d [Format], name: &str, rest: &[Segment]) -> Result<&'d Format, PatchError> {
+        let index = match name.parse::<usize>() {
+            Ok(index) if index < formats.len() => index,
+            _ => return Err(self.no_such_field()),
+        };
+        for format in &formats[..index] {
+            try!(self.reader.read(format));
+        }
+        self.find(&formats[index], rest)
+    }
+
+    fn find_field(&mut self, fields: &'d [Field], name: &str, rest: &[Segment]) -> Result<&'d Format, PatchError> {
+        let index = match fields.iter().position(|field| field.name == name) {
+            Some(index) => index,
+            None => return Err(self.no_such_field()),
+        };
+        for field in &fields[..index] {
+            try!(self.reader.read(&field.format));
+        }
+        self.find(&fields[index].format, rest)
+    }
+
+    fn find_named(&mut self, type_name: &str, segment: Segment, segments: &[Segment]) -> Result<&'d Format, PatchError> {
+        let rest = &segments[1..];
+        match (try!(self.reader.container(type_name)), segment) {
+            (&Container::NewtypeStruct(ref format), _) => self.find(format, segments),
+            (&Container::TupleStruct(ref formats), Segment::Name(name)) => self.find_element(formats, name, rest),
+            (&Container::Struct(ref fields), Segment::Name(name)) => self.find_field(fields, name, rest),
+            (&Container::Enum(ref variants), Segment::Variant(name)) => {
+                if !variants.iter().any(|variant| variant.name == name) {
+                    return Err(self.no_such_field());
+                }
+                let (_, variant) = try!(self.reader.read_tag(type_name, variants));
+                if variant.name != name {
+                    return Err(self.not_present());
+                }
+                match (&variant.format, rest.split_first()) {
+                    (&VariantFormat::Newtype(ref format), _) => self.find(format, rest),
+                    (&VariantFormat::Tuple(ref formats), Some((&Segment::Name(name), rest))) =>
+                        self.find_element(formats, name, rest),
+                    (&VariantFormat::Struct(ref fields), Some((&Segment::Name(name), rest))) =>
+                        self.find_field(fields, name, rest),
+                    _ => Err(self.no_such_field()),
+                }
+            }
+            _ => Err(self.no_such_field()),
+        }
+    }
+}
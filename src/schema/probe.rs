@@ -0,0 +1,80 @@
+use std::cmp;
+
+use ::serde::DeserializeError;
+use super::Descriptor;
+use super::value::{Value, ValueReader};
+
+/// How well a candidate layout fits a blob, as found by `probe`.
+#[derive(Debug)]
+pub struct ProbeResult {
+    /// The index of the candidate in the descriptors given to `probe`.
+    pub candidate: usize,
+    /// How plausible it is that the blob is of this layout, from 0 to 100.
+    ///
+    /// A candidate that reads a value out of the whole blob scores 100.
+    /// One that reads a value but leaves bytes over scores from 50 to 99,
+    /// and one that fails, on a tag that names no variant, invalid utf8, or
+    /// the end of the blob, scores below 50.  Within each band, the more of
+    /// the blob was read, the higher the score.
+    pub score: u32,
+    /// How many bytes were read, up to the end of the value or the point
+    /// where reading it failed.
+    pub consumed: u64,
+    /// The value, if one could be read.
+    pub value: Option<Value>,
+    /// Why no value could be read, if it couldn't.
+    pub error: Option<DeserializeError>,
+}
+
+/// Tries to read `bytes` with each of `candidates`, and ranks how plausible
+/// each of them is.
+///
+/// bincode doesn't write down the types of values, so a message whose type
+/// tag was lost, or a file that was written by some version of a program,
+/// can only be read by guessing.  This does the guessing for a list of
+/// layouts traced with `trace`, and returns a result for each, best first.
+/// Candidates that score the same are kept in the order they were given.
+///
+/// ```rust,ignore
+/// let candidates = [trace::<OrderV1>()?, trace::<OrderV2>()?, trace::<Refund>()?];
+/// let best = &bincode::probe(&blob, &candidates)[0];
+/// if best.score == 100 {
+///     println!("looks like candidate {}: {:?}", best.candidate, best.value);
+/// }
+/// ```
+pub fn probe(bytes: &[u8], candidates: &[Descriptor]) -> Vec<ProbeResult> {
+    let mut results: Vec<ProbeResult> = candidates.iter()
+        .enumerate()
+        .map(|(candidate, descriptor)| probe_one(bytes, candidate, descriptor))
+        .collect();
+    results.sort_by(|a, b| b.score.cmp(&a.score));
+    results
+}
+
+fn probe_one(bytes: &[u8], candidate: usize, descriptor: &Descriptor) -> ProbeResult {
+    let mut reader = ValueReader::new(bytes, descriptor);
+    let result = reader.read(&descriptor.root);
+    // A read that runs past the end is charged before it fails.
+    let consumed = cmp::min(reader.offset(), bytes.len() as u64);
+    let share = match bytes.len() {
+        0 => 0,
+        len => (consumed * 49 / len as u64) as u32,
+    };
+
+    match result {
+        Ok(value) => ProbeResult {
+            candidate: candidate,
+            score: if consumed == bytes.len() as u64 { 100 } else { 50 + share },
+            consumed: consumed,
+            value: Some(value),
+            error: None,
+        },
+        Err(err) => ProbeResult {
+            candidate: candidate,
+            score: share,
+            consumed: consumed,
+            value: None,
+            error: Some(err),
+        },
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt::Write;
+
+use super::{Descriptor, Format, Container, Field, Variant, VariantFormat};
+
+/// Renders a descriptor as text for people to read.
+///
+/// Every struct and enum is listed with its fields, each with its offset
+/// from the start of the struct and its width in bytes.  Where a field
+/// comes after one whose width depends on its value, like a string, the
+/// offset is the smallest it can be and is marked with `+`; widths are
+/// marked the same way.
+pub fn descriptor_to_text(descriptor: &Descriptor) -> String {
+    let sizer = Sizer { descriptor: descriptor };
+    let mut out = String::new();
+    let _ = writeln!(out, "# bincode, big-endian; lengths are u64, enum tags u32, option tags u8");
+    let _ = writeln!(out, "root: {} ({})", name(&descriptor.root), sizer.show(sizer.size(&descriptor.root, &mut Vec::new())));
+    for (type_name, container) in &descriptor.types {
+        out.push('\n');
+        match *container {
+            Container::UnitStruct => {
+                let _ = writeln!(out, "struct {}, no bytes", type_name);
+            }
+            Container::NewtypeStruct(ref format) => {
+                let _ = writeln!(out, "struct {}, written as {}", type_name, name(format));
+            }
+            Container::TupleStruct(ref formats) => {
+                let _ = writeln!(out, "struct {}", type_name);
+                sizer.write_formats(&mut out, formats, (0, true));
+            }
+            Container::Struct(ref fields) => {
+                let _ = writeln!(out, "struct {}", type_name);
+                sizer.write_fields(&mut out, fields, (0, true));
+            }
+            Container::Enum(ref variants) => {
+                let _ = writeln!(out, "enum {}, u32 tag at 0", type_name);
+                for (tag, variant) in variants.iter().enumerate() {
+                    sizer.write_variant(&mut out, tag, variant);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Returns the number of bytes `format` takes up, if it always takes up
+/// the same number.
+pub fn fixed_size(descriptor: &Descriptor, format: &Format) -> Option<usize> {
+    let sizer = Sizer { descriptor: descriptor };
+    match sizer.size(format, &mut Vec::new()) {
+        (size, true) => Some(size),
+        (_, false) => None,
+    }
+}
+
+const HEADER: &'static str = "    offset width  field\n";
+
+/// Writes a format the way it would be spelled in Rust, more or less.
+fn name(format: &Format) -> String {
+    match *format {
+        Format::Unit => "()".to_string(),
+        Format::Bool => "bool".to_string(),
+        Format::U8 => "u8".to_string(),
+        Format::U16 => "u16".to_string(),
+        Format::U32 => "u32".to_string(),
+        Format::U64 => "u64".to_string(),
+        Format::I8 => "i8".to_string(),
+        Format::I16 => "i16".to_string(),
+        Format::I32 => "i32".to_string(),
+        Format::I64 => "i64".to_string(),
+        Format::F32 => "f32".to_string(),
+        Format::F64 => "f64".to_string(),
+        Format::Char => "char (utf8)".to_string(),
+        Format::Str => "string (u64 length, utf8)".to_string(),
+        Format::Bytes => "bytes (u64 length)".to_string(),
+        Format::Option(ref inner) => format!("option<{}> (u8 tag)", name(inner)),
+        Format::Seq(ref element) => format!("seq<{}> (u64 length)", name(element)),
+        Format::Map(ref key, ref value) => format!("map<{}, {}> (u64 length)", name(key), name(value)),
+        Format::Tuple(ref elements) => {
+            let names: Vec<String> = elements.iter().map(name).collect();
+            format!("({})", names.join(", "))
+        }
+        Format::Array(size, ref element) => format!("[{}; {}] (u64 length)", name(element), size),
+        Format::Named(ref name) => name.clone(),
+    }
+}
+
+/// Works out how many bytes formats take up.
+struct Sizer<'a> {
+    descriptor: &'a Descriptor,
+}
+
+impl<'a> Sizer<'a> {
+    /// Returns the smallest number of bytes `format` can take up, and
+    /// whether it always takes up exactly that many.
+    fn size(&self, format: &'a Format, stack: &mut Vec<&'a str>) -> (usize, bool) {
+        match *format {
+            Format::Unit => (0, true),
+            Format::Bool | Format::U8 | Format::I8 => (1, true),
+            Format::U16 | Format::I16 => (2, true),
+            Format::U32 | Format::I32 | Format::F32 => (4, true),
+            Format::U64 | Format::I64 | Format::F64 => (8, true),
+            Format::Char => (1, false),
+            Format::Str | Format::Bytes | Format::Seq(_) | Format::Map(..) => (8, false),
+            Format::Option(_) => (1, false),
+            Format::Tuple(ref elements) => self.sum(elements, stack),
+            Format::Array(size, ref element) => {
+                let (min, exact) = self.size(element, stack);
+                (8 + size * min, exact)
+            }
+            Format::Named(ref type_name) => {
+                if stack.contains(&&type_name[..]) {
+                    return (0, false);
+                }
+                let container = match self.descriptor.types.get(type_name) {
+                    Some(container) => container,
+                    None => return (0, false),
+                };
+                stack.push(type_name);
+                let size = match *container {
+                    Container::UnitStruct => (0, true),
+                    Container::NewtypeStruct(ref format) => self.size(format, stack),
+                    Container::TupleStruct(ref formats) => self.sum(formats, stack),
+                    Container::Struct(ref fields) => self.sum_fields(fields, stack),
+                    Container::Enum(ref variants) => {
+                        let sizes: Vec<(usize, bool)> = variants.iter().map(|variant| {
+                            match variant.format {
+                                VariantFormat::Unit => (0, true),
+                                VariantFormat::Newtype(ref format) => self.size(format, stack),
+                                VariantFormat::Tuple(ref formats) => self.sum(formats, stack),
+                                VariantFormat::Struct(ref fields) => self.sum_fields(fields, stack),
+                            }
+                        }).collect();
+                        let min = sizes.iter().map(|&(min, _)| min).min().unwrap_or(0);
+                        let exact = sizes.iter().all(|&(size, exact)| exact && size == min);
+                        (4 + min, exact)
+                    }
+                };
+                stack.pop();
+                size
+            }
+        }
+    }
+
+    fn sum(&self, formats: &'a [Format], stack: &mut Vec<&'a str>) -> (usize, bool) {
+        formats.iter().fold((0, true), |(total, all_exact), format| {
+            let (min, exact) = self.size(format, stack);
+            (total + min, all_exact && exact)
+        })
+    }
+
+    fn sum_fields(&self, fields: &'a [Field], stack: &mut Vec<&'a str>) -> (usize, bool) {
+        fields.iter().fold((0, true), |(total, all_exact), field| {
+            let (min, exact) = self.size(&field.format, stack);
+            (total + min, all_exact && exact)
+        })
+    }
+
+    fn show(&self, (min, exact): (usize, bool)) -> String {
+        if exact {
+            format!("{}", min)
+        } else {
+            format!("{}+", min)
+        }
+    }
+
+    /// Writes one line per field, starting at `offset`.
+    fn write_fields(&self, out: &mut String, fields: &'a [Field], mut offset: (usize, bool)) {
+        out.push_str(HEADER);
+        for field in fields {
+            let size = self.size(&field.format, &mut Vec::new());
+            let _ = writeln!(out, "    {:>6} {:>5}  {}: {}", self.show(offset), self.show(size), field.name, name(&field.format));
+            offset = (offset.0 + size.0, offset.1 && size.1);
+        }
+    }
+
+    fn write_formats(&self, out: &mut String, formats: &'a [Format], mut offset: (usize, bool)) {
+        out.push_str(HEADER);
+        for (i, format) in formats.iter().enumerate() {
+            let size = self.size(format, &mut Vec::new());
+            let _ = writeln!(out, "    {:>6} {:>5}  {}: {}", self.show(offset), self.show(size), i, name(format));
+            offset = (offset.0 + size.0, offset.1 && size.1);
+        }
+    }
+
+    fn write_variant(&self, out: &mut String, tag: usize, variant: &'a Variant) {
+        let _ = writeln!(out, "  tag {}: {}", tag, variant.name);
+        match variant.format {
+            VariantFormat::Unit => {}
+            VariantFormat::Newtype(ref format) => self.write_formats(out, ::std::slice::from_ref(format), (4, true)),
+            VariantFormat::Tuple(ref formats) => self.write_formats(out, formats, (4, true)),
+            VariantFormat::Struct(ref fields) => self.write_fields(out, fields, (4, true)),
+        }
+    }
+}
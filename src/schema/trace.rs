@@ -0,0 +1,561 @@
+use std::collections::BTreeMap;
+
+use serde_crate as serde;
+use serde_crate::de::value::ValueDeserializer;
+
+use ::serde::{DeserializeError, DeserializeResult};
+use super::{Descriptor, Format, Container, Field, Variant, VariantFormat};
+
+/// How deeply nested containers may get before tracing gives up, which is
+/// what stops a type that recurses through the first variant of an enum.
+const MAX_DEPTH: usize = 128;
+
+/// The message of the error used internally to back out of a struct that is
+/// already being traced further up.
+const RECURSION: &'static str = "bincode schema tracer hit a recursive type";
+
+/// Works out the wire layout of `T`.
+///
+/// This deserializes `T` from a `Deserializer` that doesn't read any input,
+/// but instead writes down what it is asked for and hands back the empty
+/// value of each type.  Enums are deserialized once for every variant, so
+/// `T` is deserialized as many times as it takes to see all of them.
+///
+/// Types that deserialize differently depending on the input, or that
+/// reject the empty values, can't be traced.
+pub fn trace<T: serde::Deserialize>() -> DeserializeResult<Descriptor> {
+    let mut state = TraceState {
+        types: BTreeMap::new(),
+        enums: BTreeMap::new(),
+        in_progress: Vec::new(),
+    };
+
+    loop {
+        let (_, known_before) = state.variants();
+
+        let root = {
+            let mut tracer = Tracer::new(&mut state);
+            let _: T = try!(serde::Deserialize::deserialize(&mut tracer));
+            tracer.format.unwrap_or(Format::Unit)
+        };
+
+        let (missing, known) = state.variants();
+        if missing == 0 {
+            return Ok(state.finish(root));
+        }
+        if known == known_before {
+            return Err(custom("bincode could not trace every variant of an enum"));
+        }
+    }
+}
+
+struct TraceState {
+    types: BTreeMap<String, Container>,
+    enums: BTreeMap<&'static str, Vec<(&'static str, Option<VariantFormat>)>>,
+    in_progress: Vec<&'static str>,
+}
+
+impl TraceState {
+    /// Counts the enum variants that haven't been traced yet, and those
+    /// that have.
+    fn variants(&self) -> (usize, usize) {
+        let mut missing = 0;
+        let mut known = 0;
+        for &(_, ref format) in self.enums.values().flat_map(|variants| variants.iter()) {
+            if format.is_some() {
+                known += 1;
+            } else {
+                missing += 1;
+            }
+        }
+        (missing, known)
+    }
+
+    /// Picks the variant to trace next, preferring one that hasn't been
+    /// seen yet.
+    fn next_variant(&mut self, name: &'static str, variants: &'static [&'static str]) -> usize {
+        let known = self.enums.entry(name)
+            .or_insert_with(|| variants.iter().map(|&variant| (variant, None)).collect());
+        known.iter().position(|&(_, ref format)| format.is_none()).unwrap_or(0)
+    }
+
+    fn record_variant(&mut self, name: &'static str, index: usize, format: VariantFormat) {
+        if let Some(variants) = self.enums.get_mut(name) {
+            if let Some(&mut (_, ref mut slot)) = variants.get_mut(index) {
+                if slot.is_none() {
+                    *slot = Some(format);
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, name: &'static str) -> DeserializeResult<()> {
+        if self.in_progress.len() >= MAX_DEPTH {
+            return Err(custom("bincode could not trace a type that is nested too deeply"));
+        }
+        self.in_progress.push(name);
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.in_progress.pop();
+    }
+
+    fn is_in_progress(&self, name: &'static str) -> bool {
+        self.in_progress.contains(&name)
+    }
+
+    fn finish(self, root: Format) -> Descriptor {
+        let mut types = self.types;
+        for (name, variants) in self.enums {
+            let variants = variants.into_iter()
+                .map(|(variant, format)| Variant {
+                    name: variant.to_string(),
+                    format: format.unwrap_or(VariantFormat::Unit),
+                })
+                .collect();
+            types.insert(name.to_string(), Container::Enum(variants));
+        }
+        Descriptor {
+            root: root,
+            types: types,
+        }
+    }
+}
+
+fn custom(message: &str) -> DeserializeError {
+    DeserializeError::Serde(serde::de::value::Error::Custom(message.into()))
+}
+
+fn is_recursion(err: &DeserializeError) -> bool {
+    match *err {
+        DeserializeError::Serde(serde::de::value::Error::Custom(ref message)) => message == RECURSION,
+        _ => false,
+    }
+}
+
+/// Deserializes a single value, and writes down its format.
+struct Tracer<'a> {
+    state: &'a mut TraceState,
+    format: Option<Format>,
+}
+
+impl<'a> Tracer<'a> {
+    fn new(state: &'a mut TraceState) -> Tracer<'a> {
+        Tracer {
+            state: state,
+            format: None,
+        }
+    }
+
+    /// Deserializes `len` values one after the other, the way tuples and
+    /// struct fields are laid out, and returns their formats if all of them
+    /// could be traced.
+    fn trace_fields<V>(&mut self, len: usize, mut visitor: V) -> (DeserializeResult<V::Value>, Option<Vec<Format>>)
+        where V: serde::de::Visitor,
+    {
+        let mut formats = Vec::with_capacity(len);
+        let result = visitor.visit_seq(FieldsVisitor {
+            state: self.state,
+            remaining: len,
+            formats: &mut formats,
+        });
+        if formats.len() == len {
+            (result, Some(formats))
+        } else {
+            (result, None)
+        }
+    }
+
+    /// Backs out of a struct that is already being traced further up.
+    fn check_recursion(&mut self, name: &'static str) -> DeserializeResult<()> {
+        self.format = Some(Format::Named(name.to_string()));
+        if self.state.is_in_progress(name) {
+            Err(custom(RECURSION))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+macro_rules! trace_primitive {
+    ($dser_method:ident, $format:expr, $visitor_method:ident, $value:expr) => {
+        fn $dser_method<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+            where V: serde::de::Visitor,
+        {
+            self.format = Some($format);
+            visitor.$visitor_method($value)
+        }
+    }
+}
+
+impl<'a> serde::Deserializer for Tracer<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize<V>(&mut self, _visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        Err(custom("bincode can not trace a type that doesn't say what it expects"))
+    }
+
+    trace_primitive!(deserialize_bool, Format::Bool, visit_bool, false);
+    trace_primitive!(deserialize_u8, Format::U8, visit_u8, 0);
+    trace_primitive!(deserialize_u16, Format::U16, visit_u16, 0);
+    trace_primitive!(deserialize_u32, Format::U32, visit_u32, 0);
+    trace_primitive!(deserialize_u64, Format::U64, visit_u64, 0);
+    trace_primitive!(deserialize_usize, Format::U64, visit_usize, 0);
+    trace_primitive!(deserialize_i8, Format::I8, visit_i8, 0);
+    trace_primitive!(deserialize_i16, Format::I16, visit_i16, 0);
+    trace_primitive!(deserialize_i32, Format::I32, visit_i32, 0);
+    trace_primitive!(deserialize_i64, Format::I64, visit_i64, 0);
+    trace_primitive!(deserialize_isize, Format::I64, visit_isize, 0);
+    trace_primitive!(deserialize_f32, Format::F32, visit_f32, 0.0);
+    trace_primitive!(deserialize_f64, Format::F64, visit_f64, 0.0);
+    trace_primitive!(deserialize_char, Format::Char, visit_char, '\0');
+    trace_primitive!(deserialize_str, Format::Str, visit_str, "");
+    trace_primitive!(deserialize_string, Format::Str, visit_string, String::new());
+    trace_primitive!(deserialize_bytes, Format::Bytes, visit_bytes, &[]);
+
+    fn deserialize_unit<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.format = Some(Format::Unit);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let mut inner = Tracer::new(self.state);
+        let result = match visitor.visit_some(&mut inner) {
+            Err(ref err) if is_recursion(err) => visitor.visit_none(),
+            result => result,
+        };
+        if let Some(format) = inner.format {
+            self.format = Some(Format::Option(Box::new(format)));
+        }
+        result
+    }
+
+    fn deserialize_seq<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let mut element = None;
+        let result = visitor.visit_seq(ElementVisitor {
+            state: self.state,
+            done: false,
+            element: &mut element,
+        });
+        if let Some(element) = element {
+            self.format = Some(Format::Seq(Box::new(element)));
+        }
+        result
+    }
+
+    fn deserialize_fixed_size_array<V>(&mut self, len: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let (result, formats) = self.trace_fields(len, visitor);
+        if let Some(formats) = formats {
+            let element = formats.into_iter().next().unwrap_or(Format::Unit);
+            self.format = Some(Format::Array(len, Box::new(element)));
+        }
+        result
+    }
+
+    fn deserialize_map<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let mut key = None;
+        let mut value = None;
+        let result = visitor.visit_map(EntryVisitor {
+            state: self.state,
+            done: false,
+            key: &mut key,
+            value: &mut value,
+        });
+        if let (Some(key), Some(value)) = (key, value) {
+            self.format = Some(Format::Map(Box::new(key), Box::new(value)));
+        }
+        result
+    }
+
+    fn deserialize_tuple<V>(&mut self, len: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let (result, formats) = self.trace_fields(len, visitor);
+        if let Some(formats) = formats {
+            self.format = Some(Format::Tuple(formats));
+        }
+        result
+    }
+
+    fn deserialize_unit_struct<V>(&mut self, name: &'static str, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.format = Some(Format::Named(name.to_string()));
+        self.state.types.insert(name.to_string(), Container::UnitStruct);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(&mut self, name: &'static str, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        try!(self.check_recursion(name));
+        try!(self.state.enter(name));
+        let (result, format) = {
+            let mut inner = Tracer::new(self.state);
+            let result = visitor.visit_newtype_struct(&mut inner);
+            (result, inner.format)
+        };
+        self.state.leave();
+        if let Some(format) = format {
+            self.state.types.insert(name.to_string(), Container::NewtypeStruct(format));
+        }
+        result
+    }
+
+    fn deserialize_tuple_struct<V>(&mut self, name: &'static str, len: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        try!(self.check_recursion(name));
+        try!(self.state.enter(name));
+        let (result, formats) = self.trace_fields(len, visitor);
+        self.state.leave();
+        if let Some(formats) = formats {
+            self.state.types.insert(name.to_string(), Container::TupleStruct(formats));
+        }
+        result
+    }
+
+    fn deserialize_struct<V>(&mut self,
+                             name: &'static str,
+                             fields: &'static [&'static str],
+                             visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        try!(self.check_recursion(name));
+        try!(self.state.enter(name));
+        let (result, formats) = self.trace_fields(fields.len(), visitor);
+        self.state.leave();
+        if let Some(formats) = formats {
+            self.state.types.insert(name.to_string(), Container::Struct(named_fields(fields, formats)));
+        }
+        result
+    }
+
+    fn deserialize_enum<V>(&mut self,
+                           name: &'static str,
+                           variants: &'static [&'static str],
+                           mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::EnumVisitor,
+    {
+        self.format = Some(Format::Named(name.to_string()));
+
+        // Inside one of its own variants, an enum is given its first
+        // variant, which is the one that has to end the recursion.
+        let index = if self.state.is_in_progress(name) {
+            0
+        } else {
+            self.state.next_variant(name, variants)
+        };
+
+        try!(self.state.enter(name));
+        let result = match visitor.visit(VariantTracer { state: self.state, name: name, index: index }) {
+            Err(ref err) if is_recursion(err) && index != 0 => {
+                visitor.visit(VariantTracer { state: self.state, name: name, index: 0 })
+            }
+            result => result,
+        };
+        self.state.leave();
+        result
+    }
+}
+
+fn named_fields(names: &'static [&'static str], formats: Vec<Format>) -> Vec<Field> {
+    names.iter().zip(formats).map(|(name, format)| {
+        Field {
+            name: name.to_string(),
+            format: format,
+        }
+    }).collect()
+}
+
+/// Hands out exactly `remaining` traced values.
+struct FieldsVisitor<'a, 'b> {
+    state: &'a mut TraceState,
+    remaining: usize,
+    formats: &'b mut Vec<Format>,
+}
+
+impl<'a, 'b> serde::de::SeqVisitor for FieldsVisitor<'a, 'b> {
+    type Error = DeserializeError;
+
+    fn visit<T>(&mut self) -> DeserializeResult<Option<T>>
+        where T: serde::de::Deserialize,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        let mut tracer = Tracer::new(self.state);
+        let value = try!(serde::Deserialize::deserialize(&mut tracer));
+        self.formats.push(tracer.format.unwrap_or(Format::Unit));
+        Ok(Some(value))
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+}
+
+/// Hands out a single element, to find out the format of all of them.
+struct ElementVisitor<'a, 'b> {
+    state: &'a mut TraceState,
+    done: bool,
+    element: &'b mut Option<Format>,
+}
+
+impl<'a, 'b> serde::de::SeqVisitor for ElementVisitor<'a, 'b> {
+    type Error = DeserializeError;
+
+    fn visit<T>(&mut self) -> DeserializeResult<Option<T>>
+        where T: serde::de::Deserialize,
+    {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+
+        let mut tracer = Tracer::new(self.state);
+        let result = serde::Deserialize::deserialize(&mut tracer);
+        *self.element = tracer.format;
+        match result {
+            Ok(value) => Ok(Some(value)),
+            // The element type is already being traced further up, so leave
+            // the sequence empty.
+            Err(ref err) if is_recursion(err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+}
+
+/// Hands out a single entry, to find out the format of all of them.
+struct EntryVisitor<'a, 'b> {
+    state: &'a mut TraceState,
+    done: bool,
+    key: &'b mut Option<Format>,
+    value: &'b mut Option<Format>,
+}
+
+impl<'a, 'b> serde::de::MapVisitor for EntryVisitor<'a, 'b> {
+    type Error = DeserializeError;
+
+    fn visit<K, V>(&mut self) -> DeserializeResult<Option<(K, V)>>
+        where K: serde::de::Deserialize,
+              V: serde::de::Deserialize,
+    {
+        if self.done {
+            return Ok(None);
+        }
+        self.done = true;
+
+        let key = {
+            let mut tracer = Tracer::new(self.state);
+            let result = serde::Deserialize::deserialize(&mut tracer);
+            *self.key = tracer.format;
+            result
+        };
+        let value = {
+            let mut tracer = Tracer::new(self.state);
+            let result = serde::Deserialize::deserialize(&mut tracer);
+            *self.value = tracer.format;
+            result
+        };
+
+        match (key, value) {
+            (Ok(key), Ok(value)) => Ok(Some((key, value))),
+            (Err(ref err), _) | (_, Err(ref err)) if is_recursion(err) => Ok(None),
+            (Err(err), _) | (_, Err(err)) => Err(err),
+        }
+    }
+
+    fn visit_key<K>(&mut self) -> DeserializeResult<Option<K>>
+        where K: serde::de::Deserialize,
+    {
+        Err(custom("bincode can only trace maps that are read an entry at a time"))
+    }
+
+    fn visit_value<V>(&mut self) -> DeserializeResult<V>
+        where V: serde::de::Deserialize,
+    {
+        Err(custom("bincode can only trace maps that are read an entry at a time"))
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+}
+
+/// Traces the variant at `index` of the enum called `name`.
+struct VariantTracer<'a> {
+    state: &'a mut TraceState,
+    name: &'static str,
+    index: usize,
+}
+
+impl<'a> serde::de::VariantVisitor for VariantTracer<'a> {
+    type Error = DeserializeError;
+
+    fn visit_variant<V>(&mut self) -> DeserializeResult<V>
+        where V: serde::Deserialize,
+    {
+        let mut deserializer = self.index.into_deserializer();
+        let attempt: Result<V, serde::de::value::Error> = serde::Deserialize::deserialize(&mut deserializer);
+        Ok(try!(attempt))
+    }
+
+    fn visit_unit(&mut self) -> DeserializeResult<()> {
+        self.state.record_variant(self.name, self.index, VariantFormat::Unit);
+        Ok(())
+    }
+
+    fn visit_newtype<T>(&mut self) -> DeserializeResult<T>
+        where T: serde::de::Deserialize,
+    {
+        let (result, format) = {
+            let mut tracer = Tracer::new(self.state);
+            let result = serde::Deserialize::deserialize(&mut tracer);
+            (result, tracer.format)
+        };
+        if let Some(format) = format {
+            self.state.record_variant(self.name, self.index, VariantFormat::Newtype(format));
+        }
+        result
+    }
+
+    fn visit_tuple<V>(&mut self, len: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let (result, formats) = Tracer::new(self.state).trace_fields(len, visitor);
+        if let Some(formats) = formats {
+            self.state.record_variant(self.name, self.index, VariantFormat::Tuple(formats));
+        }
+        result
+    }
+
+    fn visit_struct<V>(&mut self, fields: &'static [&'static str], visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let (result, formats) = Tracer::new(self.state).trace_fields(fields.len(), visitor);
+        if let Some(formats) = formats {
+            self.state.record_variant(self.name, self.index, VariantFormat::Struct(named_fields(fields, formats)));
+        }
+        result
+    }
+}
@@ -0,0 +1,160 @@
+use std::marker::PhantomData;
+
+use serde_crate as serde;
+
+use ::{SizeLimit, Infinite};
+use ::serde::{serialize, deserialize, Deserializer, SliceReader, ByteBuf, Bytes};
+use ::serde::{SerializeResult, DeserializeResult, DeserializeError};
+use super::{trace, Descriptor};
+use super::patch::{PatchError, locate, resolve, has_layout};
+
+/// New values for some of the fields of a message of type `T`.
+///
+/// An update names each field by its path, the way `patch_field` does, and
+/// holds the new value for it, so a sender can say "set `.stats.reads` to
+/// 5" without having the old message, unlike `diff`, which needs both.  The
+/// receiver applies it to its own copy with `apply`.  Fields of any size can
+/// be set, strings and whole `Option`s included, and setting a field twice
+/// keeps the second value.
+///
+/// Every path and value is checked against the layout of `T` when it is
+/// set, and again when an update is read back, so an update that doesn't
+/// fit `T` is turned away before it touches anything.
+///
+/// ```rust,ignore
+/// let mut update = Update::<Account>::new().unwrap();
+/// update.set(".balance", &120u64).unwrap();
+/// update.set(".owner.email", &"new@example.com").unwrap();
+/// socket.write_all(&serialize_update(&update, Infinite).unwrap()).unwrap();
+///
+/// // On the other side:
+/// let update = deserialize_update::<Account>(&message).unwrap();
+/// update.apply(&mut account).unwrap();
+/// ```
+pub struct Update<T> {
+    descriptor: Descriptor,
+    fields: Vec<(String, Vec<u8>)>,
+    marker: PhantomData<T>,
+}
+
+impl<T: serde::Deserialize> Update<T> {
+    /// Traces `T` and creates an update that doesn't change anything.
+    pub fn new() -> DeserializeResult<Update<T>> {
+        Ok(Update {
+            descriptor: try!(trace::<T>()),
+            fields: Vec::new(),
+            marker: PhantomData,
+        })
+    }
+
+    /// Sets the field `path` names to `value`.
+    ///
+    /// If `path` isn't a field of `T`, or `value` isn't laid out the way the
+    /// field is, an error is returned and the update is left as it was.
+    pub fn set<V: serde::Serialize>(&mut self, path: &str, value: &V) -> Result<(), PatchError> {
+        let format = try!(resolve(path, &self.descriptor));
+        let encoded = try!(serialize(value, Infinite));
+        if !has_layout(&encoded, format, &self.descriptor) {
+            return Err(PatchError::WrongLayout(path.to_string()));
+        }
+        match self.fields.iter().position(|field| field.0 == path) {
+            Some(index) => self.fields[index].1 = encoded,
+            None => self.fields.push((path.to_string(), encoded)),
+        }
+        Ok(())
+    }
+
+    /// Returns the number of fields the update sets.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns whether the update doesn't set any fields.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Returns the paths of the fields the update sets, in the order they
+    /// were first set.
+    pub fn paths(&self) -> Vec<&str> {
+        self.fields.iter().map(|field| &field.0[..]).collect()
+    }
+
+    /// Applies the update to a serialized message of type `T`, and returns
+    /// the updated message.
+    ///
+    /// The fields are set in the order they were first set, so a path can
+    /// reach into a value that an earlier field replaced.  If a field isn't
+    /// in the message, like an element past the end of a sequence, a
+    /// `NotPresent` error is returned.
+    pub fn apply_to_bytes(&self, message: &[u8]) -> Result<Vec<u8>, PatchError> {
+        let mut message = message.to_vec();
+        for &(ref path, ref value) in &self.fields {
+            let (start, end, _) = try!(locate(&message, path, &self.descriptor));
+            message.splice(start..end, value.iter().cloned());
+        }
+        Ok(message)
+    }
+}
+
+impl<T: serde::Serialize + serde::Deserialize> Update<T> {
+    /// Applies the update to `value`.
+    ///
+    /// The value is serialized, updated with `apply_to_bytes`, and read
+    /// back.  If any of the fields can't be set, an error is returned and
+    /// `value` is left as it was.
+    pub fn apply(&self, value: &mut T) -> Result<(), PatchError> {
+        let message = try!(serialize(value, Infinite));
+        let message = try!(self.apply_to_bytes(&message));
+        *value = try!(deserialize(&message));
+        Ok(())
+    }
+}
+
+/// Serializes an update, with the fingerprint of the layout of `T` in
+/// front of it.
+///
+/// The fingerprint is followed by the fields as a `Vec<(String, Vec<u8>)>`
+/// of paths and serialized values.
+pub fn serialize_update<T, S>(update: &Update<T>, size_limit: S) -> SerializeResult<Vec<u8>>
+    where S: SizeLimit,
+{
+    let fields: Vec<(&str, Bytes)> = update.fields.iter()
+        .map(|&(ref path, ref value)| (&path[..], Bytes::from(value)))
+        .collect();
+    serialize(&(update.descriptor.fingerprint(), fields), size_limit)
+}
+
+/// Deserializes an update written by `serialize_update`.
+///
+/// If the fingerprint in front of it isn't that of `T`, nothing else is
+/// read and a `SchemaMismatch` error is returned.  A field that isn't one of
+/// `T`'s, or whose value isn't laid out like it, is a `NoSuchField` or
+/// `WrongLayout` error.
+pub fn deserialize_update<T>(bytes: &[u8]) -> Result<Update<T>, PatchError>
+    where T: serde::Deserialize,
+{
+    let mut update = try!(Update::<T>::new());
+    let mut deserializer = Deserializer::new(SliceReader::new(bytes), Infinite);
+    let expected = update.descriptor.fingerprint();
+    let found: u64 = try!(serde::Deserialize::deserialize(&mut deserializer));
+    if found != expected {
+        return Err(PatchError::Deserialize(DeserializeError::SchemaMismatch {
+            expected: expected,
+            found: found,
+        }));
+    }
+
+    let fields: Vec<(String, ByteBuf)> = try!(serde::Deserialize::deserialize(&mut deserializer));
+    for (path, value) in fields {
+        let value: Vec<u8> = value.into();
+        {
+            let format = try!(resolve(&path, &update.descriptor));
+            if !has_layout(&value, format, &update.descriptor) {
+                return Err(PatchError::WrongLayout(path));
+            }
+        }
+        update.fields.push((path, value));
+    }
+    Ok(update)
+}
@@ -0,0 +1,276 @@
+use std::cmp;
+
+use num_traits;
+use serde_crate as serde;
+use serde_crate::bytes::ByteBuf;
+
+use ::Infinite;
+use ::serde::{Deserializer, DeserializeError, DeserializeResult, SliceReader};
+use super::{Descriptor, Format, Container, Field, Variant, VariantFormat};
+
+/// How deeply nested a value may get, which stops a recursive type from
+/// running the stack out on malicious input.
+pub const MAX_DEPTH: usize = 256;
+
+/// Any value that bincode can encode, without its Rust type.
+///
+/// This is what `deserialize_value_with_schema` returns, for tools that
+/// need to look into messages of types they weren't compiled with.
+/// Numbers keep the width they were written with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A `()`, or a unit struct.
+    Unit,
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    Option(Option<Box<Value>>),
+    /// A sequence, a tuple, a fixed size array, or a tuple struct.
+    Seq(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    /// The fields of a struct, by name, in the order they were written.
+    Struct(Vec<(String, Value)>),
+    /// An enum variant.  The value is `Unit` for a unit variant, the field
+    /// for a newtype variant, a `Seq` for a tuple variant, and a `Struct`
+    /// for a struct variant.
+    Variant(u32, String, Box<Value>),
+}
+
+impl Value {
+    /// Looks up a field of a `Struct` by name.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match *self {
+            Value::Struct(ref fields) => fields.iter()
+                .find(|&&(ref field, _)| field == name)
+                .map(|&(_, ref value)| value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an unsigned integer of any width.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::U8(v) => Some(v as u64),
+            Value::U16(v) => Some(v as u64),
+            Value::U32(v) => Some(v as u64),
+            Value::U64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a signed integer of any width.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::I8(v) => Some(v as i64),
+            Value::I16(v) => Some(v as i64),
+            Value::I32(v) => Some(v as i64),
+            Value::I64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a float of either width.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::F32(v) => Some(v as f64),
+            Value::F64(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a `Str`.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a message of the type that `descriptor` was traced from, without
+/// that type.
+///
+/// bincode doesn't write down the types of values, so decoding needs the
+/// layout from `trace`, whether it was traced just now or read back from
+/// wherever it was stored.
+///
+/// ```rust,ignore
+/// let descriptor = bincode::schema::trace::<Order>().unwrap();
+/// let order = deserialize_value_with_schema(&bytes, &descriptor).unwrap();
+/// if order.field("quantity").and_then(Value::as_u64) > Some(100) {
+///     route_to_review(&bytes);
+/// }
+/// ```
+pub fn deserialize_value_with_schema(bytes: &[u8], descriptor: &Descriptor) -> DeserializeResult<Value> {
+    ValueReader::new(bytes, descriptor).read(&descriptor.root)
+}
+
+/// Reads values out of a message, following a `Descriptor`.
+pub struct ValueReader<'a, 'b> {
+    deserializer: Deserializer<SliceReader<'a>, Infinite>,
+    descriptor: &'b Descriptor,
+    depth: usize,
+}
+
+impl<'a, 'b> ValueReader<'a, 'b> {
+    pub fn new(bytes: &'a [u8], descriptor: &'b Descriptor) -> ValueReader<'a, 'b> {
+        ValueReader {
+            deserializer: Deserializer::new(SliceReader::new(bytes), Infinite),
+            descriptor: descriptor,
+            depth: 0,
+        }
+    }
+
+    /// Returns the offset of the next byte to be read.
+    pub fn offset(&self) -> u64 {
+        self.deserializer.bytes_read()
+    }
+
+    /// Reads a value of a type that is laid out the same way as `T`.
+    pub fn primitive<T: serde::Deserialize>(&mut self) -> DeserializeResult<T> {
+        serde::Deserialize::deserialize(&mut self.deserializer)
+    }
+
+    /// Reads a whole value.
+    pub fn read(&mut self, format: &Format) -> DeserializeResult<Value> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(custom("a value is nested too deeply"));
+        }
+        let value = try!(self.read_format(format));
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn read_format(&mut self, format: &Format) -> DeserializeResult<Value> {
+        Ok(match *format {
+            Format::Unit => Value::Unit,
+            Format::Bool => Value::Bool(try!(self.primitive())),
+            Format::U8 => Value::U8(try!(self.primitive())),
+            Format::U16 => Value::U16(try!(self.primitive())),
+            Format::U32 => Value::U32(try!(self.primitive())),
+            Format::U64 => Value::U64(try!(self.primitive())),
+            Format::I8 => Value::I8(try!(self.primitive())),
+            Format::I16 => Value::I16(try!(self.primitive())),
+            Format::I32 => Value::I32(try!(self.primitive())),
+            Format::I64 => Value::I64(try!(self.primitive())),
+            Format::F32 => Value::F32(try!(self.primitive())),
+            Format::F64 => Value::F64(try!(self.primitive())),
+            Format::Char => Value::Char(try!(self.primitive())),
+            Format::Str => Value::Str(try!(self.primitive())),
+            Format::Bytes => {
+                let bytes: ByteBuf = try!(self.primitive());
+                Value::Bytes(bytes.into())
+            }
+            Format::Option(ref format) => {
+                let present: Option<()> = try!(self.primitive());
+                match present {
+                    Some(()) => Value::Option(Some(Box::new(try!(self.read(format))))),
+                    None => Value::Option(None),
+                }
+            }
+            Format::Seq(ref format) | Format::Array(_, ref format) => {
+                let len = try!(self.read_len());
+                let mut values = Vec::with_capacity(cmp::min(len, 4096));
+                for _ in 0..len {
+                    values.push(try!(self.read(format)));
+                }
+                Value::Seq(values)
+            }
+            Format::Map(ref key, ref value) => {
+                let len = try!(self.read_len());
+                let mut entries = Vec::with_capacity(cmp::min(len, 4096));
+                for _ in 0..len {
+                    let k = try!(self.read(key));
+                    let v = try!(self.read(value));
+                    entries.push((k, v));
+                }
+                Value::Map(entries)
+            }
+            Format::Tuple(ref formats) => try!(self.read_tuple(formats)),
+            Format::Named(ref name) => try!(self.read_named(name)),
+        })
+    }
+
+    /// Reads the length in front of a sequence or map.
+    pub fn read_len(&mut self) -> DeserializeResult<usize> {
+        let len: u64 = try!(self.primitive());
+        match num_traits::cast(len) {
+            Some(len) => Ok(len),
+            None => Err(DeserializeError::LengthOverflow),
+        }
+    }
+
+    fn read_tuple(&mut self, formats: &[Format]) -> DeserializeResult<Value> {
+        let mut values = Vec::with_capacity(formats.len());
+        for format in formats {
+            values.push(try!(self.read(format)));
+        }
+        Ok(Value::Seq(values))
+    }
+
+    fn read_fields(&mut self, fields: &[Field]) -> DeserializeResult<Value> {
+        let mut values = Vec::with_capacity(fields.len());
+        for field in fields {
+            values.push((field.name.clone(), try!(self.read(&field.format))));
+        }
+        Ok(Value::Struct(values))
+    }
+
+    /// Looks up a struct or enum in the descriptor.
+    pub fn container(&self, name: &str) -> DeserializeResult<&'b Container> {
+        let descriptor = self.descriptor;
+        match descriptor.types.get(name) {
+            Some(container) => Ok(container),
+            None => Err(custom(&format!("the schema has no type named {}", name))),
+        }
+    }
+
+    /// Reads the tag of an enum, and returns the variant it stands for.
+    pub fn read_tag<'v>(&mut self, name: &str, variants: &'v [Variant]) -> DeserializeResult<(u32, &'v Variant)> {
+        let index: u32 = try!(self.primitive());
+        match variants.get(index as usize) {
+            Some(variant) => Ok((index, variant)),
+            None => Err(custom(&format!("{} has no variant {}", name, index))),
+        }
+    }
+
+    /// Reads the fields of an enum variant, after its tag.
+    pub fn read_variant(&mut self, variant: &Variant) -> DeserializeResult<Value> {
+        match variant.format {
+            VariantFormat::Unit => Ok(Value::Unit),
+            VariantFormat::Newtype(ref format) => self.read(format),
+            VariantFormat::Tuple(ref formats) => self.read_tuple(formats),
+            VariantFormat::Struct(ref fields) => self.read_fields(fields),
+        }
+    }
+
+    fn read_named(&mut self, name: &str) -> DeserializeResult<Value> {
+        match *try!(self.container(name)) {
+            Container::UnitStruct => Ok(Value::Unit),
+            Container::NewtypeStruct(ref format) => self.read(format),
+            Container::TupleStruct(ref formats) => self.read_tuple(formats),
+            Container::Struct(ref fields) => self.read_fields(fields),
+            Container::Enum(ref variants) => {
+                let (index, variant) = try!(self.read_tag(name, variants));
+                let value = try!(self.read_variant(variant));
+                Ok(Value::Variant(index, variant.name.clone(), Box::new(value)))
+            }
+        }
+    }
+}
+
+pub fn custom(message: &str) -> DeserializeError {
+    DeserializeError::Serde(serde::de::value::Error::Custom(message.into()))
+}
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Decides whether a `Deserializer` may allocate memory for what it reads.
+///
+/// A `SizeLimit` bounds the bytes of one message, but a server decoding many
+/// messages at once can still run out of memory when all of them are large.
+/// An `AllocGuard` is asked before memory is taken for the contents of a
+/// string or byte buffer, and for each element of a sequence or map, with
+/// the number of bytes it needs.  Refusing makes the `Deserializer` bail out
+/// with a `SizeLimit` error.
+///
+/// The sizes are those of the values as they are stored in memory, not as
+/// they are encoded, and don't include what the values point to, which is
+/// asked for separately when it is read.
+pub trait AllocGuard {
+    /// Returns `true` if `size` more bytes may be allocated.
+    fn allocate(&self, size: u64) -> bool;
+}
+
+/// Lets several `Deserializer`s share one guard.
+impl<G: AllocGuard + ?Sized> AllocGuard for Arc<G> {
+    fn allocate(&self, size: u64) -> bool {
+        (**self).allocate(size)
+    }
+}
+
+/// An `AllocGuard` that hands out memory from a fixed budget.
+///
+/// Put it in an `Arc` to share it between the decoders of several threads.
+/// The budget only ever shrinks as values are decoded; give memory back
+/// with `release` once the values it went into have been dropped.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    remaining: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `bytes` bytes.
+    pub fn new(bytes: usize) -> MemoryBudget {
+        MemoryBudget { remaining: AtomicUsize::new(bytes) }
+    }
+
+    /// Returns the number of bytes that are left.
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::SeqCst)
+    }
+
+    /// Puts `bytes` bytes back into the budget.
+    pub fn release(&self, bytes: usize) {
+        self.remaining.fetch_add(bytes, Ordering::SeqCst);
+    }
+}
+
+impl AllocGuard for MemoryBudget {
+    fn allocate(&self, size: u64) -> bool {
+        let mut remaining = self.remaining.load(Ordering::SeqCst);
+        loop {
+            if size > remaining as u64 {
+                return false;
+            }
+            let left = remaining - size as usize;
+            match self.remaining.compare_exchange(remaining, left, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return true,
+                Err(current) => remaining = current,
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::any;
+use std::cmp;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether a message was serialized or deserialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Serialized,
+    Deserialized,
+}
+
+/// What an `AuditSink` is told about a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// Whether the message was serialized or deserialized.
+    pub direction: Direction,
+    /// The name of the Rust type of the message, as `std::any::type_name`
+    /// gives it.
+    pub type_name: &'static str,
+    /// The number of bytes the message took up.
+    pub len: u64,
+    /// The 64-bit FNV-1a hash of those bytes.  It tells messages apart in
+    /// an audit log, but is not a cryptographic hash and doesn't prove that
+    /// a message wasn't tampered with.
+    pub hash: u64,
+}
+
+/// Receives a record of every message that goes through one of the
+/// `serialize` and `deserialize` functions, or a `Config`, once it has been
+/// serialized or deserialized successfully.
+///
+/// Install one with `set_audit_sink`.  It is called from whichever thread
+/// handled the message, so it has to be `Send` and `Sync`.  Any function or
+/// closure that takes an `&AuditRecord` will do.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F: Fn(&AuditRecord) + Send + Sync> AuditSink for F {
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SINK: RwLock<Option<Box<AuditSink>>> = RwLock::new(None);
+
+/// Sends a record of every message serialized or deserialized from now on,
+/// in any thread, to `sink`, replacing the sink that was there before.
+///
+/// This lets every message that crosses a trust boundary be logged without
+/// changing the code that sends and receives them.  Messages cost nothing
+/// extra to handle while no sink is installed.
+pub fn set_audit_sink<A: AuditSink + 'static>(sink: A) {
+    *SINK.write().unwrap_or_else(|err| err.into_inner()) = Some(Box::new(sink));
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Removes the sink installed by `set_audit_sink`.
+pub fn clear_audit_sink() {
+    ENABLED.store(false, Ordering::SeqCst);
+    *SINK.write().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Computes the FNV-1a hash of a message as it goes by.
+struct Hasher {
+    hash: u64,
+    len: u64,
+}
+
+impl Hasher {
+    fn new() -> Hasher {
+        Hasher { hash: 0xcbf29ce484222325, len: 0 }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+        self.len += bytes.len() as u64;
+    }
+
+    fn finish<T>(&self, direction: Direction) {
+        let record = AuditRecord {
+            direction: direction,
+            type_name: any::type_name::<T>(),
+            len: self.len,
+            hash: self.hash,
+        };
+        if let Some(ref sink) = *SINK.read().unwrap_or_else(|err| err.into_inner()) {
+            sink.record(&record);
+        }
+    }
+}
+
+/// Records a message that is already in memory.
+pub fn record<T>(direction: Direction, bytes: &[u8]) {
+    let mut hasher = Hasher::new();
+    hasher.update(bytes);
+    hasher.finish::<T>(direction);
+}
+
+/// Records a message made of the first `len` bytes of `pieces`, one after
+/// the other.
+pub fn record_pieces<'a, T, I>(direction: Direction, pieces: I, len: u64)
+    where I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut hasher = Hasher::new();
+    for piece in pieces {
+        let left = len - hasher.len;
+        if left == 0 {
+            break;
+        }
+        hasher.update(&piece[..cmp::min(piece.len() as u64, left) as usize]);
+    }
+    hasher.finish::<T>(direction);
+}
+
+/// A `Write` that hashes everything written through it.
+pub struct AuditWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> AuditWriter<W> {
+    pub fn new(inner: W) -> AuditWriter<W> {
+        AuditWriter { inner: inner, hasher: Hasher::new() }
+    }
+
+    pub fn finish<T>(&self) {
+        self.hasher.finish::<T>(Direction::Serialized)
+    }
+}
+
+impl<W: Write> Write for AuditWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = try!(self.inner.write(buf));
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Read` that hashes everything read through it, if a sink was
+/// installed when it was created.
+pub struct AuditReader<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+}
+
+impl<R: Read> AuditReader<R> {
+    pub fn new(inner: R) -> AuditReader<R> {
+        let hasher = if enabled() { Some(Hasher::new()) } else { None };
+        AuditReader { inner: inner, hasher: hasher }
+    }
+
+    pub fn finish<T>(&self) {
+        if let Some(ref hasher) = self.hasher {
+            hasher.finish::<T>(Direction::Deserialized)
+        }
+    }
+}
+
+impl<R: Read> Read for AuditReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = try!(self.inner.read(buf));
+        if let Some(ref mut hasher) = self.hasher {
+            hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for AuditReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Some(ref mut hasher) = self.hasher {
+            // The bytes being consumed are still at the start of the buffer,
+            // which `fill_buf` returns without reading when it isn't empty.
+            if let Ok(buf) = self.inner.fill_buf() {
+                hasher.update(&buf[..cmp::min(amt, buf.len())]);
+            }
+        }
+        self.inner.consume(amt)
+    }
+}
@@ -0,0 +1,102 @@
+use std::ops::Deref;
+
+use serde_crate as serde;
+
+macro_rules! bits_names {
+    ($($n:tt)*) => {
+        [$(concat!("bincode::Bits", stringify!($n))),*]
+    }
+}
+
+/// The names `Bits` gives its newtype struct, one for each width, which is
+/// how a `Serializer` or `Deserializer` doing bit packing learns the width.
+static NAMES: [&'static str; 65] = bits_names!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+    33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63 64
+);
+
+/// Returns the width of a `Bits` field from the name of its newtype struct,
+/// or `None` if it isn't one.
+pub fn width(name: &str) -> Option<u8> {
+    const PREFIX: &'static str = "bincode::Bits";
+    if name.starts_with(PREFIX) {
+        name[PREFIX.len()..].parse().ok()
+    } else {
+        None
+    }
+}
+
+/// An unsigned integer that takes up only `N` bits when bit packing is on.
+///
+/// With `Serializer::set_bit_packing`, consecutive bools and `Bits` fields
+/// are packed together into as few bytes as they fit in; see
+/// `serialize_bit_packed`.  Without it, a `Bits<T, N>` is written just like
+/// a `T`.  A value that doesn't fit in `N` bits can't be serialized.
+///
+/// `U1` through `U7` name the common widths:
+///
+/// ```rust,ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Reading {
+///     sensor: U4<u8>,
+///     channel: U3<u8>,
+///     calibrated: bool,
+///     value: u16,
+/// }
+/// ```
+///
+/// The first three fields of a `Reading` share a single byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Bits<T, const N: u8>(pub T);
+
+pub type U1<T> = Bits<T, 1>;
+pub type U2<T> = Bits<T, 2>;
+pub type U3<T> = Bits<T, 3>;
+pub type U4<T> = Bits<T, 4>;
+pub type U5<T> = Bits<T, 5>;
+pub type U6<T> = Bits<T, 6>;
+pub type U7<T> = Bits<T, 7>;
+
+impl<T, const N: u8> Bits<T, N> {
+    /// Unwraps the number.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, const N: u8> Deref for Bits<T, N> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, const N: u8> From<T> for Bits<T, N> {
+    fn from(value: T) -> Bits<T, N> {
+        Bits(value)
+    }
+}
+
+impl<T: serde::Serialize, const N: u8> serde::Serialize for Bits<T, N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_newtype_struct(NAMES[N as usize], &self.0)
+    }
+}
+
+impl<T: serde::Deserialize, const N: u8> serde::Deserialize for Bits<T, N> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Bits<T, N>, D::Error> {
+        struct BitsVisitor<T>(::std::marker::PhantomData<T>);
+
+        impl<T: serde::Deserialize> serde::de::Visitor for BitsVisitor<T> {
+            type Value = T;
+
+            fn visit_newtype_struct<D: serde::Deserializer>(&mut self, deserializer: &mut D) -> Result<T, D::Error> {
+                serde::Deserialize::deserialize(deserializer)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(NAMES[N as usize], BitsVisitor(::std::marker::PhantomData))
+            .map(Bits)
+    }
+}
@@ -0,0 +1,613 @@
+//! Encoding a `Vec` of structs one field at a time.
+//!
+//! A `Columnar<T>` holds rows like a `Vec<T>` does, but is encoded as
+//! columns: the first field of every row, then the second field of every
+//! row, and so on.  Values in a column tend to look alike, so the output
+//! compresses much better than row after row does, and a single column can
+//! be decoded with `ColumnarView` without touching the others.  Decoding a
+//! `Columnar<T>` puts the rows back together.
+//!
+//! `T` has to be a struct, a tuple struct, or a tuple; its fields can be
+//! anything.  A field that only ever takes a handful of values, like the
+//! type of an event, can be wrapped in a `LowCardinality` to have its column
+//! dictionary-encoded: every distinct value is stored once, and each row
+//! only refers to one of them.
+//!
+//! The layout is a `(u64, Vec<Column>)`: the number of rows, then the
+//! columns, each of which is one of
+//!
+//! ```rust,ignore
+//! enum Column {
+//!     // The bincode encoding of the field for every row in turn.
+//!     Plain(Vec<u8>),
+//!     // The bincode encoding of every distinct value, then the index of
+//!     // each row's value as a big-endian `u32`.
+//!     Dictionary((Vec<Vec<u8>>, Vec<u8>)),
+//! }
+//! ```
+//!
+//! ```rust,ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Sample { time: u64, sensor: LowCardinality<String>, value: f32 }
+//!
+//! let bytes = bincode::serde::serialize(&Columnar(samples), Infinite).unwrap();
+//! let view = ColumnarView::new(&bytes).unwrap();
+//! let values: Vec<f32> = view.column(2).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::io;
+use std::ops::Deref;
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use serde_crate as serde;
+use serde_crate::bytes::ByteBuf;
+
+use ::Infinite;
+use super::{serialize, serialize_into, SerializeResult, SerializeError};
+use super::{Deserializer, SliceReader, DeserializeResult, DeserializeError};
+
+/// A `Vec` of structs that is encoded column by column.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Columnar<T>(pub Vec<T>);
+
+impl<T> Columnar<T> {
+    /// Unwraps the `Vec`.
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> From<Vec<T>> for Columnar<T> {
+    fn from(rows: Vec<T>) -> Columnar<T> {
+        Columnar(rows)
+    }
+}
+
+impl<T: serde::Serialize> serde::Serialize for Columnar<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        let columns = match split_columns(&self.0) {
+            Ok(columns) => columns,
+            Err(err) => return Err(serde::ser::Error::custom(err.to_string())),
+        };
+        (self.0.len() as u64, columns).serialize(serializer)
+    }
+}
+
+impl<T: serde::Deserialize> serde::Deserialize for Columnar<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Columnar<T>, D::Error> {
+        let (rows, columns): (u64, Vec<Column>) = try!(serde::Deserialize::deserialize(deserializer));
+        let sources: Vec<Source> = columns.iter().map(Column::source).collect();
+        match join_rows(rows, sources) {
+            Ok(rows) => Ok(Columnar(rows)),
+            Err(err) => Err(serde::de::Error::custom(err.to_string())),
+        }
+    }
+}
+
+/// A field of a `Columnar` row that only takes a few distinct values.
+///
+/// Its column is dictionary-encoded.  Anywhere else, it is encoded exactly
+/// like the value it holds.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct LowCardinality<T>(pub T);
+
+const LOW_CARDINALITY: &'static str = "LowCardinality";
+
+impl<T> LowCardinality<T> {
+    /// Unwraps the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for LowCardinality<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for LowCardinality<T> {
+    fn from(value: T) -> LowCardinality<T> {
+        LowCardinality(value)
+    }
+}
+
+impl<T: serde::Serialize> serde::Serialize for LowCardinality<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_newtype_struct(LOW_CARDINALITY, &self.0)
+    }
+}
+
+impl<T: serde::Deserialize> serde::Deserialize for LowCardinality<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<LowCardinality<T>, D::Error> {
+        deserializer.deserialize_newtype_struct(LOW_CARDINALITY, LowCardinalityVisitor(::std::marker::PhantomData))
+    }
+}
+
+struct LowCardinalityVisitor<T>(::std::marker::PhantomData<T>);
+
+impl<T: serde::Deserialize> serde::de::Visitor for LowCardinalityVisitor<T> {
+    type Value = LowCardinality<T>;
+
+    fn visit_newtype_struct<D: serde::Deserializer>(&mut self, deserializer: &mut D)
+                                                    -> Result<LowCardinality<T>, D::Error> {
+        serde::Deserialize::deserialize(deserializer).map(LowCardinality)
+    }
+}
+
+/// Decodes single columns out of an encoded `Columnar`, without copying or
+/// decoding the others.
+pub struct ColumnarView<'a> {
+    rows: u64,
+    columns: Vec<Source<'a>>,
+}
+
+impl<'a> ColumnarView<'a> {
+    /// Reads the column boundaries of a `Columnar` encoded at the start of
+    /// `bytes`.
+    pub fn new(bytes: &'a [u8]) -> DeserializeResult<ColumnarView<'a>> {
+        let mut reader = bytes;
+        let rows = try!(reader.read_u64::<BigEndian>());
+        let count = try!(reader.read_u64::<BigEndian>());
+        let mut columns = Vec::new();
+        for _ in 0..count {
+            let column = match try!(reader.read_u32::<BigEndian>()) {
+                0 => Source::Plain(try!(split_bytes(&mut reader))),
+                1 => {
+                    let len = try!(reader.read_u64::<BigEndian>());
+                    let mut entries = Vec::new();
+                    for _ in 0..len {
+                        entries.push(try!(split_bytes(&mut reader)));
+                    }
+                    Source::Dictionary(entries, try!(split_bytes(&mut reader)))
+                }
+                tag => return Err(unknown_column(tag)),
+            };
+            columns.push(column);
+        }
+        Ok(ColumnarView {
+            rows: rows,
+            columns: columns,
+        })
+    }
+
+    /// Returns the number of rows.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Returns the number of columns, which is the number of fields in a
+    /// row.
+    pub fn columns(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Returns whether a column is dictionary-encoded.
+    pub fn is_dictionary(&self, index: usize) -> bool {
+        match self.columns.get(index) {
+            Some(&Source::Dictionary(..)) => true,
+            _ => false,
+        }
+    }
+
+    /// Decodes the values of one field, in row order.
+    pub fn column<F: serde::Deserialize>(&self, index: usize) -> DeserializeResult<Vec<F>> {
+        let mut column = match self.columns.get(index) {
+            Some(column) => column.clone(),
+            None => return Err(serde::de::Error::custom(format!("no column {}", index))),
+        };
+        let mut values = Vec::new();
+        for _ in 0..self.rows {
+            values.push(try!(column.next()));
+        }
+        Ok(values)
+    }
+}
+
+/// Takes a length-prefixed run of bytes off the front of `reader`.
+fn split_bytes<'a>(reader: &mut &'a [u8]) -> DeserializeResult<&'a [u8]> {
+    let len = try!(reader.read_u64::<BigEndian>());
+    if len > reader.len() as u64 {
+        return Err(DeserializeError::IoError(
+            io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of input")));
+    }
+    let (bytes, rest) = reader.split_at(len as usize);
+    *reader = rest;
+    Ok(bytes)
+}
+
+fn unknown_column(tag: u32) -> DeserializeError {
+    serde::de::Error::custom(format!("unknown kind of Columnar column {}", tag))
+}
+
+/// A column as it is serialized.
+enum Column {
+    Plain(ByteBuf),
+    Dictionary(Vec<ByteBuf>, ByteBuf),
+}
+
+impl Column {
+    fn source(&self) -> Source {
+        match *self {
+            Column::Plain(ref bytes) => Source::Plain(bytes),
+            Column::Dictionary(ref entries, ref indices) =>
+                Source::Dictionary(entries.iter().map(|entry| &entry[..]).collect(), indices),
+        }
+    }
+}
+
+impl serde::Serialize for Column {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        match *self {
+            Column::Plain(ref bytes) =>
+                serializer.serialize_newtype_variant("Column", 0, "Plain", bytes),
+            Column::Dictionary(ref entries, ref indices) =>
+                serializer.serialize_newtype_variant("Column", 1, "Dictionary", (entries, indices)),
+        }
+    }
+}
+
+impl serde::Deserialize for Column {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Column, D::Error> {
+        deserializer.deserialize_enum("Column", &["Plain", "Dictionary"], ColumnVisitor)
+    }
+}
+
+struct ColumnVisitor;
+
+impl serde::de::EnumVisitor for ColumnVisitor {
+    type Value = Column;
+
+    fn visit<V: serde::de::VariantVisitor>(&mut self, mut visitor: V) -> Result<Column, V::Error> {
+        let ColumnKind(tag) = try!(visitor.visit_variant());
+        match tag {
+            0 => visitor.visit_newtype().map(Column::Plain),
+            _ => {
+                let (entries, indices) = try!(visitor.visit_newtype());
+                Ok(Column::Dictionary(entries, indices))
+            }
+        }
+    }
+}
+
+/// The tag of a `Column`.
+struct ColumnKind(u32);
+
+impl serde::Deserialize for ColumnKind {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<ColumnKind, D::Error> {
+        deserializer.deserialize(ColumnKindVisitor)
+    }
+}
+
+struct ColumnKindVisitor;
+
+impl serde::de::Visitor for ColumnKindVisitor {
+    type Value = ColumnKind;
+
+    fn visit_usize<E: serde::de::Error>(&mut self, value: usize) -> Result<ColumnKind, E> {
+        match value {
+            0 | 1 => Ok(ColumnKind(value as u32)),
+            _ => Err(E::unknown_field(&value.to_string())),
+        }
+    }
+
+    fn visit_str<E: serde::de::Error>(&mut self, value: &str) -> Result<ColumnKind, E> {
+        match value {
+            "Plain" => Ok(ColumnKind(0)),
+            "Dictionary" => Ok(ColumnKind(1)),
+            _ => Err(E::unknown_field(value)),
+        }
+    }
+}
+
+/// The values of a column that have not been read yet.
+#[derive(Clone)]
+enum Source<'a> {
+    Plain(&'a [u8]),
+    Dictionary(Vec<&'a [u8]>, &'a [u8]),
+}
+
+impl<'a> Source<'a> {
+    /// Deserializes the next value in the column.
+    fn next<F: serde::Deserialize>(&mut self) -> DeserializeResult<F> {
+        match *self {
+            Source::Plain(ref mut bytes) => {
+                let mut deserializer = Deserializer::new(SliceReader::new(bytes), Infinite);
+                let value = try!(serde::Deserialize::deserialize(&mut deserializer));
+                *bytes = &bytes[deserializer.bytes_read() as usize..];
+                Ok(value)
+            }
+            Source::Dictionary(ref entries, ref mut indices) => {
+                let index = try!(indices.read_u32::<BigEndian>());
+                match entries.get(index as usize) {
+                    Some(entry) => super::deserialize(entry),
+                    None => Err(serde::de::Error::custom(
+                        format!("index {} is past the end of a Columnar dictionary", index))),
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match *self {
+            Source::Plain(bytes) => bytes.is_empty(),
+            Source::Dictionary(_, indices) => indices.is_empty(),
+        }
+    }
+}
+
+fn not_a_struct<E: serde::ser::Error>() -> E {
+    E::invalid_value("the rows of a Columnar must be structs or tuples")
+}
+
+/// Serializes every row, putting each of its fields in its own column.
+fn split_columns<T: serde::Serialize>(rows: &[T]) -> SerializeResult<Vec<Column>> {
+    let mut columns = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let mut splitter = Splitter {
+            columns: &mut columns,
+            index: 0,
+            first_row: i == 0,
+        };
+        try!(row.serialize(&mut splitter));
+        if splitter.index != splitter.columns.len() {
+            return Err(uneven_rows());
+        }
+    }
+    Ok(columns.into_iter().map(ColumnBuilder::finish).collect())
+}
+
+fn uneven_rows() -> SerializeError {
+    SerializeError::Custom("the rows of a Columnar have different numbers of fields".to_string())
+}
+
+/// A column that is being serialized.
+enum ColumnBuilder {
+    Plain(Vec<u8>),
+    Dictionary {
+        entries: Vec<Vec<u8>>,
+        lookup: HashMap<Vec<u8>, u32>,
+        indices: Vec<u8>,
+    },
+}
+
+impl ColumnBuilder {
+    fn push<V: serde::Serialize>(&mut self, value: &V) -> SerializeResult<()> {
+        match *self {
+            ColumnBuilder::Plain(ref mut bytes) => serialize_into(bytes, value, Infinite),
+            ColumnBuilder::Dictionary { ref mut entries, ref mut lookup, ref mut indices } => {
+                let entry = try!(serialize(value, Infinite));
+                let next = entries.len() as u32;
+                let index = *lookup.entry(entry.clone()).or_insert(next);
+                if index == next {
+                    entries.push(entry);
+                }
+                let mut buf = [0; 4];
+                BigEndian::write_u32(&mut buf, index);
+                indices.extend_from_slice(&buf);
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> Column {
+        match self {
+            ColumnBuilder::Plain(bytes) => Column::Plain(ByteBuf::from(bytes)),
+            ColumnBuilder::Dictionary { entries, indices, .. } =>
+                Column::Dictionary(entries.into_iter().map(ByteBuf::from).collect(), ByteBuf::from(indices)),
+        }
+    }
+}
+
+/// A `Serializer` that takes a struct apart into its fields.
+struct Splitter<'a> {
+    columns: &'a mut Vec<ColumnBuilder>,
+    index: usize,
+    first_row: bool,
+}
+
+impl<'a> Splitter<'a> {
+    fn field<V: serde::Serialize>(&mut self, value: V) -> SerializeResult<()> {
+        if self.index == self.columns.len() {
+            if !self.first_row {
+                return Err(uneven_rows());
+            }
+            let mut probe = Probe { low_cardinality: false };
+            try!(value.serialize(&mut probe));
+            self.columns.push(if probe.low_cardinality {
+                ColumnBuilder::Dictionary { entries: Vec::new(), lookup: HashMap::new(), indices: Vec::new() }
+            } else {
+                ColumnBuilder::Plain(Vec::new())
+            });
+        }
+        try!(self.columns[self.index].push(&value));
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl<'a> serde::Serializer for Splitter<'a> {
+    type Error = SerializeError;
+
+    fn serialize_struct<V: serde::ser::MapVisitor>(&mut self, _name: &'static str, mut visitor: V) -> SerializeResult<()> {
+        while let Some(()) = try!(visitor.visit(self)) {}
+        Ok(())
+    }
+
+    fn serialize_struct_elt<V: serde::Serialize>(&mut self, _key: &'static str, value: V) -> SerializeResult<()> {
+        self.field(value)
+    }
+
+    fn serialize_tuple<V: serde::ser::SeqVisitor>(&mut self, mut visitor: V) -> SerializeResult<()> {
+        while let Some(()) = try!(visitor.visit(self)) {}
+        Ok(())
+    }
+
+    fn serialize_tuple_elt<V: serde::Serialize>(&mut self, value: V) -> SerializeResult<()> {
+        self.field(value)
+    }
+
+    fn serialize_newtype_variant<T: serde::Serialize>(&mut self, _: &'static str, _: usize, _: &'static str, _: T)
+                                                      -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_tuple_variant<V: serde::ser::SeqVisitor>(&mut self, _: &'static str, _: usize, _: &'static str, _: V)
+                                                          -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_struct_variant<V: serde::ser::MapVisitor>(&mut self, _: &'static str, _: usize, _: &'static str, _: V)
+                                                           -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_bool(&mut self, _: bool) -> SerializeResult<()> { Err(not_a_struct()) }
+    fn serialize_i64(&mut self, _: i64) -> SerializeResult<()> { Err(not_a_struct()) }
+    fn serialize_u64(&mut self, _: u64) -> SerializeResult<()> { Err(not_a_struct()) }
+    fn serialize_f64(&mut self, _: f64) -> SerializeResult<()> { Err(not_a_struct()) }
+    fn serialize_str(&mut self, _: &str) -> SerializeResult<()> { Err(not_a_struct()) }
+    fn serialize_unit(&mut self) -> SerializeResult<()> { Err(not_a_struct()) }
+    fn serialize_none(&mut self) -> SerializeResult<()> { Err(not_a_struct()) }
+
+    fn serialize_some<V: serde::Serialize>(&mut self, _: V) -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_seq<V: serde::ser::SeqVisitor>(&mut self, _: V) -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_seq_elt<V: serde::Serialize>(&mut self, _: V) -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_map<V: serde::ser::MapVisitor>(&mut self, _: V) -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+
+    fn serialize_map_elt<K: serde::Serialize, V: serde::Serialize>(&mut self, _: K, _: V) -> SerializeResult<()> {
+        Err(not_a_struct())
+    }
+}
+
+/// A `Serializer` that only checks whether a value is a `LowCardinality`.
+struct Probe {
+    low_cardinality: bool,
+}
+
+impl serde::Serializer for Probe {
+    type Error = SerializeError;
+
+    fn serialize_newtype_struct<T: serde::Serialize>(&mut self, name: &'static str, _: T) -> SerializeResult<()> {
+        self.low_cardinality = name == LOW_CARDINALITY;
+        Ok(())
+    }
+
+    fn serialize_bool(&mut self, _: bool) -> SerializeResult<()> { Ok(()) }
+    fn serialize_i64(&mut self, _: i64) -> SerializeResult<()> { Ok(()) }
+    fn serialize_u64(&mut self, _: u64) -> SerializeResult<()> { Ok(()) }
+    fn serialize_f64(&mut self, _: f64) -> SerializeResult<()> { Ok(()) }
+    fn serialize_str(&mut self, _: &str) -> SerializeResult<()> { Ok(()) }
+    fn serialize_unit(&mut self) -> SerializeResult<()> { Ok(()) }
+    fn serialize_none(&mut self) -> SerializeResult<()> { Ok(()) }
+
+    fn serialize_some<V: serde::Serialize>(&mut self, _: V) -> SerializeResult<()> {
+        Ok(())
+    }
+
+    fn serialize_seq<V: serde::ser::SeqVisitor>(&mut self, _: V) -> SerializeResult<()> {
+        Ok(())
+    }
+
+    fn serialize_seq_elt<V: serde::Serialize>(&mut self, _: V) -> SerializeResult<()> {
+        Ok(())
+    }
+
+    fn serialize_map<V: serde::ser::MapVisitor>(&mut self, _: V) -> SerializeResult<()> {
+        Ok(())
+    }
+
+    fn serialize_map_elt<K: serde::Serialize, V: serde::Serialize>(&mut self, _: K, _: V) -> SerializeResult<()> {
+        Ok(())
+    }
+}
+
+/// Deserializes `rows` rows, taking each field from its own column.
+fn join_rows<T: serde::Deserialize>(rows: u64, mut columns: Vec<Source>) -> DeserializeResult<Vec<T>> {
+    let mut values = Vec::with_capacity(::std::cmp::min(rows, 4096) as usize);
+    for _ in 0..rows {
+        values.push(try!(serde::Deserialize::deserialize(&mut Joiner { columns: &mut columns })));
+    }
+    if columns.iter().any(|column| !column.is_empty()) {
+        return Err(serde::de::Error::custom("a column of a Columnar has bytes left over"));
+    }
+    Ok(values)
+}
+
+/// A `Deserializer` that puts a struct together out of columns.
+struct Joiner<'a, 'b: 'a> {
+    columns: &'a mut Vec<Source<'b>>,
+}
+
+impl<'a, 'b> serde::Deserializer for Joiner<'a, 'b> {
+    type Error = DeserializeError;
+
+    fn deserialize<V: serde::de::Visitor>(&mut self, _: V) -> DeserializeResult<V::Value> {
+        Err(serde::de::Error::custom("the rows of a Columnar must be structs or tuples"))
+    }
+
+    fn deserialize_struct<V: serde::de::Visitor>(&mut self,
+                                                 _name: &'static str,
+                                                 _fields: &'static [&'static str],
+                                                 mut visitor: V) -> DeserializeResult<V::Value> {
+        visitor.visit_seq(Fields { columns: self.columns, index: 0 })
+    }
+
+    fn deserialize_tuple_struct<V: serde::de::Visitor>(&mut self,
+                                                       _name: &'static str,
+                                                       _len: usize,
+                                                       mut visitor: V) -> DeserializeResult<V::Value> {
+        visitor.visit_seq(Fields { columns: self.columns, index: 0 })
+    }
+
+    fn deserialize_tuple<V: serde::de::Visitor>(&mut self, _len: usize, mut visitor: V) -> DeserializeResult<V::Value> {
+        visitor.visit_seq(Fields { columns: self.columns, index: 0 })
+    }
+}
+
+/// Hands out the next value of each column in turn, as the fields of one
+/// row.
+struct Fields<'a, 'b: 'a> {
+    columns: &'a mut Vec<Source<'b>>,
+    index: usize,
+}
+
+impl<'a, 'b> serde::de::SeqVisitor for Fields<'a, 'b> {
+    type Error = DeserializeError;
+
+    fn visit<F: serde::Deserialize>(&mut self) -> DeserializeResult<Option<F>> {
+        let value = match self.columns.get_mut(self.index) {
+            Some(column) => try!(column.next()),
+            None => return Ok(None),
+        };
+        self.index += 1;
+        Ok(Some(value))
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        if self.index == self.columns.len() {
+            Ok(())
+        } else {
+            Err(serde::de::Error::custom("a Columnar has more columns than its rows have fields"))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.columns.len() - self.index;
+        (left, Some(left))
+    }
+}
@@ -0,0 +1,252 @@
+use std::io::{Read, Write};
+
+use serde_crate as serde;
+
+use ::{SizeLimit, Infinite, Bounded};
+pub use ::format::{Layout, StaticLayout, StandardLayout, CompactLayout, LegacyLayout, FixedLayout};
+use super::read::BincodeRead;
+use super::{serialize_audited, deserialize_audited, deserialize_from_audited};
+use super::reader::{Deserializer, DeserializeResult};
+use super::writer::{Serializer, SerializeResult, SerializeError};
+
+/// A named combination of the settings of a `Serializer` and a
+/// `Deserializer`, along with a size limit.
+///
+/// Rather than every program turning the same knobs the same way, pick one
+/// of the presets, and write and read with it:
+///
+/// * `standard` is the plain bincode layout, byte for byte what `serialize`
+///   writes.
+/// * `compact` packs bools and `Bits` fields into bits.
+/// * `legacy` writes chars as a string of one char, the way older versions
+///   of bincode did, so their readers can read the output and their output
+///   can be read.
+/// * `fixed` writes fixed-size arrays without their length, the layout of
+///   `serialize_c_struct`.
+///
+/// Every preset writes big-endian integers, `u64` lengths, `u32` enum tags
+/// and a `u8` in front of an `Option`.  Those can't be configured; use `Le`
+/// and `Var` on the fields that need something else.  The methods of
+/// `Layout` give the width of each part of a message in a config's layout.
+///
+/// # Stability
+///
+/// The settings of a preset never change.  A value written with a preset
+/// by one version of bincode is written to the same bytes by every later
+/// version, and can be read back with the same preset.  A new combination
+/// of settings gets a new preset instead.  The size limit is not part of
+/// the layout, and can be set on any preset with `limit`.
+///
+/// ```rust,ignore
+/// let config = Config::compact().limit(1 << 20);
+/// let bytes = config.serialize(&status)?;
+/// let status: Status = config.deserialize(&bytes)?;
+/// ```
+///
+/// A `Config` is looked at as the value is written or read, which suits a
+/// layout that is only known at run time, say from a handshake.  When it
+/// is known up front, use the `Layout` type of the preset instead, which
+/// makes it part of the type of the `Serializer` or `Deserializer`, so
+/// that the checks compile away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    bit_packing: bool,
+    legacy_chars: bool,
+    fixed_arrays: bool,
+    human_readable: bool,
+    limit: Option<u64>,
+}
+
+impl Config {
+    /// The plain bincode layout, the same as `serialize` and `deserialize`.
+    pub fn standard() -> Config {
+        Config {
+            bit_packing: false,
+            legacy_chars: false,
+            fixed_arrays: false,
+            human_readable: false,
+            limit: None,
+        }
+    }
+
+    /// The plain layout with bools and `Bits` fields packed into bits, the
+    /// same as `serialize_bit_packed` and `deserialize_bit_packed`.
+    pub fn compact() -> Config {
+        Config { bit_packing: true, ..Config::standard() }
+    }
+
+    /// The plain layout with chars written as a string of one char, with a
+    /// `u64` length in front.
+    ///
+    /// This is what the `serde` frontend of older versions of bincode wrote,
+    /// and what they expect to read.
+    pub fn legacy() -> Config {
+        Config { legacy_chars: true, ..Config::standard() }
+    }
+
+    /// The plain layout with fixed-size arrays written without their
+    /// length, the same as `serialize_c_struct` and `deserialize_c_struct`.
+    pub fn fixed() -> Config {
+        Config { fixed_arrays: true, ..Config::standard() }
+    }
+
+    /// Limits how many bytes a value can take, both when it is written and
+    /// when it is read.
+    pub fn limit(self, bytes: u64) -> Config {
+        Config { limit: Some(bytes), ..self }
+    }
+
+    /// Returns the size limit, if there is one.
+    pub fn size_limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// Controls whether bools and `Bits` fields are packed into bits.  See
+    /// `Serializer::set_bit_packing`.
+    pub fn set_bit_packing(&mut self, packing: bool) {
+        self.bit_packing = packing;
+    }
+
+    /// Controls whether chars are written as a string of one char.  See
+    /// `Serializer::set_legacy_chars`.
+    pub fn set_legacy_chars(&mut self, legacy: bool) {
+        self.legacy_chars = legacy;
+    }
+
+    /// Controls whether fixed-size arrays are written without their
+    /// length.  See `Serializer::set_fixed_arrays`.
+    pub fn set_fixed_arrays(&mut self, fixed: bool) {
+        self.fixed_arrays = fixed;
+    }
+
+    /// Controls whether the `Serializer` and `Deserializer` report that
+    /// the format is human-readable.  See `Serializer::is_human_readable`.
+    ///
+    /// This doesn't change what bincode writes.  serde 0.7's traits have no
+    /// way to pass the flag on, so a `Serialize` or `Deserialize`
+    /// implementation that is generic over the `Serializer` never sees it;
+    /// only code that holds the bincode `Serializer` or `Deserializer`
+    /// itself, and asks `is_human_readable`, can act on it.
+    pub fn set_human_readable(&mut self, human_readable: bool) {
+        self.human_readable = human_readable;
+    }
+
+    /// Applies the layout of this config to `serializer`.
+    pub fn configure_serializer<W: Write + ?Sized>(&self, serializer: &mut Serializer<W>) {
+        serializer.set_bit_packing(self.bit_packing);
+        serializer.set_legacy_chars(self.legacy_chars);
+        serializer.set_fixed_arrays(self.fixed_arrays);
+        serializer.set_human_readable(self.human_readable);
+    }
+
+    /// Applies the layout of this config to `deserializer`.  Its size
+    /// limit is the one it was created with.
+    pub fn configure_deserializer<R: BincodeRead, S: SizeLimit>(&self, deserializer: &mut Deserializer<R, S>) {
+        deserializer.set_bit_packing(self.bit_packing);
+        deserializer.set_legacy_chars(self.legacy_chars);
+        deserializer.set_fixed_arrays(self.fixed_arrays);
+        deserializer.set_human_readable(self.human_readable);
+    }
+
+    /// Serializes an object into a `Vec` of bytes.
+    ///
+    /// If it takes more bytes than the limit, a `SizeLimit` error is
+    /// returned.
+    pub fn serialize<T: serde::Serialize>(&self, value: &T) -> SerializeResult<Vec<u8>> {
+        match self.limit {
+            Some(limit) => serialize_with_layout(value, *self, Bounded(limit)),
+            None => serialize_with_layout(value, *self, Infinite),
+        }
+    }
+
+    /// Serializes an object directly into a `Writer`.
+    ///
+    /// If it takes more bytes than the limit, a `SizeLimit` error is
+    /// returned and *no bytes* are written.
+    pub fn serialize_into<W, T>(&self, writer: &mut W, value: &T) -> SerializeResult<()>
+        where W: Write, T: serde::Serialize,
+    {
+        let bytes = try!(self.serialize(value));
+        writer.write_all(&bytes).map_err(SerializeError::IoError)
+    }
+
+    /// Deserializes an object from a slice of bytes.
+    pub fn deserialize<T: serde::Deserialize>(&self, bytes: &[u8]) -> DeserializeResult<T> {
+        let read = match self.limit {
+            Some(limit) => deserialize_audited(bytes, Bounded(limit), *self, |_| {}),
+            None => deserialize_audited(bytes, Infinite, *self, |_| {}),
+        };
+        read.map(|(value, _)| value)
+    }
+
+    /// Deserializes an object directly from a `Reader`.
+    pub fn deserialize_from<R, T>(&self, reader: &mut R) -> DeserializeResult<T>
+        where R: Read, T: serde::Deserialize,
+    {
+        match self.limit {
+            Some(limit) => deserialize_from_audited(reader, Bounded(limit), *self, |_| {}),
+            None => deserialize_from_audited(reader, Infinite, *self, |_| {}),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::standard()
+    }
+}
+
+impl Layout for Config {
+    fn bit_packing(&self) -> bool {
+        self.bit_packing
+    }
+
+    fn legacy_chars(&self) -> bool {
+        self.legacy_chars
+    }
+
+    fn fixed_arrays(&self) -> bool {
+        self.fixed_arrays
+    }
+
+    fn human_readable(&self) -> bool {
+        self.human_readable
+    }
+}
+
+macro_rules! from_static_layouts {
+    ($($name:ident => $preset:ident,)*) => {
+        $(
+            impl From<$name> for Config {
+                fn from(_: $name) -> Config {
+                    Config::$preset()
+                }
+            }
+        )*
+    }
+}
+
+from_static_layouts! {
+    StandardLayout => standard,
+    CompactLayout => compact,
+    LegacyLayout => legacy,
+    FixedLayout => fixed,
+}
+
+/// Serializes an object into a `Vec` of bytes with the given layout.
+///
+/// If it takes more bytes than `size_limit`, a `SizeLimit` error is
+/// returned.
+pub fn serialize_with_layout<T, L, S>(value: &T, layout: L, size_limit: S) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize, L: Layout, S: SizeLimit,
+{
+    serialize_audited(value, size_limit, layout, |_| {})
+}
+
+/// Deserializes an object written with the given layout from a slice of
+/// bytes.
+pub fn deserialize_with_layout<T, L>(bytes: &[u8], layout: L) -> DeserializeResult<T>
+    where T: serde::Deserialize, L: Layout,
+{
+    deserialize_audited(bytes, Infinite, layout, |_| {}).map(|(value, _)| value)
+}
@@ -0,0 +1,282 @@
+use serde_crate as serde;
+use serde_crate::de::value::ValueDeserializer;
+
+use super::reader::{DeserializeError, DeserializeResult};
+
+/// A `Deserializer` that doesn't read anything and instead hands every
+/// visitor the "empty" value of its type: `0`, `false`, `""`, `None`, an
+/// empty collection, or the first variant of an enum.
+///
+/// This is what fills in the fields that an older writer didn't know about
+/// when the `Deserializer` is told to fill missing fields.
+pub struct DefaultDeserializer;
+
+impl serde::Deserializer for DefaultDeserializer {
+    type Error = DeserializeError;
+
+    fn deserialize<V>(&mut self, _visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let message = "bincode can not fill in a missing field of an unknown type";
+        Err(DeserializeError::Serde(serde::de::value::Error::Custom(message.into())))
+    }
+
+    fn deserialize_bool<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_usize<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_usize(0)
+    }
+
+    fn deserialize_u8<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_u16<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_u16(0)
+    }
+
+    fn deserialize_u32<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_u64<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_isize<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_isize(0)
+    }
+
+    fn deserialize_i8<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_i8(0)
+    }
+
+    fn deserialize_i16<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_i16(0)
+    }
+
+    fn deserialize_i32<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_f32<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_f32(0.0)
+    }
+
+    fn deserialize_f64<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_char<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_char('\0')
+    }
+
+    fn deserialize_str<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_string(String::new())
+    }
+
+    fn deserialize_bytes<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_bytes(&[])
+    }
+
+    fn deserialize_unit<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(&mut self,
+                                  _name: &'static str,
+                                  mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_none()
+    }
+
+    fn deserialize_seq<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_seq(DefaultSeqVisitor { len: 0 })
+    }
+
+    fn deserialize_map<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_map(DefaultSeqVisitor { len: 0 })
+    }
+
+    fn deserialize_fixed_size_array<V>(&mut self,
+                                       len: usize,
+                                       mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_seq(DefaultSeqVisitor { len: len })
+    }
+
+    fn deserialize_tuple<V>(&mut self,
+                            len: usize,
+                            mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_seq(DefaultSeqVisitor { len: len })
+    }
+
+    fn deserialize_struct<V>(&mut self,
+                             _name: &'static str,
+                             fields: &'static [&'static str],
+                             mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_seq(DefaultSeqVisitor { len: fields.len() })
+    }
+
+    fn deserialize_newtype_struct<V>(&mut self,
+                                     _name: &'static str,
+                                     mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(&mut self,
+                           _enum: &'static str,
+                           _variants: &'static [&'static str],
+                           mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::EnumVisitor,
+    {
+        visitor.visit(DefaultDeserializer)
+    }
+}
+
+impl serde::de::VariantVisitor for DefaultDeserializer {
+    type Error = DeserializeError;
+
+    fn visit_variant<V>(&mut self) -> DeserializeResult<V>
+        where V: serde::Deserialize,
+    {
+        let mut deserializer = 0usize.into_deserializer();
+        let attempt: Result<V, serde::de::value::Error> = serde::Deserialize::deserialize(&mut deserializer);
+        Ok(try!(attempt))
+    }
+
+    fn visit_unit(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+
+    fn visit_newtype<T>(&mut self) -> DeserializeResult<T>
+        where T: serde::de::Deserialize,
+    {
+        serde::de::Deserialize::deserialize(self)
+    }
+
+    fn visit_tuple<V>(&mut self,
+                      len: usize,
+                      visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        serde::de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn visit_struct<V>(&mut self,
+                       fields: &'static [&'static str],
+                       visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        serde::de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+/// Hands out `len` default elements, or nothing at all for maps.
+struct DefaultSeqVisitor {
+    len: usize,
+}
+
+impl serde::de::SeqVisitor for DefaultSeqVisitor {
+    type Error = DeserializeError;
+
+    fn visit<T>(&mut self) -> DeserializeResult<Option<T>>
+        where T: serde::de::Deserialize,
+    {
+        if self.len > 0 {
+            self.len -= 1;
+            let value = try!(serde::Deserialize::deserialize(&mut DefaultDeserializer));
+            Ok(Some(value))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+}
+
+impl serde::de::MapVisitor for DefaultSeqVisitor {
+    type Error = DeserializeError;
+
+    fn visit_key<K>(&mut self) -> DeserializeResult<Option<K>>
+        where K: serde::de::Deserialize,
+    {
+        Ok(None)
+    }
+
+    fn visit_value<V>(&mut self) -> DeserializeResult<V>
+        where V: serde::de::Deserialize,
+    {
+        serde::Deserialize::deserialize(&mut DefaultDeserializer)
+    }
+
+    fn end(&mut self) -> DeserializeResult<()> {
+        Ok(())
+    }
+}
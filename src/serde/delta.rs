@@ -0,0 +1,122 @@
+use std::ops::Deref;
+
+use serde_crate as serde;
+
+use super::varint::Var;
+
+/// Integers whose differences `DeltaSeq` can encode.
+pub trait DeltaInt: Copy {
+    /// Returns the integer as a `u64`, sign-extended if it is signed, so
+    /// that differences between two of them wrap around correctly.
+    fn to_wrapping(self) -> u64;
+
+    /// Undoes `to_wrapping`, or returns `None` if the result doesn't fit.
+    fn from_wrapping(value: u64) -> Option<Self>;
+}
+
+macro_rules! impl_delta_unsigned {
+    ($($ty:ident),*) => {
+        $(
+            impl DeltaInt for $ty {
+                fn to_wrapping(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_wrapping(value: u64) -> Option<$ty> {
+                    if value > ::std::$ty::MAX as u64 {
+                        None
+                    } else {
+                        Some(value as $ty)
+                    }
+                }
+            }
+        )*
+    }
+}
+
+macro_rules! impl_delta_signed {
+    ($($ty:ident),*) => {
+        $(
+            impl DeltaInt for $ty {
+                fn to_wrapping(self) -> u64 {
+                    self as i64 as u64
+                }
+
+                fn from_wrapping(value: u64) -> Option<$ty> {
+                    let value = value as i64;
+                    if value < ::std::$ty::MIN as i64 || value > ::std::$ty::MAX as i64 {
+                        None
+                    } else {
+                        Some(value as $ty)
+                    }
+                }
+            }
+        )*
+    }
+}
+
+impl_delta_unsigned!(u16, u32, u64);
+impl_delta_signed!(i16, i32, i64);
+
+/// A `Vec` of integers that is encoded as the differences between them.
+///
+/// Each value is written as how far it is from the one before it, the
+/// first from zero, as a zigzag encoded `Var<i64>`.  A sorted column of
+/// timestamps or offsets, whose neighbours are close together, takes a
+/// byte or two per value instead of eight.  The values come back exactly,
+/// including any that are further apart than an `i64` can say, since the
+/// differences wrap around.
+///
+/// The layout is a `Vec<Var<i64>>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct DeltaSeq<T>(pub Vec<T>);
+
+impl<T> DeltaSeq<T> {
+    /// Unwraps the `Vec`.
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for DeltaSeq<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> From<Vec<T>> for DeltaSeq<T> {
+    fn from(values: Vec<T>) -> DeltaSeq<T> {
+        DeltaSeq(values)
+    }
+}
+
+impl<T: DeltaInt> serde::Serialize for DeltaSeq<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        let mut previous = 0u64;
+        let deltas: Vec<Var<i64>> = self.0.iter().map(|value| {
+            let value = value.to_wrapping();
+            let delta = value.wrapping_sub(previous) as i64;
+            previous = value;
+            Var(delta)
+        }).collect();
+        deltas.serialize(serializer)
+    }
+}
+
+impl<T: DeltaInt> serde::Deserialize for DeltaSeq<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<DeltaSeq<T>, D::Error> {
+        let deltas: Vec<Var<i64>> = try!(serde::Deserialize::deserialize(deserializer));
+        let mut previous = 0u64;
+        let mut values = Vec::with_capacity(deltas.len());
+        for Var(delta) in deltas {
+            previous = previous.wrapping_add(delta as u64);
+            match T::from_wrapping(previous) {
+                Some(value) => values.push(value),
+                None => return Err(serde::de::Error::custom("a delta-encoded integer is too large for its type")),
+            }
+        }
+        Ok(DeltaSeq(values))
+    }
+}
@@ -0,0 +1,109 @@
+use std::cell::Cell;
+use std::fmt::{self, Display, Write};
+
+use serde_crate as serde;
+
+/// The name `CollectStr` gives its newtype struct, which tells the bincode
+/// `Serializer` that it can take the string in pieces.
+pub const NAME: &'static str = "bincode::CollectStr";
+
+/// The name `CollectStr` asks with whether the `Serializer` takes the
+/// string in pieces.  The bincode `Serializer` answers by leaving the
+/// newtype struct unwritten; any other one writes it, as the whole string.
+pub const STREAM: &'static str = "bincode::CollectStr::Stream";
+
+/// A value that is serialized as a string of what its `Display`
+/// implementation writes.
+///
+/// Big decimals, URLs, addresses and the like often have no better way to
+/// be serialized than as their text.  The usual way of writing that text
+/// to a `String` first and serializing the string allocates for every
+/// value.  The bincode `Serializer` takes the text as it is formatted
+/// instead: it formats it once to count its length, writes the length, and
+/// formats it again straight into the output.  Every other `Serializer` is
+/// given a `String`.
+///
+/// It is read back as a `String`.  Use `collect_str` to serialize a field
+/// this way with `#[serde(serialize_with)]`.
+#[derive(Clone, Copy, Debug)]
+pub struct CollectStr<'a, T: ?Sized + 'a>(pub &'a T);
+
+impl<'a, T: ?Sized + Display> serde::Serialize for CollectStr<'a, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_newtype_struct(NAME, Pieces(self.0))
+    }
+}
+
+/// Serializes `value` as a string of what its `Display` implementation
+/// writes, like `CollectStr`.
+///
+/// This is the stand-in for `Serializer::collect_str`, which this version
+/// of serde doesn't have.
+///
+/// ```rust,ignore
+/// #[derive(Serialize)]
+/// struct Payment {
+///     #[serde(serialize_with = "bincode::serde::collect_str")]
+///     amount: BigDecimal,
+/// }
+/// ```
+pub fn collect_str<T, S>(value: &T, serializer: &mut S) -> Result<(), S::Error>
+    where T: ?Sized + Display, S: serde::Serializer,
+{
+    serde::Serialize::serialize(&CollectStr(value), serializer)
+}
+
+struct Pieces<'a, T: ?Sized + 'a>(&'a T);
+
+impl<'a, T: ?Sized + Display> serde::Serialize for Pieces<'a, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        let whole = Whole { value: self.0, written: Cell::new(false) };
+        try!(serializer.serialize_newtype_struct(STREAM, &whole));
+        if whole.written.get() {
+            return Ok(());
+        }
+
+        let mut streamer = Streamer { serializer: serializer, error: None };
+        let result = write!(streamer, "{}", self.0);
+        if let Some(err) = streamer.error {
+            return Err(err);
+        }
+        if result.is_err() {
+            return Err(serde::ser::Error::custom("a Display implementation returned an error"));
+        }
+        Ok(())
+    }
+}
+
+/// The text as one string, for a `Serializer` that doesn't take it in
+/// pieces.  `written` tells `Pieces` that it was.
+struct Whole<'a, T: ?Sized + 'a> {
+    value: &'a T,
+    written: Cell<bool>,
+}
+
+impl<'a, T: ?Sized + Display> serde::Serialize for Whole<'a, T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        self.written.set(true);
+        serializer.serialize_str(&self.value.to_string())
+    }
+}
+
+/// Hands what is formatted into it to a `Serializer` that writes strings
+/// without their length, and keeps the first error it returns.
+struct Streamer<'a, S: serde::Serializer + 'a> {
+    serializer: &'a mut S,
+    error: Option<S::Error>,
+}
+
+impl<'a, S: serde::Serializer> Write for Streamer<'a, S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.serializer.serialize_str(s) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                self.error = Some(err);
+                Err(fmt::Error)
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use std::io::{Read, Write};
+
+use serde_crate as serde;
+
+use ::{SizeLimit, Bounded};
+use super::audit::{self, AuditWriter, AuditReader};
+use super::read::IoReader;
+use super::reader::{Deserializer, DeserializeResult};
+use super::writer::{Serializer, SerializeResult, SerializeError};
+use super::serialized_size_bounded;
+
+/// A `Serializer` that writes to a `dyn Write`.
+///
+/// Every generic function in bincode is compiled once for each combination
+/// of message type and writer type it is used with.  A program with
+/// hundreds of message types that it writes to files, sockets and buffers
+/// pays for all of those combinations in compile time and binary size.
+/// Through a `SerializerDyn`, each message type is compiled once, whatever
+/// it is written to, at the cost of a virtual call for every write.  Keep
+/// the generic `Serializer` for the hot paths.
+pub type SerializerDyn<'a> = Serializer<'a, dyn Write + 'a>;
+
+/// A `Deserializer` that reads from a `dyn Read`, with a `Bounded` size
+/// limit.
+///
+/// This is the reading side of `SerializerDyn`: each message type is
+/// compiled once, whatever it is read from.  An `Infinite` limit is a
+/// `Bounded` one of `u64::MAX`.
+pub type DeserializerDyn<'a> = Deserializer<IoReader<&'a mut dyn Read>, Bounded>;
+
+/// Serializes an object into a `dyn Write` through a `SerializerDyn`.
+///
+/// This behaves like `serialize_into`, but is compiled once for each type
+/// of value rather than once for each type of value and writer.
+pub fn serialize_into_dyn<T, S>(writer: &mut dyn Write, value: &T, size_limit: S) -> SerializeResult<()>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    write_dyn(writer, value, size_limit.limit())
+}
+
+fn write_dyn<T: serde::Serialize>(writer: &mut dyn Write, value: &T, limit: Option<u64>) -> SerializeResult<()> {
+    if let Some(limit) = limit {
+        if serialized_size_bounded(value, limit).is_none() {
+            return Err(SerializeError::SizeLimit);
+        }
+    }
+
+    if audit::enabled() {
+        let mut writer = AuditWriter::new(writer);
+        try!(value.serialize(&mut SerializerDyn::new(&mut writer)));
+        writer.finish::<T>();
+        return Ok(());
+    }
+
+    value.serialize(&mut SerializerDyn::new(writer))
+}
+
+/// Deserializes an object from a `dyn Read` through a `DeserializerDyn`.
+///
+/// This behaves like `deserialize_from`, but is compiled once for each
+/// type of value rather than once for each type of value and reader.
+pub fn deserialize_from_dyn<T, S>(reader: &mut dyn Read, size_limit: S) -> DeserializeResult<T>
+    where T: serde::Deserialize, S: SizeLimit,
+{
+    read_dyn(reader, Bounded(size_limit.limit().unwrap_or(!0)))
+}
+
+fn read_dyn<T: serde::Deserialize>(reader: &mut dyn Read, size_limit: Bounded) -> DeserializeResult<T> {
+    if audit::enabled() {
+        let mut reader = AuditReader::new(reader);
+        let value = {
+            let audited: &mut dyn Read = &mut reader;
+            let mut deserializer = DeserializerDyn::new(IoReader::new(audited), size_limit);
+            try!(serde::Deserialize::deserialize(&mut deserializer))
+        };
+        reader.finish::<T>();
+        return Ok(value);
+    }
+
+    let mut deserializer = DeserializerDyn::new(IoReader::new(reader), size_limit);
+    serde::Deserialize::deserialize(&mut deserializer)
+}
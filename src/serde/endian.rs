@@ -0,0 +1,125 @@
+use std::ops::Deref;
+
+use serde_crate as serde;
+
+/// Numbers whose bytes can be put in the opposite order.
+///
+/// This is implemented for every integer and float type except `usize` and
+/// `isize`, whose width depends on the target.
+pub trait SwapBytes: Copy {
+    /// Returns the number with its bytes in the opposite order.
+    fn swap_bytes(self) -> Self;
+}
+
+macro_rules! impl_swap_bytes {
+    ($($ty:ty),*) => {
+        $(
+            impl SwapBytes for $ty {
+                fn swap_bytes(self) -> $ty {
+                    <$ty>::swap_bytes(self)
+                }
+            }
+        )*
+    }
+}
+
+impl_swap_bytes!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl SwapBytes for f32 {
+    fn swap_bytes(self) -> f32 {
+        f32::from_bits(self.to_bits().swap_bytes())
+    }
+}
+
+impl SwapBytes for f64 {
+    fn swap_bytes(self) -> f64 {
+        f64::from_bits(self.to_bits().swap_bytes())
+    }
+}
+
+/// A number that is encoded little-endian.
+///
+/// bincode writes numbers big-endian.  Wrapping a field in `Le` writes it
+/// the other way around, for protocols that mix the two, such as a
+/// little-endian length from some other system inside a bincode message.
+/// It takes up as many bytes as the number does.
+///
+/// The value is byte-swapped on its way to and from the serializer, so
+/// formats other than bincode see the swapped number.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Le<T>(pub T);
+
+/// A number that is encoded big-endian.
+///
+/// This is how bincode writes every number already, so `Be` changes nothing
+/// on the wire.  It is there to spell out the byte order of a field in a
+/// type that also has `Le` fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Be<T>(pub T);
+
+impl<T> Le<T> {
+    /// Unwraps the number.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Be<T> {
+    /// Unwraps the number.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Le<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Deref for Be<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Le<T> {
+    fn from(value: T) -> Le<T> {
+        Le(value)
+    }
+}
+
+impl<T> From<T> for Be<T> {
+    fn from(value: T) -> Be<T> {
+        Be(value)
+    }
+}
+
+impl<T: SwapBytes + serde::Serialize> serde::Serialize for Le<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        self.0.swap_bytes().serialize(serializer)
+    }
+}
+
+impl<T: SwapBytes + serde::Deserialize> serde::Deserialize for Le<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Le<T>, D::Error> {
+        let value: T = try!(serde::Deserialize::deserialize(deserializer));
+        Ok(Le(value.swap_bytes()))
+    }
+}
+
+impl<T: SwapBytes + serde::Serialize> serde::Serialize for Be<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<T: SwapBytes + serde::Deserialize> serde::Deserialize for Be<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Be<T>, D::Error> {
+        serde::Deserialize::deserialize(deserializer).map(Be)
+    }
+}
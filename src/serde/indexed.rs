@@ -0,0 +1,62 @@
+use serde_crate as serde;
+
+use ::{SizeLimit, Infinite};
+use super::{serialize_into, serialized_size_bounded, deserialize_exact};
+use super::reader::{DeserializeResult, invalid_offset};
+use super::writer::{SerializeResult, SerializeError};
+
+/// Serializes a slice of values as a `Vec<T>`, and returns the offset of
+/// each of them in the bytes alongside.
+///
+/// The bytes are the same as those of `serialize(&values, size_limit)`, so
+/// they can still be read as a whole `Vec<T>`.  The offsets, one per value,
+/// let `deserialize_nth` read any single value straight out of them,
+/// without going through the ones before it, which is what a large dataset
+/// in a memory-mapped file needs.  Keep the offsets next to the bytes, in a
+/// sidecar file or in a section of their own.
+///
+/// If the bytes would be longer than `size_limit`, an error is returned.
+pub fn serialize_indexed<T, S>(values: &[T], size_limit: S) -> SerializeResult<(Vec<u8>, Vec<u64>)>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    let mut bytes = match size_limit.limit() {
+        Some(size_limit) => match serialized_size_bounded(&values, size_limit) {
+            Some(size) => Vec::with_capacity(size as usize),
+            None => return Err(SerializeError::SizeLimit),
+        },
+        None => Vec::new(),
+    };
+    let mut offsets = Vec::with_capacity(values.len());
+
+    try!(serialize_into(&mut bytes, &(values.len() as u64), Infinite));
+    for value in values {
+        offsets.push(bytes.len() as u64);
+        try!(serialize_into(&mut bytes, value, Infinite));
+    }
+    Ok((bytes, offsets))
+}
+
+/// Deserializes the value at `index` out of bytes written by
+/// `serialize_indexed`, using the offsets it returned.
+///
+/// The value is read from its offset up to the offset of the next one, or
+/// the end of the bytes for the last, and has to take up exactly that much,
+/// so offsets that don't belong to the bytes are an error rather than a
+/// wrong value.
+///
+/// # Panics
+///
+/// Panics if `index` is not less than `offsets.len()`.
+pub fn deserialize_nth<T>(bytes: &[u8], offsets: &[u64], index: usize) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    let start = offsets[index];
+    let end = match offsets.get(index + 1) {
+        Some(&end) => end,
+        None => bytes.len() as u64,
+    };
+    if start > end || end > bytes.len() as u64 {
+        return Err(invalid_offset(index, start));
+    }
+    deserialize_exact(&bytes[start as usize..end as usize])
+}
@@ -1,14 +1,50 @@
 //! A collection of serialization and deserialization functions
 //! that use the `serde` crate for the serialazble and deserializable
 //! implementation.
+//!
+//! Bincode is not a human-readable format, and the `Serializer` and
+//! `Deserializer` both say so from `is_human_readable`, unless a `Config`
+//! with `set_human_readable` says otherwise.  The version of serde this is
+//! built on has no `is_human_readable` in its traits, so types that pick a
+//! compact or a textual representation by it, like those of chrono or uuid
+//! in their later versions, can only ask a bincode `Serializer` or
+//! `Deserializer` they are handed by name.  Other types write whatever their
+//! serde 0.7 implementation writes, which is the same here and in
+//! `rustc_serialize` only if both implementations agree.
+//!
+//! `Config` bundles the settings of the `Serializer` and `Deserializer`
+//! into a few named presets whose layout never changes between versions,
+//! and each preset has a `Layout` type that fixes it at compile time.
+//!
+//! `serialize_scatter` leaves the bytes of `Attachment` fields out of the
+//! message, so that a large payload can be sent next to its header with a
+//! vectored write rather than copied in after it.  On the way back,
+//! `deserialize_detached` leaves long attachments in the input and reads
+//! them as `AttachmentBuf`s that say where their bytes are.
+//!
+//! `CollectStr` and `collect_str` serialize a value as the text its
+//! `Display` implementation writes, without a `String` in between.
+//!
+//! `MapAsSeq` and `SeqAsMap` make a map look like a sequence of pairs to
+//! serde, and the other way around, to match what a peer that isn't built
+//! on serde expects.
 
-use std::io::{Write, Read};
-use ::SizeLimit;
+use std::io::{self, Write, Read, BufRead};
+use std::ops::Deref;
+use ::{SizeLimit, Infinite, Bounded};
+use ::MaxSize;
+use ::max_size::AssertFits;
+use ::tee::TeeWriteAll;
 
 pub use self::reader::{
     Deserializer,
+    Checkpoint,
     DeserializeResult,
     DeserializeError,
+    InvalidEncoding,
+    Utf8Mode,
+    Narrowing,
+    UnknownVariants,
 };
 
 pub use self::writer::{
@@ -17,11 +53,115 @@ pub use self::writer::{
     SerializeError,
 };
 
+pub use self::observer::{
+    Observer,
+    NoObserver,
+};
+
+pub use self::stats::DecodeStats;
+
+pub use self::alloc_guard::{AllocGuard, MemoryBudget};
+
+pub use self::audit::{AuditSink, AuditRecord, Direction, set_audit_sink, clear_audit_sink};
+
+pub use self::partial::{deserialize_partial, PartialDecode};
+
+pub use self::config::{
+    Config,
+    Layout,
+    StaticLayout,
+    StandardLayout,
+    CompactLayout,
+    LegacyLayout,
+    FixedLayout,
+    serialize_with_layout,
+    deserialize_with_layout,
+};
+
+pub use self::dynamic::{SerializerDyn, DeserializerDyn, serialize_into_dyn, deserialize_from_dyn};
+
+pub use self::throttle::ThrottledRead;
+
+pub use self::peek::{peek_variant_index, peek_prefix, PeekRead};
+
+pub use self::indexed::{serialize_indexed, deserialize_nth};
+
+pub use self::display::{CollectStr, collect_str};
+pub use self::scatter::{Attachment, AttachmentBuf, Scatter, Placeholder, serialize_scatter, deserialize_detached};
+
+pub use self::raw_string::RawString;
+
+pub use self::unknown::Unknown;
+
+pub use serde_crate::bytes::{ByteBuf, Bytes, ByteBufVisitor};
+
+pub use self::endian::{Le, Be, SwapBytes};
+
+pub use self::precision::{F16Of, F32Of, HalfPrecision};
+
+pub use self::bitpack::{Bits, U1, U2, U3, U4, U5, U6, U7};
+
+pub use self::varint::{Var, Fixed, VarInt};
+
+pub use self::delta::{DeltaSeq, DeltaInt};
+
+pub use self::pairs::{MapAsSeq, SeqAsMap};
+
+pub use self::non_zero::{NonZeroInt, serialize_non_zero, deserialize_non_zero};
+
+pub use self::columnar::{Columnar, ColumnarView, LowCardinality};
+
+pub use self::rle::Rle;
+
+pub use self::os_str::{PortableOsString, PortablePathBuf};
+
+pub use self::tagged::{Tagged, Registry, TaggedDeserializer, TagCollision, serialize_tagged, deserialize_tagged};
+
+pub use self::ordered::{serialize_key, deserialize_key, KeySerializer, KeyDeserializer};
+
+pub use self::read::{
+    BincodeRead,
+    SliceReader,
+    SegmentedReader,
+    BufferedReader,
+    IoReader,
+};
+
 use self::writer::SizeChecker;
+use self::reader::invalid_padding;
+use self::audit::{AuditWriter, AuditReader};
 
 use serde_crate as serde;
 
+mod alloc_guard;
+mod audit;
+mod bitpack;
+mod columnar;
+mod config;
+mod defaults;
+mod delta;
+mod display;
+mod dynamic;
+mod endian;
+mod indexed;
+mod non_zero;
+mod observer;
+mod ordered;
+mod pairs;
+pub mod os_str;
+mod partial;
+mod peek;
+mod precision;
+mod raw_string;
+mod read;
 mod reader;
+mod rle;
+mod scatter;
+mod stats;
+mod tagged;
+mod throttle;
+mod unknown;
+mod varint;
 mod writer;
 
 /// Serializes an object directly into a `Writer`.
@@ -32,46 +172,254 @@ mod writer;
 /// If this returns an `SerializeError` (other than SizeLimit), assume that the
 /// writer is in an invalid state, as writing could bail out in the middle of
 /// serializing.
-pub fn serialize_into<W, T>(writer: &mut W, value: &T, size_limit: SizeLimit) -> SerializeResult<()>
-    where W: Write, T: serde::Serialize,
+pub fn serialize_into<W, T, S>(writer: &mut W, value: &T, size_limit: S) -> SerializeResult<()>
+    where W: Write, T: serde::Serialize, S: SizeLimit,
 {
-    match size_limit {
-        SizeLimit::Infinite => { }
-        SizeLimit::Bounded(x) => {
-            let mut size_checker = SizeChecker::new(x);
-            try!(value.serialize(&mut size_checker))
-        }
+    if let Some(x) = size_limit.limit() {
+        let mut size_checker = SizeChecker::new(x);
+        try!(value.serialize(&mut size_checker))
+    }
+
+    if audit::enabled() {
+        let mut writer = AuditWriter::new(writer);
+        try!(serde::Serialize::serialize(value, &mut Serializer::new(&mut writer)));
+        writer.finish::<T>();
+        return Ok(());
     }
 
     let mut serializer = Serializer::new(writer);
     serde::Serialize::serialize(value, &mut serializer)
 }
 
+/// Serializes an object directly into several `Writer`s at once.
+///
+/// The value is only serialized once, and every byte of it is written to each of
+/// the writers in turn, so a message can go to a file and a socket without
+/// being serialized twice or buffered.  Use a `TeeWrite` instead when there is a
+/// fixed number of writers.
+///
+/// If the serialization would take more bytes than allowed by `size_limit`, an error
+/// is returned and *no bytes* will be written into any of the writers.
+pub fn serialize_into_many<T, S>(value: &T, writers: &mut [&mut dyn Write], size_limit: S) -> SerializeResult<()>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    serialize_into(&mut TeeWriteAll::new(writers), value, size_limit)
+}
+
 /// Serializes a serializable object into a `Vec` of bytes.
 ///
 /// If the serialization would take more bytes than allowed by `size_limit`,
 /// an error is returned.
-pub fn serialize<T>(value: &T, size_limit: SizeLimit) -> SerializeResult<Vec<u8>>
-    where T: serde::Serialize,
+pub fn serialize<T, S>(value: &T, size_limit: S) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize, S: SizeLimit,
 {
     // Since we are putting values directly into a vector, we can do size
     // computation out here and pre-allocate a buffer of *exactly*
     // the right size.
-    let mut writer = match size_limit {
-        SizeLimit::Bounded(size_limit) => {
+    let mut writer = match size_limit.limit() {
+        Some(size_limit) => {
             let actual_size = match serialized_size_bounded(value, size_limit) {
                 Some(actual_size) => actual_size,
                 None => { return Err(SerializeError::SizeLimit); }
             };
             Vec::with_capacity(actual_size as usize)
         }
-        SizeLimit::Infinite => Vec::new()
+        None => Vec::new()
+    };
+
+    try!(serialize_into(&mut writer, value, Infinite));
+    Ok(writer)
+}
+
+/// Serializes an object into a `Vec` of bytes, leaving out struct fields
+/// that hold the empty value of their type.
+///
+/// See `Serializer::set_skip_defaults`.  The output can be read with
+/// `deserialize_skipping_defaults`.  If it is longer than `size_limit`, an
+/// error is returned.
+pub fn serialize_skipping_defaults<T, S>(value: &T, size_limit: S) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    serialize_audited(value, size_limit, Config::standard(), |serializer| serializer.set_skip_defaults(true))
+}
+
+/// Serializes an object with the layout of a packed C struct in network
+/// byte order.
+///
+/// This is for talking to programs that send and receive C structs as
+/// they are laid out in memory, after converting every field to network
+/// byte order.  The fields of a struct are written one after the other,
+/// each with the width of its Rust type, so a C `int32_t` is an `i32`, a
+/// `uint8_t` is a `u8`, and so on.  Fixed-size arrays are written without
+/// their length, as `Serializer::set_fixed_arrays` describes, so a
+/// `char name[8]` is a `[u8; 8]`.
+///
+/// Nothing is inserted between fields.  Where the C compiler would add
+/// padding, give the Rust struct a field for it, such as `_pad: [u8; 3]`.
+/// Types with no C counterpart, like `Vec`, `String` or `Option`, are
+/// written the usual bincode way and should be left out of such structs.
+pub fn serialize_c_struct<T>(value: &T) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize,
+{
+    serialize_audited(value, Infinite, Config::fixed(), |_| {})
+}
+
+/// Serializes an object with its bools and `Bits` fields packed into bits.
+///
+/// Consecutive bools and `Bits` fields share bytes, as
+/// `Serializer::set_bit_packing` describes, which makes structs of flags
+/// and small numbers several times smaller.  Everything else is written
+/// the usual way, starting on a whole byte.
+pub fn serialize_bit_packed<T>(value: &T) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize,
+{
+    serialize_audited(value, Infinite, Config::compact(), |_| {})
+}
+
+/// Serializes a batch of messages into a `Vec` of bytes.
+///
+/// The batch is written as the number of messages followed by the messages
+/// back to back, which is the same as a `Vec<T>`.  The whole batch is sized
+/// and checked against `size_limit` at once, rather than message by message.
+pub fn serialize_batch<T, S>(values: &[T], size_limit: S) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    serialize(&values, size_limit)
+}
+
+/// Serializes a batch of messages directly into a `Writer`.
+///
+/// See `serialize_batch`.  If the batch would take more bytes than allowed
+/// by `size_limit`, an error is returned and *no bytes* will be written.
+pub fn serialize_batch_into<W, T, S>(writer: &mut W, values: &[T], size_limit: S) -> SerializeResult<()>
+    where W: Write, T: serde::Serialize, S: SizeLimit,
+{
+    serialize_into(writer, &values, size_limit)
+}
+
+/// Serializes an object into a `Vec` of exactly `slot_len` bytes, padded
+/// with zeros after the object.
+///
+/// This is for formats made of fixed-size slots, like a file of 512-byte
+/// records.  If the object takes more than `slot_len` bytes, or `slot_len`
+/// is more than a `usize` can hold, a `SizeLimit` error is returned.  Read
+/// it back with `deserialize_padded`.
+pub fn serialize_padded<T>(value: &T, slot_len: u64) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize,
+{
+    let len = match ::num_traits::cast(slot_len) {
+        Some(len) => len,
+        None => return Err(SerializeError::SizeLimit),
     };
+    let mut writer = try!(serialize(value, Bounded(slot_len)));
+    writer.resize(len, 0);
+    Ok(writer)
+}
+
+/// Serializes an object directly into a `Writer`, followed by enough zeros
+/// to make up exactly `slot_len` bytes.
+///
+/// If the object takes more than `slot_len` bytes, a `SizeLimit` error is
+/// returned and *no bytes* will be written into the `Writer`.
+pub fn serialize_into_padded<W, T>(writer: &mut W, value: &T, slot_len: u64) -> SerializeResult<()>
+    where W: Write, T: serde::Serialize,
+{
+    let size = match serialized_size_bounded(value, slot_len) {
+        Some(size) => size,
+        None => return Err(SerializeError::SizeLimit),
+    };
+    try!(serialize_into(writer, value, Infinite));
+    let padding = [0; 64];
+    let mut remaining = slot_len - size;
+    while remaining > 0 {
+        let len = if remaining < 64 { remaining as usize } else { 64 };
+        try!(writer.write_all(&padding[..len]).map_err(SerializeError::IoError));
+        remaining -= len as u64;
+    }
+    Ok(())
+}
 
-    try!(serialize_into(&mut writer, value, SizeLimit::Infinite));
+/// Serializes a serializable object into a `Vec` of bytes that is allocated
+/// exactly once.
+///
+/// The size of the value is worked out first, so that the `Vec` starts out
+/// with exactly the capacity it needs instead of growing as it is written
+/// to.  That means walking the value twice, which is still cheaper than
+/// reallocating and copying the output for all but the smallest messages.
+pub fn serialize_sized<T: serde::Serialize>(value: &T) -> SerializeResult<Vec<u8>> {
+    let mut writer = Vec::with_capacity(serialized_size(value) as usize);
+    try!(serialize_into(&mut writer, value, Infinite));
     Ok(writer)
 }
 
+/// Serializes an object into a fixed-size buffer, returning the number of
+/// bytes written.
+///
+/// The buffer must be at least `T::MAX_SIZE` bytes long; if it isn't, the
+/// call fails to compile instead of failing at runtime.
+///
+/// ```rust,ignore
+/// let mut buffer = [0u8; 12];
+/// let written = serialize_into_array(&(1u32, 2u64), &mut buffer).unwrap();
+/// ```
+pub fn serialize_into_array<T, const N: usize>(value: &T, buffer: &mut [u8; N]) -> SerializeResult<usize>
+    where T: serde::Serialize + MaxSize,
+{
+    let () = AssertFits::<T, N>::OK;
+
+    let mut writer = &mut buffer[..];
+    try!(serialize_into(&mut writer, value, Infinite));
+    Ok(N - writer.len())
+}
+
+/// Serializes an object into the start of a slice of bytes, returning the
+/// number of bytes written.
+///
+/// Nothing is allocated, so this can be used where the heap can't, such as
+/// on a real-time thread writing into a buffer on the stack.  If the object
+/// doesn't fit, a `BufferTooSmall` error is returned and *no bytes* are
+/// written.  When the size of the buffer is known at compile time and the
+/// type implements `MaxSize`, `serialize_into_array` checks that it fits
+/// when compiling instead.
+///
+/// ```rust,ignore
+/// let mut buffer = [0u8; 64];
+/// let written = serialize_into_slice(&reading, &mut buffer).unwrap();
+/// socket.send(&buffer[..written]);
+/// ```
+pub fn serialize_into_slice<T>(value: &T, buffer: &mut [u8]) -> SerializeResult<usize>
+    where T: serde::Serialize,
+{
+    let needed = serialized_size(value);
+    if needed > buffer.len() as u64 {
+        return Err(SerializeError::BufferTooSmall { needed: needed });
+    }
+
+    let mut writer = &mut buffer[..needed as usize];
+    try!(serialize_into(&mut writer, value, Infinite));
+    Ok(needed as usize)
+}
+
+/// Serializes an object into a `Vec` of bytes to be sent as one datagram of
+/// at most `mtu` bytes.
+///
+/// A UDP datagram that is larger than the path MTU gets fragmented on the
+/// way, or dropped, so each packet of a game's state updates has to fit in
+/// one.  The size is worked out before anything is written, and the size
+/// checker gives up at the first byte past `mtu` instead of walking the
+/// rest of an object that is far too large.  An object that doesn't fit is
+/// a `SizeLimit` error.  Read it back with `deserialize_datagram`.
+///
+/// ```rust,ignore
+/// let packet = serialize_datagram(&snapshot, 1200)?;
+/// socket.send_to(&packet, peer)?;
+/// ```
+pub fn serialize_datagram<T>(value: &T, mtu: usize) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize,
+{
+    serialize(value, Bounded(mtu as u64))
+}
+
 /// Returns the size that an object would be if serialized using bincode.
 ///
 /// This is used internally as part of the check for encode_into, but it can
@@ -102,12 +450,55 @@ pub fn serialized_size_bounded<T: serde::Serialize>(value: &T, max: u64) -> Opti
 /// If this returns an `DeserializeError`, assume that the buffer that you passed
 /// in is in an invalid state, as the error could be returned during any point
 /// in the reading.
-pub fn deserialize_from<R, T>(reader: &mut R, size_limit: SizeLimit) -> DeserializeResult<T>
+pub fn deserialize_from<R, T, S>(reader: &mut R, size_limit: S) -> DeserializeResult<T>
+    where R: Read,
+          T: serde::Deserialize,
+          S: SizeLimit,
+{
+    deserialize_from_audited(reader, size_limit, Config::standard(), |_| {})
+}
+
+/// Deserializes an object directly from a buffered Reader, such as a
+/// `std::io::BufReader` over a file.
+///
+/// This behaves like `deserialize_from`, but strings and byte buffers are
+/// taken straight out of the reader's buffer when they are all in it
+/// instead of being copied out first.  See `BufferedReader`.
+pub fn deserialize_from_buffered<R, T, S>(reader: &mut R, size_limit: S) -> DeserializeResult<T>
+    where R: BufRead,
+          T: serde::Deserialize,
+          S: SizeLimit,
+{
+    let mut reader = AuditReader::new(reader);
+    let value = {
+        let mut deserializer = Deserializer::new(BufferedReader::new(&mut reader), size_limit);
+        try!(serde::Deserialize::deserialize(&mut deserializer))
+    };
+    reader.finish::<T>();
+    Ok(value)
+}
+
+/// Deserializes an object directly from a Reader, and reports statistics
+/// about what it took to do so.
+///
+/// This behaves just like `deserialize_from`.  The `DecodeStats` it returns
+/// along with the value count the bytes read, the elements and strings
+/// decoded, the deepest nesting, and the largest single allocation.
+pub fn deserialize_from_with_stats<R, T, S>(reader: &mut R, size_limit: S) -> DeserializeResult<(T, DecodeStats)>
     where R: Read,
           T: serde::Deserialize,
+          S: SizeLimit,
 {
-    let mut deserializer = Deserializer::new(reader, size_limit);
-    serde::Deserialize::deserialize(&mut deserializer)
+    let mut stats = DecodeStats::default();
+    let mut reader = AuditReader::new(reader);
+    let (value, bytes_read) = {
+        let mut deserializer = Deserializer::with_observer(IoReader::new(&mut reader), size_limit, &mut stats);
+        let value = try!(serde::Deserialize::deserialize(&mut deserializer));
+        (value, deserializer.bytes_read())
+    };
+    reader.finish::<T>();
+    stats.bytes = bytes_read;
+    Ok((value, stats))
 }
 
 /// Deserializes a slice of bytes into an object.
@@ -117,7 +508,295 @@ pub fn deserialize_from<R, T>(reader: &mut R, size_limit: SizeLimit) -> Deserial
 pub fn deserialize<T>(bytes: &[u8]) -> DeserializeResult<T>
     where T: serde::Deserialize,
 {
-    let mut reader = bytes;
-    deserialize_from(&mut reader, SizeLimit::Infinite)
+    deserialize_audited(bytes, Infinite, Config::standard(), |_| {}).map(|(value, _)| value)
+}
+
+/// Deserializes a slice of bytes into an object, and checks that the object
+/// takes up all of them.
+///
+/// `deserialize` ignores whatever comes after the object, which can hide a
+/// sender and receiver disagreeing about the format.  This returns a
+/// `TrailingBytes` error instead.
+pub fn deserialize_exact<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    let mut deserializer = Deserializer::new(SliceReader::new(bytes), Infinite);
+    let value = try!(serde::Deserialize::deserialize(&mut deserializer));
+    try!(deserializer.end());
+    if audit::enabled() {
+        audit::record::<T>(audit::Direction::Deserialized, bytes);
+    }
+    Ok(value)
+}
+
+/// Deserializes an object from a datagram written by `serialize_datagram`,
+/// and checks that the object takes up the whole datagram.
+///
+/// A datagram holds exactly one message, so bytes left over after it mean
+/// the peer sent something else, and are a `TrailingBytes` error like they
+/// are in `deserialize_exact`.  A datagram that was cut short fails with an
+/// `UnexpectedEof` `IoError`.
+pub fn deserialize_datagram<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    deserialize_exact(bytes)
+}
+
+/// Deserializes an object from the bytes of a packed C struct in network
+/// byte order, as `serialize_c_struct` writes them.
+///
+/// The contents of padding fields are not checked.  Like `deserialize`,
+/// this ignores whatever comes after the struct, such as the payload of a
+/// packet whose header it is.
+pub fn deserialize_c_struct<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    deserialize_audited(bytes, Infinite, Config::fixed(), |_| {}).map(|(value, _)| value)
+}
+
+/// Deserializes an object written by `serialize_bit_packed`.
+pub fn deserialize_bit_packed<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    deserialize_audited(bytes, Infinite, Config::compact(), |_| {}).map(|(value, _)| value)
+}
+
+/// Deserializes a batch of messages written by `serialize_batch`.
+pub fn deserialize_batch<T>(bytes: &[u8]) -> DeserializeResult<Vec<T>>
+    where T: serde::Deserialize,
+{
+    deserialize(bytes)
+}
+
+/// Deserializes a batch of messages directly from a Reader.
+///
+/// `size_limit` applies to the batch as a whole, not to each message in it.
+pub fn deserialize_batch_from<R, T, S>(reader: &mut R, size_limit: S) -> DeserializeResult<Vec<T>>
+    where R: Read,
+          T: serde::Deserialize,
+          S: SizeLimit,
+{
+    deserialize_from(reader, size_limit)
+}
+
+/// Deserializes a batch of messages from a Reader into an existing `Vec`.
+///
+/// `out` is cleared first, and only its buffer is reused: a loop that reads
+/// a batch at a time into the same `Vec` stops allocating for the `Vec` once
+/// it has grown to the size of the largest batch, so messages of types that
+/// own no memory, like numbers and tuples of them, are then read without
+/// allocating at all.  The messages themselves are dropped and read anew,
+/// so the `String`s, `Vec`s and other memory they own are allocated again
+/// for every batch.
+///
+/// If this returns an error, `out` holds the messages that were read before
+/// it happened.
+pub fn deserialize_batch_into<R, T, S>(reader: &mut R, out: &mut Vec<T>, size_limit: S) -> DeserializeResult<()>
+    where R: Read,
+          T: serde::Deserialize,
+          S: SizeLimit,
+{
+    out.clear();
+    let mut reader = AuditReader::new(reader);
+    {
+        let mut deserializer = Deserializer::new(IoReader::new(&mut reader), size_limit);
+        let len: u64 = try!(serde::Deserialize::deserialize(&mut deserializer));
+        for _ in 0..len {
+            out.push(try!(serde::Deserialize::deserialize(&mut deserializer)));
+        }
+    }
+    reader.finish::<Vec<T>>();
+    Ok(())
+}
+
+/// Deserializes a slot written by `serialize_padded`, and checks that the
+/// padding after the object is all zeros.
+///
+/// The whole slice is taken to be the slot.  A non-zero byte after the
+/// object is an `InvalidEncoding` error.  To ignore whatever comes after
+/// the object instead, use `deserialize`.
+pub fn deserialize_padded<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    let (value, read) = try!(deserialize_audited(bytes, Infinite, Config::standard(), |_| {}));
+    match bytes[read..].iter().position(|&b| b != 0) {
+        Some(index) => Err(invalid_padding((read + index) as u64)),
+        None => Ok(value),
+    }
+}
+
+/// Reads a slot of exactly `slot_len` bytes from a Reader, and deserializes
+/// it like `deserialize_padded`.
+///
+/// The Reader is always left at the start of the next slot, unless reading
+/// fails.
+pub fn deserialize_from_padded<R, T>(reader: &mut R, slot_len: u64) -> DeserializeResult<T>
+    where R: Read,
+          T: serde::Deserialize,
+{
+    let len = match ::num_traits::cast(slot_len) {
+        Some(len) => len,
+        None => return Err(DeserializeError::SizeLimit),
+    };
+    let mut slot = vec![0; len];
+    try!(reader.read_exact(&mut slot));
+    deserialize_padded(&slot)
+}
+
+/// Deserializes a slice of bytes written by `serialize_skipping_defaults`
+/// into an object.
+pub fn deserialize_skipping_defaults<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    deserialize_audited(bytes, Infinite, Config::standard(), |deserializer| deserializer.set_skip_defaults(true))
+        .map(|(value, _)| value)
+}
+
+/// Deserializes an object from several slices of bytes that are read one
+/// after the other, as if they were one.
+///
+/// This saves copying a message that arrived in pieces into one buffer
+/// before decoding it.  See `SegmentedReader`.
+pub fn deserialize_segments<T, S>(segments: &[S]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+          S: Deref<Target = [u8]>,
+{
+    let mut deserializer = Deserializer::new(SegmentedReader::new(segments), Infinite);
+    let value = try!(serde::Deserialize::deserialize(&mut deserializer));
+    if audit::enabled() {
+        let pieces = segments.iter().map(|segment| &segment[..]);
+        audit::record_pieces::<T, _>(audit::Direction::Deserialized, pieces, deserializer.bytes_read());
+    }
+    Ok(value)
+}
+
+/// Deserializes an object from a Reader, filling in struct fields that are
+/// missing from the end of the input.
+///
+/// This behaves like `deserialize_from`, except that input ending right
+/// before a struct field isn't an error: that field and all the ones after
+/// it get the empty value of their type.  See
+/// `Deserializer::set_fill_missing_fields` for the details.
+pub fn deserialize_from_with_defaults<R, T, S>(reader: &mut R, size_limit: S) -> DeserializeResult<T>
+    where R: Read,
+          T: serde::Deserialize,
+          S: SizeLimit,
+{
+    deserialize_from_audited(reader, size_limit, Config::standard(), |deserializer| {
+        deserializer.set_fill_missing_fields(true)
+    })
+}
+
+/// Deserializes a slice of bytes into an object, filling in struct fields
+/// that are missing from the end of the input.
+///
+/// See `deserialize_from_with_defaults`.
+pub fn deserialize_with_defaults<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    deserialize_audited(bytes, Infinite, Config::standard(), |deserializer| deserializer.set_fill_missing_fields(true))
+        .map(|(value, _)| value)
+}
+
+/// Serializes an object into a `Vec` of bytes with the given layout, once
+/// `configure` has set up the rest of the `Serializer`, and reports it to
+/// the audit sink.
+///
+/// Everything that serializes with settings of its own, rather than
+/// through `serialize_into`, goes through here, so that it can't skip the
+/// audit.  If the object takes more bytes than `size_limit`, a `SizeLimit`
+/// error is returned and nothing is reported.
+fn serialize_audited<T, S, L, F>(value: &T, size_limit: S, layout: L, configure: F) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize, S: SizeLimit, L: Layout, F: FnOnce(&mut Serializer<LimitedWriter, L>),
+{
+    let mut writer = LimitedWriter {
+        bytes: Vec::new(),
+        limit: size_limit.limit(),
+        exceeded: false,
+    };
+    let result = {
+        let mut serializer = Serializer::with_layout(&mut writer, layout);
+        configure(&mut serializer);
+        value.serialize(&mut serializer).and_then(|()| serializer.flush_bits())
+    };
+    if writer.exceeded {
+        return Err(SerializeError::SizeLimit);
+    }
+    try!(result);
+    if audit::enabled() {
+        audit::record::<T>(audit::Direction::Serialized, &writer.bytes);
+    }
+    Ok(writer.bytes)
+}
+
+/// The buffer `serialize_audited` serializes into.  It refuses the first
+/// write that would take it past the size limit, so a value that is far too
+/// large fails as soon as it passes the limit instead of once it has all
+/// been serialized.
+struct LimitedWriter {
+    bytes: Vec<u8>,
+    limit: Option<u64>,
+    exceeded: bool,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.bytes.len() as u64 + buf.len() as u64 > limit {
+                self.exceeded = true;
+                return Err(io::Error::new(io::ErrorKind::Other, "size limit exceeded"));
+            }
+        }
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Deserializes an object from the start of a slice of bytes with the given
+/// layout and size limit, once `configure` has set up the rest of the `Deserializer`, and
+/// reports the bytes it took up to the audit sink.
+///
+/// This is the counterpart of `serialize_audited` for slices.  It returns
+/// the object along with the number of bytes it took up.
+fn deserialize_audited<T, S, L, F>(bytes: &[u8], size_limit: S, layout: L, configure: F) -> DeserializeResult<(T, usize)>
+    where T: serde::Deserialize,
+          S: SizeLimit,
+          L: Layout,
+          F: FnOnce(&mut Deserializer<SliceReader, S, NoObserver, L>),
+{
+    let mut deserializer = Deserializer::with_layout(SliceReader::new(bytes), size_limit, layout);
+    configure(&mut deserializer);
+    let value = try!(serde::Deserialize::deserialize(&mut deserializer));
+    let read = bytes.len() - deserializer.into_inner().remaining().len();
+    if audit::enabled() {
+        audit::record::<T>(audit::Direction::Deserialized, &bytes[..read]);
+    }
+    Ok((value, read))
 }
 
+/// Deserializes an object from a Reader with the given layout and size
+/// limit, once
+/// `configure` has set up the rest of the `Deserializer`, and reports the
+/// bytes it read to the audit sink.
+///
+/// This is the counterpart of `serialize_audited` for Readers.
+fn deserialize_from_audited<R, T, S, L, F>(reader: &mut R, size_limit: S, layout: L, configure: F) -> DeserializeResult<T>
+    where R: Read,
+          T: serde::Deserialize,
+          S: SizeLimit,
+          L: Layout,
+          F: FnOnce(&mut Deserializer<IoReader<&mut AuditReader<&mut R>>, S, NoObserver, L>),
+{
+    let mut reader = AuditReader::new(reader);
+    let value = {
+        let mut deserializer = Deserializer::with_layout(IoReader::new(&mut reader), size_limit, layout);
+        configure(&mut deserializer);
+        try!(serde::Deserialize::deserialize(&mut deserializer))
+    };
+    reader.finish::<T>();
+    Ok(value)
+}
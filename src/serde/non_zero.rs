@@ -0,0 +1,67 @@
+use std::num;
+
+use serde_crate as serde;
+
+/// The `NonZero*` integer types of the standard library.
+///
+/// serde has no implementations for these, and they can't be added outside
+/// of serde, so they are (de)serialized through `serialize_non_zero` and
+/// `deserialize_non_zero` instead.
+pub trait NonZeroInt: Copy {
+    /// The plain integer type underneath.
+    type Int: serde::Serialize + serde::Deserialize;
+
+    /// Returns the integer, which is never zero.
+    fn get(self) -> Self::Int;
+
+    /// Wraps the integer, or returns `None` if it is zero.
+    fn new(value: Self::Int) -> Option<Self>;
+}
+
+macro_rules! impl_non_zero_int {
+    ($($ty:ident: $int:ident),*) => {
+        $(
+            impl NonZeroInt for num::$ty {
+                type Int = $int;
+
+                fn get(self) -> $int {
+                    num::$ty::get(self)
+                }
+
+                fn new(value: $int) -> Option<num::$ty> {
+                    num::$ty::new(value)
+                }
+            }
+        )*
+    }
+}
+
+impl_non_zero_int!(NonZeroU8: u8, NonZeroU16: u16, NonZeroU32: u32, NonZeroU64: u64,
+                   NonZeroUsize: usize, NonZeroI8: i8, NonZeroI16: i16, NonZeroI32: i32,
+                   NonZeroI64: i64, NonZeroIsize: isize);
+
+/// Serializes a `NonZero*` integer as the plain integer it holds.
+///
+/// Meant for `#[serde(serialize_with = "bincode::serde::serialize_non_zero")]`
+/// or a hand-written `Serialize` implementation.
+pub fn serialize_non_zero<T, S>(value: &T, serializer: &mut S) -> Result<(), S::Error>
+    where T: NonZeroInt,
+          S: serde::Serializer
+{
+    serde::Serialize::serialize(&value.get(), serializer)
+}
+
+/// Deserializes a `NonZero*` integer written by `serialize_non_zero`.
+///
+/// A zero is rejected as an invalid value, which bincode reports as an
+/// `InvalidEncoding` error.
+pub fn deserialize_non_zero<T, D>(deserializer: &mut D) -> Result<T, D::Error>
+    where T: NonZeroInt,
+          D: serde::Deserializer
+{
+    let value = try!(serde::Deserialize::deserialize(deserializer));
+    match T::new(value) {
+        Some(value) => Ok(value),
+        None => Err(serde::de::Error::invalid_value("zero in a non-zero integer")),
+    }
+}
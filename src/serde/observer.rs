@@ -0,0 +1,90 @@
+/// Receives events from a `Deserializer` as it works its way through the
+/// input.
+///
+/// This is meant for tools that want to know what a message is made of
+/// without writing a deserializer of their own: profilers, metrics that
+/// work out which field takes up most of a message, or monitors that flag
+/// suspicious inputs.  Every event gets the offset in the input at which it
+/// happened, so the size of a struct, field, or collection is the difference
+/// between the offset of its start and that of the event that comes after it.
+/// For strings, byte buffers, sequences, and maps the offset is that of the
+/// length prefix.
+///
+/// All methods do nothing by default, so an implementation only needs to
+/// override the events it cares about.
+pub trait Observer {
+    /// A struct or struct variant with the given fields is about to be read.
+    fn begin_struct(&mut self, _offset: u64, _fields: &'static [&'static str]) {}
+
+    /// The field at `index` of the innermost struct is about to be read.
+    fn field(&mut self, _offset: u64, _index: usize) {}
+
+    /// An enum variant with the given index was read.
+    fn variant(&mut self, _offset: u64, _index: u32) {}
+
+    /// A sequence of `len` elements is about to be read.
+    fn begin_seq(&mut self, _offset: u64, _len: usize) {}
+
+    /// A map of `len` entries is about to be read.
+    fn begin_map(&mut self, _offset: u64, _len: usize) {}
+
+    /// The element or entry at `index` of the innermost sequence or map is
+    /// about to be read.
+    fn element(&mut self, _offset: u64, _index: usize) {}
+
+    /// A string of `len` bytes is about to be read.
+    fn string(&mut self, _offset: u64, _len: usize) {}
+
+    /// A byte buffer of `len` bytes is about to be read.
+    fn bytes(&mut self, _offset: u64, _len: usize) {}
+
+    /// The innermost struct, sequence, or map has been read completely.
+    fn end(&mut self, _offset: u64) {}
+}
+
+/// An `Observer` that ignores every event.
+///
+/// This is what a `Deserializer` uses unless it is given an observer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
+
+/// Lets a caller keep hold of an observer and look at it afterwards.
+impl<'a, O: Observer + ?Sized> Observer for &'a mut O {
+    fn begin_struct(&mut self, offset: u64, fields: &'static [&'static str]) {
+        (**self).begin_struct(offset, fields)
+    }
+
+    fn field(&mut self, offset: u64, index: usize) {
+        (**self).field(offset, index)
+    }
+
+    fn variant(&mut self, offset: u64, index: u32) {
+        (**self).variant(offset, index)
+    }
+
+    fn begin_seq(&mut self, offset: u64, len: usize) {
+        (**self).begin_seq(offset, len)
+    }
+
+    fn begin_map(&mut self, offset: u64, len: usize) {
+        (**self).begin_map(offset, len)
+    }
+
+    fn element(&mut self, offset: u64, index: usize) {
+        (**self).element(offset, index)
+    }
+
+    fn string(&mut self, offset: u64, len: usize) {
+        (**self).string(offset, len)
+    }
+
+    fn bytes(&mut self, offset: u64, len: usize) {
+        (**self).bytes(offset, len)
+    }
+
+    fn end(&mut self, offset: u64) {
+        (**self).end(offset)
+    }
+}
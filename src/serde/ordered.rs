@@ -0,0 +1,683 @@
+//! An encoding whose bytes sort in the same order as the values.
+//!
+//! The regular bincode encoding doesn't sort: little numbers come after big
+//! negative ones, and a length prefix puts `"b"` before `"aa"`.  This one is
+//! meant for keys in a sorted store like an LSM tree or a B-tree, which
+//! compare keys as bytes:
+//!
+//! * Integers are big-endian with the sign bit flipped, so negative numbers
+//!   come first.  `usize` and `isize` are 64 bits wide, as usual.
+//! * Floats have the sign bit flipped, and every other bit flipped too when
+//!   they are negative.  `-0.0` comes before `0.0`, and NaNs come first or
+//!   last depending on their sign.
+//! * Strings and byte buffers are written out in full and end with `00 01`.
+//!   A `00` in them is escaped to `00 ff`, so that a string sorts before
+//!   the strings it is a prefix of.
+//! * Options are `00` for `None` and `01` followed by the value for
+//!   `Some`.  Enums are their variant index as a big-endian `u32`, then
+//!   their fields.
+//! * Sequences and maps put `01` in front of every element and end with
+//!   `00`, instead of starting with a length.
+//! * Tuples and structs are their fields one after the other, so the key of
+//!   a tuple starts with the key of its first few fields: all the keys that
+//!   start with `("users", 12)` can be found by a range scan from the key of
+//!   that pair.
+//!
+//! Unlike bincode's own encoding, this one is only meant for keys, and
+//! only goes to and from slices of bytes.
+
+use std::io::Write;
+
+use serde_crate as serde;
+use serde_crate::de::value::ValueDeserializer;
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+
+use super::{SerializeResult, SerializeError, DeserializeResult, DeserializeError};
+use super::reader::invalid_utf8;
+
+const SIGN_64: u64 = 1 << 63;
+
+/// Serializes an object into a `Vec` of bytes that sorts in the same order
+/// as the object.
+pub fn serialize_key<T>(value: &T) -> SerializeResult<Vec<u8>>
+    where T: serde::Serialize,
+{
+    let mut writer = Vec::new();
+    try!(value.serialize(&mut KeySerializer::new(&mut writer)));
+    Ok(writer)
+}
+
+/// Deserializes an object from bytes written by `serialize_key`.
+///
+/// Every byte has to be used up.
+pub fn deserialize_key<T>(bytes: &[u8]) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    let mut deserializer = KeyDeserializer::new(bytes);
+    let value = try!(serde::Deserialize::deserialize(&mut deserializer));
+    match deserializer.remaining().len() {
+        0 => {}
+        remaining => return Err(DeserializeError::TrailingBytes { remaining: remaining as u64 }),
+    }
+    Ok(value)
+}
+
+fn wrap_io(err: ::std::io::Error) -> SerializeError {
+    SerializeError::IoError(err)
+}
+
+/// A Serializer that writes the order-preserving encoding.  See
+/// `serialize_key`.
+pub struct KeySerializer<'a, W: 'a> {
+    writer: &'a mut W,
+}
+
+impl<'a, W: Write> KeySerializer<'a, W> {
+    pub fn new(w: &'a mut W) -> KeySerializer<'a, W> {
+        KeySerializer {
+            writer: w,
+        }
+    }
+
+    fn write_escaped(&mut self, bytes: &[u8]) -> SerializeResult<()> {
+        for (i, part) in bytes.split(|&b| b == 0).enumerate() {
+            if i > 0 {
+                try!(self.writer.write_all(&[0, 0xff]).map_err(wrap_io));
+            }
+            try!(self.writer.write_all(part).map_err(wrap_io));
+        }
+        self.writer.write_all(&[0, 1]).map_err(wrap_io)
+    }
+
+    fn add_enum_tag(&mut self, tag: usize) -> SerializeResult<()> {
+        self.writer.write_u32::<BigEndian>(try!(::format::variant_tag(tag))).map_err(wrap_io)
+    }
+}
+
+impl<'a, W: Write> serde::Serializer for KeySerializer<'a, W> {
+    type Error = SerializeError;
+
+    fn serialize_unit(&mut self) -> SerializeResult<()> { Ok(()) }
+
+    fn serialize_bool(&mut self, v: bool) -> SerializeResult<()> {
+        self.writer.write_u8(if v {1} else {0}).map_err(wrap_io)
+    }
+
+    fn serialize_u8(&mut self, v: u8) -> SerializeResult<()> {
+        self.writer.write_u8(v).map_err(wrap_io)
+    }
+
+    fn serialize_u16(&mut self, v: u16) -> SerializeResult<()> {
+        self.writer.write_u16::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    fn serialize_u32(&mut self, v: u32) -> SerializeResult<()> {
+        self.writer.write_u32::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    fn serialize_u64(&mut self, v: u64) -> SerializeResult<()> {
+        self.writer.write_u64::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    fn serialize_i8(&mut self, v: i8) -> SerializeResult<()> {
+        self.writer.write_u8(v as u8 ^ 0x80).map_err(wrap_io)
+    }
+
+    fn serialize_i16(&mut self, v: i16) -> SerializeResult<()> {
+        self.writer.write_u16::<BigEndian>(v as u16 ^ 0x8000).map_err(wrap_io)
+    }
+
+    fn serialize_i32(&mut self, v: i32) -> SerializeResult<()> {
+        self.writer.write_u32::<BigEndian>(v as u32 ^ 0x8000_0000).map_err(wrap_io)
+    }
+
+    fn serialize_i64(&mut self, v: i64) -> SerializeResult<()> {
+        self.writer.write_u64::<BigEndian>(v as u64 ^ SIGN_64).map_err(wrap_io)
+    }
+
+    fn serialize_f32(&mut self, v: f32) -> SerializeResult<()> {
+        let bits = v.to_bits();
+        let bits = if bits & 0x8000_0000 != 0 { !bits } else { bits ^ 0x8000_0000 };
+        self.writer.write_u32::<BigEndian>(bits).map_err(wrap_io)
+    }
+
+    fn serialize_f64(&mut self, v: f64) -> SerializeResult<()> {
+        let bits = v.to_bits();
+        let bits = if bits & SIGN_64 != 0 { !bits } else { bits ^ SIGN_64 };
+        self.writer.write_u64::<BigEndian>(bits).map_err(wrap_io)
+    }
+
+    fn serialize_char(&mut self, v: char) -> SerializeResult<()> {
+        self.writer.write_u32::<BigEndian>(v as u32).map_err(wrap_io)
+    }
+
+    fn serialize_str(&mut self, v: &str) -> SerializeResult<()> {
+        self.write_escaped(v.as_bytes())
+    }
+
+    fn serialize_bytes(&mut self, v: &[u8]) -> SerializeResult<()> {
+        self.write_escaped(v)
+    }
+
+    fn serialize_none(&mut self) -> SerializeResult<()> {
+        self.writer.write_u8(0).map_err(wrap_io)
+    }
+
+    fn serialize_some<T>(&mut self, v: T) -> SerializeResult<()>
+        where T: serde::Serialize,
+    {
+        try!(self.writer.write_u8(1).map_err(wrap_io));
+        v.serialize(self)
+    }
+
+    fn serialize_seq<V>(&mut self, mut visitor: V) -> SerializeResult<()>
+        where V: serde::ser::SeqVisitor,
+    {
+        while let Some(()) = try!(visitor.visit(self)) { }
+
+        self.writer.write_u8(0).map_err(wrap_io)
+    }
+
+    fn serialize_seq_elt<V>(&mut self, value: V) -> SerializeResult<()>
+        where V: serde::Serialize,
+    {
+        try!(self.writer.write_u8(1).map_err(wrap_io));
+        value.serialize(self)
+    }
+
+    fn serialize_tuple<V>(&mut self, mut visitor: V) -> SerializeResult<()>
+        where V: serde::ser::SeqVisitor,
+    {
+        while let Some(()) = try!(visitor.visit(self)) { }
+
+        Ok(())
+    }
+
+    fn serialize_tuple_elt<V>(&mut self, value: V) -> SerializeResult<()>
+        where V: serde::Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_map<V>(&mut self, mut visitor: V) -> SerializeResult<()>
+        where V: serde::ser::MapVisitor,
+    {
+        while let Some(()) = try!(visitor.visit(self)) { }
+
+        self.writer.write_u8(0).map_err(wrap_io)
+    }
+
+    fn serialize_map_elt<K, V>(&mut self, key: K, value: V) -> SerializeResult<()>
+        where K: serde::Serialize,
+              V: serde::Serialize,
+    {
+        try!(self.writer.write_u8(1).map_err(wrap_io));
+        try!(key.serialize(self));
+        value.serialize(self)
+    }
+
+    fn serialize_struct<V>(&mut self, _name: &str, mut visitor: V) -> SerializeResult<()>
+        where V: serde::ser::MapVisitor,
+    {
+        while let Some(()) = try!(visitor.visit(self)) { }
+
+        Ok(())
+    }
+
+    fn serialize_struct_elt<V>(&mut self, _key: &str, value: V) -> SerializeResult<()>
+        where V: serde::Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T>(&mut self,
+                               _name: &str,
+                               value: T) -> SerializeResult<()>
+        where T: serde::ser::Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(&mut self,
+                          _name: &str,
+                          variant_index: usize,
+                          _variant: &str) -> SerializeResult<()> {
+        self.add_enum_tag(variant_index)
+    }
+
+    fn serialize_newtype_variant<T>(&mut self,
+                                _name: &str,
+                                variant_index: usize,
+                                _variant: &str,
+                                value: T) -> SerializeResult<()>
+        where T: serde::ser::Serialize,
+    {
+        try!(self.add_enum_tag(variant_index));
+        value.serialize(self)
+    }
+
+    fn serialize_tuple_variant<V>(&mut self,
+                              _name: &str,
+                              variant_index: usize,
+                              _variant: &str,
+                              mut visitor: V) -> SerializeResult<()>
+        where V: serde::ser::SeqVisitor,
+    {
+        try!(self.add_enum_tag(variant_index));
+
+        while let Some(()) = try!(visitor.visit(self)) { }
+
+        Ok(())
+    }
+
+    fn serialize_struct_variant<V>(&mut self,
+                               _name: &str,
+                               variant_index: usize,
+                               _variant: &str,
+                               mut visitor: V) -> SerializeResult<()>
+        where V: serde::ser::MapVisitor,
+    {
+        try!(self.add_enum_tag(variant_index));
+
+        while let Some(()) = try!(visitor.visit(self)) { }
+
+        Ok(())
+    }
+}
+
+/// A Deserializer that reads the order-preserving encoding out of a slice.
+/// See `deserialize_key`.
+pub struct KeyDeserializer<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> KeyDeserializer<'a> {
+    pub fn new(input: &'a [u8]) -> KeyDeserializer<'a> {
+        KeyDeserializer {
+            input: input,
+        }
+    }
+
+    /// Returns the bytes that haven't been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.input
+    }
+
+    fn take(&mut self, count: usize) -> DeserializeResult<&'a [u8]> {
+        if self.input.len() < count {
+            return Err(serde::de::Error::end_of_stream());
+        }
+        let (taken, rest) = self.input.split_at(count);
+        self.input = rest;
+        Ok(taken)
+    }
+
+    fn read_u8(&mut self) -> DeserializeResult<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn read_u16(&mut self) -> DeserializeResult<u16> {
+        self.take(2).map(BigEndian::read_u16)
+    }
+
+    fn read_u32(&mut self) -> DeserializeResult<u32> {
+        self.take(4).map(BigEndian::read_u32)
+    }
+
+    fn read_u64(&mut self) -> DeserializeResult<u64> {
+        self.take(8).map(BigEndian::read_u64)
+    }
+
+    fn read_i8(&mut self) -> DeserializeResult<i8> {
+        self.read_u8().map(|v| (v ^ 0x80) as i8)
+    }
+
+    fn read_i16(&mut self) -> DeserializeResult<i16> {
+        self.read_u16().map(|v| (v ^ 0x8000) as i16)
+    }
+
+    fn read_i32(&mut self) -> DeserializeResult<i32> {
+        self.read_u32().map(|v| (v ^ 0x8000_0000) as i32)
+    }
+
+    fn read_i64(&mut self) -> DeserializeResult<i64> {
+        self.read_u64().map(|v| (v ^ SIGN_64) as i64)
+    }
+
+    fn read_f32(&mut self) -> DeserializeResult<f32> {
+        let bits = try!(self.read_u32());
+        Ok(f32::from_bits(if bits & 0x8000_0000 != 0 { bits ^ 0x8000_0000 } else { !bits }))
+    }
+
+    fn read_f64(&mut self) -> DeserializeResult<f64> {
+        let bits = try!(self.read_u64());
+        Ok(f64::from_bits(if bits & SIGN_64 != 0 { bits ^ SIGN_64 } else { !bits }))
+    }
+
+    /// Reads an escaped string or byte buffer, up to and including its end.
+    fn read_escaped(&mut self) -> DeserializeResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        loop {
+            let zero = match self.input.iter().position(|&b| b == 0) {
+                Some(zero) => zero,
+                None => return Err(serde::de::Error::end_of_stream()),
+            };
+            bytes.extend_from_slice(&self.input[..zero]);
+            let escape = try!(self.take(zero + 2))[zero + 1];
+            match escape {
+                0xff => bytes.push(0),
+                1 => return Ok(bytes),
+                _ => return Err(serde::de::Error::custom(
+                    format!("invalid escape 00 {:02x} in a key string", escape))),
+            }
+        }
+    }
+
+    /// Reads the marker in front of an element of a sequence or map.
+    fn read_marker(&mut self) -> DeserializeResult<bool> {
+        match try!(self.read_u8()) {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(serde::de::Error::custom(
+                format!("invalid element marker in a key, expected 0 or 1, got {}", value))),
+        }
+    }
+
+    fn deserialize_fields<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        struct FieldVisitor<'a, 'b: 'a> {
+            deserializer: &'a mut KeyDeserializer<'b>,
+        }
+
+        impl<'a, 'b: 'a> serde::de::SeqVisitor for FieldVisitor<'a, 'b> {
+            type Error = DeserializeError;
+
+            fn visit<T>(&mut self) -> Result<Option<T>, Self::Error>
+                where T: serde::de::Deserialize,
+            {
+                serde::Deserialize::deserialize(self.deserializer).map(Some)
+            }
+
+            fn end(&mut self) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        visitor.visit_seq(FieldVisitor { deserializer: self })
+    }
+}
+
+macro_rules! impl_nums {
+    ($dser_method:ident, $visitor_method:ident, $reader_method:ident) => {
+        #[inline]
+        fn $dser_method<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+            where V: serde::de::Visitor,
+        {
+            let value = try!(self.$reader_method());
+            visitor.$visitor_method(value)
+        }
+    }
+}
+
+impl<'a> serde::Deserializer for KeyDeserializer<'a> {
+    type Error = DeserializeError;
+
+    #[inline]
+    fn deserialize<V>(&mut self, _visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let message = "bincode does not support Deserializer::deserialize";
+        Err(DeserializeError::Serde(serde::de::value::Error::Custom(message.into())))
+    }
+
+    fn deserialize_bool<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        match try!(self.read_u8()) {
+            1 => visitor.visit_bool(true),
+            0 => visitor.visit_bool(false),
+            value => Err(serde::de::Error::custom(
+                format!("invalid u8 when decoding bool, expected 0 or 1, got {}", value))),
+        }
+    }
+
+    impl_nums!(deserialize_u8, visit_u8, read_u8);
+    impl_nums!(deserialize_u16, visit_u16, read_u16);
+    impl_nums!(deserialize_u32, visit_u32, read_u32);
+    impl_nums!(deserialize_u64, visit_u64, read_u64);
+    impl_nums!(deserialize_i8, visit_i8, read_i8);
+    impl_nums!(deserialize_i16, visit_i16, read_i16);
+    impl_nums!(deserialize_i32, visit_i32, read_i32);
+    impl_nums!(deserialize_i64, visit_i64, read_i64);
+    impl_nums!(deserialize_f32, visit_f32, read_f32);
+    impl_nums!(deserialize_f64, visit_f64, read_f64);
+
+    #[inline]
+    fn deserialize_usize<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = try!(self.read_u64());
+        match ::num_traits::cast(value) {
+            Some(value) => visitor.visit_usize(value),
+            None => Err(serde::de::Error::custom("expected usize")),
+        }
+    }
+
+    #[inline]
+    fn deserialize_isize<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = try!(self.read_i64());
+        match ::num_traits::cast(value) {
+            Some(value) => visitor.visit_isize(value),
+            None => Err(serde::de::Error::custom("expected isize")),
+        }
+    }
+
+    fn deserialize_unit<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_char<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let value = try!(self.read_u32());
+        match ::std::char::from_u32(value) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(serde::de::Error::custom(format!("invalid char {:#x} in a key", value))),
+        }
+    }
+
+    fn deserialize_str<V>(&mut self, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let bytes = try!(self.read_escaped());
+        match String::from_utf8(bytes) {
+            Ok(s) => visitor.visit_string(s),
+            Err(err) => Err(invalid_utf8(err.utf8_error())),
+        }
+    }
+
+    fn deserialize_bytes<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let bytes = try!(self.read_escaped());
+        visitor.visit_byte_buf(bytes)
+    }
+
+    fn deserialize_enum<V>(&mut self,
+                     _enum: &'static str,
+                     _variants: &'static [&'static str],
+                     mut visitor: V) -> Result<V::Value, Self::Error>
+        where V: serde::de::EnumVisitor,
+    {
+        visitor.visit(self)
+    }
+
+    fn deserialize_tuple<V>(&mut self,
+                      _len: usize,
+                      visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_fields(visitor)
+    }
+
+    fn deserialize_fixed_size_array<V>(&mut self,
+                            _: usize,
+                            visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        match try!(self.read_u8()) {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            value => Err(serde::de::Error::custom(
+                format!("invalid tag when decoding Option, expected 0 or 1, got {}", value))),
+        }
+    }
+
+    fn deserialize_seq<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        struct SeqVisitor<'a, 'b: 'a> {
+            deserializer: &'a mut KeyDeserializer<'b>,
+            done: bool,
+        }
+
+        impl<'a, 'b: 'a> serde::de::SeqVisitor for SeqVisitor<'a, 'b> {
+            type Error = DeserializeError;
+
+            fn visit<T>(&mut self) -> Result<Option<T>, Self::Error>
+                where T: serde::de::Deserialize,
+            {
+                if self.done || !try!(self.deserializer.read_marker()) {
+                    self.done = true;
+                    return Ok(None);
+                }
+                serde::Deserialize::deserialize(self.deserializer).map(Some)
+            }
+
+            fn end(&mut self) -> Result<(), Self::Error> {
+                if self.done || !try!(self.deserializer.read_marker()) {
+                    self.done = true;
+                    Ok(())
+                } else {
+                    Err(serde::de::Error::custom("expected end"))
+                }
+            }
+        }
+
+        visitor.visit_seq(SeqVisitor { deserializer: self, done: false })
+    }
+
+    fn deserialize_map<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        struct MapVisitor<'a, 'b: 'a> {
+            deserializer: &'a mut KeyDeserializer<'b>,
+            done: bool,
+        }
+
+        impl<'a, 'b: 'a> serde::de::MapVisitor for MapVisitor<'a, 'b> {
+            type Error = DeserializeError;
+
+            fn visit_key<K>(&mut self) -> Result<Option<K>, Self::Error>
+                where K: serde::de::Deserialize,
+            {
+                if self.done || !try!(self.deserializer.read_marker()) {
+                    self.done = true;
+                    return Ok(None);
+                }
+                serde::Deserialize::deserialize(self.deserializer).map(Some)
+            }
+
+            fn visit_value<V>(&mut self) -> Result<V, Self::Error>
+                where V: serde::de::Deserialize,
+            {
+                serde::Deserialize::deserialize(self.deserializer)
+            }
+
+            fn end(&mut self) -> Result<(), Self::Error> {
+                if self.done || !try!(self.deserializer.read_marker()) {
+                    self.done = true;
+                    Ok(())
+                } else {
+                    Err(serde::de::Error::custom("expected end"))
+                }
+            }
+        }
+
+        visitor.visit_map(MapVisitor { deserializer: self, done: false })
+    }
+
+    fn deserialize_struct<V>(&mut self,
+                       _name: &str,
+                       _fields: &'static [&'static str],
+                       visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_fields(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(&mut self,
+                               _name: &str,
+                               mut visitor: V) -> Result<V::Value, Self::Error>
+        where V: serde::de::Visitor,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+}
+
+impl<'a> serde::de::VariantVisitor for KeyDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn visit_variant<V>(&mut self) -> Result<V, Self::Error>
+        where V: serde::Deserialize,
+    {
+        let index = try!(self.read_u32());
+        let mut deserializer = (index as usize).into_deserializer();
+        let attempt: Result<V, serde::de::value::Error> = serde::Deserialize::deserialize(&mut deserializer);
+        Ok(try!(attempt))
+    }
+
+    fn visit_unit(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn visit_newtype<T>(&mut self) -> Result<T, Self::Error>
+        where T: serde::de::Deserialize,
+    {
+        serde::de::Deserialize::deserialize(self)
+    }
+
+    fn visit_tuple<V>(&mut self,
+                      _len: usize,
+                      visitor: V) -> Result<V::Value, Self::Error>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_fields(visitor)
+    }
+
+    fn visit_struct<V>(&mut self,
+                       _fields: &'static [&'static str],
+                       visitor: V) -> Result<V::Value, Self::Error>
+        where V: serde::de::Visitor,
+    {
+        self.deserialize_fields(visitor)
+    }
+}
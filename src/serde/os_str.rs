@@ -0,0 +1,241 @@
+//! An encoding for `OsString` and `PathBuf` that keeps names which aren't
+//! valid unicode.
+//!
+//! serde's own impls for paths refuse to serialize a path that isn't valid
+//! utf8, and serde has none at all for `OsString`.  Unix file names are only
+//! bytes, though, and Windows ones are only `u16`s, so a backup of a real
+//! file system will run into names that can't be written that way.
+//!
+//! Here, an `OsStr` is encoded like this enum:
+//!
+//! ```rust,ignore
+//! enum OsString {
+//!     Utf8(String),
+//!     Unix(Vec<u8>),
+//!     Windows(Vec<u16>),
+//! }
+//! ```
+//!
+//! Names that are valid unicode are always written as `Utf8`, and can be
+//! read back on any platform.  Otherwise, a name is written as the bytes a
+//! unix system uses for it, or as the wide characters (potentially
+//! ill-formed UTF-16) a Windows system uses, and comes back exactly the same
+//! on that same kind of platform.  Reading a `Unix` name on Windows, or a
+//! `Windows` name on unix, is an error: there's no name on the other
+//! platform that it could be turned into without losing something.  On
+//! platforms that are neither, only `Utf8` names can be written or read.
+//!
+//! Use `PortableOsString` and `PortablePathBuf` in place of `OsString` and
+//! `PathBuf`, or point `serialize_with` and `deserialize_with` at the
+//! functions in this module.
+
+use std::ffi::{OsStr, OsString};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use serde_crate as serde;
+use serde_crate::bytes::ByteBuf;
+
+const VARIANTS: &'static [&'static str] = &["Utf8", "Unix", "Windows"];
+
+/// An `OsString` that is encoded without losing names that aren't valid
+/// unicode.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct PortableOsString(pub OsString);
+
+/// A `PathBuf` that is encoded without losing names that aren't valid
+/// unicode.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct PortablePathBuf(pub PathBuf);
+
+impl PortableOsString {
+    /// Unwraps the `OsString`.
+    pub fn into_inner(self) -> OsString {
+        self.0
+    }
+}
+
+impl PortablePathBuf {
+    /// Unwraps the `PathBuf`.
+    pub fn into_inner(self) -> PathBuf {
+        self.0
+    }
+}
+
+impl Deref for PortableOsString {
+    type Target = OsStr;
+
+    fn deref(&self) -> &OsStr {
+        &self.0
+    }
+}
+
+impl Deref for PortablePathBuf {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl From<OsString> for PortableOsString {
+    fn from(s: OsString) -> PortableOsString {
+        PortableOsString(s)
+    }
+}
+
+impl From<PathBuf> for PortablePathBuf {
+    fn from(path: PathBuf) -> PortablePathBuf {
+        PortablePathBuf(path)
+    }
+}
+
+impl serde::Serialize for PortableOsString {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serialize_os_str(&self.0, serializer)
+    }
+}
+
+impl serde::Deserialize for PortableOsString {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<PortableOsString, D::Error> {
+        deserialize_os_string(deserializer).map(PortableOsString)
+    }
+}
+
+impl serde::Serialize for PortablePathBuf {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serialize_path(&self.0, serializer)
+    }
+}
+
+impl serde::Deserialize for PortablePathBuf {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<PortablePathBuf, D::Error> {
+        deserialize_path_buf(deserializer).map(PortablePathBuf)
+    }
+}
+
+/// Serializes an `OsStr`, for use with `serialize_with`.
+pub fn serialize_os_str<S: serde::Serializer>(s: &OsStr, serializer: &mut S) -> Result<(), S::Error> {
+    match s.to_str() {
+        Some(s) => serializer.serialize_newtype_variant("OsString", 0, "Utf8", s),
+        None => serialize_native(s, serializer),
+    }
+}
+
+/// Deserializes an `OsString`, for use with `deserialize_with`.
+pub fn deserialize_os_string<D: serde::Deserializer>(deserializer: &mut D) -> Result<OsString, D::Error> {
+    deserializer.deserialize_enum("OsString", VARIANTS, OsStringVisitor)
+}
+
+/// Serializes a `Path`, for use with `serialize_with`.
+pub fn serialize_path<S: serde::Serializer>(path: &Path, serializer: &mut S) -> Result<(), S::Error> {
+    serialize_os_str(path.as_os_str(), serializer)
+}
+
+/// Deserializes a `PathBuf`, for use with `deserialize_with`.
+pub fn deserialize_path_buf<D: serde::Deserializer>(deserializer: &mut D) -> Result<PathBuf, D::Error> {
+    deserialize_os_string(deserializer).map(PathBuf::from)
+}
+
+#[cfg(unix)]
+fn serialize_native<S: serde::Serializer>(s: &OsStr, serializer: &mut S) -> Result<(), S::Error> {
+    use std::os::unix::ffi::OsStrExt;
+    serializer.serialize_newtype_variant("OsString", 1, "Unix", serde::bytes::Bytes::from(s.as_bytes()))
+}
+
+#[cfg(windows)]
+fn serialize_native<S: serde::Serializer>(s: &OsStr, serializer: &mut S) -> Result<(), S::Error> {
+    use std::os::windows::ffi::OsStrExt;
+    let wide: Vec<u16> = s.encode_wide().collect();
+    serializer.serialize_newtype_variant("OsString", 2, "Windows", wide)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn serialize_native<S: serde::Serializer>(_: &OsStr, _: &mut S) -> Result<(), S::Error> {
+    Err(serde::ser::Error::invalid_value("OsString is not valid unicode"))
+}
+
+#[cfg(unix)]
+fn from_unix<E: serde::de::Error>(bytes: Vec<u8>) -> Result<OsString, E> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn from_unix<E: serde::de::Error>(bytes: Vec<u8>) -> Result<OsString, E> {
+    String::from_utf8(bytes)
+        .map(OsString::from)
+        .map_err(|_| E::invalid_value("a unix name that is not valid utf8 can't be read on this platform"))
+}
+
+#[cfg(windows)]
+fn from_windows<E: serde::de::Error>(wide: Vec<u16>) -> Result<OsString, E> {
+    use std::os::windows::ffi::OsStringExt;
+    Ok(OsString::from_wide(&wide))
+}
+
+#[cfg(not(windows))]
+fn from_windows<E: serde::de::Error>(wide: Vec<u16>) -> Result<OsString, E> {
+    String::from_utf16(&wide)
+        .map(OsString::from)
+        .map_err(|_| E::invalid_value("a Windows name that is not valid UTF-16 can't be read on this platform"))
+}
+
+enum Tag {
+    Utf8,
+    Unix,
+    Windows,
+}
+
+impl serde::Deserialize for Tag {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Tag, D::Error> {
+        deserializer.deserialize(TagVisitor)
+    }
+}
+
+struct TagVisitor;
+
+impl serde::de::Visitor for TagVisitor {
+    type Value = Tag;
+
+    fn visit_usize<E: serde::de::Error>(&mut self, value: usize) -> Result<Tag, E> {
+        match value {
+            0 => Ok(Tag::Utf8),
+            1 => Ok(Tag::Unix),
+            2 => Ok(Tag::Windows),
+            _ => Err(E::unknown_field(&value.to_string())),
+        }
+    }
+
+    fn visit_str<E: serde::de::Error>(&mut self, value: &str) -> Result<Tag, E> {
+        match value {
+            "Utf8" => Ok(Tag::Utf8),
+            "Unix" => Ok(Tag::Unix),
+            "Windows" => Ok(Tag::Windows),
+            _ => Err(E::unknown_field(value)),
+        }
+    }
+}
+
+struct OsStringVisitor;
+
+impl serde::de::EnumVisitor for OsStringVisitor {
+    type Value = OsString;
+
+    fn visit<V: serde::de::VariantVisitor>(&mut self, mut visitor: V) -> Result<OsString, V::Error> {
+        match try!(visitor.visit_variant()) {
+            Tag::Utf8 => {
+                let s: String = try!(visitor.visit_newtype());
+                Ok(OsString::from(s))
+            }
+            Tag::Unix => {
+                let bytes: ByteBuf = try!(visitor.visit_newtype());
+                from_unix(bytes.into())
+            }
+            Tag::Windows => {
+                let wide: Vec<u16> = try!(visitor.visit_newtype());
+                from_windows(wide)
+            }
+        }
+    }
+}
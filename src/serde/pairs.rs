@@ -0,0 +1,191 @@
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use serde_crate as serde;
+use serde_crate::ser::impls::{SeqIteratorVisitor, MapIteratorVisitor};
+
+/// A map that is serialized as a sequence of key-value pairs.
+///
+/// In bincode's own layout a map and a sequence of pairs take the same
+/// bytes, a `u64` length and then the keys and values in turn.  They are
+/// still different types to serde: `trace` describes the one as a `Map`
+/// and the other as a `Seq` of `Tuple`s, and so does the descriptor that
+/// `export_descriptor` hands to decoders written in other languages, and
+/// so does every other serde format.  When the other side of a protocol
+/// was written to read a list of pairs, wrap the map in a `MapAsSeq`
+/// rather than copying it into a `Vec`.
+///
+/// When it is read, a key that comes more than once keeps its last value.
+///
+/// ```rust,ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Headers {
+///     values: MapAsSeq<HashMap<String, String>>,
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct MapAsSeq<M>(pub M);
+
+impl<M> MapAsSeq<M> {
+    /// Unwraps the map.
+    pub fn into_inner(self) -> M {
+        self.0
+    }
+}
+
+impl<M> Deref for MapAsSeq<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.0
+    }
+}
+
+impl<M> From<M> for MapAsSeq<M> {
+    fn from(map: M) -> MapAsSeq<M> {
+        MapAsSeq(map)
+    }
+}
+
+/// Key-value pairs that are serialized as a map.
+///
+/// This is the other way around from `MapAsSeq`, for a protocol that
+/// expects a map where the program has a list: the pairs are written and
+/// read back in order, duplicate keys and all.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct SeqAsMap<K, V>(pub Vec<(K, V)>);
+
+impl<K, V> SeqAsMap<K, V> {
+    /// Unwraps the pairs.
+    pub fn into_inner(self) -> Vec<(K, V)> {
+        self.0
+    }
+}
+
+impl<K, V> Deref for SeqAsMap<K, V> {
+    type Target = Vec<(K, V)>;
+
+    fn deref(&self) -> &Vec<(K, V)> {
+        &self.0
+    }
+}
+
+impl<K, V> From<Vec<(K, V)>> for SeqAsMap<K, V> {
+    fn from(pairs: Vec<(K, V)>) -> SeqAsMap<K, V> {
+        SeqAsMap(pairs)
+    }
+}
+
+impl<K, V, H> serde::Serialize for MapAsSeq<HashMap<K, V, H>>
+    where K: serde::Serialize + Eq + Hash, V: serde::Serialize, H: BuildHasher,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_seq(SeqIteratorVisitor::new(self.0.iter(), Some(self.0.len())))
+    }
+}
+
+impl<K, V, H> serde::Deserialize for MapAsSeq<HashMap<K, V, H>>
+    where K: serde::Deserialize + Eq + Hash, V: serde::Deserialize, H: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<MapAsSeq<HashMap<K, V, H>>, D::Error> {
+        deserializer.deserialize_seq(PairsVisitor(PhantomData))
+    }
+}
+
+impl<K, V> serde::Serialize for MapAsSeq<BTreeMap<K, V>>
+    where K: serde::Serialize + Ord, V: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_seq(SeqIteratorVisitor::new(self.0.iter(), Some(self.0.len())))
+    }
+}
+
+impl<K, V> serde::Deserialize for MapAsSeq<BTreeMap<K, V>>
+    where K: serde::Deserialize + Ord, V: serde::Deserialize,
+{
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<MapAsSeq<BTreeMap<K, V>>, D::Error> {
+        deserializer.deserialize_seq(PairsVisitor(PhantomData))
+    }
+}
+
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for SeqAsMap<K, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        let entries = self.0.iter().map(|&(ref key, ref value)| (key, value));
+        serializer.serialize_map(MapIteratorVisitor::new(entries, Some(self.0.len())))
+    }
+}
+
+impl<K: serde::Deserialize, V: serde::Deserialize> serde::Deserialize for SeqAsMap<K, V> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<SeqAsMap<K, V>, D::Error> {
+        deserializer.deserialize_map(PairsVisitor(PhantomData))
+    }
+}
+
+/// Collects key-value pairs into `W`, whether they come as a sequence of
+/// pairs or as a map.
+struct PairsVisitor<K, V, W>(PhantomData<(K, V, W)>);
+
+/// A collection of key-value pairs that `PairsVisitor` can fill.
+trait Pairs<K, V> {
+    fn with_capacity(capacity: usize) -> Self;
+    fn push(&mut self, key: K, value: V);
+}
+
+impl<K: Eq + Hash, V, H: BuildHasher + Default> Pairs<K, V> for MapAsSeq<HashMap<K, V, H>> {
+    fn with_capacity(capacity: usize) -> Self {
+        MapAsSeq(HashMap::with_capacity_and_hasher(capacity, H::default()))
+    }
+
+    fn push(&mut self, key: K, value: V) {
+        self.0.insert(key, value);
+    }
+}
+
+impl<K: Ord, V> Pairs<K, V> for MapAsSeq<BTreeMap<K, V>> {
+    fn with_capacity(_: usize) -> Self {
+        MapAsSeq(BTreeMap::new())
+    }
+
+    fn push(&mut self, key: K, value: V) {
+        self.0.insert(key, value);
+    }
+}
+
+impl<K, V> Pairs<K, V> for SeqAsMap<K, V> {
+    fn with_capacity(capacity: usize) -> Self {
+        SeqAsMap(Vec::with_capacity(capacity))
+    }
+
+    fn push(&mut self, key: K, value: V) {
+        self.0.push((key, value));
+    }
+}
+
+impl<K, V, W> serde::de::Visitor for PairsVisitor<K, V, W>
+    where K: serde::Deserialize, V: serde::Deserialize, W: Pairs<K, V> + serde::Deserialize,
+{
+    type Value = W;
+
+    fn visit_seq<A: serde::de::SeqVisitor>(&mut self, mut visitor: A) -> Result<W, A::Error> {
+        // The length comes from the input, so don't trust it with too much
+        // memory up front.
+        let mut pairs = W::with_capacity(cmp::min(visitor.size_hint().0, 4096));
+        while let Some((key, value)) = try!(visitor.visit()) {
+            pairs.push(key, value);
+        }
+        try!(visitor.end());
+        Ok(pairs)
+    }
+
+    fn visit_map<A: serde::de::MapVisitor>(&mut self, mut visitor: A) -> Result<W, A::Error> {
+        let mut pairs = W::with_capacity(cmp::min(visitor.size_hint().0, 4096));
+        while let Some((key, value)) = try!(visitor.visit()) {
+            pairs.push(key, value);
+        }
+        try!(visitor.end());
+        Ok(pairs)
+    }
+}
@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fmt;
+
+use serde_crate as serde;
+
+use ::Infinite;
+use super::observer::Observer;
+use super::read::SliceReader;
+use super::reader::{Deserializer, DeserializeError};
+
+/// What `deserialize_partial` could make of a message it failed to decode.
+#[derive(Debug)]
+pub struct PartialDecode<T> {
+    /// The error that stopped decoding.
+    pub error: DeserializeError,
+    /// The field that was being decoded when the error happened, as
+    /// `.name` for struct fields and `[3]` for elements of sequences and
+    /// maps, like `.frames[3].function`.  It is empty if the error happened
+    /// before any field was reached.
+    pub path: String,
+    /// The offset at which that field starts.  Everything before it was
+    /// decoded successfully.
+    pub offset: u64,
+    /// The message decoded up to the innermost struct field that failed,
+    /// with that field and every field after it set to its empty value, or
+    /// `None` if that isn't possible, such as when the failure isn't inside
+    /// a struct.
+    pub value: Option<T>,
+}
+
+impl<T: fmt::Debug> fmt::Display for PartialDecode<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(fmt, "{} at offset {}", self.error, self.offset)
+        } else {
+            write!(fmt, "{} in {} at offset {}", self.error, self.path, self.offset)
+        }
+    }
+}
+
+impl<T: fmt::Debug> Error for PartialDecode<T> {
+    fn description(&self) -> &str {
+        self.error.description()
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.error)
+    }
+}
+
+/// Deserializes a slice of bytes into an object, salvaging what it can if
+/// the bytes turn out to be truncated or corrupt.
+///
+/// On success this behaves like `deserialize`.  On failure the
+/// `PartialDecode` says where decoding got to, and holds the value decoded
+/// with the struct field that failed and every field after it filled in
+/// with empty values, as `deserialize_with_defaults` would.  This is meant
+/// for things like crash reports, where part of a message is much better
+/// than none of it.
+///
+/// ```rust,ignore
+/// match bincode::serde::deserialize_partial::<Report>(&bytes) {
+///     Ok(report) => store(report),
+///     Err(partial) => {
+///         warn!("report damaged: {}", partial);
+///         if let Some(report) = partial.value {
+///             store(report);
+///         }
+///     }
+/// }
+/// ```
+pub fn deserialize_partial<T>(bytes: &[u8]) -> Result<T, PartialDecode<T>>
+    where T: serde::Deserialize,
+{
+    let mut tracker = PathTracker { frames: Vec::new() };
+    let error = {
+        let mut deserializer = Deserializer::with_observer(SliceReader::new(bytes), Infinite, &mut tracker);
+        match serde::Deserialize::deserialize(&mut deserializer) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        }
+    };
+
+    let value = tracker.salvage_offset().and_then(|offset| {
+        let mut deserializer = Deserializer::new(SliceReader::new(&bytes[..offset as usize]), Infinite);
+        deserializer.set_fill_missing_fields(true);
+        serde::Deserialize::deserialize(&mut deserializer).ok()
+    });
+
+    Err(PartialDecode {
+        error: error,
+        path: tracker.path(),
+        offset: tracker.offset(),
+        value: value,
+    })
+}
+
+/// Keeps track of the field that is being decoded.
+struct PathTracker {
+    frames: Vec<Frame>,
+}
+
+struct Frame {
+    /// The names of the fields, for a struct.
+    fields: Option<&'static [&'static str]>,
+    /// The field or element that is being decoded, and its offset.
+    current: Option<(usize, u64)>,
+}
+
+impl PathTracker {
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for frame in &self.frames {
+            match (frame.fields, frame.current) {
+                (Some(fields), Some((index, _))) => {
+                    path.push('.');
+                    path.push_str(fields.get(index).cloned().unwrap_or("?"));
+                }
+                (None, Some((index, _))) => path.push_str(&format!("[{}]", index)),
+                (_, None) => {}
+            }
+        }
+        path
+    }
+
+    fn offset(&self) -> u64 {
+        self.frames.iter().rev()
+            .filter_map(|frame| frame.current)
+            .map(|(_, offset)| offset)
+            .next()
+            .unwrap_or(0)
+    }
+
+    /// Returns the offset of the innermost struct field that was being
+    /// decoded, which is where the input can be cut off for the decoder
+    /// to fill in the rest.
+    fn salvage_offset(&self) -> Option<u64> {
+        self.frames.iter().rev()
+            .filter(|frame| frame.fields.is_some())
+            .filter_map(|frame| frame.current)
+            .map(|(_, offset)| offset)
+            .next()
+    }
+
+    fn enter(&mut self, fields: Option<&'static [&'static str]>) {
+        self.frames.push(Frame { fields: fields, current: None });
+    }
+
+    fn at(&mut self, offset: u64, index: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.current = Some((index, offset));
+        }
+    }
+}
+
+impl Observer for PathTracker {
+    fn begin_struct(&mut self, _offset: u64, fields: &'static [&'static str]) {
+        self.enter(Some(fields));
+    }
+
+    fn field(&mut self, offset: u64, index: usize) {
+        self.at(offset, index);
+    }
+
+    fn begin_seq(&mut self, _offset: u64, _len: usize) {
+        self.enter(None);
+    }
+
+    fn begin_map(&mut self, _offset: u64, _len: usize) {
+        self.enter(None);
+    }
+
+    fn element(&mut self, offset: u64, index: usize) {
+        self.at(offset, index);
+    }
+
+    fn end(&mut self, _offset: u64) {
+        self.frames.pop();
+    }
+}
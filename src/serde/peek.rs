@@ -0,0 +1,139 @@
+use std::cmp;
+use std::io::{self, Read};
+
+use serde_crate as serde;
+
+use ::SizeLimit;
+use super::deserialize;
+use super::read::IoReader;
+use super::reader::{Deserializer, DeserializeResult};
+
+/// Reads the tag of the enum at the start of a message, which is the index
+/// of its variant, without reading any further.
+///
+/// This is all a router needs to send a message of an enum type to the
+/// worker that handles its variant.
+pub fn peek_variant_index(bytes: &[u8]) -> DeserializeResult<u32> {
+    deserialize(bytes)
+}
+
+/// Reads the first fields of a message as a `T`, without reading any
+/// further.
+///
+/// `T` is laid out like the start of the message, such as a struct of its
+/// first few fields, or a tuple of the enum tag and the fields of a
+/// header every variant starts with.
+pub fn peek_prefix<T: serde::Deserialize>(bytes: &[u8]) -> DeserializeResult<T> {
+    deserialize(bytes)
+}
+
+/// A reader whose next message can be peeked at before it is read.
+///
+/// Peeking reads as much of the message as it needs from the underlying
+/// reader and keeps those bytes, and reading hands them out again before
+/// anything else, so the message is still read whole afterwards.
+/// `peeked` says exactly which bytes have been taken from the underlying
+/// reader but not read yet.
+///
+/// ```rust,ignore
+/// let mut reader = PeekRead::new(stream);
+/// let worker = &workers[reader.peek_variant_index()? as usize % workers.len()];
+/// let request: Request = deserialize_from(&mut reader, Bounded(1 << 20))?;
+/// ```
+pub struct PeekRead<R> {
+    reader: R,
+    peeked: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> PeekRead<R> {
+    /// Creates a reader that reads from `reader`.
+    pub fn new(reader: R) -> PeekRead<R> {
+        PeekRead {
+            reader: reader,
+            peeked: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Reads the tag of the enum at the start of the next message without
+    /// reading past it.  See `peek_variant_index`.
+    pub fn peek_variant_index(&mut self) -> DeserializeResult<u32> {
+        self.peek_prefix(::Bounded(4))
+    }
+
+    /// Reads the first fields of the next message as a `T` without reading
+    /// past them.  See `peek_prefix`.
+    ///
+    /// If the prefix takes more than `size_limit` bytes, a `SizeLimit`
+    /// error is returned.  Whatever was taken from the underlying reader is
+    /// kept either way.
+    pub fn peek_prefix<T, S>(&mut self, size_limit: S) -> DeserializeResult<T>
+        where T: serde::Deserialize, S: SizeLimit,
+    {
+        let mut ahead = Ahead {
+            reader: &mut self.reader,
+            peeked: &mut self.peeked,
+            pos: self.pos,
+        };
+        let mut deserializer = Deserializer::new(IoReader::new(&mut ahead), size_limit);
+        serde::Deserialize::deserialize(&mut deserializer)
+    }
+
+    /// Returns the bytes that have been taken from the underlying reader by
+    /// peeking but not read yet.
+    pub fn peeked(&self) -> &[u8] {
+        &self.peeked[self.pos..]
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps the underlying reader, along with the bytes that were taken
+    /// from it by peeking but not read yet.
+    pub fn into_parts(mut self) -> (Vec<u8>, R) {
+        self.peeked.drain(..self.pos);
+        (self.peeked, self.reader)
+    }
+}
+
+impl<R: Read> Read for PeekRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.peeked.len() {
+            return self.reader.read(buf);
+        }
+        let len = cmp::min(buf.len(), self.peeked.len() - self.pos);
+        buf[..len].copy_from_slice(&self.peeked[self.pos..self.pos + len]);
+        self.pos += len;
+        if self.pos == self.peeked.len() {
+            self.peeked.clear();
+            self.pos = 0;
+        }
+        Ok(len)
+    }
+}
+
+/// Reads the peeked bytes from `pos` on, then more from the underlying
+/// reader, which it keeps with the peeked bytes.
+struct Ahead<'a, R: 'a> {
+    reader: &'a mut R,
+    peeked: &'a mut Vec<u8>,
+    pos: usize,
+}
+
+impl<'a, R: Read> Read for Ahead<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.peeked.len() {
+            let read = try!(self.reader.read(buf));
+            self.peeked.extend_from_slice(&buf[..read]);
+            self.pos += read;
+            return Ok(read);
+        }
+        let len = cmp::min(buf.len(), self.peeked.len() - self.pos);
+        buf[..len].copy_from_slice(&self.peeked[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
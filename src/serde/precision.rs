@@ -0,0 +1,209 @@
+//! Floats that are written at a lower precision than they are held at.
+//!
+//! Telemetry and sensor readings rarely carry more than a few significant
+//! digits, so most of an `f64` on the wire is noise.  `F32Of<f64>` writes
+//! an `f64` as an `f32`, in 4 bytes, and `F16Of<T>` writes an `f32` or
+//! `f64` as an IEEE 754 half-precision float, in 2, which keeps about three
+//! significant digits and values up to 65504.
+//!
+//! Values are rounded to the nearest one the smaller type can hold, and
+//! those out of its range become infinities.  Where a value has to come
+//! back exactly, build the wrapper with `exact`, which only accepts values
+//! that survive the trip; NaN does, though its payload may not.
+
+use std::ops::Deref;
+
+use serde_crate as serde;
+
+/// An `f32` or `f64` that can be converted to and from a half-precision
+/// float.
+pub trait HalfPrecision: Copy {
+    /// Returns the bits of the nearest half-precision float.
+    fn to_f16_bits(self) -> u16;
+
+    /// Returns the value of a half-precision float, which is always exact.
+    fn from_f16_bits(bits: u16) -> Self;
+
+    /// Returns whether this is a NaN.
+    fn is_nan(self) -> bool;
+}
+
+impl HalfPrecision for f64 {
+    fn to_f16_bits(self) -> u16 {
+        f64_to_f16_bits(self)
+    }
+
+    fn from_f16_bits(bits: u16) -> f64 {
+        f16_bits_to_f64(bits)
+    }
+
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+}
+
+impl HalfPrecision for f32 {
+    fn to_f16_bits(self) -> u16 {
+        // Every f32 is exactly an f64, so this rounds only once.
+        f64_to_f16_bits(self as f64)
+    }
+
+    fn from_f16_bits(bits: u16) -> f32 {
+        f16_bits_to_f64(bits) as f32
+    }
+
+    fn is_nan(self) -> bool {
+        f32::is_nan(self)
+    }
+}
+
+/// Rounds to the nearest half-precision float, ties to even.
+fn f64_to_f16_bits(value: f64) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 48) & 0x8000) as u16;
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let mantissa = bits & ((1 << 52) - 1);
+
+    if exponent == 0x7ff {
+        return if mantissa == 0 { sign | 0x7c00 } else { sign | 0x7e00 };
+    }
+    if exponent == 0 {
+        // Zero, or an f64 subnormal, which is far too small for an f16.
+        return sign;
+    }
+
+    let e = exponent - 1023;
+    if e > 15 {
+        return sign | 0x7c00;
+    }
+    // The significand with its implicit bit, and how far to shift it right
+    // to leave the bits an f16 keeps.  A carry out of the rounding moves
+    // into the exponent, which is how it should be, up to infinity.
+    let (significand, shift, base) = if e >= -14 {
+        (mantissa, 42, ((e + 15) as u16) << 10)
+    } else {
+        ((1 << 52) | mantissa, (28 - e) as u32, 0)
+    };
+    if shift >= 64 {
+        return sign;
+    }
+    let kept = significand >> shift;
+    let rest = significand & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let rounded = if rest > half || (rest == half && kept & 1 == 1) { kept + 1 } else { kept };
+    sign | (base + rounded as u16)
+}
+
+fn f16_bits_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+    match exponent {
+        0 => sign * mantissa * 2f64.powi(-24),
+        0x1f if mantissa == 0.0 => sign * ::std::f64::INFINITY,
+        0x1f => ::std::f64::NAN,
+        _ => sign * (1024.0 + mantissa) * 2f64.powi(exponent - 25),
+    }
+}
+
+/// An `f32` or `f64` that is written as a half-precision float, in two
+/// bytes: the `u16` of its bits.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Default)]
+pub struct F16Of<T>(pub T);
+
+impl<T: HalfPrecision> F16Of<T> {
+    /// Wraps `value` if it can be written as a half-precision float and
+    /// read back unchanged, or returns `None` if it can't.
+    pub fn exact(value: T) -> Option<F16Of<T>>
+        where T: PartialEq,
+    {
+        let back = T::from_f16_bits(value.to_f16_bits());
+        if back == value || value.is_nan() {
+            Some(F16Of(value))
+        } else {
+            None
+        }
+    }
+
+    /// Unwraps the number.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for F16Of<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for F16Of<T> {
+    fn from(value: T) -> F16Of<T> {
+        F16Of(value)
+    }
+}
+
+impl<T: HalfPrecision> serde::Serialize for F16Of<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_u16(self.0.to_f16_bits())
+    }
+}
+
+impl<T: HalfPrecision> serde::Deserialize for F16Of<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<F16Of<T>, D::Error> {
+        let bits: u16 = try!(serde::Deserialize::deserialize(deserializer));
+        Ok(F16Of(T::from_f16_bits(bits)))
+    }
+}
+
+/// An `f64` that is written as an `f32`, in four bytes.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Default)]
+pub struct F32Of<T>(pub T);
+
+impl F32Of<f64> {
+    /// Wraps `value` if it can be written as an `f32` and read back
+    /// unchanged, or returns `None` if it can't.
+    pub fn exact(value: f64) -> Option<F32Of<f64>> {
+        if (value as f32) as f64 == value || value.is_nan() {
+            Some(F32Of(value))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> F32Of<T> {
+    /// Unwraps the number.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for F32Of<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for F32Of<T> {
+    fn from(value: T) -> F32Of<T> {
+        F32Of(value)
+    }
+}
+
+impl serde::Serialize for F32Of<f64> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_f32(self.0 as f32)
+    }
+}
+
+impl serde::Deserialize for F32Of<f64> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<F32Of<f64>, D::Error> {
+        let value: f32 = try!(serde::Deserialize::deserialize(deserializer));
+        Ok(F32Of(value as f64))
+    }
+}
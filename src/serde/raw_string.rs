@@ -0,0 +1,88 @@
+use std::fmt;
+use std::ops::Deref;
+use std::str;
+
+use serde_crate as serde;
+
+/// A string that is kept as bytes, whatever its encoding.
+///
+/// It is encoded just like a `String`, and can take the place of one in a
+/// type that has to read strings that may not be valid utf8.  Together with
+/// `Utf8Mode::Raw`, it gets the bytes of the string exactly as they were
+/// written, to be decoded from Latin-1 or whatever else the writer used.
+/// In the other modes, it gets the string after it has been checked or
+/// repaired.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct RawString(pub Vec<u8>);
+
+impl RawString {
+    /// Returns the string if it is valid utf8.
+    pub fn to_str(&self) -> Option<&str> {
+        str::from_utf8(&self.0).ok()
+    }
+
+    /// Unwraps the bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Deref for RawString {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<'a> From<&'a str> for RawString {
+    fn from(s: &'a str) -> RawString {
+        RawString(s.as_bytes().to_vec())
+    }
+}
+
+impl From<Vec<u8>> for RawString {
+    fn from(bytes: Vec<u8>) -> RawString {
+        RawString(bytes)
+    }
+}
+
+impl fmt::Debug for RawString {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "RawString({:?})", String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl serde::Serialize for RawString {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl serde::Deserialize for RawString {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<RawString, D::Error> {
+        deserializer.deserialize_string(RawStringVisitor)
+    }
+}
+
+struct RawStringVisitor;
+
+impl serde::de::Visitor for RawStringVisitor {
+    type Value = RawString;
+
+    fn visit_str<E: serde::de::Error>(&mut self, s: &str) -> Result<RawString, E> {
+        Ok(RawString::from(s))
+    }
+
+    fn visit_string<E: serde::de::Error>(&mut self, s: String) -> Result<RawString, E> {
+        Ok(RawString(s.into_bytes()))
+    }
+
+    fn visit_bytes<E: serde::de::Error>(&mut self, bytes: &[u8]) -> Result<RawString, E> {
+        Ok(RawString(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(&mut self, bytes: Vec<u8>) -> Result<RawString, E> {
+        Ok(RawString(bytes))
+    }
+}
@@ -0,0 +1,590 @@
+use std::cmp;
+use std::io::{self, BufRead, Read};
+use std::ops::Deref;
+use std::str;
+
+use serde_crate as serde;
+
+use super::reader::{DeserializeError, DeserializeResult, invalid_utf8};
+
+/// A source of bytes that the `Deserializer` can read from.
+///
+/// Besides plain `Read` access, a `BincodeRead` knows how to hand out
+/// length-prefixed runs of bytes (strings and byte buffers) in the cheapest
+/// way available to it.  A `SliceReader` can give the visitor a view directly
+/// into the input, while an `IoReader` has to copy through a buffer first.
+///
+/// This trait is implemented for the strategies that ship with bincode.
+/// It is public so that it can appear in the `Deserializer` signature; you
+/// should rarely need to implement it yourself.
+pub trait BincodeRead: Read {
+    /// Reads exactly `length` bytes and passes them to the visitor as a `&str`.
+    fn forward_read_str<V>(&mut self, length: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor;
+
+    /// Reads exactly `length` bytes into a newly allocated buffer.
+    fn get_byte_buffer(&mut self, length: usize) -> DeserializeResult<Vec<u8>>;
+
+    /// Reads exactly `length` bytes and passes them to the visitor as a `&[u8]`.
+    fn forward_read_bytes<V>(&mut self, length: usize, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor;
+
+    /// Returns `true` if there are no more bytes to read.
+    ///
+    /// This may need to pull a byte out of the underlying reader, which is
+    /// then kept around for the next read.
+    fn is_at_end(&mut self) -> DeserializeResult<bool>;
+
+    /// Fills `buf`, which is no more than 16 bytes, with the next bytes of
+    /// the input.
+    ///
+    /// This is how numbers and chars are read, so readers that can hand
+    /// out a few bytes more cheaply than `Read::read_exact` override it.
+    #[inline]
+    fn read_small(&mut self, buf: &mut [u8]) -> DeserializeResult<()> {
+        self.read_exact(buf).map_err(DeserializeError::from)
+    }
+
+    /// Moves past the next `length` bytes without handing them out.
+    ///
+    /// This is how detached attachments are left in the input.  Readers
+    /// that can move past bytes without reading them override it.
+    fn skip(&mut self, length: usize) -> DeserializeResult<()> {
+        let mut chunk = [0; 4096];
+        let mut left = length;
+        while left > 0 {
+            let len = cmp::min(left, chunk.len());
+            try!(self.read_exact(&mut chunk[..len]));
+            left -= len;
+        }
+        Ok(())
+    }
+
+    /// Returns up to `before` bytes before the current position and up to
+    /// `after` bytes after it, along with the offset of the first of them,
+    /// if the reader can still see them.
+    ///
+    /// These are attached to `InvalidEncoding` errors.  Readers that can't
+    /// look back or ahead without consuming input return `None`.
+    fn window(&self, _before: usize, _after: usize) -> Option<(u64, Vec<u8>)> {
+        None
+    }
+}
+
+/// A `BincodeRead` over an in-memory slice of bytes.
+///
+/// Strings and byte buffers are handed to the visitor straight out of the
+/// slice without an intermediate copy.
+#[derive(Clone)]
+pub struct SliceReader<'a> {
+    input: &'a [u8],
+    slice: &'a [u8],
+}
+
+/// A `BincodeRead` over several slices of bytes that are read one after the
+/// other, as if they were one.
+///
+/// This decodes a message that arrived in pieces, such as a frame put
+/// together from several network reads, without first copying the pieces
+/// into one buffer.  The segments can be anything that dereferences to
+/// `[u8]`: slices, `Vec`s, or `std::io::IoSlice`s.  Values may be split
+/// across segments anywhere.  Strings and byte buffers that lie within a
+/// single segment are handed to the visitor straight out of it; those that
+/// span segments are copied together first.
+pub struct SegmentedReader<'a, S: 'a> {
+    segments: &'a [S],
+    offset: usize,
+    temp_buffer: Vec<u8>,
+}
+
+/// A `BincodeRead` over a `std::io::BufRead`, such as a `std::io::BufReader`.
+///
+/// This reads through the reader's own buffer.  Strings and byte buffers
+/// that are in the buffer already are handed to the visitor straight out of
+/// it, and only those that reach past the end of the buffer are copied
+/// together first.  Unlike an `IoReader`, it never reads ahead of the
+/// value it is decoding, so whatever follows is left in the reader.
+pub struct BufferedReader<R> {
+    reader: R,
+    temp_buffer: Vec<u8>,
+}
+
+/// A `BincodeRead` over any `std::io::Read`.
+pub struct IoReader<R> {
+    reader: R,
+    temp_buffer: Vec<u8>,
+    peeked: Option<u8>,
+    // An error from the underlying reader that came after the peeked byte
+    // was handed out, to be returned by the next read.
+    error: Option<io::Error>,
+}
+
+impl<'a> SliceReader<'a> {
+    /// Creates a new `SliceReader` that reads from the start of `bytes`.
+    pub fn new(bytes: &'a [u8]) -> SliceReader<'a> {
+        SliceReader {
+            input: bytes,
+            slice: bytes,
+        }
+    }
+
+    /// Returns the bytes that have not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.slice
+    }
+
+    fn take(&mut self, length: usize) -> DeserializeResult<&'a [u8]> {
+        if length > self.slice.len() {
+            return Err(DeserializeError::IoError(unexpected_eof()));
+        }
+
+        let (read, rest) = self.slice.split_at(length);
+        self.slice = rest;
+        Ok(read)
+    }
+}
+
+impl<'a, S: Deref<Target = [u8]>> SegmentedReader<'a, S> {
+    /// Creates a new `SegmentedReader` that reads from the start of the
+    /// first segment.
+    pub fn new(segments: &'a [S]) -> SegmentedReader<'a, S> {
+        SegmentedReader {
+            segments: segments,
+            offset: 0,
+            temp_buffer: Vec::new(),
+        }
+    }
+
+    /// Returns the number of bytes that have not been read yet.
+    pub fn remaining(&self) -> usize {
+        self.segments.iter().map(|segment| segment.len()).sum::<usize>() - self.offset
+    }
+
+    /// Moves past segments that have been read completely, and returns the
+    /// unread part of the current one.
+    fn current(&mut self) -> &'a [u8] {
+        while let Some((first, rest)) = self.segments.split_first() {
+            if self.offset < first.len() {
+                return &first[self.offset..];
+            }
+            self.segments = rest;
+            self.offset = 0;
+        }
+        &[]
+    }
+
+    /// Takes `length` bytes, straight out of the current segment if they
+    /// all lie within it, or copied into the temporary buffer if they don't.
+    fn take(&mut self, length: usize) -> DeserializeResult<Option<&'a [u8]>> {
+        let current = self.current();
+        if length <= current.len() {
+            self.offset += length;
+            return Ok(Some(&current[..length]));
+        }
+
+        if length > self.remaining() {
+            return Err(DeserializeError::IoError(unexpected_eof()));
+        }
+        let mut temp_buffer = ::std::mem::replace(&mut self.temp_buffer, Vec::new());
+        temp_buffer.clear();
+        let result = read_exact_vec(self, &mut temp_buffer, length);
+        self.temp_buffer = temp_buffer;
+        try!(result);
+        Ok(None)
+    }
+}
+
+impl<R> IoReader<R> {
+    /// Creates a new `IoReader` wrapping `r`.
+    pub fn new(r: R) -> IoReader<R> {
+        IoReader {
+            reader: r,
+            temp_buffer: Vec::new(),
+            peeked: None,
+            error: None,
+        }
+    }
+
+    /// Unwraps this `IoReader`, returning the underlying reader.
+    ///
+    /// A byte that was pulled out by `is_at_end` and not read yet is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> IoReader<R> {
+    fn fill_buffer(&mut self, length: usize) -> DeserializeResult<()> {
+        self.temp_buffer.clear();
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+        let mut length = length;
+        if length > 0 {
+            if let Some(byte) = self.peeked.take() {
+                self.temp_buffer.push(byte);
+                length -= 1;
+            }
+        }
+        // Reading from the inner reader directly lets it fill the buffer
+        // without it being zeroed first.
+        read_exact_vec(&mut self.reader, &mut self.temp_buffer, length)
+    }
+}
+
+impl<R> BufferedReader<R> {
+    /// Creates a new `BufferedReader` wrapping `r`.
+    pub fn new(r: R) -> BufferedReader<R> {
+        BufferedReader {
+            reader: r,
+            temp_buffer: Vec::new(),
+        }
+    }
+
+    /// Unwraps this `BufferedReader`, returning the underlying reader.
+    ///
+    /// Nothing is lost: the bytes that have been buffered but not read are
+    /// still in the reader's buffer.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: BufRead> BufferedReader<R> {
+    /// Hands the next `length` bytes to `visit`, straight out of the
+    /// reader's buffer if they are all in it, or copied into the temporary
+    /// buffer if they aren't.
+    fn with_bytes<F, T>(&mut self, length: usize, visit: F) -> DeserializeResult<T>
+        where F: FnOnce(&[u8]) -> DeserializeResult<T>,
+    {
+        if try!(buffered(&mut self.reader)) >= length {
+            // The buffer isn't empty, so this doesn't read anything more.
+            let result = visit(&try!(self.reader.fill_buf())[..length]);
+            self.reader.consume(length);
+            return result;
+        }
+
+        try!(read_into(&mut self.reader, &mut self.temp_buffer, length));
+        visit(&self.temp_buffer)
+    }
+}
+
+/// Reads exactly `length` bytes into `buffer`, replacing what was in it.
+fn read_into<R: Read>(reader: &mut R, buffer: &mut Vec<u8>, length: usize) -> DeserializeResult<()> {
+    buffer.clear();
+    read_exact_vec(reader, buffer, length)
+}
+
+/// How much `read_exact_vec` reserves before it has read anything.
+const FIRST_CHUNK: usize = 8 * 1024;
+
+/// The longest read that `read_exact_vec` zeroes the buffer for instead of
+/// going through `Read::take`.
+const SMALL_READ: usize = 256;
+
+/// Reads exactly `length` bytes onto the end of `buffer`.
+///
+/// The bytes are read straight into the spare capacity of the `Vec` by
+/// `read_to_end`, which readers like files and sockets fill without it
+/// being zeroed first.  A lying length prefix can't make this allocate the
+/// whole claimed size up front: the `Vec` grows in chunks that are never
+/// larger than what has been read so far, so it only ever holds about twice
+/// as much memory as the input actually had.
+///
+/// Short reads, like most strings in small messages, skip all that: the
+/// `Take` adapter and `read_to_end` cost far more per call than zeroing a
+/// few bytes does, so those are read with `read_exact` instead.
+pub fn read_exact_vec<R: Read>(reader: &mut R, buffer: &mut Vec<u8>, length: usize) -> DeserializeResult<()> {
+    if length <= SMALL_READ {
+        let start = buffer.len();
+        buffer.resize(start + length, 0);
+        return match reader.read_exact(&mut buffer[start..]) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                buffer.truncate(start);
+                Err(err.into())
+            }
+        };
+    }
+
+    let mut remaining = length;
+    let mut read = 0;
+    while remaining > 0 {
+        let chunk = cmp::min(remaining, cmp::max(read, FIRST_CHUNK));
+        buffer.reserve_exact(chunk);
+        if try!(reader.by_ref().take(chunk as u64).read_to_end(buffer)) != chunk {
+            return Err(DeserializeError::IoError(unexpected_eof()));
+        }
+        read += chunk;
+        remaining -= chunk;
+    }
+    Ok(())
+}
+
+/// Fills the buffer of `reader` if it is empty, and returns how many bytes
+/// are in it.
+fn buffered<R: BufRead>(reader: &mut R) -> io::Result<usize> {
+    loop {
+        match reader.fill_buf() {
+            Ok(buffer) => return Ok(buffer.len()),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+impl<'a> Read for SliceReader<'a> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.slice.read(out)
+    }
+}
+
+impl<'a, S: Deref<Target = [u8]>> Read for SegmentedReader<'a, S> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < out.len() {
+            let current = self.current();
+            if current.is_empty() {
+                break;
+            }
+            let len = cmp::min(current.len(), out.len() - read);
+            out[read..read + len].copy_from_slice(&current[..len]);
+            self.offset += len;
+            read += len;
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Read> Read for IoReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        match self.peeked.take() {
+            Some(byte) if !out.is_empty() => {
+                out[0] = byte;
+                // The peeked byte has been handed out, so an error from the
+                // underlying reader has to wait for the next call.  Returning
+                // it now would lose the byte, since `read_exact` retries an
+                // `Interrupted` read from scratch.  An `Interrupted` or
+                // `WouldBlock` read is simply tried again then, but anything
+                // else is kept to be returned.
+                match self.reader.read(&mut out[1..]) {
+                    Ok(read) => Ok(read + 1),
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted ||
+                                    err.kind() == io::ErrorKind::WouldBlock => Ok(1),
+                    Err(err) => {
+                        self.error = Some(err);
+                        Ok(1)
+                    }
+                }
+            }
+            peeked => {
+                self.peeked = peeked;
+                match self.error.take() {
+                    Some(err) => Err(err),
+                    None => self.reader.read(out),
+                }
+            }
+        }
+    }
+}
+
+impl<'a> BincodeRead for SliceReader<'a> {
+    fn forward_read_str<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let bytes = try!(self.take(length));
+        match str::from_utf8(bytes) {
+            Ok(s) => visitor.visit_str(s),
+            Err(err) => Err(invalid_utf8(err)),
+        }
+    }
+
+    fn get_byte_buffer(&mut self, length: usize) -> DeserializeResult<Vec<u8>> {
+        self.take(length).map(|bytes| bytes.to_vec())
+    }
+
+    fn forward_read_bytes<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let bytes = try!(self.take(length));
+        visitor.visit_bytes(bytes)
+    }
+
+    fn is_at_end(&mut self) -> DeserializeResult<bool> {
+        Ok(self.slice.is_empty())
+    }
+
+    #[inline]
+    fn read_small(&mut self, buf: &mut [u8]) -> DeserializeResult<()> {
+        buf.copy_from_slice(try!(self.take(buf.len())));
+        Ok(())
+    }
+
+    fn skip(&mut self, length: usize) -> DeserializeResult<()> {
+        self.take(length).map(|_| ())
+    }
+
+    fn window(&self, before: usize, after: usize) -> Option<(u64, Vec<u8>)> {
+        let position = self.input.len() - self.slice.len();
+        let start = position.saturating_sub(before);
+        let end = cmp::min(position.saturating_add(after), self.input.len());
+        Some((start as u64, self.input[start..end].to_vec()))
+    }
+}
+
+impl<'a, S: Deref<Target = [u8]>> BincodeRead for SegmentedReader<'a, S> {
+    fn forward_read_str<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let bytes = match try!(self.take(length)) {
+            Some(bytes) => bytes,
+            None => &self.temp_buffer[..],
+        };
+        match str::from_utf8(bytes) {
+            Ok(s) => visitor.visit_str(s),
+            Err(err) => Err(invalid_utf8(err)),
+        }
+    }
+
+    fn get_byte_buffer(&mut self, length: usize) -> DeserializeResult<Vec<u8>> {
+        match try!(self.take(length)) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Ok(::std::mem::replace(&mut self.temp_buffer, Vec::new())),
+        }
+    }
+
+    fn forward_read_bytes<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let bytes = match try!(self.take(length)) {
+            Some(bytes) => bytes,
+            None => &self.temp_buffer[..],
+        };
+        visitor.visit_bytes(bytes)
+    }
+
+    fn is_at_end(&mut self) -> DeserializeResult<bool> {
+        Ok(self.current().is_empty())
+    }
+
+    #[inline]
+    fn read_small(&mut self, buf: &mut [u8]) -> DeserializeResult<()> {
+        let current = self.current();
+        if buf.len() <= current.len() {
+            buf.copy_from_slice(&current[..buf.len()]);
+            self.offset += buf.len();
+            return Ok(());
+        }
+        self.read_exact(buf).map_err(DeserializeError::from)
+    }
+
+    fn skip(&mut self, length: usize) -> DeserializeResult<()> {
+        if length > self.remaining() {
+            return Err(DeserializeError::IoError(unexpected_eof()));
+        }
+        let mut left = length;
+        while left > 0 {
+            let step = cmp::min(left, self.current().len());
+            self.offset += step;
+            left -= step;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> BincodeRead for IoReader<R> {
+    fn forward_read_str<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        try!(self.fill_buffer(length));
+        match str::from_utf8(&self.temp_buffer[..]) {
+            Ok(s) => visitor.visit_str(s),
+            Err(err) => Err(invalid_utf8(err)),
+        }
+    }
+
+    fn get_byte_buffer(&mut self, length: usize) -> DeserializeResult<Vec<u8>> {
+        try!(self.fill_buffer(length));
+        Ok(::std::mem::replace(&mut self.temp_buffer, Vec::new()))
+    }
+
+    fn forward_read_bytes<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        try!(self.fill_buffer(length));
+        visitor.visit_bytes(&self.temp_buffer[..])
+    }
+
+    fn is_at_end(&mut self) -> DeserializeResult<bool> {
+        if self.peeked.is_some() {
+            return Ok(false);
+        }
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+
+        let mut buf = [0];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => return Ok(true),
+                Ok(_) => {
+                    self.peeked = Some(buf[0]);
+                    return Ok(false);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for BufferedReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(out)
+    }
+}
+
+impl<R: BufRead> BincodeRead for BufferedReader<R> {
+    fn forward_read_str<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.with_bytes(length, |bytes| match str::from_utf8(bytes) {
+            Ok(s) => visitor.visit_str(s),
+            Err(err) => Err(invalid_utf8(err)),
+        })
+    }
+
+    fn get_byte_buffer(&mut self, length: usize) -> DeserializeResult<Vec<u8>> {
+        let mut buffer = Vec::new();
+        try!(read_into(&mut self.reader, &mut buffer, length));
+        Ok(buffer)
+    }
+
+    fn forward_read_bytes<V>(&mut self, length: usize, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        self.with_bytes(length, |bytes| visitor.visit_bytes(bytes))
+    }
+
+    fn is_at_end(&mut self) -> DeserializeResult<bool> {
+        Ok(try!(buffered(&mut self.reader)) == 0)
+    }
+
+    #[inline]
+    fn read_small(&mut self, buf: &mut [u8]) -> DeserializeResult<()> {
+        let len = buf.len();
+        if try!(buffered(&mut self.reader)) >= len {
+            // The buffer isn't empty, so this doesn't read anything more.
+            buf.copy_from_slice(&try!(self.reader.fill_buf())[..len]);
+            self.reader.consume(len);
+            return Ok(());
+        }
+
+        // The number runs past the end of the buffer.
+        self.reader.read_exact(buf).map_err(DeserializeError::from)
+    }
+}
+
+fn unexpected_eof() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of input")
+}
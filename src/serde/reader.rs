@@ -1,31 +1,79 @@
-use std::io::Read;
 use std::io::Error as IoError;
 use std::error::Error;
+use std::cmp;
 use std::fmt;
+use std::mem;
 use std::convert::From;
+use std::str::{self, Utf8Error};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use num_traits;
 use serde_crate as serde;
-use serde_crate::de::value::ValueDeserializer;
-use serde_crate::de::Deserializer as SerdeDeserializer;
+use serde_crate::de::value::{MapDeserializer, ValueDeserializer};
 
 use ::SizeLimit;
+use ::format;
+use super::bitpack;
+use super::rle;
+use super::scatter;
+use super::unknown;
+use super::varint;
+use super::throttle;
+use super::read::{BincodeRead, SliceReader};
+use super::defaults::DefaultDeserializer;
+use super::config::{Config, Layout};
+use super::observer::{Observer, NoObserver};
+use super::alloc_guard::AllocGuard;
+
+/// How many elements a collection may reserve room for before they have
+/// been read.
+const MAX_PREALLOCATION: usize = 4096;
+
+/// How many bytes on either side of the failure point an `InvalidEncoding`
+/// keeps.
+const RAW_WINDOW: usize = 16;
+
+/// How many elements are read between two looks at the clock when there is
+/// a deadline.
+const DEADLINE_INTERVAL: u32 = 256;
 
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct InvalidEncoding {
     desc: &'static str,
     detail: Option<String>,
+    raw: Option<(u64, Vec<u8>)>,
+}
+
+impl InvalidEncoding {
+    /// Returns the bytes of the input around the point where decoding
+    /// failed, and the offset of the first of them.
+    ///
+    /// Up to 16 bytes are kept on either side, so a bad message can be
+    /// looked into without keeping all of it.  This is only available when
+    /// the `Deserializer` reads from a slice.
+    pub fn raw_bytes(&self) -> Option<(u64, &[u8])> {
+        self.raw.as_ref().map(|&(offset, ref bytes)| (offset, &bytes[..]))
+    }
 }
 
 impl fmt::Display for InvalidEncoding {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            InvalidEncoding { detail: None, desc } =>
-                write!(fmt, "{}", desc),
-            InvalidEncoding { detail: Some(ref detail), desc } =>
-                write!(fmt, "{} ({})", desc, detail)
+            InvalidEncoding { detail: None, desc, .. } =>
+                try!(write!(fmt, "{}", desc)),
+            InvalidEncoding { detail: Some(ref detail), desc, .. } =>
+                try!(write!(fmt, "{} ({})", desc, detail)),
+        }
+        if let Some((offset, ref bytes)) = self.raw {
+            try!(write!(fmt, " near bytes at offset {}:", offset));
+            for byte in bytes {
+                try!(write!(fmt, " {:02x}", byte));
+            }
         }
+        Ok(())
     }
 }
 
@@ -33,6 +81,10 @@ impl fmt::Display for InvalidEncoding {
 ///
 /// If decoding from a Buffer, assume that the buffer has been left
 /// in an invalid state.
+///
+/// It is `Send`, `Sync`, and `'static`, so it can be sent to another
+/// thread or boxed into a `Box<Error + Send + Sync>`.  Anything put into
+/// `Custom` has to be as well.
 #[derive(Debug)]
 pub enum DeserializeError {
     /// If the error stems from the reader that is being used
@@ -46,6 +98,46 @@ pub enum DeserializeError {
     /// If decoding a message takes more than the provided size limit, this
     /// error is returned.
     SizeLimit,
+    /// If a length in the input is too large to be added up or to fit in a
+    /// `usize`, which on a 32-bit target could otherwise wrap around to a
+    /// small number, this error is returned.
+    LengthOverflow,
+    /// If a message was read completely but did not take up all of the
+    /// input, as checked by `deserialize_exact` and `Deserializer::end`,
+    /// this error is returned with the number of bytes left over.
+    TrailingBytes {
+        remaining: u64,
+    },
+    /// If the flag given to `Deserializer::set_cancel_flag` was raised
+    /// while a message was being read, this error is returned.
+    Cancelled,
+    /// If the deadline given to `Deserializer::set_deadline` passed while a
+    /// message was being read, this error is returned.
+    DeadlineExceeded,
+    /// If a message was written for a type whose layout differs from the
+    /// one it is being read as, as found by comparing their fingerprints,
+    /// this error is returned with both.  See
+    /// `schema::deserialize_fingerprinted`.
+    SchemaMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// If a `Deserialize` implementation asked for more elements of a
+    /// tuple or struct than it has, or ended it or returned before reading
+    /// all of them, this error is returned with the number of elements and
+    /// the number that were read or asked for, rather than reading the next
+    /// value from the wrong place.
+    StructLengthMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// If a `Deserialize` implementation rejects a value, such as one that
+    /// fails validation, its error is stored and returned here.
+    ///
+    /// Errors reported through `serde::de::Error::custom` keep their
+    /// message.  Code that knows it is decoding with bincode can also return
+    /// an error of its own type here and get it back with `downcast_ref`.
+    Custom(Box<Error + Send + Sync>),
     Serde(serde::de::value::Error)
 }
 
@@ -55,6 +147,13 @@ impl Error for DeserializeError {
             DeserializeError::IoError(ref err) => Error::description(err),
             DeserializeError::InvalidEncoding(ref ib) => ib.desc,
             DeserializeError::SizeLimit => "the size limit for decoding has been reached",
+            DeserializeError::LengthOverflow => "a length in the input is too large",
+            DeserializeError::TrailingBytes { .. } => "the input has bytes left over after the message",
+            DeserializeError::Cancelled => "decoding was cancelled",
+            DeserializeError::DeadlineExceeded => "decoding did not finish before its deadline",
+            DeserializeError::SchemaMismatch { .. } => "the message was written for a type with a different layout",
+            DeserializeError::StructLengthMismatch { .. } => "a tuple or struct was not read element for element",
+            DeserializeError::Custom(ref err) => err.description(),
             DeserializeError::Serde(ref s) => s.description(),
 
         }
@@ -65,6 +164,13 @@ impl Error for DeserializeError {
             DeserializeError::IoError(ref err) => err.cause(),
             DeserializeError::InvalidEncoding(_) => None,
             DeserializeError::SizeLimit => None,
+            DeserializeError::LengthOverflow => None,
+            DeserializeError::TrailingBytes { .. } => None,
+            DeserializeError::Cancelled => None,
+            DeserializeError::DeadlineExceeded => None,
+            DeserializeError::SchemaMismatch { .. } => None,
+            DeserializeError::StructLengthMismatch { .. } => None,
+            DeserializeError::Custom(ref err) => err.cause(),
             DeserializeError::Serde(ref s) => s.cause(),
         }
     }
@@ -72,7 +178,10 @@ impl Error for DeserializeError {
 
 impl From<IoError> for DeserializeError {
     fn from(err: IoError) -> DeserializeError {
-        DeserializeError::IoError(err)
+        match throttle::stopped_error(&err) {
+            Some(err) => err,
+            None => DeserializeError::IoError(err),
+        }
     }
 }
 
@@ -91,6 +200,20 @@ impl fmt::Display for DeserializeError {
                 write!(fmt, "InvalidEncoding: {}", ib),
             DeserializeError::SizeLimit =>
                 write!(fmt, "SizeLimit"),
+            DeserializeError::LengthOverflow =>
+                write!(fmt, "LengthOverflow"),
+            DeserializeError::TrailingBytes { remaining } =>
+                write!(fmt, "TrailingBytes: {} bytes left over", remaining),
+            DeserializeError::Cancelled =>
+                write!(fmt, "Cancelled"),
+            DeserializeError::DeadlineExceeded =>
+                write!(fmt, "DeadlineExceeded"),
+            DeserializeError::SchemaMismatch { expected, found } =>
+                write!(fmt, "SchemaMismatch: expected fingerprint {:016x}, found {:016x}", expected, found),
+            DeserializeError::StructLengthMismatch { expected, found } =>
+                write!(fmt, "StructLengthMismatch: {} elements, but {} were read", expected, found),
+            DeserializeError::Custom(ref err) =>
+                write!(fmt, "{}", err),
             DeserializeError::Serde(ref s) =>
                 s.fmt(fmt),
         }
@@ -99,79 +222,753 @@ impl fmt::Display for DeserializeError {
 
 impl serde::de::Error for DeserializeError {
     fn custom<T: Into<String>>(desc: T) -> DeserializeError {
-        DeserializeError::Serde(serde::de::value::Error::Custom(desc.into()))
+        DeserializeError::Custom(desc.into().into())
     }
 
     fn end_of_stream() -> DeserializeError {
         DeserializeError::Serde(serde::de::value::Error::EndOfStream)
     }
+
+    fn invalid_value(msg: &str) -> DeserializeError {
+        DeserializeError::InvalidEncoding(InvalidEncoding {
+            desc: "invalid value",
+            detail: Some(msg.into()),
+            raw: None,
+        })
+    }
 }
 
 pub type DeserializeResult<T> = Result<T, DeserializeError>;
 
+pub fn invalid_utf8(err: Utf8Error) -> DeserializeError {
+    DeserializeError::InvalidEncoding(InvalidEncoding {
+        desc: "error while decoding utf8 string",
+        detail: Some(format!("Deserialize error: {}", err)),
+        raw: None,
+    })
+}
+
+/// Converts a length read from the input to a `usize`.
+fn to_usize(len: u64) -> DeserializeResult<usize> {
+    match num_traits::cast(len) {
+        Some(len) => Ok(len),
+        None => Err(DeserializeError::LengthOverflow),
+    }
+}
+
+fn not_packable() -> DeserializeError {
+    let message = "only unsigned integers can be packed into bits";
+    DeserializeError::Serde(serde::de::value::Error::Custom(message.into()))
+}
+
+pub fn invalid_padding(offset: u64) -> DeserializeError {
+    DeserializeError::InvalidEncoding(InvalidEncoding {
+        desc: "non-zero byte in the padding after a message",
+        detail: Some(format!("at offset {}", offset)),
+        raw: None,
+    })
+}
+
+pub fn invalid_offset(index: usize, offset: u64) -> DeserializeError {
+    DeserializeError::InvalidEncoding(InvalidEncoding {
+        desc: "element offset out of range",
+        detail: Some(format!("offset {} of element {}", offset, index)),
+        raw: None,
+    })
+}
+
+pub fn invalid_tag(desc: &'static str, detail: String) -> DeserializeError {
+    DeserializeError::InvalidEncoding(InvalidEncoding {
+        desc: desc,
+        detail: Some(detail),
+        raw: None,
+    })
+}
+
+/// How a `Deserializer` treats strings that aren't valid utf8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Utf8Mode {
+    /// Invalid utf8 is an `InvalidEncoding` error.  This is the default.
+    Strict,
+    /// Invalid utf8 is replaced with `U+FFFD REPLACEMENT CHARACTER`, so
+    /// strings never fail to decode.
+    Lossy,
+    /// The bytes of strings are handed to the visitor as they are, without
+    /// being checked, through `visit_bytes` or `visit_byte_buf`.
+    ///
+    /// This is meant for types that keep strings as bytes, like `RawString`,
+    /// to read text in some other encoding.  `String` checks the bytes
+    /// itself, and still fails on invalid utf8.
+    Raw,
+}
+
+/// How a `Deserializer` treats a `usize` or `isize` that was written on a
+/// machine where they are wider, and doesn't fit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Narrowing {
+    /// A value that doesn't fit is an `InvalidEncoding` error.  This is
+    /// the default.
+    Error,
+    /// A value that doesn't fit is replaced with the largest or smallest
+    /// value that does.
+    Saturate,
+}
+
+/// What a `Deserializer` does with an enum variant it doesn't know, such as
+/// one added by a newer version of the program that wrote the message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownVariants {
+    /// A variant index past the last variant is an error.  This is the
+    /// default.
+    Error,
+    /// A variant index past the last variant is read as the variant with
+    /// this name instead, in enums that have one, like
+    /// `#[serde(other)]` does in later versions of serde.
+    ///
+    /// Nothing of the unknown variant but its tag is read, since there is no
+    /// telling how long its data is.  The fallback should be a unit
+    /// variant, and this only works for unknown variants without data, or
+    /// for an enum that is the last thing in the message, like the message
+    /// itself, since `deserialize` ignores whatever is left over.
+    Fallback(&'static str),
+}
+
+/// Passes the bytes of a string on to a visitor as `Utf8Mode` says.
+struct Utf8Visitor<V> {
+    visitor: V,
+    mode: Utf8Mode,
+}
+
+impl<V: serde::de::Visitor> serde::de::Visitor for Utf8Visitor<V> {
+    type Value = V::Value;
+
+    fn visit_bytes<E: serde::de::Error>(&mut self, bytes: &[u8]) -> Result<V::Value, E> {
+        match (self.mode, str::from_utf8(bytes)) {
+            (Utf8Mode::Raw, _) => self.visitor.visit_bytes(bytes),
+            (_, Ok(s)) => self.visitor.visit_str(s),
+            (_, Err(_)) => self.visitor.visit_string(String::from_utf8_lossy(bytes).into_owned()),
+        }
+    }
+}
 
 /// A Deserializer that reads bytes from a buffer.
 ///
 /// This struct should rarely be used.
 /// In most cases, prefer the `decode_from` function.
 ///
+/// The reading strategy is picked through the `BincodeRead` type parameter:
+/// wrap a slice in a `SliceReader` or any `std::io::Read` in an `IoReader`.
+///
 /// ```rust,ignore
-/// let d = Deserializer::new(&mut some_reader, SizeLimit::new());
+/// let d = Deserializer::new(IoReader::new(&mut some_reader), bincode::Infinite);
 /// serde::Deserialize::deserialize(&mut deserializer);
 /// let bytes_read = d.bytes_read();
 /// ```
-pub struct Deserializer<'a, R: 'a> {
-    reader: &'a mut R,
-    size_limit: SizeLimit,
-    read: u64
+///
+/// An `Observer` can be attached with `with_observer` to be told about the
+/// structure of the input as it is read.
+pub struct Deserializer<R, S: SizeLimit, O: Observer = NoObserver, L: Layout = Config> {
+    reader: R,
+    size_limit: S,
+    read: u64,
+    fill_missing_fields: bool,
+    skip_defaults: bool,
+    utf8_mode: Utf8Mode,
+    narrowing: Narrowing,
+    unknown_variants: UnknownVariants,
+    /// The variants of the enum whose tag is about to be read.
+    variants: Option<&'static [&'static str]>,
+    layout: L,
+    /// Where numbers and chars are read into before they are decoded.
+    scratch: [u8; 16],
+    /// The byte bits are being unpacked from, and how many are left in it.
+    bits: u8,
+    bits_left: u8,
+    /// The width of the `Bits` field being read.
+    packed_width: Option<u8>,
+    /// Where the `Rle` run being read started.
+    run_start: Option<u64>,
+    /// How much memory the value of the `Rle` run being read takes up.
+    run_value_size: u64,
+    /// Whether the bytes about to be read are those of an `Unknown`, which
+    /// run to the end of the input.
+    rest: bool,
+    /// How long an `Attachment` has to be to be left in the input.
+    detach: Option<u64>,
+    alloc_guard: Option<Box<AllocGuard + Send>>,
+    cancel: Option<Arc<AtomicBool>>,
+    deadline: Option<Instant>,
+    /// Counts elements, so that the clock is only read every so often.
+    ticks: u32,
+    observer: O,
 }
 
-impl<'a, R: Read> Deserializer<'a, R> {
-    pub fn new(r: &'a mut R, size_limit: SizeLimit) -> Deserializer<'a, R> {
+impl<R: BincodeRead, S: SizeLimit> Deserializer<R, S> {
+    pub fn new(r: R, size_limit: S) -> Deserializer<R, S> {
+        Deserializer::with_observer(r, size_limit, NoObserver)
+    }
+}
+
+impl<R: BincodeRead, S: SizeLimit, L: Layout> Deserializer<R, S, NoObserver, L> {
+    /// Creates a `Deserializer` that reads the layout `layout` describes.
+    ///
+    /// With one of the layout types, such as `CompactLayout`, the layout
+    /// is part of the type of the `Deserializer` and its checks compile
+    /// away.  With a `Config`, they are made as the input is read.
+    pub fn with_layout(r: R, size_limit: S, layout: L) -> Deserializer<R, S, NoObserver, L> {
+        Deserializer::build(r, size_limit, NoObserver, layout)
+    }
+}
+
+impl<R: BincodeRead, S: SizeLimit, O: Observer> Deserializer<R, S, O> {
+    /// Creates a `Deserializer` that reports what it reads to `observer`.
+    pub fn with_observer(r: R, size_limit: S, observer: O) -> Deserializer<R, S, O> {
+        Deserializer::build(r, size_limit, observer, Config::standard())
+    }
+
+    /// Controls whether fixed-size arrays are read without a length in
+    /// front of them, as `Serializer::set_fixed_arrays` writes them.
+    pub fn set_fixed_arrays(&mut self, fixed: bool) {
+        self.layout.set_fixed_arrays(fixed);
+    }
+
+    /// Controls whether chars are read as a string of one char, with a
+    /// `u64` length in front, the way the `Serializer` wrote them before it
+    /// was brought in line with the other frontends.
+    ///
+    /// Turn this on to read data that was serialized with `serde` by an
+    /// older bincode and has a char in it.  Data written by
+    /// `rustc_serialize`'s `EncoderWriter` never changed and is read without
+    /// it, as are its `usize`s, which have always been 64 bits.
+    pub fn set_legacy_chars(&mut self, legacy: bool) {
+        self.layout.set_legacy_chars(legacy);
+    }
+
+    /// Controls whether bools and `Bits` fields are read as packed bits,
+    /// the way `Serializer::set_bit_packing` writes them.
+    pub fn set_bit_packing(&mut self, packing: bool) {
+        self.layout.set_bit_packing(packing);
+    }
+
+    /// Controls whether `is_human_readable` returns `true`.
+    pub fn set_human_readable(&mut self, human_readable: bool) {
+        self.layout.set_human_readable(human_readable);
+    }
+}
+
+impl<R: BincodeRead, S: SizeLimit, O: Observer, L: Layout> Deserializer<R, S, O, L> {
+    fn build(r: R, size_limit: S, observer: O, layout: L) -> Deserializer<R, S, O, L> {
         Deserializer {
             reader: r,
             size_limit: size_limit,
-            read: 0
+            read: 0,
+            fill_missing_fields: false,
+            skip_defaults: false,
+            utf8_mode: Utf8Mode::Strict,
+            narrowing: Narrowing::Error,
+            unknown_variants: UnknownVariants::Error,
+            variants: None,
+            layout: layout,
+            scratch: [0; 16],
+            bits: 0,
+            bits_left: 0,
+            packed_width: None,
+            run_start: None,
+            run_value_size: 0,
+            rest: false,
+            detach: None,
+            alloc_guard: None,
+            cancel: None,
+            deadline: None,
+            ticks: 0,
+            observer: observer,
         }
     }
 
+    /// Returns the observer attached to this `Deserializer`.
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    /// Controls what happens when the input ends right before a struct field.
+    ///
+    /// By default that is an error like any other truncated input.  With
+    /// `fill` set to `true`, the field and every field after it are instead
+    /// given the empty value of their type (`0`, `false`, `""`, `None`, an
+    /// empty collection), so that a struct written by an older version of a
+    /// program, before fields were appended to it, can still be read.
+    ///
+    /// Input that ends in the middle of a field is still an error.
+    pub fn set_fill_missing_fields(&mut self, fill: bool) {
+        self.fill_missing_fields = fill;
+    }
+
+    /// Controls whether structs start with a bitmap of which of their
+    /// fields are present, as written by a `Serializer` with
+    /// `set_skip_defaults`.  The fields that are missing are given the empty
+    /// value of their type.
+    pub fn set_skip_defaults(&mut self, skip: bool) {
+        self.skip_defaults = skip;
+    }
+
+    /// Controls what happens to strings that aren't valid utf8.  See
+    /// `Utf8Mode`.
+    ///
+    /// Input written by something other than bincode, say in Latin-1, can
+    /// still be read with `Utf8Mode::Lossy` or `Utf8Mode::Raw`.  Chars are
+    /// always checked.
+    pub fn set_utf8_mode(&mut self, mode: Utf8Mode) {
+        self.utf8_mode = mode;
+    }
+
+    /// Controls what happens to a `usize` or `isize` that doesn't fit in
+    /// the type on this machine.  See `Narrowing`.
+    ///
+    /// They are always written as 64 bits, so this only matters on targets
+    /// where they are narrower than that.
+    pub fn set_narrowing(&mut self, narrowing: Narrowing) {
+        self.narrowing = narrowing;
+    }
+
+    /// Controls what happens to an enum variant that isn't one of the
+    /// variants of the enum being read.  See `UnknownVariants`.
+    pub fn set_unknown_variants(&mut self, unknown: UnknownVariants) {
+        self.unknown_variants = unknown;
+    }
+
+    /// Has `guard` approve the memory taken up by everything that is read
+    /// from here on.  See `AllocGuard`.
+    pub fn set_alloc_guard<G: AllocGuard + Send + 'static>(&mut self, guard: G) {
+        self.alloc_guard = Some(Box::new(guard));
+    }
+
+    /// Gives up with a `Cancelled` error once `flag` is set.
+    ///
+    /// The flag is looked at before each element of a sequence or map, so
+    /// that a server can stop decoding a huge message, for example when the
+    /// client that sent it has gone away, from another thread.
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
+
+    /// Gives up with a `DeadlineExceeded` error once `deadline` has passed.
+    ///
+    /// Like the cancel flag, this is checked as sequences and maps are read,
+    /// though the clock is only looked at every few hundred elements.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Leaves the bytes of every `Attachment` that is at least `threshold`
+    /// bytes long in the input, instead of copying them into the value.
+    ///
+    /// An `AttachmentBuf` that is read from here on is then given where its
+    /// bytes are, which can be resolved against the buffer or mapped file
+    /// the input is read from.  Offsets count from where this
+    /// `Deserializer` started reading.  Shorter attachments are copied as
+    /// before.  The bytes that are left in the input still count towards
+    /// the size limit, but aren't put to the `AllocGuard`.
+    pub fn set_detach_threshold(&mut self, threshold: u64) {
+        self.detach = Some(threshold);
+    }
+
     /// Returns the number of bytes read from the contained Reader.
     pub fn bytes_read(&self) -> u64 {
         self.read
     }
+
+    /// Returns whether types that have a human-readable form should expect
+    /// it rather than a compact one.  See `Serializer::is_human_readable`.
+    pub fn is_human_readable(&self) -> bool {
+        self.layout.human_readable()
+    }
+
+    /// Unwraps this `Deserializer`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<'a, S: SizeLimit, O: Observer, L: Layout> Deserializer<SliceReader<'a>, S, O, L> {
+    /// Checks that the whole input has been read, and returns a
+    /// `TrailingBytes` error if it hasn't.
+    ///
+    /// Call this after reading the last value out of a slice to make sure
+    /// that nothing was left behind by mistake.
+    pub fn end(&self) -> DeserializeResult<()> {
+        match self.reader.remaining().len() {
+            0 => Ok(()),
+            remaining => Err(DeserializeError::TrailingBytes { remaining: remaining as u64 }),
+        }
+    }
+
+    /// Remembers where this `Deserializer` is in its input, so that it can
+    /// go back there with `rollback`.
+    ///
+    /// This lets a value be read as one type and, if that fails, as
+    /// another, without framing it first, for example when the first
+    /// message of a connection may be in either of two versions of a
+    /// protocol:
+    ///
+    /// ```rust,ignore
+    /// let checkpoint = deserializer.checkpoint();
+    /// let hello = match HelloV2::deserialize(&mut deserializer) {
+    ///     Ok(hello) => hello,
+    ///     Err(_) => {
+    ///         deserializer.rollback(checkpoint);
+    ///         HelloV1::deserialize(&mut deserializer)?.into()
+    ///     }
+    /// };
+    /// ```
+    pub fn checkpoint(&self) -> Checkpoint<'a, S> where S: Clone {
+        Checkpoint {
+            reader: self.reader.clone(),
+            size_limit: self.size_limit.clone(),
+            read: self.read,
+            variants: self.variants,
+            bits: self.bits,
+            bits_left: self.bits_left,
+            packed_width: self.packed_width,
+            run_start: self.run_start,
+            rest: self.rest,
+        }
+    }
+
+    /// Goes back to where this `Deserializer` was when `checkpoint` was
+    /// taken, as if nothing had been read since.
+    ///
+    /// The input, the size limit and the count of bytes read are all put
+    /// back.  What was reported to the observer or approved by the
+    /// `AllocGuard` in the meantime is not taken back.  The checkpoint has
+    /// to have been taken from this `Deserializer`.
+    pub fn rollback(&mut self, checkpoint: Checkpoint<'a, S>) {
+        self.reader = checkpoint.reader;
+        self.size_limit = checkpoint.size_limit;
+        self.read = checkpoint.read;
+        self.variants = checkpoint.variants;
+        self.bits = checkpoint.bits;
+        self.bits_left = checkpoint.bits_left;
+        self.packed_width = checkpoint.packed_width;
+        self.run_start = checkpoint.run_start;
+        self.rest = checkpoint.rest;
+    }
 }
 
-impl <'a, A> Deserializer<'a, A> {
+/// A position in the input of a `Deserializer` that reads from a slice,
+/// which it can go back to.  See `Deserializer::checkpoint`.
+#[derive(Clone)]
+pub struct Checkpoint<'a, S> {
+    reader: SliceReader<'a>,
+    size_limit: S,
+    read: u64,
+    variants: Option<&'static [&'static str]>,
+    bits: u8,
+    bits_left: u8,
+    packed_width: Option<u8>,
+    run_start: Option<u64>,
+    rest: bool,
+}
+
+impl <A, S: SizeLimit, O: Observer, L: Layout> Deserializer<A, S, O, L> {
     fn read_bytes(&mut self, count: u64) -> Result<(), DeserializeError> {
-        self.read += count;
-        match self.size_limit {
-            SizeLimit::Infinite => Ok(()),
-            SizeLimit::Bounded(x) if self.read <= x => Ok(()),
-            SizeLimit::Bounded(_) => Err(DeserializeError::SizeLimit)
+        if !self.size_limit.add(count) {
+            return Err(DeserializeError::SizeLimit);
+        }
+        self.read = match self.read.checked_add(count) {
+            Some(read) => read,
+            None => return Err(DeserializeError::LengthOverflow),
+        };
+        Ok(())
+    }
+
+
+    /// Moves on to the next whole byte, dropping what is left of the byte
+    /// bits were being unpacked from, which has to be zeros.
+    fn align_bits(&mut self) -> Result<(), DeserializeError> {
+        let rest = self.bits & ((1u16 << self.bits_left) - 1) as u8;
+        self.bits_left = 0;
+        if rest != 0 {
+            return Err(DeserializeError::InvalidEncoding(InvalidEncoding {
+                desc: "non-zero unused bits after packed bits",
+                detail: Some(format!("in the byte at offset {}", self.read - 1)),
+                raw: None,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Returns an error if the cancel flag has been set or the deadline has
+    /// passed.
+    fn check_interrupted(&mut self) -> Result<(), DeserializeError> {
+        if let Some(ref flag) = self.cancel {
+            if flag.load(Ordering::Relaxed) {
+                return Err(DeserializeError::Cancelled);
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if self.ticks % DEADLINE_INTERVAL == 0 && Instant::now() >= deadline {
+                return Err(DeserializeError::DeadlineExceeded);
+            }
+            self.ticks = self.ticks.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// Asks the `AllocGuard`, if there is one, for `size` bytes of memory.
+    fn allocate(&self, size: u64) -> Result<(), DeserializeError> {
+        match self.alloc_guard {
+            Some(ref guard) if !guard.allocate(size) => Err(DeserializeError::SizeLimit),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<R: BincodeRead, S: SizeLimit, O: Observer, L: Layout> Deserializer<R, S, O, L> {
+    /// Charges `len` bytes against the size limit and reads them into the
+    /// scratch buffer.
+    ///
+    /// Every number and char goes through here, so it is kept to the one
+    /// size limit update and the one read.  Packed bits are only looked at
+    /// if the layout packs them.
+    #[inline]
+    fn read_fixed(&mut self, len: usize) -> DeserializeResult<&[u8]> {
+        if self.layout.bit_packing() {
+            if self.packed_width.take().is_some() {
+                return Err(not_packable());
+            }
+            try!(self.align_bits());
+        }
+        try!(self.read_bytes(len as u64));
+        try!(self.reader.read_small(&mut self.scratch[..len]));
+        Ok(&self.scratch[..len])
+    }
+
+    /// Attaches the bytes around the current position to an
+    /// `InvalidEncoding` error, if the reader can see them.
+    fn annotate(&self, err: DeserializeError) -> DeserializeError {
+        match err {
+            DeserializeError::InvalidEncoding(mut err) => {
+                if err.raw.is_none() {
+                    err.raw = self.reader.window(RAW_WINDOW, RAW_WINDOW);
+                }
+                DeserializeError::InvalidEncoding(err)
+            }
+            err => err,
         }
     }
 
-    fn read_type<T>(&mut self) -> Result<(), DeserializeError> {
-        use std::mem::size_of;
-        self.read_bytes(size_of::<T>() as u64)
+    /// Unpacks the next `width` bits, highest first.
+    fn read_bits(&mut self, width: u8) -> DeserializeResult<u64> {
+        let mut value = 0;
+        for _ in 0..width {
+            if self.bits_left == 0 {
+                try!(self.read_bytes(1));
+                self.bits = try!(self.reader.read_u8());
+                self.bits_left = 8;
+            }
+            self.bits_left -= 1;
+            value = (value << 1) | ((self.bits >> self.bits_left) & 1) as u64;
+        }
+        Ok(value)
+    }
+
+    /// Reads the length prefix of a string or byte buffer and charges the
+    /// bytes it covers against the size limit and the `AllocGuard`.
+    fn read_length(&mut self) -> DeserializeResult<usize> {
+        let len: u64 = try!(serde::Deserialize::deserialize(self));
+        try!(self.read_bytes(len));
+        try!(self.allocate(len));
+        to_usize(len)
+    }
+
+    /// Reads the bytes of an `Attachment`, leaving them in the input if
+    /// there are at least `threshold` of them.
+    fn read_attachment<V>(&mut self, threshold: u64, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let offset = self.read;
+        let len: u64 = try!(serde::Deserialize::deserialize(self));
+        let size = try!(to_usize(len));
+        self.observer.bytes(offset, size);
+        try!(self.read_bytes(len));
+        if len < threshold {
+            try!(self.allocate(len));
+            let bytes = try!(self.reader.get_byte_buffer(size));
+            return visitor.visit_byte_buf(bytes);
+        }
+
+        let start = self.read - len;
+        try!(self.reader.skip(size));
+        let fields = vec![("offset", start), ("len", len)];
+        visitor.visit_map(MapDeserializer::new(fields.into_iter(), 2))
+    }
+
+    /// Reads everything that is left of the input, charging it against the
+    /// size limit as it goes.
+    fn read_rest(&mut self) -> DeserializeResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut chunk = [0; 4096];
+        loop {
+            let read = match self.reader.read(&mut chunk) {
+                Ok(0) => return Ok(bytes),
+                Ok(read) => read,
+                Err(ref err) if err.kind() == ::std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            };
+            try!(self.read_bytes(read as u64));
+            try!(self.allocate(read as u64));
+            bytes.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    /// Reads the length in front of a sequence or map.
+    ///
+    /// Unlike a `usize` field, this is never narrowed, whatever the
+    /// `Narrowing` setting.
+    fn read_seq_length(&mut self) -> DeserializeResult<usize> {
+        let len: u64 = try!(serde::Deserialize::deserialize(self));
+        to_usize(len)
+    }
+
+    /// Reads the elements of a tuple, or the fields of a struct if `fields`
+    /// is given.
+    fn deserialize_fields<V>(&mut self,
+                             fields: Option<&'static [&'static str]>,
+                             len: usize,
+                             kind: TupleKind,
+                             mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        struct TupleVisitor<'a, R: BincodeRead + 'a, S: SizeLimit + 'a, O: Observer + 'a, L: Layout + 'a> {
+            deserializer: &'a mut Deserializer<R, S, O, L>,
+            is_struct: bool,
+            fill_missing: bool,
+            present: Option<Vec<u8>>,
+            index: usize,
+            len: usize,
+            ended: bool,
+            kind: TupleKind,
+        }
+
+        impl<'a, R: BincodeRead + 'a, S: SizeLimit + 'a, O: Observer + 'a, L: Layout + 'a> serde::de::SeqVisitor for TupleVisitor<'a, R, S, O, L> {
+            type Error = DeserializeError;
+
+            fn visit<T>(&mut self) -> Result<Option<T>, Self::Error>
+                where T: serde::de::Deserialize,
+            {
+                let index = self.index;
+                if index == self.len {
+                    return Err(DeserializeError::StructLengthMismatch { expected: self.len, found: index + 1 });
+                }
+                self.index += 1;
+                if self.is_struct {
+                    self.deserializer.observer.field(self.deserializer.read, index);
+                }
+                if let Some(ref present) = self.present {
+                    if present.get(index / 8).map_or(true, |&b| b & (1 << (index % 8)) == 0) {
+                        let value = try!(serde::Deserialize::deserialize(&mut DefaultDeserializer));
+                        return Ok(Some(value));
+                    }
+                }
+                if self.fill_missing && try!(self.deserializer.reader.is_at_end()) {
+                    let value = try!(serde::Deserialize::deserialize(&mut DefaultDeserializer));
+                    return Ok(Some(value));
+                }
+                let value = try!(serde::Deserialize::deserialize(self.deserializer));
+                if self.kind == TupleKind::Run && index == 0 {
+                    self.deserializer.run_value_size = mem::size_of::<T>() as u64;
+                }
+                Ok(Some(value))
+            }
+
+            fn end(&mut self) -> Result<(), Self::Error> {
+                self.ended = true;
+                if self.kind != TupleKind::OpenEnded && self.index < self.len {
+                    return Err(DeserializeError::StructLengthMismatch { expected: self.len, found: self.index });
+                }
+                Ok(())
+            }
+        }
+
+        if let Some(fields) = fields {
+            try!(self.align_bits());
+            self.observer.begin_struct(self.read, fields);
+        }
+
+        let present = match fields {
+            Some(fields) if self.skip_defaults => {
+                let mut bitmap = vec![0; (fields.len() + 7) / 8];
+                try!(self.read_bytes(bitmap.len() as u64));
+                try!(self.reader.read_exact(&mut bitmap));
+                Some(bitmap)
+            }
+            _ => None,
+        };
+
+        let fill_missing = fields.is_some() && self.fill_missing_fields;
+        let mut elements = TupleVisitor {
+            deserializer: self,
+            is_struct: fields.is_some(),
+            fill_missing: fill_missing,
+            present: present,
+            index: 0,
+            len: len,
+            ended: false,
+            kind: kind,
+        };
+        let value = try!(visitor.visit_seq(&mut elements));
+        // Elements that weren't read would be left in the input, to be read
+        // as whatever comes next.  Only a `Var` stops early, and says so
+        // with `end`.
+        if !(kind == TupleKind::OpenEnded && elements.ended) && elements.index < len {
+            return Err(DeserializeError::StructLengthMismatch { expected: len, found: elements.index });
+        }
+
+        if fields.is_some() {
+            try!(self.align_bits());
+            self.observer.end(self.read);
+        }
+        Ok(value)
     }
 }
 
+/// The kinds of tuple `deserialize_fields` reads.
+#[derive(Clone, Copy, PartialEq)]
+enum TupleKind {
+    /// Every element has to be read.
+    Fixed,
+    /// A `Var`'s bytes, which may be ended early.
+    OpenEnded,
+    /// An `Rle` run: its value, then how many times it repeats.
+    Run,
+}
+
 macro_rules! impl_nums {
+    (unsigned $ty:ty, $dser_method:ident, $visitor_method:ident, $reader_method:ident) => {
+        #[inline]
+        fn $dser_method<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+            where V: serde::de::Visitor,
+        {
+            if let Some(width) = self.packed_width.take() {
+                return visitor.visit_u64(try!(self.read_bits(width)));
+            }
+            let value = BigEndian::$reader_method(try!(self.read_fixed(mem::size_of::<$ty>())));
+            visitor.$visitor_method(value)
+        }
+    };
     ($ty:ty, $dser_method:ident, $visitor_method:ident, $reader_method:ident) => {
         #[inline]
         fn $dser_method<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
             where V: serde::de::Visitor,
         {
-            try!(self.read_type::<$ty>());
-            let value = try!(self.reader.$reader_method::<BigEndian>());
+            let value = BigEndian::$reader_method(try!(self.read_fixed(mem::size_of::<$ty>())));
             visitor.$visitor_method(value)
         }
     }
 }
 
 
-impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
+impl<R: BincodeRead, S: SizeLimit, O: Observer, L: Layout> serde::Deserializer for Deserializer<R, S, O, L> {
     type Error = DeserializeError;
 
     #[inline]
@@ -185,22 +982,26 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
     fn deserialize_bool<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
+        if self.layout.bit_packing() {
+            self.packed_width = None;
+            return visitor.visit_bool(try!(self.read_bits(1)) == 1);
+        }
         let value: u8 = try!(serde::Deserialize::deserialize(self));
-        match value {
-            1 => visitor.visit_bool(true),
-            0 => visitor.visit_bool(false),
-            value => {
-                Err(DeserializeError::InvalidEncoding(InvalidEncoding {
+        match format::bool_from_byte(value) {
+            Some(value) => visitor.visit_bool(value),
+            None => {
+                Err(self.annotate(DeserializeError::InvalidEncoding(InvalidEncoding {
                     desc: "invalid u8 when decoding bool",
-                    detail: Some(format!("Expected 0 or 1, got {}", value))
-                }))
+                    detail: Some(format!("Expected 0 or 1, got {}", value)),
+                    raw: None,
+                })))
             }
         }
     }
 
-    impl_nums!(u16, deserialize_u16, visit_u16, read_u16);
-    impl_nums!(u32, deserialize_u32, visit_u32, read_u32);
-    impl_nums!(u64, deserialize_u64, visit_u64, read_u64);
+    impl_nums!(unsigned u16, deserialize_u16, visit_u16, read_u16);
+    impl_nums!(unsigned u32, deserialize_u32, visit_u32, read_u32);
+    impl_nums!(unsigned u64, deserialize_u64, visit_u64, read_u64);
     impl_nums!(i16, deserialize_i16, visit_i16, read_i16);
     impl_nums!(i32, deserialize_i32, visit_i32, read_i32);
     impl_nums!(i64, deserialize_i64, visit_i64, read_i64);
@@ -212,19 +1013,29 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
     fn deserialize_u8<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        try!(self.read_type::<u8>());
-        visitor.visit_u8(try!(self.reader.read_u8()))
+        if let Some(width) = self.packed_width.take() {
+            return visitor.visit_u64(try!(self.read_bits(width)));
+        }
+        let value = try!(self.read_fixed(1))[0];
+        visitor.visit_u8(value)
     }
 
     #[inline]
     fn deserialize_usize<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        try!(self.read_type::<u64>());
-        let value = try!(self.reader.read_u64::<BigEndian>());
-        match num_traits::cast(value) {
-            Some(value) => visitor.visit_usize(value),
-            None => Err(DeserializeError::Serde(serde::de::value::Error::Custom("expected usize".into())))
+        if let Some(width) = self.packed_width.take() {
+            return visitor.visit_u64(try!(self.read_bits(width)));
+        }
+        let value = BigEndian::read_u64(try!(self.read_fixed(8)));
+        match (num_traits::cast(value), self.narrowing) {
+            (Some(value), _) => visitor.visit_usize(value),
+            (None, Narrowing::Saturate) => visitor.visit_usize(::std::usize::MAX),
+            (None, Narrowing::Error) => Err(self.annotate(DeserializeError::InvalidEncoding(InvalidEncoding {
+                desc: "usize out of range",
+                detail: Some(format!("{} does not fit in a usize", value)),
+                raw: None,
+            }))),
         }
     }
 
@@ -232,19 +1043,24 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
     fn deserialize_i8<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        try!(self.read_type::<i8>());
-        visitor.visit_i8(try!(self.reader.read_i8()))
+        let value = try!(self.read_fixed(1))[0] as i8;
+        visitor.visit_i8(value)
     }
 
     #[inline]
     fn deserialize_isize<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        try!(self.read_type::<i64>());
-        let value = try!(self.reader.read_i64::<BigEndian>());
-        match num_traits::cast(value) {
-            Some(value) => visitor.visit_isize(value),
-            None => Err(DeserializeError::Serde(serde::de::value::Error::Custom("expected isize".into()))),
+        let value = BigEndian::read_i64(try!(self.read_fixed(8)));
+        match (num_traits::cast(value), self.narrowing) {
+            (Some(value), _) => visitor.visit_isize(value),
+            (None, Narrowing::Saturate) if value < 0 => visitor.visit_isize(::std::isize::MIN),
+            (None, Narrowing::Saturate) => visitor.visit_isize(::std::isize::MAX),
+            (None, Narrowing::Error) => Err(self.annotate(DeserializeError::InvalidEncoding(InvalidEncoding {
+                desc: "isize out of range",
+                detail: Some(format!("{} does not fit in an isize", value)),
+                raw: None,
+            }))),
         }
     }
 
@@ -257,99 +1073,130 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
     fn deserialize_char<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        use std::str;
-
         let error = DeserializeError::InvalidEncoding(InvalidEncoding {
             desc: "Invalid char encoding",
-            detail: None
+            detail: None,
+            raw: None,
         });
 
-        let mut buf = [0];
+        if self.layout.legacy_chars() {
+            let len = try!(self.read_length());
+            if len == 0 || len > 4 { return Err(self.annotate(error)) }
+            let mut buf = [0; 4];
+            try!(self.reader.read_exact(&mut buf[..len]));
+            return match format::char_from_bytes(&buf[..len]) {
+                Some(c) => visitor.visit_char(c),
+                None => Err(self.annotate(error)),
+            };
+        }
 
-        let _ = try!(self.reader.read(&mut buf[..]));
-        let first_byte = buf[0];
-        let width = utf8_char_width(first_byte);
-        if width == 1 { return visitor.visit_char(first_byte as char) }
-        if width == 0 { return Err(error)}
+        // Charge every byte against the size limit before it is read.
+        let first_byte = try!(self.read_fixed(1))[0];
+        let width = format::char_width(first_byte);
+        if width == 0 { return Err(self.annotate(error)) }
 
         let mut buf = [first_byte, 0, 0, 0];
-        {
-            let mut start = 1;
-            while start < width {
-                match try!(self.reader.read(&mut buf[start .. width])) {
-                    n if n == width - start => break,
-                    n if n < width - start => { start += n; }
-                    _ => return Err(error)
-                }
-            }
+        if width > 1 {
+            buf[1..width].copy_from_slice(try!(self.read_fixed(width - 1)));
         }
 
-        let res = try!(match str::from_utf8(&buf[..width]).ok() {
-            Some(s) => Ok(s.chars().next().unwrap()),
-            None => Err(error)
-        });
+        match format::char_from_bytes(&buf[..width]) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(self.annotate(error)),
+        }
+    }
 
-        try!(self.read_bytes(res.len_utf8() as u64));
-        visitor.visit_char(res)
+    fn deserialize_str<V>(&mut self, visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        let offset = self.read;
+        let len = try!(self.read_length());
+        self.observer.string(offset, len);
+        let result = match self.utf8_mode {
+            Utf8Mode::Strict => self.reader.forward_read_str(len, visitor),
+            mode => self.reader.forward_read_bytes(len, Utf8Visitor { visitor: visitor, mode: mode }),
+        };
+        result.map_err(|err| self.annotate(err))
     }
 
     fn deserialize_string<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        let len = try!(serde::Deserialize::deserialize(self));
-        try!(self.read_bytes(len));
-
-        let mut buffer = Vec::new();
-        try!(self.reader.by_ref().take(len as u64).read_to_end(&mut buffer));
+        let offset = self.read;
+        let len = try!(self.read_length());
+        self.observer.string(offset, len);
+        let buffer = try!(self.reader.get_byte_buffer(len));
 
+        if self.utf8_mode == Utf8Mode::Raw {
+            return visitor.visit_byte_buf(buffer);
+        }
         match String::from_utf8(buffer) {
             Ok(s) => visitor.visit_string(s),
-            Err(err) => Err(DeserializeError::InvalidEncoding(InvalidEncoding {
-                desc: "error while decoding utf8 string",
-                detail: Some(format!("Deserialize error: {}", err))
-            })),
+            Err(err) => match self.utf8_mode {
+                Utf8Mode::Lossy => visitor.visit_string(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+                _ => Err(self.annotate(invalid_utf8(err.utf8_error()))),
+            },
+        }
+    }
+
+    fn deserialize_bytes<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        if self.rest {
+            self.rest = false;
+            let bytes = try!(self.read_rest());
+            return visitor.visit_byte_buf(bytes);
         }
+        let offset = self.read;
+        let len = try!(self.read_length());
+        self.observer.bytes(offset, len);
+        self.reader.forward_read_bytes(len, visitor)
     }
 
     fn deserialize_enum<V>(&mut self,
                      _enum: &'static str,
-                     _variants: &'static [&'static str],
+                     variants: &'static [&'static str],
                      mut visitor: V) -> Result<V::Value, Self::Error>
         where V: serde::de::EnumVisitor,
     {
+        self.variants = Some(variants);
         visitor.visit(self)
     }
 
     fn deserialize_tuple<V>(&mut self,
-                      _len: usize,
-                      mut visitor: V) -> DeserializeResult<V::Value>
+                      len: usize,
+                      visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        struct TupleVisitor<'a, 'b: 'a, R: Read + 'b>(&'a mut Deserializer<'b, R>);
-
-        impl<'a, 'b: 'a, R: Read + 'b> serde::de::SeqVisitor for TupleVisitor<'a, 'b, R> {
-            type Error = DeserializeError;
-
-            fn visit<T>(&mut self) -> Result<Option<T>, Self::Error>
-                where T: serde::de::Deserialize,
-            {
-                let value = try!(serde::Deserialize::deserialize(self.0));
-                Ok(Some(value))
-            }
+        self.deserialize_fields(None, len, TupleKind::Fixed, visitor)
+    }
 
-            fn end(&mut self) -> Result<(), Self::Error> {
-                Ok(())
-            }
+    fn deserialize_tuple_struct<V>(&mut self,
+                             name: &'static str,
+                             len: usize,
+                             visitor: V) -> DeserializeResult<V::Value>
+        where V: serde::de::Visitor,
+    {
+        if name == rle::RUN {
+            let outer = mem::replace(&mut self.run_start, Some(self.read));
+            let value = self.deserialize_fields(None, len, TupleKind::Run, visitor);
+            self.run_start = outer;
+            return value;
         }
-
-        visitor.visit_seq(TupleVisitor(self))
+        if name == varint::NAME {
+            return self.deserialize_fields(None, len, TupleKind::OpenEnded, visitor);
+        }
+        self.deserialize_tuple(len, visitor)
     }
 
     fn deserialize_fixed_size_array<V>(&mut self, 
-                            _: usize,
+                            len: usize,
                             visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
+        if self.layout.fixed_arrays() {
+            return self.deserialize_fields(None, len, TupleKind::Fixed, visitor);
+        }
         self.deserialize_seq(visitor)
     }
 
@@ -357,25 +1204,27 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
         where V: serde::de::Visitor,
     {
         let value: u8 = try!(serde::de::Deserialize::deserialize(self));
-        match value {
-            0 => visitor.visit_none(),
-            1 => visitor.visit_some(self),
-            _ => Err(DeserializeError::InvalidEncoding(InvalidEncoding {
+        match format::option_from_tag(value) {
+            Some(false) => visitor.visit_none(),
+            Some(true) => visitor.visit_some(self),
+            None => Err(self.annotate(DeserializeError::InvalidEncoding(InvalidEncoding {
                 desc: "invalid tag when decoding Option",
-                detail: Some(format!("Expected 0 or 1, got {}", value))
-            })),
+                detail: Some(format!("Expected 0 or 1, got {}", value)),
+                raw: None,
+            }))),
         }
     }
 
     fn deserialize_seq<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        struct SeqVisitor<'a, 'b: 'a, R: Read + 'b> {
-            deserializer: &'a mut Deserializer<'b, R>,
+        struct SeqVisitor<'a, R: BincodeRead + 'a, S: SizeLimit + 'a, O: Observer + 'a, L: Layout + 'a> {
+            deserializer: &'a mut Deserializer<R, S, O, L>,
             len: usize,
+            index: usize,
         }
 
-        impl<'a, 'b: 'a, R: Read + 'b> serde::de::SeqVisitor for SeqVisitor<'a, 'b, R> {
+        impl<'a, R: BincodeRead + 'a, S: SizeLimit + 'a, O: Observer + 'a, L: Layout + 'a> serde::de::SeqVisitor for SeqVisitor<'a, R, S, O, L> {
             type Error = DeserializeError;
 
             fn visit<T>(&mut self) -> Result<Option<T>, Self::Error>
@@ -383,6 +1232,10 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
             {
                 if self.len > 0 {
                     self.len -= 1;
+                    self.deserializer.observer.element(self.deserializer.read, self.index);
+                    self.index += 1;
+                    try!(self.deserializer.check_interrupted());
+                    try!(self.deserializer.allocate(mem::size_of::<T>() as u64));
                     let value = try!(serde::Deserialize::deserialize(self.deserializer));
                     Ok(Some(value))
                 } else {
@@ -397,22 +1250,33 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
                     Err(DeserializeError::Serde(serde::de::value::Error::Custom("expected end".into())))
                 }
             }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                // Collections reserve room for the lower bound, and the
+                // length comes from the input, so don't let it ask for much.
+                (cmp::min(self.len, MAX_PREALLOCATION), Some(self.len))
+            }
         }
 
-        let len = try!(serde::Deserialize::deserialize(self));
+        let offset = self.read;
+        let len = try!(self.read_seq_length());
+        self.observer.begin_seq(offset, len);
 
-        visitor.visit_seq(SeqVisitor { deserializer: self, len: len })
+        let value = try!(visitor.visit_seq(SeqVisitor { deserializer: self, len: len, index: 0 }));
+        self.observer.end(self.read);
+        Ok(value)
     }
 
     fn deserialize_map<V>(&mut self, mut visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        struct MapVisitor<'a, 'b: 'a, R: Read + 'b> {
-            deserializer: &'a mut Deserializer<'b, R>,
+        struct MapVisitor<'a, R: BincodeRead + 'a, S: SizeLimit + 'a, O: Observer + 'a, L: Layout + 'a> {
+            deserializer: &'a mut Deserializer<R, S, O, L>,
             len: usize,
+            index: usize,
         }
 
-        impl<'a, 'b: 'a, R: Read + 'b> serde::de::MapVisitor for MapVisitor<'a, 'b, R> {
+        impl<'a, R: BincodeRead + 'a, S: SizeLimit + 'a, O: Observer + 'a, L: Layout + 'a> serde::de::MapVisitor for MapVisitor<'a, R, S, O, L> {
             type Error = DeserializeError;
 
             fn visit_key<K>(&mut self) -> Result<Option<K>, Self::Error>
@@ -420,6 +1284,10 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
             {
                 if self.len > 0 {
                     self.len -= 1;
+                    self.deserializer.observer.element(self.deserializer.read, self.index);
+                    self.index += 1;
+                    try!(self.deserializer.check_interrupted());
+                    try!(self.deserializer.allocate(mem::size_of::<K>() as u64));
                     let key = try!(serde::Deserialize::deserialize(self.deserializer));
                     Ok(Some(key))
                 } else {
@@ -430,6 +1298,7 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
             fn visit_value<V>(&mut self) -> Result<V, Self::Error>
                 where V: serde::de::Deserialize,
             {
+                try!(self.deserializer.allocate(mem::size_of::<V>() as u64));
                 let value = try!(serde::Deserialize::deserialize(self.deserializer));
                 Ok(value)
             }
@@ -441,11 +1310,21 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
                     Err(DeserializeError::Serde(serde::de::value::Error::Custom("expected end".into())))
                 }
             }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                // Collections reserve room for the lower bound, and the
+                // length comes from the input, so don't let it ask for much.
+                (cmp::min(self.len, MAX_PREALLOCATION), Some(self.len))
+            }
         }
 
-        let len = try!(serde::Deserialize::deserialize(self));
+        let offset = self.read;
+        let len = try!(self.read_seq_length());
+        self.observer.begin_map(offset, len);
 
-        visitor.visit_map(MapVisitor { deserializer: self, len: len })
+        let value = try!(visitor.visit_map(MapVisitor { deserializer: self, len: len, index: 0 }));
+        self.observer.end(self.read);
+        Ok(value)
     }
 
     fn deserialize_struct<V>(&mut self,
@@ -454,26 +1333,78 @@ impl<'a, R: Read> serde::Deserializer for Deserializer<'a, R> {
                        visitor: V) -> DeserializeResult<V::Value>
         where V: serde::de::Visitor,
     {
-        self.deserialize_tuple(fields.len(), visitor)
+        self.deserialize_fields(Some(fields), fields.len(), TupleKind::Fixed, visitor)
     }
 
     fn deserialize_newtype_struct<V>(&mut self,
-                               _name: &str,
+                               name: &str,
                                mut visitor: V) -> Result<V::Value, Self::Error>
         where V: serde::de::Visitor,
     {
+        if name == unknown::NAME {
+            self.rest = true;
+            let value = visitor.visit_newtype_struct(&mut *self);
+            self.rest = false;
+            return value;
+        }
+        if name == scatter::NAME {
+            if let Some(threshold) = self.detach {
+                return self.read_attachment(threshold, visitor);
+            }
+        }
+        if name == rle::RUN_LENGTH {
+            if let Some(start) = self.run_start.take() {
+                // The value of the run has just been read, and is charged
+                // again for every time it repeats, at least a byte each so
+                // that a long run of nothing isn't free.
+                let size = cmp::max(self.read - start, 1);
+                let count: u64 = try!(serde::Deserialize::deserialize(self));
+                let repeats = match size.checked_mul(count.saturating_sub(1)) {
+                    Some(repeats) => repeats,
+                    None => return Err(DeserializeError::LengthOverflow),
+                };
+                if !self.size_limit.add(repeats) {
+                    return Err(DeserializeError::SizeLimit);
+                }
+                match self.run_value_size.checked_mul(count) {
+                    Some(memory) => try!(self.allocate(memory)),
+                    None => return Err(DeserializeError::LengthOverflow),
+                }
+                return visitor.visit_newtype_struct(&mut count.into_deserializer());
+            }
+        }
+        if self.layout.bit_packing() {
+            if let Some(width) = bitpack::width(name) {
+                self.packed_width = Some(width);
+                let value = try!(visitor.visit_newtype_struct(&mut *self));
+                if self.packed_width.take().is_some() {
+                    return Err(not_packable());
+                }
+                return Ok(value);
+            }
+        }
         visitor.visit_newtype_struct(self)
     }
 }
 
-impl<'a, R: Read> serde::de::VariantVisitor for Deserializer<'a, R> {
+impl<R: BincodeRead, S: SizeLimit, O: Observer, L: Layout> serde::de::VariantVisitor for Deserializer<R, S, O, L> {
     type Error = DeserializeError;
 
     fn visit_variant<V>(&mut self) -> Result<V, Self::Error>
         where V: serde::Deserialize,
     {
+        let offset = self.read;
         let index: u32 = try!(serde::Deserialize::deserialize(self));
-        let mut deserializer = (index as usize).into_deserializer();
+        self.observer.variant(offset, index);
+        let mut index = index as usize;
+        if let (Some(variants), UnknownVariants::Fallback(name)) = (self.variants.take(), self.unknown_variants) {
+            if index >= variants.len() {
+                if let Some(fallback) = variants.iter().position(|&variant| variant == name) {
+                    index = fallback;
+                }
+            }
+        }
+        let mut deserializer = index.into_deserializer();
         let attempt: Result<V, serde::de::value::Error> = serde::Deserialize::deserialize(&mut deserializer);
         Ok(try!(attempt))
     }
@@ -501,28 +1432,6 @@ impl<'a, R: Read> serde::de::VariantVisitor for Deserializer<'a, R> {
                        visitor: V) -> Result<V::Value, Self::Error>
         where V: serde::de::Visitor,
     {
-        serde::de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
-    }
-}
-static UTF8_CHAR_WIDTH: [u8; 256] = [
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x1F
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x3F
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x5F
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
-1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x7F
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0, // 0x9F
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,
-0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0, // 0xBF
-0,0,2,2,2,2,2,2,2,2,2,2,2,2,2,2,
-2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2, // 0xDF
-3,3,3,3,3,3,3,3,3,3,3,3,3,3,3,3, // 0xEF
-4,4,4,4,4,0,0,0,0,0,0,0,0,0,0,0, // 0xFF
-];
-
-fn utf8_char_width(b: u8) -> usize {
-    UTF8_CHAR_WIDTH[b as usize] as usize
+        self.deserialize_fields(Some(fields), fields.len(), TupleKind::Fixed, visitor)
+    }
 }
@@ -0,0 +1,128 @@
+//! Run-length encoding of sequences that repeat themselves.
+//!
+//! An `Rle<T>` holds values like a `Vec<T>` does, but is encoded as the
+//! runs of equal values in it, each as the value followed by how many times
+//! it repeats.  A tile map or a bitmap that is mostly one value shrinks to a
+//! handful of runs.  The layout is a `Vec<(T, u64)>`.
+//!
+//! Decoding puts every value back, so a few bytes of input can stand for a
+//! very large `Vec`.  The bincode `Deserializer` charges each run against
+//! the size limit as though its value had been written out in full every
+//! time it repeats, which keeps such input from taking up more memory than
+//! the limit allows.
+//!
+//! ```rust,ignore
+//! let tiles = Rle(vec![Tile::Water; 4096]);
+//! let bytes = bincode::serde::serialize(&tiles, Bounded(1 << 20)).unwrap();
+//! ```
+
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use serde_crate as serde;
+
+/// The names a run and its length are deserialized under, which tell the
+/// bincode `Deserializer` to charge the repeats against the size limit.
+pub const RUN: &'static str = "bincode::Run";
+pub const RUN_LENGTH: &'static str = "bincode::RunLength";
+
+/// A `Vec` that is encoded as runs of equal values.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Rle<T>(pub Vec<T>);
+
+impl<T> Rle<T> {
+    /// Unwraps the `Vec`.
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for Rle<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> From<Vec<T>> for Rle<T> {
+    fn from(values: Vec<T>) -> Rle<T> {
+        Rle(values)
+    }
+}
+
+impl<T: serde::Serialize + PartialEq> serde::Serialize for Rle<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        let mut runs: Vec<(&T, u64)> = Vec::new();
+        for value in &self.0 {
+            match runs.last_mut() {
+                Some(&mut (last, ref mut count)) if last == value => *count += 1,
+                _ => runs.push((value, 1)),
+            }
+        }
+        runs.serialize(serializer)
+    }
+}
+
+impl<T: serde::Deserialize + Clone> serde::Deserialize for Rle<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Rle<T>, D::Error> {
+        let runs: Vec<Run<T>> = try!(serde::Deserialize::deserialize(deserializer));
+        let mut values = Vec::new();
+        for Run(value, count) in runs {
+            if count == 0 {
+                return Err(serde::de::Error::invalid_value("run of length 0"));
+            }
+            for _ in 1..count {
+                values.push(value.clone());
+            }
+            values.push(value);
+        }
+        Ok(Rle(values))
+    }
+}
+
+/// A value and how many times in a row it appears.
+struct Run<T>(T, u64);
+
+impl<T: serde::Deserialize> serde::Deserialize for Run<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Run<T>, D::Error> {
+        deserializer.deserialize_tuple_struct(RUN, 2, RunVisitor(PhantomData))
+    }
+}
+
+struct RunVisitor<T>(PhantomData<T>);
+
+impl<T: serde::Deserialize> serde::de::Visitor for RunVisitor<T> {
+    type Value = Run<T>;
+
+    fn visit_seq<V: serde::de::SeqVisitor>(&mut self, mut visitor: V) -> Result<Run<T>, V::Error> {
+        let value = match try!(visitor.visit()) {
+            Some(value) => value,
+            None => return Err(serde::de::Error::invalid_length(0)),
+        };
+        let RunLength(count) = match try!(visitor.visit()) {
+            Some(count) => count,
+            None => return Err(serde::de::Error::invalid_length(1)),
+        };
+        try!(visitor.end());
+        Ok(Run(value, count))
+    }
+}
+
+struct RunLength(u64);
+
+impl serde::Deserialize for RunLength {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<RunLength, D::Error> {
+        deserializer.deserialize_newtype_struct(RUN_LENGTH, RunLengthVisitor)
+    }
+}
+
+struct RunLengthVisitor;
+
+impl serde::de::Visitor for RunLengthVisitor {
+    type Value = RunLength;
+
+    fn visit_newtype_struct<D: serde::Deserializer>(&mut self, deserializer: &mut D) -> Result<RunLength, D::Error> {
+        serde::Deserialize::deserialize(deserializer).map(RunLength)
+    }
+}
@@ -0,0 +1,305 @@
+use std::io::{self, IoSlice, Write};
+
+use serde_crate as serde;
+
+use ::{SizeLimit, Infinite};
+use serde_crate::bytes::{Bytes, ByteBuf};
+use super::read::SliceReader;
+use super::reader::{Deserializer, DeserializeResult};
+use super::writer::{self, SerializeResult, SerializeError};
+
+/// The name `Attachment` gives its newtype struct, which tells the bincode
+/// `Serializer` to leave its bytes out of a `Scatter`.
+pub const NAME: &'static str = "bincode::Attachment";
+
+/// A byte buffer that `serialize_scatter` leaves out of the bytes it
+/// writes, so that it can be sent as it is rather than copied.
+///
+/// Everywhere else, including `serialize`, it is written like any other
+/// byte buffer, and it can be read back as a `ByteBuf` or a `Vec<u8>`.
+///
+/// ```rust,ignore
+/// #[derive(Serialize)]
+/// struct Upload<'a> {
+///     name: String,
+///     contents: Attachment<'a>,
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Attachment<'a>(pub &'a [u8]);
+
+impl<'a> serde::Serialize for Attachment<'a> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_newtype_struct(NAME, Bytes::from(self.0))
+    }
+}
+
+/// What an `Attachment` is read back as.
+///
+/// Read with `deserialize_detached`, or by a `Deserializer` that has been
+/// given a detach threshold, the bytes of a long attachment are left in the
+/// input and only where they are is kept, so that a large payload in a
+/// buffer or a mapped file is not copied to get at it.  Everywhere else the
+/// bytes are copied in.
+///
+/// ```rust,ignore
+/// #[derive(Deserialize)]
+/// struct Upload {
+///     name: String,
+///     contents: AttachmentBuf,
+/// }
+///
+/// let upload: Upload = deserialize_detached(&mmap, 4096)?;
+/// let contents = upload.contents.resolve(&mmap).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AttachmentBuf {
+    /// The bytes themselves.
+    Inline(Vec<u8>),
+    /// The bytes were left in the input, at `offset`.
+    Detached { offset: u64, len: u64 },
+}
+
+impl AttachmentBuf {
+    /// Returns the bytes of the attachment, looking them up in `input` if
+    /// they were left there.
+    ///
+    /// `input` has to be what the attachment was read from, starting where
+    /// reading started.  `None` is returned if it is too short.
+    pub fn resolve<'a>(&'a self, input: &'a [u8]) -> Option<&'a [u8]> {
+        match *self {
+            AttachmentBuf::Inline(ref bytes) => Some(bytes),
+            AttachmentBuf::Detached { offset, len } => {
+                let end = match offset.checked_add(len) {
+                    Some(end) if end <= input.len() as u64 => end,
+                    _ => return None,
+                };
+                Some(&input[offset as usize..end as usize])
+            }
+        }
+    }
+
+    /// Returns whether the bytes were left in the input.
+    pub fn is_detached(&self) -> bool {
+        match *self {
+            AttachmentBuf::Inline(_) => false,
+            AttachmentBuf::Detached { .. } => true,
+        }
+    }
+}
+
+impl serde::Serialize for AttachmentBuf {
+    /// Writes the bytes of an inline attachment.  Those of a detached one
+    /// aren't at hand, so it can't be written.
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        match *self {
+            AttachmentBuf::Inline(ref bytes) => Attachment(&bytes[..]).serialize(serializer),
+            AttachmentBuf::Detached { .. } =>
+                Err(serde::ser::Error::custom("a detached attachment can't be serialized without its bytes")),
+        }
+    }
+}
+
+impl serde::Deserialize for AttachmentBuf {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<AttachmentBuf, D::Error> {
+        deserializer.deserialize_newtype_struct(NAME, AttachmentBufVisitor)
+    }
+}
+
+struct AttachmentBufVisitor;
+
+impl serde::de::Visitor for AttachmentBufVisitor {
+    type Value = AttachmentBuf;
+
+    fn visit_newtype_struct<D>(&mut self, deserializer: &mut D) -> Result<AttachmentBuf, D::Error>
+        where D: serde::Deserializer,
+    {
+        let bytes: ByteBuf = try!(serde::Deserialize::deserialize(deserializer));
+        Ok(AttachmentBuf::Inline(bytes.into()))
+    }
+
+    fn visit_bytes<E: serde::de::Error>(&mut self, bytes: &[u8]) -> Result<AttachmentBuf, E> {
+        Ok(AttachmentBuf::Inline(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(&mut self, bytes: Vec<u8>) -> Result<AttachmentBuf, E> {
+        Ok(AttachmentBuf::Inline(bytes))
+    }
+
+    /// The bincode `Deserializer` hands over a detached attachment as a map
+    /// of its `offset` and `len`.
+    fn visit_map<V: serde::de::MapVisitor>(&mut self, mut visitor: V) -> Result<AttachmentBuf, V::Error> {
+        let mut offset = None;
+        let mut len = None;
+        while let Some(key) = try!(visitor.visit_key::<String>()) {
+            match &key[..] {
+                "offset" => offset = Some(try!(visitor.visit_value())),
+                "len" => len = Some(try!(visitor.visit_value())),
+                _ => return Err(serde::de::Error::unknown_field(&key)),
+            }
+        }
+        try!(visitor.end());
+        Ok(AttachmentBuf::Detached {
+            offset: match offset {
+                Some(offset) => offset,
+                None => try!(visitor.missing_field("offset")),
+            },
+            len: match len {
+                Some(len) => len,
+                None => try!(visitor.missing_field("len")),
+            },
+        })
+    }
+}
+
+/// Where the bytes of an `Attachment` go in a `Scatter`: at `offset` in the
+/// header, right after their length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placeholder {
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// A message serialized without the bytes of its `Attachment`s.
+///
+/// The header is everything the message is made of but those bytes, and a
+/// placeholder for each says where in the header they go.  Given the same
+/// byte slices the message was serialized with, in the same order,
+/// `io_slices` returns the pieces of the message for a vectored write, so
+/// that a large payload goes out next to a small header without either
+/// being copied.  Put back together, the pieces are exactly what `serialize`
+/// writes.
+///
+/// ```rust,ignore
+/// let upload = Upload { name: name, contents: Attachment(&file) };
+/// let scatter = serialize_scatter(&upload, Infinite)?;
+/// socket.write_vectored(&scatter.io_slices(&[&file]))?;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scatter {
+    header: Vec<u8>,
+    placeholders: Vec<Placeholder>,
+}
+
+impl Scatter {
+    /// Returns the header.
+    pub fn header(&self) -> &[u8] {
+        &self.header
+    }
+
+    /// Returns where the attachments go, in the order they were written.
+    pub fn placeholders(&self) -> &[Placeholder] {
+        &self.placeholders
+    }
+
+    /// Returns the length of the whole message, attachments included.
+    pub fn len(&self) -> usize {
+        self.placeholders.iter().fold(self.header.len(), |len, placeholder| len + placeholder.len)
+    }
+
+    /// Returns whether the message is empty, which it is if it is made of
+    /// nothing but units.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the pieces of the message, the pieces of the header and the
+    /// attachments in turn.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `attachments` aren't as many as the placeholders, or one
+    /// of them isn't as long as its placeholder.
+    pub fn io_slices<'a>(&'a self, attachments: &[&'a [u8]]) -> Vec<IoSlice<'a>> {
+        self.pieces(attachments).into_iter().map(IoSlice::new).collect()
+    }
+
+    /// Puts the message back together into one buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `io_slices`.
+    pub fn gather(&self, attachments: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len());
+        for piece in self.pieces(attachments) {
+            bytes.extend_from_slice(piece);
+        }
+        bytes
+    }
+
+    /// Writes the whole message to `writer` with vectored writes.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `io_slices`.
+    pub fn write_to<W: Write>(&self, writer: &mut W, attachments: &[&[u8]]) -> io::Result<()> {
+        let pieces = self.pieces(attachments);
+        let mut first = 0;
+        let mut skip = 0;
+        while first < pieces.len() {
+            let slices: Vec<IoSlice> = pieces[first..].iter().enumerate()
+                .map(|(i, piece)| IoSlice::new(if i == 0 { &piece[skip..] } else { piece }))
+                .collect();
+            let mut written = match writer.write_vectored(&slices) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "failed to write whole message")),
+                Ok(written) => written,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            while first < pieces.len() && written >= pieces[first].len() - skip {
+                written -= pieces[first].len() - skip;
+                first += 1;
+                skip = 0;
+            }
+            skip += written;
+        }
+        Ok(())
+    }
+
+    fn pieces<'a>(&'a self, attachments: &[&'a [u8]]) -> Vec<&'a [u8]> {
+        assert_eq!(attachments.len(), self.placeholders.len(), "wrong number of attachments");
+        let mut pieces = Vec::with_capacity(2 * attachments.len() + 1);
+        let mut start = 0;
+        for (placeholder, attachment) in self.placeholders.iter().zip(attachments) {
+            assert_eq!(attachment.len(), placeholder.len, "an attachment isn't as long as its placeholder");
+            pieces.push(&self.header[start..placeholder.offset]);
+            pieces.push(*attachment);
+            start = placeholder.offset;
+        }
+        pieces.push(&self.header[start..]);
+        pieces.retain(|piece| !piece.is_empty());
+        pieces
+    }
+}
+
+/// Serializes an object into a `Scatter`, leaving the bytes of its
+/// `Attachment`s out.
+///
+/// If the whole message, attachments included, would take more bytes than
+/// allowed by `size_limit`, an error is returned.
+pub fn serialize_scatter<T, S>(value: &T, size_limit: S) -> SerializeResult<Scatter>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    let (header, placeholders) = try!(writer::serialize_header(value));
+    let scatter = Scatter {
+        header: header,
+        placeholders: placeholders,
+    };
+    match size_limit.limit() {
+        Some(limit) if scatter.len() as u64 > limit => Err(SerializeError::SizeLimit),
+        _ => Ok(scatter),
+    }
+}
+
+/// Deserializes an object from a slice of bytes, leaving the bytes of every
+/// attachment that is at least `threshold` bytes long in `bytes`.
+///
+/// Those are read back as `AttachmentBuf::Detached`, with offsets into
+/// `bytes` that `AttachmentBuf::resolve` looks up.
+pub fn deserialize_detached<T>(bytes: &[u8], threshold: u64) -> DeserializeResult<T>
+    where T: serde::Deserialize,
+{
+    let mut deserializer = Deserializer::new(SliceReader::new(bytes), Infinite);
+    deserializer.set_detach_threshold(threshold);
+    serde::Deserialize::deserialize(&mut deserializer)
+}
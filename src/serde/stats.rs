@@ -0,0 +1,82 @@
+use super::observer::Observer;
+
+/// Statistics about a single decoded message.
+///
+/// Returned by `deserialize_from_with_stats`, these are meant to help with
+/// capacity planning and with picking size limits that real traffic stays
+/// well within.  A `DecodeStats` is also an `Observer`, so it can be attached
+/// to a `Deserializer` directly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// The number of bytes read.
+    ///
+    /// This is filled in by `deserialize_from_with_stats`; when attaching a
+    /// `DecodeStats` to a `Deserializer` yourself, use `bytes_read` instead.
+    pub bytes: u64,
+    /// The number of structs and struct variants read.
+    pub structs: u64,
+    /// The number of sequences read.
+    pub seqs: u64,
+    /// The number of maps read.
+    pub maps: u64,
+    /// The total number of sequence elements and map entries.
+    pub elements: u64,
+    /// The number of strings read.
+    pub strings: u64,
+    /// The number of byte buffers read.
+    pub byte_buffers: u64,
+    /// The deepest nesting of structs, sequences, and maps.
+    pub max_depth: usize,
+    /// The length of the longest string or byte buffer, and so the largest
+    /// single allocation made for one.
+    pub largest_allocation: usize,
+    depth: usize,
+}
+
+impl DecodeStats {
+    fn enter(&mut self) {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            self.max_depth = self.depth;
+        }
+    }
+
+    fn allocate(&mut self, len: usize) {
+        if len > self.largest_allocation {
+            self.largest_allocation = len;
+        }
+    }
+}
+
+impl Observer for DecodeStats {
+    fn begin_struct(&mut self, _offset: u64, _fields: &'static [&'static str]) {
+        self.structs += 1;
+        self.enter();
+    }
+
+    fn begin_seq(&mut self, _offset: u64, len: usize) {
+        self.seqs += 1;
+        self.elements += len as u64;
+        self.enter();
+    }
+
+    fn begin_map(&mut self, _offset: u64, len: usize) {
+        self.maps += 1;
+        self.elements += len as u64;
+        self.enter();
+    }
+
+    fn string(&mut self, _offset: u64, len: usize) {
+        self.strings += 1;
+        self.allocate(len);
+    }
+
+    fn bytes(&mut self, _offset: u64, len: usize) {
+        self.byte_buffers += 1;
+        self.allocate(len);
+    }
+
+    fn end(&mut self, _offset: u64) {
+        self.depth -= 1;
+    }
+}
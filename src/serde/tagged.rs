@@ -0,0 +1,172 @@
+use std::any;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use serde_crate as serde;
+
+use ::SizeLimit;
+use super::{serialize, Deserializer, SliceReader, SerializeResult, DeserializeResult};
+use super::reader::invalid_tag;
+
+/// A message type with a number that identifies it on the wire.
+///
+/// The tag has to stay the same for as long as old messages can still be
+/// around, and has to be different for every type sent down the same
+/// stream; a `Registry` checks the latter.
+pub trait Tagged: serde::Serialize + serde::Deserialize {
+    const TAG: u32;
+}
+
+/// Serializes a message with its tag in front of it.
+///
+/// The tag is a `u32`, like the tag of an enum variant, so the output is the
+/// same as that of an enum with a variant holding `T` at index `T::TAG`.
+pub fn serialize_tagged<T: Tagged, S: SizeLimit>(value: &T, size_limit: S) -> SerializeResult<Vec<u8>> {
+    serialize(&(T::TAG, value), size_limit)
+}
+
+/// Deserializes a message written by `serialize_tagged`, and checks that it
+/// has the tag of `T`.
+///
+/// Use a `Registry` when the message could be one of several types.
+pub fn deserialize_tagged<T: Tagged>(bytes: &[u8]) -> DeserializeResult<T> {
+    let mut deserializer = Deserializer::new(SliceReader::new(bytes), ::Infinite);
+    let tag: u32 = try!(serde::Deserialize::deserialize(&mut deserializer));
+    if tag != T::TAG {
+        return Err(invalid_tag("unexpected message tag",
+                               format!("expected tag {} for {}, got {}", T::TAG, any::type_name::<T>(), tag)));
+    }
+    serde::Deserialize::deserialize(&mut deserializer)
+}
+
+/// Two message types were registered with the same tag.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagCollision {
+    /// The tag both types have.
+    pub tag: u32,
+    /// The type that was registered first.
+    pub existing: &'static str,
+    /// The type that was being registered.
+    pub new: &'static str,
+}
+
+impl fmt::Display for TagCollision {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "TagCollision: {} and {} both have tag {}", self.existing, self.new, self.tag)
+    }
+}
+
+impl Error for TagCollision {
+    fn description(&self) -> &str {
+        "two message types have the same tag"
+    }
+}
+
+/// The `Deserializer` a `Registry` hands to the decoders registered with
+/// `register_decoder`, positioned just after the tag.
+pub type TaggedDeserializer<'a> = Deserializer<SliceReader<'a>, ::Infinite>;
+
+struct Entry<M> {
+    type_name: &'static str,
+    decode: Box<Fn(&mut TaggedDeserializer) -> DeserializeResult<M>>,
+}
+
+/// Decodes messages written by `serialize_tagged` into whichever of several
+/// types their tag says they are.
+///
+/// Each type is registered with a handler that turns it into an `M`, which
+/// can be a common trait object, the reply to send back, or `()` if the
+/// handlers do all the work themselves.  That lets one stream carry many
+/// types of message without an enum listing all of them.
+///
+/// ```rust,ignore
+/// let mut registry = Registry::new();
+/// registry.register(|ping: Ping| Reply::Pong(ping.seq)).unwrap();
+/// registry.register(|get: Get| store.lookup(&get.key)).unwrap();
+/// let reply = registry.deserialize(&frame).unwrap();
+/// ```
+///
+/// To decode into trait objects, box the messages in the handlers, as in
+/// `registry.register(|ping: Ping| Box::new(ping) as Box<Message>)`.
+/// Plugins that add message types while the program runs, and don't have a
+/// `Tagged` type to register, can give the tag and a function that does the
+/// decoding to `register_decoder` instead.
+pub struct Registry<M> {
+    entries: BTreeMap<u32, Entry<M>>,
+}
+
+impl<M> Registry<M> {
+    /// Creates a registry with no types in it.
+    pub fn new() -> Registry<M> {
+        Registry { entries: BTreeMap::new() }
+    }
+
+    /// Registers the message type `T`, which `handler` turns into an `M`.
+    ///
+    /// If another type already has the same tag, nothing is registered and
+    /// a `TagCollision` naming both types is returned.
+    pub fn register<T, F>(&mut self, handler: F) -> Result<(), TagCollision>
+        where T: Tagged + 'static,
+              F: Fn(T) -> M + 'static,
+    {
+        self.register_decoder(T::TAG, any::type_name::<T>(), move |deserializer| {
+            let value: T = try!(serde::Deserialize::deserialize(deserializer));
+            Ok(handler(value))
+        })
+    }
+
+    /// Registers a message type by its tag, with a function that reads a
+    /// message of that type, from just after the tag, and turns it into an
+    /// `M`.
+    ///
+    /// `type_name` is only used to report collisions.  If another type
+    /// already has the same tag, nothing is registered and a `TagCollision`
+    /// is returned.
+    pub fn register_decoder<F>(&mut self, tag: u32, type_name: &'static str, decode: F) -> Result<(), TagCollision>
+        where F: Fn(&mut TaggedDeserializer) -> DeserializeResult<M> + 'static,
+    {
+        if let Some(entry) = self.entries.get(&tag) {
+            return Err(TagCollision {
+                tag: tag,
+                existing: entry.type_name,
+                new: type_name,
+            });
+        }
+        self.entries.insert(tag, Entry {
+            type_name: type_name,
+            decode: Box::new(decode),
+        });
+        Ok(())
+    }
+
+    /// Removes the type registered with `tag`, if there is one, such as when
+    /// the plugin that added it is unloaded.  Returns `true` if there was.
+    pub fn unregister(&mut self, tag: u32) -> bool {
+        self.entries.remove(&tag).is_some()
+    }
+
+    /// Returns `true` if a type has been registered with `tag`.
+    pub fn contains(&self, tag: u32) -> bool {
+        self.entries.contains_key(&tag)
+    }
+
+    /// Deserializes a message, and passes it to the handler of its type.
+    ///
+    /// A tag that no type was registered with is an `InvalidEncoding` error.
+    pub fn deserialize(&self, bytes: &[u8]) -> DeserializeResult<M> {
+        let mut deserializer = Deserializer::new(SliceReader::new(bytes), ::Infinite);
+        let tag: u32 = try!(serde::Deserialize::deserialize(&mut deserializer));
+        match self.entries.get(&tag) {
+            Some(entry) => (entry.decode)(&mut deserializer),
+            None => Err(invalid_tag("unknown message tag",
+                                    format!("no message type is registered with tag {}", tag))),
+        }
+    }
+}
+
+impl<M> Default for Registry<M> {
+    fn default() -> Registry<M> {
+        Registry::new()
+    }
+}
@@ -0,0 +1,192 @@
+use std::cmp;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::reader::DeserializeError;
+
+/// The longest a `ThrottledRead` sleeps before it looks at its cancel flag
+/// again.
+const SLICE: Duration = Duration::from_millis(10);
+
+/// A reader that reads no faster than a given number of bytes per second.
+///
+/// This caps how much of a link a single connection can use while its
+/// messages are decoded, so that one tenant sending a huge message doesn't
+/// starve the others.  Reads take bytes out of a bucket that fills up at
+/// the given rate and holds up to `set_burst` bytes, and wait for it to
+/// fill up when it is empty.
+///
+/// Waiting can be cut short the same ways decoding can, with a cancel flag
+/// and a deadline.  Give it the same ones as the `Deserializer`, and a
+/// throttled read that is waiting when the flag is raised, or that would
+/// have to wait past the deadline, fails at once with the `Cancelled` or
+/// `DeadlineExceeded` error that the `Deserializer` itself would give.
+///
+/// ```rust,ignore
+/// let mut reader = ThrottledRead::new(socket, 64 * 1024);
+/// reader.set_deadline(deadline);
+/// let message: Request = deserialize_from(&mut reader, Bounded(1 << 20))?;
+/// ```
+pub struct ThrottledRead<R> {
+    reader: R,
+    rate: u64,
+    burst: u64,
+    tokens: u64,
+    filled: Instant,
+    cancel: Option<Arc<AtomicBool>>,
+    deadline: Option<Instant>,
+}
+
+impl<R: Read> ThrottledRead<R> {
+    /// Creates a reader that reads from `reader` at no more than
+    /// `bytes_per_second`, with a burst of a second's worth of bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is 0.
+    pub fn new(reader: R, bytes_per_second: u64) -> ThrottledRead<R> {
+        assert!(bytes_per_second > 0, "a ThrottledRead needs a rate above zero");
+        ThrottledRead {
+            reader: reader,
+            rate: bytes_per_second,
+            burst: bytes_per_second,
+            tokens: bytes_per_second,
+            filled: Instant::now(),
+            cancel: None,
+            deadline: None,
+        }
+    }
+
+    /// Sets how many bytes can be read at once without waiting, after the
+    /// reader has been idle.  It is at least 1.
+    pub fn set_burst(&mut self, bytes: u64) {
+        self.burst = cmp::max(bytes, 1);
+        self.tokens = cmp::min(self.tokens, self.burst);
+    }
+
+    /// Stops waiting with a `Cancelled` error once `flag` is set.
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
+
+    /// Stops waiting with a `DeadlineExceeded` error if the bytes to read
+    /// wouldn't be allowed through until after `deadline`.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwraps the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Adds the bytes that have been earned since the bucket was last
+    /// filled.
+    fn fill(&mut self) {
+        let now = Instant::now();
+        let earned = now.duration_since(self.filled).as_nanos() * self.rate as u128 / 1_000_000_000;
+        if self.tokens as u128 + earned >= self.burst as u128 {
+            self.tokens = self.burst;
+            self.filled = now;
+        } else if earned > 0 {
+            self.tokens += earned as u64;
+            // Only move on by the time the whole bytes took to earn, so
+            // that the fractions add up.
+            self.filled += Duration::from_nanos((earned * 1_000_000_000 / self.rate as u128) as u64);
+        }
+    }
+
+    /// Waits until `wanted` bytes can be read.
+    fn wait_for(&mut self, wanted: u64) -> io::Result<()> {
+        loop {
+            self.fill();
+            if self.tokens >= wanted {
+                return Ok(());
+            }
+            if let Some(ref flag) = self.cancel {
+                if flag.load(Ordering::Relaxed) {
+                    return Err(Stopped::Cancelled.into());
+                }
+            }
+            let missing = (wanted - self.tokens) as u128;
+            let wait = Duration::from_nanos(((missing * 1_000_000_000 + self.rate as u128 - 1) / self.rate as u128) as u64);
+            if let Some(deadline) = self.deadline {
+                if Instant::now() + wait > deadline {
+                    return Err(Stopped::DeadlineExceeded.into());
+                }
+            }
+            thread::sleep(cmp::min(wait, SLICE));
+        }
+    }
+}
+
+impl<R: Read> Read for ThrottledRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.reader.read(buf);
+        }
+        try!(self.wait_for(cmp::min(buf.len() as u64, self.burst)));
+        let len = cmp::min(buf.len() as u64, self.tokens) as usize;
+        let read = try!(self.reader.read(&mut buf[..len]));
+        self.tokens -= read as u64;
+        Ok(read)
+    }
+}
+
+/// Why a `ThrottledRead` stopped waiting, carried inside the `io::Error` it
+/// returns so that the `Deserializer` can turn it back into its own error.
+#[derive(Debug)]
+enum Stopped {
+    Cancelled,
+    DeadlineExceeded,
+}
+
+impl fmt::Display for Stopped {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.description())
+    }
+}
+
+impl Error for Stopped {
+    fn description(&self) -> &str {
+        match *self {
+            Stopped::Cancelled => "a throttled read was cancelled",
+            Stopped::DeadlineExceeded => "a throttled read would not finish before its deadline",
+        }
+    }
+}
+
+impl From<Stopped> for io::Error {
+    fn from(stopped: Stopped) -> io::Error {
+        let kind = match stopped {
+            Stopped::Cancelled => io::ErrorKind::Other,
+            Stopped::DeadlineExceeded => io::ErrorKind::TimedOut,
+        };
+        io::Error::new(kind, stopped)
+    }
+}
+
+/// Returns the `Deserializer` error for an `io::Error` returned by a
+/// `ThrottledRead` that stopped waiting, if that is what `err` is.
+pub fn stopped_error(err: &io::Error) -> Option<DeserializeError> {
+    match err.get_ref().and_then(|err| err.downcast_ref::<Stopped>()) {
+        Some(&Stopped::Cancelled) => Some(DeserializeError::Cancelled),
+        Some(&Stopped::DeadlineExceeded) => Some(DeserializeError::DeadlineExceeded),
+        None => None,
+    }
+}
@@ -0,0 +1,81 @@
+use std::ops::Deref;
+
+use serde_crate as serde;
+
+use serde_crate::bytes::{ByteBuf, Bytes};
+
+/// The name `Unknown` gives its newtype struct, which tells the bincode
+/// `Serializer` and `Deserializer` to write and read its bytes without a
+/// length in front.
+pub const NAME: &'static str = "bincode::Unknown";
+
+/// The bytes at the end of a message that the type reading it doesn't know
+/// about, kept so that they can be written out again.
+///
+/// bincode structs have no field names or lengths to skip unknown fields
+/// by, so the only fields that can be kept are the ones a newer version of
+/// a struct has added at the end, which is also how `set_fill_missing_fields`
+/// expects structs to grow.  Make an `Unknown` the last field of the
+/// struct that is the message, and it takes everything from there to the
+/// end of the input.  Serializing the struct writes those bytes back as they
+/// were, without a length, so that a proxy that only knows an older version
+/// of a message can pass newer ones on without losing anything.
+///
+/// ```rust,ignore
+/// #[derive(Serialize, Deserialize)]
+/// struct Order {
+///     id: u64,
+///     amount: u32,
+///     newer_fields: Unknown,
+/// }
+/// ```
+///
+/// Anywhere but the end of the message it would take the bytes of whatever
+/// comes after it, so it should only be used there.  Other formats write it
+/// as a byte buffer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct Unknown(pub Vec<u8>);
+
+impl Unknown {
+    /// Unwraps the bytes.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Deref for Unknown {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Unknown {
+    fn from(bytes: Vec<u8>) -> Unknown {
+        Unknown(bytes)
+    }
+}
+
+impl serde::Serialize for Unknown {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_newtype_struct(NAME, Bytes::from(&self.0))
+    }
+}
+
+impl serde::Deserialize for Unknown {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Unknown, D::Error> {
+        deserializer.deserialize_newtype_struct(NAME, UnknownVisitor)
+    }
+}
+
+struct UnknownVisitor;
+
+impl serde::de::Visitor for UnknownVisitor {
+    type Value = Unknown;
+
+    fn visit_newtype_struct<D: serde::Deserializer>(&mut self, deserializer: &mut D) -> Result<Unknown, D::Error> {
+        let bytes: ByteBuf = try!(serde::Deserialize::deserialize(deserializer));
+        Ok(Unknown(bytes.into()))
+    }
+}
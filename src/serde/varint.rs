@@ -0,0 +1,220 @@
+use std::ops::Deref;
+
+use serde_crate as serde;
+
+/// The most bytes a `Var` takes, which is what a `u64` needs.
+const MAX_VAR_LEN: usize = 10;
+
+/// The name `Var` reads its bytes under, as a tuple struct of `MAX_VAR_LEN`
+/// that it ends as soon as the last byte is read.  The bincode
+/// `Deserializer` lets a tuple struct with this name end early.
+pub const NAME: &'static str = "bincode::Var";
+
+/// Integers that can be encoded with a variable width by `Var`.
+pub trait VarInt: Copy {
+    /// Maps the integer to an unsigned one that is small when it is close
+    /// to zero.
+    fn to_var(self) -> u64;
+
+    /// Undoes `to_var`, or returns `None` if the result doesn't fit.
+    fn from_var(value: u64) -> Option<Self>;
+}
+
+macro_rules! impl_var_unsigned {
+    ($($ty:ident),*) => {
+        $(
+            impl VarInt for $ty {
+                fn to_var(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_var(value: u64) -> Option<$ty> {
+                    if value > ::std::$ty::MAX as u64 {
+                        None
+                    } else {
+                        Some(value as $ty)
+                    }
+                }
+            }
+        )*
+    }
+}
+
+macro_rules! impl_var_signed {
+    ($($ty:ident),*) => {
+        $(
+            impl VarInt for $ty {
+                fn to_var(self) -> u64 {
+                    // Zigzag: 0, -1, 1, -2, ... become 0, 1, 2, 3, ...
+                    let value = self as i64;
+                    ((value << 1) ^ (value >> 63)) as u64
+                }
+
+                fn from_var(value: u64) -> Option<$ty> {
+                    let value = (value >> 1) as i64 ^ -((value & 1) as i64);
+                    if value < ::std::$ty::MIN as i64 || value > ::std::$ty::MAX as i64 {
+                        None
+                    } else {
+                        Some(value as $ty)
+                    }
+                }
+            }
+        )*
+    }
+}
+
+impl_var_unsigned!(u16, u32, u64);
+impl_var_signed!(i16, i32, i64);
+
+/// An integer that is encoded with as few bytes as its value needs.
+///
+/// bincode writes every integer with its full width.  `Var` writes seven
+/// bits to a byte, least significant first, with the top bit of each byte
+/// set when another one follows, so values below 128 take a single byte
+/// and a `u64` takes at most ten.  Signed integers are zigzag encoded
+/// first, so that small negative numbers are short as well.
+///
+/// The bytes are written as a tuple of `u8`s, which only bincode reads back
+/// as one integer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Var<T>(pub T);
+
+/// An integer that is encoded with its full width.
+///
+/// This is how bincode writes every integer already, so `Fixed` changes
+/// nothing on the wire.  It marks the fields of a type that also has `Var`
+/// fields that have to keep their width, such as an offset that is patched
+/// in place after the rest of the message has been written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fixed<T>(pub T);
+
+impl<T> Var<T> {
+    /// Unwraps the integer.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Fixed<T> {
+    /// Unwraps the integer.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Var<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Deref for Fixed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> From<T> for Var<T> {
+    fn from(value: T) -> Var<T> {
+        Var(value)
+    }
+}
+
+impl<T> From<T> for Fixed<T> {
+    fn from(value: T) -> Fixed<T> {
+        Fixed(value)
+    }
+}
+
+impl<T: VarInt> serde::Serialize for Var<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        serializer.serialize_tuple(VarBytes { value: self.0.to_var(), done: false })
+    }
+}
+
+/// Hands out the bytes of a `Var` one at a time.
+struct VarBytes {
+    value: u64,
+    done: bool,
+}
+
+impl serde::ser::SeqVisitor for VarBytes {
+    fn visit<S: serde::Serializer>(&mut self, serializer: &mut S) -> Result<Option<()>, S::Error> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut byte = (self.value & 0x7f) as u8;
+        self.value >>= 7;
+        if self.value == 0 {
+            self.done = true;
+        } else {
+            byte |= 0x80;
+        }
+        serializer.serialize_tuple_elt(byte).map(Some)
+    }
+
+    fn len(&self) -> Option<usize> {
+        let mut len = 1;
+        let mut value = self.value >> 7;
+        while value != 0 {
+            len += 1;
+            value >>= 7;
+        }
+        Some(if self.done { 0 } else { len })
+    }
+}
+
+impl<T: VarInt> serde::Deserialize for Var<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Var<T>, D::Error> {
+        let value = try!(deserializer.deserialize_tuple_struct(NAME, MAX_VAR_LEN, VarVisitor));
+        match T::from_var(value) {
+            Some(value) => Ok(Var(value)),
+            None => Err(serde::de::Error::custom("a variable-width integer is too large for its type")),
+        }
+    }
+}
+
+struct VarVisitor;
+
+impl serde::de::Visitor for VarVisitor {
+    type Value = u64;
+
+    fn visit_seq<V: serde::de::SeqVisitor>(&mut self, mut visitor: V) -> Result<u64, V::Error> {
+        let mut value = 0u64;
+        for i in 0..MAX_VAR_LEN {
+            let byte: u8 = match try!(visitor.visit()) {
+                Some(byte) => byte,
+                None => return Err(serde::de::Error::end_of_stream()),
+            };
+            if i > 0 && byte == 0 {
+                return Err(serde::de::Error::custom("a variable-width integer is not in its shortest form"));
+            }
+            let bits = (byte & 0x7f) as u64;
+            if i == MAX_VAR_LEN - 1 && bits > 1 {
+                break;
+            }
+            value |= bits << (7 * i);
+            if byte & 0x80 == 0 {
+                try!(visitor.end());
+                return Ok(value);
+            }
+        }
+        Err(serde::de::Error::custom("a variable-width integer is longer than a u64"))
+    }
+}
+
+impl<T: serde::Serialize> serde::Serialize for Fixed<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<T: serde::Deserialize> serde::Deserialize for Fixed<T> {
+    fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Fixed<T>, D::Error> {
+        serde::Deserialize::deserialize(deserializer).map(Fixed)
+    }
+}
@@ -2,16 +2,24 @@ use std::error::Error;
 use std::fmt;
 use std::io::Error as IoError;
 use std::io::Write;
-use std::u32;
 
 use serde_crate as serde;
 
 use byteorder::{BigEndian, WriteBytesExt};
 
+use ::format::{self, StaticLayout, StandardLayout, TagOverflow};
+use super::bitpack;
+use super::config::{Config, Layout};
+use super::display;
+use super::scatter::{self, Placeholder};
+use super::unknown;
+
 pub type SerializeResult<T> = Result<T, SerializeError>;
 
 
 /// An error that can be produced during encoding.
+///
+/// Like `DeserializeError`, it is `Send`, `Sync`, and `'static`.
 #[derive(Debug)]
 pub enum SerializeError {
     /// An error originating from the underlying `Writer`.
@@ -21,6 +29,13 @@ pub enum SerializeError {
     /// This error is returned before any bytes are written to the
     /// output `Writer`.
     SizeLimit,
+    /// An object does not fit in the buffer it was to be encoded into, which
+    /// would have taken `needed` bytes.
+    ///
+    /// This error is returned before any bytes are written to the buffer.
+    BufferTooSmall {
+        needed: u64,
+    },
     /// A custom error message
     Custom(String)
 }
@@ -29,14 +44,64 @@ pub enum SerializeError {
 ///
 /// This struct should not be used often.
 /// For most cases, prefer the `encode_into` function.
-pub struct Serializer<'a, W: 'a> {
+pub struct Serializer<'a, W: ?Sized + 'a, L: Layout = Config> {
     writer: &'a mut W,
+    skip_defaults: bool,
+    /// Whether each field of the struct being written is present, while
+    /// its fields are being written to a buffer.
+    present: Option<Vec<bool>>,
+    layout: L,
+    /// The bits packed so far into the next byte, from the top down.
+    bits: u8,
+    bit_count: u8,
+    /// The width of the `Bits` field being written.
+    packed_width: Option<u8>,
+    /// Whether the bytes about to be written are those of an `Unknown`,
+    /// which go without a length.
+    raw_bytes: bool,
+    /// Where the attachments left out of the output go, and how to tell
+    /// how much has been written, while a `Scatter` is being written.
+    placeholders: Option<(fn(&W) -> usize, Vec<Placeholder>)>,
+    /// Whether the bytes about to be written are those of an `Attachment`.
+    attachment: bool,
+    /// The bytes left of the `CollectStr` being written, whose pieces go
+    /// without a length.
+    raw_strs: Option<usize>,
+}
+
+/// Serializes `value` into the header of a `Scatter`, leaving out the
+/// bytes of its `Attachment`s and returning where they go.
+pub fn serialize_header<T>(value: &T) -> SerializeResult<(Vec<u8>, Vec<Placeholder>)>
+    where T: serde::Serialize,
+{
+    let mut header = Vec::new();
+    let placeholders = {
+        let mut serializer = Serializer::new(&mut header);
+        serializer.placeholders = Some((|header: &Vec<u8>| header.len(), Vec::new()));
+        try!(value.serialize(&mut serializer));
+        serializer.placeholders.map(|(_, placeholders)| placeholders).unwrap_or_default()
+    };
+    Ok((header, placeholders))
 }
 
 fn wrap_io(err: IoError) -> SerializeError {
     SerializeError::IoError(err)
 }
 
+fn changed_text() -> SerializeError {
+    SerializeError::Custom("a Display implementation wrote something else the second time".to_string())
+}
+
+fn not_packable() -> SerializeError {
+    SerializeError::Custom("only unsigned integers can be packed into bits".to_string())
+}
+
+impl From<TagOverflow> for SerializeError {
+    fn from(err: TagOverflow) -> SerializeError {
+        SerializeError::Custom(err.to_string())
+    }
+}
+
 impl serde::ser::Error for SerializeError {
     fn custom<T: Into<String>>(msg: T) -> Self {
         SerializeError::Custom(msg.into())
@@ -49,6 +114,8 @@ impl fmt::Display for SerializeError {
             SerializeError::IoError(ref err) => write!(f, "IoError: {}", err),
             SerializeError::Custom(ref s) => write!(f, "Custom Error {}", s),
             SerializeError::SizeLimit => write!(f, "SizeLimit"),
+            SerializeError::BufferTooSmall { needed } =>
+                write!(f, "BufferTooSmall: {} bytes needed", needed),
         }
     }
 }
@@ -58,6 +125,7 @@ impl Error for SerializeError {
         match *self {
             SerializeError::IoError(ref err) => Error::description(err),
             SerializeError::SizeLimit => "the size limit for decoding has been reached",
+            SerializeError::BufferTooSmall { .. } => "the buffer is too small for the encoded object",
             SerializeError::Custom(_) => "a custom serialization error was reported",
         }
     }
@@ -66,95 +134,305 @@ impl Error for SerializeError {
         match *self {
             SerializeError::IoError(ref err) => err.cause(),
             SerializeError::SizeLimit => None,
+            SerializeError::BufferTooSmall { .. } => None,
             SerializeError::Custom(_) => None,
         }
     }
 }
 
-impl<'a, W: Write> Serializer<'a, W> {
+impl<'a, W: Write + ?Sized> Serializer<'a, W> {
     pub fn new(w: &'a mut W) -> Serializer<'a, W> {
+        Serializer::with_layout(w, Config::standard())
+    }
+
+    /// Controls whether fixed-size arrays are written with their length.
+    ///
+    /// Like any other sequence, an array such as `[u16; 4]` starts with its
+    /// length by default.  With `fixed` set to `true`, it is written as
+    /// nothing but its elements, the way a C array is laid out in a struct.
+    /// The output has to be read by a `Deserializer` with the same setting.
+    pub fn set_fixed_arrays(&mut self, fixed: bool) {
+        self.layout.set_fixed_arrays(fixed);
+    }
+
+    /// Controls whether bools and `Bits` fields are packed into bits.
+    ///
+    /// With `packing` set to `true`, a `bool` takes up a single bit and a
+    /// `Bits<T, N>` takes up `N`, and consecutive ones share bytes, filled
+    /// from the most significant bit down.  Anything else starts on a new
+    /// byte, as does every struct and the first thing after a struct, so
+    /// the unused bits of a partly filled byte are always zero.  Call
+    /// `flush_bits` once the value is written to write out the last byte.
+    ///
+    /// The output has to be read by a `Deserializer` with the same setting.
+    /// Packing doesn't combine with `set_skip_defaults`.
+    pub fn set_bit_packing(&mut self, packing: bool) {
+        self.layout.set_bit_packing(packing);
+    }
+
+    /// Controls whether chars are written as a string of one char, with a
+    /// `u64` length in front, the way older versions of bincode wrote them.
+    ///
+    /// The output can be read by those versions, or by a `Deserializer`
+    /// with `set_legacy_chars`.
+    pub fn set_legacy_chars(&mut self, legacy: bool) {
+        self.layout.set_legacy_chars(legacy);
+    }
+
+    /// Controls whether `is_human_readable` returns `true`.
+    pub fn set_human_readable(&mut self, human_readable: bool) {
+        self.layout.set_human_readable(human_readable);
+    }
+}
+
+impl<'a, W: Write + ?Sized, L: Layout> Serializer<'a, W, L> {
+    /// Creates a `Serializer` that writes the layout `layout` describes.
+    ///
+    /// With one of the layout types, such as `CompactLayout`, the layout
+    /// is part of the type of the `Serializer` and its checks compile
+    /// away.  With a `Config`, they are made as the value is written.
+    pub fn with_layout(w: &'a mut W, layout: L) -> Serializer<'a, W, L> {
         Serializer {
             writer: w,
+            skip_defaults: false,
+            present: None,
+            layout: layout,
+            bits: 0,
+            bit_count: 0,
+            packed_width: None,
+            raw_bytes: false,
+            placeholders: None,
+            attachment: false,
+            raw_strs: None,
         }
     }
 
-    fn add_enum_tag(&mut self, tag: usize) -> SerializeResult<()> {
-        if tag > u32::MAX as usize {
-            panic!("Variant tag doesn't fit in a u32")
+    /// Controls whether struct fields that hold the empty value of their
+    /// type are left out.
+    ///
+    /// With `skip` set to `true`, every struct starts with a bitmap of which
+    /// of its fields are present, one bit per field (the lowest bit of the
+    /// first byte for the first field), and the fields that hold `0`,
+    /// `false`, `""`, `None`, an empty collection, or the first variant of
+    /// an enum with no data, are left out.  A struct of such fields is
+    /// itself left out of the struct around it.  This applies to any field,
+    /// not just `Option`s, and saves a lot of space on structs that are
+    /// mostly left at their defaults.
+    ///
+    /// The output has to be read by a `Deserializer` with the same setting,
+    /// which fills the missing fields back in.
+    pub fn set_skip_defaults(&mut self, skip: bool) {
+        self.skip_defaults = skip;
+    }
+
+    /// Returns whether types that have a human-readable form, such as a
+    /// date written as text, should write it rather than a compact one.
+    ///
+    /// This is `false` unless the layout is a `Config` that was given
+    /// `set_human_readable`, and the `Deserializer` of the same layout
+    /// returns the same.  serde 0.7 has no such method on its `Serializer`
+    /// trait, so only code that holds this `Serializer`, rather than a
+    /// generic `S: serde::Serializer`, can ask.
+    ///
+    /// ```rust,ignore
+    /// fn write_timestamp<W: Write>(serializer: &mut Serializer<W, Config>, secs: u64) -> SerializeResult<()> {
+    ///     if serializer.is_human_readable() {
+    ///         serde::Serializer::serialize_str(serializer, &format_rfc3339(secs))
+    ///     } else {
+    ///         serde::Serializer::serialize_u64(serializer, secs)
+    ///     }
+    /// }
+    /// ```
+    pub fn is_human_readable(&self) -> bool {
+        self.layout.human_readable()
+    }
+
+    /// Writes out the byte that bits are being packed into, if there is
+    /// one.
+    pub fn flush_bits(&mut self) -> SerializeResult<()> {
+        if self.bit_count == 0 {
+            return Ok(());
         }
+        let byte = self.bits;
+        self.bits = 0;
+        self.bit_count = 0;
+        self.writer.write_u8(byte).map_err(wrap_io)
+    }
 
-        serde::Serializer::serialize_u32(self, tag as u32)
+    /// Returns the writer to write whole bytes to, once any packed bits
+    /// are written out.
+    fn out(&mut self) -> SerializeResult<&mut W> {
+        if self.packed_width.take().is_some() {
+            return Err(not_packable());
+        }
+        try!(self.flush_bits());
+        Ok(&mut *self.writer)
+    }
+
+    /// Packs the lowest `width` bits of `value`, highest first.
+    fn write_bits(&mut self, value: u64, width: u8) -> SerializeResult<()> {
+        for i in (0..width).rev() {
+            self.bits |= (((value >> i) & 1) as u8) << (7 - self.bit_count);
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                try!(self.flush_bits());
+            }
+        }
+        Ok(())
+    }
+
+    fn write_packed(&mut self, value: u64, width: u8) -> SerializeResult<()> {
+        if width < 64 && value >> width != 0 {
+            return Err(SerializeError::Custom(
+                format!("{} doesn't fit in {} bits", value, width)));
+        }
+        self.write_bits(value, width)
+    }
+
+    fn add_enum_tag(&mut self, tag: usize) -> SerializeResult<()> {
+        serde::Serializer::serialize_u32(self, try!(format::variant_tag(tag)))
+    }
+
+    /// Writes a struct's presence bitmap and the fields that are present.
+    fn serialize_fields<V>(&mut self, mut visitor: V) -> SerializeResult<()>
+        where V: serde::ser::MapVisitor,
+    {
+        let mut fields = Vec::new();
+        let present = {
+            let mut serializer = Serializer::with_layout(&mut fields, self.layout);
+            serializer.skip_defaults = true;
+            serializer.present = Some(Vec::new());
+            while let Some(()) = try!(visitor.visit(&mut serializer)) { }
+            serializer.present.unwrap_or_default()
+        };
+
+        let mut bitmap = vec![0u8; (present.len() + 7) / 8];
+        for (i, _) in present.iter().enumerate().filter(|&(_, &present)| present) {
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+        try!(self.writer.write_all(&bitmap).map_err(wrap_io));
+        self.writer.write_all(&fields).map_err(wrap_io)
     }
 }
 
-impl<'a, W: Write> serde::Serializer for Serializer<'a, W> {
+impl<'a, W: Write + ?Sized, L: Layout> serde::Serializer for Serializer<'a, W, L> {
     type Error = SerializeError;
 
     fn serialize_unit(&mut self) -> SerializeResult<()> { Ok(()) }
 
     fn serialize_bool(&mut self, v: bool) -> SerializeResult<()> {
-        self.writer.write_u8(if v {1} else {0}).map_err(wrap_io)
+        if self.layout.bit_packing() {
+            self.packed_width = None;
+            return self.write_bits(v as u64, 1);
+        }
+        try!(self.out()).write_u8(format::bool_byte(v)).map_err(wrap_io)
     }
 
     fn serialize_u8(&mut self, v: u8) -> SerializeResult<()> {
-        self.writer.write_u8(v).map_err(wrap_io)
+        if let Some(width) = self.packed_width.take() {
+            return self.write_packed(v as u64, width);
+        }
+        try!(self.out()).write_u8(v).map_err(wrap_io)
     }
 
     fn serialize_u16(&mut self, v: u16) -> SerializeResult<()> {
-        self.writer.write_u16::<BigEndian>(v).map_err(wrap_io)
+        if let Some(width) = self.packed_width.take() {
+            return self.write_packed(v as u64, width);
+        }
+        try!(self.out()).write_u16::<BigEndian>(v).map_err(wrap_io)
     }
 
     fn serialize_u32(&mut self, v: u32) -> SerializeResult<()> {
-        self.writer.write_u32::<BigEndian>(v).map_err(wrap_io)
+        if let Some(width) = self.packed_width.take() {
+            return self.write_packed(v as u64, width);
+        }
+        try!(self.out()).write_u32::<BigEndian>(v).map_err(wrap_io)
     }
 
     fn serialize_u64(&mut self, v: u64) -> SerializeResult<()> {
-        self.writer.write_u64::<BigEndian>(v).map_err(wrap_io)
+        if let Some(width) = self.packed_width.take() {
+            return self.write_packed(v as u64, width);
+        }
+        try!(self.out()).write_u64::<BigEndian>(v).map_err(wrap_io)
     }
 
     fn serialize_i8(&mut self, v: i8) -> SerializeResult<()> {
-        self.writer.write_i8(v).map_err(wrap_io)
+        try!(self.out()).write_i8(v).map_err(wrap_io)
     }
 
     fn serialize_i16(&mut self, v: i16) -> SerializeResult<()> {
-        self.writer.write_i16::<BigEndian>(v).map_err(wrap_io)
+        try!(self.out()).write_i16::<BigEndian>(v).map_err(wrap_io)
     }
 
     fn serialize_i32(&mut self, v: i32) -> SerializeResult<()> {
-        self.writer.write_i32::<BigEndian>(v).map_err(wrap_io)
+        try!(self.out()).write_i32::<BigEndian>(v).map_err(wrap_io)
     }
 
     fn serialize_i64(&mut self, v: i64) -> SerializeResult<()> {
-        self.writer.write_i64::<BigEndian>(v).map_err(wrap_io)
+        try!(self.out()).write_i64::<BigEndian>(v).map_err(wrap_io)
     }
 
     fn serialize_f32(&mut self, v: f32) -> SerializeResult<()> {
-        self.writer.write_f32::<BigEndian>(v).map_err(wrap_io)
+        try!(self.out()).write_f32::<BigEndian>(v).map_err(wrap_io)
     }
 
     fn serialize_f64(&mut self, v: f64) -> SerializeResult<()> {
-        self.writer.write_f64::<BigEndian>(v).map_err(wrap_io)
+        try!(self.out()).write_f64::<BigEndian>(v).map_err(wrap_io)
+    }
+
+    fn serialize_char(&mut self, v: char) -> SerializeResult<()> {
+        let mut buf = [0; 4];
+        if self.layout.legacy_chars() {
+            try!(self.serialize_usize(v.len_utf8()));
+        }
+        try!(self.out()).write_all(format::char_bytes(v, &mut buf)).map_err(wrap_io)
     }
 
     fn serialize_str(&mut self, v: &str) -> SerializeResult<()> {
+        try!(self.out());
+        if let Some(left) = self.raw_strs {
+            if v.len() > left {
+                return Err(changed_text());
+            }
+            self.raw_strs = Some(left - v.len());
+            return try!(self.out()).write_all(v.as_bytes()).map_err(SerializeError::IoError);
+        }
+        try!(self.serialize_usize(v.len()));
+        try!(self.out()).write_all(v.as_bytes()).map_err(SerializeError::IoError)
+    }
+
+    fn serialize_bytes(&mut self, v: &[u8]) -> SerializeResult<()> {
+        try!(self.out());
+        if self.raw_bytes {
+            self.raw_bytes = false;
+            return try!(self.out()).write_all(v).map_err(SerializeError::IoError);
+        }
         try!(self.serialize_usize(v.len()));
-        self.writer.write_all(v.as_bytes()).map_err(SerializeError::IoError)
+        if self.attachment {
+            self.attachment = false;
+            if let Some((position, ref mut placeholders)) = self.placeholders {
+                placeholders.push(Placeholder { offset: position(self.writer), len: v.len() });
+                return Ok(());
+            }
+        }
+        try!(self.out()).write_all(v).map_err(SerializeError::IoError)
     }
 
     fn serialize_none(&mut self) -> SerializeResult<()> {
-        self.writer.write_u8(0).map_err(wrap_io)
+        try!(self.out()).write_u8(format::option_tag(false)).map_err(wrap_io)
     }
 
     fn serialize_some<T>(&mut self, v: T) -> SerializeResult<()>
         where T: serde::Serialize,
     {
-        try!(self.writer.write_u8(1).map_err(wrap_io));
+        try!(try!(self.out()).write_u8(format::option_tag(true)).map_err(wrap_io));
         v.serialize(self)
     }
 
     fn serialize_seq<V>(&mut self, mut visitor: V) -> SerializeResult<()>
         where V: serde::ser::SeqVisitor,
     {
+        try!(self.out());
         let len = match visitor.len() {
             Some(len) => len,
             None => panic!("do not know how to serialize a sequence with no length"),
@@ -181,9 +459,20 @@ impl<'a, W: Write> serde::Serializer for Serializer<'a, W> {
         value.serialize(self)
     }
 
+    fn serialize_fixed_size_array<V>(&mut self, visitor: V) -> SerializeResult<()>
+        where V: serde::ser::SeqVisitor,
+    {
+        if self.layout.fixed_arrays() {
+            self.serialize_tuple(visitor)
+        } else {
+            self.serialize_seq(visitor)
+        }
+    }
+
     fn serialize_map<V>(&mut self, mut visitor: V) -> SerializeResult<()>
         where V: serde::ser::MapVisitor,
     {
+        try!(self.out());
         let len = match visitor.len() {
             Some(len) => len,
             None => panic!("do not know how to serialize a map with no length"),
@@ -207,22 +496,89 @@ impl<'a, W: Write> serde::Serializer for Serializer<'a, W> {
     fn serialize_struct<V>(&mut self, _name: &str, mut visitor: V) -> SerializeResult<()>
         where V: serde::ser::MapVisitor,
     {
+        if self.skip_defaults {
+            return self.serialize_fields(visitor);
+        }
+
+        try!(self.flush_bits());
         while let Some(()) = try!(visitor.visit(self)) { }
 
-        Ok(())
+        self.flush_bits()
     }
 
     fn serialize_struct_elt<V>(&mut self, _key: &str, value: V) -> SerializeResult<()>
         where V: serde::Serialize,
     {
-        value.serialize(self)
+        if self.present.is_none() {
+            return value.serialize(self);
+        }
+
+        // The empty value of every type is written as nothing but zeros,
+        // and every other value has a non-zero byte somewhere.
+        let mut field = Vec::new();
+        {
+            let mut serializer = Serializer::with_layout(&mut field, self.layout);
+            serializer.skip_defaults = true;
+            try!(value.serialize(&mut serializer));
+        }
+        let present = field.iter().any(|&b| b != 0);
+        if let Some(ref mut fields) = self.present {
+            fields.push(present);
+        }
+        if present {
+            try!(try!(self.out()).write_all(&field).map_err(wrap_io));
+        }
+        Ok(())
     }
 
     fn serialize_newtype_struct<T>(&mut self,
-                               _name: &str,
+                               name: &str,
                                value: T) -> SerializeResult<()>
         where T: serde::ser::Serialize,
     {
+        if name == unknown::NAME {
+            self.raw_bytes = true;
+            let result = value.serialize(&mut *self);
+            self.raw_bytes = false;
+            return result;
+        }
+        if name == display::NAME {
+            // Format the text once to count its length, then again into the
+            // output after the length.
+            let mut counter = SizeChecker::new(::std::u64::MAX);
+            counter.raw_strs = true;
+            try!(value.serialize(&mut counter));
+            let len = counter.written as usize;
+            try!(self.serialize_usize(len));
+            self.raw_strs = Some(len);
+            let result = value.serialize(&mut *self);
+            let left = self.raw_strs.take();
+            try!(result);
+            if left != Some(0) {
+                // The length is already written, so the output is no good.
+                return Err(changed_text());
+            }
+            return Ok(());
+        }
+        if name == display::STREAM && self.raw_strs.is_some() {
+            return Ok(());
+        }
+        if name == scatter::NAME && self.placeholders.is_some() {
+            self.attachment = true;
+            let result = value.serialize(&mut *self);
+            self.attachment = false;
+            return result;
+        }
+        if self.layout.bit_packing() {
+            if let Some(width) = bitpack::width(name) {
+                self.packed_width = Some(width);
+                try!(value.serialize(&mut *self));
+                if self.packed_width.take().is_some() {
+                    return Err(not_packable());
+                }
+                return Ok(());
+            }
+        }
         value.serialize(self)
     }
 
@@ -256,22 +612,31 @@ impl<'a, W: Write> serde::Serializer for Serializer<'a, W> {
     {
         try!(self.add_enum_tag(variant_index));
 
+        if self.skip_defaults {
+            return self.serialize_fields(visitor);
+        }
+
+        try!(self.flush_bits());
         while let Some(()) = try!(visitor.visit(self)) { }
 
-        Ok(())
+        self.flush_bits()
     }
 }
 
 pub struct SizeChecker {
     pub size_limit: u64,
-    pub written: u64
+    pub written: u64,
+    /// Whether the strings about to be counted are the pieces of a
+    /// `CollectStr`.
+    raw_strs: bool,
 }
 
 impl SizeChecker {
     pub fn new(limit: u64) -> SizeChecker {
         SizeChecker {
             size_limit: limit,
-            written: 0
+            written: 0,
+            raw_strs: false,
         }
     }
 
@@ -290,11 +655,7 @@ impl SizeChecker {
     }
 
     fn add_enum_tag(&mut self, tag: usize) -> SerializeResult<()> {
-        if tag > u32::MAX as usize {
-            panic!("Variant tag doesn't fit in a u32")
-        }
-
-        self.add_value(tag as u32)
+        self.add_value(try!(format::variant_tag(tag)))
     }
 }
 
@@ -347,11 +708,47 @@ impl serde::Serializer for SizeChecker {
         self.add_value(v)
     }
 
+    fn serialize_char(&mut self, v: char) -> SerializeResult<()> {
+        self.add_raw(v.len_utf8())
+    }
+
     fn serialize_str(&mut self, v: &str) -> SerializeResult<()> {
-        try!(self.add_value(0 as u64));
+        if self.raw_strs {
+            return self.add_raw(v.len());
+        }
+        try!(self.add_raw(StandardLayout::LEN_PREFIX_BYTES));
         self.add_raw(v.len())
     }
 
+    fn serialize_bytes(&mut self, v: &[u8]) -> SerializeResult<()> {
+        try!(self.add_raw(StandardLayout::LEN_PREFIX_BYTES));
+        self.add_raw(v.len())
+    }
+
+    fn serialize_newtype_struct<T>(&mut self,
+                               name: &str,
+                               value: T) -> SerializeResult<()>
+        where T: serde::ser::Serialize,
+    {
+        if name == unknown::NAME {
+            // The bytes of an `Unknown` are written without their length.
+            let mut checker = SizeChecker::new(::std::u64::MAX);
+            try!(value.serialize(&mut checker));
+            return self.add_raw((checker.written as usize).saturating_sub(StandardLayout::LEN_PREFIX_BYTES));
+        }
+        if name == display::NAME {
+            try!(self.add_raw(StandardLayout::LEN_PREFIX_BYTES));
+            self.raw_strs = true;
+            let result = value.serialize(&mut *self);
+            self.raw_strs = false;
+            return result;
+        }
+        if name == display::STREAM && self.raw_strs {
+            return Ok(());
+        }
+        value.serialize(self)
+    }
+
     fn serialize_none(&mut self) -> SerializeResult<()> {
         self.add_value(0 as u8)
     }
@@ -0,0 +1,422 @@
+//! Passing bincode messages between processes through shared memory.
+//!
+//! Two processes on one host can trade messages without a socket by
+//! mapping the same region of memory, from a file in `/dev/shm`, a
+//! `memfd`, or `shm_open`, and treating it as a ring.  One process owns the
+//! `RingProducer` and the other the `RingConsumer`; there must never be
+//! more than one of each for a region.  Neither side ever blocks: a full
+//! ring refuses a message and an empty one has none, and it's up to the
+//! caller to spin, sleep, or wait on some other signal.
+//!
+//! The region starts with a header of `HEADER_LEN` bytes that holds the
+//! positions of both sides, and the rest is the ring itself, cut down to a
+//! power of two.  Each message is a frame, laid out like those of a
+//! `FrameWriter`: its length as a big-endian `u64`, then its bytes.
+//! Frames run over the end of the ring and on from its start, and the
+//! consumer decodes one that does in place, through a `SegmentedReader`
+//! over the two pieces, instead of copying it together first.
+//!
+//! ```rust,ignore
+//! // In the producing process, once the region is mapped.
+//! let mut producer = unsafe { RingProducer::create(region, region_len)? };
+//! while !producer.send(&Tick { price: 101 })? {
+//!     thread::yield_now();
+//! }
+//!
+//! // In the consuming process.
+//! let mut consumer = unsafe { RingConsumer::attach(region, region_len)? };
+//! if let Some(tick) = consumer.recv::<Tick>()? {
+//!     handle(tick);
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use byteorder::{BigEndian, ByteOrder};
+use serde_crate as serde;
+
+use ::Infinite;
+use ::serde::{serialize_into, serialized_size, deserialize_segments, SerializeError, DeserializeError};
+
+/// The number of bytes at the start of a region that hold the positions of
+/// the producer and the consumer.
+///
+/// Each position sits on a cache line of its own, so the two sides don't
+/// slow each other down by writing to the same one.
+pub const HEADER_LEN: usize = 128;
+
+/// Where in the header the producer's position is.
+const WRITE_POS: usize = 0;
+/// Where in the header the consumer's position is.
+const READ_POS: usize = 64;
+/// The length in front of every frame.
+const FRAME_HEADER_LEN: usize = 8;
+
+/// An error that can be produced while using a ring.
+#[derive(Debug)]
+pub enum ShmError {
+    /// The region is too small to hold a ring, or isn't aligned for the
+    /// positions in its header.
+    InvalidRegion,
+    /// A message is too long to ever fit in the ring, however empty it is.
+    TooLarge,
+    /// A position in the header is further from the other than the ring
+    /// is long, or a frame in the ring claims to be longer than what the
+    /// producer has written, so the region was changed by something else.
+    Corrupt,
+    /// A message to send could not be serialized.
+    Serialize(SerializeError),
+    /// A message that was received could not be deserialized.  The frame
+    /// it was in has been consumed.
+    Deserialize(DeserializeError),
+}
+
+impl fmt::Display for ShmError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShmError::InvalidRegion =>
+                write!(fmt, "InvalidRegion"),
+            ShmError::TooLarge =>
+                write!(fmt, "TooLarge"),
+            ShmError::Corrupt =>
+                write!(fmt, "Corrupt"),
+            ShmError::Serialize(ref err) =>
+                write!(fmt, "Serialize: {}", err),
+            ShmError::Deserialize(ref err) =>
+                write!(fmt, "Deserialize: {}", err),
+        }
+    }
+}
+
+pub type ShmResult<T> = Result<T, ShmError>;
+
+impl Error for ShmError {
+    fn description(&self) -> &str {
+        match *self {
+            ShmError::InvalidRegion => "the region is too small or misaligned for a ring",
+            ShmError::TooLarge => "a message is too long to fit in the ring",
+            ShmError::Corrupt => "the positions or a frame length in the ring don't add up",
+            ShmError::Serialize(ref err) => err.description(),
+            ShmError::Deserialize(ref err) => err.description(),
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ShmError::InvalidRegion => None,
+            ShmError::TooLarge => None,
+            ShmError::Corrupt => None,
+            ShmError::Serialize(ref err) => Some(err),
+            ShmError::Deserialize(ref err) => Some(err),
+        }
+    }
+}
+
+impl From<SerializeError> for ShmError {
+    fn from(err: SerializeError) -> ShmError {
+        ShmError::Serialize(err)
+    }
+}
+
+impl From<DeserializeError> for ShmError {
+    fn from(err: DeserializeError) -> ShmError {
+        ShmError::Deserialize(err)
+    }
+}
+
+/// The view of a region that both sides share.
+///
+/// The positions only ever grow, wrapping around at `usize::MAX`; because
+/// the ring's capacity is a power of two, masking a position gives its
+/// offset in the ring either way.
+struct Ring<'a> {
+    write_pos: &'a AtomicUsize,
+    read_pos: &'a AtomicUsize,
+    data: *mut u8,
+    mask: usize,
+    _region: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Ring<'a> {
+    unsafe fn new(region: *mut u8, len: usize) -> ShmResult<Ring<'a>> {
+        if region.is_null() || len < HEADER_LEN + FRAME_HEADER_LEN + 1
+            || region as usize % mem::align_of::<AtomicUsize>() != 0 {
+            return Err(ShmError::InvalidRegion);
+        }
+
+        // The largest power of two that fits after the header.
+        let available = len - HEADER_LEN;
+        let mut capacity = 1;
+        while capacity <= available / 2 {
+            capacity *= 2;
+        }
+
+        Ok(Ring {
+            write_pos: &*(region.offset(WRITE_POS as isize) as *const AtomicUsize),
+            read_pos: &*(region.offset(READ_POS as isize) as *const AtomicUsize),
+            data: region.offset(HEADER_LEN as isize),
+            mask: capacity - 1,
+            _region: PhantomData,
+        })
+    }
+
+    fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// Copies `bytes` into the ring at `pos`, going on from the start of
+    /// the ring if they run over its end.
+    unsafe fn write_at(&self, pos: usize, bytes: &[u8]) {
+        let offset = pos & self.mask;
+        let first = ::std::cmp::min(bytes.len(), self.capacity() - offset);
+        ptr::copy_nonoverlapping(bytes.as_ptr(), self.data.offset(offset as isize), first);
+        ptr::copy_nonoverlapping(bytes[first..].as_ptr(), self.data, bytes.len() - first);
+    }
+
+    /// Returns the `len` bytes at `pos`, as the piece up to the end of the
+    /// ring and the piece from its start.
+    ///
+    /// `len` must be at most the capacity, or the second piece runs past
+    /// the end of the region.
+    unsafe fn pieces_at(&self, pos: usize, len: usize) -> (&[u8], &[u8]) {
+        let offset = pos & self.mask;
+        let first = ::std::cmp::min(len, self.capacity() - offset);
+        (slice::from_raw_parts(self.data.offset(offset as isize), first),
+         slice::from_raw_parts(self.data, len - first))
+    }
+}
+
+/// The writing side of a ring.
+pub struct RingProducer<'a> {
+    ring: Ring<'a>,
+}
+
+// The producer only writes to the part of the ring the consumer has given
+// up, and hands it over through the positions.
+unsafe impl<'a> Send for RingProducer<'a> {}
+
+impl<'a> RingProducer<'a> {
+    /// Sets up an empty ring in the region of `len` bytes at `region`, and
+    /// returns its producer.
+    ///
+    /// The consumer attaches to the region with `RingConsumer::attach`
+    /// after this.  Returns `ShmError::InvalidRegion` if the region isn't
+    /// aligned for a `usize`, or has no room for a frame after the header.
+    ///
+    /// # Safety
+    ///
+    /// `region` must be valid for reads and writes of `len` bytes for as
+    /// long as `'a`, and nothing but the one consumer may touch it while the
+    /// producer exists.
+    pub unsafe fn create(region: *mut u8, len: usize) -> ShmResult<RingProducer<'a>> {
+        let ring = try!(Ring::new(region, len));
+        ring.write_pos.store(0, Ordering::Relaxed);
+        ring.read_pos.store(0, Ordering::Release);
+        Ok(RingProducer { ring: ring })
+    }
+
+    /// The number of bytes that messages can take up in the ring, each
+    /// with an 8-byte length in front.
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity()
+    }
+
+    /// The number of bytes in the ring that the consumer has not read yet.
+    pub fn pending(&self) -> usize {
+        let write = self.ring.write_pos.load(Ordering::Relaxed);
+        write.wrapping_sub(self.ring.read_pos.load(Ordering::Acquire))
+    }
+
+    /// Serializes `value` straight into the ring.
+    ///
+    /// Returns `false`, and writes nothing, if the ring doesn't have room
+    /// for the message right now.  Returns `ShmError::TooLarge` if it
+    /// never will.
+    pub fn send<T: serde::Serialize>(&mut self, value: &T) -> ShmResult<bool> {
+        let len = serialized_size(value);
+        let pos = match try!(self.reserve(len)) {
+            Some(pos) => pos,
+            None => return Ok(false),
+        };
+        let mut writer = RingWriter {
+            ring: &self.ring,
+            pos: pos.wrapping_add(FRAME_HEADER_LEN),
+            remaining: len as usize,
+        };
+        try!(serialize_into(&mut writer, value, Infinite));
+        if writer.remaining != 0 {
+            // The length in front of the frame would be wrong, so the frame
+            // is left unpublished.
+            return Err(ShmError::Serialize(SerializeError::Custom(
+                "the message is shorter than its serialized size".to_string())));
+        }
+        self.publish(pos, len as usize);
+        Ok(true)
+    }
+
+    /// Copies `payload` into the ring as one frame.
+    ///
+    /// Returns `false`, and writes nothing, if the ring doesn't have room
+    /// for it right now.
+    pub fn send_bytes(&mut self, payload: &[u8]) -> ShmResult<bool> {
+        let pos = match try!(self.reserve(payload.len() as u64)) {
+            Some(pos) => pos,
+            None => return Ok(false),
+        };
+        unsafe { self.ring.write_at(pos.wrapping_add(FRAME_HEADER_LEN), payload) };
+        self.publish(pos, payload.len());
+        Ok(true)
+    }
+
+    /// Finds room for a frame with `len` bytes in it, writes its length,
+    /// and returns where the frame starts.
+    fn reserve(&mut self, len: u64) -> ShmResult<Option<usize>> {
+        let needed = (FRAME_HEADER_LEN as u64).saturating_add(len);
+        if needed > self.capacity() as u64 {
+            return Err(ShmError::TooLarge);
+        }
+        // The consumer owns the read position, so don't take it on trust.
+        let free = match self.capacity().checked_sub(self.pending()) {
+            Some(free) => free,
+            None => return Err(ShmError::Corrupt),
+        };
+        if needed as usize > free {
+            return Ok(None);
+        }
+
+        let pos = self.ring.write_pos.load(Ordering::Relaxed);
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        BigEndian::write_u64(&mut header, len);
+        unsafe { self.ring.write_at(pos, &header) };
+        Ok(Some(pos))
+    }
+
+    /// Hands the frame at `pos` over to the consumer.
+    fn publish(&mut self, pos: usize, len: usize) {
+        let end = pos.wrapping_add(FRAME_HEADER_LEN + len);
+        self.ring.write_pos.store(end, Ordering::Release);
+    }
+}
+
+/// Writes a serialized message into the ring, after its length.
+struct RingWriter<'r, 'a: 'r> {
+    ring: &'r Ring<'a>,
+    pos: usize,
+    remaining: usize,
+}
+
+impl<'r, 'a> Write for RingWriter<'r, 'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only the room reserved for the message may be written, in case
+        // it serializes to more than `serialized_size` said.
+        if buf.len() > self.remaining {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "the message is longer than its serialized size"));
+        }
+        unsafe { self.ring.write_at(self.pos, buf) };
+        self.pos = self.pos.wrapping_add(buf.len());
+        self.remaining -= buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The reading side of a ring.
+pub struct RingConsumer<'a> {
+    ring: Ring<'a>,
+}
+
+// The consumer only reads the part of the ring the producer has handed
+// over, and gives it back through the positions.
+unsafe impl<'a> Send for RingConsumer<'a> {}
+
+impl<'a> RingConsumer<'a> {
+    /// Attaches to the ring that a `RingProducer` set up in the region of
+    /// `len` bytes at `region`.
+    ///
+    /// # Safety
+    ///
+    /// `region` must be valid for reads and writes of `len` bytes for as
+    /// long as `'a`, must have been set up by `RingProducer::create` with the
+    /// same length, and nothing but the one producer may touch it while the
+    /// consumer exists.
+    pub unsafe fn attach(region: *mut u8, len: usize) -> ShmResult<RingConsumer<'a>> {
+        Ok(RingConsumer { ring: try!(Ring::new(region, len)) })
+    }
+
+    /// The number of bytes in the ring that have not been read yet.
+    pub fn pending(&self) -> usize {
+        let read = self.ring.read_pos.load(Ordering::Relaxed);
+        self.ring.write_pos.load(Ordering::Acquire).wrapping_sub(read)
+    }
+
+    /// Deserializes the next message, if there is one.
+    ///
+    /// A message that runs over the end of the ring is decoded from both
+    /// pieces without being copied together.  The frame is consumed even
+    /// if the message in it can't be deserialized.
+    pub fn recv<T: serde::Deserialize>(&mut self) -> ShmResult<Option<T>> {
+        let (pos, len) = match try!(self.next_frame()) {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        let (first, second) = unsafe { self.ring.pieces_at(pos.wrapping_add(FRAME_HEADER_LEN), len) };
+        let result = deserialize_segments(&[first, second]);
+        self.release(pos, len);
+        Ok(Some(try!(result)))
+    }
+
+    /// Copies the payload of the next frame out of the ring, if there is
+    /// one.
+    pub fn recv_bytes(&mut self) -> ShmResult<Option<Vec<u8>>> {
+        let (pos, len) = match try!(self.next_frame()) {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        let (first, second) = unsafe { self.ring.pieces_at(pos.wrapping_add(FRAME_HEADER_LEN), len) };
+        let mut payload = Vec::with_capacity(len);
+        payload.extend_from_slice(first);
+        payload.extend_from_slice(second);
+        self.release(pos, len);
+        Ok(Some(payload))
+    }
+
+    /// Returns where the next frame starts and the length of its payload.
+    fn next_frame(&mut self) -> ShmResult<Option<(usize, usize)>> {
+        let pending = self.pending();
+        if pending == 0 {
+            return Ok(None);
+        }
+        // The producer owns the write position, so don't take it on trust:
+        // nothing may be sliced out of the ring before this is checked.
+        if pending < FRAME_HEADER_LEN || pending > self.ring.capacity() {
+            return Err(ShmError::Corrupt);
+        }
+
+        let pos = self.ring.read_pos.load(Ordering::Relaxed);
+        let (first, second) = unsafe { self.ring.pieces_at(pos, FRAME_HEADER_LEN) };
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        header[..first.len()].copy_from_slice(first);
+        header[first.len()..].copy_from_slice(second);
+        let len = BigEndian::read_u64(&header);
+        if len > (pending - FRAME_HEADER_LEN) as u64 || len > (self.ring.capacity() - FRAME_HEADER_LEN) as u64 {
+            return Err(ShmError::Corrupt);
+        }
+        Ok(Some((pos, len as usize)))
+    }
+
+    /// Gives the frame at `pos` back to the producer.
+    fn release(&mut self, pos: usize, len: usize) {
+        let end = pos.wrapping_add(FRAME_HEADER_LEN + len);
+        self.ring.read_pos.store(end, Ordering::Release);
+    }
+}
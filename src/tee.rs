@@ -0,0 +1,82 @@
+use std::io::{self, Write};
+
+/// A writer that writes everything to two writers.
+///
+/// This lets a message be encoded once and sent to two places at the same
+/// time, say a file and a socket, without buffering all of it first.  Every
+/// write goes to `A` first and then to `B`; if either fails, the error is
+/// returned and the two may have been sent different amounts of data.
+///
+/// Nest `TeeWrite`s to write to more than two writers, or use
+/// `serialize_into_many` when the writers aren't known until runtime.
+pub struct TeeWrite<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Write, B: Write> TeeWrite<A, B> {
+    /// Creates a writer that writes to both `first` and `second`.
+    pub fn new(first: A, second: B) -> TeeWrite<A, B> {
+        TeeWrite {
+            first: first,
+            second: second,
+        }
+    }
+
+    /// Gets references to the two writers.
+    pub fn get_ref(&self) -> (&A, &B) {
+        (&self.first, &self.second)
+    }
+
+    /// Gets mutable references to the two writers.
+    pub fn get_mut(&mut self) -> (&mut A, &mut B) {
+        (&mut self.first, &mut self.second)
+    }
+
+    /// Unwraps the two writers.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Write, B: Write> Write for TeeWrite<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        try!(self.first.write_all(buf));
+        try!(self.second.write_all(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.first.flush());
+        self.second.flush()
+    }
+}
+
+/// Writes everything to each of a list of writers, in order.
+pub struct TeeWriteAll<'a, 'b: 'a> {
+    writers: &'a mut [&'b mut dyn Write],
+}
+
+impl<'a, 'b> TeeWriteAll<'a, 'b> {
+    pub fn new(writers: &'a mut [&'b mut dyn Write]) -> TeeWriteAll<'a, 'b> {
+        TeeWriteAll {
+            writers: writers,
+        }
+    }
+}
+
+impl<'a, 'b> Write for TeeWriteAll<'a, 'b> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for writer in self.writers.iter_mut() {
+            try!(writer.write_all(buf));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for writer in self.writers.iter_mut() {
+            try!(writer.flush());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+//! Helpers for testing code that sends bincode messages over streams.
+//!
+//! `loopback` connects a sender and a receiver through an in-memory pipe,
+//! so protocol code can be tested without sockets.  The receiving end reads
+//! through `deserialize_from`, just like it would from a socket, and the
+//! pipe hands out data a few bytes at a time, so decoding gets exercised
+//! with reads that stop in the middle of a value.
+//!
+//! ```rust,ignore
+//! let (sender, mut receiver) = bincode::test_util::loopback::<Request>();
+//! thread::spawn(move || client(sender));
+//! assert_eq!(receiver.recv().unwrap(), Request::Hello);
+//! ```
+//!
+//! `ChaosRead` and `ChaosWrite` wrap a reader or writer and make it
+//! misbehave the way sockets and pipes do: short reads and writes,
+//! `Interrupted` and `WouldBlock` errors, and an end of input in the middle
+//! of a value.  What happens on each call is drawn from a seeded schedule,
+//! so a failure can be reproduced from its seed.
+//!
+//! ```rust,ignore
+//! for seed in 0..100 {
+//!     let mut reader = ChaosRead::new(&bytes[..], seed);
+//!     assert_eq!(deserialize_from::<_, Request, _>(&mut reader, Infinite).unwrap(), request);
+//! }
+//! ```
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, Condvar};
+
+use serde_crate as serde;
+
+use ::Infinite;
+use ::serde::{serialize_into, deserialize_from, SerializeResult, DeserializeResult};
+
+/// The most bytes a single read from a `PipeReader` returns.  Reads cycle
+/// through every length up to this one.
+const MAX_CHUNK: usize = 7;
+
+struct Pipe {
+    buffer: VecDeque<u8>,
+    closed: bool,
+}
+
+type Shared = Arc<(Mutex<Pipe>, Condvar)>;
+
+/// Creates an in-memory pipe.
+///
+/// Everything written to the `PipeWriter` can be read from the
+/// `PipeReader`, which blocks until there is data to read.  Once the writer
+/// is dropped and the data is used up, reads return 0.
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let shared = Arc::new((Mutex::new(Pipe { buffer: VecDeque::new(), closed: false }), Condvar::new()));
+    (PipeWriter { shared: shared.clone() }, PipeReader { shared: shared, next_chunk: 1 })
+}
+
+/// The writing end of a `pipe`.
+pub struct PipeWriter {
+    shared: Shared,
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let &(ref pipe, ref readable) = &*self.shared;
+        let mut pipe = pipe.lock().unwrap();
+        pipe.buffer.extend(buf.iter().cloned());
+        readable.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let &(ref pipe, ref readable) = &*self.shared;
+        if let Ok(mut pipe) = pipe.lock() {
+            pipe.closed = true;
+        }
+        readable.notify_all();
+    }
+}
+
+/// The reading end of a `pipe`.
+///
+/// Reads never return more than a few bytes at a time, however much data
+/// is waiting.
+pub struct PipeReader {
+    shared: Shared,
+    next_chunk: usize,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let &(ref pipe, ref readable) = &*self.shared;
+        let mut pipe = pipe.lock().unwrap();
+        while pipe.buffer.is_empty() && !pipe.closed {
+            pipe = readable.wait(pipe).unwrap();
+        }
+
+        let len = cmp::min(cmp::min(buf.len(), pipe.buffer.len()), self.next_chunk);
+        for (slot, byte) in buf.iter_mut().zip(pipe.buffer.drain(..len)) {
+            *slot = byte;
+        }
+        self.next_chunk = self.next_chunk % MAX_CHUNK + 1;
+        Ok(len)
+    }
+}
+
+/// Creates a sender and a receiver of `T`s, connected through a `pipe`.
+///
+/// The two can be used from different threads.  Once the sender is dropped,
+/// the receiver gets an error after the last message.
+pub fn loopback<T>() -> (LoopbackSender<T>, LoopbackReceiver<T>)
+    where T: serde::Serialize + serde::Deserialize
+{
+    let (writer, reader) = pipe();
+    (LoopbackSender { writer: writer, _type: PhantomData },
+     LoopbackReceiver { reader: reader, _type: PhantomData })
+}
+
+/// The sending end of a `loopback`.
+pub struct LoopbackSender<T> {
+    writer: PipeWriter,
+    _type: PhantomData<fn(&T)>,
+}
+
+impl<T: serde::Serialize> LoopbackSender<T> {
+    /// Serializes a message into the pipe.
+    pub fn send(&mut self, message: &T) -> SerializeResult<()> {
+        serialize_into(&mut self.writer, message, Infinite)
+    }
+}
+
+/// The receiving end of a `loopback`.
+pub struct LoopbackReceiver<T> {
+    reader: PipeReader,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: serde::Deserialize> LoopbackReceiver<T> {
+    /// Waits for the next message and deserializes it from the pipe.
+    pub fn recv(&mut self) -> DeserializeResult<T> {
+        deserialize_from(&mut self.reader, Infinite)
+    }
+}
+
+/// Draws the fate of each call to a `ChaosRead` or `ChaosWrite`.
+struct Schedule {
+    state: u64,
+}
+
+impl Schedule {
+    fn new(seed: u64) -> Schedule {
+        // xorshift gets stuck on zero, so the lowest bit is always set.
+        Schedule { state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    /// Returns `true` once in `n` times on average, and never if `n` is 0.
+    fn one_in(&mut self, n: u32) -> bool {
+        n != 0 && self.next_u64() % n as u64 == 0
+    }
+
+    /// Returns how much of a `len` byte call to let through.
+    fn chunk(&mut self, len: usize, short: bool) -> usize {
+        if !short || len <= 1 {
+            return len;
+        }
+        1 + (self.next_u64() % len as u64) as usize
+    }
+
+    /// Returns the error this call fails with, if it fails.
+    fn fault(&mut self, interrupted: u32, would_block: u32) -> Option<io::Error> {
+        if self.one_in(interrupted) {
+            return Some(io::Error::new(io::ErrorKind::Interrupted, "interrupted by ChaosRead or ChaosWrite"));
+        }
+        if self.one_in(would_block) {
+            return Some(io::Error::new(io::ErrorKind::WouldBlock, "would block in ChaosRead or ChaosWrite"));
+        }
+        None
+    }
+}
+
+/// A reader that reads from another one as unreliably as a socket would.
+///
+/// By default every read returns anything from one byte to as many as were
+/// asked for, and one in four fails with `Interrupted` before reading
+/// anything, which a well-behaved caller retries.  `WouldBlock` errors and
+/// an early end of input are off until they are turned on.
+pub struct ChaosRead<R> {
+    reader: R,
+    schedule: Schedule,
+    short_reads: bool,
+    interrupted: u32,
+    would_block: u32,
+    eof_after: Option<u64>,
+    read: u64,
+}
+
+impl<R: Read> ChaosRead<R> {
+    /// Creates a reader that reads from `reader` on the schedule drawn
+    /// from `seed`.
+    pub fn new(reader: R, seed: u64) -> ChaosRead<R> {
+        ChaosRead {
+            reader: reader,
+            schedule: Schedule::new(seed),
+            short_reads: true,
+            interrupted: 4,
+            would_block: 0,
+            eof_after: None,
+            read: 0,
+        }
+    }
+
+    /// Controls whether reads may return fewer bytes than were asked for.
+    pub fn set_short_reads(&mut self, short: bool) {
+        self.short_reads = short;
+    }
+
+    /// Fails one read in `one_in` with `Interrupted`, or none if it is 0.
+    pub fn set_interrupted(&mut self, one_in: u32) {
+        self.interrupted = one_in;
+    }
+
+    /// Fails one read in `one_in` with `WouldBlock`, or none if it is 0.
+    pub fn set_would_block(&mut self, one_in: u32) {
+        self.would_block = one_in;
+    }
+
+    /// Ends the input after `bytes` bytes, however much more the
+    /// underlying reader has.
+    pub fn set_eof_after(&mut self, bytes: u64) {
+        self.eof_after = Some(bytes);
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for ChaosRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(err) = self.schedule.fault(self.interrupted, self.would_block) {
+            return Err(err);
+        }
+        let mut len = self.schedule.chunk(buf.len(), self.short_reads);
+        if let Some(eof_after) = self.eof_after {
+            len = cmp::min(len as u64, eof_after - self.read) as usize;
+        }
+        let read = try!(self.reader.read(&mut buf[..len]));
+        self.read += read as u64;
+        Ok(read)
+    }
+}
+
+/// A writer that writes to another one as unreliably as a socket would.
+///
+/// This is the writing side of `ChaosRead`: by default every write takes
+/// anything from one byte to all of those it was given, and one in four
+/// fails with `Interrupted`.  Once the space set by `set_full_after` is
+/// used up, writes take nothing, which `write_all` reports as `WriteZero`.
+pub struct ChaosWrite<W> {
+    writer: W,
+    schedule: Schedule,
+    short_writes: bool,
+    interrupted: u32,
+    would_block: u32,
+    full_after: Option<u64>,
+    written: u64,
+}
+
+impl<W: Write> ChaosWrite<W> {
+    /// Creates a writer that writes to `writer` on the schedule drawn from
+    /// `seed`.
+    pub fn new(writer: W, seed: u64) -> ChaosWrite<W> {
+        ChaosWrite {
+            writer: writer,
+            schedule: Schedule::new(seed),
+            short_writes: true,
+            interrupted: 4,
+            would_block: 0,
+            full_after: None,
+            written: 0,
+        }
+    }
+
+    /// Controls whether writes may take fewer bytes than they were given.
+    pub fn set_short_writes(&mut self, short: bool) {
+        self.short_writes = short;
+    }
+
+    /// Fails one write in `one_in` with `Interrupted`, or none if it is 0.
+    pub fn set_interrupted(&mut self, one_in: u32) {
+        self.interrupted = one_in;
+    }
+
+    /// Fails one write in `one_in` with `WouldBlock`, or none if it is 0.
+    pub fn set_would_block(&mut self, one_in: u32) {
+        self.would_block = one_in;
+    }
+
+    /// Takes no more than `bytes` bytes in all.
+    pub fn set_full_after(&mut self, bytes: u64) {
+        self.full_after = Some(bytes);
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Unwraps the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Write for ChaosWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(err) = self.schedule.fault(self.interrupted, self.would_block) {
+            return Err(err);
+        }
+        let mut len = self.schedule.chunk(buf.len(), self.short_writes);
+        if let Some(full_after) = self.full_after {
+            len = cmp::min(len as u64, full_after - self.written) as usize;
+        }
+        let written = try!(self.writer.write(&buf[..len]));
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
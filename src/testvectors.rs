@@ -0,0 +1,101 @@
+//! Golden test vectors.
+//!
+//! A fixture is a file holding the exact bytes a value is expected to encode
+//! to.  Checking values against committed fixtures catches any change to
+//! their encoding, whether it comes from a new version of bincode or from a
+//! change to the types themselves, before it ships.  bincode checks its own
+//! encoding this way against the fixtures in `tests/fixtures`.
+//!
+//! `assert_fixture!` looks for fixtures in the `tests/fixtures` directory of
+//! the crate it is called from:
+//!
+//! ```rust,ignore
+//! #[macro_use]
+//! extern crate bincode;
+//!
+//! #[test]
+//! fn message_encoding() {
+//!     assert_fixture!(Message { id: 7, body: "hi".to_string() }, "message");
+//! }
+//! ```
+//!
+//! A fixture that doesn't exist yet makes the check fail.  Run the tests with
+//! the `BINCODE_BLESS` environment variable set to write out the current
+//! encodings instead, then commit the new files.
+
+use std::env;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde_crate::{Serialize, Deserialize};
+
+use ::Infinite;
+use ::serde::{serialize, deserialize};
+
+/// Checks `value` against the fixture in the crate's `tests/fixtures`
+/// directory called `$name.bin`.
+///
+/// See `assert_fixture` for what is checked.
+#[macro_export]
+macro_rules! assert_fixture {
+    ($value:expr, $name:expr) => {
+        $crate::testvectors::assert_fixture(
+            &$value,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/", $name, ".bin"))
+    }
+}
+
+/// Checks that `value` encodes to exactly the bytes in the file at `path`,
+/// and that those bytes decode back to `value`.
+///
+/// If `BINCODE_BLESS` is set in the environment, the file is written with
+/// the current encoding instead, creating its directory if needed.
+///
+/// # Panics
+///
+/// Panics if the encoding doesn't match the fixture, if the fixture doesn't
+/// decode to `value`, or if the fixture can't be read.
+pub fn assert_fixture<T, P>(value: &T, path: P)
+    where T: Serialize + Deserialize + PartialEq + Debug,
+          P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let encoded = serialize(value, Infinite).unwrap();
+
+    if env::var_os("BINCODE_BLESS").is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        File::create(path).and_then(|mut file| file.write_all(&encoded)).unwrap();
+        return;
+    }
+
+    let expected = match read_fixture(path) {
+        Some(expected) => expected,
+        None => panic!("no fixture at {}; set BINCODE_BLESS to write it", path.display()),
+    };
+    if encoded != expected {
+        panic!("{:?} encodes to\n    {}\nbut the fixture at {} holds\n    {}",
+               value, to_hex(&encoded), path.display(), to_hex(&expected));
+    }
+
+    let decoded: T = deserialize(&expected).unwrap();
+    assert_eq!(&decoded, value);
+}
+
+fn read_fixture(path: &Path) -> Option<Vec<u8>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return None,
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    Some(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.join(" ")
+}
@@ -0,0 +1,371 @@
+//! Encoding to and decoding from hex and base64 text.
+//!
+//! A bincode message is raw bytes, which can't be put in a JSON config file,
+//! a URL, or a log line as they are.  The functions here serialize straight
+//! to hex or base64 text and deserialize straight from it, and the `_into`
+//! and `_from` versions work on a `Write` or `Read` of text without holding
+//! all of a large message in memory.  This module is enabled by the `text`
+//! feature.
+//!
+//! Text that isn't valid hex or base64 is reported as an `IoError` of kind
+//! `InvalidData`.  ASCII whitespace in it is skipped, so text that has been
+//! wrapped over several lines can be read as it is.
+
+use std::io::{self, Read, Write};
+
+use serde_crate as serde;
+
+use ::SizeLimit;
+use ::serde::{serialize_into, deserialize_from, SerializeResult, SerializeError, DeserializeResult};
+
+/// Which characters base64 text is written with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base64 {
+    /// The alphabet of RFC 4648 with `+` and `/`, padded with `=` to a
+    /// multiple of four characters.
+    Standard,
+    /// The URL and file name safe alphabet of RFC 4648, with `-` and `_`,
+    /// and no padding.
+    UrlSafe,
+}
+
+impl Base64 {
+    fn alphabet(self) -> &'static [u8; 64] {
+        match self {
+            Base64::Standard => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+            Base64::UrlSafe => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+        }
+    }
+
+    fn value(self, c: u8) -> io::Result<u8> {
+        match self.alphabet().iter().position(|&a| a == c) {
+            Some(value) => Ok(value as u8),
+            None => Err(invalid_text("invalid character in base64 text")),
+        }
+    }
+}
+
+/// Serializes an object to lowercase hex.
+pub fn serialize_hex<T, S>(value: &T, size_limit: S) -> SerializeResult<String>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    let mut out = Vec::new();
+    try!(serialize_hex_into(&mut out, value, size_limit));
+    Ok(String::from_utf8(out).expect("hex is ascii"))
+}
+
+/// Serializes an object to lowercase hex, written to `writer` as it goes.
+pub fn serialize_hex_into<W, T, S>(writer: &mut W, value: &T, size_limit: S) -> SerializeResult<()>
+    where W: Write, T: serde::Serialize, S: SizeLimit,
+{
+    serialize_into(&mut HexWriter::new(writer), value, size_limit)
+}
+
+/// Deserializes an object from hex, in upper or lower case.
+pub fn deserialize_hex<T: serde::Deserialize>(text: &str) -> DeserializeResult<T> {
+    deserialize_hex_from(&mut text.as_bytes(), ::Infinite)
+}
+
+/// Deserializes an object from hex read from `reader`.  Only as much text
+/// as the object takes up is read.
+pub fn deserialize_hex_from<R, T, S>(reader: &mut R, size_limit: S) -> DeserializeResult<T>
+    where R: Read, T: serde::Deserialize, S: SizeLimit,
+{
+    deserialize_from(&mut HexReader::new(reader), size_limit)
+}
+
+/// Serializes an object to base64.
+pub fn serialize_base64<T, S>(value: &T, base64: Base64, size_limit: S) -> SerializeResult<String>
+    where T: serde::Serialize, S: SizeLimit,
+{
+    let mut out = Vec::new();
+    try!(serialize_base64_into(&mut out, value, base64, size_limit));
+    Ok(String::from_utf8(out).expect("base64 is ascii"))
+}
+
+/// Serializes an object to base64, written to `writer` as it goes.
+pub fn serialize_base64_into<W, T, S>(writer: &mut W, value: &T, base64: Base64, size_limit: S) -> SerializeResult<()>
+    where W: Write, T: serde::Serialize, S: SizeLimit,
+{
+    let mut base64_writer = Base64Writer::new(writer, base64);
+    try!(serialize_into(&mut base64_writer, value, size_limit));
+    base64_writer.finish().map(|_| ()).map_err(SerializeError::IoError)
+}
+
+/// Deserializes an object from base64.  Padding is optional.
+pub fn deserialize_base64<T: serde::Deserialize>(text: &str, base64: Base64) -> DeserializeResult<T> {
+    deserialize_base64_from(&mut text.as_bytes(), base64, ::Infinite)
+}
+
+/// Deserializes an object from base64 read from `reader`.
+pub fn deserialize_base64_from<R, T, S>(reader: &mut R, base64: Base64, size_limit: S) -> DeserializeResult<T>
+    where R: Read, T: serde::Deserialize, S: SizeLimit,
+{
+    deserialize_from(&mut Base64Reader::new(reader, base64), size_limit)
+}
+
+fn invalid_text(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the next character of `reader` that isn't whitespace, or `None`
+/// at the end of the input.
+///
+/// Characters are read one at a time, so that nothing after the message is
+/// taken out of `reader`; wrap a file or socket in a `BufReader`.
+fn next_char<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut c = [0];
+    loop {
+        match reader.read(&mut c) {
+            Ok(0) => return Ok(None),
+            Ok(_) if (c[0] as char).is_ascii_whitespace() => continue,
+            Ok(_) => return Ok(Some(c[0])),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+const HEX_DIGITS: &'static [u8; 16] = b"0123456789abcdef";
+
+fn hex_digit(c: u8) -> io::Result<u8> {
+    match (c as char).to_digit(16) {
+        Some(digit) => Ok(digit as u8),
+        None => Err(invalid_text("invalid character in hex text")),
+    }
+}
+
+/// A `Write` that writes what is written to it on to another `Write` as
+/// hex.
+pub struct HexWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> HexWriter<W> {
+    pub fn new(inner: W) -> HexWriter<W> {
+        HexWriter { inner: inner }
+    }
+
+    /// Unwraps the `Write` the hex is written to.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for HexWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut text = [0; 512];
+        for chunk in buf.chunks(text.len() / 2) {
+            for (i, &byte) in chunk.iter().enumerate() {
+                text[2 * i] = HEX_DIGITS[(byte >> 4) as usize];
+                text[2 * i + 1] = HEX_DIGITS[(byte & 0xf) as usize];
+            }
+            try!(self.inner.write_all(&text[..2 * chunk.len()]));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Read` that reads hex from another `Read` and gives back the bytes it
+/// stands for.
+pub struct HexReader<R> {
+    inner: R,
+}
+
+impl<R: Read> HexReader<R> {
+    pub fn new(inner: R) -> HexReader<R> {
+        HexReader { inner: inner }
+    }
+
+    /// Unwraps the `Read` the hex is read from.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for HexReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            let high = match try!(next_char(&mut self.inner)) {
+                Some(c) => try!(hex_digit(c)),
+                None => return Ok(i),
+            };
+            let low = match try!(next_char(&mut self.inner)) {
+                Some(c) => try!(hex_digit(c)),
+                None => return Err(invalid_text("hex text ends in the middle of a byte")),
+            };
+            *byte = high << 4 | low;
+        }
+        Ok(buf.len())
+    }
+}
+
+/// A `Write` that writes what is written to it on to another `Write` as
+/// base64.
+///
+/// Bytes are encoded three at a time, so up to two of them are held back
+/// until more are written; call `finish` once everything has been written,
+/// or they are lost.
+pub struct Base64Writer<W> {
+    inner: W,
+    base64: Base64,
+    pending: [u8; 3],
+    len: usize,
+}
+
+impl<W: Write> Base64Writer<W> {
+    pub fn new(inner: W, base64: Base64) -> Base64Writer<W> {
+        Base64Writer {
+            inner: inner,
+            base64: base64,
+            pending: [0; 3],
+            len: 0,
+        }
+    }
+
+    /// Writes the bytes that are still held back, with padding if the
+    /// alphabet has it, and returns the `Write` the base64 was written to.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.len > 0 {
+            let mut text = [b'='; 4];
+            let chars = encode_group(self.base64, &self.pending[..self.len], &mut text);
+            let end = match self.base64 {
+                Base64::Standard => 4,
+                Base64::UrlSafe => chars,
+            };
+            try!(self.inner.write_all(&text[..end]));
+        }
+        Ok(self.inner)
+    }
+}
+
+/// Encodes up to three bytes into `text`, and returns how many characters
+/// they take up.
+fn encode_group(base64: Base64, bytes: &[u8], text: &mut [u8; 4]) -> usize {
+    let alphabet = base64.alphabet();
+    let mut group = [0; 3];
+    group[..bytes.len()].copy_from_slice(bytes);
+    let n = (group[0] as u32) << 16 | (group[1] as u32) << 8 | group[2] as u32;
+    let chars = bytes.len() + 1;
+    for i in 0..chars {
+        text[i] = alphabet[(n >> (18 - 6 * i) & 0x3f) as usize];
+    }
+    chars
+}
+
+impl<W: Write> Write for Base64Writer<W> {
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        let written = buf.len();
+        if self.len > 0 {
+            let take = ::std::cmp::min(3 - self.len, buf.len());
+            self.pending[self.len..self.len + take].copy_from_slice(&buf[..take]);
+            self.len += take;
+            buf = &buf[take..];
+            if self.len < 3 {
+                return Ok(written);
+            }
+            let mut text = [0; 4];
+            encode_group(self.base64, &self.pending, &mut text);
+            try!(self.inner.write_all(&text));
+            self.len = 0;
+        }
+        let mut text = [0; 512];
+        let whole = buf.len() / 3 * 3;
+        for chunk in buf[..whole].chunks(text.len() / 4 * 3) {
+            let mut end = 0;
+            for group in chunk.chunks(3) {
+                let mut group_text = [0; 4];
+                encode_group(self.base64, group, &mut group_text);
+                text[end..end + 4].copy_from_slice(&group_text);
+                end += 4;
+            }
+            try!(self.inner.write_all(&text[..end]));
+        }
+        let rest = &buf[whole..];
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.len = rest.len();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A `Read` that reads base64 from another `Read` and gives back the bytes
+/// it stands for.
+pub struct Base64Reader<R> {
+    inner: R,
+    base64: Base64,
+    decoded: [u8; 3],
+    pos: usize,
+    len: usize,
+    done: bool,
+}
+
+impl<R: Read> Base64Reader<R> {
+    pub fn new(inner: R, base64: Base64) -> Base64Reader<R> {
+        Base64Reader {
+            inner: inner,
+            base64: base64,
+            decoded: [0; 3],
+            pos: 0,
+            len: 0,
+            done: false,
+        }
+    }
+
+    /// Unwraps the `Read` the base64 is read from.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decodes the next group of up to four characters.
+    fn fill(&mut self) -> io::Result<()> {
+        let mut n = 0u32;
+        let mut chars = 0;
+        while chars < 4 {
+            match try!(next_char(&mut self.inner)) {
+                Some(b'=') | None => {
+                    self.done = true;
+                    break;
+                }
+                Some(c) => {
+                    n = n << 6 | try!(self.base64.value(c)) as u32;
+                    chars += 1;
+                }
+            }
+        }
+        if chars == 1 {
+            return Err(invalid_text("base64 text ends in the middle of a byte"));
+        }
+        n <<= 6 * (4 - chars);
+        self.decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        self.pos = 0;
+        self.len = if chars == 0 { 0 } else { chars as usize - 1 };
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Base64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buf.len() {
+            if self.pos == self.len {
+                if self.done {
+                    break;
+                }
+                try!(self.fill());
+                continue;
+            }
+            buf[read] = self.decoded[self.pos];
+            self.pos += 1;
+            read += 1;
+        }
+        Ok(read)
+    }
+}
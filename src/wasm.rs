@@ -0,0 +1,125 @@
+//! Helpers for handing bincode messages to and from JavaScript.
+//!
+//! bincode itself builds for `wasm32-unknown-unknown` without any extra
+//! setup; this module only adds conversions between encoded messages and
+//! `js_sys::Uint8Array`, so that a browser client can exchange the same
+//! structs with a native server.  It is enabled by the `wasm` feature.
+//!
+//! ```rust,ignore
+//! #[wasm_bindgen]
+//! pub fn handle(message: Uint8Array) -> Uint8Array {
+//!     let request: Request = bincode::wasm::deserialize_from_uint8array(&message).unwrap();
+//!     bincode::wasm::serialize_to_uint8array(&respond(request), bincode::Infinite).unwrap()
+//! }
+//! ```
+
+use js_sys::Uint8Array;
+
+use ::SizeLimit;
+use codec::{self, Encode, Decode, EncodeResult, DecodeResult};
+
+#[cfg(feature = "serde")]
+use serde_crate as serde_lib;
+#[cfg(feature = "serde")]
+use serde::{self, SerializeResult, DeserializeResult};
+
+// `JsArray` and the functions ending in `_js_array` are what the
+// `Uint8Array` functions are built on.  They are hidden rather than private
+// only so that the tests can run them on the host, where a real JavaScript
+// array can't be created, with a stand-in backed by a `Vec`.
+
+/// A JavaScript array that messages are copied into and out of.
+#[doc(hidden)]
+pub trait JsArray: Sized {
+    /// Creates an array holding a copy of `bytes`.
+    fn from_bytes(bytes: &[u8]) -> Self;
+
+    /// Copies the contents of the array out of it.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+impl JsArray for Uint8Array {
+    fn from_bytes(bytes: &[u8]) -> Uint8Array {
+        Uint8Array::from(bytes)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+/// Serializes an object into a new `Uint8Array`.
+///
+/// If the serialization would take more bytes than allowed by `size_limit`,
+/// an error is returned.
+#[cfg(feature = "serde")]
+pub fn serialize_to_uint8array<T, S>(value: &T, size_limit: S) -> SerializeResult<Uint8Array>
+    where T: serde_lib::Serialize, S: SizeLimit,
+{
+    serialize_to_js_array(value, size_limit)
+}
+
+/// Deserializes an object from the contents of a `Uint8Array`.
+///
+/// The bytes are copied out of the JavaScript heap before decoding.
+#[cfg(feature = "serde")]
+pub fn deserialize_from_uint8array<T>(array: &Uint8Array) -> DeserializeResult<T>
+    where T: serde_lib::Deserialize,
+{
+    deserialize_from_js_array(array)
+}
+
+/// Encodes an object into a new `Uint8Array`.
+///
+/// If the encoding would take more bytes than allowed by `size_limit`,
+/// an error is returned.
+pub fn encode_to_uint8array<T, S>(t: &T, size_limit: S) -> EncodeResult<Uint8Array>
+    where T: Encode + ?Sized, S: SizeLimit,
+{
+    encode_to_js_array(t, size_limit)
+}
+
+/// Decodes an object from the contents of a `Uint8Array`.
+///
+/// The bytes are copied out of the JavaScript heap before decoding.
+pub fn decode_from_uint8array<T: Decode>(array: &Uint8Array) -> DecodeResult<T> {
+    decode_from_js_array(array)
+}
+
+/// Serializes an object into a new JavaScript array.  See
+/// `serialize_to_uint8array`.
+#[doc(hidden)]
+#[cfg(feature = "serde")]
+pub fn serialize_to_js_array<A, T, S>(value: &T, size_limit: S) -> SerializeResult<A>
+    where A: JsArray, T: serde_lib::Serialize, S: SizeLimit,
+{
+    let bytes = try!(serde::serialize(value, size_limit));
+    Ok(A::from_bytes(&bytes))
+}
+
+/// Deserializes an object from the contents of a JavaScript array.  See
+/// `deserialize_from_uint8array`.
+#[doc(hidden)]
+#[cfg(feature = "serde")]
+pub fn deserialize_from_js_array<A, T>(array: &A) -> DeserializeResult<T>
+    where A: JsArray, T: serde_lib::Deserialize,
+{
+    serde::deserialize(&array.to_bytes())
+}
+
+/// Encodes an object into a new JavaScript array.  See
+/// `encode_to_uint8array`.
+#[doc(hidden)]
+pub fn encode_to_js_array<A, T, S>(t: &T, size_limit: S) -> EncodeResult<A>
+    where A: JsArray, T: Encode + ?Sized, S: SizeLimit,
+{
+    let bytes = try!(codec::encode(t, size_limit));
+    Ok(A::from_bytes(&bytes))
+}
+
+/// Decodes an object from the contents of a JavaScript array.  See
+/// `decode_from_uint8array`.
+#[doc(hidden)]
+pub fn decode_from_js_array<A: JsArray, T: Decode>(array: &A) -> DecodeResult<T> {
+    codec::decode(&array.to_bytes())
+}
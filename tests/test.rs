@@ -1,9 +1,12 @@
 #![feature(plugin, custom_derive, custom_attribute)]
 #![plugin(serde_macros)]
 
+#[macro_use]
 extern crate bincode;
 extern crate rustc_serialize;
 extern crate serde;
+#[cfg(feature = "bytes")]
+extern crate bytes;
 
 use std::fmt::Debug;
 use std::collections::HashMap;
@@ -13,12 +16,13 @@ use rustc_serialize::{Encoder, Decoder, Encodable, Decodable};
 
 use bincode::{RefBox, StrBox, SliceBox};
 
-use bincode::SizeLimit::{self, Infinite, Bounded};
+use bincode::{SizeLimit, Infinite, Bounded};
 use bincode::rustc_serialize::{encode, decode, decode_from, DecodingError};
 use bincode::serde::{serialize, deserialize, deserialize_from, DeserializeError, DeserializeResult};
 
-fn proxy_encode<V>(element: &V, size_limit: SizeLimit) -> Vec<u8>
-    where V: Encodable + Decodable + serde::Serialize + serde::Deserialize + PartialEq + Debug + 'static
+fn proxy_encode<V, S>(element: &V, size_limit: S) -> Vec<u8>
+    where S: SizeLimit + Copy,
+          V: Encodable + Decodable + serde::Serialize + serde::Deserialize + PartialEq + Debug + 'static
 {
     let v1 = bincode::rustc_serialize::encode(element, size_limit).unwrap();
     let v2 = bincode::serde::serialize(element, size_limit).unwrap();
@@ -268,6 +272,60 @@ fn deserializing_errors() {
     isize_invalid_deserialize(deserialize::<Option<u8>>(&vec![5, 0][..]));
 }
 
+#[test]
+fn test_custom_errors() {
+    #[derive(Debug)]
+    struct Percent(u8);
+
+    impl serde::Deserialize for Percent {
+        fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Percent, D::Error> {
+            let value: u8 = try!(serde::Deserialize::deserialize(deserializer));
+            if value > 100 {
+                return Err(serde::de::Error::custom(format!("{} is not a percentage", value)));
+            }
+            Ok(Percent(value))
+        }
+    }
+
+    assert_eq!(deserialize::<Percent>(&[42]).unwrap().0, 42);
+    match deserialize::<Percent>(&[150]) {
+        Err(err @ DeserializeError::Custom(_)) => assert_eq!(err.to_string(), "150 is not a percentage"),
+        other => panic!("Expecting Custom, got {:?}", other),
+    }
+
+    let err = DeserializeError::Custom(Box::new(std::io::Error::new(std::io::ErrorKind::Other, "checksum")));
+    match err {
+        DeserializeError::Custom(ref inner) => {
+            assert_eq!(inner.downcast_ref::<std::io::Error>().unwrap().to_string(), "checksum");
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn test_errors_are_send_sync() {
+    use std::error::Error;
+    use std::thread;
+    use bincode::serde::SerializeError;
+    use bincode::rustc_serialize::EncodingError;
+    use bincode::frame::FrameError;
+    use bincode::channel::ChannelError;
+    use bincode::log::LogError;
+
+    fn assert_send_sync<T: Error + Send + Sync + 'static>() {}
+    assert_send_sync::<DeserializeError>();
+    assert_send_sync::<SerializeError>();
+    assert_send_sync::<DecodingError>();
+    assert_send_sync::<EncodingError>();
+    assert_send_sync::<FrameError>();
+    assert_send_sync::<ChannelError>();
+    assert_send_sync::<LogError>();
+
+    let handle = thread::spawn(|| deserialize::<bool>(&[2]));
+    let err: Box<Error + Send + Sync> = Box::new(handle.join().unwrap().unwrap_err());
+    assert!(err.to_string().starts_with("InvalidEncoding"));
+}
+
 #[test]
 fn too_big_decode() {
     let encoded = vec![0,0,0,3];
@@ -306,6 +364,101 @@ fn too_big_char_deserialize() {
     assert_eq!(deserialized.unwrap(), 'A');
 }
 
+#[test]
+fn too_big_char_is_charged_before_reading() {
+    // 'é' is two bytes long, so only the first one fits in the budget.
+    let encoded = vec![0xc3, 0xa9];
+
+    let mut reader = &encoded[..];
+    match decode_from::<_, char, _>(&mut reader, Bounded(1)) {
+        Err(DecodingError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    assert_eq!(reader.len(), 1);
+
+    let mut reader = &encoded[..];
+    match deserialize_from::<_, char, _>(&mut reader, Bounded(1)) {
+        Err(DeserializeError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    assert_eq!(reader.len(), 1);
+
+    assert!(decode_from::<_, char, _>(&mut &[0x41][..], Bounded(0)).is_err());
+    assert!(deserialize_from::<_, char, _>(&mut &[0x41][..], Bounded(0)).is_err());
+}
+
+#[test]
+fn too_big_string_is_charged_before_reading() {
+    let mut encoded = vec![0, 0, 0, 0, 0, 0, 0, 5];
+    encoded.extend(b"hello");
+
+    let mut reader = &encoded[..];
+    match decode_from::<_, String, _>(&mut reader, Bounded(1)) {
+        Err(DecodingError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    assert_eq!(reader.len(), encoded.len());
+
+    let mut reader = &encoded[..];
+    match deserialize_from::<_, String, _>(&mut reader, Bounded(1)) {
+        Err(DeserializeError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    assert_eq!(reader.len(), encoded.len());
+
+    // A huge length prefix has to be rejected without reading the body or
+    // allocating room for it.
+    let encoded = vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, b'a'];
+
+    let mut reader = &encoded[..];
+    match decode_from::<_, String, _>(&mut reader, Bounded(16)) {
+        Err(DecodingError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    assert_eq!(reader.len(), 1);
+
+    let mut reader = &encoded[..];
+    match deserialize_from::<_, String, _>(&mut reader, Bounded(16)) {
+        Err(DeserializeError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    assert_eq!(reader.len(), 1);
+}
+
+#[test]
+fn custom_size_limit() {
+    // Never fails, just keeps count of what went through it.
+    struct Tally(u64);
+
+    impl SizeLimit for Tally {
+        fn add(&mut self, n: u64) -> bool {
+            self.0 += n;
+            true
+        }
+
+        fn limit(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    let value = (1u8, "hello".to_string(), vec![2u16, 3]);
+    let encoded = encode(&value, Infinite).unwrap();
+
+    let mut tally = Tally(0);
+    let decoded: (u8, String, Vec<u16>) = decode_from(&mut &encoded[..], &mut tally).unwrap();
+    assert_eq!(decoded, value);
+    assert_eq!(tally.0, encoded.len() as u64);
+
+    let mut tally = Tally(0);
+    let deserialized: (u8, String, Vec<u16>) = deserialize_from(&mut &encoded[..], &mut tally).unwrap();
+    assert_eq!(deserialized, value);
+    assert_eq!(tally.0, encoded.len() as u64);
+
+    let mut bounded = Bounded(100);
+    let _: (u8, String, Vec<u16>) = deserialize_from(&mut &encoded[..], &mut bounded).unwrap();
+    assert_eq!(bounded, Bounded(100 - encoded.len() as u64));
+}
+
 #[test]
 fn too_big_encode() {
     assert!(encode(&0u32, Bounded(3)).is_err());
@@ -492,8 +645,8 @@ fn test_oom_protection() {
         len: u64,
         byte: u8
     }
-    let x = bincode::rustc_serialize::encode(&FakeVec { len: 0xffffffffffffffffu64, byte: 1 }, bincode::SizeLimit::Bounded(10)).unwrap();
-    let y : Result<Vec<u8>, _> = bincode::rustc_serialize::decode_from(&mut Cursor::new(&x[..]), bincode::SizeLimit::Bounded(10));
+    let x = bincode::rustc_serialize::encode(&FakeVec { len: 0xffffffffffffffffu64, byte: 1 }, Bounded(10)).unwrap();
+    let y : Result<Vec<u8>, _> = bincode::rustc_serialize::decode_from(&mut Cursor::new(&x[..]), Bounded(10));
     assert!(y.is_err());
 }
 
@@ -505,3 +658,4046 @@ fn path_buf() {
     let decoded: PathBuf = bincode::serde::deserialize(&serde_encoded).unwrap();
     assert!(path.to_str() == decoded.to_str());
 }
+
+#[test]
+fn test_slice_and_io_readers() {
+    use bincode::serde::{Deserializer, SliceReader, IoReader};
+
+    let value = ("foo".to_string(), vec![1u8, 2, 3], 5u32);
+    let serialized = serialize(&value, Infinite).unwrap();
+
+    let mut deserializer = Deserializer::new(SliceReader::new(&serialized[..]), Infinite);
+    let from_slice: (String, Vec<u8>, u32) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(deserializer.bytes_read(), serialized.len() as u64);
+
+    let mut deserializer = Deserializer::new(IoReader::new(&serialized[..]), Infinite);
+    let from_io: (String, Vec<u8>, u32) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(deserializer.bytes_read(), serialized.len() as u64);
+
+    assert_eq!(from_slice, value);
+    assert_eq!(from_io, value);
+}
+
+#[test]
+fn test_truncated_string() {
+    let serialized = vec![0, 0, 0, 0, 0, 0, 0, 5, b'a', b'b'];
+    match deserialize::<String>(&serialized[..]) {
+        Err(DeserializeError::IoError(_)) => {},
+        other => panic!("Expecting IoError, got {:?}", other),
+    }
+    match deserialize_from::<_, String, _>(&mut &serialized[..], Infinite) {
+        Err(DeserializeError::IoError(_)) => {},
+        other => panic!("Expecting IoError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_fill_missing_fields() {
+    use bincode::serde::{deserialize_with_defaults, deserialize_from_with_defaults};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct OldRecord {
+        id: u32,
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Kind {
+        Plain,
+        Tagged(u8),
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct NewRecord {
+        id: u32,
+        name: String,
+        tags: Vec<String>,
+        score: Option<u64>,
+        position: (u8, bool),
+        kind: Kind,
+    }
+
+    let old = OldRecord { id: 7, name: "seven".to_string() };
+    let serialized = serialize(&old, Infinite).unwrap();
+    let expected = NewRecord {
+        id: 7,
+        name: "seven".to_string(),
+        tags: vec![],
+        score: None,
+        position: (0, false),
+        kind: Kind::Plain,
+    };
+
+    assert_eq!(deserialize_with_defaults::<NewRecord>(&serialized[..]).unwrap(), expected);
+    assert_eq!(deserialize_from_with_defaults::<_, NewRecord, _>(&mut &serialized[..], Infinite).unwrap(),
+               expected);
+    assert!(deserialize::<NewRecord>(&serialized[..]).is_err());
+
+    // Running out of input inside a field is still an error.
+    assert!(deserialize_with_defaults::<NewRecord>(&serialized[..serialized.len() - 1]).is_err());
+
+    let new = NewRecord {
+        id: 8,
+        name: "eight".to_string(),
+        tags: vec!["a".to_string()],
+        score: Some(3),
+        position: (1, true),
+        kind: Kind::Tagged(2),
+    };
+    let serialized = serialize(&new, Infinite).unwrap();
+    assert_eq!(deserialize_with_defaults::<NewRecord>(&serialized[..]).unwrap(), new);
+}
+
+#[test]
+fn test_fill_missing_fields_only_for_structs() {
+    use bincode::serde::deserialize_with_defaults;
+
+    assert!(deserialize_with_defaults::<(u32, u32)>(&[0, 0, 0, 1]).is_err());
+}
+
+#[test]
+fn test_observer() {
+    use bincode::serde::{Deserializer, SliceReader, Observer};
+
+    #[derive(Default)]
+    struct Log(Vec<String>);
+
+    impl Observer for Log {
+        fn begin_seq(&mut self, offset: u64, len: usize) {
+            self.0.push(format!("seq {} {}", offset, len));
+        }
+
+        fn begin_map(&mut self, offset: u64, len: usize) {
+            self.0.push(format!("map {} {}", offset, len));
+        }
+
+        fn string(&mut self, offset: u64, len: usize) {
+            self.0.push(format!("string {} {}", offset, len));
+        }
+
+        fn end(&mut self, offset: u64) {
+            self.0.push(format!("end {}", offset));
+        }
+    }
+
+    let mut map = HashMap::new();
+    map.insert(3u8, "abc".to_string());
+    let value = (vec![1u16, 2], "hi".to_string(), map);
+    let serialized = serialize(&value, Infinite).unwrap();
+
+    let mut log = Log::default();
+    {
+        let mut deserializer = Deserializer::with_observer(SliceReader::new(&serialized[..]),
+                                                           Infinite,
+                                                           &mut log);
+        let deserialized: (Vec<u16>, String, HashMap<u8, String>) =
+            serde::Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!(deserialized, value);
+    }
+    assert_eq!(log.0, vec!["seq 0 2", "end 12",
+                           "string 12 2",
+                           "map 22 1", "string 31 3", "end 42"]);
+}
+
+#[test]
+fn test_decode_stats() {
+    use bincode::serde::deserialize_from_with_stats;
+
+    let value = (vec![vec![1u8, 2], vec![]], "hello".to_string(), Some("hi".to_string()));
+    let serialized = serialize(&value, Infinite).unwrap();
+
+    let (deserialized, stats) = deserialize_from_with_stats::<_, (Vec<Vec<u8>>, String, Option<String>), _>(
+        &mut &serialized[..], Infinite).unwrap();
+    assert_eq!(deserialized, value);
+    assert_eq!(stats.bytes, serialized.len() as u64);
+    assert_eq!(stats.seqs, 3);
+    assert_eq!(stats.elements, 4);
+    assert_eq!(stats.strings, 2);
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.largest_allocation, 5);
+}
+
+#[test]
+fn test_schema_trace() {
+    use bincode::schema::{trace, Format};
+
+    let descriptor = trace::<(u8, Vec<String>, Option<u64>, HashMap<u16, bool>)>().unwrap();
+    assert_eq!(descriptor.root,
+               Format::Tuple(vec![Format::U8,
+                                  Format::Seq(Box::new(Format::Str)),
+                                  Format::Option(Box::new(Format::U64)),
+                                  Format::Map(Box::new(Format::U16), Box::new(Format::Bool))]));
+    assert!(descriptor.types.is_empty());
+
+    let json = bincode::schema::export_descriptor::<(i16, Option<char>)>().unwrap();
+    assert_eq!(json,
+               concat!(r#"{"encoding":"bincode","endianness":"big","root":"#,
+                       r#"{"kind":"tuple","elements":[{"kind":"i16","width":2},"#,
+                       r#"{"kind":"option","tag":{"kind":"u8","width":1},"#,
+                       r#""some":{"kind":"char","encoding":"utf8"}}]},"types":{}}"#));
+}
+
+#[test]
+fn test_schema_trace_named() {
+    use bincode::schema::{trace, Format, Container, Field, Variant, VariantFormat};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Tree {
+        label: String,
+        children: Vec<Tree>,
+        shape: Shape,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Shape {
+        Leaf,
+        Weighted(u32),
+        Box { width: f32, height: f32 },
+    }
+
+    let descriptor = trace::<Tree>().unwrap();
+    assert_eq!(descriptor.root, Format::Named("Tree".to_string()));
+    assert_eq!(descriptor.types["Tree"],
+               Container::Struct(vec![
+                   Field { name: "label".to_string(), format: Format::Str },
+                   Field { name: "children".to_string(),
+                           format: Format::Seq(Box::new(Format::Named("Tree".to_string()))) },
+                   Field { name: "shape".to_string(), format: Format::Named("Shape".to_string()) },
+               ]));
+    assert_eq!(descriptor.types["Shape"],
+               Container::Enum(vec![
+                   Variant { name: "Leaf".to_string(), format: VariantFormat::Unit },
+                   Variant { name: "Weighted".to_string(), format: VariantFormat::Newtype(Format::U32) },
+                   Variant { name: "Box".to_string(),
+                             format: VariantFormat::Struct(vec![
+                                 Field { name: "width".to_string(), format: Format::F32 },
+                                 Field { name: "height".to_string(), format: Format::F32 },
+                             ]) },
+               ]));
+}
+
+#[test]
+fn test_roundtrips() {
+    use bincode::roundtrip::Generator;
+
+    assert_roundtrips!((u8, String, Vec<Option<i32>>, HashMap<u16, Vec<u8>>));
+    assert_roundtrips!((bool, f32, f64, [u64; 3], Vec<(i16, Option<String>)>), Bounded(64));
+
+    let first: Vec<String> = Generator::new(3).generate().unwrap();
+    let again: Vec<String> = Generator::new(3).generate().unwrap();
+    assert_eq!(first, again);
+
+    let empty: Vec<Vec<u8>> = Generator::new(3).max_len(0).generate().unwrap();
+    assert!(empty.is_empty());
+    let flat: Option<Vec<Option<u8>>> = Generator::new(3).max_depth(0).generate().unwrap();
+    assert_eq!(flat, None);
+}
+
+#[test]
+fn test_roundtrips_derived() {
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Expr {
+        Literal(i64),
+        Name(String),
+        Call { function: Box<Expr>, arguments: Vec<Expr> },
+        Unit,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Program {
+        version: u16,
+        body: Vec<Expr>,
+    }
+
+    assert_roundtrips!(Expr);
+    assert_roundtrips!(Program, Bounded(256));
+}
+
+#[cfg(feature = "fuzz")]
+#[test]
+fn test_fuzz_util() {
+    use bincode::fuzz_util::{arbitrary_roundtrip, roundtrip};
+
+    roundtrip(&(1u8, "hello".to_string(), vec![Some(2u32), None]));
+
+    let inputs: &[&[u8]] = &[
+        &[],
+        &[0, 0, 0, 0, 0, 0, 0, 2, 0, 1, 0, 2],
+        &[255, 255, 255, 255, 255, 255, 255, 255, 1],
+        &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 3, b'a', b'b'],
+    ];
+    for input in inputs {
+        arbitrary_roundtrip::<Vec<u16>>(input, 64);
+        arbitrary_roundtrip::<Vec<String>>(input, 64);
+        arbitrary_roundtrip::<Option<Vec<u8>>>(input, 64);
+    }
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_bytes() {
+    use bytes::{Buf, Bytes, BytesMut};
+    use bincode::bytes::{serialize_into_buf, serialize_to_bytes, deserialize_from_buf, Payload};
+
+    let value = (7u16, "buf".to_string(), Payload::from(vec![1u8, 2, 3]));
+    let expected = serialize(&value, Infinite).unwrap();
+    assert_eq!(expected, serialize(&(7u16, "buf".to_string(), vec![1u8, 2, 3]), Infinite).unwrap());
+
+    let mut buf = BytesMut::new();
+    serialize_into_buf(&mut buf, &value, Infinite).unwrap();
+    serialize_into_buf(&mut buf, &5u8, Infinite).unwrap();
+    assert!(serialize_into_buf(&mut buf, &value, Bounded(4)).is_err());
+    assert_eq!(&buf[..expected.len()], &expected[..]);
+
+    let mut buf = buf.freeze();
+    let decoded: (u16, String, Payload) = deserialize_from_buf(&mut buf, Infinite).unwrap();
+    assert_eq!(decoded, value);
+    assert_eq!(&*decoded.2, &[1, 2, 3]);
+    assert_eq!(buf.remaining(), 1);
+    assert_eq!(deserialize_from_buf::<_, u8, _>(&mut buf, Infinite).unwrap(), 5);
+
+    assert_eq!(serialize_to_bytes(&value).unwrap(), Bytes::from(expected));
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn test_ffi_frames() {
+    use std::ptr;
+    use bincode::ffi::*;
+
+    let mut stream = serialize(&vec![1u8, 2, 3], Infinite).unwrap();
+    stream.extend(serialize(&vec![4u8], Infinite).unwrap());
+
+    unsafe {
+        let mut payload_len = 0u64;
+        assert_eq!(bincode_frame_payload_len(stream.as_ptr(), stream.len(), &mut payload_len),
+                   BINCODE_FRAME_OK);
+        assert_eq!(payload_len, 3);
+
+        let mut frame_len = 0usize;
+        assert_eq!(bincode_frame_check(stream.as_ptr(), stream.len(), 16, &mut frame_len),
+                   BINCODE_FRAME_OK);
+        assert_eq!(frame_len, 11);
+
+        let mut payload = ptr::null();
+        let mut len = 0usize;
+        let rest = &stream[frame_len..];
+        assert_eq!(bincode_frame_payload(rest.as_ptr(), rest.len(), 16, &mut payload, &mut len),
+                   BINCODE_FRAME_OK);
+        assert_eq!(::std::slice::from_raw_parts(payload, len), &[4]);
+
+        assert_eq!(bincode_frame_check(stream.as_ptr(), 10, 16, &mut frame_len),
+                   BINCODE_FRAME_INCOMPLETE);
+        assert_eq!(bincode_frame_check(stream.as_ptr(), 4, 16, &mut frame_len),
+                   BINCODE_FRAME_INCOMPLETE);
+        assert_eq!(bincode_frame_check(stream.as_ptr(), stream.len(), 2, &mut frame_len),
+                   BINCODE_FRAME_TOO_LARGE);
+        assert_eq!(bincode_frame_check(ptr::null(), 0, 16, &mut frame_len),
+                   BINCODE_FRAME_NULL);
+    }
+}
+
+#[cfg(feature = "shmem")]
+#[test]
+fn test_shmem_ring() {
+    use std::thread;
+    use bincode::shmem::{RingProducer, RingConsumer, ShmError, HEADER_LEN};
+
+    // A `u64` buffer, so that the positions in the header are aligned.
+    let mut region = vec![0u64; (HEADER_LEN + 64) / 8];
+    let ptr = region.as_mut_ptr() as *mut u8;
+    let len = region.len() * 8;
+    let mut producer = unsafe { RingProducer::create(ptr, len).unwrap() };
+    let mut consumer = unsafe { RingConsumer::attach(ptr, len).unwrap() };
+    assert_eq!(producer.capacity(), 64);
+
+    assert_eq!(consumer.recv::<String>().unwrap(), None);
+    let message = "twenty characters!!!".to_string();
+    // Each frame takes 8 + 8 + 20 bytes, so only one fits at a time.
+    assert!(producer.send(&message).unwrap());
+    assert!(!producer.send(&message).unwrap());
+    assert_eq!(producer.pending(), 36);
+    assert_eq!(consumer.recv::<String>().unwrap(), Some(message.clone()));
+
+    // The second frame starts at 36, and runs over the end of the ring.
+    assert!(producer.send(&message).unwrap());
+    assert!(producer.send_bytes(&[1, 2, 3]).unwrap());
+    assert_eq!(consumer.recv::<String>().unwrap(), Some(message.clone()));
+    assert_eq!(consumer.recv_bytes().unwrap(), Some(vec![1, 2, 3]));
+    assert_eq!(consumer.pending(), 0);
+
+    match producer.send_bytes(&[0; 57]) {
+        Err(ShmError::TooLarge) => {}
+        other => panic!("Expecting TooLarge, got {:?}", other),
+    }
+    match unsafe { RingConsumer::attach(ptr, HEADER_LEN) } {
+        Err(ShmError::InvalidRegion) => {}
+        Ok(_) => panic!("Expecting InvalidRegion"),
+        Err(err) => panic!("Expecting InvalidRegion, got {:?}", err),
+    }
+
+    // Both sides on their own threads, with the ring wrapping many times.
+    let producer_thread = thread::spawn(move || {
+        for i in 0..1000u32 {
+            while !producer.send(&(i, vec![i as u8; (i % 13) as usize])).unwrap() {
+                thread::yield_now();
+            }
+        }
+    });
+    let mut next = 0u32;
+    while next < 1000 {
+        match consumer.recv::<(u32, Vec<u8>)>().unwrap() {
+            Some((i, bytes)) => {
+                assert_eq!(i, next);
+                assert_eq!(bytes, vec![i as u8; (i % 13) as usize]);
+                next += 1;
+            }
+            None => thread::yield_now(),
+        }
+    }
+    producer_thread.join().unwrap();
+    drop(consumer);
+
+    // Positions that are further apart than the ring is long are refused
+    // on both sides, rather than read or written past the region.
+    let ptr = region.as_mut_ptr() as *mut u8;
+    let mut producer = unsafe { RingProducer::create(ptr, len).unwrap() };
+    let mut consumer = unsafe { RingConsumer::attach(ptr, len).unwrap() };
+    assert!(producer.send_bytes(&[1, 2, 3]).unwrap());
+    region[0] = 1000;
+    match consumer.recv_bytes() {
+        Err(ShmError::Corrupt) => {}
+        other => panic!("Expecting Corrupt, got {:?}", other),
+    }
+    region[0] = 0;
+    region[8] = 1000;
+    match producer.send_bytes(&[1]) {
+        Err(ShmError::Corrupt) => {}
+        other => panic!("Expecting Corrupt, got {:?}", other),
+    }
+    drop(region);
+}
+
+#[test]
+fn test_max_size() {
+    use bincode::MaxSize;
+
+    assert_eq!(<(u8, u16, u32, u64)>::MAX_SIZE, 15);
+    assert_eq!(<Option<[u32; 4]>>::MAX_SIZE, 1 + 8 + 16);
+
+    let value = (Some(7u16), [1u32, 2, 3], true);
+    assert_eq!(proxy_encoded_size(&value), <(Option<u16>, [u32; 3], bool)>::MAX_SIZE as u64);
+    assert!(proxy_encoded_size(&None::<u64>) < <Option<u64>>::MAX_SIZE as u64);
+}
+
+#[test]
+fn test_max_size_in_layout() {
+    use bincode::{MaxSize, MaxSizeIn};
+    use bincode::format::{FixedLayout, LegacyLayout};
+    use bincode::serde::Config;
+
+    assert_eq!(char::MAX_SIZE, 4);
+    assert_eq!(proxy_encoded_size(&'\u{10ffff}'), char::MAX_SIZE as u64);
+    assert_eq!(<char as MaxSizeIn<LegacyLayout>>::MAX_SIZE_IN, 12);
+    assert_eq!(Config::legacy().serialize(&'\u{10ffff}').unwrap().len(), <char as MaxSizeIn<LegacyLayout>>::MAX_SIZE_IN);
+
+    // Arrays have no length in front of them in the fixed layout.
+    let value = ([1u16, 2, 3], Some([4u8; 2]));
+    assert_eq!(<([u16; 3], Option<[u8; 2]>)>::MAX_SIZE, 8 + 6 + 1 + 8 + 2);
+    assert_eq!(<([u16; 3], Option<[u8; 2]>) as MaxSizeIn<FixedLayout>>::MAX_SIZE_IN, 6 + 1 + 2);
+    assert_eq!(Config::fixed().serialize(&value).unwrap().len(), <([u16; 3], Option<[u8; 2]>) as MaxSizeIn<FixedLayout>>::MAX_SIZE_IN);
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_derive_max_size() {
+    use bincode::{MaxSize, MaxSizeIn};
+    use bincode::format::FixedLayout;
+
+    #[derive(bincode::MaxSize)]
+    struct Header {
+        id: u32,
+        key: [u8; 4],
+        flag: Option<bool>,
+    }
+
+    #[derive(bincode::MaxSize)]
+    struct Wrapper<T>(T, u8);
+
+    #[derive(bincode::MaxSize)]
+    #[allow(dead_code)]
+    enum Message {
+        Ping,
+        Header(Header),
+        Pair(u64, u64),
+    }
+
+    assert_eq!(Header::MAX_SIZE, 4 + 8 + 4 + 2);
+    assert_eq!(<Header as MaxSizeIn<FixedLayout>>::MAX_SIZE_IN, 4 + 4 + 2);
+    assert_eq!(<Wrapper<u16>>::MAX_SIZE, 3);
+    assert_eq!(Message::MAX_SIZE, 4 + 18);
+    assert_eq!(<Message as MaxSizeIn<FixedLayout>>::MAX_SIZE_IN, 4 + 16);
+}
+
+#[test]
+fn test_encode_into_array() {
+    let value = (1u32, Some(2u64));
+
+    let mut encoded = [0u8; 13];
+    let written = bincode::rustc_serialize::encode_into_array(&value, &mut encoded).unwrap();
+    let mut serialized = [0u8; 16];
+    let serialized_written = bincode::serde::serialize_into_array(&value, &mut serialized).unwrap();
+
+    assert_eq!(written, 13);
+    assert_eq!(serialized_written, 13);
+    assert_eq!(&encoded[..], &serialized[..13]);
+    assert_eq!(encoded.to_vec(), serialize(&value, Infinite).unwrap());
+}
+
+#[test]
+fn test_frame_reader() {
+    use bincode::frame::{FrameReader, FrameError, LengthPrefix};
+
+    let mut stream = serialize(&vec![1u8, 2, 3], Infinite).unwrap();
+    stream.extend(serialize(&Vec::<u8>::new(), Infinite).unwrap());
+    stream.extend(serialize(&vec![4u8], Infinite).unwrap());
+
+    let frames: Vec<Vec<u8>> = FrameReader::new(&stream[..]).map(|frame| frame.unwrap()).collect();
+    assert_eq!(frames, vec![vec![1, 2, 3], vec![], vec![4]]);
+
+    let mut reader = FrameReader::new(&stream[..]).max_frame_len(2);
+    match reader.next_frame() {
+        Err(FrameError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+
+    let mut reader = FrameReader::new(&stream[..stream.len() - 1]);
+    assert_eq!(reader.next_frame().unwrap(), Some(&[1u8, 2, 3][..]));
+    assert_eq!(reader.next_frame().unwrap(), Some(&[][..]));
+    match reader.next_frame() {
+        Err(FrameError::Truncated) => {}
+        other => panic!("Expecting Truncated, got {:?}", other),
+    }
+
+    let short = [0u8, 0, 0, 2, 5, 6, 0, 0];
+    let mut reader = FrameReader::new(&short[..]).length_prefix(LengthPrefix::U32);
+    assert_eq!(reader.next_frame().unwrap(), Some(&[5u8, 6][..]));
+    match reader.next_frame() {
+        Err(FrameError::Truncated) => {}
+        other => panic!("Expecting Truncated, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_frame_sequence_numbers() {
+    use bincode::frame::{FrameReader, FrameWriter, FrameError, LengthPrefix};
+
+    let mut writer = FrameWriter::new(Vec::new()).length_prefix(LengthPrefix::U32).sequence_numbers();
+    let mut frames = Vec::new();
+    for payload in &[&[1u8][..], &[2, 2][..], &[3][..], &[4][..]] {
+        let start = writer.get_ref().len();
+        writer.write_frame(payload).unwrap();
+        frames.push(writer.get_ref()[start..].to_vec());
+    }
+    assert_eq!(writer.next_sequence(), Some(4));
+    assert_eq!(frames[1], vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 2, 2]);
+
+    let stream: Vec<u8> = frames.concat();
+    let mut reader = FrameReader::new(&stream[..]).length_prefix(LengthPrefix::U32).sequence_numbers();
+    let read: Vec<Vec<u8>> = reader.by_ref().map(|frame| frame.unwrap()).collect();
+    assert_eq!(read, vec![vec![1], vec![2, 2], vec![3], vec![4]]);
+
+    // Frame 1 is lost and frames 2 and 3 are swapped.
+    let lossy: Vec<u8> = [&frames[0][..], &frames[3][..], &frames[2][..]].concat();
+    let mut reader = FrameReader::new(&lossy[..]).length_prefix(LengthPrefix::U32).sequence_numbers();
+    assert_eq!(reader.next_frame().unwrap(), Some(&[1u8][..]));
+    match reader.next_frame() {
+        Err(FrameError::FrameGap { expected: 1, got: 3 }) => {}
+        other => panic!("Expecting FrameGap, got {:?}", other),
+    }
+    assert_eq!(reader.next_frame().unwrap(), Some(&[4u8][..]));
+    match reader.next_frame() {
+        Err(FrameError::FrameGap { expected: 4, got: 2 }) => {}
+        other => panic!("Expecting FrameGap, got {:?}", other),
+    }
+    assert_eq!(reader.next_frame().unwrap(), Some(&[3u8][..]));
+    assert_eq!(reader.next_frame().unwrap(), None);
+
+    // Without sequence numbers, the writer's frames are plain byte buffers.
+    let mut writer = FrameWriter::new(Vec::new());
+    writer.write_frame(&[7, 8]).unwrap();
+    assert_eq!(writer.next_sequence(), None);
+    assert_eq!(writer.into_inner(), serialize(&vec![7u8, 8], Infinite).unwrap());
+}
+
+#[test]
+fn test_frame_sink() {
+    use std::io::{self, Write};
+    use bincode::frame::{FrameReader, FrameSink, LengthPrefix};
+
+    // Takes up to `room` bytes, and then would block until it is given
+    // more room.
+    struct Slow {
+        output: Vec<u8>,
+        room: usize,
+    }
+
+    impl Write for Slow {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.room == 0 {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "full"));
+            }
+            let n = ::std::cmp::min(self.room, buf.len());
+            self.output.extend_from_slice(&buf[..n]);
+            self.room -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let slow = Slow { output: Vec::new(), room: 5 };
+    let mut sink = FrameSink::new(slow).length_prefix(LengthPrefix::U32).sequence_numbers().high_water_mark(30);
+
+    // Each frame is 8 + 4 + 4 bytes, so the third one goes over the mark.
+    let mut sent = 0u32;
+    while sink.poll_ready().unwrap() {
+        assert!(sink.start_send(&[sent as u8; 4]).unwrap());
+        sent += 1;
+    }
+    assert_eq!(sent, 3);
+    assert_eq!(sink.buffered(), 43);
+    assert!(!sink.start_send(&[9; 4]).unwrap());
+    assert_eq!(sink.next_sequence(), Some(3));
+    assert!(!sink.poll_flush().unwrap());
+    assert_eq!(sink.get_ref().output.len(), 5);
+
+    sink.get_mut().room = 20;
+    assert!(sink.poll_ready().unwrap());
+    assert_eq!(sink.buffered(), 23);
+    assert!(sink.start_send(&[3; 4]).unwrap());
+
+    sink.get_mut().room = 1000;
+    assert!(sink.poll_flush().unwrap());
+    assert_eq!(sink.buffered(), 0);
+
+    let output = sink.into_inner().output;
+    let frames: Vec<Vec<u8>> = FrameReader::new(&output[..])
+        .length_prefix(LengthPrefix::U32)
+        .sequence_numbers()
+        .map(|frame| frame.unwrap())
+        .collect();
+    assert_eq!(frames, vec![vec![0; 4], vec![1; 4], vec![2; 4], vec![3; 4]]);
+}
+
+#[test]
+fn test_typed_stream() {
+    use std::io::{self, Read, Write};
+    use bincode::channel::{TypedStream, ChannelError};
+
+    // Hands out its input a byte at a time, to check that messages are
+    // put back together from partial reads.
+    struct Duplex {
+        input: Vec<u8>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.input.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.input.remove(0);
+            Ok(1)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut client: TypedStream<(u8, String), Vec<u32>, _> =
+        TypedStream::new(Duplex { input: Vec::new(), output: Vec::new() }).max_message_len(64);
+    client.send(&(1, "ping".to_string())).unwrap();
+    client.send(&(2, "pong".to_string())).unwrap();
+    match client.send(&(3, "x".repeat(100))) {
+        Err(ChannelError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    let requests = client.into_inner().output;
+
+    let mut server: TypedStream<Vec<u32>, (u8, String), _> =
+        TypedStream::new(Duplex { input: requests, output: Vec::new() });
+    assert_eq!(server.recv().unwrap(), (1, "ping".to_string()));
+    server.send(&vec![1, 2, 3]).unwrap();
+    assert_eq!(server.recv().unwrap(), (2, "pong".to_string()));
+    match server.recv() {
+        Err(ChannelError::Closed) => {}
+        other => panic!("Expecting Closed, got {:?}", other),
+    }
+    let mut responses = server.into_inner().output;
+
+    let mut client: TypedStream<(u8, String), Vec<u32>, _> =
+        TypedStream::new(Duplex { input: responses.clone(), output: Vec::new() }).max_message_len(64);
+    assert_eq!(client.recv().unwrap(), vec![1, 2, 3]);
+
+    responses.pop();
+    let mut client: TypedStream<(u8, String), Vec<u32>, _> =
+        TypedStream::new(Duplex { input: responses, output: Vec::new() });
+    match client.recv() {
+        Err(ChannelError::IoError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+        other => panic!("Expecting UnexpectedEof, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_loopback() {
+    use std::thread;
+    use bincode::test_util::loopback;
+
+    let (mut sender, mut receiver) = loopback::<(u32, String, Vec<Option<u16>>)>();
+    let messages: Vec<_> = (0..20u32)
+        .map(|i| (i, "message ".to_string() + &i.to_string(), vec![Some(i as u16), None]))
+        .collect();
+
+    let sent = messages.clone();
+    let handle = thread::spawn(move || {
+        for message in &sent {
+            sender.send(message).unwrap();
+        }
+    });
+
+    for message in &messages {
+        assert_eq!(&receiver.recv().unwrap(), message);
+    }
+    handle.join().unwrap();
+
+    match receiver.recv() {
+        Err(DeserializeError::IoError(_)) => {}
+        other => panic!("Expecting IoError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_deserialize_segments() {
+    use bincode::serde::deserialize_segments;
+
+    let value = (0x01020304u32, "segmented".to_string(), vec![5u16, 6, 7], 0x08090a0b0c0d0e0fu64);
+    let encoded = serialize(&value, Infinite).unwrap();
+
+    // Every way of cutting the message in three, so that numbers, lengths,
+    // and strings all get split somewhere.
+    for first in 0..encoded.len() + 1 {
+        for second in first..encoded.len() + 1 {
+            let segments = [&encoded[..first], &encoded[first..second], &encoded[second..]];
+            let decoded: (u32, String, Vec<u16>, u64) = deserialize_segments(&segments).unwrap();
+            assert_eq!(decoded, value);
+        }
+    }
+
+    let segments = vec![encoded[..10].to_vec(), encoded[10..20].to_vec()];
+    match deserialize_segments::<(u32, String, Vec<u16>, u64), _>(&segments) {
+        Err(DeserializeError::IoError(_)) => {}
+        other => panic!("Expecting IoError, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_utf8_mode() {
+    use bincode::serde::{Deserializer, SliceReader, IoReader, Utf8Mode, RawString};
+
+    fn decode<T: serde::Deserialize>(bytes: &[u8], mode: Utf8Mode) -> (DeserializeResult<T>, DeserializeResult<T>) {
+        let mut deserializer = Deserializer::new(SliceReader::new(bytes), Infinite);
+        deserializer.set_utf8_mode(mode);
+        let from_slice = serde::Deserialize::deserialize(&mut deserializer);
+
+        let mut deserializer = Deserializer::new(IoReader::new(bytes), Infinite);
+        deserializer.set_utf8_mode(mode);
+        let from_io = serde::Deserialize::deserialize(&mut deserializer);
+        (from_slice, from_io)
+    }
+
+    // "café" in Latin-1.
+    let latin1 = serialize(&RawString(vec![b'c', b'a', b'f', 0xe9]), Infinite).unwrap();
+    assert_eq!(latin1, serialize(&vec![b'c', b'a', b'f', 0xe9], Infinite).unwrap());
+
+    let (from_slice, from_io) = decode::<String>(&latin1, Utf8Mode::Strict);
+    for result in vec![from_slice, from_io] {
+        match result {
+            Err(DeserializeError::InvalidEncoding(_)) => {}
+            other => panic!("Expecting InvalidEncoding, got {:?}", other),
+        }
+    }
+
+    let (from_slice, from_io) = decode::<String>(&latin1, Utf8Mode::Lossy);
+    assert_eq!(from_slice.unwrap(), "caf\u{FFFD}");
+    assert_eq!(from_io.unwrap(), "caf\u{FFFD}");
+
+    let (from_slice, from_io) = decode::<RawString>(&latin1, Utf8Mode::Raw);
+    assert_eq!(from_slice.unwrap(), RawString(vec![b'c', b'a', b'f', 0xe9]));
+    assert_eq!(from_io.unwrap(), RawString(vec![b'c', b'a', b'f', 0xe9]));
+
+    // `String` checks the bytes it is given itself.
+    let (from_slice, from_io) = decode::<String>(&latin1, Utf8Mode::Raw);
+    assert!(from_slice.is_err());
+    assert!(from_io.is_err());
+
+    // Valid strings come out the same in every mode.
+    let valid = serialize(&"café", Infinite).unwrap();
+    for &mode in &[Utf8Mode::Strict, Utf8Mode::Lossy, Utf8Mode::Raw] {
+        let (from_slice, from_io) = decode::<String>(&valid, mode);
+        assert_eq!(from_slice.unwrap(), "café");
+        assert_eq!(from_io.unwrap(), "café");
+        let (from_slice, from_io) = decode::<RawString>(&valid, mode);
+        assert_eq!(from_slice.unwrap().to_str(), Some("café"));
+        assert_eq!(from_io.unwrap().to_str(), Some("café"));
+    }
+
+    // `RawString` decodes in strict mode too, as long as the string is valid.
+    let decoded: RawString = deserialize(&valid).unwrap();
+    assert_eq!(decoded, RawString::from("café"));
+    assert!(deserialize::<RawString>(&latin1).is_err());
+}
+
+#[test]
+fn test_portable_paths() {
+    use std::ffi::OsString;
+    use std::path::PathBuf;
+    use bincode::serde::{PortableOsString, PortablePathBuf};
+
+    let path = PortablePathBuf(PathBuf::from("backup/naïve.txt"));
+    let encoded = serialize(&path, Infinite).unwrap();
+    let mut expected = vec![0, 0, 0, 0];
+    expected.extend(serialize(&"backup/naïve.txt", Infinite).unwrap());
+    assert_eq!(encoded, expected);
+    assert_eq!(deserialize::<PortablePathBuf>(&encoded).unwrap(), path);
+
+    // A name written by a Windows system that is valid UTF-16 can be read
+    // anywhere.
+    let wide: Vec<u16> = "résumé".encode_utf16().collect();
+    let mut encoded = vec![0, 0, 0, 2];
+    encoded.extend(serialize(&wide, Infinite).unwrap());
+    let decoded: PortableOsString = deserialize(&encoded).unwrap();
+    assert_eq!(decoded.0, OsString::from("résumé"));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+
+        // "café" in Latin-1, as an old unix system might have named a file.
+        let name = PortableOsString(OsString::from_vec(vec![b'c', b'a', b'f', 0xe9]));
+        let encoded = serialize(&name, Infinite).unwrap();
+        assert_eq!(encoded, vec![0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 4, b'c', b'a', b'f', 0xe9]);
+        assert_eq!(deserialize::<PortableOsString>(&encoded).unwrap(), name);
+
+        let path = PortablePathBuf(PathBuf::from(OsString::from_vec(b"/home/\xff/file".to_vec())));
+        let encoded = serialize(&path, Infinite).unwrap();
+        assert_eq!(deserialize::<PortablePathBuf>(&encoded).unwrap(), path);
+
+        // An unpaired surrogate has no unix equivalent.
+        let mut encoded = vec![0, 0, 0, 2];
+        encoded.extend(serialize(&vec![0x61u16, 0xd800], Infinite).unwrap());
+        assert!(deserialize::<PortableOsString>(&encoded).is_err());
+    }
+
+    assert!(deserialize::<PortableOsString>(&[0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+}
+
+#[test]
+fn test_columnar() {
+    use bincode::serde::{Columnar, ColumnarView};
+
+    let rows: Vec<(u64, String, Option<u8>)> = (0..50)
+        .map(|i| (1_000_000 + i, format!("sensor-{}", i % 3), if i % 2 == 0 { Some(i as u8) } else { None }))
+        .collect();
+    let columnar = Columnar(rows.clone());
+    let encoded = serialize(&columnar, Infinite).unwrap();
+
+    // All the timestamps come first, right after the row and column counts
+    // and the kind and length of the first column.
+    let mut expected = vec![0, 0, 0, 0, 0, 0, 0, 50, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 144];
+    expected.extend(serialize(&1_000_000u64, Infinite).unwrap());
+    expected.extend(serialize(&1_000_001u64, Infinite).unwrap());
+    assert_eq!(&encoded[..expected.len()], &expected[..]);
+
+    let decoded: Columnar<(u64, String, Option<u8>)> = deserialize(&encoded).unwrap();
+    assert_eq!(decoded.0, rows);
+
+    let view = ColumnarView::new(&encoded).unwrap();
+    assert_eq!(view.rows(), 50);
+    assert_eq!(view.columns(), 3);
+    let names: Vec<String> = view.column(1).unwrap();
+    assert_eq!(names, rows.iter().map(|row| row.1.clone()).collect::<Vec<_>>());
+    assert!(view.column::<u8>(3).is_err());
+
+    let empty: Columnar<(u8, u8)> = Columnar(vec![]);
+    let encoded = serialize(&empty, Infinite).unwrap();
+    assert_eq!(deserialize::<Columnar<(u8, u8)>>(&encoded).unwrap(), empty);
+
+    // Rows have to be structs or tuples, and columns have to line up with
+    // the fields.
+    assert!(serialize(&Columnar(vec![1u32, 2]), Infinite).is_err());
+    let encoded = serialize(&Columnar(vec![(1u8, 2u8, 3u8)]), Infinite).unwrap();
+    assert!(deserialize::<Columnar<(u8, u8)>>(&encoded).is_err());
+}
+
+#[test]
+fn test_columnar_dictionary() {
+    use bincode::serde::{Columnar, ColumnarView, LowCardinality};
+
+    let kinds = ["click", "scroll", "keypress"];
+    let rows: Vec<(u32, LowCardinality<String>)> = (0..1000)
+        .map(|i| (i, LowCardinality(kinds[i as usize % 3].to_string())))
+        .collect();
+    let encoded = serialize(&Columnar(rows.clone()), Infinite).unwrap();
+    let plain: Vec<(u32, String)> = rows.iter().map(|row| (row.0, row.1.to_string())).collect();
+    assert!(encoded.len() < serialize(&Columnar(plain), Infinite).unwrap().len() / 2);
+
+    let decoded: Columnar<(u32, LowCardinality<String>)> = deserialize(&encoded).unwrap();
+    assert_eq!(decoded.0, rows);
+
+    let view = ColumnarView::new(&encoded).unwrap();
+    assert!(!view.is_dictionary(0));
+    assert!(view.is_dictionary(1));
+    let column: Vec<String> = view.column(1).unwrap();
+    assert_eq!(&column[..4], &["click", "scroll", "keypress", "click"]);
+
+    // Outside of a `Columnar`, the wrapper changes nothing.
+    assert_eq!(serialize(&LowCardinality("click"), Infinite).unwrap(),
+               serialize(&"click", Infinite).unwrap());
+    assert_eq!(deserialize::<LowCardinality<u16>>(&[0, 7]).unwrap(), LowCardinality(7));
+}
+
+#[test]
+fn test_record_log() {
+    use bincode::log::{RecordWriter, RecordReader, LogError};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let syncs = Rc::new(Cell::new(0));
+    let counter = syncs.clone();
+    let mut writer = RecordWriter::new(Vec::new())
+        .sync_with(move |_: &mut Vec<u8>| { counter.set(counter.get() + 1); Ok(()) })
+        .sync_every(2);
+    writer.append(&(1u8, "one".to_string())).unwrap();
+    writer.append(&(2u8, "two".to_string())).unwrap();
+    writer.append(&(3u8, "three".to_string())).unwrap();
+    assert_eq!(syncs.get(), 1);
+    writer.sync().unwrap();
+    assert_eq!(syncs.get(), 2);
+    let log = writer.into_inner();
+
+    // Length, then the CRC-32 of the length and payload.
+    assert_eq!(&log[..4], &[0, 0, 0, 12]);
+    assert_eq!(&log[4..8], &[0x7a, 0x3c, 0x4e, 0xcc]);
+
+    let mut reader = RecordReader::new(&log[..]);
+    assert_eq!(reader.next_record::<(u8, String)>().unwrap(), Some((1, "one".to_string())));
+    assert_eq!(reader.next_record::<(u8, String)>().unwrap(), Some((2, "two".to_string())));
+    assert_eq!(reader.next_record::<(u8, String)>().unwrap(), Some((3, "three".to_string())));
+    assert_eq!(reader.next_record::<(u8, String)>().unwrap(), None);
+    assert!(!reader.is_torn());
+    assert_eq!(reader.valid_len(), log.len() as u64);
+
+    // Every way of cutting the last record short is recovered from.
+    let end_of_second = 2 * (8 + 12);
+    for cut in end_of_second + 1..log.len() {
+        let mut reader = RecordReader::new(&log[..cut]);
+        assert!(reader.next_bytes().unwrap().is_some());
+        assert!(reader.next_bytes().unwrap().is_some());
+        assert_eq!(reader.next_bytes().unwrap(), None);
+        assert!(reader.is_torn());
+        assert_eq!(reader.valid_len(), end_of_second as u64);
+    }
+
+    // So is a last record whose payload never reached the disk.
+    let mut zeroed = log.clone();
+    for byte in &mut zeroed[end_of_second + 8..] {
+        *byte = 0;
+    }
+    let mut reader = RecordReader::new(&zeroed[..]);
+    assert!(reader.next_bytes().unwrap().is_some());
+    assert!(reader.next_bytes().unwrap().is_some());
+    assert_eq!(reader.next_bytes().unwrap(), None);
+    assert!(reader.is_torn());
+
+    // But damage in the middle of the log is not.
+    let mut damaged = log.clone();
+    damaged[8 + 12 + 10] ^= 1;
+    let mut reader = RecordReader::new(&damaged[..]);
+    assert!(reader.next_bytes().unwrap().is_some());
+    match reader.next_bytes() {
+        Err(LogError::Corrupt(20)) => {}
+        other => panic!("expected Corrupt(20), got {:?}", other.map(|r| r.map(|r| r.to_vec()))),
+    }
+
+    let mut reader = RecordReader::new(&log[..]).max_record_len(12);
+    assert!(reader.next_bytes().unwrap().is_some());
+    assert!(reader.next_bytes().unwrap().is_some());
+    match reader.next_bytes() {
+        Err(LogError::SizeLimit) => {}
+        other => panic!("expected SizeLimit, got {:?}", other.map(|r| r.map(|r| r.to_vec()))),
+    }
+}
+
+#[test]
+fn test_resync_markers() {
+    use bincode::log::{RecordWriter, RecordReader, LogError, resync};
+
+    let mut writer = RecordWriter::new(Vec::new()).resync_markers();
+    for i in 0..5u32 {
+        writer.append(&(i, format!("record {}", i))).unwrap();
+    }
+    writer.append(&"not a pair").unwrap();
+    writer.append(&(6u32, "last".to_string())).unwrap();
+    let log = writer.into_inner();
+    let record_len = 4 + 8 + 4 + 8 + 8;
+    assert_eq!(&log[..4], &[0xb1, 0x9c, 0x5a, 0x7e]);
+    assert_eq!(&log[record_len..record_len + 4], &[0xb1, 0x9c, 0x5a, 0x7e]);
+
+    // Damage the payload of the second record and the header of the fourth.
+    let mut damaged = log.clone();
+    damaged[record_len + 20] ^= 0xff;
+    damaged[3 * record_len + 5] ^= 0xff;
+
+    // A damaged length would otherwise take the rest of the log with it.
+    let mut reader = RecordReader::new(&damaged[..]).resync_markers().max_record_len(64);
+    let mut read = Vec::new();
+    let mut errors = 0;
+    loop {
+        match reader.next_record::<(u32, String)>() {
+            Ok(Some((i, _))) => read.push(i),
+            Ok(None) => break,
+            Err(_) => {
+                errors += 1;
+                if reader.resync().unwrap().is_none() {
+                    break;
+                }
+            }
+        }
+    }
+    assert_eq!(read, vec![0, 2, 4, 6]);
+    assert_eq!(errors, 3);
+    assert!(!reader.is_torn());
+    assert_eq!(reader.valid_len(), log.len() as u64);
+
+    // A record without its marker is corrupt.
+    let mut unmarked = log.clone();
+    unmarked[record_len] = 0;
+    let mut reader = RecordReader::new(&unmarked[..]).resync_markers();
+    assert!(reader.next_bytes().unwrap().is_some());
+    match reader.next_bytes() {
+        Err(LogError::Corrupt(offset)) => assert_eq!(offset, record_len as u64),
+        other => panic!("expected Corrupt, got {:?}", other.map(|r| r.map(|r| r.to_vec()))),
+    }
+    assert_eq!(reader.resync().unwrap(), Some(record_len as u64 - 4));
+    assert_eq!(reader.next_record::<(u32, String)>().unwrap(), Some((2, "record 2".to_string())));
+
+    // Every way of cutting the last record short, marker included, is
+    // still a torn end.
+    let start_of_last = log.len() - (4 + 8 + 4 + 8 + 4);
+    for cut in start_of_last + 1..log.len() {
+        let mut reader = RecordReader::new(&log[..cut]).resync_markers();
+        let mut count = 0;
+        while let Ok(Some(_)) = reader.next_bytes() {
+            count += 1;
+        }
+        assert_eq!(count, 6);
+        assert!(reader.is_torn());
+        assert_eq!(reader.valid_len(), start_of_last as u64);
+    }
+
+    // A damaged length in the middle of the log, whether it runs into the
+    // records after it or past the end of the log, only loses its own
+    // record, even without a maximum record length.
+    for &(byte, bit) in &[(7, 0x20), (6, 0x01), (4, 0x80)] {
+        let mut damaged = log.clone();
+        damaged[2 * record_len + byte] ^= bit;
+        let mut reader = RecordReader::new(&damaged[..]).resync_markers();
+        let mut read = Vec::new();
+        let mut errors = 0;
+        loop {
+            match reader.next_record::<(u32, String)>() {
+                Ok(Some((i, _))) => read.push(i),
+                Ok(None) => break,
+                Err(_) => {
+                    errors += 1;
+                    if reader.resync().unwrap().is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        assert_eq!(read, vec![0, 1, 3, 4, 6]);
+        assert_eq!(errors, 2);
+        assert!(!reader.is_torn());
+        assert_eq!(reader.valid_len(), log.len() as u64);
+    }
+
+    let mut rest = &damaged[5..];
+    assert_eq!(resync(&mut rest).unwrap(), Some(record_len as u64 - 5));
+    assert_eq!(rest.len(), log.len() - record_len - 4);
+    assert_eq!(resync(&mut &b"no marker"[..]).unwrap(), None);
+}
+
+#[test]
+fn test_journal() {
+    use bincode::log::{Journal, Journaled, RecordWriter, LogError};
+    use bincode::Bounded;
+
+    // A list of numbers, which deltas push onto or pop off of.
+    #[derive(Debug, PartialEq)]
+    struct Stack(Vec<u32>);
+
+    impl serde::Serialize for Stack {
+        fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+            self.0.serialize(serializer)
+        }
+    }
+
+    impl serde::Deserialize for Stack {
+        fn deserialize<D: serde::Deserializer>(deserializer: &mut D) -> Result<Stack, D::Error> {
+            Vec::deserialize(deserializer).map(Stack)
+        }
+    }
+
+    impl Journaled for Stack {
+        type Delta = Option<u32>;
+
+        fn apply(&mut self, delta: Option<u32>) {
+            match delta {
+                Some(n) => self.0.push(n),
+                None => { self.0.pop(); }
+            }
+        }
+    }
+
+    let mut journal = Journal::new(RecordWriter::new(Vec::new()), Stack(vec![7])).unwrap()
+        .snapshot_every(4);
+    for n in 0..10 {
+        journal.apply(Some(n)).unwrap();
+    }
+    journal.apply(None).unwrap();
+    assert_eq!(journal.state(), &Stack(vec![7, 0, 1, 2, 3, 4, 5, 6, 7, 8]));
+    let (state, log) = journal.into_parts();
+    let log = log.into_inner();
+
+    let replay = Journal::<Stack>::replay(&log[..], Bounded(1024)).unwrap();
+    assert_eq!(replay.state, Some(state));
+    // Snapshots went in place of the 4th and 8th changes.
+    assert_eq!(replay.deltas, 3);
+    assert_eq!(replay.valid_len, log.len() as u64);
+
+    // A torn last delta is left out.
+    let replay = Journal::<Stack>::replay(&log[..log.len() - 3], Bounded(1024)).unwrap();
+    assert_eq!(replay.state, Some(Stack(vec![7, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9])));
+    assert_eq!(replay.deltas, 2);
+
+    let empty = Journal::<Stack>::replay(&[][..], Bounded(1024)).unwrap();
+    assert_eq!(empty.state, None);
+
+    // Every entry has to fit the size limit.
+    match Journal::<Stack>::replay(&log[..], Bounded(16)) {
+        Err(LogError::SizeLimit) => {}
+        other => panic!("expected SizeLimit, got {:?}", other.map(|replay| replay.state)),
+    }
+
+    // Deltas don't make sense without a snapshot to apply them to.
+    let mut writer = RecordWriter::new(Vec::new());
+    writer.append_bytes(&[1, 0]).unwrap();
+    match Journal::<Stack>::replay(&writer.into_inner()[..], Bounded(1024)) {
+        Err(LogError::InvalidJournal(_)) => {}
+        other => panic!("expected InvalidJournal, got {:?}", other.map(|replay| replay.state)),
+    }
+}
+
+#[test]
+fn test_ordered_keys() {
+    use bincode::serde::{serialize_key, deserialize_key};
+    use std::collections::BTreeMap;
+
+    fn check_order<T>(values: &[T])
+        where T: serde::Serialize + serde::Deserialize + PartialOrd + Debug
+    {
+        for pair in values.windows(2) {
+            assert!(pair[0] < pair[1]);
+            let keys = (serialize_key(&pair[0]).unwrap(), serialize_key(&pair[1]).unwrap());
+            assert!(keys.0 < keys.1, "{:?} and {:?} encode to {:?} and {:?}", pair[0], pair[1], keys.0, keys.1);
+        }
+        for value in values {
+            let key = serialize_key(value).unwrap();
+            assert_eq!(&deserialize_key::<T>(&key).unwrap(), value);
+        }
+    }
+
+    check_order(&[0u8, 1, 200, 255]);
+    check_order(&[0u32, 1, 256, 70000, u32::MAX]);
+    check_order(&[i64::MIN, -70000, -1, 0, 1, 256, i64::MAX]);
+    check_order(&[i8::MIN, -1, 0, i8::MAX]);
+    check_order(&[f64::NEG_INFINITY, -1e10, -1.5, 0.0, 1e-300, 2.5, f64::INFINITY]);
+    check_order(&[-3.5f32, -0.25, 0.0, 0.25, 3.5]);
+    assert!(serialize_key(&-0.0f64).unwrap() < serialize_key(&0.0f64).unwrap());
+    check_order(&['\0', 'A', 'a', '\u{e9}', '\u{1F600}']);
+    check_order(&[false, true]);
+    check_order(&["".to_string(), "\0".to_string(), "\0\0".to_string(), "a".to_string(),
+                  "a\0".to_string(), "a\0b".to_string(), "aa".to_string(), "b".to_string()]);
+    check_order(&[None, Some(-5i32), Some(0), Some(7)]);
+    check_order(&[vec![], vec![0u16], vec![0, 0], vec![0, 1], vec![1]]);
+    check_order(&[("a".to_string(), 9u32), ("aa".to_string(), 0), ("b".to_string(), 1), ("b".to_string(), 2)]);
+
+    let mut map = BTreeMap::new();
+    map.insert(-2i16, "minus two".to_string());
+    map.insert(3, "three".to_string());
+    let key = serialize_key(&map).unwrap();
+    assert_eq!(deserialize_key::<BTreeMap<i16, String>>(&key).unwrap(), map);
+
+    // The key of a tuple starts with the keys of its leading fields.
+    let prefix = serialize_key(&("users".to_string(), 12u64)).unwrap();
+    let full = serialize_key(&("users".to_string(), 12u64, "name".to_string())).unwrap();
+    assert!(full.starts_with(&prefix));
+
+    assert_eq!(serialize_key(&"a\0b").unwrap(), vec![b'a', 0, 0xff, b'b', 0, 1]);
+    assert_eq!(serialize_key(&-1i16).unwrap(), vec![0x7f, 0xff]);
+    assert!(deserialize_key::<u16>(&[0, 1, 2]).is_err());
+    assert!(deserialize_key::<String>(&[b'a', 0, 2]).is_err());
+}
+
+#[test]
+fn test_skip_defaults() {
+    use bincode::serde::{serialize_skipping_defaults, deserialize_skipping_defaults};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+    struct Inner {
+        count: u32,
+        label: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+    struct Settings {
+        id: u64,
+        verbose: bool,
+        name: String,
+        tags: Vec<String>,
+        limit: Option<u16>,
+        ratio: f64,
+        inner: Inner,
+        list: Vec<Inner>,
+        flag: bool,
+    }
+
+    let defaults = Settings::default();
+    let encoded = serialize_skipping_defaults(&defaults, Infinite).unwrap();
+    // Nine fields take two bytes of bitmap, and nothing else.
+    assert_eq!(encoded, vec![0, 0]);
+    assert_eq!(deserialize_skipping_defaults::<Settings>(&encoded).unwrap(), defaults);
+
+    let settings = Settings {
+        id: 7,
+        inner: Inner { count: 0, label: "x".to_string() },
+        list: vec![Inner::default(), Inner { count: 2, label: String::new() }],
+        flag: true,
+        ..Settings::default()
+    };
+    let encoded = serialize_skipping_defaults(&settings, Infinite).unwrap();
+    let mut expected = vec![0b1100_0001, 0b1];
+    expected.extend(&[0, 0, 0, 0, 0, 0, 0, 7]);
+    expected.extend(&[0b10, 0, 0, 0, 0, 0, 0, 0, 1, b'x']);
+    expected.extend(&[0, 0, 0, 0, 0, 0, 0, 2, 0, 0b01, 0, 0, 0, 2]);
+    expected.push(1);
+    assert_eq!(encoded, expected);
+    assert_eq!(deserialize_skipping_defaults::<Settings>(&encoded).unwrap(), settings);
+
+    // Nothing changes outside of structs.
+    assert_eq!(serialize_skipping_defaults(&(0u8, 0u8), Infinite).unwrap(), vec![0, 0]);
+    assert!(serialize_skipping_defaults(&settings, Bounded(10)).is_err());
+}
+
+#[test]
+fn test_value_with_schema() {
+    use bincode::Value;
+    use bincode::schema::{trace, deserialize_value_with_schema};
+    use std::collections::BTreeMap;
+
+    type Message = (u8, String, Option<Vec<i16>>, BTreeMap<u16, f32>, Result<u32, bool>);
+
+    let mut map = BTreeMap::new();
+    map.insert(300, 1.5);
+    let message: Message = (7, "seven".to_string(), Some(vec![-1, 2]), map, Err(true));
+    let encoded = serialize(&message, Infinite).unwrap();
+
+    let descriptor = trace::<Message>().unwrap();
+    let value = deserialize_value_with_schema(&encoded, &descriptor).unwrap();
+    assert_eq!(value, Value::Seq(vec![
+        Value::U8(7),
+        Value::Str("seven".to_string()),
+        Value::Option(Some(Box::new(Value::Seq(vec![Value::I16(-1), Value::I16(2)])))),
+        Value::Map(vec![(Value::U16(300), Value::F32(1.5))]),
+        Value::Variant(1, "Err".to_string(), Box::new(Value::Bool(true))),
+    ]));
+
+    match value {
+        Value::Seq(ref values) => {
+            assert_eq!(values[0].as_u64(), Some(7));
+            assert_eq!(values[1].as_str(), Some("seven"));
+        }
+        _ => unreachable!(),
+    }
+
+    assert!(deserialize_value_with_schema(&encoded[..encoded.len() - 1], &descriptor).is_err());
+}
+
+#[test]
+fn test_diff() {
+    use bincode::{Value, FieldDiff, diff};
+    use bincode::schema::trace;
+
+    type Snapshot = (u32, Vec<(String, u16)>, Option<bool>, Result<u8, String>);
+
+    let descriptor = trace::<Snapshot>().unwrap();
+    let a: Snapshot = (1, vec![("x".to_string(), 1), ("y".to_string(), 2)], Some(true), Ok(3));
+    let b: Snapshot = (1, vec![("x".to_string(), 5)], None, Ok(4));
+    let bytes_a = serialize(&a, Infinite).unwrap();
+    let bytes_b = serialize(&b, Infinite).unwrap();
+
+    assert_eq!(diff(&bytes_a, &bytes_a, &descriptor).unwrap(), vec![]);
+
+    let diffs = diff(&bytes_a, &bytes_b, &descriptor).unwrap();
+    assert_eq!(diffs, vec![
+        FieldDiff {
+            path: ".1[0].1".to_string(),
+            offset_a: 4 + 8 + 9,
+            offset_b: 4 + 8 + 9,
+            a: Some(Value::U16(1)),
+            b: Some(Value::U16(5)),
+        },
+        FieldDiff {
+            path: ".1[1]".to_string(),
+            offset_a: 4 + 8 + 11,
+            offset_b: 4 + 8 + 11,
+            a: Some(Value::Seq(vec![Value::Str("y".to_string()), Value::U16(2)])),
+            b: None,
+        },
+        FieldDiff {
+            path: ".2".to_string(),
+            offset_a: 4 + 8 + 22,
+            offset_b: 4 + 8 + 11,
+            a: Some(Value::Option(Some(Box::new(Value::Bool(true))))),
+            b: Some(Value::Option(None)),
+        },
+        FieldDiff {
+            path: ".3::Ok".to_string(),
+            offset_a: 4 + 8 + 22 + 2 + 4,
+            offset_b: 4 + 8 + 11 + 1 + 4,
+            a: Some(Value::U8(3)),
+            b: Some(Value::U8(4)),
+        },
+    ]);
+
+    let c: Snapshot = (2, vec![], Some(true), Err("no".to_string()));
+    let diffs = diff(&bytes_a, &serialize(&c, Infinite).unwrap(), &descriptor).unwrap();
+    let paths: Vec<&str> = diffs.iter().map(|d| &d.path[..]).collect();
+    assert_eq!(paths, vec![".0", ".1[0]", ".1[1]", ".3"]);
+    assert_eq!(diffs[3].b, Some(Value::Variant(1, "Err".to_string(), Box::new(Value::Str("no".to_string())))));
+}
+
+#[test]
+fn test_probe() {
+    use bincode::{Value, probe};
+    use bincode::schema::trace;
+    use bincode::serde::DeserializeError;
+
+    let blob = serialize(&(7u32, "name".to_string(), Some(3u16)), Infinite).unwrap();
+    let candidates = [
+        trace::<(u64, String)>().unwrap(),
+        trace::<(u32, String)>().unwrap(),
+        trace::<(u32, String, Option<u16>)>().unwrap(),
+        trace::<(u32, u64, bool)>().unwrap(),
+    ];
+
+    let results = probe(&blob, &candidates);
+    let order: Vec<usize> = results.iter().map(|result| result.candidate).collect();
+    assert_eq!(order, vec![2, 1, 0, 3]);
+
+    assert_eq!(results[0].score, 100);
+    assert_eq!(results[0].consumed, blob.len() as u64);
+    assert_eq!(results[0].value.as_ref().and_then(|value| match *value {
+        Value::Seq(ref values) => values[1].as_str().map(|s| s.to_string()),
+        _ => None,
+    }), Some("name".to_string()));
+
+    assert!(results[1].score >= 50 && results[1].score < 100);
+    assert_eq!(results[1].consumed, 16);
+    assert!(results[1].error.is_none());
+
+    // Reading a length out of the middle of the string runs out of input,
+    // and the byte `n` is no bool.
+    assert!(results[2].score < 50 && results[2].value.is_none());
+    match results[3].error {
+        Some(DeserializeError::InvalidEncoding(_)) => {}
+        ref other => panic!("expected an invalid encoding, got {:?}", other),
+    }
+    assert!(results[3].score < results[2].score);
+
+    assert!(probe(&blob, &[]).is_empty());
+}
+
+#[test]
+fn test_deserialize_partial() {
+    use bincode::serde::deserialize_partial;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+    struct Frame {
+        function: String,
+        line: u32,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Report {
+        id: u32,
+        frames: Vec<Frame>,
+        note: String,
+    }
+
+    let report = Report {
+        id: 7,
+        frames: vec![Frame { function: "a".to_string(), line: 1 },
+                     Frame { function: "bb".to_string(), line: 2 }],
+        note: "x".to_string(),
+    };
+    let encoded = serialize(&report, Infinite).unwrap();
+    assert_eq!(deserialize_partial::<Report>(&encoded).unwrap(), report);
+
+    // Cut off in the middle of the second frame's function name.
+    let partial = deserialize_partial::<Report>(&encoded[..34]).unwrap_err();
+    assert_eq!(partial.path, ".frames[1].function");
+    assert_eq!(partial.offset, 25);
+    assert_eq!(partial.value, Some(Report {
+        id: 7,
+        frames: vec![Frame { function: "a".to_string(), line: 1 }, Frame::default()],
+        note: String::new(),
+    }));
+
+    // Nothing can be salvaged outside of a struct.
+    let partial = deserialize_partial::<Vec<u32>>(&[0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0]).unwrap_err();
+    assert_eq!(partial.path, "[1]");
+    assert_eq!(partial.offset, 12);
+    assert_eq!(partial.value, None);
+}
+
+#[test]
+fn test_deserialize_exact() {
+    use bincode::serde::{deserialize_exact, Deserializer, SliceReader};
+
+    let encoded = serialize(&(1u16, "ab".to_string()), Infinite).unwrap();
+    assert_eq!(deserialize_exact::<(u16, String)>(&encoded).unwrap(), (1, "ab".to_string()));
+
+    let mut padded = encoded.clone();
+    padded.extend(&[0, 0, 0]);
+    assert_eq!(deserialize::<(u16, String)>(&padded).unwrap(), (1, "ab".to_string()));
+    match deserialize_exact::<(u16, String)>(&padded) {
+        Err(DeserializeError::TrailingBytes { remaining: 3 }) => {}
+        other => panic!("Expecting TrailingBytes, got {:?}", other),
+    }
+
+    // A Deserializer can check the same after reading several values.
+    let mut deserializer = Deserializer::new(SliceReader::new(&padded), Infinite);
+    let _: (u16, String) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert!(deserializer.end().is_err());
+    let _: (u16, u8) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    deserializer.end().unwrap();
+}
+
+#[test]
+fn test_invalid_encoding_raw_bytes() {
+    let mut bytes: Vec<u8> = (0..20).collect();
+    bytes.push(5);
+    bytes.extend(0..20);
+    match deserialize::<(u64, u64, u32, bool)>(&bytes) {
+        Err(DeserializeError::InvalidEncoding(err)) => {
+            let (offset, raw) = err.raw_bytes().unwrap();
+            assert_eq!(offset, 5);
+            assert_eq!(raw, &bytes[5..37]);
+            assert!(err.to_string().ends_with("offset 5: 05 06 07 08 09 0a 0b 0c 0d 0e 0f 10 11 12 13 05 \
+                                               00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f"));
+        }
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+
+    match deserialize::<String>(&[0, 0, 0, 0, 0, 0, 0, 2, b'a', 0xff]) {
+        Err(DeserializeError::InvalidEncoding(err)) => {
+            assert_eq!(err.raw_bytes(), Some((0, &[0, 0, 0, 0, 0, 0, 0, 2, b'a', 0xff][..])));
+        }
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+
+    // Readers that can't look back don't keep anything.
+    match deserialize_from::<_, bool, _>(&mut &[2u8][..], Infinite) {
+        Err(DeserializeError::InvalidEncoding(err)) => assert_eq!(err.raw_bytes(), None),
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_byte_buf() {
+    use bincode::serde::{ByteBuf, Bytes, serialized_size};
+
+    let data: Vec<u8> = (0..200).map(|i| i as u8).collect();
+    let encoded = serialize(&ByteBuf::from(data.clone()), Infinite).unwrap();
+    assert_eq!(encoded, serialize(&data, Infinite).unwrap());
+    assert_eq!(serialize(&Bytes::from(&data), Infinite).unwrap(), encoded);
+    assert_eq!(serialized_size(&Bytes::from(&data)), 208);
+
+    assert_eq!(deserialize::<ByteBuf>(&encoded).unwrap(), ByteBuf::from(data.clone()));
+    assert_eq!(deserialize_from::<_, ByteBuf, _>(&mut &encoded[..], Infinite).unwrap()[..], data[..]);
+    assert_eq!(deserialize::<Vec<u8>>(&encoded).unwrap(), data);
+    assert!(deserialize_from::<_, ByteBuf, _>(&mut &encoded[..], Bounded(100)).is_err());
+
+    // A huge length doesn't get a huge allocation up front.
+    assert!(deserialize::<Vec<u64>>(&[0x10, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    assert!(deserialize::<ByteBuf>(&[0x10, 0, 0, 0, 0, 0, 0, 0]).is_err());
+}
+
+#[test]
+fn test_padded_slots() {
+    use bincode::serde::{serialize_padded, serialize_into_padded, deserialize_padded, deserialize_from_padded,
+                         SerializeError};
+
+    let record = (7u32, "seven".to_string());
+    let slot = serialize_padded(&record, 32).unwrap();
+    assert_eq!(slot.len(), 32);
+    assert_eq!(&slot[..17], &serialize(&record, Infinite).unwrap()[..]);
+    assert!(slot[17..].iter().all(|&b| b == 0));
+    assert_eq!(deserialize_padded::<(u32, String)>(&slot).unwrap(), record);
+
+    let mut file = Vec::new();
+    serialize_into_padded(&mut file, &record, 100).unwrap();
+    serialize_into_padded(&mut file, &(8u32, "eight".to_string()), 100).unwrap();
+    assert_eq!(file.len(), 200);
+    let mut reader = &file[..];
+    assert_eq!(deserialize_from_padded::<_, (u32, String)>(&mut reader, 100).unwrap(), record);
+    assert_eq!(deserialize_from_padded::<_, (u32, String)>(&mut reader, 100).unwrap().0, 8);
+    assert!(deserialize_from_padded::<_, (u32, String)>(&mut reader, 100).is_err());
+
+    match serialize_into_padded(&mut file, &record, 16) {
+        Err(SerializeError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    assert_eq!(file.len(), 200);
+    assert!(serialize_padded(&record, 16).is_err());
+
+    let mut corrupt = slot.clone();
+    corrupt[20] = 1;
+    match deserialize_padded::<(u32, String)>(&corrupt) {
+        Err(DeserializeError::InvalidEncoding(_)) => {}
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+    assert_eq!(deserialize::<(u32, String)>(&corrupt).unwrap(), record);
+
+    // A slot longer than memory can hold is refused rather than cut short.
+    if cfg!(target_pointer_width = "32") {
+        let huge = 1u64 << 32;
+        match serialize_padded(&0u8, huge) {
+            Err(SerializeError::SizeLimit) => {}
+            other => panic!("Expecting SizeLimit, got {:?}", other),
+        }
+        match deserialize_from_padded::<_, u8>(&mut &slot[..], huge) {
+            Err(DeserializeError::SizeLimit) => {}
+            other => panic!("Expecting SizeLimit, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_batch() {
+    use bincode::serde::{serialize_batch, serialize_batch_into, deserialize_batch, deserialize_batch_from};
+
+    let batch = vec![(1u16, "a".to_string()), (2, "bc".to_string()), (3, String::new())];
+    let encoded = serialize_batch(&batch, Infinite).unwrap();
+    assert_eq!(encoded, serialize(&batch, Infinite).unwrap());
+    assert_eq!(deserialize_batch::<(u16, String)>(&encoded).unwrap(), batch);
+
+    let mut writer = Vec::new();
+    serialize_batch_into(&mut writer, &batch[..2], Infinite).unwrap();
+    assert_eq!(deserialize_batch_from::<_, (u16, String), _>(&mut &writer[..], Infinite).unwrap(), &batch[..2]);
+
+    // The size limit covers the whole batch.
+    assert_eq!(encoded.len(), 41);
+    assert!(serialize_batch(&batch, Bounded(40)).is_err());
+    assert!(deserialize_batch_from::<_, (u16, String), _>(&mut &encoded[..], Bounded(40)).is_err());
+    assert!(deserialize_batch_from::<_, (u16, String), _>(&mut &encoded[..], Bounded(41)).is_ok());
+    assert_eq!(deserialize_batch::<u8>(&[0; 8]).unwrap(), vec![]);
+}
+
+#[test]
+fn test_batch_into() {
+    use bincode::serde::{serialize_batch, deserialize_batch_into};
+
+    let mut out: Vec<(u32, f64)> = Vec::new();
+    let first = serialize_batch(&[(1u32, 1.5f64), (2, 2.5), (3, 3.5)], Infinite).unwrap();
+    deserialize_batch_into(&mut &first[..], &mut out, Infinite).unwrap();
+    assert_eq!(out, vec![(1, 1.5), (2, 2.5), (3, 3.5)]);
+
+    let capacity = out.capacity();
+    let buffer = out.as_ptr();
+    let second = serialize_batch(&[(4u32, 4.5f64)], Infinite).unwrap();
+    deserialize_batch_into(&mut &second[..], &mut out, Infinite).unwrap();
+    assert_eq!(out, vec![(4, 4.5)]);
+    assert_eq!(out.capacity(), capacity);
+    assert_eq!(out.as_ptr(), buffer);
+
+    // What was read before an error is kept.
+    assert!(deserialize_batch_into(&mut &first[..30], &mut out, Infinite).is_err());
+    assert_eq!(out, vec![(1, 1.5)]);
+    assert!(deserialize_batch_into(&mut &first[..], &mut out, Bounded(20)).is_err());
+}
+
+#[test]
+fn test_buffered_reader() {
+    use std::io::{BufReader, Read};
+    use bincode::serde::deserialize_from_buffered;
+
+    let value = (7u32, "a string that is longer than the buffer".to_string(), "short".to_string(), 3.5f64);
+    let mut encoded = serialize(&value, Infinite).unwrap();
+    encoded.extend(serialize(&value, Infinite).unwrap());
+    encoded.push(0xaa);
+
+    for &capacity in &[1, 8, 16, 1024] {
+        let mut reader = BufReader::with_capacity(capacity, &encoded[..]);
+        assert_eq!(deserialize_from_buffered::<_, (u32, String, String, f64), _>(&mut reader, Infinite).unwrap(),
+                   value);
+        assert_eq!(deserialize_from_buffered::<_, (u32, String, String, f64), _>(&mut reader, Infinite).unwrap(),
+                   value);
+        // Nothing past the values was taken out of the reader.
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, vec![0xaa]);
+    }
+
+    let mut reader = BufReader::with_capacity(16, &encoded[..20]);
+    assert!(deserialize_from_buffered::<_, (u32, String, String, f64), _>(&mut reader, Infinite).is_err());
+    let mut reader = BufReader::new(&[0, 0, 0, 0, 0, 0, 0, 1, 0xff][..]);
+    assert!(deserialize_from_buffered::<_, String, _>(&mut reader, Infinite).is_err());
+}
+
+#[test]
+fn test_buffered_reader_numbers() {
+    use std::io::{self, BufRead, BufReader, Read};
+    use bincode::serde::deserialize_from_buffered;
+
+    // Only hands out bytes through its buffer, so that a number that is
+    // read any other way fails the test.
+    struct BufferOnly<'a>(&'a [u8]);
+
+    impl<'a> Read for BufferOnly<'a> {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            panic!("read a number without going through the buffer")
+        }
+    }
+
+    impl<'a> BufRead for BufferOnly<'a> {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Ok(self.0)
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.0 = &self.0[amt..];
+        }
+    }
+
+    let value = (1u8, -2i16, 3u32, -4i64, 5.5f32, 6.25f64, 'ß', true);
+    let encoded = serialize(&value, Infinite).unwrap();
+    let mut reader = BufferOnly(&encoded);
+    assert_eq!(deserialize_from_buffered::<_, (u8, i16, u32, i64, f32, f64, char, bool), _>(&mut reader, Infinite)
+                   .unwrap(),
+               value);
+    assert!(reader.0.is_empty());
+
+    // Numbers that run past the end of the buffer are read the slow way.
+    for capacity in 1..8 {
+        let mut reader = BufReader::with_capacity(capacity, &encoded[..]);
+        assert_eq!(deserialize_from_buffered::<_, (u8, i16, u32, i64, f32, f64, char, bool), _>(&mut reader,
+                                                                                                 Infinite)
+                       .unwrap(),
+                   value);
+    }
+    let mut reader = BufReader::with_capacity(4, &encoded[..5]);
+    assert!(deserialize_from_buffered::<_, (u8, i16, u32), _>(&mut reader, Infinite).is_err());
+}
+
+#[test]
+fn test_large_byte_payloads() {
+    use std::io::BufReader;
+    use bincode::serde::{ByteBuf, deserialize_from_buffered};
+
+    let payload = ByteBuf::from((0..300000).map(|i| i as u8).collect::<Vec<u8>>());
+    let encoded = serialize(&payload, Infinite).unwrap();
+    assert_eq!(deserialize_from::<_, ByteBuf, _>(&mut &encoded[..], Infinite).unwrap(), payload);
+    assert_eq!(deserialize_from_buffered::<_, ByteBuf, _>(&mut BufReader::new(&encoded[..]), Infinite).unwrap(),
+               payload);
+    assert_eq!(deserialize_from::<_, String, _>(&mut &serialize(&"x".repeat(100000), Infinite).unwrap()[..],
+                                                Infinite).unwrap().len(),
+               100000);
+
+    // A length that claims far more than there is fails without allocating it.
+    let mut lying = vec![0x00, 0x00, 0x10, 0, 0, 0, 0, 0];
+    lying.extend(&encoded[8..]);
+    assert!(deserialize_from::<_, ByteBuf, _>(&mut &lying[..], Infinite).is_err());
+    assert!(deserialize_from::<_, String, _>(&mut &lying[..], Infinite).is_err());
+}
+
+#[test]
+fn test_serialize_into_slice() {
+    use bincode::serde::{serialize_into_slice, SerializeError};
+
+    let value = (1u32, "four".to_string(), Some(2u8));
+    let mut buffer = [0xffu8; 32];
+    let written = serialize_into_slice(&value, &mut buffer).unwrap();
+    assert_eq!(written, 18);
+    assert_eq!(&buffer[..written], &serialize(&value, Infinite).unwrap()[..]);
+    assert!(buffer[written..].iter().all(|&b| b == 0xff));
+
+    let mut small = [0xffu8; 17];
+    match serialize_into_slice(&value, &mut small) {
+        Err(SerializeError::BufferTooSmall { needed: 18 }) => {}
+        other => panic!("Expecting BufferTooSmall, got {:?}", other),
+    }
+    assert!(small.iter().all(|&b| b == 0xff));
+    assert_eq!(serialize_into_slice(&(), &mut []).unwrap(), 0);
+}
+
+#[test]
+fn test_endian_wrappers() {
+    use bincode::serde::{Le, Be};
+
+    let header = (Be(0x0102u16), Le(0x03040506u32), Le(-2i16), Le(1.0f32), 7u8);
+    let encoded = serialize(&header, Infinite).unwrap();
+    assert_eq!(encoded, vec![0x01, 0x02, 0x06, 0x05, 0x04, 0x03, 0xfe, 0xff, 0x00, 0x00, 0x80, 0x3f, 7]);
+    assert_eq!(deserialize::<(Be<u16>, Le<u32>, Le<i16>, Le<f32>, u8)>(&encoded).unwrap(), header);
+    assert_eq!(serialize(&Be(5u64), Infinite).unwrap(), serialize(&5u64, Infinite).unwrap());
+    assert_eq!(*deserialize::<Le<f64>>(&serialize(&Le(-0.5f64), Infinite).unwrap()).unwrap(), -0.5);
+}
+
+#[test]
+fn test_var_and_fixed() {
+    use bincode::serde::{Var, Fixed, serialized_size};
+
+    assert_eq!(serialize(&Var(0u64), Infinite).unwrap(), vec![0]);
+    assert_eq!(serialize(&Var(127u32), Infinite).unwrap(), vec![0x7f]);
+    assert_eq!(serialize(&Var(300u16), Infinite).unwrap(), vec![0xac, 0x02]);
+    assert_eq!(serialize(&Var(-1i32), Infinite).unwrap(), vec![0x01]);
+    assert_eq!(serialize(&Var(1i32), Infinite).unwrap(), vec![0x02]);
+    assert_eq!(serialize(&Var(::std::u64::MAX), Infinite).unwrap().len(), 10);
+    assert_eq!(serialized_size(&Var(300u64)), 2);
+    assert_eq!(serialize(&Fixed(300u64), Infinite).unwrap(), serialize(&300u64, Infinite).unwrap());
+
+    for &value in &[0u64, 1, 127, 128, 300, 1 << 35, ::std::u64::MAX] {
+        let encoded = serialize(&(Var(value), Fixed(value), 9u8), Infinite).unwrap();
+        assert_eq!(deserialize::<(Var<u64>, Fixed<u64>, u8)>(&encoded).unwrap(), (Var(value), Fixed(value), 9));
+    }
+    for &value in &[0i64, -1, 63, -64, 64, ::std::i64::MIN, ::std::i64::MAX] {
+        assert_eq!(*deserialize::<Var<i64>>(&serialize(&Var(value), Infinite).unwrap()).unwrap(), value);
+    }
+
+    // Too large for the type, not in the shortest form, too long, or cut off.
+    assert!(deserialize::<Var<u16>>(&[0x80, 0x80, 0x04]).is_err());
+    assert!(deserialize::<Var<u32>>(&[0x81, 0x00]).is_err());
+    assert!(deserialize::<Var<u64>>(&[0xff; 11]).is_err());
+    assert!(deserialize::<Var<u64>>(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]).is_err());
+    assert!(deserialize::<Var<u64>>(&[0x80]).is_err());
+}
+
+#[test]
+fn test_non_zero_integers() {
+    use std::num::{NonZeroU32, NonZeroI64};
+    use bincode::codec::{self, DecodeError};
+    use bincode::serde::{Deserializer, SliceReader, Narrowing, Serializer};
+    use bincode::serde::{serialize_non_zero, deserialize_non_zero};
+
+    let value = NonZeroU32::new(7).unwrap();
+    let encoded = codec::encode(&value, Infinite).unwrap();
+    assert_eq!(encoded, vec![0, 0, 0, 7]);
+    assert_eq!(codec::decode::<NonZeroU32>(&encoded).unwrap(), value);
+    assert_eq!(codec::decode::<NonZeroI64>(&codec::encode(&-3i64, Infinite).unwrap()).unwrap().get(), -3);
+    match codec::decode::<NonZeroU32>(&[0, 0, 0, 0][..]) {
+        Err(DecodeError::InvalidEncoding(_)) => {},
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+
+    let mut encoded = vec![];
+    serialize_non_zero(&value, &mut Serializer::new(&mut encoded)).unwrap();
+    assert_eq!(encoded, vec![0, 0, 0, 7]);
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    let decoded: NonZeroU32 = deserialize_non_zero(&mut deserializer).unwrap();
+    assert_eq!(decoded, value);
+
+    let zero = [0, 0, 0, 0];
+    let mut deserializer = Deserializer::new(SliceReader::new(&zero), Infinite);
+    match deserialize_non_zero::<NonZeroU32, _>(&mut deserializer) {
+        Err(DeserializeError::InvalidEncoding(_)) => {},
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+
+    // Every u64 fits in a usize here, so saturating changes nothing.
+    let encoded = serialize(&::std::u64::MAX, Infinite).unwrap();
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    deserializer.set_narrowing(Narrowing::Saturate);
+    let decoded: usize = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(decoded as u64, ::std::u64::MAX);
+}
+
+#[test]
+fn test_c_struct_layout() {
+    use bincode::serde::{serialize_c_struct, deserialize_c_struct};
+
+    // struct header {
+    //     uint16_t kind;
+    //     uint8_t flags;
+    //     uint8_t pad;
+    //     int32_t seq;
+    //     char name[4];
+    // };
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Header {
+        kind: u16,
+        flags: u8,
+        _pad: [u8; 1],
+        seq: i32,
+        name: [u8; 4],
+    }
+
+    let header = Header { kind: 0x0102, flags: 3, _pad: [0], seq: -2, name: *b"ping" };
+    let encoded = serialize_c_struct(&header).unwrap();
+    assert_eq!(encoded, vec![1, 2, 3, 0, 0xff, 0xff, 0xff, 0xfe, b'p', b'i', b'n', b'g']);
+    assert_eq!(deserialize_c_struct::<Header>(&encoded).unwrap(), header);
+
+    // Outside of the preset, arrays keep their length.
+    assert_eq!(serialize(&[7u8; 2], Infinite).unwrap(), vec![0, 0, 0, 0, 0, 0, 0, 2, 7, 7]);
+    assert_eq!(serialize_c_struct(&[7u8; 2]).unwrap(), vec![7, 7]);
+    assert_eq!(deserialize_c_struct::<(u8, [u16; 2])>(&[1, 0, 2, 0, 3, 9]).unwrap(), (1, [2, 3]));
+}
+
+#[test]
+fn test_alloc_guard() {
+    use std::sync::Arc;
+    use bincode::serde::{Deserializer, SliceReader, MemoryBudget};
+
+    let value = vec!["abc".to_string(), "de".to_string()];
+    let encoded = serialize(&value, Infinite).unwrap();
+    let needed = 2 * ::std::mem::size_of::<String>() + 5;
+
+    let budget = Arc::new(MemoryBudget::new(2 * needed));
+    for _ in 0..2 {
+        let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+        deserializer.set_alloc_guard(budget.clone());
+        let decoded: Vec<String> = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!(decoded, value);
+    }
+    assert_eq!(budget.remaining(), 0);
+
+    // The budget is shared, so a third decode is refused.
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    deserializer.set_alloc_guard(budget.clone());
+    match serde::Deserialize::deserialize(&mut deserializer) as DeserializeResult<Vec<String>> {
+        Err(DeserializeError::SizeLimit) => {},
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+
+    budget.release(needed);
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    deserializer.set_alloc_guard(budget.clone());
+    let decoded: Vec<String> = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(decoded, value);
+}
+
+#[test]
+fn test_cancel_flag() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use bincode::serde::{Deserializer, SliceReader};
+
+    let encoded = serialize(&vec![1u32; 1000], Infinite).unwrap();
+    let flag = Arc::new(AtomicBool::new(false));
+
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    deserializer.set_cancel_flag(flag.clone());
+    let decoded: Vec<u32> = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(decoded.len(), 1000);
+
+    flag.store(true, Ordering::SeqCst);
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    deserializer.set_cancel_flag(flag.clone());
+    match serde::Deserialize::deserialize(&mut deserializer) as DeserializeResult<Vec<u32>> {
+        Err(DeserializeError::Cancelled) => {},
+        other => panic!("Expecting Cancelled, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_deadline() {
+    use std::time::{Duration, Instant};
+    use bincode::serde::{Deserializer, SliceReader};
+
+    let encoded = serialize(&vec![vec![1u8; 100]; 100], Infinite).unwrap();
+
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    deserializer.set_deadline(Instant::now() + Duration::from_secs(60));
+    let decoded: Vec<Vec<u8>> = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(decoded.len(), 100);
+
+    let mut deserializer = Deserializer::new(SliceReader::new(&encoded), Infinite);
+    deserializer.set_deadline(Instant::now());
+    match serde::Deserialize::deserialize(&mut deserializer) as DeserializeResult<Vec<Vec<u8>>> {
+        Err(DeserializeError::DeadlineExceeded) => {},
+        other => panic!("Expecting DeadlineExceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_pooled_serializer() {
+    use std::thread;
+    use bincode::frame::{FrameReader, LengthPrefix};
+    use bincode::pool::{BufferPool, PooledSerializer};
+
+    let serializer = PooledSerializer::new(BufferPool::new(2));
+    let frame = serializer.serialize(&(1u8, 2u16)).unwrap();
+    assert_eq!(&frame[..], &[0, 0, 0, 0, 0, 0, 0, 3, 1, 0, 2][..]);
+    let address = frame.as_ptr();
+    drop(frame);
+    assert_eq!(serializer.pool().available(), 1);
+
+    // The next message goes into the same buffer.
+    let frame = serializer.serialize(&(3u8, 4u16)).unwrap();
+    assert_eq!(frame.as_ptr(), address);
+    drop(frame);
+
+    let frame = serializer.serialize(&"hi").unwrap();
+    let frames: Vec<Vec<u8>> = FrameReader::new(&frame[..]).map(|frame| frame.unwrap()).collect();
+    assert_eq!(deserialize::<String>(&frames[0]).unwrap(), "hi");
+    assert_eq!(frame.into_vec().len(), 18);
+    assert_eq!(serializer.pool().available(), 0);
+
+    let short = serializer.clone().length_prefix(LengthPrefix::U32).max_message_len(4);
+    assert_eq!(&short.serialize(&7u32).unwrap()[..], &[0, 0, 0, 4, 0, 0, 0, 7][..]);
+    assert!(short.serialize(&7u64).is_err());
+
+    let threads: Vec<_> = (0..4u32).map(|i| {
+        let serializer = serializer.clone();
+        thread::spawn(move || {
+            for j in 0..100u32 {
+                let frame = serializer.serialize(&(i, j)).unwrap();
+                assert_eq!(deserialize::<(u32, u32)>(&frame[8..]).unwrap(), (i, j));
+            }
+        })
+    }).collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert!(serializer.pool().available() <= 2);
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_pooled_serializer_bytes() {
+    use bincode::pool::{BufferPool, PooledSerializer};
+
+    let serializer = PooledSerializer::new(BufferPool::new(1));
+    let frame = serializer.serialize(&(1u8, 2u16)).unwrap();
+    let address = frame.as_ptr();
+    let bytes = frame.into_bytes();
+    assert_eq!(&bytes[..], &[0, 0, 0, 0, 0, 0, 0, 3, 1, 0, 2][..]);
+    let clone = bytes.clone();
+    drop(bytes);
+    assert_eq!(serializer.pool().available(), 0);
+    drop(clone);
+    assert_eq!(serializer.pool().available(), 1);
+    assert_eq!(serializer.serialize(&0u8).unwrap().as_ptr(), address);
+}
+
+#[test]
+fn test_audit_sink() {
+    use std::sync::{Arc, Mutex};
+    use std::io::BufReader;
+    use bincode::serde::{set_audit_sink, clear_audit_sink, AuditRecord, Direction};
+    use bincode::serde::{serialize_c_struct, deserialize_c_struct, serialize_bit_packed, deserialize_bit_packed,
+                         deserialize_from_buffered};
+
+    let records: Arc<Mutex<Vec<AuditRecord>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = records.clone();
+    set_audit_sink(move |record: &AuditRecord| sink.lock().unwrap().push(record.clone()));
+
+    let encoded = serialize(&(0xdeadbeefu32, 1u8), Infinite).unwrap();
+    let decoded: (u32, u8) = deserialize_from(&mut &encoded[..], Infinite).unwrap();
+    let _: (u32, u8) = deserialize(&encoded).unwrap();
+    assert_eq!(decoded, (0xdeadbeef, 1));
+
+    // The helpers with settings of their own are audited too.
+    let flags = ([1u16, 2u16], true, false);
+    let c_struct = serialize_c_struct(&flags).unwrap();
+    assert_eq!(deserialize_c_struct::<([u16; 2], bool, bool)>(&c_struct).unwrap(), flags);
+    let packed = serialize_bit_packed(&flags).unwrap();
+    assert_eq!(deserialize_bit_packed::<([u16; 2], bool, bool)>(&packed).unwrap(), flags);
+    let plain = serialize(&flags, Infinite).unwrap();
+    let read: ([u16; 2], bool, bool) = deserialize_from_buffered(&mut BufReader::new(&plain[..]), Infinite).unwrap();
+    assert_eq!(read, flags);
+    clear_audit_sink();
+    let _: (u32, u8) = deserialize(&encoded).unwrap();
+
+    // Messages of other tests running at the same time may have been
+    // recorded too.
+    let records = records.lock().unwrap();
+    let sent = records.iter().find(|record| record.type_name == "(u32, u8)").unwrap();
+    assert_eq!((sent.direction, sent.len), (Direction::Serialized, 5));
+    let received = records.iter()
+        .filter(|record| record.type_name == "(u32, u8)" && record.hash == sent.hash)
+        .filter(|record| record.direction == Direction::Deserialized)
+        .count();
+    assert_eq!(received, 2);
+
+    let seen = |direction: Direction, len: usize| {
+        records.iter().any(|record| {
+            record.type_name == "([u16; 2], bool, bool)" && record.direction == direction && record.len == len as u64
+        })
+    };
+    assert!(seen(Direction::Serialized, c_struct.len()));
+    assert!(seen(Direction::Deserialized, c_struct.len()));
+    assert!(seen(Direction::Serialized, packed.len()));
+    assert!(seen(Direction::Deserialized, packed.len()));
+    assert!(seen(Direction::Deserialized, plain.len()));
+}
+
+#[test]
+fn test_tagged_messages() {
+    use bincode::serde::{Tagged, Registry, TagCollision, serialize_tagged, deserialize_tagged};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Ping(u32);
+    impl Tagged for Ping { const TAG: u32 = 1; }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Echo { text: String }
+    impl Tagged for Echo { const TAG: u32 = 2; }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Clash(u8);
+    impl Tagged for Clash { const TAG: u32 = 2; }
+
+    let ping = serialize_tagged(&Ping(7), Infinite).unwrap();
+    assert_eq!(ping, vec![0, 0, 0, 1, 0, 0, 0, 7]);
+    assert_eq!(deserialize_tagged::<Ping>(&ping).unwrap(), Ping(7));
+    assert!(deserialize_tagged::<Echo>(&ping).is_err());
+
+    let mut registry = Registry::new();
+    registry.register(|ping: Ping| format!("pong {}", ping.0)).unwrap();
+    registry.register(|echo: Echo| echo.text).unwrap();
+    match registry.register(|clash: Clash| clash.0.to_string()) {
+        Err(TagCollision { tag: 2, .. }) => {},
+        other => panic!("Expecting TagCollision, got {:?}", other),
+    }
+
+    let echo = serialize_tagged(&Echo { text: "hi".to_string() }, Infinite).unwrap();
+    assert_eq!(registry.deserialize(&ping).unwrap(), "pong 7");
+    assert_eq!(registry.deserialize(&echo).unwrap(), "hi");
+    match registry.deserialize(&[0, 0, 0, 9]) {
+        Err(DeserializeError::InvalidEncoding(_)) => {},
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_registry_trait_objects() {
+    use bincode::serde::{Registry, TaggedDeserializer, TagCollision};
+
+    trait Message {
+        fn describe(&self) -> String;
+    }
+
+    struct Ping(u32);
+    impl Message for Ping {
+        fn describe(&self) -> String { format!("ping {}", self.0) }
+    }
+
+    struct Quit;
+    impl Message for Quit {
+        fn describe(&self) -> String { "quit".to_string() }
+    }
+
+    fn decode_ping(deserializer: &mut TaggedDeserializer) -> DeserializeResult<Box<Message>> {
+        let seq: u32 = try!(serde::Deserialize::deserialize(deserializer));
+        Ok(Box::new(Ping(seq)))
+    }
+
+    let mut registry: Registry<Box<Message>> = Registry::new();
+    registry.register_decoder(1, "Ping", decode_ping).unwrap();
+    registry.register_decoder(2, "Quit", |_: &mut TaggedDeserializer| Ok(Box::new(Quit) as Box<Message>)).unwrap();
+    match registry.register_decoder(1, "Pong", decode_ping) {
+        Err(TagCollision { tag: 1, existing: "Ping", new: "Pong" }) => {},
+        other => panic!("Expecting TagCollision, got {:?}", other),
+    }
+
+    let ping = serialize(&(1u32, 9u32), Infinite).unwrap();
+    let quit = serialize(&2u32, Infinite).unwrap();
+    assert_eq!(registry.deserialize(&ping).unwrap().describe(), "ping 9");
+    assert_eq!(registry.deserialize(&quit).unwrap().describe(), "quit");
+
+    assert!(registry.unregister(2));
+    assert!(!registry.contains(2));
+    assert!(registry.deserialize(&quit).is_err());
+}
+
+#[test]
+fn test_schema_fingerprint() {
+    use bincode::schema::{trace, fingerprint, serialize_fingerprinted, deserialize_fingerprinted};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Old { id: u32, name: String }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Renamed { id: u32, name: String }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Widened { id: u64, name: String }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Reordered { name: String, id: u32 }
+
+    let old = fingerprint::<Old>().unwrap();
+    assert_eq!(old, trace::<Old>().unwrap().fingerprint());
+    assert_eq!(old, fingerprint::<Renamed>().unwrap());
+    assert!(old != fingerprint::<Widened>().unwrap());
+    assert!(old != fingerprint::<Reordered>().unwrap());
+
+    let encoded = serialize_fingerprinted(&Old { id: 1, name: "a".to_string() }, Infinite).unwrap();
+    assert_eq!(deserialize_fingerprinted::<Renamed>(&encoded).unwrap(), Renamed { id: 1, name: "a".to_string() });
+    match deserialize_fingerprinted::<Widened>(&encoded) {
+        Err(DeserializeError::SchemaMismatch { expected, found }) => {
+            assert_eq!(found, old);
+            assert_eq!(expected, fingerprint::<Widened>().unwrap());
+        }
+        other => panic!("Expecting SchemaMismatch, got {:?}", other),
+    }
+
+    // Plain types have fingerprints too.
+    assert!(fingerprint::<(u32, String)>().unwrap() != fingerprint::<(String, u32)>().unwrap());
+    let encoded = serialize_fingerprinted(&(7u8, 9u16), Infinite).unwrap();
+    assert_eq!(deserialize_fingerprinted::<(u8, u16)>(&encoded).unwrap(), (7, 9));
+    assert!(deserialize_fingerprinted::<(u16, u8)>(&encoded).is_err());
+}
+
+#[test]
+fn test_schema_compatible() {
+    use bincode::schema::{trace, compatible};
+
+    #[derive(Serialize, Deserialize)]
+    struct V1 { id: u32, name: String, kind: KindV1 }
+    #[derive(Serialize, Deserialize)]
+    enum KindV1 { A, B(u8) }
+
+    #[derive(Serialize, Deserialize)]
+    struct V2 { id: u32, title: String, kind: KindV2 }
+    #[derive(Serialize, Deserialize)]
+    enum KindV2 { A, B(u8), C }
+
+    #[derive(Serialize, Deserialize)]
+    struct Swapped { id: u32, kind: KindV1, name: String }
+
+    #[derive(Serialize, Deserialize)]
+    struct Wider { id: u64, name: String, kind: KindV1 }
+
+    let v1 = trace::<V1>().unwrap();
+    assert_eq!(compatible(&v1, &v1), Ok(()));
+    // Renaming a field and adding a variant at the end keep old messages readable.
+    assert_eq!(compatible(&v1, &trace::<V2>().unwrap()), Ok(()));
+    assert!(compatible(&trace::<V2>().unwrap(), &v1).is_err());
+
+    let err = compatible(&v1, &trace::<Swapped>().unwrap()).unwrap_err();
+    assert_eq!(err.reason, "field `name` moved from position 1 to 2");
+    let err = compatible(&v1, &trace::<Wider>().unwrap()).unwrap_err();
+    assert_eq!((&err.path[..], &err.reason[..]), (".id", "u32 became u64"));
+
+    let err = compatible(&trace::<Vec<(u8, u16)>>().unwrap(), &trace::<Vec<(u8, u32)>>().unwrap()).unwrap_err();
+    assert_eq!(err.path, "[].1");
+}
+
+#[test]
+fn test_schema_text() {
+    use bincode::schema::{trace, export_descriptor};
+
+    #[derive(Serialize, Deserialize)]
+    struct Header { id: u32, name: String, flags: u8 }
+
+    let descriptor = trace::<Header>().unwrap();
+    assert_eq!(descriptor.to_json(), export_descriptor::<Header>().unwrap());
+    let text = descriptor.to_text();
+    assert!(text.contains("root: Header (13+)"));
+    assert!(text.contains("         0     4  id: u32\n"));
+    assert!(text.contains("         4    8+  name: string (u64 length, utf8)\n"));
+    assert!(text.contains("       12+     1  flags: u8\n"));
+
+    let text = trace::<(u16, Option<[u8; 2]>)>().unwrap().to_text();
+    assert!(text.contains("root: (u16, option<[u8; 2] (u64 length)> (u8 tag)) (3+)"));
+}
+
+#[test]
+fn test_char_is_the_same_in_every_frontend() {
+    for &c in &['a', '\u{e9}', '\u{20ac}', '\u{1F600}'] {
+        the_same(c);
+        let serialized = serialize(&c, Infinite).unwrap();
+        assert_eq!(serialized.len(), c.len_utf8());
+        assert_eq!(bincode::codec::encode(&c, Infinite).unwrap(), serialized);
+        assert_eq!(bincode::codec::decode::<char>(&serialized).unwrap(), c);
+    }
+    assert_eq!(serialize(&Some('z'), Infinite).unwrap(), vec![1, b'z']);
+}
+
+#[test]
+fn test_legacy_chars() {
+    use bincode::serde::{Deserializer, SliceReader};
+
+    // A `(char, usize)` as serde used to write it, with the char as a string.
+    let old = vec![0, 0, 0, 0, 0, 0, 0, 2, 0xc3, 0xa9, 0, 0, 0, 0, 0, 0, 0, 7];
+    let mut deserializer = Deserializer::new(SliceReader::new(&old), Infinite);
+    deserializer.set_legacy_chars(true);
+    let value: (char, usize) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(value, ('\u{e9}', 7));
+
+    // More than one char in the string is not a char.
+    let two = vec![0, 0, 0, 0, 0, 0, 0, 2, b'a', b'b'];
+    let mut deserializer = Deserializer::new(SliceReader::new(&two), Infinite);
+    deserializer.set_legacy_chars(true);
+    let result: DeserializeResult<char> = serde::Deserialize::deserialize(&mut deserializer);
+    assert!(result.is_err());
+
+    // What rustc_serialize has always written is read as it is.
+    let encoded = encode(&('\u{e9}', 7usize), Infinite).unwrap();
+    assert_eq!(deserialize::<(char, usize)>(&encoded).unwrap(), ('\u{e9}', 7));
+}
+
+#[cfg(feature = "text")]
+#[test]
+fn test_hex_and_base64() {
+    use bincode::text::{serialize_hex, deserialize_hex, serialize_base64, deserialize_base64,
+                        serialize_base64_into, deserialize_base64_from, Base64};
+
+    let value = (1u16, "hi".to_string());
+    let hex = serialize_hex(&value, Infinite).unwrap();
+    assert_eq!(hex, "0001000000000000000268 69".replace(" ", ""));
+    assert_eq!(deserialize_hex::<(u16, String)>(&hex.to_uppercase()).unwrap(), value);
+    assert!(deserialize_hex::<(u16, String)>("00zz").is_err());
+
+    // 12 bytes need no padding, 13 need two characters of it.
+    assert_eq!(serialize_base64(&value, Base64::Standard, Infinite).unwrap(), "AAEAAAAAAAAAAmhp");
+    let value = (value.0, value.1, 0xfbu8);
+    let standard = serialize_base64(&value, Base64::Standard, Infinite).unwrap();
+    let url_safe = serialize_base64(&value, Base64::UrlSafe, Infinite).unwrap();
+    assert_eq!(standard, "AAEAAAAAAAAAAmhp+w==");
+    assert_eq!(url_safe, "AAEAAAAAAAAAAmhp-w");
+    assert_eq!(deserialize_base64::<(u16, String, u8)>(&standard, Base64::Standard).unwrap(), value);
+    assert_eq!(deserialize_base64::<(u16, String, u8)>(&url_safe, Base64::UrlSafe).unwrap(), value);
+    assert!(deserialize_base64::<(u16, String, u8)>(&url_safe, Base64::Standard).is_err());
+
+    // A large message streamed through, wrapped over lines, with more text
+    // after it.
+    let big: Vec<u32> = (0..1000).collect();
+    let mut text = Vec::new();
+    serialize_base64_into(&mut text, &big, Base64::Standard, Infinite).unwrap();
+    let wrapped: Vec<u8> = text.chunks(76).collect::<Vec<_>>().join(&b'\n');
+    let mut reader = &wrapped[..];
+    let read: Vec<u32> = deserialize_base64_from(&mut reader, Base64::Standard, Infinite).unwrap();
+    assert_eq!(read, big);
+}
+
+#[test]
+fn test_json_value() {
+    use std::collections::BTreeMap;
+    use bincode::schema::{to_json_value, from_json_value};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Price { Market, Limit(u32) }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Order {
+        id: u64,
+        side: char,
+        price: Price,
+        tags: Vec<String>,
+        note: Option<String>,
+        attrs: BTreeMap<String, i8>,
+        levels: BTreeMap<u16, bool>,
+    }
+
+    let mut attrs = BTreeMap::new();
+    attrs.insert("a\"b".to_string(), -3);
+    let mut levels = BTreeMap::new();
+    levels.insert(5, true);
+    let order = Order {
+        id: 7,
+        side: 'B',
+        price: Price::Limit(100),
+        tags: vec!["x".to_string()],
+        note: None,
+        attrs: attrs,
+        levels: levels,
+    };
+    let json = to_json_value(&order).unwrap();
+    assert_eq!(json, r#"{"id":7,"side":"B","price":{"Limit":100},"tags":["x"],"note":null,"attrs":{"a\"b":-3},"levels":[[5,true]]}"#);
+    assert_eq!(from_json_value::<Order>(&json).unwrap(), order);
+
+    let fixture = r#"{
+        "id": 8, "side": "S", "price": "Market", "tags": [], "note": "caf\u00e9",
+        "attrs": [["k", 1]], "levels": []
+    }"#;
+    let order: Order = from_json_value(fixture).unwrap();
+    assert_eq!(order.price, Price::Market);
+    assert_eq!(order.note, Some("caf\u{e9}".to_string()));
+    assert_eq!(order.attrs.get("k"), Some(&1));
+
+    let err = from_json_value::<Order>(&fixture.replace("\"id\": 8", "\"id\": -8")).unwrap_err();
+    assert!(err.to_string().contains("-8 is not a valid u64"));
+    let err = from_json_value::<Order>(&fixture.replace("\"Market\"", "\"Stop\"")).unwrap_err();
+    assert!(err.to_string().contains("Price has no variant named `Stop`"));
+    assert!(from_json_value::<Order>(&fixture.replace("\"levels\": []", "\"extra\": 1")).is_err());
+}
+
+#[test]
+fn test_bit_packing() {
+    use bincode::serde::{serialize_bit_packed, deserialize_bit_packed, Bits, U3, U4};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Reading {
+        sensor: U4<u8>,
+        channel: U3<u8>,
+        calibrated: bool,
+        value: u16,
+        alarm: bool,
+        level: Bits<u16, 10>,
+    }
+
+    let reading = Reading {
+        sensor: Bits(0xa),
+        channel: Bits(5),
+        calibrated: true,
+        value: 0x0102,
+        alarm: true,
+        level: Bits(0x3ff),
+    };
+    let encoded = serialize_bit_packed(&reading).unwrap();
+    assert_eq!(encoded, vec![0b1010_1011, 1, 2, 0xff, 0b1110_0000]);
+    assert_eq!(deserialize_bit_packed::<Reading>(&encoded).unwrap(), reading);
+
+    // Outside of bit packing, the fields are written the usual way.
+    assert_eq!(serialize(&reading, Infinite).unwrap(), vec![10, 5, 1, 1, 2, 1, 3, 0xff]);
+
+    // Each struct starts on a whole byte.
+    let pair = ((true, U3::<u8>::from(2)), Reading { alarm: false, ..reading });
+    let encoded = serialize_bit_packed(&pair).unwrap();
+    assert_eq!(encoded[0], 0b1010_0000);
+    assert_eq!(encoded[1], 0b1010_1011);
+    assert_eq!(deserialize_bit_packed::<((bool, U3<u8>), Reading)>(&encoded).unwrap(), pair);
+
+    // A value has to fit in its bits, and unused bits have to be zero.
+    assert!(serialize_bit_packed(&U4::<u8>::from(16)).is_err());
+    assert!(deserialize_bit_packed::<(bool, u8)>(&[0b1100_0000, 7]).is_err());
+}
+
+#[test]
+fn test_rle() {
+    use std::sync::Arc;
+    use bincode::serde::{Rle, Deserializer, SliceReader, MemoryBudget};
+
+    let tiles = Rle(vec![7u8, 7, 7, 7, 2, 2, 7]);
+    let encoded = serialize(&tiles, Infinite).unwrap();
+    assert_eq!(encoded, serialize(&vec![(7u8, 4u64), (2, 2), (7, 1)], Infinite).unwrap());
+    assert_eq!(deserialize::<Rle<u8>>(&encoded).unwrap(), tiles);
+
+    let words = Rle(vec!["a".to_string(), "a".to_string(), "b".to_string()]);
+    assert_eq!(deserialize::<Rle<String>>(&serialize(&words, Infinite).unwrap()).unwrap(), words);
+    let nested = Rle(vec![Rle(vec![1u16; 3]), Rle(vec![1u16; 3]), Rle(vec![])]);
+    assert_eq!(deserialize::<Rle<Rle<u16>>>(&serialize(&nested, Infinite).unwrap()).unwrap(), nested);
+    assert_eq!(serialize(&Rle(Vec::<u32>::new()), Infinite).unwrap(), vec![0; 8]);
+
+    // Each run is charged as if its value were written out every time.
+    let big = serialize(&Rle(vec![5u32; 1000]), Infinite).unwrap();
+    assert_eq!(big.len(), 8 + 4 + 8);
+    assert!(deserialize_from::<_, Rle<u32>, _>(&mut &big[..], Bounded(4000 + 16)).is_ok());
+    assert!(deserialize_from::<_, Rle<u32>, _>(&mut &big[..], Bounded(3999 + 16)).is_err());
+    let bomb = serialize(&vec![(5u32, ::std::u64::MAX)], Infinite).unwrap();
+    assert!(deserialize_from::<_, Rle<u32>, _>(&mut &bomb[..], Bounded(1 << 20)).is_err());
+    let empty_run = serialize(&vec![(5u32, 0u64)], Infinite).unwrap();
+    assert!(deserialize::<Rle<u32>>(&empty_run).is_err());
+
+    // A value that takes no bytes is still charged a byte for each repeat.
+    let nothing = serialize(&vec![((), ::std::u64::MAX)], Infinite).unwrap();
+    assert!(deserialize_from::<_, Rle<()>, _>(&mut &nothing[..], Bounded(1 << 20)).is_err());
+
+    // The repeats take memory, which the `AllocGuard` is asked for.
+    let budget = Arc::new(MemoryBudget::new(3000));
+    let mut deserializer = Deserializer::new(SliceReader::new(&big), Infinite);
+    deserializer.set_alloc_guard(budget.clone());
+    match serde::Deserialize::deserialize(&mut deserializer) as DeserializeResult<Rle<u32>> {
+        Err(DeserializeError::SizeLimit) => {},
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_reduced_precision_floats() {
+    use bincode::serde::{F16Of, F32Of};
+
+    fn f16_bits(value: f64) -> Vec<u8> {
+        serialize(&F16Of(value), Infinite).unwrap()
+    }
+
+    assert_eq!(f16_bits(1.0), vec![0x3c, 0x00]);
+    assert_eq!(f16_bits(-2.0), vec![0xc0, 0x00]);
+    assert_eq!(f16_bits(0.1), vec![0x2e, 0x66]);
+    assert_eq!(f16_bits(65504.0), vec![0x7b, 0xff]);
+    assert_eq!(f16_bits(65520.0), vec![0x7c, 0x00]);
+    assert_eq!(f16_bits(2f64.powi(-24)), vec![0x00, 0x01]);
+    assert_eq!(f16_bits(2f64.powi(-26)), vec![0x00, 0x00]);
+    assert_eq!(f16_bits(::std::f64::NEG_INFINITY), vec![0xfc, 0x00]);
+    assert_eq!(serialize(&F16Of(1.0f32), Infinite).unwrap(), vec![0x3c, 0x00]);
+
+    let decoded: F16Of<f32> = deserialize(&[0x2e, 0x66]).unwrap();
+    assert!((decoded.0 - 0.1).abs() < 0.0001);
+    let decoded: F16Of<f64> = deserialize(&[0x7e, 0x00]).unwrap();
+    assert!(decoded.0.is_nan());
+    for &value in &[0.5f32, -1024.0, 2f32.powi(-20), 0.0] {
+        let decoded: F16Of<f32> = deserialize(&serialize(&F16Of(value), Infinite).unwrap()).unwrap();
+        assert_eq!(decoded.0, value);
+        assert!(F16Of::exact(value).is_some());
+    }
+    assert!(F16Of::exact(0.1f32).is_none());
+    assert!(F16Of::exact(70000.0f64).is_none());
+
+    let encoded = serialize(&F32Of(0.1f64), Infinite).unwrap();
+    assert_eq!(encoded, serialize(&0.1f32, Infinite).unwrap());
+    assert_eq!(deserialize::<F32Of<f64>>(&encoded).unwrap().0, 0.1f32 as f64);
+    assert!(F32Of::exact(0.1).is_none());
+    assert!(F32Of::exact(0.25).is_some());
+}
+
+#[test]
+fn test_delta_seq() {
+    use bincode::serde::DeltaSeq;
+
+    let times = DeltaSeq(vec![1_000_000_000u64, 1_000_000_010, 1_000_000_010, 1_000_000_005]);
+    let encoded = serialize(&times, Infinite).unwrap();
+    // The length, then 1_000_000_000 in five bytes, then 10, 0, and -5.
+    assert_eq!(encoded.len(), 8 + 5 + 1 + 1 + 1);
+    assert_eq!(&encoded[13..], &[20, 0, 9]);
+    assert_eq!(deserialize::<DeltaSeq<u64>>(&encoded).unwrap(), times);
+
+    let extremes = DeltaSeq(vec![0u64, ::std::u64::MAX, 0, 1 << 63]);
+    assert_eq!(deserialize::<DeltaSeq<u64>>(&serialize(&extremes, Infinite).unwrap()).unwrap(), extremes);
+    let signed = DeltaSeq(vec![-3i16, ::std::i16::MAX, ::std::i16::MIN, 0]);
+    assert_eq!(deserialize::<DeltaSeq<i16>>(&serialize(&signed, Infinite).unwrap()).unwrap(), signed);
+
+    // Values that don't fit the type are an error, not a wrap around.
+    let wide = serialize(&DeltaSeq(vec![70000u32]), Infinite).unwrap();
+    assert!(deserialize::<DeltaSeq<u16>>(&wide).is_err());
+}
+
+#[test]
+fn test_length_overflow() {
+    use bincode::codec::{self, DecodeError};
+
+    // A length that would take the count of bytes read past u64::MAX.  A
+    // bounded size limit turns it down first, as a SizeLimit.
+    match deserialize::<String>(&[0xff; 8]) {
+        Err(DeserializeError::LengthOverflow) => {}
+        other => panic!("Expecting LengthOverflow, got {:?}", other),
+    }
+    match decode::<String>(&[0xff; 8]) {
+        Err(DecodingError::LengthOverflow) => {}
+        other => panic!("Expecting LengthOverflow, got {:?}", other),
+    }
+    match codec::decode::<String>(&[0xff; 8]) {
+        Err(DecodeError::LengthOverflow) => {}
+        other => panic!("Expecting LengthOverflow, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_unknown_variant_fallback() {
+    use bincode::serde::{Deserializer, SliceReader, UnknownVariants};
+
+    // A newer server has added two variants to the message.
+    #[derive(Serialize)]
+    enum NewMessage {
+        Ping,
+        Pong,
+        Unknown,
+        Shutdown,
+        Resize(u32, u32),
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    enum Message {
+        Ping,
+        Pong,
+        Unknown,
+    }
+
+    fn read<T: serde::Deserialize>(bytes: &[u8], unknown: UnknownVariants) -> DeserializeResult<T> {
+        let mut deserializer = Deserializer::new(SliceReader::new(bytes), Infinite);
+        deserializer.set_unknown_variants(unknown);
+        serde::Deserialize::deserialize(&mut deserializer)
+    }
+
+    let batch = serialize(&vec![NewMessage::Ping, NewMessage::Shutdown, NewMessage::Pong], Infinite).unwrap();
+    assert!(deserialize::<Vec<Message>>(&batch).is_err());
+    assert!(read::<Vec<Message>>(&batch, UnknownVariants::Error).is_err());
+    assert_eq!(read::<Vec<Message>>(&batch, UnknownVariants::Fallback("Unknown")).unwrap(),
+               vec![Message::Ping, Message::Unknown, Message::Pong]);
+    assert!(read::<Vec<Message>>(&batch, UnknownVariants::Fallback("Other")).is_err());
+
+    // The data of an unknown variant is left unread, which is fine at the
+    // end of the message.
+    let resize = serialize(&NewMessage::Resize(640, 480), Infinite).unwrap();
+    assert_eq!(read::<Message>(&resize, UnknownVariants::Fallback("Unknown")).unwrap(), Message::Unknown);
+}
+
+#[test]
+fn test_unknown_fields() {
+    use bincode::serde::{Unknown, serialized_size};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct OrderV2 {
+        id: u32,
+        amount: u32,
+        note: String,
+        tags: Vec<String>,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Order {
+        id: u32,
+        amount: u32,
+        newer_fields: Unknown,
+    }
+
+    let newer = OrderV2 { id: 1, amount: 250, note: "gift".to_string(), tags: vec!["rush".to_string()] };
+    let encoded = serialize(&newer, Infinite).unwrap();
+    let order: Order = deserialize(&encoded).unwrap();
+    assert_eq!((order.id, order.amount), (1, 250));
+    assert_eq!(order.newer_fields.len(), encoded.len() - 8);
+
+    // Passing it on loses nothing.
+    let passed_on = serialize(&order, Infinite).unwrap();
+    assert_eq!(passed_on, encoded);
+    assert_eq!(serialized_size(&order), encoded.len() as u64);
+    assert_eq!(deserialize::<OrderV2>(&passed_on).unwrap(), newer);
+
+    // A message with nothing new leaves it empty.
+    let older = serialize(&(1u32, 250u32), Infinite).unwrap();
+    assert_eq!(deserialize::<Order>(&older).unwrap().newer_fields, Unknown(vec![]));
+
+    // What it takes up counts against the size limit.
+    assert!(deserialize_from::<_, Order, _>(&mut &encoded[..], Bounded(12)).is_err());
+}
+
+#[test]
+fn test_container() {
+    use bincode::container::{ContainerWriter, ContainerReader, ContainerError};
+    use std::io::Cursor;
+
+    let mut writer = ContainerWriter::new(Vec::new()).unwrap();
+    assert_eq!(writer.write_section("meta", &("level 3".to_string(), 1200u32)).unwrap(), 0);
+    assert_eq!(writer.write_section("world", &vec![7u16; 1000]).unwrap(), 1);
+    match writer.write_section("meta", &0u8) {
+        Err(ContainerError::DuplicateSection(ref name)) if name == "meta" => {}
+        other => panic!("expected DuplicateSection, got {:?}", other),
+    }
+    let file = writer.finish().unwrap();
+    assert_eq!(&file[..12], b"bincont\0\0\0\0\x01");
+
+    let mut reader = ContainerReader::open(Cursor::new(&file[..])).unwrap();
+    assert_eq!(reader.len(), 2);
+    assert_eq!(reader.names(), vec!["meta", "world"]);
+    assert_eq!(reader.position("world"), Some(1));
+    assert_eq!(reader.section::<Vec<u16>>("world").unwrap(), vec![7u16; 1000]);
+    assert_eq!(reader.section::<(String, u32)>("meta").unwrap(), ("level 3".to_string(), 1200));
+    assert_eq!(reader.section_at::<(String, u32)>(0).unwrap(), ("level 3".to_string(), 1200));
+    match reader.section::<u8>("sky") {
+        Err(ContainerError::NoSection) => {}
+        other => panic!("expected NoSection, got {:?}", other),
+    }
+    match reader.section_at::<u8>(2) {
+        Err(ContainerError::NoSection) => {}
+        other => panic!("expected NoSection, got {:?}", other),
+    }
+    // A section can't be read past its own end, into the next one.
+    match reader.section::<(String, u32, u16)>("meta") {
+        Err(ContainerError::Deserialize(_)) => {}
+        other => panic!("expected Deserialize, got {:?}", other),
+    }
+
+    match ContainerReader::open(Cursor::new(&b"not a container"[..])) {
+        Err(ContainerError::NotAContainer(_)) => {}
+        other => panic!("expected NotAContainer, got {:?}", other.map(|_| ())),
+    }
+    match ContainerReader::open(Cursor::new(&file[..6])) {
+        Err(ContainerError::NotAContainer(_)) => {}
+        other => panic!("expected NotAContainer, got {:?}", other.map(|_| ())),
+    }
+    let mut damaged = file.clone();
+    let len = damaged.len();
+    damaged[len - 1] ^= 0xff;
+    match ContainerReader::open(Cursor::new(&damaged[..])) {
+        Err(ContainerError::NotAContainer(_)) | Err(ContainerError::Deserialize(_)) => {}
+        other => panic!("expected NotAContainer, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_indexed_vec() {
+    use bincode::serde::{serialize_indexed, deserialize_nth};
+
+    let values = vec!["a".to_string(), "bcd".to_string(), String::new(), "ef".to_string()];
+    let (bytes, offsets) = serialize_indexed(&values, Infinite).unwrap();
+    assert_eq!(bytes, serialize(&values, Infinite).unwrap());
+    assert_eq!(offsets, vec![8, 17, 28, 36]);
+    for (i, value) in values.iter().enumerate() {
+        assert_eq!(&deserialize_nth::<String>(&bytes, &offsets, i).unwrap(), value);
+    }
+    assert_eq!(deserialize::<Vec<String>>(&bytes).unwrap(), values);
+
+    // Offsets that don't belong to the bytes are caught.
+    match deserialize_nth::<String>(&bytes, &[8, 18, 28, 36], 0) {
+        Err(DeserializeError::TrailingBytes { remaining: 1 }) => {}
+        other => panic!("expected TrailingBytes, got {:?}", other),
+    }
+    match deserialize_nth::<String>(&bytes[..30], &offsets, 2) {
+        Err(DeserializeError::InvalidEncoding(_)) => {}
+        other => panic!("expected InvalidEncoding, got {:?}", other),
+    }
+
+    match serialize_indexed(&values, Bounded(20)) {
+        Err(bincode::serde::SerializeError::SizeLimit) => {}
+        other => panic!("expected SizeLimit, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_patch_field() {
+    use bincode::schema::{trace, patch_field, PatchError};
+
+    type Record = (String, (u32, u32), Vec<(u16, i32)>, Option<f64>, Result<u8, String>);
+
+    let descriptor = trace::<Record>().unwrap();
+    let record: Record = ("big".to_string(), (7, 9), vec![(1, -1), (2, -2)], None, Ok(3));
+    let mut bytes = serialize(&record, Infinite).unwrap();
+
+    patch_field(&mut bytes, ".1.0", &8u32, &descriptor).unwrap();
+    patch_field(&mut bytes, ".2[1].1", &-20i32, &descriptor).unwrap();
+    patch_field(&mut bytes, ".4::Ok", &4u8, &descriptor).unwrap();
+    assert_eq!(deserialize::<Record>(&bytes).unwrap(),
+               ("big".to_string(), (8, 9), vec![(1, -1), (2, -20)], None, Ok(4)));
+
+    // A whole struct of fixed-size fields can be patched at once.
+    patch_field(&mut bytes, ".1", &(10u32, 11u32), &descriptor).unwrap();
+    assert_eq!(deserialize::<Record>(&bytes).unwrap().1, (10, 11));
+
+    match patch_field(&mut bytes, ".0", &"small", &descriptor) {
+        Err(PatchError::NotFixedSize(ref path)) if path == ".0" => {}
+        other => panic!("expected NotFixedSize, got {:?}", other),
+    }
+    match patch_field(&mut bytes, ".2", &vec![(0u16, 0i32)], &descriptor) {
+        Err(PatchError::NotFixedSize(_)) => {}
+        other => panic!("expected NotFixedSize, got {:?}", other),
+    }
+    match patch_field(&mut bytes, ".1.0", &8u64, &descriptor) {
+        Err(PatchError::WrongLayout(_)) => {}
+        other => panic!("expected WrongLayout, got {:?}", other),
+    }
+    match patch_field(&mut bytes, ".2[2].0", &0u16, &descriptor) {
+        Err(PatchError::NotPresent(_)) => {}
+        other => panic!("expected NotPresent, got {:?}", other),
+    }
+    match patch_field(&mut bytes, ".3", &0u8, &descriptor) {
+        Err(PatchError::NotFixedSize(_)) => {}
+        other => panic!("expected NotFixedSize, got {:?}", other),
+    }
+    match patch_field(&mut bytes, ".4::Err", &0u8, &descriptor) {
+        Err(PatchError::NotPresent(_)) => {}
+        other => panic!("expected NotPresent, got {:?}", other),
+    }
+    for path in &[".5", ".1.x", "[0]", ".4::Maybe", "1.0", ".2[x]"] {
+        match patch_field(&mut bytes, path, &0u8, &descriptor) {
+            Err(PatchError::NoSuchField(_)) => {}
+            other => panic!("expected NoSuchField for {}, got {:?}", path, other),
+        }
+    }
+    let len = bytes.len();
+    match patch_field(&mut bytes[..len - 1], ".4::Ok", &0u8, &descriptor) {
+        Err(PatchError::Deserialize(_)) => {}
+        other => panic!("expected Deserialize, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_update() {
+    use bincode::schema::{Update, PatchError, serialize_update, deserialize_update};
+
+    type Account = (u64, String, Option<(String, u32)>, Vec<u16>);
+
+    let mut update = Update::<Account>::new().unwrap();
+    assert!(update.is_empty());
+    update.set(".1", &"renamed").unwrap();
+    update.set(".2.1", &7u32).unwrap();
+    update.set(".3[1]", &9u16).unwrap();
+    update.set(".1", &"renamed again").unwrap();
+    assert_eq!(update.paths(), vec![".1", ".2.1", ".3[1]"]);
+
+    match update.set(".0", &1u8) {
+        Err(PatchError::WrongLayout(ref path)) if path == ".0" => {}
+        other => panic!("expected WrongLayout, got {:?}", other),
+    }
+    match update.set(".4", &1u8) {
+        Err(PatchError::NoSuchField(_)) => {}
+        other => panic!("expected NoSuchField, got {:?}", other),
+    }
+    assert_eq!(update.len(), 3);
+
+    let bytes = serialize_update(&update, Infinite).unwrap();
+    let update = deserialize_update::<Account>(&bytes).unwrap();
+
+    let mut account: Account = (1, "old".to_string(), Some(("x".to_string(), 2)), vec![1, 2, 3]);
+    update.apply(&mut account).unwrap();
+    assert_eq!(account, (1, "renamed again".to_string(), Some(("x".to_string(), 7)), vec![1, 9, 3]));
+
+    // Fields the receiver's copy doesn't have leave it as it was.
+    let mut account: Account = (1, "old".to_string(), None, vec![1, 2, 3]);
+    match update.apply(&mut account) {
+        Err(PatchError::NotPresent(ref path)) if path == ".2.1" => {}
+        other => panic!("expected NotPresent, got {:?}", other),
+    }
+    assert_eq!(account, (1, "old".to_string(), None, vec![1, 2, 3]));
+
+    match deserialize_update::<(u64, String)>(&bytes) {
+        Err(PatchError::Deserialize(DeserializeError::SchemaMismatch { .. })) => {}
+        other => panic!("expected SchemaMismatch, got {:?}", other.map(|update| update.len())),
+    }
+}
+
+#[test]
+fn test_throttled_read() {
+    use bincode::serde::ThrottledRead;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+    use std::time::{Duration, Instant};
+
+    let message = serialize(&vec![7u8; 292], Infinite).unwrap();
+
+    // 100 bytes go through at once, and the other 200 take 200ms.
+    let mut reader = ThrottledRead::new(&message[..], 1000);
+    reader.set_burst(100);
+    let start = Instant::now();
+    let decoded: Vec<u8> = deserialize_from(&mut reader, Infinite).unwrap();
+    assert_eq!(decoded, vec![7u8; 292]);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+
+    let flag = Arc::new(AtomicBool::new(true));
+    let mut reader = ThrottledRead::new(&message[..], 1000);
+    reader.set_burst(100);
+    reader.set_cancel_flag(flag);
+    match deserialize_from::<_, Vec<u8>, _>(&mut reader, Infinite) {
+        Err(DeserializeError::Cancelled) => {}
+        other => panic!("expected Cancelled, got {:?}", other),
+    }
+
+    let mut reader = ThrottledRead::new(&message[..], 100);
+    reader.set_burst(10);
+    reader.set_deadline(Instant::now() + Duration::from_millis(200));
+    let start = Instant::now();
+    match deserialize_from::<_, Vec<u8>, _>(&mut reader, Infinite) {
+        Err(DeserializeError::DeadlineExceeded) => {}
+        other => panic!("expected DeadlineExceeded, got {:?}", other),
+    }
+    // It gives up as soon as it knows the next byte can't make it, rather
+    // than sleeping past the deadline.
+    assert!(start.elapsed() < Duration::from_millis(700));
+}
+
+#[test]
+fn test_slice_cursor() {
+    use bincode::{SliceCursor, Checkpoint};
+    use bincode::frame::{LengthPrefix, FrameError};
+    use std::io::Read;
+
+    let mut buffer = serialize(&(1u8, "one".to_string()), Infinite).unwrap();
+    buffer.extend(serialize(&(2u8, 7u32), Infinite).unwrap());
+    let mut cursor = SliceCursor::new(&buffer);
+
+    assert_eq!(cursor.peek::<u8>().unwrap(), 1);
+    assert_eq!(cursor.offset(), 0);
+    let start: Checkpoint = cursor.checkpoint();
+    assert_eq!(cursor.deserialize::<(u8, String)>().unwrap(), (1, "one".to_string()));
+    assert_eq!(cursor.offset(), 12);
+    assert_eq!(cursor.peek::<u8>().unwrap(), 2);
+
+    // A copy reads ahead without moving the original.
+    let mut ahead = cursor;
+    assert_eq!(ahead.deserialize::<(u8, u32)>().unwrap(), (2, 7));
+    assert!(ahead.is_empty());
+    assert_eq!(cursor.remaining().len(), 5);
+
+    // A failed read doesn't move the cursor either.
+    assert!(cursor.deserialize::<(u8, u64)>().is_err());
+    assert_eq!(cursor.offset(), 12);
+
+    cursor.rewind(start);
+    assert_eq!(cursor.split_off(1), Some(&[1u8][..]));
+    let mut rest = Vec::new();
+    cursor.read_to_end(&mut rest).unwrap();
+    assert_eq!(&rest[..], &buffer[1..]);
+    assert_eq!(cursor.split_off(1), None);
+
+    let frames = [0, 0, 0, 2, 10, 11, 0, 0, 0, 0, 0, 0, 0, 3, 12];
+    let mut cursor = SliceCursor::new(&frames);
+    assert_eq!(cursor.split_off_message(LengthPrefix::U32).unwrap(), Some(&[10u8, 11][..]));
+    assert_eq!(cursor.split_off_message(LengthPrefix::U32).unwrap(), Some(&[][..]));
+    match cursor.split_off_message(LengthPrefix::U32) {
+        Err(FrameError::Truncated) => {}
+        other => panic!("expected Truncated, got {:?}", other),
+    }
+    assert_eq!(cursor.offset(), 10);
+    let mut cursor = SliceCursor::new(&frames[..10]);
+    cursor.split_off(10);
+    assert_eq!(cursor.split_off_message(LengthPrefix::U64).unwrap(), None);
+}
+
+#[test]
+fn test_peek() {
+    use bincode::serde::{peek_variant_index, peek_prefix, PeekRead};
+    use std::io::Read;
+
+    type Request = Result<(u16, String), (u16, u64)>;
+    let request: Request = Err((7, 9));
+    let message = serialize(&request, Infinite).unwrap();
+
+    assert_eq!(peek_variant_index(&message).unwrap(), 1);
+    assert_eq!(peek_prefix::<(u32, u16)>(&message).unwrap(), (1, 7));
+    assert!(peek_variant_index(&message[..3]).is_err());
+
+    let mut stream = message.clone();
+    stream.extend_from_slice(&[0xff, 0xff]);
+    let mut reader = PeekRead::new(&stream[..]);
+    assert_eq!(reader.peek_variant_index().unwrap(), 1);
+    assert_eq!(reader.peeked(), &[0, 0, 0, 1]);
+    assert_eq!(reader.peek_prefix::<(u32, u16), _>(Infinite).unwrap(), (1, 7));
+    assert_eq!(reader.peeked(), &[0, 0, 0, 1, 0, 7]);
+    match reader.peek_prefix::<(u32, u16, u64), _>(Bounded(8)) {
+        Err(DeserializeError::SizeLimit) => {}
+        other => panic!("expected SizeLimit, got {:?}", other),
+    }
+
+    // The whole message is still there to be read.
+    let request: Request = deserialize_from(&mut reader, Infinite).unwrap();
+    assert_eq!(request, Err((7, 9)));
+    assert!(reader.peeked().is_empty());
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, vec![0xff, 0xff]);
+
+    let mut reader = PeekRead::new(&stream[..]);
+    reader.peek_variant_index().unwrap();
+    let (peeked, rest) = reader.into_parts();
+    assert_eq!(peeked, vec![0, 0, 0, 1]);
+    assert_eq!(rest.len(), stream.len() - 4);
+}
+
+#[test]
+fn test_format_constants() {
+    use bincode::format::{Layout, StaticLayout, StandardLayout, FixedLayout, LegacyLayout, variant_tag, TagOverflow};
+    use bincode::serde::Config;
+
+    let standard = Config::standard();
+    assert_eq!(serialize(&"", Infinite).unwrap().len(), standard.len_prefix_bytes());
+    assert_eq!(serialize(&0usize, Infinite).unwrap().len(), standard.usize_bytes());
+    assert_eq!(serialize(&Ok::<(), ()>(()), Infinite).unwrap().len(), standard.variant_tag_bytes());
+    assert_eq!(serialize(&None::<u8>, Infinite).unwrap().len(), standard.option_tag_bytes());
+    assert_eq!(serialize(&true, Infinite).unwrap().len() * 8, standard.bool_bits());
+    assert_eq!(serialize(&'\u{10ffff}', Infinite).unwrap().len(), standard.max_char_bytes());
+    assert_eq!(encode(&"", Infinite).unwrap().len(), StandardLayout::LEN_PREFIX_BYTES);
+    assert_eq!(encode(&None::<u8>, Infinite).unwrap().len(), StandardLayout::OPTION_TAG_BYTES);
+
+    // The widths follow the settings of the layout.
+    let array = [7u8; 3];
+    assert_eq!(serialize(&array, Infinite).unwrap().len(), standard.array_len_bytes() + 3);
+    assert_eq!(Config::fixed().serialize(&array).unwrap().len(), Config::fixed().array_len_bytes() + 3);
+    assert_eq!(FixedLayout::ARRAY_LEN_BYTES, 0);
+    assert_eq!(Config::legacy().serialize(&'\u{10ffff}').unwrap().len(), LegacyLayout::MAX_CHAR_BYTES);
+    assert_eq!(Config::compact().bool_bits(), 1);
+    assert_eq!(Config::compact().serialize(&(true, false, true)).unwrap().len(), 1);
+
+    assert_eq!(variant_tag(7), Ok(7));
+    if ::std::mem::size_of::<usize>() > 4 {
+        let index = ::std::u32::MAX as usize + 1;
+        assert_eq!(variant_tag(index), Err(TagOverflow { index: index }));
+    }
+}
+
+#[test]
+fn test_human_readable() {
+    use bincode::serde::{Config, Serializer, Deserializer, SliceReader, NoObserver};
+
+    let mut bytes = Vec::new();
+    assert!(!Serializer::new(&mut bytes).is_human_readable());
+    assert!(!Deserializer::new(SliceReader::new(&[]), Infinite).is_human_readable());
+
+    let mut config = Config::standard();
+    config.set_human_readable(true);
+    let mut serializer = Serializer::with_layout(&mut bytes, config);
+    assert!(serializer.is_human_readable());
+    serde::Serialize::serialize(&(1u16, 'x'), &mut serializer).unwrap();
+    let deserializer = Deserializer::with_layout(SliceReader::new(&[]), Infinite, config);
+    assert!(deserializer.is_human_readable());
+
+    let mut serializer = Serializer::new(&mut bytes);
+    config.configure_serializer(&mut serializer);
+    assert!(serializer.is_human_readable());
+    let mut deserializer = Deserializer::new(SliceReader::new(&[]), Infinite);
+    config.configure_deserializer(&mut deserializer);
+    assert!(deserializer.is_human_readable());
+
+    // bincode itself writes the same bytes either way.
+    assert_eq!(config.serialize(&(1u16, 'x')).unwrap(), serialize(&(1u16, 'x'), Infinite).unwrap());
+    assert_eq!(bytes, serialize(&(1u16, 'x'), Infinite).unwrap());
+
+    // Code that holds the bincode `Serializer` and `Deserializer` can pick
+    // its representation by the flag.
+    fn write_seconds(serializer: &mut Serializer<Vec<u8>, Config>, secs: u64) {
+        if serializer.is_human_readable() {
+            serde::Serializer::serialize_str(serializer, &format!("{}s", secs)).unwrap();
+        } else {
+            serde::Serializer::serialize_u64(serializer, secs).unwrap();
+        }
+    }
+
+    fn read_seconds(deserializer: &mut Deserializer<SliceReader, Infinite, NoObserver, Config>) -> u64 {
+        if deserializer.is_human_readable() {
+            let text: String = serde::Deserialize::deserialize(deserializer).unwrap();
+            text[..text.len() - 1].parse().unwrap()
+        } else {
+            serde::Deserialize::deserialize(deserializer).unwrap()
+        }
+    }
+
+    for &human_readable in &[false, true] {
+        let mut config = Config::standard();
+        config.set_human_readable(human_readable);
+        let mut bytes = Vec::new();
+        write_seconds(&mut Serializer::with_layout(&mut bytes, config), 90);
+        let expected = if human_readable {
+            serialize(&"90s", Infinite).unwrap()
+        } else {
+            serialize(&90u64, Infinite).unwrap()
+        };
+        assert_eq!(bytes, expected);
+        let mut deserializer = Deserializer::with_layout(SliceReader::new(&bytes), Infinite, config);
+        assert_eq!(read_seconds(&mut deserializer), 90);
+    }
+}
+
+#[test]
+fn test_config_presets() {
+    use bincode::serde::{Config, SerializeError};
+
+    let value = (7u16, 'a', [1u8, 2], true, false, true);
+    let standard = Config::standard().serialize(&value).unwrap();
+    assert_eq!(standard, serialize(&value, Infinite).unwrap());
+    assert_eq!(standard, vec![0, 7, b'a', 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 0, 1]);
+
+    let compact = Config::compact().serialize(&value).unwrap();
+    assert_eq!(compact, vec![0, 7, b'a', 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 0b1010_0000]);
+
+    let legacy = Config::legacy().serialize(&value).unwrap();
+    assert_eq!(legacy, vec![0, 7, 0, 0, 0, 0, 0, 0, 0, 1, b'a', 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 0, 1]);
+
+    let fixed = Config::fixed().serialize(&value).unwrap();
+    assert_eq!(fixed, vec![0, 7, b'a', 1, 2, 1, 0, 1]);
+
+    for &(config, ref bytes) in &[(Config::standard(), &standard), (Config::compact(), &compact),
+                                  (Config::legacy(), &legacy), (Config::fixed(), &fixed)] {
+        assert_eq!(config.deserialize::<(u16, char, [u8; 2], bool, bool, bool)>(bytes).unwrap(), value);
+        let mut reader = &bytes[..];
+        assert_eq!(config.deserialize_from::<_, (u16, char, [u8; 2], bool, bool, bool)>(&mut reader).unwrap(), value);
+    }
+    assert_eq!(Config::default(), Config::standard());
+
+    let limited = Config::fixed().limit(7);
+    assert_eq!(limited.size_limit(), Some(7));
+    let mut writer = Vec::new();
+    match limited.serialize_into(&mut writer, &value) {
+        Err(SerializeError::SizeLimit) => {}
+        other => panic!("expected a size limit error, got {:?}", other),
+    }
+    assert!(writer.is_empty());
+    match limited.deserialize::<(u16, char, [u8; 2], bool, bool, bool)>(&fixed) {
+        Err(DeserializeError::SizeLimit) => {}
+        other => panic!("expected a size limit error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_config_limit_fails_fast() {
+    use std::cell::Cell;
+    use bincode::serde::{Config, SerializeError, serialize_c_struct};
+
+    // Writes a thousand `u64`s, counting how many it got to write.
+    struct Counted(Cell<usize>);
+
+    impl serde::Serialize for Counted {
+        fn serialize<S: serde::Serializer>(&self, serializer: &mut S) -> Result<(), S::Error> {
+            for _ in 0..1000 {
+                self.0.set(self.0.get() + 1);
+                try!(serializer.serialize_u64(0));
+            }
+            Ok(())
+        }
+    }
+
+    let value = Counted(Cell::new(0));
+    match Config::fixed().limit(16).serialize(&value) {
+        Err(SerializeError::SizeLimit) => {}
+        other => panic!("expected a size limit error, got {:?}", other),
+    }
+    assert_eq!(value.0.get(), 3);
+
+    let value = Counted(Cell::new(0));
+    assert_eq!(serialize_c_struct(&value).unwrap().len(), 8000);
+    assert_eq!(value.0.get(), 1000);
+}
+
+#[test]
+fn test_static_layouts() {
+    use bincode::serde::{Config, Deserializer, SliceReader, serialize_with_layout, deserialize_with_layout};
+    use bincode::serde::{StandardLayout, CompactLayout, LegacyLayout, FixedLayout};
+
+    type Value = (u16, char, [u8; 2], bool, bool, bool);
+    let value: Value = (7u16, 'a', [1u8, 2], true, false, true);
+
+    let standard = serialize_with_layout(&value, StandardLayout, Infinite).unwrap();
+    assert_eq!(standard, Config::standard().serialize(&value).unwrap());
+    let compact = serialize_with_layout(&value, CompactLayout, Infinite).unwrap();
+    assert_eq!(compact, Config::compact().serialize(&value).unwrap());
+    let legacy = serialize_with_layout(&value, LegacyLayout, Infinite).unwrap();
+    assert_eq!(legacy, Config::legacy().serialize(&value).unwrap());
+    let fixed = serialize_with_layout(&value, FixedLayout, Infinite).unwrap();
+    assert_eq!(fixed, Config::fixed().serialize(&value).unwrap());
+
+    assert_eq!(deserialize_with_layout::<Value, _>(&standard, StandardLayout).unwrap(), value);
+    assert_eq!(deserialize_with_layout::<Value, _>(&compact, CompactLayout).unwrap(), value);
+    assert_eq!(deserialize_with_layout::<Value, _>(&legacy, LegacyLayout).unwrap(), value);
+    assert_eq!(deserialize_with_layout::<Value, _>(&fixed, FixedLayout).unwrap(), value);
+
+    let mut deserializer = Deserializer::with_layout(SliceReader::new(&compact), Bounded(14), CompactLayout);
+    let read: Value = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(read, value);
+    assert_eq!(deserializer.bytes_read(), 14);
+
+    assert_eq!(Config::from(CompactLayout), Config::compact());
+    assert_eq!(Config::from(FixedLayout), Config::fixed());
+    match serialize_with_layout(&value, FixedLayout, Bounded(7)) {
+        Err(bincode::serde::SerializeError::SizeLimit) => {}
+        other => panic!("expected a size limit error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_small_reads() {
+    use bincode::serde::{Deserializer, SegmentedReader};
+
+    type Value = (u8, i16, u32, f64, char, String, i8, usize);
+    let value: Value = (1, -2, 3, 4.5, 'é', "small".to_string(), -6, 7);
+    let bytes = serialize(&value, Infinite).unwrap();
+
+    for split in 0..bytes.len() + 1 {
+        let segments = [&bytes[..split], &bytes[split..]];
+        let mut deserializer = Deserializer::new(SegmentedReader::new(&segments), Bounded(bytes.len() as u64));
+        let read: Value = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!(read, value);
+        assert_eq!(deserializer.bytes_read(), bytes.len() as u64);
+
+        let mut reader = &bytes[..split];
+        match deserialize_from::<_, Value, _>(&mut reader, Infinite) {
+            Err(DeserializeError::IoError(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => {}
+            other if split == bytes.len() => assert_eq!(other.unwrap(), value),
+            other => panic!("expected an unexpected EOF error at {}, got {:?}", split, other),
+        }
+    }
+}
+
+#[test]
+fn test_dyn_io() {
+    use std::io::{Read, Write};
+    use bincode::serde::{SerializerDyn, SerializeError, serialize_into_dyn, deserialize_from_dyn};
+
+    let value = (1u32, "dyn".to_string(), vec![2u16, 3], Some('x'));
+    let expected = serialize(&value, Infinite).unwrap();
+
+    let mut buffer = Vec::new();
+    {
+        let writer: &mut Write = &mut buffer;
+        serialize_into_dyn(writer, &value, Infinite).unwrap();
+        serialize_into_dyn(writer, &7u8, Bounded(1)).unwrap();
+        match serialize_into_dyn(writer, &value, Bounded(expected.len() as u64 - 1)) {
+            Err(SerializeError::SizeLimit) => {}
+            other => panic!("expected a size limit error, got {:?}", other),
+        }
+    }
+    assert_eq!(&buffer[..expected.len()], &expected[..]);
+    assert_eq!(&buffer[expected.len()..], &[7]);
+
+    let mut written = Vec::new();
+    serde::Serialize::serialize(&value, &mut SerializerDyn::new(&mut written)).unwrap();
+    assert_eq!(written, expected);
+
+    let mut input = &buffer[..];
+    {
+        let reader: &mut Read = &mut input;
+        let read: (u32, String, Vec<u16>, Option<char>) = deserialize_from_dyn(reader, Infinite).unwrap();
+        assert_eq!(read, value);
+        match deserialize_from_dyn::<u16, _>(reader, Bounded(1)) {
+            Err(DeserializeError::SizeLimit) => {}
+            other => panic!("expected a size limit error, got {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_chaos_io() {
+    use std::io::{self, BufReader};
+    use bincode::serde::{SerializeError, deserialize_from_buffered};
+    use bincode::test_util::{ChaosRead, ChaosWrite};
+
+    let value = (1u32, "chaos".to_string(), vec![2u64, 3, 4], Some('é'), (-5i16, 6.5f64));
+    let bytes = serialize(&value, Infinite).unwrap();
+
+    for seed in 0..64 {
+        let mut reader = ChaosRead::new(&bytes[..], seed);
+        assert_eq!(deserialize_from(&mut reader, Infinite).ok(), Some(value.clone()));
+        assert_eq!(reader.bytes_read(), bytes.len() as u64);
+
+        let mut reader = BufReader::with_capacity(3, ChaosRead::new(&bytes[..], seed));
+        assert_eq!(deserialize_from_buffered(&mut reader, Infinite).ok(), Some(value.clone()));
+
+        let mut writer = ChaosWrite::new(Vec::new(), seed);
+        bincode::serde::serialize_into(&mut writer, &value, Infinite).unwrap();
+        assert_eq!(writer.into_inner(), bytes);
+    }
+
+    let mut reader = ChaosRead::new(&bytes[..], 1);
+    reader.set_eof_after(bytes.len() as u64 - 3);
+    match deserialize_from::<_, (u32, String, Vec<u64>, Option<char>, (i16, f64)), _>(&mut reader, Infinite) {
+        Err(DeserializeError::IoError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+        other => panic!("expected an unexpected EOF error, got {:?}", other),
+    }
+
+    let mut reader = ChaosRead::new(&bytes[..], 1);
+    reader.set_interrupted(0);
+    reader.set_would_block(1);
+    match deserialize_from::<_, u32, _>(&mut reader, Infinite) {
+        Err(DeserializeError::IoError(ref err)) if err.kind() == io::ErrorKind::WouldBlock => {}
+        other => panic!("expected a would block error, got {:?}", other),
+    }
+
+    let mut writer = ChaosWrite::new(Vec::new(), 1);
+    writer.set_full_after(4);
+    match bincode::serde::serialize_into(&mut writer, &value, Infinite) {
+        Err(SerializeError::IoError(ref err)) if err.kind() == io::ErrorKind::WriteZero => {}
+        other => panic!("expected a write zero error, got {:?}", other),
+    }
+    assert_eq!(writer.bytes_written(), 4);
+}
+
+#[test]
+fn test_retry_interrupted() {
+    use std::io::Read;
+    use bincode::serde::{BincodeRead, IoReader, Deserializer, deserialize_from_with_defaults};
+    use bincode::test_util::ChaosRead;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct Record {
+        id: u32,
+        name: String,
+        tag: char,
+        scores: Vec<i64>,
+    }
+
+    let record = Record { id: 7, name: "interrupted".to_string(), tag: 'ß', scores: vec![-1, 2, 3] };
+    let bytes = serialize(&record, Infinite).unwrap();
+
+    for seed in 0..64 {
+        let mut chaos = ChaosRead::new(&bytes[..], seed);
+        chaos.set_interrupted(2);
+        let mut reader = IoReader::new(chaos);
+        assert!(!reader.is_at_end().unwrap());
+        let mut read = vec![0; bytes.len()];
+        reader.read_exact(&mut read).unwrap();
+        assert_eq!(read, bytes);
+        assert!(reader.is_at_end().unwrap());
+
+        let mut chaos = ChaosRead::new(&bytes[..], seed);
+        chaos.set_interrupted(2);
+        let mut deserializer = Deserializer::new(IoReader::new(chaos), Bounded(bytes.len() as u64));
+        let read: Record = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!(read, record);
+        assert_eq!(deserializer.bytes_read(), bytes.len() as u64);
+
+        let mut chaos = ChaosRead::new(&bytes[..], seed);
+        chaos.set_interrupted(2);
+        assert_eq!(deserialize_from_with_defaults::<_, Record, _>(&mut chaos, Infinite).unwrap(), record);
+    }
+}
+
+#[test]
+fn test_peeked_byte_keeps_error() {
+    use std::io::{self, Read};
+    use bincode::serde::{BincodeRead, IoReader};
+
+    // Hands out one byte, fails once, and then ends.
+    struct FailOnce {
+        calls: u32,
+    }
+
+    impl Read for FailOnce {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.calls += 1;
+            match self.calls {
+                1 => {
+                    buf[0] = 7;
+                    Ok(1)
+                }
+                2 => Err(io::Error::new(io::ErrorKind::Other, "broken")),
+                _ => Ok(0),
+            }
+        }
+    }
+
+    let mut reader = IoReader::new(FailOnce { calls: 0 });
+    assert!(!reader.is_at_end().unwrap());
+    let mut buf = [0; 2];
+    match reader.read_exact(&mut buf) {
+        Err(ref err) if err.kind() == io::ErrorKind::Other => {}
+        other => panic!("expected the reader's error, got {:?}", other),
+    }
+    assert_eq!(buf[0], 7);
+}
+
+#[test]
+fn test_scatter() {
+    use bincode::serde::{Attachment, ByteBuf, SerializeError, serialize_scatter};
+    use bincode::test_util::ChaosWrite;
+
+    let first = vec![0xabu8; 1000];
+    let second = b"second".to_vec();
+    let value = (7u32, Attachment(&first), "between".to_string(), Attachment(&second), Attachment(&[]), 9u8);
+    let expected = serialize(&value, Infinite).unwrap();
+
+    let scatter = serialize_scatter(&value, Infinite).unwrap();
+    assert_eq!(scatter.len(), expected.len());
+    assert_eq!(scatter.header().len(), expected.len() - first.len() - second.len());
+    let offsets: Vec<(usize, usize)> = scatter.placeholders().iter().map(|p| (p.offset, p.len)).collect();
+    assert_eq!(offsets, vec![(12, 1000), (35, 6), (43, 0)]);
+
+    let attachments: [&[u8]; 3] = [&first, &second, &[]];
+    assert_eq!(scatter.gather(&attachments), expected);
+    let slices = scatter.io_slices(&attachments);
+    assert_eq!(slices.iter().map(|slice| slice.len()).sum::<usize>(), expected.len());
+    for seed in 0..16 {
+        let mut writer = ChaosWrite::new(Vec::new(), seed);
+        scatter.write_to(&mut writer, &attachments).unwrap();
+        assert_eq!(writer.into_inner(), expected);
+    }
+
+    let read: (u32, ByteBuf, String, ByteBuf, ByteBuf, u8) = deserialize(&expected).unwrap();
+    assert_eq!(&read.1[..], &first[..]);
+    assert_eq!(&read.3[..], &second[..]);
+
+    match serialize_scatter(&value, Bounded(expected.len() as u64 - 1)) {
+        Err(SerializeError::SizeLimit) => {}
+        other => panic!("expected a size limit error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_detached_attachments() {
+    use bincode::serde::{Attachment, AttachmentBuf, Deserializer, DeserializeError, IoReader, SegmentedReader};
+    use bincode::serde::deserialize_detached;
+
+    let first = vec![0xcdu8; 300];
+    let value = (1u16, Attachment(&first), Attachment(b"tiny"), 2u8);
+    let bytes = serialize(&value, Infinite).unwrap();
+
+    let read: (u16, AttachmentBuf, AttachmentBuf, u8) = deserialize_detached(&bytes, 16).unwrap();
+    assert_eq!(read.1, AttachmentBuf::Detached { offset: 10, len: 300 });
+    assert_eq!(read.1.resolve(&bytes), Some(&first[..]));
+    assert_eq!(read.2, AttachmentBuf::Inline(b"tiny".to_vec()));
+    assert_eq!(read.3, 2);
+    assert_eq!(read.1.resolve(&bytes[..309]), None);
+
+    // Without a threshold, the bytes are copied in, and an inline
+    // attachment is written back the way it was read.
+    let copied: (u16, AttachmentBuf, AttachmentBuf, u8) = deserialize(&bytes).unwrap();
+    assert_eq!(copied.1, AttachmentBuf::Inline(first.clone()));
+    assert_eq!(serialize(&copied, Infinite).unwrap(), bytes);
+    assert!(serialize(&read, Infinite).is_err());
+
+    let segments = [&bytes[..5], &bytes[5..100], &bytes[100..]];
+    let mut deserializer = Deserializer::new(SegmentedReader::new(&segments), Infinite);
+    deserializer.set_detach_threshold(16);
+    let segmented: (u16, AttachmentBuf, AttachmentBuf, u8) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(segmented, read);
+
+    let mut deserializer = Deserializer::new(IoReader::new(&bytes[..]), Infinite);
+    deserializer.set_detach_threshold(16);
+    let streamed: (u16, AttachmentBuf, AttachmentBuf, u8) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(streamed, read);
+
+    // Bytes that are left in the input still count towards the size limit.
+    let mut deserializer = Deserializer::new(IoReader::new(&bytes[..]), Bounded(100));
+    deserializer.set_detach_threshold(16);
+    let limited: Result<(u16, AttachmentBuf, AttachmentBuf, u8), _> = serde::Deserialize::deserialize(&mut deserializer);
+    match limited {
+        Err(DeserializeError::SizeLimit) => {}
+        other => panic!("expected a size limit error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_checkpoint_rollback() {
+    use bincode::serde::{Deserializer, DeserializeError, SliceReader};
+
+    let bytes = serialize(&(3u8, "hello".to_string()), Infinite).unwrap();
+    let mut deserializer = Deserializer::new(SliceReader::new(&bytes), Bounded(bytes.len() as u64));
+
+    let checkpoint = deserializer.checkpoint();
+    let wrong: Result<(u8, u64, u64), _> = serde::Deserialize::deserialize(&mut deserializer);
+    assert!(wrong.is_err());
+    deserializer.rollback(checkpoint.clone());
+    assert_eq!(deserializer.bytes_read(), 0);
+
+    // The size limit is back to what it was, so the whole value still
+    // fits in it.
+    let right: (u8, String) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(right, (3, "hello".to_string()));
+    deserializer.end().unwrap();
+
+    deserializer.rollback(checkpoint);
+    let again: (u8, String) = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(again, right);
+
+    let mut deserializer = Deserializer::new(SliceReader::new(&bytes), Bounded(bytes.len() as u64 - 1));
+    let checkpoint = deserializer.checkpoint();
+    let limited: Result<(u8, String), _> = serde::Deserialize::deserialize(&mut deserializer);
+    match limited {
+        Err(DeserializeError::SizeLimit) => {}
+        other => panic!("expected a size limit error, got {:?}", other),
+    }
+    deserializer.rollback(checkpoint);
+    let first: u8 = serde::Deserialize::deserialize(&mut deserializer).unwrap();
+    assert_eq!(first, 3);
+}
+
+#[test]
+fn test_collect_str() {
+    use std::cell::Cell;
+    use std::fmt;
+    use bincode::serde::{CollectStr, SerializeError, serialized_size, serialize_key};
+
+    struct Version(Vec<u32>);
+
+    impl fmt::Display for Version {
+        fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            for (i, part) in self.0.iter().enumerate() {
+                if i > 0 {
+                    try!(fmt.write_str("."));
+                }
+                try!(write!(fmt, "{}", part));
+            }
+            Ok(())
+        }
+    }
+
+    let version = Version(vec![1, 22, 333]);
+    let value = (7u8, CollectStr(&version), CollectStr(""));
+    let bytes = serialize(&value, Infinite).unwrap();
+    assert_eq!(bytes, serialize(&(7u8, "1.22.333", ""), Infinite).unwrap());
+    assert_eq!(serialized_size(&value), bytes.len() as u64);
+    assert_eq!(serialize(&value, Bounded(bytes.len() as u64)).unwrap(), bytes);
+    let read: (u8, String, String) = deserialize(&bytes).unwrap();
+    assert_eq!(read.1, "1.22.333");
+
+    // Text that changes between the two times it is formatted can't be
+    // written after its length.
+    struct Fickle(Cell<u32>);
+
+    impl fmt::Display for Fickle {
+        fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+            self.0.set(self.0.get() + 1);
+            write!(fmt, "{}", self.0.get() * 10)
+        }
+    }
+
+    match serialize(&CollectStr(&Fickle(Cell::new(8))), Infinite) {
+        Err(SerializeError::Custom(_)) => {}
+        other => panic!("expected a custom error, got {:?}", other),
+    }
+
+    // Any other `Serializer` is given the whole string.
+    assert_eq!(serialize_key(&CollectStr(&version)).unwrap(), serialize_key(&"1.22.333").unwrap());
+}
+
+#[test]
+fn test_map_seq_adapters() {
+    use std::collections::BTreeMap;
+    use bincode::schema::{trace, Format};
+    use bincode::serde::{MapAsSeq, SeqAsMap};
+
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), 1u32);
+    map.insert("b".to_string(), 2);
+    let pairs = vec![("a".to_string(), 1u32), ("b".to_string(), 2)];
+
+    // The bytes are the same; only the shape serde sees changes.
+    let bytes = serialize(&map, Infinite).unwrap();
+    assert_eq!(serialize(&MapAsSeq(map.clone()), Infinite).unwrap(), bytes);
+    assert_eq!(serialize(&pairs, Infinite).unwrap(), bytes);
+    assert_eq!(serialize(&SeqAsMap(pairs.clone()), Infinite).unwrap(), bytes);
+
+    let read: MapAsSeq<BTreeMap<String, u32>> = deserialize(&bytes).unwrap();
+    assert_eq!(read.into_inner(), map);
+    let read: MapAsSeq<HashMap<String, u32>> = deserialize(&bytes).unwrap();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read.get("b"), Some(&2));
+    let read: SeqAsMap<String, u32> = deserialize(&bytes).unwrap();
+    assert_eq!(read.0, pairs);
+
+    // Pairs keep their order and their duplicates.
+    let dup = SeqAsMap(vec![(2u8, 'x'), (1, 'y'), (2, 'z')]);
+    let read: SeqAsMap<u8, char> = deserialize(&serialize(&dup, Infinite).unwrap()).unwrap();
+    assert_eq!(read, dup);
+    let read: MapAsSeq<BTreeMap<u8, char>> = deserialize(&serialize(&dup, Infinite).unwrap()).unwrap();
+    assert_eq!(read.get(&2), Some(&'z'));
+
+    assert_eq!(trace::<MapAsSeq<BTreeMap<String, u32>>>().unwrap().root,
+               Format::Seq(Box::new(Format::Tuple(vec![Format::Str, Format::U32]))));
+    assert_eq!(trace::<SeqAsMap<String, u32>>().unwrap().root,
+               Format::Map(Box::new(Format::Str), Box::new(Format::U32)));
+}
+
+#[test]
+fn test_struct_length_mismatch() {
+    use bincode::serde::Var;
+
+    // Asks for `ask` elements of a pair, and calls `end` only if `end` is set.
+    struct Pair<T> {
+        ask: usize,
+        end: bool,
+        marker: ::std::marker::PhantomData<T>,
+    }
+
+    impl<T: serde::Deserialize> serde::de::Visitor for Pair<T> {
+        type Value = Vec<T>;
+
+        fn visit_seq<V: serde::de::SeqVisitor>(&mut self, mut visitor: V) -> Result<Vec<T>, V::Error> {
+            let mut values = Vec::new();
+            for _ in 0..self.ask {
+                values.extend(try!(visitor.visit()));
+            }
+            if self.end {
+                try!(visitor.end());
+            }
+            Ok(values)
+        }
+    }
+
+    fn read(bytes: &[u8], ask: usize, end: bool) -> DeserializeResult<Vec<u8>> {
+        let mut deserializer = bincode::serde::Deserializer::new(bincode::serde::SliceReader::new(bytes), Infinite);
+        let visitor = Pair { ask: ask, end: end, marker: ::std::marker::PhantomData };
+        serde::Deserializer::deserialize_tuple(&mut deserializer, 2, visitor)
+    }
+
+    let bytes = [1u8, 2, 3];
+    assert_eq!(read(&bytes, 2, true).unwrap(), vec![1, 2]);
+    match read(&bytes, 3, true) {
+        Err(DeserializeError::StructLengthMismatch { expected: 2, found: 3 }) => {}
+        other => panic!("expected a length mismatch, got {:?}", other),
+    }
+    match read(&bytes, 1, false) {
+        Err(DeserializeError::StructLengthMismatch { expected: 2, found: 1 }) => {}
+        other => panic!("expected a length mismatch, got {:?}", other),
+    }
+    // Calling `end` early doesn't make the rest of the elements go away.
+    match read(&bytes, 1, true) {
+        Err(DeserializeError::StructLengthMismatch { expected: 2, found: 1 }) => {}
+        other => panic!("expected a length mismatch, got {:?}", other),
+    }
+    let mut deserializer = bincode::serde::Deserializer::new(bincode::serde::SliceReader::new(&bytes), Infinite);
+    let visitor = Pair::<u8> { ask: 1, end: true, marker: ::std::marker::PhantomData };
+    match serde::Deserializer::deserialize_struct(&mut deserializer, "Point", &["x", "y"], visitor) {
+        Err(DeserializeError::StructLengthMismatch { expected: 2, found: 1 }) => {}
+        other => panic!("expected a length mismatch, got {:?}", other),
+    }
+    // Only a `Var` may stop early, as it reads only as many bytes as it
+    // needs.
+    let var: (Var<u64>, u8) = deserialize(&serialize(&(Var(300u64), 7u8), Infinite).unwrap()).unwrap();
+    assert_eq!(var, (Var(300), 7));
+}
+
+#[test]
+fn test_datagrams() {
+    use bincode::serde::{serialize_datagram, deserialize_datagram, SerializeError};
+
+    let update = (7u32, vec![1.5f32, -2.0], Some("ping".to_string()));
+    let packet = serialize_datagram(&update, 1200).unwrap();
+    assert_eq!(packet, serialize(&update, Infinite).unwrap());
+    assert_eq!(deserialize_datagram::<(u32, Vec<f32>, Option<String>)>(&packet).unwrap(), update);
+
+    // Exactly at the budget is fine, one byte over isn't.
+    let len = packet.len();
+    assert!(serialize_datagram(&update, len).is_ok());
+    match serialize_datagram(&update, len - 1) {
+        Err(SerializeError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+    match serialize_datagram(&vec![0u8; 1 << 20], 1200) {
+        Err(SerializeError::SizeLimit) => {}
+        other => panic!("Expecting SizeLimit, got {:?}", other),
+    }
+
+    let mut padded = packet.clone();
+    padded.push(0);
+    match deserialize_datagram::<(u32, Vec<f32>, Option<String>)>(&padded) {
+        Err(DeserializeError::TrailingBytes { remaining: 1 }) => {}
+        other => panic!("Expecting TrailingBytes, got {:?}", other),
+    }
+    match deserialize_datagram::<(u32, Vec<f32>, Option<String>)>(&packet[..len - 1]) {
+        Err(DeserializeError::IoError(ref err)) if err.kind() == ::std::io::ErrorKind::UnexpectedEof => {}
+        other => panic!("Expecting UnexpectedEof, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_fragments() {
+    use std::mem;
+    use std::time::{Duration, Instant};
+    use bincode::fragment::{Fragmenter, Reassembler, FragmentError, FRAGMENT_HEADER_LEN};
+
+    let snapshot: Vec<u32> = (0..100).collect();
+    let mut fragmenter = Fragmenter::new(108);
+    let fragments = fragmenter.fragment(&snapshot).unwrap();
+    // 8 + 400 bytes of message, 100 to a fragment.
+    assert_eq!(fragments.len(), 5);
+    assert!(fragments.iter().all(|fragment| fragment.len() <= 108));
+    assert_eq!(&fragments[1][..FRAGMENT_HEADER_LEN], &[0, 0, 0, 0, 0, 1, 0, 5]);
+    assert_eq!(fragmenter.next_id(), 1);
+
+    let start = Instant::now();
+    let mut reassembler = Reassembler::new();
+    for &i in &[3, 0, 4, 0, 1] {
+        assert_eq!(reassembler.receive::<Vec<u32>>(&fragments[i], start).unwrap(), None);
+    }
+    // Each message started also pays for its table of five fragments.
+    let slots = 5 * mem::size_of::<Option<Vec<u8>>>();
+    assert_eq!(reassembler.pending(), 1);
+    assert_eq!(reassembler.buffered(), slots + 308);
+    assert_eq!(reassembler.receive::<Vec<u32>>(&fragments[2], start).unwrap(), Some(snapshot.clone()));
+    assert_eq!(reassembler.pending(), 0);
+    assert_eq!(reassembler.buffered(), 0);
+
+    // A small message goes in one fragment.
+    let small = fragmenter.fragment(&7u8).unwrap();
+    assert_eq!(small.len(), 1);
+    assert_eq!(reassembler.receive::<u8>(&small[0], start).unwrap(), Some(7));
+
+    // A message with a fragment missing is given up on after the timeout.
+    let mut reassembler = Reassembler::new().timeout(Duration::from_millis(100));
+    let lost = fragmenter.fragment(&snapshot).unwrap();
+    reassembler.push(&lost[0], start).unwrap();
+    assert_eq!(reassembler.expire(start + Duration::from_millis(50)), 0);
+    assert_eq!(reassembler.expire(start + Duration::from_millis(150)), 1);
+    assert_eq!(reassembler.dropped(), 1);
+    assert_eq!(reassembler.buffered(), 0);
+
+    // The oldest message makes room for a new one when over the budget.
+    let mut reassembler = Reassembler::new().memory_budget(2 * slots + 250);
+    let first = fragmenter.fragment(&snapshot).unwrap();
+    let second = fragmenter.fragment(&snapshot).unwrap();
+    reassembler.push(&first[0], start).unwrap();
+    reassembler.push(&first[1], start).unwrap();
+    reassembler.push(&second[0], start + Duration::from_millis(1)).unwrap();
+    assert_eq!(reassembler.pending(), 1);
+    assert_eq!(reassembler.buffered(), slots + 100);
+    assert_eq!(reassembler.dropped(), 1);
+    match reassembler.push(&first[0], start) {
+        Ok(None) => {}
+        other => panic!("Expecting Ok(None), got {:?}", other),
+    }
+    match Reassembler::new().memory_budget(50).push(&first[0], start) {
+        Err(FragmentError::OverBudget) => {}
+        other => panic!("Expecting OverBudget, got {:?}", other),
+    }
+
+    // Empty first fragments of made-up messages of the most fragments
+    // still have their tables counted, and can't go over the budget.
+    let budget = 4 << 20;
+    let mut reassembler = Reassembler::new().memory_budget(budget);
+    for id in 0..100u8 {
+        assert_eq!(reassembler.push(&[0, 0, 0, id, 0, 0, 0xff, 0xff], start).unwrap(), None);
+        assert!(reassembler.buffered() <= budget);
+    }
+    assert_eq!(reassembler.pending(), 2);
+    assert_eq!(reassembler.dropped(), 98);
+    match Reassembler::new().push(&[0, 0, 0, 1, 0, 0, 0xff, 0xff], start) {
+        Err(FragmentError::OverBudget) => {}
+        other => panic!("Expecting OverBudget, got {:?}", other),
+    }
+
+    match reassembler.push(&[0, 0, 0, 9, 0, 2, 0, 2], start) {
+        Err(FragmentError::Malformed) => {}
+        other => panic!("Expecting Malformed, got {:?}", other),
+    }
+    match reassembler.push(&[0, 0], start) {
+        Err(FragmentError::Malformed) => {}
+        other => panic!("Expecting Malformed, got {:?}", other),
+    }
+    match Fragmenter::new(16).fragment(&vec![0u8; 8 * 0xffff + 1]) {
+        Err(FragmentError::TooLarge) => {}
+        other => panic!("Expecting TooLarge, got {:?}", other),
+    }
+    match Fragmenter::new(FRAGMENT_HEADER_LEN).fragment_bytes(&[1]) {
+        Err(FragmentError::TooLarge) => {}
+        other => panic!("Expecting TooLarge, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_tee_write() {
+    use std::io::Write;
+    use bincode::TeeWrite;
+
+    let value = (1u8, "tee".to_string(), vec![2u32, 3]);
+    let expected = serialize(&value, Infinite).unwrap();
+
+    let mut tee = TeeWrite::new(Vec::new(), Vec::new());
+    bincode::serde::serialize_into(&mut tee, &value, Infinite).unwrap();
+    let (first, second) = tee.into_inner();
+    assert_eq!(first, expected);
+    assert_eq!(second, expected);
+
+    let mut file = Vec::new();
+    let mut socket = Vec::new();
+    {
+        let mut writers: [&mut Write; 2] = [&mut file, &mut socket];
+        bincode::serde::serialize_into_many(&value, &mut writers, Infinite).unwrap();
+    }
+    assert_eq!(file, expected);
+    assert_eq!(socket, expected);
+
+    let mut encoded = Vec::new();
+    {
+        let mut writers: [&mut Write; 1] = [&mut encoded];
+        bincode::rustc_serialize::encode_into_many(&value, &mut writers, Infinite).unwrap();
+        bincode::codec::encode_into_many(&value, &mut writers, Infinite).unwrap();
+    }
+    assert_eq!(encoded, [&expected[..], &expected[..]].concat());
+
+    let mut limited = Vec::new();
+    {
+        let mut writers: [&mut Write; 1] = [&mut limited];
+        assert!(bincode::serde::serialize_into_many(&value, &mut writers, Bounded(4)).is_err());
+    }
+    assert!(limited.is_empty());
+}
+
+#[test]
+fn test_serialize_sized() {
+    let value = (vec![1u16, 2, 3], "hello".to_string(), Some(4u64));
+    let expected = serialize(&value, Infinite).unwrap();
+
+    let serialized = bincode::serde::serialize_sized(&value).unwrap();
+    assert_eq!(serialized, expected);
+    assert_eq!(serialized.capacity(), serialized.len());
+
+    let encoded = bincode::rustc_serialize::encode_sized(&value).unwrap();
+    assert_eq!(encoded, expected);
+    assert_eq!(encoded.capacity(), encoded.len());
+
+    let encoded = bincode::codec::encode_sized(&value).unwrap();
+    assert_eq!(encoded, expected);
+    assert_eq!(encoded.capacity(), encoded.len());
+}
+
+#[test]
+fn test_codec_matches_serde() {
+    fn codec_the_same<V>(element: V)
+        where V: bincode::Encode + bincode::Decode + serde::Serialize + PartialEq + Debug
+    {
+        let encoded = bincode::codec::encode(&element, Infinite).unwrap();
+        let serialized = serialize(&element, Infinite).unwrap();
+        assert_eq!(encoded, serialized);
+        assert_eq!(bincode::codec::encoded_size(&element), encoded.len() as u64);
+
+        let decoded: V = bincode::codec::decode(&encoded[..]).unwrap();
+        assert_eq!(decoded, element);
+    }
+
+    codec_the_same(5u8);
+    codec_the_same(-5i64);
+    codec_the_same(3.5f32);
+    codec_the_same(true);
+    codec_the_same("aåååååååa".to_string());
+    codec_the_same((1u16, Some(2u32), None::<u8>, ()));
+    codec_the_same(vec![1u64, 2, 3]);
+    codec_the_same([24u32; 32]);
+
+    let mut m = HashMap::new();
+    m.insert(4u64, "foo".to_string());
+    m.insert(0u64, "bar".to_string());
+    codec_the_same(m);
+
+    // An array with the wrong length is turned down before its elements
+    // are read.
+    let mut wrong_len = serialize(&1000u64, Infinite).unwrap();
+    wrong_len.extend_from_slice(&[1, 2, 3]);
+    match bincode::codec::decode::<[u8; 3]>(&wrong_len[..]) {
+        Err(bincode::codec::DecodeError::InvalidEncoding(_)) => {}
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_vectors() {
+    use std::collections::BTreeMap;
+    use std::fs::File;
+    use std::io::Read;
+
+    fn check<V>(value: V, name: &str)
+        where V: Encodable + Decodable + bincode::Encode + bincode::Decode +
+                 serde::Serialize + serde::Deserialize + PartialEq + Debug
+    {
+        let path = format!("{}/tests/fixtures/{}.bin", env!("CARGO_MANIFEST_DIR"), name);
+        bincode::testvectors::assert_fixture(&value, &path);
+
+        let mut expected = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut expected).unwrap();
+        assert_eq!(encode(&value, Infinite).unwrap(), expected);
+        assert_eq!(bincode::codec::encode(&value, Infinite).unwrap(), expected);
+        assert_eq!(decode::<V>(&expected).unwrap(), value);
+        assert_eq!(bincode::codec::decode::<V>(&expected).unwrap(), value);
+    }
+
+    check((), "unit");
+    check(true, "bool");
+    check(0xabu8, "u8");
+    check(0x1234u16, "u16");
+    check(0x12345678u32, "u32");
+    check(0x0102030405060708u64, "u64");
+    check(7usize, "usize");
+    check(-2i8, "i8");
+    check(-2i16, "i16");
+    check(-2i32, "i32");
+    check(-2i64, "i64");
+    check(-2isize, "isize");
+    check(1.5f32, "f32");
+    check(-0.25f64, "f64");
+    check("héllo".to_string(), "string");
+    check(vec![1u8, 2, 3], "bytes");
+    check(None::<u16>, "option_none");
+    check(Some(5u16), "option_some");
+    check(vec![1u32, 2], "vec_u32");
+    check((1u8, -1i16, "a".to_string()), "tuple");
+    check(vec![Some("x".to_string()), None], "nested");
+
+    let mut map = BTreeMap::new();
+    map.insert(2u8, "bc".to_string());
+    map.insert(1u8, "a".to_string());
+    check(map, "btreemap");
+}
+
+#[test]
+fn test_vectors_derived() {
+    #[derive(RustcEncodable, RustcDecodable, Serialize, Deserialize, PartialEq, Debug)]
+    struct Record {
+        id: u32,
+        name: String,
+        flag: bool,
+        delta: i16,
+    }
+
+    #[derive(RustcEncodable, RustcDecodable, Serialize, Deserialize, PartialEq, Debug)]
+    enum Message {
+        Empty,
+        Count(u32),
+        Pair(u8, u8),
+        Named { on: bool, label: String },
+    }
+
+    fn check<V>(value: V, expected: &[u8])
+        where V: Encodable + Decodable + PartialEq + Debug
+    {
+        assert_eq!(encode(&value, Infinite).unwrap(), expected);
+        assert_eq!(decode::<V>(expected).unwrap(), value);
+    }
+
+    let record = Record { id: 1, name: "hi".to_string(), flag: true, delta: -3 };
+    assert_fixture!(record, "struct");
+    check(record, include_bytes!("fixtures/struct.bin"));
+
+    assert_fixture!(Message::Empty, "enum_unit");
+    assert_fixture!(Message::Count(9), "enum_newtype");
+    assert_fixture!(Message::Pair(1, 2), "enum_tuple");
+    assert_fixture!(Message::Named { on: false, label: "z".to_string() }, "enum_struct");
+    check(Message::Empty, include_bytes!("fixtures/enum_unit.bin"));
+    check(Message::Count(9), include_bytes!("fixtures/enum_newtype.bin"));
+    check(Message::Pair(1, 2), include_bytes!("fixtures/enum_tuple.bin"));
+    check(Message::Named { on: false, label: "z".to_string() }, include_bytes!("fixtures/enum_struct.bin"));
+}
+
+#[test]
+fn test_codec_errors() {
+    use bincode::codec::{decode, decode_from, encode, DecodeError};
+
+    match decode::<bool>(&[0xA][..]) {
+        Err(DecodeError::InvalidEncoding(_)) => {},
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+    match decode::<[u8; 2]>(&[0, 0, 0, 0, 0, 0, 0, 1, 0][..]) {
+        Err(DecodeError::InvalidEncoding(_)) => {},
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+
+    assert!(decode_from::<_, u32, _>(&mut &[0, 0, 0, 3][..], Bounded(3)).is_err());
+    assert!(encode(&"abcde", Bounded(8 + 4)).is_err());
+    assert!(encode(&"abcde", Bounded(8 + 5)).is_ok());
+}
+
+#[test]
+fn test_codec_pod_vec() {
+    use bincode::codec::{Pod, PodVec};
+
+    let samples: Vec<u32> = (0..1000u32).map(|i| i.wrapping_mul(0x01010101)).collect();
+    let encoded = bincode::codec::encode(&PodVec(samples.clone()), Infinite).unwrap();
+    assert_eq!(encoded, bincode::codec::encode(&samples, Infinite).unwrap());
+    let decoded: PodVec<u32> = bincode::codec::decode(&encoded[..]).unwrap();
+    assert_eq!(decoded.0, samples);
+
+    let readings = PodVec(vec![1.5f64, -0.25, 1e300]);
+    let encoded = bincode::codec::encode(&readings, Infinite).unwrap();
+    assert_eq!(encoded, serialize(&readings.0, Infinite).unwrap());
+    assert_eq!(bincode::codec::decode::<PodVec<f64>>(&encoded[..]).unwrap(), readings);
+
+    assert!(<i16 as Pod>::PACKED);
+
+    // A length that is far larger than the input fails without allocating
+    // it all.
+    let mut lying = vec![0, 0, 0, 0, 0x10, 0, 0, 0];
+    lying.extend_from_slice(&[0; 16]);
+    assert!(bincode::codec::decode::<PodVec<u64>>(&lying[..]).is_err());
+    assert!(bincode::codec::decode_from::<_, PodVec<u64>, _>(&mut &encoded[..], Bounded(8 + 16)).is_err());
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_codec_derive_pod() {
+    use bincode::codec::{Pod, PodVec};
+
+    #[repr(C)]
+    #[derive(bincode::Encode, bincode::Decode, bincode::Pod, Clone, Copy, PartialEq, Debug)]
+    struct Sample {
+        timestamp: u64,
+        x: f32,
+        y: f32,
+        sensor: u32,
+        flags: u16,
+        kind: u8,
+        level: i8,
+    }
+
+    #[repr(C)]
+    #[derive(bincode::Encode, bincode::Decode, bincode::Pod, Clone, Copy, PartialEq, Debug)]
+    struct Padded(u8, u32);
+
+    #[derive(bincode::Encode, bincode::Decode, bincode::Pod, Clone, Copy, PartialEq, Debug)]
+    struct Unordered {
+        a: u16,
+        b: u64,
+    }
+
+    assert!(<Sample as Pod>::PACKED);
+    assert!(!<Padded as Pod>::PACKED);
+    assert!(!<Unordered as Pod>::PACKED);
+
+    let samples: Vec<Sample> = (0..600).map(|i| Sample {
+        timestamp: 1_500_000_000_000 + i,
+        x: i as f32 * 0.5,
+        y: -(i as f32),
+        sensor: i as u32 * 7,
+        flags: 0xbeef,
+        kind: i as u8,
+        level: -3,
+    }).collect();
+    let encoded = bincode::codec::encode(&PodVec(samples.clone()), Infinite).unwrap();
+    assert_eq!(encoded, bincode::codec::encode(&samples, Infinite).unwrap());
+    let decoded: PodVec<Sample> = bincode::codec::decode(&encoded[..]).unwrap();
+    assert_eq!(decoded.0, samples);
+
+    let padded = vec![Padded(1, 2), Padded(3, 4)];
+    let encoded = bincode::codec::encode(&PodVec(padded.clone()), Infinite).unwrap();
+    assert_eq!(encoded, bincode::codec::encode(&padded, Infinite).unwrap());
+    assert_eq!(bincode::codec::decode::<PodVec<Padded>>(&encoded[..]).unwrap().0, padded);
+
+    let unordered = vec![Unordered { a: 1, b: 2 }];
+    let encoded = bincode::codec::encode(&PodVec(unordered.clone()), Infinite).unwrap();
+    assert_eq!(encoded, vec![0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]);
+    assert_eq!(bincode::codec::decode::<PodVec<Unordered>>(&encoded[..]).unwrap().0, unordered);
+}
+
+#[test]
+fn test_codec_aligned() {
+    use bincode::codec::{encode_aligned, decode_aligned, FixedVec, FixedSeq};
+
+    let value = (1u8, 2u32, 3u16, 4u64);
+    let encoded = encode_aligned(&value).unwrap();
+    assert_eq!(encoded, vec![1, 0, 0, 0, 0, 0, 0, 2,
+                             0, 3, 0, 0, 0, 0, 0, 0,
+                             0, 0, 0, 0, 0, 0, 0, 4]);
+    assert_eq!(decode_aligned::<(u8, u32, u16, u64)>(&encoded).unwrap(), value);
+
+    // Rows of a u8 and a u32 take 8 bytes each.
+    let rows: Vec<(u8, u32)> = (0..100).map(|i| (i as u8, i * 1000)).collect();
+    let encoded = encode_aligned(&(7u8, FixedVec(rows.clone()))).unwrap();
+    assert_eq!(encoded.len(), 8 + 16 + 100 * 8);
+    assert_eq!(&encoded[8..24], &[0, 0, 0, 0, 0, 0, 0, 100, 0, 0, 0, 0, 0, 0, 0, 8]);
+    let decoded: (u8, FixedVec<(u8, u32)>) = decode_aligned(&encoded).unwrap();
+    assert_eq!(decoded.1, FixedVec(rows.clone()));
+
+    let seq = FixedSeq::new(&encoded[8..], true).unwrap();
+    assert_eq!(seq.len(), 100);
+    assert_eq!(seq.stride(), 8);
+    assert_eq!(seq.encoded_len(), encoded.len() - 8);
+    assert_eq!(seq.get::<(u8, u32)>(57).unwrap(), Some((57, 57000)));
+    assert_eq!(seq.get::<(u8, u32)>(100).unwrap(), None);
+    assert!(FixedSeq::new(&encoded[8..encoded.len() - 1], true).is_err());
+
+    // Without aligned mode, the stride is the size of the largest element.
+    let names = FixedVec(vec!["a".to_string(), "abc".to_string(), "".to_string()]);
+    let encoded = bincode::codec::encode(&names, Infinite).unwrap();
+    assert_eq!(encoded.len(), 16 + 3 * 11);
+    let seq = FixedSeq::new(&encoded, false).unwrap();
+    assert_eq!(seq.get::<String>(1).unwrap(), Some("abc".to_string()));
+    assert_eq!(bincode::codec::decode::<FixedVec<String>>(&encoded[..]).unwrap(), names);
+
+    // A stride too small for the elements is an error.
+    let mut bad = encoded.clone();
+    bad[15] = 9;
+    assert!(bincode::codec::decode::<FixedVec<String>>(&bad[..]).is_err());
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_codec_derive() {
+    #[derive(bincode::Encode, bincode::Decode, Serialize, PartialEq, Debug)]
+    struct Easy<T> {
+        x: isize,
+        s: String,
+        y: T
+    }
+
+    #[derive(bincode::Encode, bincode::Decode, Serialize, PartialEq, Debug)]
+    enum TestEnum {
+        NoArg,
+        OneArg(usize),
+        Args(usize, usize),
+        StructLike{x: usize, y: f32}
+    }
+
+    let easy = Easy { x: -4, s: "foo".to_string(), y: vec![TestEnum::NoArg, TestEnum::OneArg(4),
+                                                           TestEnum::Args(4, 5),
+                                                           TestEnum::StructLike{x: 4, y: 3.14159}] };
+    let encoded = bincode::codec::encode(&easy, Infinite).unwrap();
+    assert_eq!(encoded, serialize(&easy, Infinite).unwrap());
+    let decoded: Easy<Vec<TestEnum>> = bincode::codec::decode(&encoded[..]).unwrap();
+    assert_eq!(decoded, easy);
+
+    match bincode::codec::decode::<TestEnum>(&[0, 0, 0, 5][..]) {
+        Err(bincode::codec::DecodeError::InvalidEncoding(_)) => {},
+        other => panic!("Expecting InvalidEncoding, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_codec_derive_tags() {
+    #[derive(bincode::Encode, bincode::Decode, PartialEq, Debug)]
+    enum Old {
+        A,
+        #[bincode(tag = 5)]
+        B(u8),
+        C,
+    }
+
+    // `A` was removed and `D` added in front, but the tags stay stable.
+    #[derive(bincode::Encode, bincode::Decode, PartialEq, Debug)]
+    enum New {
+        #[bincode(tag = 7)]
+        D,
+        #[bincode(tag = 6)]
+        C,
+        #[bincode(tag = 5)]
+        B(u8),
+    }
+
+    assert_eq!(bincode::codec::encode(&Old::A, Infinite).unwrap(), vec![0, 0, 0, 0]);
+    assert_eq!(bincode::codec::encode(&Old::B(1), Infinite).unwrap(), vec![0, 0, 0, 5, 1]);
+    assert_eq!(bincode::codec::encode(&Old::C, Infinite).unwrap(), vec![0, 0, 0, 6]);
+
+    let encoded = bincode::codec::encode(&Old::B(1), Infinite).unwrap();
+    assert_eq!(bincode::codec::decode::<New>(&encoded[..]).unwrap(), New::B(1));
+    let encoded = bincode::codec::encode(&Old::C, Infinite).unwrap();
+    assert_eq!(bincode::codec::decode::<New>(&encoded[..]).unwrap(), New::C);
+    let encoded = bincode::codec::encode(&Old::A, Infinite).unwrap();
+    assert!(bincode::codec::decode::<New>(&encoded[..]).is_err());
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn test_wasm_round_trip() {
+    use bincode::wasm::{JsArray, serialize_to_js_array, deserialize_from_js_array, encode_to_js_array,
+                        decode_from_js_array};
+
+    // JavaScript arrays can only be created on wasm, so the host tests go
+    // through a stand-in.
+    struct HostArray(Vec<u8>);
+
+    impl JsArray for HostArray {
+        fn from_bytes(bytes: &[u8]) -> HostArray {
+            HostArray(bytes.to_vec())
+        }
+
+        fn to_bytes(&self) -> Vec<u8> {
+            self.0.clone()
+        }
+    }
+
+    let value = (7u32, "from the browser".to_string(), vec![Some(1i64), None]);
+    let array: HostArray = serialize_to_js_array(&value, Infinite).unwrap();
+    assert_eq!(array.0, serialize(&value, Infinite).unwrap());
+    assert_eq!(deserialize_from_js_array::<_, (u32, String, Vec<Option<i64>>)>(&array).unwrap(), value);
+    assert!(serialize_to_js_array::<HostArray, _, _>(&value, Bounded(4)).is_err());
+
+    let array: HostArray = encode_to_js_array(&value, Infinite).unwrap();
+    assert_eq!(array.0, encode(&value, Infinite).unwrap());
+    assert_eq!(decode_from_js_array::<_, (u32, String, Vec<Option<i64>>)>(&array).unwrap(), value);
+}